@@ -0,0 +1,13 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    {
+        // Use the vendored protoc so the grpc feature builds without a system install.
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc");
+        // Safety: build scripts are single-threaded.
+        unsafe { std::env::set_var("PROTOC", protoc) };
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/aiproxy.proto"], &["proto"])
+            .expect("compile proto/aiproxy.proto");
+    }
+}
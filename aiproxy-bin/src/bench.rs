@@ -0,0 +1,291 @@
+//! `aiproxy bench`: latency / TTFT / throughput / cost comparison across models.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use aiproxy_core::model::ChatRequest;
+use aiproxy_core::pricing;
+use aiproxy_core::provider::ChatProvider;
+use aiproxy_core::provider_factory::ProviderRegistry;
+use aiproxy_core::router::RoutingResolver;
+use aiproxy_core::stream::StreamEvent;
+use futures_util::StreamExt;
+use futures_util::future::join_all;
+use serde::Serialize;
+
+/// Outcome of a single benchmark call.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunResult {
+    pub model: String,
+    pub provider: String,
+    pub run: usize,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    /// Time to first text; equals latency for providers that only return a final response.
+    pub ttft_ms: Option<u64>,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    /// True when the provider reported no usage and tokens were approximated from text length.
+    pub tokens_estimated: bool,
+    pub cost_usd: Option<f64>,
+}
+
+impl RunResult {
+    pub fn tokens_per_sec(&self) -> Option<f64> {
+        (self.error.is_none() && self.latency_ms > 0)
+            .then(|| self.completion_tokens as f64 / (self.latency_ms as f64 / 1000.0))
+    }
+}
+
+/// Per-model aggregate over all runs.
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub model: String,
+    pub provider: String,
+    pub runs: usize,
+    pub errors: usize,
+    pub p50_latency_ms: Option<u64>,
+    pub p95_latency_ms: Option<u64>,
+    pub p50_ttft_ms: Option<u64>,
+    pub mean_tokens_per_sec: Option<f64>,
+    pub total_cost_usd: Option<f64>,
+}
+
+/// Rough token count (~4 chars per token) for providers that omit usage while streaming.
+fn approx_tokens(text: &str) -> u32 {
+    text.chars().count().div_ceil(4) as u32
+}
+
+fn bench_request(model: &str, prompt: &str) -> ChatRequest {
+    ChatRequest::builder().model(model).user(prompt).build()
+}
+
+/// Stream one request and time it.
+pub async fn run_once(
+    provider: &dyn ChatProvider,
+    model: &str,
+    prompt: &str,
+    run: usize,
+) -> RunResult {
+    let started = Instant::now();
+    let elapsed_ms = |s: Instant| s.elapsed().as_millis() as u64;
+    let mut ttft_ms = None;
+    let mut text = String::new();
+    let mut usage: Option<(u32, u32)> = None;
+    let mut error = None;
+    let stream = provider
+        .chat_stream_events(bench_request(model, prompt))
+        .await;
+    match stream {
+        Err(e) => error = Some(e.to_string()),
+        Ok(mut events) => {
+            while let Some(ev) = events.next().await {
+                match ev {
+                    StreamEvent::DeltaText(t) => {
+                        ttft_ms.get_or_insert_with(|| elapsed_ms(started));
+                        text.push_str(&t);
+                    }
+                    StreamEvent::Usage { prompt, completion } => {
+                        usage = Some((prompt.unwrap_or(0), completion.unwrap_or(0)));
+                    }
+                    StreamEvent::Final(resp) => {
+                        ttft_ms.get_or_insert_with(|| elapsed_ms(started));
+                        if text.is_empty() {
+                            text = resp.text;
+                        }
+                        usage = Some((resp.usage.prompt, resp.usage.completion));
+                        break;
+                    }
+                    StreamEvent::Error(e) => {
+                        error = Some(e.to_string());
+                        break;
+                    }
+                    StreamEvent::Stop { .. } => break,
+                    _ => {}
+                }
+            }
+        }
+    }
+    let latency_ms = elapsed_ms(started);
+    let (prompt_tokens, completion_tokens, tokens_estimated) = match usage {
+        Some((p, c)) if c > 0 || text.is_empty() => (p, c, false),
+        _ => (approx_tokens(prompt), approx_tokens(&text), true),
+    };
+    RunResult {
+        model: model.to_string(),
+        provider: provider.name().to_string(),
+        run,
+        cost_usd: error
+            .is_none()
+            .then(|| pricing::cost_usd(provider.name(), model, prompt_tokens, completion_tokens))
+            .flatten(),
+        error,
+        latency_ms,
+        ttft_ms,
+        prompt_tokens,
+        completion_tokens,
+        tokens_estimated,
+    }
+}
+
+/// Nearest-rank percentile of an unsorted sample.
+pub(crate) fn percentile(mut xs: Vec<u64>, pct: f64) -> Option<u64> {
+    if xs.is_empty() {
+        return None;
+    }
+    xs.sort_unstable();
+    let rank = ((pct / 100.0) * xs.len() as f64).ceil() as usize;
+    Some(xs[rank.clamp(1, xs.len()) - 1])
+}
+
+/// Aggregate per model, keeping the order in which models were requested.
+pub fn summarize(results: &[RunResult]) -> Vec<Summary> {
+    let mut models: Vec<&str> = Vec::new();
+    for r in results {
+        if !models.contains(&r.model.as_str()) {
+            models.push(&r.model);
+        }
+    }
+    models
+        .into_iter()
+        .map(|model| {
+            let rs: Vec<&RunResult> = results.iter().filter(|r| r.model == model).collect();
+            let ok: Vec<&&RunResult> = rs.iter().filter(|r| r.error.is_none()).collect();
+            let tps: Vec<f64> = ok.iter().filter_map(|r| r.tokens_per_sec()).collect();
+            let costs: Vec<f64> = ok.iter().filter_map(|r| r.cost_usd).collect();
+            Summary {
+                model: model.to_string(),
+                provider: rs[0].provider.clone(),
+                runs: rs.len(),
+                errors: rs.len() - ok.len(),
+                p50_latency_ms: percentile(ok.iter().map(|r| r.latency_ms).collect(), 50.0),
+                p95_latency_ms: percentile(ok.iter().map(|r| r.latency_ms).collect(), 95.0),
+                p50_ttft_ms: percentile(ok.iter().filter_map(|r| r.ttft_ms).collect(), 50.0),
+                mean_tokens_per_sec: (!tps.is_empty())
+                    .then(|| tps.iter().sum::<f64>() / tps.len() as f64),
+                total_cost_usd: (!costs.is_empty()).then(|| costs.iter().sum()),
+            }
+        })
+        .collect()
+}
+
+pub(crate) fn cell<T: std::fmt::Display>(v: Option<T>) -> String {
+    v.map_or_else(|| "-".to_string(), |v| v.to_string())
+}
+
+/// Fixed-width comparison table, one row per model.
+pub fn render_table(rows: &[Summary]) -> String {
+    let header = [
+        "model", "provider", "runs", "errors", "p50 ms", "p95 ms", "ttft ms", "tok/s", "cost $",
+    ];
+    let mut table: Vec<Vec<String>> = vec![header.iter().map(|h| h.to_string()).collect()];
+    for r in rows {
+        table.push(vec![
+            r.model.clone(),
+            r.provider.clone(),
+            r.runs.to_string(),
+            r.errors.to_string(),
+            cell(r.p50_latency_ms),
+            cell(r.p95_latency_ms),
+            cell(r.p50_ttft_ms),
+            cell(r.mean_tokens_per_sec.map(|t| format!("{t:.1}"))),
+            cell(r.total_cost_usd.map(|c| format!("{c:.6}"))),
+        ]);
+    }
+    render_grid(&table)
+}
+
+/// Left-aligned columns separated by two spaces; the first row is the header.
+pub(crate) fn render_grid(table: &[Vec<String>]) -> String {
+    let columns = table.first().map_or(0, Vec::len);
+    let widths: Vec<usize> = (0..columns)
+        .map(|i| table.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for row in table {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{c:w$}"))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Run `runs` sequential calls per model, with all models benchmarked concurrently.
+pub async fn run(
+    reg: &ProviderRegistry,
+    router: &RoutingResolver,
+    models: &[String],
+    prompt: &str,
+    runs: usize,
+) -> anyhow::Result<Vec<RunResult>> {
+    let mut targets: Vec<(String, Arc<dyn ChatProvider>)> = Vec::new();
+    for m in models {
+        targets.push((m.clone(), router.select_chat(reg, m)?));
+    }
+    let per_model = targets.into_iter().map(|(model, provider)| async move {
+        let mut out = Vec::with_capacity(runs);
+        for i in 0..runs {
+            out.push(run_once(provider.as_ref(), &model, prompt, i).await);
+        }
+        out
+    });
+    Ok(join_all(per_model).await.into_iter().flatten().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aiproxy_core::provider::NullProvider;
+
+    fn result(model: &str, latency_ms: u64, error: Option<&str>) -> RunResult {
+        RunResult {
+            model: model.into(),
+            provider: "p".into(),
+            run: 0,
+            error: error.map(Into::into),
+            latency_ms,
+            ttft_ms: Some(latency_ms / 2),
+            prompt_tokens: 10,
+            completion_tokens: 100,
+            tokens_estimated: false,
+            cost_usd: Some(0.5),
+        }
+    }
+
+    #[tokio::test]
+    async fn null_provider_final_counts_as_first_token() {
+        let r = run_once(&NullProvider, "gpt-4o", "hello there", 0).await;
+        assert!(r.error.is_none());
+        assert!(r.ttft_ms.is_some());
+        assert_eq!(r.provider, "null");
+        // The null provider reports no completion tokens, so they are approximated.
+        assert!(r.tokens_estimated);
+        assert!(r.cost_usd.is_some());
+    }
+
+    #[test]
+    fn summary_excludes_errors_from_timings() {
+        let results = vec![
+            result("a", 100, None),
+            result("b", 50, None),
+            result("a", 300, None),
+            result("a", 10, Some("boom")),
+        ];
+        let rows = summarize(&results);
+        assert_eq!(rows[0].model, "a");
+        assert_eq!(rows[0].runs, 3);
+        assert_eq!(rows[0].errors, 1);
+        assert_eq!(rows[0].p50_latency_ms, Some(100));
+        assert_eq!(rows[0].p95_latency_ms, Some(300));
+        assert_eq!(rows[0].total_cost_usd, Some(1.0));
+        assert_eq!(rows[1].model, "b");
+
+        let table = render_table(&rows);
+        assert!(table.lines().next().unwrap().starts_with("model"));
+        assert_eq!(table.lines().count(), 3);
+    }
+}
@@ -0,0 +1,294 @@
+//! Flags shared by `chat` and `chat-stream`, and their translation into a `ChatRequest`.
+
+use std::io::Read;
+use std::path::PathBuf;
+
+use aiproxy_core::model::{ChatMessage, ChatRequest, Role};
+use clap::Args;
+
+use crate::markdown::Render;
+
+/// Largest file accepted by `--file`, and largest prompt read from stdin.
+pub const MAX_INPUT_BYTES: u64 = 1024 * 1024;
+
+#[derive(Args, Debug)]
+pub struct ChatArgs {
+    #[arg(long)]
+    pub model: String,
+    #[arg(
+        short,
+        long = "message",
+        required_unless_present = "files",
+        help = "Message as role:text (system|user|assistant|tool); no prefix means user; \
+                text '-' reads stdin. Repeatable"
+    )]
+    pub messages: Vec<String>,
+    #[arg(
+        long = "file",
+        help = "Attach a file's contents as [role:]path (default role user). Repeatable"
+    )]
+    pub files: Vec<String>,
+    #[arg(long, help = "System prompt, sent before all messages")]
+    pub system: Option<String>,
+    #[arg(long)]
+    pub temperature: Option<f32>,
+    #[arg(long)]
+    pub top_p: Option<f32>,
+    #[arg(long)]
+    pub max_tokens: Option<u32>,
+    #[arg(long, help = "Stop sequence. Repeatable")]
+    pub stop: Vec<String>,
+    #[arg(
+        long,
+        help = "Attach the provider's unparsed response as `raw` in --json output (non-streaming)"
+    )]
+    pub include_raw: bool,
+    #[arg(
+        long = "extra",
+        help = "Provider parameter passed through as key=JSON (non-JSON text is a string). \
+                Repeatable"
+    )]
+    pub extra: Vec<String>,
+    #[arg(long, help = "Load and extend the named saved conversation")]
+    pub session: Option<String>,
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Render::Raw,
+        help = "Output style; markdown is styled only when stdout is a terminal"
+    )]
+    pub render: Render,
+    #[arg(
+        long,
+        help = "Also record every stream event, timestamped, as NDJSON to this file (chat-stream)"
+    )]
+    pub out: Option<std::path::PathBuf>,
+}
+
+fn split_role(raw: &str) -> (Role, &str) {
+    match raw.split_once(':') {
+        Some(("system", rest)) => (Role::System, rest),
+        Some(("user", rest)) => (Role::User, rest),
+        Some(("assistant", rest)) => (Role::Assistant, rest),
+        Some(("tool", rest)) => (Role::Tool, rest),
+        _ => (Role::User, raw),
+    }
+}
+
+/// Split `role:text`; text without a known role prefix is a user message.
+pub fn parse_message(raw: &str) -> ChatMessage {
+    let (role, content) = split_role(raw);
+    ChatMessage::new(role, content)
+}
+
+/// `--extra key=value`, where the value is parsed as JSON when it can be.
+fn parse_extra(raw: &str) -> anyhow::Result<(String, serde_json::Value)> {
+    let (key, value) = raw
+        .split_once('=')
+        .filter(|(k, _)| !k.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("--extra expects key=value, got '{raw}'"))?;
+    let value = serde_json::from_str(value).unwrap_or_else(|_| value.into());
+    Ok((key.to_string(), value))
+}
+
+/// Read at most `MAX_INPUT_BYTES` of UTF-8 from `r`, naming `what` in errors.
+fn read_limited(r: impl Read, what: &str) -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    r.take(MAX_INPUT_BYTES + 1).read_to_end(&mut buf)?;
+    if buf.len() as u64 > MAX_INPUT_BYTES {
+        anyhow::bail!("{what} is larger than {MAX_INPUT_BYTES} bytes");
+    }
+    String::from_utf8(buf).map_err(|_| anyhow::anyhow!("{what} is not valid UTF-8"))
+}
+
+/// `--file [role:]path`, with the contents headed by the file name.
+fn file_message(raw: &str) -> anyhow::Result<ChatMessage> {
+    let (role, path) = split_role(raw);
+    let path = PathBuf::from(path);
+    let file =
+        std::fs::File::open(&path).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))?;
+    let contents = read_limited(file, &path.display().to_string())?;
+    Ok(ChatMessage::new(
+        role,
+        format!("File: {}\n\n{contents}", path.display()),
+    ))
+}
+
+impl ChatArgs {
+    pub fn into_request(self) -> anyhow::Result<ChatRequest> {
+        self.into_request_with(std::io::stdin().lock())
+    }
+
+    /// Build the request, reading any `-` message from `stdin`. Attached files come after the
+    /// system prompt and before the messages.
+    pub fn into_request_with(self, stdin: impl Read) -> anyhow::Result<ChatRequest> {
+        let mut messages = Vec::with_capacity(self.messages.len() + self.files.len() + 1);
+        if let Some(system) = self.system {
+            messages.push(ChatMessage::system(system));
+        }
+        for f in &self.files {
+            messages.push(file_message(f)?);
+        }
+        let mut stdin = Some(stdin);
+        for raw in &self.messages {
+            let mut msg = parse_message(raw);
+            if msg.content == "-" {
+                let r = stdin
+                    .take()
+                    .ok_or_else(|| anyhow::anyhow!("stdin ('-') can only be read once"))?;
+                msg.content = read_limited(r, "stdin")?.into();
+            }
+            messages.push(msg);
+        }
+        let extra = self
+            .extra
+            .iter()
+            .map(|raw| parse_extra(raw))
+            .collect::<anyhow::Result<serde_json::Map<_, _>>>()?;
+        Ok(ChatRequest {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            max_output_tokens: self.max_tokens,
+            stop_sequences: (!self.stop.is_empty()).then_some(self.stop),
+            include_raw: self.include_raw,
+            extra: (!extra.is_empty()).then_some(extra),
+            ..ChatRequest::builder()
+                .model(self.model)
+                .messages(messages)
+                .build()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Wrapper {
+        #[command(flatten)]
+        args: ChatArgs,
+    }
+
+    fn args(argv: &[&str]) -> ChatArgs {
+        let mut full = vec!["test"];
+        full.extend_from_slice(argv);
+        Wrapper::parse_from(full).args
+    }
+
+    fn parse(argv: &[&str]) -> ChatRequest {
+        args(argv).into_request_with(std::io::empty()).unwrap()
+    }
+
+    #[test]
+    fn role_prefixes_are_optional() {
+        assert_eq!(parse_message("assistant:ok").role, Role::Assistant);
+        assert_eq!(parse_message("assistant:ok").content, "ok");
+        let plain = parse_message("see https://example.com");
+        assert_eq!(plain.role, Role::User);
+        assert_eq!(plain.content, "see https://example.com");
+    }
+
+    #[test]
+    fn flags_map_onto_chat_request() {
+        let req = parse(&[
+            "--model",
+            "gpt-4o",
+            "--system",
+            "be terse",
+            "-m",
+            "user:hi",
+            "-m",
+            "assistant:hello",
+            "--message",
+            "and again",
+            "--temperature",
+            "0.2",
+            "--max-tokens",
+            "50",
+            "--stop",
+            "END",
+            "--stop",
+            "STOP",
+        ]);
+        let roles: Vec<Role> = req.messages.iter().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            vec![Role::System, Role::User, Role::Assistant, Role::User]
+        );
+        assert_eq!(req.temperature, Some(0.2));
+        assert_eq!(req.top_p, None);
+        assert_eq!(req.max_output_tokens, Some(50));
+        assert_eq!(req.stop_sequences, Some(vec!["END".into(), "STOP".into()]));
+    }
+
+    #[test]
+    fn no_stop_flags_means_none() {
+        let req = parse(&["--model", "m", "-m", "hi"]);
+        assert_eq!(req.stop_sequences, None);
+        assert_eq!(req.messages.len(), 1);
+        assert!(!req.include_raw);
+        assert!(parse(&["--model", "m", "-m", "hi", "--include-raw"]).include_raw);
+        assert_eq!(req.extra, None);
+    }
+
+    #[test]
+    fn extra_values_parse_as_json_or_text() {
+        let req = parse(&[
+            "--model",
+            "m",
+            "-m",
+            "hi",
+            "--extra",
+            "seed=7",
+            "--extra",
+            "route=fallback",
+        ]);
+        let extra = req.extra.unwrap();
+        assert_eq!(extra["seed"], 7);
+        assert_eq!(extra["route"], "fallback");
+        let err = args(&["--model", "m", "-m", "hi", "--extra", "seed"])
+            .into_request_with(std::io::empty())
+            .unwrap_err();
+        assert!(err.to_string().contains("key=value"));
+    }
+
+    #[test]
+    fn dash_reads_stdin_once() {
+        let req = args(&["--model", "m", "-m", "assistant:-"])
+            .into_request_with("piped prompt\n".as_bytes())
+            .unwrap();
+        assert_eq!(req.messages[0].role, Role::Assistant);
+        assert_eq!(req.messages[0].content, "piped prompt\n");
+
+        let err = args(&["--model", "m", "-m", "-", "-m", "-"])
+            .into_request_with("x".as_bytes())
+            .unwrap_err();
+        assert!(err.to_string().contains("only be read once"));
+    }
+
+    #[test]
+    fn files_are_attached_with_role_and_size_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let small = dir.path().join("notes.txt");
+        std::fs::write(&small, "remember this").unwrap();
+        let spec = format!("system:{}", small.display());
+        let req = parse(&["--model", "m", "--file", &spec]);
+        assert_eq!(req.messages.len(), 1);
+        assert_eq!(req.messages[0].role, Role::System);
+        assert!(
+            req.messages[0]
+                .content
+                .text()
+                .ends_with("\n\nremember this")
+        );
+
+        let big = dir.path().join("big.txt");
+        std::fs::write(&big, vec![b'a'; MAX_INPUT_BYTES as usize + 1]).unwrap();
+        let err = args(&["--model", "m", "--file", big.to_str().unwrap(), "-m", "hi"])
+            .into_request_with(std::io::empty())
+            .unwrap_err();
+        assert!(err.to_string().contains("larger than"));
+    }
+}
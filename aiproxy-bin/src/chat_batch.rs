@@ -0,0 +1,284 @@
+//! `aiproxy chat-batch`: run a JSONL file of chat requests with bounded concurrency.
+//!
+//! Results are appended to the output file as each item finishes, keyed by the input's
+//! line index. Re-running with the same `--out` skips every index already recorded as a
+//! success, so an interrupted run resumes where it stopped and failed items are tried again.
+//! A retried item gets a new line; the last line for an index is its result.
+
+use std::collections::HashSet;
+use std::io::Write;
+use std::time::Instant;
+
+use aiproxy_core::model::{ChatMessage, ChatRequest};
+use aiproxy_core::provider_factory::ProviderRegistry;
+use aiproxy_core::router::RoutingResolver;
+use futures_util::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// One input line: either `messages` or a bare `prompt` (plus optional `system`).
+#[derive(Debug, Deserialize)]
+struct PromptLine {
+    #[serde(default)]
+    id: Option<Value>,
+    model: Option<String>,
+    #[serde(default)]
+    messages: Vec<ChatMessage>,
+    prompt: Option<String>,
+    system: Option<String>,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    #[serde(alias = "max_output_tokens")]
+    max_tokens: Option<u32>,
+    stop: Option<Vec<String>>,
+}
+
+/// A parsed input; `request` holds the line's parse error when it could not be read.
+#[derive(Debug)]
+pub struct BatchInput {
+    pub index: usize,
+    pub id: Option<Value>,
+    pub request: Result<ChatRequest, String>,
+}
+
+fn to_request(line: PromptLine, default_model: Option<&str>) -> Result<ChatRequest, String> {
+    let model = line
+        .model
+        .or_else(|| default_model.map(str::to_string))
+        .ok_or("no model on this line and no --model default")?;
+    let mut messages = Vec::new();
+    if let Some(system) = line.system {
+        messages.push(ChatMessage::system(system));
+    }
+    messages.extend(line.messages);
+    if let Some(prompt) = line.prompt {
+        messages.push(ChatMessage::user(prompt));
+    }
+    if messages.is_empty() {
+        return Err("line has neither messages nor prompt".into());
+    }
+    Ok(ChatRequest {
+        temperature: line.temperature,
+        top_p: line.top_p,
+        max_output_tokens: line.max_tokens,
+        stop_sequences: line.stop,
+        ..ChatRequest::builder()
+            .model(model)
+            .messages(messages)
+            .build()
+    })
+}
+
+/// Parse every non-blank line; `index` counts non-blank lines from 0.
+pub fn parse_inputs(raw: &str, default_model: Option<&str>) -> Vec<BatchInput> {
+    raw.lines()
+        .filter(|l| !l.trim().is_empty())
+        .enumerate()
+        .map(
+            |(index, line)| match serde_json::from_str::<PromptLine>(line) {
+                Ok(p) => BatchInput {
+                    index,
+                    id: p.id.clone(),
+                    request: to_request(p, default_model),
+                },
+                Err(e) => BatchInput {
+                    index,
+                    id: None,
+                    request: Err(format!("invalid JSON: {e}")),
+                },
+            },
+        )
+        .collect()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub index: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub model: Option<String>,
+    pub provider: Option<String>,
+    pub text: Option<String>,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub cost_usd: Option<f64>,
+}
+
+/// Indexes an existing output file records as succeeded.
+pub fn succeeded_indexes(existing: &str) -> HashSet<usize> {
+    existing
+        .lines()
+        .filter_map(|l| serde_json::from_str::<BatchResult>(l).ok())
+        .filter(|r| r.error.is_none())
+        .map(|r| r.index)
+        .collect()
+}
+
+async fn run_item(
+    reg: &ProviderRegistry,
+    router: &RoutingResolver,
+    input: BatchInput,
+) -> BatchResult {
+    let mut result = BatchResult {
+        index: input.index,
+        id: input.id,
+        model: None,
+        provider: None,
+        text: None,
+        error: None,
+        latency_ms: 0,
+        prompt_tokens: 0,
+        completion_tokens: 0,
+        cost_usd: None,
+    };
+    let req = match input.request {
+        Ok(req) => req,
+        Err(e) => {
+            result.error = Some(e);
+            return result;
+        }
+    };
+    result.model = Some(req.model.clone());
+    let provider = match router.select_chat(reg, &req.model) {
+        Ok(p) => p,
+        Err(e) => {
+            result.error = Some(e.to_string());
+            return result;
+        }
+    };
+    result.provider = Some(provider.name().to_string());
+    let started = Instant::now();
+    let outcome = provider.chat(req).await;
+    result.latency_ms = started.elapsed().as_millis() as u64;
+    match outcome {
+        Ok(resp) => {
+            result.cost_usd = resp.cost_usd;
+            result.prompt_tokens = resp.usage.prompt;
+            result.completion_tokens = resp.usage.completion;
+            result.text = Some(resp.text);
+        }
+        Err(e) => result.error = Some(e.to_string()),
+    }
+    result
+}
+
+/// Run every input not in `skip`, appending one line to `out` per finished item (in
+/// completion order). Returns `(succeeded, failed)` counts for this run.
+pub async fn run(
+    reg: &ProviderRegistry,
+    router: &RoutingResolver,
+    inputs: Vec<BatchInput>,
+    skip: &HashSet<usize>,
+    concurrency: usize,
+    mut out: impl Write,
+) -> anyhow::Result<(usize, usize)> {
+    let pending = inputs.into_iter().filter(|i| !skip.contains(&i.index));
+    let mut results = stream::iter(pending)
+        .map(|input| run_item(reg, router, input))
+        .buffer_unordered(concurrency.max(1));
+    let (mut ok, mut failed) = (0, 0);
+    while let Some(result) = results.next().await {
+        if result.error.is_some() {
+            failed += 1;
+        } else {
+            ok += 1;
+        }
+        writeln!(out, "{}", serde_json::to_string(&result)?)?;
+        // Flush per item so an interrupted run keeps everything already finished.
+        out.flush()?;
+    }
+    Ok((ok, failed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::null_cfg;
+    use aiproxy_core::model::Role;
+
+    const INPUT: &str = r#"{"id": "a", "prompt": "hi"}
+
+{"model": "other", "messages": [{"role": "user", "content": "yo"}], "max_tokens": 5}
+{"prompt": 3}
+{"id": "d", "system": "only a system prompt"}
+"#;
+
+    #[test]
+    fn parses_prompt_and_message_lines_with_per_line_errors() {
+        let inputs = parse_inputs(INPUT, Some("default-model"));
+        assert_eq!(inputs.len(), 4);
+        let first = inputs[0].request.as_ref().unwrap();
+        assert_eq!(first.model, "default-model");
+        assert_eq!(first.messages[0].role, Role::User);
+        assert_eq!(
+            inputs[1].request.as_ref().unwrap().max_output_tokens,
+            Some(5)
+        );
+        assert!(
+            inputs[2]
+                .request
+                .as_ref()
+                .unwrap_err()
+                .contains("invalid JSON")
+        );
+        // A system prompt alone is still a valid conversation.
+        assert!(inputs[3].request.is_ok());
+
+        let no_default = parse_inputs(INPUT, None);
+        assert!(no_default[0].request.is_err());
+    }
+
+    #[tokio::test]
+    async fn resumes_by_skipping_succeeded_indexes_and_retrying_failed_ones() {
+        let cfg = null_cfg();
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let router = RoutingResolver::new(&cfg).unwrap();
+
+        let mut first = Vec::new();
+        let skip: HashSet<usize> = [1].into();
+        let (ok, failed) = run(
+            &reg,
+            &router,
+            parse_inputs(INPUT, Some("m")),
+            &skip,
+            2,
+            &mut first,
+        )
+        .await
+        .unwrap();
+        assert_eq!((ok, failed), (2, 1));
+        let first = String::from_utf8(first).unwrap();
+        let done = succeeded_indexes(&first);
+        assert_eq!(done, [0, 3].into());
+        let a: BatchResult = first
+            .lines()
+            .map(|l| serde_json::from_str::<BatchResult>(l).unwrap())
+            .find(|r| r.index == 0)
+            .unwrap();
+        assert_eq!(a.id, Some(serde_json::json!("a")));
+        assert_eq!(a.provider.as_deref(), Some("null"));
+
+        let mut second = Vec::new();
+        let (ok, failed) = run(
+            &reg,
+            &router,
+            parse_inputs(INPUT, Some("m")),
+            &done,
+            2,
+            &mut second,
+        )
+        .await
+        .unwrap();
+        assert_eq!((ok, failed), (1, 1));
+        let second = String::from_utf8(second).unwrap();
+        let retried: Vec<BatchResult> = second
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(retried.len(), 2);
+        assert!(retried.iter().any(|r| r.index == 2 && r.error.is_some()));
+        assert_eq!(succeeded_indexes(&second), [1].into());
+    }
+}
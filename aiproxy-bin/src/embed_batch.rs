@@ -0,0 +1,287 @@
+//! `aiproxy embed-batch`: embed a file of inputs with bounded concurrency and pacing.
+
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use aiproxy_core::error::AiProxyError;
+use aiproxy_core::model::EmbedRequest;
+use aiproxy_core::normalizer::normalize_embed_indexed;
+use aiproxy_core::provider::EmbedProvider;
+use futures_util::{StreamExt, stream};
+use serde::Deserialize;
+use tokio::time::Instant;
+
+/// Attempts per batch when the provider answers `RateLimited`.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// One input to embed, with the caller's optional id echoed into the output.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchItem {
+    pub id: Option<serde_json::Value>,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonlLine {
+    Text(String),
+    Object {
+        #[serde(default)]
+        id: Option<serde_json::Value>,
+        #[serde(alias = "input")]
+        text: String,
+    },
+}
+
+/// Parse inputs: `.jsonl`/`.ndjson` files hold a JSON string or `{"id", "text"}` object per
+/// line; anything else is plain text with one input per line. Blank lines are skipped.
+pub fn parse_inputs(path: &Path, raw: &str) -> anyhow::Result<Vec<BatchItem>> {
+    let jsonl = matches!(
+        path.extension().and_then(|e| e.to_str()),
+        Some("jsonl" | "ndjson")
+    );
+    let mut items = Vec::new();
+    for (lineno, line) in raw.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let item = if jsonl {
+            match serde_json::from_str::<JsonlLine>(line) {
+                Ok(JsonlLine::Text(text)) => BatchItem { id: None, text },
+                Ok(JsonlLine::Object { id, text }) => BatchItem { id, text },
+                Err(e) => anyhow::bail!("{}:{}: {e}", path.display(), lineno + 1),
+            }
+        } else {
+            BatchItem {
+                id: None,
+                text: line.to_string(),
+            }
+        };
+        if item.text.trim().is_empty() {
+            anyhow::bail!("{}:{}: empty input", path.display(), lineno + 1);
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOpts {
+    /// Requested inputs per call; capped by the provider's `max_batch_inputs`.
+    pub batch_size: usize,
+    /// Calls in flight at once.
+    pub concurrency: usize,
+    /// Maximum calls started per second (`None` = unpaced).
+    pub rps: Option<f64>,
+}
+
+/// Spaces call start times at least `gap` apart across all workers.
+struct Pacer {
+    gap: Duration,
+    next: Mutex<Option<Instant>>,
+}
+
+impl Pacer {
+    fn new(rps: Option<f64>) -> Self {
+        let gap = rps
+            .filter(|r| *r > 0.0)
+            .map(|r| Duration::from_secs_f64(1.0 / r))
+            .unwrap_or_default();
+        Self {
+            gap,
+            next: Mutex::new(None),
+        }
+    }
+
+    async fn wait(&self) {
+        if self.gap.is_zero() {
+            return;
+        }
+        let at = {
+            let mut next = self.next.lock().unwrap();
+            let at = next.map_or_else(Instant::now, |n| n.max(Instant::now()));
+            *next = Some(at + self.gap);
+            at
+        };
+        tokio::time::sleep_until(at).await;
+    }
+}
+
+async fn embed_chunk(
+    provider: &dyn EmbedProvider,
+    pacer: &Pacer,
+    model: &str,
+    chunk: &[BatchItem],
+) -> Result<Vec<Vec<f32>>, AiProxyError> {
+    let (req, index) = normalize_embed_indexed(
+        EmbedRequest::builder()
+            .model(model)
+            .inputs(chunk.iter().map(|i| i.text.clone()))
+            .build(),
+    );
+    let mut attempt = 1;
+    let resp = loop {
+        pacer.wait().await;
+        match provider.embed(req.clone()).await {
+            Err(e) if e.is_retryable() && attempt < MAX_ATTEMPTS => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_secs(e.retry_after().unwrap_or(1))).await;
+            }
+            other => break other?,
+        }
+    };
+    index
+        .into_iter()
+        .map(|pos| {
+            pos.and_then(|p| resp.vectors.get(p).cloned())
+                .ok_or_else(|| AiProxyError::ProviderError {
+                    provider: resp.provider.clone(),
+                    code: "vector_count_mismatch".into(),
+                    message: "provider returned fewer vectors than inputs".into(),
+                    upstream: Default::default(),
+                })
+        })
+        .collect()
+}
+
+/// Embed every item, returning vectors in input order regardless of completion order.
+pub async fn embed_all(
+    provider: Arc<dyn EmbedProvider>,
+    model: &str,
+    items: &[BatchItem],
+    opts: BatchOpts,
+) -> Result<Vec<Vec<f32>>, AiProxyError> {
+    let batch_size = opts.batch_size.clamp(1, provider.max_batch_inputs().max(1));
+    let pacer = Pacer::new(opts.rps);
+    let mut chunks = stream::iter(items.chunks(batch_size))
+        .map(|chunk| embed_chunk(provider.as_ref(), &pacer, model, chunk))
+        .buffered(opts.concurrency.max(1));
+    let mut out = Vec::with_capacity(items.len());
+    while let Some(vectors) = chunks.next().await {
+        out.extend(vectors?);
+    }
+    Ok(out)
+}
+
+/// Write one NDJSON line per input: `{"index", "id"?, "embedding"}`.
+pub fn write_ndjson(
+    mut w: impl Write,
+    items: &[BatchItem],
+    vectors: &[Vec<f32>],
+) -> anyhow::Result<()> {
+    for (index, (item, embedding)) in items.iter().zip(vectors).enumerate() {
+        let mut line = serde_json::json!({ "index": index, "embedding": embedding });
+        if let Some(id) = &item.id {
+            line["id"] = id.clone();
+        }
+        writeln!(w, "{line}")?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aiproxy_core::error::CoreResult;
+    use aiproxy_core::model::EmbedResponse;
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Embeds each input as `[len]`; later calls finish first to shake out ordering bugs.
+    #[derive(Debug, Default)]
+    struct LenProvider {
+        calls: AtomicUsize,
+        max_seen: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl EmbedProvider for LenProvider {
+        fn name(&self) -> &str {
+            "len"
+        }
+
+        async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst);
+            self.max_seen.fetch_max(req.inputs.len(), Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(20u64.saturating_sub(n as u64 * 5))).await;
+            Ok(EmbedResponse {
+                vectors: req.inputs.iter().map(|s| vec![s.len() as f32]).collect(),
+                model: req.model,
+                usage: 0,
+                cached: false,
+                provider: "len".into(),
+                cost_usd: None,
+            })
+        }
+
+        fn max_batch_inputs(&self) -> usize {
+            2
+        }
+    }
+
+    fn items(texts: &[&str]) -> Vec<BatchItem> {
+        texts
+            .iter()
+            .map(|t| BatchItem {
+                id: None,
+                text: t.to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_text_and_jsonl_inputs() {
+        let txt = parse_inputs(Path::new("in.txt"), "one\n\n two \n").unwrap();
+        assert_eq!(txt, items(&["one", " two "]));
+
+        let jsonl = parse_inputs(
+            Path::new("in.jsonl"),
+            "\"plain\"\n{\"id\": 7, \"text\": \"obj\"}\n{\"input\": \"alias\"}\n",
+        )
+        .unwrap();
+        assert_eq!(jsonl[0].text, "plain");
+        assert_eq!(jsonl[1].id, Some(serde_json::json!(7)));
+        assert_eq!(jsonl[2].text, "alias");
+
+        let err = parse_inputs(Path::new("in.jsonl"), "{\"id\": 1}\n").unwrap_err();
+        assert!(err.to_string().contains("in.jsonl:1"));
+    }
+
+    #[tokio::test]
+    async fn preserves_order_and_caps_batch_size() {
+        let provider = Arc::new(LenProvider::default());
+        let input = items(&["a", "bb", "ccc", "bb", "eeeee"]);
+        let opts = BatchOpts {
+            batch_size: 100,
+            concurrency: 3,
+            rps: None,
+        };
+        let vectors = embed_all(provider.clone(), "m", &input, opts)
+            .await
+            .unwrap();
+        let lens: Vec<f32> = vectors.iter().map(|v| v[0]).collect();
+        assert_eq!(lens, vec![1.0, 2.0, 3.0, 2.0, 5.0]);
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(provider.max_seen.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn writes_ids_when_present() {
+        let mut input = items(&["a", "b"]);
+        input[1].id = Some(serde_json::json!("doc-2"));
+        let mut buf = Vec::new();
+        write_ndjson(&mut buf, &input, &[vec![0.5], vec![1.5]]).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(buf)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["index"], 0);
+        assert!(lines[0].get("id").is_none());
+        assert_eq!(lines[1]["id"], "doc-2");
+        assert_eq!(lines[1]["embedding"][0], 1.5);
+    }
+}
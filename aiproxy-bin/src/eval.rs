@@ -0,0 +1,473 @@
+//! `aiproxy eval`: run a JSONL prompt set against several models and compare them.
+//!
+//! Each prompt is fanned out to every model at once (`fanout::ask_all`), through the normal
+//! routing, so cached replies and telemetry work as for any other call. An answer is scored
+//! by a judge model when `--judge` is given, otherwise by an exact match against the case's
+//! `expected` answer; cases with neither are left unscored. The report aggregates score,
+//! latency and cost per model.
+
+use std::time::Duration;
+
+use aiproxy_core::fanout::{self, Target};
+use aiproxy_core::model::{ChatMessage, ChatRequest, Role};
+use aiproxy_core::provider::ChatProvider;
+use aiproxy_core::provider_factory::ProviderRegistry;
+use aiproxy_core::router::RoutingResolver;
+use aiproxy_core::structured::{self, JsonSchema};
+use futures_util::future::join_all;
+use futures_util::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::bench::{cell, percentile, render_grid};
+
+/// One input line: `messages` or a bare `prompt` (plus optional `system`), and optionally
+/// the answer a correct reply should give.
+#[derive(Debug, Deserialize)]
+struct CaseLine {
+    #[serde(default)]
+    id: Option<Value>,
+    #[serde(default)]
+    messages: Vec<ChatMessage>,
+    prompt: Option<String>,
+    system: Option<String>,
+    expected: Option<String>,
+}
+
+/// A parsed case; `request` holds the line's parse error when it could not be read.
+#[derive(Debug)]
+pub struct EvalCase {
+    pub index: usize,
+    pub id: Option<Value>,
+    pub request: Result<ChatRequest, String>,
+    pub expected: Option<String>,
+}
+
+/// Parse every non-blank line; `index` counts non-blank lines from 0. The request's model is
+/// a placeholder, replaced by each evaluated model in turn.
+pub fn parse_cases(raw: &str) -> Vec<EvalCase> {
+    raw.lines()
+        .filter(|l| !l.trim().is_empty())
+        .enumerate()
+        .map(|(index, line)| {
+            let line = match serde_json::from_str::<CaseLine>(line) {
+                Ok(line) => line,
+                Err(e) => {
+                    return EvalCase {
+                        index,
+                        id: None,
+                        request: Err(format!("invalid JSON: {e}")),
+                        expected: None,
+                    };
+                }
+            };
+            let mut messages = Vec::new();
+            if let Some(system) = line.system {
+                messages.push(ChatMessage::system(system));
+            }
+            messages.extend(line.messages);
+            if let Some(prompt) = line.prompt {
+                messages.push(ChatMessage::user(prompt));
+            }
+            EvalCase {
+                index,
+                id: line.id,
+                request: if messages.is_empty() {
+                    Err("line has neither messages nor prompt".into())
+                } else {
+                    Ok(ChatRequest::builder().model("").messages(messages).build())
+                },
+                expected: line.expected,
+            }
+        })
+        .collect()
+}
+
+/// One model's answer to one case.
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalResult {
+    pub case: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    pub model: String,
+    pub provider: Option<String>,
+    pub output: Option<String>,
+    pub error: Option<String>,
+    /// 0 (wrong) to 1 (correct); `None` when the case could not be scored.
+    pub score: Option<f64>,
+    /// The judge's explanation, or why judging failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub judge_note: Option<String>,
+    pub latency_ms: u64,
+    pub cost_usd: Option<f64>,
+    pub judge_cost_usd: Option<f64>,
+    pub cached: bool,
+}
+
+/// The judge's structured reply.
+#[derive(Debug, Deserialize)]
+struct Verdict {
+    score: f64,
+    reason: String,
+}
+
+impl JsonSchema for Verdict {
+    fn schema_name() -> String {
+        "Verdict".into()
+    }
+
+    fn json_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": {
+                "score": {"type": "number", "minimum": 0, "maximum": 1},
+                "reason": {"type": "string"}
+            },
+            "required": ["score", "reason"],
+            "additionalProperties": false
+        })
+    }
+}
+
+/// Whitespace- and case-insensitive equality.
+fn exact_match(output: &str, expected: &str) -> bool {
+    let norm = |s: &str| {
+        s.split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+    };
+    norm(output) == norm(expected)
+}
+
+fn judge_request(req: &ChatRequest, answer: &str, expected: Option<&str>) -> ChatRequest {
+    let question = req
+        .messages
+        .iter()
+        .filter(|m| m.role == Role::User)
+        .map(|m| m.content.text())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let mut prompt = format!("Question:\n{question}\n\n");
+    if let Some(expected) = expected {
+        prompt.push_str(&format!("Reference answer:\n{expected}\n\n"));
+    }
+    prompt.push_str(&format!("Candidate answer:\n{answer}"));
+    ChatRequest::builder()
+        .model("")
+        .system(
+            "You grade answers. Score the candidate answer from 0 (wrong or unhelpful) to 1 \
+             (fully correct), comparing it with the reference answer when one is given, and \
+             explain the score in one sentence.",
+        )
+        .user(prompt)
+        .build()
+}
+
+/// Ask `judge` (routed for `model`) to score `answer`; returns `(score, note, cost)`.
+async fn judge_answer(
+    judge: &dyn ChatProvider,
+    model: &str,
+    req: &ChatRequest,
+    answer: &str,
+    expected: Option<&str>,
+) -> (Option<f64>, String, Option<f64>) {
+    let mut jreq = judge_request(req, answer, expected);
+    jreq.model = model.to_string();
+    match structured::chat_typed::<Verdict>(judge, jreq).await {
+        Ok(typed) => (
+            Some(typed.value.score.clamp(0.0, 1.0)),
+            typed.value.reason,
+            typed.response.cost_usd,
+        ),
+        Err(e) => (None, format!("judge failed: {e}"), None),
+    }
+}
+
+/// The model that scores answers and the provider routing chose for it.
+pub struct Judge<'a> {
+    pub model: &'a str,
+    pub provider: &'a dyn ChatProvider,
+}
+
+/// How `run` paces the evaluation.
+#[derive(Debug, Clone, Copy)]
+pub struct EvalOpts {
+    /// Cases in flight at once.
+    pub concurrency: usize,
+    /// Time each case may take across all models.
+    pub deadline: Duration,
+}
+
+async fn run_case(
+    reg: &ProviderRegistry,
+    router: &RoutingResolver,
+    models: &[String],
+    judge: Option<&Judge<'_>>,
+    case: EvalCase,
+    deadline: Duration,
+) -> Vec<EvalResult> {
+    let blank = |model: &str| EvalResult {
+        case: case.index,
+        id: case.id.clone(),
+        model: model.to_string(),
+        provider: None,
+        output: None,
+        error: None,
+        score: None,
+        judge_note: None,
+        latency_ms: 0,
+        cost_usd: None,
+        judge_cost_usd: None,
+        cached: false,
+    };
+    let req = match &case.request {
+        Ok(req) => req.clone(),
+        Err(e) => {
+            return models
+                .iter()
+                .map(|m| EvalResult {
+                    error: Some(e.clone()),
+                    ..blank(m)
+                })
+                .collect();
+        }
+    };
+    let targets = models
+        .iter()
+        .map(|m| Target::model(reg, router, m))
+        .collect();
+    let answers = fanout::ask_all(targets, req.clone(), deadline).await;
+    let expected = case.expected.as_deref();
+    join_all(answers.into_iter().map(|answer| {
+        let mut result = blank(&answer.target);
+        result.latency_ms = answer.latency.as_millis() as u64;
+        result.cost_usd = answer.cost_usd;
+        let req = &req;
+        async move {
+            let resp = match answer.result {
+                Ok(resp) => resp,
+                Err(e) => {
+                    result.error = Some(e.to_string());
+                    return result;
+                }
+            };
+            result.provider = Some(resp.provider);
+            result.cached = resp.cached;
+            if let Some(judge) = judge {
+                let (score, note, cost) =
+                    judge_answer(judge.provider, judge.model, req, &resp.text, expected).await;
+                result.score = score;
+                result.judge_note = Some(note);
+                result.judge_cost_usd = cost;
+            } else if let Some(expected) = expected {
+                result.score = Some(if exact_match(&resp.text, expected) {
+                    1.0
+                } else {
+                    0.0
+                });
+            }
+            result.output = Some(resp.text);
+            result
+        }
+    }))
+    .await
+}
+
+/// Evaluate every case against every model. Results are ordered by case, then by model in
+/// the order given.
+pub async fn run(
+    reg: &ProviderRegistry,
+    router: &RoutingResolver,
+    cases: Vec<EvalCase>,
+    models: &[String],
+    judge: Option<&Judge<'_>>,
+    opts: EvalOpts,
+) -> Vec<EvalResult> {
+    stream::iter(cases)
+        .map(|case| run_case(reg, router, models, judge, case, opts.deadline))
+        .buffered(opts.concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Per-model aggregate over all cases.
+#[derive(Debug, Clone, Serialize)]
+pub struct Summary {
+    pub model: String,
+    pub cases: usize,
+    pub errors: usize,
+    /// Answers that received a score.
+    pub scored: usize,
+    /// Mean score of the scored answers, the accuracy proxy.
+    pub mean_score: Option<f64>,
+    pub p50_latency_ms: Option<u64>,
+    pub p95_latency_ms: Option<u64>,
+    pub total_cost_usd: Option<f64>,
+    pub judge_cost_usd: Option<f64>,
+    pub cached: usize,
+}
+
+fn total(costs: impl Iterator<Item = Option<f64>>) -> Option<f64> {
+    costs
+        .flatten()
+        .fold(None, |acc, c| Some(acc.unwrap_or(0.0) + c))
+}
+
+/// Aggregate per model, keeping the order in which models were requested.
+pub fn summarize(models: &[String], results: &[EvalResult]) -> Vec<Summary> {
+    models
+        .iter()
+        .map(|model| {
+            let rs: Vec<&EvalResult> = results.iter().filter(|r| &r.model == model).collect();
+            let ok: Vec<&&EvalResult> = rs.iter().filter(|r| r.error.is_none()).collect();
+            let scores: Vec<f64> = rs.iter().filter_map(|r| r.score).collect();
+            Summary {
+                model: model.clone(),
+                cases: rs.len(),
+                errors: rs.len() - ok.len(),
+                scored: scores.len(),
+                mean_score: (!scores.is_empty())
+                    .then(|| scores.iter().sum::<f64>() / scores.len() as f64),
+                p50_latency_ms: percentile(ok.iter().map(|r| r.latency_ms).collect(), 50.0),
+                p95_latency_ms: percentile(ok.iter().map(|r| r.latency_ms).collect(), 95.0),
+                total_cost_usd: total(rs.iter().map(|r| r.cost_usd)),
+                judge_cost_usd: total(rs.iter().map(|r| r.judge_cost_usd)),
+                cached: ok.iter().filter(|r| r.cached).count(),
+            }
+        })
+        .collect()
+}
+
+/// Fixed-width comparison table, one row per model.
+pub fn render_table(rows: &[Summary]) -> String {
+    let header = [
+        "model", "cases", "errors", "score", "scored", "p50 ms", "p95 ms", "cost $", "judge $",
+        "cached",
+    ];
+    let mut table: Vec<Vec<String>> = vec![header.iter().map(|h| h.to_string()).collect()];
+    for r in rows {
+        table.push(vec![
+            r.model.clone(),
+            r.cases.to_string(),
+            r.errors.to_string(),
+            cell(r.mean_score.map(|s| format!("{s:.3}"))),
+            r.scored.to_string(),
+            cell(r.p50_latency_ms),
+            cell(r.p95_latency_ms),
+            cell(r.total_cost_usd.map(|c| format!("{c:.6}"))),
+            cell(r.judge_cost_usd.map(|c| format!("{c:.6}"))),
+            r.cached.to_string(),
+        ]);
+    }
+    render_grid(&table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::null_cfg;
+    use aiproxy_core::error::CoreResult;
+    use aiproxy_core::model::ChatResponse;
+    use aiproxy_core::provider::NullProvider;
+    use async_trait::async_trait;
+
+    const INPUT: &str = r#"{"id": "q1", "prompt": "Say the canned reply", "expected": "[NULL provider   response]"}
+{"prompt": "hi", "expected": "hello"}
+{"prompt": "no reference"}
+
+not json
+"#;
+
+    #[tokio::test]
+    async fn exact_match_scores_and_errors_are_per_model() {
+        let cfg = null_cfg();
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let router = RoutingResolver::new(&cfg).unwrap();
+        let cases = parse_cases(INPUT);
+        assert_eq!(cases.len(), 4);
+        let models = vec!["model-a".to_string(), "text-embedding-3-small".to_string()];
+        let opts = EvalOpts {
+            concurrency: 2,
+            deadline: Duration::from_secs(5),
+        };
+        let results = run(&reg, &router, cases, &models, None, opts).await;
+        assert_eq!(results.len(), 8);
+
+        let a: Vec<&EvalResult> = results.iter().filter(|r| r.model == "model-a").collect();
+        assert_eq!(a[0].id, Some(json!("q1")));
+        assert_eq!(a[0].provider.as_deref(), Some("null"));
+        let scores: Vec<Option<f64>> = a.iter().map(|r| r.score).collect();
+        assert_eq!(scores, [Some(1.0), Some(0.0), None, None]);
+        assert!(a[3].error.as_deref().unwrap().starts_with("invalid JSON"));
+        assert!(
+            results
+                .iter()
+                .filter(|r| r.model == "text-embedding-3-small")
+                .all(|r| r.error.is_some())
+        );
+
+        let rows = summarize(&models, &results);
+        assert_eq!(rows[0].cases, 4);
+        assert_eq!(rows[0].errors, 1);
+        assert_eq!(rows[0].scored, 2);
+        assert_eq!(rows[0].mean_score, Some(0.5));
+        assert_eq!(rows[1].errors, 4);
+        assert_eq!(rows[1].mean_score, None);
+        let table = render_table(&rows);
+        assert!(table.lines().next().unwrap().starts_with("model"));
+        assert_eq!(table.lines().count(), 3);
+    }
+
+    /// Grades every answer 0.8 and remembers what it was asked.
+    #[derive(Debug, Default)]
+    struct Grader {
+        prompts: std::sync::Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl ChatProvider for Grader {
+        fn name(&self) -> &str {
+            "grader"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            let prompt = req.messages.last().unwrap().content.text();
+            self.prompts.lock().unwrap().push(prompt.into_owned());
+            let mut resp = NullProvider.chat(req).await?;
+            resp.text = r#"{"score": 0.8, "reason": "mostly right"}"#.into();
+            resp.cost_usd = Some(0.01);
+            Ok(resp)
+        }
+    }
+
+    #[tokio::test]
+    async fn judge_scores_answers_against_the_reference() {
+        let cfg = null_cfg();
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let router = RoutingResolver::new(&cfg).unwrap();
+        let grader = Grader::default();
+        let judge = Judge {
+            model: "judge-model",
+            provider: &grader,
+        };
+        let cases = parse_cases(r#"{"prompt": "Capital of France?", "expected": "Paris"}"#);
+        let opts = EvalOpts {
+            concurrency: 1,
+            deadline: Duration::from_secs(5),
+        };
+        let models = ["model-a".to_string()];
+        let results = run(&reg, &router, cases, &models, Some(&judge), opts).await;
+
+        assert_eq!(results[0].score, Some(0.8));
+        assert_eq!(results[0].judge_note.as_deref(), Some("mostly right"));
+        assert_eq!(results[0].judge_cost_usd, Some(0.01));
+        let prompt = &grader.prompts.lock().unwrap()[0];
+        assert!(prompt.contains("Capital of France?"));
+        assert!(prompt.contains("Reference answer:\nParis"));
+        assert!(prompt.contains("Candidate answer:\n[null provider response]"));
+    }
+}
@@ -0,0 +1,162 @@
+//! Process exit codes by error category (see docs/error.md), so scripts can branch on
+//! the kind of failure. Code 2 is left to clap for usage errors.
+
+use aiproxy_core::error::{AiProxyError, ErrorCategory};
+use serde_json::{Value, json};
+
+pub const FAILURE: i32 = 1;
+pub const VALIDATION: i32 = 3;
+pub const RATE_LIMITED: i32 = 4;
+pub const BUDGET_EXCEEDED: i32 = 5;
+pub const PROVIDER_UNAVAILABLE: i32 = 6;
+pub const PROVIDER_ERROR: i32 = 7;
+pub const IO: i32 = 8;
+/// A streaming command was interrupted with Ctrl-C (128 + SIGINT, as shells report it).
+pub const CANCELLED: i32 = 130;
+
+/// Category name and exit code; errors that did not come from core are generic failures.
+fn classify(err: &anyhow::Error) -> (&'static str, i32) {
+    let category = match err
+        .downcast_ref::<AiProxyError>()
+        .map(AiProxyError::category)
+    {
+        Some(ErrorCategory::Other) | None if err.downcast_ref::<std::io::Error>().is_some() => {
+            ErrorCategory::Io
+        }
+        Some(c) => c,
+        None => ErrorCategory::Other,
+    };
+    let code = match category {
+        ErrorCategory::Validation => VALIDATION,
+        ErrorCategory::RateLimited => RATE_LIMITED,
+        ErrorCategory::BudgetExceeded => BUDGET_EXCEEDED,
+        ErrorCategory::ProviderUnavailable => PROVIDER_UNAVAILABLE,
+        ErrorCategory::ProviderError => PROVIDER_ERROR,
+        ErrorCategory::Io => IO,
+        ErrorCategory::Other => FAILURE,
+    };
+    (category.as_str(), code)
+}
+
+pub fn code_for(err: &anyhow::Error) -> i32 {
+    classify(err).1
+}
+
+/// `{"error": {"kind", "message", "exit_code", ...}}` with provider details when known.
+pub fn error_json(err: &anyhow::Error) -> Value {
+    let (kind, exit_code) = classify(err);
+    let mut body = json!({
+        "kind": kind,
+        "message": format!("{err:#}"),
+        "exit_code": exit_code,
+        "retryable": err
+            .downcast_ref::<AiProxyError>()
+            .is_some_and(AiProxyError::is_retryable),
+    });
+    match err.downcast_ref::<AiProxyError>() {
+        Some(AiProxyError::RateLimited {
+            provider,
+            retry_after,
+            ..
+        }) => {
+            body["provider"] = json!(provider);
+            body["retry_after"] = json!(retry_after);
+        }
+        Some(AiProxyError::ProviderUnavailable { provider, .. }) => {
+            body["provider"] = json!(provider);
+        }
+        Some(AiProxyError::ProviderError { provider, code, .. }) => {
+            body["provider"] = json!(provider);
+            body["code"] = json!(code);
+        }
+        Some(AiProxyError::BudgetExceeded { remaining }) => {
+            body["remaining"] = json!(remaining);
+        }
+        _ => {}
+    }
+    if let Some(upstream) = err
+        .downcast_ref::<AiProxyError>()
+        .and_then(AiProxyError::upstream)
+    {
+        body["model"] = json!(upstream.model);
+        body["status"] = json!(upstream.status);
+        body["provider_request_id"] = json!(upstream.provider_request_id);
+    }
+    json!({ "error": body })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aiproxy_core::error::Upstream;
+
+    #[test]
+    fn core_errors_map_to_distinct_codes() {
+        let cases = [
+            (AiProxyError::Validation("x".into()), VALIDATION),
+            (
+                AiProxyError::RateLimited {
+                    provider: "p".into(),
+                    retry_after: Some(3),
+                    remaining_requests: None,
+                    remaining_tokens: None,
+                    reset_at_ms: None,
+                    upstream: Default::default(),
+                },
+                RATE_LIMITED,
+            ),
+            (
+                AiProxyError::BudgetExceeded { remaining: 0 },
+                BUDGET_EXCEEDED,
+            ),
+            (
+                AiProxyError::ProviderUnavailable {
+                    provider: "p".into(),
+                    upstream: Default::default(),
+                },
+                PROVIDER_UNAVAILABLE,
+            ),
+            (
+                AiProxyError::ProviderError {
+                    provider: "p".into(),
+                    code: "400".into(),
+                    message: "bad".into(),
+                    upstream: Default::default(),
+                },
+                PROVIDER_ERROR,
+            ),
+        ];
+        for (e, code) in cases {
+            assert_eq!(code_for(&e.into()), code);
+        }
+        assert_eq!(code_for(&anyhow::anyhow!("plain")), FAILURE);
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        assert_eq!(code_for(&io.into()), IO);
+    }
+
+    #[test]
+    fn error_json_includes_provider_details() {
+        let err: anyhow::Error = AiProxyError::RateLimited {
+            provider: "openai".into(),
+            retry_after: Some(7),
+            remaining_requests: None,
+            remaining_tokens: None,
+            reset_at_ms: None,
+            upstream: Box::new(Upstream {
+                model: Some("gpt-4o".into()),
+                status: Some(429),
+                provider_request_id: Some("req_abc".into()),
+            }),
+        }
+        .into();
+        let v = error_json(&err);
+        assert_eq!(v["error"]["kind"], "rate_limited");
+        assert_eq!(v["error"]["retryable"], true);
+        assert_eq!(v["error"]["model"], "gpt-4o");
+        assert_eq!(v["error"]["status"], 429);
+        assert_eq!(v["error"]["provider_request_id"], "req_abc");
+        assert_eq!(v["error"]["exit_code"], RATE_LIMITED);
+        assert_eq!(v["error"]["provider"], "openai");
+        assert_eq!(v["error"]["retry_after"], 7);
+    }
+}
@@ -0,0 +1,73 @@
+//! `aiproxy keys check` rendering.
+
+use aiproxy_core::provider_factory::{KeyCheck, KeyState};
+
+fn label(state: KeyState) -> &'static str {
+    match state {
+        KeyState::Valid => "valid",
+        KeyState::Missing => "missing",
+        KeyState::Malformed => "MALFORMED",
+        KeyState::Rejected => "REJECTED",
+        KeyState::ProjectScoped => "project-scoped",
+        KeyState::Unverified => "unverified",
+    }
+}
+
+/// True when a key is present but unusable; these make `keys check` exit non-zero.
+pub fn is_failure(check: &KeyCheck) -> bool {
+    matches!(
+        check.state,
+        KeyState::Malformed | KeyState::Rejected | KeyState::ProjectScoped
+    )
+}
+
+/// One line per provider: name, env var, state and detail.
+pub fn render_checks(checks: &[KeyCheck]) -> String {
+    let mut out = String::new();
+    for c in checks {
+        let line = format!(
+            "{:<11} {:<19} {:<15} {}",
+            c.provider,
+            c.env,
+            label(c.state),
+            c.detail.as_deref().unwrap_or("")
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(provider: &str, state: KeyState, detail: Option<&str>) -> KeyCheck {
+        KeyCheck {
+            provider: provider.into(),
+            env: format!("{}_API_KEY", provider.to_uppercase()),
+            state,
+            detail: detail.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn renders_states_and_flags_failures() {
+        let checks = vec![
+            check(
+                "openai",
+                KeyState::ProjectScoped,
+                Some("set OPENAI_PROJECT"),
+            ),
+            check("anthropic", KeyState::Missing, None),
+            check("openrouter", KeyState::Valid, Some("3 models visible")),
+        ];
+        let text = render_checks(&checks);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].contains("project-scoped") && lines[0].ends_with("set OPENAI_PROJECT"));
+        assert!(lines[1].ends_with("missing"));
+        assert!(lines[2].contains("OPENROUTER_API_KEY"));
+        let failed: Vec<bool> = checks.iter().map(is_failure).collect();
+        assert_eq!(failed, vec![true, false, false]);
+    }
+}
@@ -1,15 +1,57 @@
 use aiproxy_core::{
-    config::{Config, HttpCfg},
-    model::{ChatMessage, ChatRequest, EmbedRequest, Role},
-    provider_factory::ProviderRegistry,
+    config::{
+        CONFIG_ENV, CatalogCfg, ChaosCfg, Config, Diagnostic, EnvOverride, HttpCfg, PrivacyCfg,
+        ScreeningCfg, Severity, SummarizationCfg, TruncationCfg, ValidationCfg,
+    },
+    error::AiProxyError,
+    guardrail::{Guardrail, GuardrailMiddleware},
+    middleware::MiddlewareProvider,
+    model::{ChatRequest, EmbedRequest, StopReason, TruncationReport},
+    normalizer::{self, ChatRules, truncate_measured},
+    provider::{Capability, ChatProvider},
+    provider_factory::{self, ProviderRegistry},
     router::RoutingResolver,
+    stream::StreamRecorder,
+    telemetry::{FanoutSink, TelemetrySink, access::AccessLogWriter, metrics::MetricsSink},
+    tokenizer,
+    usage::store::UsageStore,
 };
 use clap::{Parser, Subcommand};
 use futures_util::StreamExt;
 
+use chat_args::ChatArgs;
+
+mod bench;
+mod chat_args;
+mod chat_batch;
+mod embed_batch;
+mod eval;
+mod exit;
+mod keys;
+mod markdown;
+mod models;
+mod output;
+mod route;
+mod server;
+mod session;
+mod similarity;
+mod usage;
+
 #[derive(Parser)]
-#[command(author, version, about = "ai-proxy CLI smoke tool", long_about = None)]
+#[command(name = "aiproxy", author, version, about = "ai-proxy CLI smoke tool", long_about = None)]
 struct Cli {
+    #[arg(
+        long,
+        global = true,
+        help = "Config file (JSON or TOML); defaults to $AIPROXY_CONFIG, then a built-in config"
+    )]
+    config: Option<std::path::PathBuf>,
+    #[arg(
+        long,
+        global = true,
+        help = "Print full responses / NDJSON events instead of text"
+    )]
+    json: bool,
     #[command(subcommand)]
     command: Commands,
 }
@@ -17,33 +59,213 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     /// Send a chat completion request
-    Chat {
-        #[arg(long)]
-        model: String,
-        #[arg(short, long, help = "Message from the user")]
-        message: String,
-    },
+    Chat(ChatArgs),
     /// Stream a chat completion (prints deltas live)
-    ChatStream {
-        #[arg(long)]
-        model: String,
-        #[arg(short, long, help = "Message from the user")]
-        message: String,
+    ChatStream(ChatArgs),
+    /// Run every chat request in a JSONL file; resumable via the output file, retrying failures
+    ChatBatch {
+        #[arg(
+            long,
+            help = "JSONL: one {model?, messages | prompt, system?, ...} per line"
+        )]
+        input: std::path::PathBuf,
+        #[arg(
+            long,
+            help = "Results JSONL; existing successes are kept and skipped, failures are retried"
+        )]
+        out: std::path::PathBuf,
+        #[arg(long, help = "Model for lines that do not set one")]
+        model: Option<String>,
+        #[arg(long, default_value_t = 4, help = "Requests in flight at once")]
+        concurrency: usize,
     },
     /// Send an embedding request
+    #[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
     Embed {
+        #[command(subcommand)]
+        command: Option<EmbedCommand>,
+        #[arg(long, required = true)]
+        model: Option<String>,
+        #[arg(short, long, required = true, help = "Input text")]
+        input: Option<String>,
+    },
+    /// Embed every line of a text/JSONL file and write vectors as NDJSON
+    EmbedBatch {
         #[arg(long)]
         model: String,
-        #[arg(short, long, help = "Input text")]
-        input: String,
+        #[arg(long, help = "Input file: .txt (one input per line) or .jsonl")]
+        input: std::path::PathBuf,
+        #[arg(long, help = "Output NDJSON file")]
+        out: std::path::PathBuf,
+        #[arg(long, default_value_t = 4, help = "Requests in flight at once")]
+        concurrency: usize,
+        #[arg(
+            long,
+            default_value_t = 256,
+            help = "Inputs per request (capped by the provider)"
+        )]
+        batch_size: usize,
+        #[arg(long, help = "Maximum requests started per second")]
+        rps: Option<f64>,
+    },
+    /// Compare latency, TTFT, throughput and cost across models
+    Bench {
+        #[arg(
+            long = "model",
+            required = true,
+            help = "Model to benchmark. Repeatable"
+        )]
+        models: Vec<String>,
+        #[arg(long, help = "File whose contents are sent as the user prompt")]
+        prompt_file: std::path::PathBuf,
+        #[arg(long, default_value_t = 3)]
+        runs: usize,
+        #[arg(long, help = "Also write every individual run as NDJSON to this file")]
+        out: Option<std::path::PathBuf>,
+    },
+    /// Score models on a JSONL prompt set, optionally graded by a judge model
+    Eval {
+        #[arg(
+            long,
+            help = "JSONL: one {id?, messages | prompt, system?, expected?} per line"
+        )]
+        input: std::path::PathBuf,
+        #[arg(
+            long = "model",
+            required = true,
+            help = "Model to evaluate. Repeatable"
+        )]
+        models: Vec<String>,
+        #[arg(long, help = "Model that scores each answer from 0 to 1")]
+        judge: Option<String>,
+        #[arg(long, default_value_t = 4, help = "Prompts in flight at once")]
+        concurrency: usize,
+        #[arg(
+            long,
+            default_value_t = 120,
+            help = "Seconds each prompt may take across all models"
+        )]
+        timeout_secs: u64,
+        #[arg(
+            long,
+            help = "Also write every individual result as NDJSON to this file"
+        )]
+        out: Option<std::path::PathBuf>,
+    },
+    /// List, show or delete saved chat sessions
+    Session {
+        #[command(subcommand)]
+        command: SessionCommand,
+    },
+    /// Inspect routing decisions
+    Route {
+        #[command(subcommand)]
+        command: RouteCommand,
+    },
+    /// Count prompt tokens
+    Tokens {
+        #[command(subcommand)]
+        command: TokensCommand,
+    },
+    /// List models offered by each provider and where routing would send them
+    Models {
+        #[arg(long, help = "Only query this provider")]
+        provider: Option<String>,
+    },
+    /// Manage provider credentials
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+    /// Summarize recorded token usage and estimated cost
+    Usage {
+        #[arg(
+            long,
+            default_value = "7d",
+            value_parser = usage::parse_since,
+            help = "Lookback, e.g. 24h, 7d, 2w"
+        )]
+        since: std::time::Duration,
+        #[arg(
+            long,
+            default_value = "model",
+            help = "model, provider, client_key or day"
+        )]
+        group_by: aiproxy_core::usage::GroupBy,
+    },
+    /// Inspect the resolved config
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Alias of `config validate` for the config file at `path`
+    ValidateConfig { path: std::path::PathBuf },
+    /// Run an OpenAI- and Anthropic-compatible HTTP server
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080", help = "Address to bind")]
+        addr: std::net::SocketAddr,
+        #[arg(
+            long,
+            help = "Env var holding comma-separated client keys to accept (default: no auth)"
+        )]
+        api_keys_env: Option<String>,
+        #[arg(
+            long,
+            help = "Env var holding the key for the /admin API (default: disabled)"
+        )]
+        admin_key_env: Option<String>,
+        #[arg(
+            long = "cors-origin",
+            help = "Allow browser calls from this origin ('*' for any). Repeatable"
+        )]
+        cors_origins: Vec<String>,
+        #[arg(
+            long = "cors-header",
+            help = "Request header allowed for CORS (default: the headers the API reads). Repeatable"
+        )]
+        cors_headers: Vec<String>,
+        #[arg(
+            long = "cors-method",
+            help = "Method allowed for CORS (default: GET, POST, OPTIONS). Repeatable"
+        )]
+        cors_methods: Vec<String>,
+        #[arg(long, help = "Allow credentials (cookies, auth) on CORS requests")]
+        cors_credentials: bool,
+        #[arg(long, help = "Requests per minute allowed per client key")]
+        rpm: Option<u32>,
+        #[arg(long, help = "Tokens per minute allowed per client key")]
+        tpm: Option<u32>,
+        #[arg(long, help = "Tokens per UTC day allowed per client key")]
+        daily_tokens: Option<u64>,
+        #[arg(
+            long,
+            default_value_t = server::DEFAULT_MAX_BODY_BYTES,
+            help = "Largest request body accepted, in bytes; larger bodies get 413"
+        )]
+        max_body_bytes: usize,
+        #[arg(long, help = "API requests handled at once; further requests get 429")]
+        max_concurrent_requests: Option<usize>,
+        #[arg(long, help = "Streaming responses open at once per client key")]
+        max_streams_per_key: Option<usize>,
+        #[arg(
+            long,
+            help = "Append one NDJSON access log line per request to this file ('-' for stderr)"
+        )]
+        access_log: Option<std::path::PathBuf>,
+        #[arg(
+            long,
+            default_value = "omit",
+            help = "Prompt/response text in access logs: omit, redact (mask keys and emails) or full"
+        )]
+        access_log_content: aiproxy_core::telemetry::access::ContentPolicy,
+        #[cfg(feature = "grpc")]
+        #[arg(long, help = "Also serve the gRPC API on this address")]
+        grpc_addr: Option<std::net::SocketAddr>,
     },
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-
-    // For now, just load default config (ignores file loading).
+/// Built-in config used when no `--config` is given.
+fn default_config() -> Config {
     // Pick a sensible default provider based on env presence.
     let default_provider = if std::env::var("OPENAI_API_KEY").is_ok() {
         "openai"
@@ -52,11 +274,14 @@ async fn main() -> anyhow::Result<()> {
     } else {
         "null"
     };
-    let cfg = Config {
+    Config {
         providers: aiproxy_core::config::Providers {
             openai: None,
             anthropic: None,
             openrouter: None,
+            hash: None,
+            ollama: None,
+            replay: None,
         },
         cache: aiproxy_core::config::CacheCfg {
             path: ":memory:".into(),
@@ -71,66 +296,466 @@ async fn main() -> anyhow::Result<()> {
         routing: aiproxy_core::config::RoutingCfg {
             default: default_provider.into(),
             rules: vec![],
+            aliases: Default::default(),
         },
         http: HttpCfg::default(),
+        privacy: PrivacyCfg::default(),
+        truncation: TruncationCfg::default(),
+        screening: ScreeningCfg::default(),
+        guardrail: Default::default(),
+        summarization: SummarizationCfg::default(),
+        validation: ValidationCfg::default(),
+        embeddings: Default::default(),
+        dedup: Default::default(),
+        catalog: CatalogCfg::default(),
+        pricing: Default::default(),
+        chaos: ChaosCfg::default(),
+        tenants: Default::default(),
+    }
+}
+
+/// Summarize older turns of `req` when `[summarization]` asks for it. Failures are reported
+/// and the history is sent as it was.
+async fn compress_history(
+    cfg: &Config,
+    reg: &ProviderRegistry,
+    router: &RoutingResolver,
+    req: &mut ChatRequest,
+    json: bool,
+) {
+    let Some(model) = &cfg.summarization.model else {
+        return;
+    };
+    let compressed = match router.select_chat(reg, model) {
+        Ok(summarizer) => {
+            aiproxy_core::summarize::compress_history(req, &cfg.summarization, summarizer.as_ref())
+                .await
+        }
+        Err(e) => Err(e),
     };
+    match compressed {
+        Ok(Some(n)) if !json => eprintln!("summarized {n} earlier message(s)"),
+        Err(e) => eprintln!("warning: history summarization skipped: {e}"),
+        _ => {}
+    }
+}
+
+/// Apply the configured prompt budget to `req`, noting on stderr what was cut.
+async fn fit_prompt(
+    cfg: &Config,
+    req: &mut ChatRequest,
+    provider: &dyn ChatProvider,
+    json: bool,
+) -> Option<TruncationReport> {
+    cfg.truncation.budget(&req.model, None)?;
+    let measured = tokenizer::count_prompt(provider, req, cfg.truncation.exact_counts).await;
+    if cfg.truncation.auto_fit
+        && let Some(requested) = normalizer::fit_output(req, &cfg.truncation, measured.tokens)
+        && !json
+    {
+        eprintln!(
+            "lowered max_output_tokens from {requested} to {} to fit {}",
+            req.max_output_tokens.unwrap_or(0),
+            req.model
+        );
+    }
+    let budget = cfg.truncation.budget(&req.model, req.max_output_tokens)?;
+    let report = truncate_measured(req, budget, measured.tokens)?;
+    if !json {
+        eprintln!(
+            "truncated: dropped {} message(s){} to fit {budget} prompt tokens",
+            report.dropped.len(),
+            if report.truncated.is_some() {
+                " and shortened one"
+            } else {
+                ""
+            },
+        );
+    }
+    Some(report)
+}
+
+/// Report the config file at `path` that `config validate` could not load, and exit like
+/// `exit_for`.
+fn unloadable_config(path: &std::path::Path, err: &AiProxyError, json: bool) -> ! {
+    let diags = vec![Diagnostic {
+        severity: Severity::Error,
+        field: path.display().to_string(),
+        message: err.to_string(),
+    }];
+    if json {
+        println!("{}", serde_json::json!(diags));
+    } else {
+        print_diagnostics(&diags, &path.display().to_string());
+    }
+    exit_for(&diags);
+}
+
+/// Print the config `run` resolved, secrets masked, with the env overrides applied to it
+/// and its diagnostics, then exit like `exit_for`.
+fn validate_resolved(
+    cfg: &Config,
+    source: Option<&std::path::Path>,
+    overrides: &[EnvOverride],
+    json: bool,
+) -> anyhow::Result<()> {
+    let diags = cfg.validate();
+    let mut resolved = serde_json::to_value(cfg)?;
+    aiproxy_core::telemetry::access::redact_json(&mut resolved);
+    if json {
+        output::print_json(&serde_json::json!({
+            "source": source,
+            "overrides": overrides,
+            "config": resolved,
+            "diagnostics": diags,
+        }))?;
+    } else {
+        let source = source.map_or("built-in config".into(), |p| p.display().to_string());
+        println!("# {source}");
+        for o in overrides {
+            println!("# {} sets {}", o.var, o.field);
+        }
+        println!("{}", serde_json::to_string_pretty(&resolved)?);
+        print_diagnostics(&diags, &source);
+    }
+    exit_for(&diags);
+}
+
+fn print_diagnostics(diags: &[Diagnostic], source: &str) {
+    for d in diags {
+        let level = match d.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        println!("{level}: {}: {}", d.field, d.message);
+    }
+    let errors = diags
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    println!(
+        "{source}: {errors} error(s), {} warning(s)",
+        diags.len() - errors
+    );
+}
+
+/// Exit 0 when `diags` are clean or warnings only, `exit::VALIDATION` otherwise.
+fn exit_for(diags: &[Diagnostic]) -> ! {
+    let failed = diags.iter().any(|d| d.severity == Severity::Error);
+    std::process::exit(if failed { exit::VALIDATION } else { 0 });
+}
+
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Print the config after `--config`/`$AIPROXY_CONFIG` and `AIPROXY_*` overrides, with
+    /// secrets masked, and its diagnostics; exits 3 if any errors are found
+    Validate,
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Make one authenticated call per provider key and report which keys work
+    Check,
+}
+
+#[derive(Subcommand)]
+enum EmbedCommand {
+    /// Print the cosine similarity of two texts, or of every pair of lines in a file
+    Similarity {
+        #[arg(long)]
+        model: String,
+        #[arg(
+            num_args = 2,
+            required_unless_present = "file",
+            conflicts_with = "file"
+        )]
+        texts: Vec<String>,
+        #[arg(long, help = "Compare every pair of inputs in a .txt or .jsonl file")]
+        file: Option<std::path::PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum SessionCommand {
+    /// List saved sessions
+    List,
+    /// Print a session's messages
+    Show { name: String },
+    /// Delete a session
+    Delete { name: String },
+}
+
+#[derive(Subcommand)]
+enum RouteCommand {
+    /// Show the matched rule, chosen provider, capability checks and fallbacks for a model
+    Explain {
+        model: String,
+        #[arg(long, help = "Require streaming support")]
+        stream: bool,
+        #[arg(long, help = "Require tool-calling support")]
+        tools: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokensCommand {
+    /// Count the prompt tokens `chat` would send with the same flags
+    Count {
+        #[command(flatten)]
+        args: ChatArgs,
+        #[arg(
+            long,
+            help = "Ask the routed provider for an exact count where it offers one"
+        )]
+        exact: bool,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let json = cli.json;
+    let result = run(cli).await;
+    finish_background_calls().await;
+    if let Err(e) = result {
+        if json {
+            println!("{}", exit::error_json(&e));
+        } else {
+            eprintln!("Error: {e:#}");
+        }
+        std::process::exit(exit::code_for(&e));
+    }
+}
+
+/// How long an exiting command waits for shadow calls still in flight.
+const SHADOW_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Wait for the shadow calls and transcript records still in flight, which exiting would
+/// lose.
+async fn finish_background_calls() {
+    let drain = aiproxy_core::providers::shadow::ShadowProvider::drain();
+    let _ = tokio::time::timeout(SHADOW_DRAIN_TIMEOUT, drain).await;
+    if let Some(writer) = aiproxy_core::transcript::installed() {
+        let _ = writer.flush();
+    }
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    // These inspect config and credentials, so they run before the registry rejects either.
+    if let Commands::Keys {
+        command: KeysCommand::Check,
+    } = &cli.command
+    {
+        let checks = provider_factory::check_keys().await;
+        if cli.json {
+            output::print_json(&checks)?;
+        } else {
+            print!("{}", keys::render_checks(&checks));
+        }
+        std::process::exit(i32::from(checks.iter().any(keys::is_failure)));
+    }
+
+    let validating = matches!(
+        cli.command,
+        Commands::ValidateConfig { .. }
+            | Commands::Config {
+                command: ConfigCommand::Validate
+            }
+    );
+    let source = match &cli.command {
+        Commands::ValidateConfig { path } => Some(path.clone()),
+        _ => cli
+            .config
+            .clone()
+            .or_else(|| std::env::var_os(CONFIG_ENV).map(std::path::PathBuf::from)),
+    };
+    let mut cfg = match &source {
+        Some(path) => match Config::from_path(path) {
+            Ok(cfg) => cfg,
+            Err(e) if validating => unloadable_config(path, &e, cli.json),
+            Err(e) => return Err(e.into()),
+        },
+        None => default_config(),
+    };
+    let env = std::env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)));
+    let overrides = cfg.apply_env_overrides(env)?;
+    if validating {
+        validate_resolved(&cfg, source.as_deref(), &overrides, cli.json)?;
+    }
+    aiproxy_core::catalog::set_overrides(cfg.catalog.models.clone());
+    aiproxy_core::pricing::set_overrides(cfg.pricing.clone());
+
+    let metrics = std::sync::Arc::new(MetricsSink::new());
+    // Commands that make billable calls record into the usage store read by `aiproxy usage`.
+    let usage_db =
+        std::path::Path::new(&cfg.transcript.dir).join(aiproxy_core::usage::store::USAGE_DB);
+    let mut usage_store = None;
+    if matches!(
+        cli.command,
+        Commands::Chat(_)
+            | Commands::ChatStream(_)
+            | Commands::ChatBatch { .. }
+            | Commands::Embed { .. }
+            | Commands::EmbedBatch { .. }
+            | Commands::Bench { .. }
+            | Commands::Eval { .. }
+            | Commands::Serve { .. }
+    ) {
+        let store = std::sync::Arc::new(UsageStore::open(&usage_db)?);
+        let mut sinks: Vec<std::sync::Arc<dyn TelemetrySink>> = vec![store.clone()];
+        usage_store = Some(store);
+        // `serve` also aggregates the same completions for `GET /metrics`.
+        if let Commands::Serve { access_log, .. } = &cli.command {
+            // Server warnings are `tracing` events written to stderr; `RUST_LOG` overrides
+            // the level.
+            let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("warn"));
+            tracing_subscriber::fmt()
+                .with_env_filter(filter)
+                .with_writer(std::io::stderr)
+                .init();
+            sinks.push(metrics.clone());
+            // Access events reach every sink; only this one writes them out.
+            match access_log.as_deref() {
+                Some(p) if p == std::path::Path::new("-") => {
+                    sinks.push(std::sync::Arc::new(AccessLogWriter::new(std::io::stderr())))
+                }
+                Some(p) => sinks.push(std::sync::Arc::new(AccessLogWriter::open(p)?)),
+                None => {}
+            }
+            #[cfg(feature = "otel")]
+            if let Ok(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+                let service =
+                    std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "aiproxy".into());
+                let otlp = std::sync::Arc::new(aiproxy_core::telemetry::otel::OtlpSink::new(
+                    &endpoint, &service,
+                ));
+                otlp.clone().spawn(std::time::Duration::from_secs(5));
+                sinks.push(otlp);
+            }
+        }
+        aiproxy_core::telemetry::set_telemetry_sink(std::sync::Arc::new(FanoutSink(sinks)));
+        // The same calls are transcribed by every provider the registry below builds.
+        aiproxy_core::transcript::install(std::sync::Arc::new(
+            aiproxy_core::transcript::TranscriptWriter::open(&cfg.transcript)?,
+        ));
+    }
 
     let reg = ProviderRegistry::from_config(&cfg)?;
     let router = RoutingResolver::new(&cfg)?;
 
     match cli.command {
-        Commands::Chat { model, message } => {
-            let provider = router.select_chat(&reg, &model)?;
-            let req = ChatRequest {
-                model,
-                messages: vec![ChatMessage {
-                    role: Role::User,
-                    content: message,
-                }],
-                temperature: None,
-                top_p: None,
-                metadata: None,
-                client_key: None,
-                request_id: None,
-                trace_id: None,
-                idempotency_key: None,
-                max_output_tokens: None,
-                stop_sequences: None,
-            };
-            let resp = provider.chat(req).await?;
-            println!("{} -> {}", resp.provider, resp.text);
-        }
-        Commands::ChatStream { model, message } => {
-            let provider = router.select_chat(&reg, &model)?;
-            let req = ChatRequest {
-                model,
-                messages: vec![ChatMessage { role: Role::User, content: message }],
-                temperature: None,
-                top_p: None,
-                metadata: None,
-                client_key: None,
-                request_id: None,
-                trace_id: None,
-                idempotency_key: None,
-                max_output_tokens: None,
-                stop_sequences: None,
-            };
+        Commands::Chat(args) => {
+            let guardrail = Guardrail::from_cfg(&cfg.guardrail)?;
+            let moderator = guardrail
+                .moderation_provider()
+                .and_then(|name| reg.moderation(name));
+            let provider: std::sync::Arc<dyn ChatProvider> =
+                std::sync::Arc::new(MiddlewareProvider::new(
+                    router.select_chat(&reg, &args.model)?,
+                    std::sync::Arc::new(GuardrailMiddleware::new(guardrail, moderator)),
+                ));
+            let store = session::SessionStore::from_env();
+            let session_name = args.session.clone();
+            let renderer = args.render.renderer();
+            let mut req = args.into_request()?;
+            if let Some(name) = &session_name {
+                store.load(name)?.apply(&mut req);
+            }
+            compress_history(&cfg, &reg, &router, &mut req, cli.json).await;
+            let truncation = fit_prompt(&cfg, &mut req, provider.as_ref(), cli.json).await;
+            normalizer::validate_chat(
+                &req,
+                &ChatRules::for_request(&cfg.validation, &cfg.truncation, provider.as_ref(), &req),
+            )?;
+            let mut resp = provider.chat(req.clone()).await?;
+            resp.truncation = truncation;
+            if let Some(name) = &session_name {
+                store.save(name, &session::Session::after_turn(&req, resp.text.clone()))?;
+            }
+            if cli.json {
+                output::print_json(&resp)?;
+            } else if let Some(mut md) = renderer {
+                println!("{} ->", resp.provider);
+                print!("{}", md.push(&resp.text));
+                println!("{}", md.finish());
+            } else {
+                println!("{} -> {}", resp.provider, resp.text);
+            }
+            if let (false, Some(refusal)) = (cli.json, &resp.refusal) {
+                eprintln!("refused: {refusal}");
+            }
+        }
+        Commands::ChatStream(args) => {
+            Guardrail::from_cfg(&cfg.guardrail)?.allow_stream()?;
+            let provider = router.select_chat(&reg, &args.model)?;
+            let store = session::SessionStore::from_env();
+            let session_name = args.session.clone();
+            let mut renderer = args.render.renderer();
+            let mut recorder = args.out.as_ref().map(StreamRecorder::create).transpose()?;
+            let mut req = args.into_request()?;
+            if let Some(name) = &session_name {
+                store.load(name)?.apply(&mut req);
+            }
+            compress_history(&cfg, &reg, &router, &mut req, cli.json).await;
+            fit_prompt(&cfg, &mut req, provider.as_ref(), cli.json).await;
+            normalizer::validate_chat(
+                &req,
+                &ChatRules::for_request(&cfg.validation, &cfg.truncation, provider.as_ref(), &req),
+            )?;
 
-            let mut stream = provider.chat_stream_events(req).await?;
+            let stream = provider.chat_stream_events(req.clone()).await?;
             use aiproxy_core::stream::StreamEvent;
             use std::io::{self, Write};
+            let (cancel, mut stream) =
+                aiproxy_core::stream::cancellable(stream, provider.name(), &req.model);
+            tokio::spawn(async move {
+                if tokio::signal::ctrl_c().await.is_ok() {
+                    cancel.cancel();
+                }
+            });
             let mut saw_delta = false;
+            let mut reply = String::new();
+            let mut error = None;
+            let mut cancelled = false;
             while let Some(ev) = stream.next().await {
+                match &ev {
+                    StreamEvent::DeltaText(txt) => reply.push_str(txt),
+                    StreamEvent::Final(resp) if reply.is_empty() => reply.push_str(&resp.text),
+                    StreamEvent::Stop {
+                        reason: Some(StopReason::Cancelled),
+                    } => cancelled = true,
+                    _ => {}
+                }
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.record(&ev)?;
+                }
+                // The error is reported on exit, with its exit code (and as JSON under --json).
+                if let StreamEvent::Error(err) = ev {
+                    error = Some(err);
+                    break;
+                }
+                if cli.json {
+                    output::print_json(&ev.to_json())?;
+                    continue;
+                }
                 match ev {
                     StreamEvent::DeltaText(txt) => {
                         saw_delta = true;
-                        print!("{}", txt);
+                        match renderer.as_mut() {
+                            Some(md) => print!("{}", md.push(&txt)),
+                            None => print!("{}", txt),
+                        }
                         io::stdout().flush().ok();
                     }
                     StreamEvent::Usage { .. } => {
                         // Optional: could log usage here
                     }
                     StreamEvent::Stop { reason } => {
+                        if let Some(md) = renderer.as_mut() {
+                            print!("{}", md.finish());
+                        }
                         if saw_delta {
                             println!();
                         }
@@ -138,26 +763,425 @@ async fn main() -> anyhow::Result<()> {
                     }
                     StreamEvent::Final(resp) => {
                         // Non-streaming providers produce a single Final
-                        println!("{}", resp.text);
-                    }
-                    StreamEvent::Error(err) => {
-                        eprintln!("[error: {:?}]", err);
-                        break;
+                        match renderer.as_mut() {
+                            Some(md) => println!("{}{}", md.push(&resp.text), md.finish()),
+                            None => println!("{}", resp.text),
+                        }
                     }
                     _ => {}
                 }
             }
+            if let Some(recorder) = recorder {
+                recorder.finish()?;
+            }
+            if cancelled {
+                io::stdout().flush().ok();
+                // Dropping the stream records the cancelled call before the transcript flush.
+                drop(stream);
+                finish_background_calls().await;
+                std::process::exit(exit::CANCELLED);
+            }
+            // A failed turn is not saved, so retrying with the same --session starts clean.
+            if let Some(err) = error {
+                if !cli.json {
+                    if let Some(md) = renderer.as_mut() {
+                        print!("{}", md.finish());
+                    }
+                    if saw_delta {
+                        println!();
+                    }
+                }
+                return Err(err.into());
+            }
+            if let Some(name) = &session_name {
+                store.save(name, &session::Session::after_turn(&req, reply))?;
+            }
+        }
+        Commands::Usage { since, group_by } => {
+            let now_ms = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)?
+                .as_millis() as u64;
+            let since_ms = now_ms.saturating_sub(since.as_millis() as u64);
+            let filter = aiproxy_core::usage::store::UsageFilter {
+                since_ms,
+                ..Default::default()
+            };
+            let rows = UsageStore::open(&usage_db)?.aggregate(&filter, group_by)?;
+            if cli.json {
+                output::print_json(&rows)?;
+            } else {
+                print!("{}", usage::render_table(group_by.as_str(), &rows));
+            }
+        }
+        Commands::Session { command } => {
+            let store = session::SessionStore::from_env();
+            match command {
+                SessionCommand::List => {
+                    let sessions = store.list()?;
+                    if cli.json {
+                        let rows: Vec<_> = sessions
+                            .iter()
+                            .map(|(name, n)| serde_json::json!({"name": name, "messages": n}))
+                            .collect();
+                        output::print_json(&rows)?;
+                    } else if sessions.is_empty() {
+                        eprintln!("no sessions in {}", store.dir().display());
+                    } else {
+                        for (name, n) in sessions {
+                            println!("{name}\t{n} messages");
+                        }
+                    }
+                }
+                SessionCommand::Show { name } => {
+                    let s = store.load(&name)?;
+                    if s.messages.is_empty() {
+                        anyhow::bail!("no session named '{name}'");
+                    }
+                    if cli.json {
+                        output::print_json(&s)?;
+                    } else {
+                        print!("{}", session::render_session(&s));
+                    }
+                }
+                SessionCommand::Delete { name } => {
+                    if !store.delete(&name)? {
+                        anyhow::bail!("no session named '{name}'");
+                    }
+                }
+            }
+        }
+        Commands::ChatBatch {
+            input,
+            out,
+            model,
+            concurrency,
+        } => {
+            let inputs =
+                chat_batch::parse_inputs(&std::fs::read_to_string(&input)?, model.as_deref());
+            let existing = match std::fs::read_to_string(&out) {
+                Ok(existing) => existing,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+                Err(e) => return Err(e.into()),
+            };
+            let skip = chat_batch::succeeded_indexes(&existing);
+            let total = inputs.len();
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&out)?;
+            if !existing.is_empty() && !existing.ends_with('\n') {
+                // Terminate a line cut off by an interrupted run before appending.
+                std::io::Write::write_all(&mut file, b"\n")?;
+            }
+            let (ok, failed) =
+                chat_batch::run(&reg, &router, inputs, &skip, concurrency, file).await?;
+            let skipped = total - ok - failed;
+            if cli.json {
+                output::print_json(
+                    &serde_json::json!({"succeeded": ok, "failed": failed, "skipped": skipped}),
+                )?;
+            } else {
+                eprintln!(
+                    "{ok} succeeded, {failed} failed, {skipped} already done; results in {}",
+                    out.display()
+                );
+            }
+        }
+        Commands::EmbedBatch {
+            model,
+            input,
+            out,
+            concurrency,
+            batch_size,
+            rps,
+        } => {
+            let provider = router.select_embed(&reg, &model)?;
+            let raw = std::fs::read_to_string(&input)?;
+            let items = embed_batch::parse_inputs(&input, &raw)?;
+            let opts = embed_batch::BatchOpts {
+                batch_size,
+                concurrency,
+                rps,
+            };
+            let vectors = embed_batch::embed_all(provider, &model, &items, opts).await?;
+            let file = std::io::BufWriter::new(std::fs::File::create(&out)?);
+            embed_batch::write_ndjson(file, &items, &vectors)?;
+            eprintln!("wrote {} vectors to {}", vectors.len(), out.display());
+        }
+        Commands::Bench {
+            models,
+            prompt_file,
+            runs,
+            out,
+        } => {
+            let prompt = std::fs::read_to_string(&prompt_file)?;
+            let results = bench::run(&reg, &router, &models, &prompt, runs).await?;
+            if let Some(path) = out {
+                let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                for r in &results {
+                    use std::io::Write;
+                    writeln!(file, "{}", serde_json::to_string(r)?)?;
+                }
+            }
+            let summary = bench::summarize(&results);
+            if cli.json {
+                for row in &summary {
+                    output::print_json(row)?;
+                }
+            } else {
+                print!("{}", bench::render_table(&summary));
+            }
+        }
+        Commands::Eval {
+            input,
+            models,
+            judge,
+            concurrency,
+            timeout_secs,
+            out,
+        } => {
+            let cases = eval::parse_cases(&std::fs::read_to_string(&input)?);
+            let judge_provider = match &judge {
+                Some(model) => Some(router.select_chat(&reg, model)?),
+                None => None,
+            };
+            let judge = judge
+                .as_deref()
+                .zip(judge_provider.as_deref())
+                .map(|(model, provider)| eval::Judge { model, provider });
+            let opts = eval::EvalOpts {
+                concurrency,
+                deadline: std::time::Duration::from_secs(timeout_secs),
+            };
+            let results = eval::run(&reg, &router, cases, &models, judge.as_ref(), opts).await;
+            if let Some(path) = out {
+                let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+                for r in &results {
+                    use std::io::Write;
+                    writeln!(file, "{}", serde_json::to_string(r)?)?;
+                }
+            }
+            let summary = eval::summarize(&models, &results);
+            if cli.json {
+                for row in &summary {
+                    output::print_json(row)?;
+                }
+            } else {
+                print!("{}", eval::render_table(&summary));
+            }
+        }
+        Commands::Route {
+            command:
+                RouteCommand::Explain {
+                    model,
+                    stream,
+                    tools,
+                },
+        } => {
+            let mut required = vec![Capability::Chat];
+            if stream {
+                required.push(Capability::ChatStream);
+            }
+            if tools {
+                required.push(Capability::Tools);
+            }
+            let ex = router.explain(&reg, &model, &required);
+            if cli.json {
+                output::print_json(&ex)?;
+            } else {
+                print!("{}", route::render_explanation(&ex));
+            }
+            if !ex.routable() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Tokens {
+            command: TokensCommand::Count { args, exact },
+        } => {
+            let req = args.into_request()?;
+            let mut exact_count = None;
+            if exact {
+                let provider = router.select_chat(&reg, &req.model)?;
+                exact_count = provider
+                    .count_tokens(&req)
+                    .await?
+                    .map(|n| (n as usize, provider.name().to_string()));
+            }
+            let (tokens, method) = exact_count.unwrap_or_else(|| {
+                let counter = tokenizer::counter_for(&req.model);
+                (
+                    tokenizer::count_tokens(&req.model, &req.messages),
+                    counter.as_str().to_string(),
+                )
+            });
+            if cli.json {
+                output::print_json(
+                    &serde_json::json!({"model": req.model, "tokens": tokens, "method": method}),
+                )?;
+            } else {
+                println!("{tokens} tokens ({method})");
+            }
+        }
+        Commands::Models { provider } => {
+            models::run(&reg, &router, provider.as_deref(), cli.json).await?;
+        }
+        Commands::ValidateConfig { .. } | Commands::Keys { .. } | Commands::Config { .. } => {
+            unreachable!("handled before the registry is built")
+        }
+        Commands::Serve {
+            addr,
+            api_keys_env,
+            admin_key_env,
+            cors_origins,
+            cors_headers,
+            cors_methods,
+            cors_credentials,
+            rpm,
+            tpm,
+            daily_tokens,
+            max_body_bytes,
+            max_concurrent_requests,
+            max_streams_per_key,
+            access_log: _,
+            access_log_content,
+            #[cfg(feature = "grpc")]
+            grpc_addr,
+        } => {
+            let api_keys = match api_keys_env {
+                Some(var) => {
+                    let raw =
+                        std::env::var(&var).map_err(|_| anyhow::anyhow!("{var} is not set"))?;
+                    Some(
+                        raw.split(',')
+                            .map(|k| k.trim().to_string())
+                            .filter(|k| !k.is_empty())
+                            .collect(),
+                    )
+                }
+                None => None,
+            };
+            let mut state = server::AppState::new(reg, router);
+            state.api_keys = api_keys;
+            state.tenants = aiproxy_core::tenant::Tenants::from_config(&cfg)?;
+            state.admin_key = match admin_key_env {
+                Some(var) => {
+                    Some(std::env::var(&var).map_err(|_| anyhow::anyhow!("{var} is not set"))?)
+                }
+                None => None,
+            };
+            state.limiter = aiproxy_core::limits::Limiter::new(aiproxy_core::limits::KeyLimits {
+                rpm,
+                tpm,
+                daily_tokens,
+            });
+            // Daily quotas count what each key already spent today, across restarts.
+            if let Some(store) = &usage_store {
+                let today = aiproxy_core::usage::utc_day(
+                    std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)?
+                        .as_millis() as u64,
+                );
+                let spent_by =
+                    |key: &str| store.day_tokens(&aiproxy_core::usage::key_label(key), &today);
+                if daily_tokens.is_some() {
+                    let tenant_keys = state.tenants.iter().flat_map(|t| &t.keys);
+                    for key in state.api_keys.iter().flatten().chain(tenant_keys) {
+                        state.limiter.preload_day_tokens(key, spent_by(key)?);
+                    }
+                }
+                for tenant in state.tenants.iter() {
+                    if tenant.limiter.limits().daily_tokens.is_some() {
+                        let spent = tenant
+                            .keys
+                            .iter()
+                            .map(|k| spent_by(k))
+                            .sum::<aiproxy_core::error::CoreResult<u64>>()?;
+                        tenant.limiter.preload_day_tokens(&tenant.name, spent);
+                    }
+                }
+            }
+            state.capacity = std::sync::Arc::new(server::Capacity::new(
+                max_body_bytes,
+                max_concurrent_requests,
+                max_streams_per_key,
+            ));
+            state.access_content = access_log_content;
+            state.truncation = cfg.truncation.clone();
+            state.screening = cfg.screening.clone();
+            state.guardrail = Guardrail::from_cfg(&cfg.guardrail)?;
+            state.validation = cfg.validation.clone();
+            state.summarization = cfg.summarization.clone();
+            state.metrics = Some(metrics);
+            let state = std::sync::Arc::new(state);
+            let cors = server::CorsOpts {
+                origins: cors_origins,
+                headers: cors_headers,
+                methods: cors_methods,
+                credentials: cors_credentials,
+            };
+            #[cfg(feature = "grpc")]
+            if let Some(grpc_addr) = grpc_addr {
+                tokio::try_join!(
+                    server::serve(addr, state.clone(), &cors),
+                    server::grpc::serve(grpc_addr, state)
+                )?;
+                return Ok(());
+            }
+            server::serve(addr, state, &cors).await?;
         }
-        Commands::Embed { model, input } => {
+        Commands::Embed {
+            command: Some(EmbedCommand::Similarity { model, texts, file }),
+            ..
+        } => {
             let provider = router.select_embed(&reg, &model)?;
-            let req = EmbedRequest {
-                model,
-                inputs: vec![input],
-                client_key: None,
+            let items = match &file {
+                Some(path) => embed_batch::parse_inputs(path, &std::fs::read_to_string(path)?)?,
+                None => texts
+                    .into_iter()
+                    .map(|text| embed_batch::BatchItem { id: None, text })
+                    .collect(),
+            };
+            if items.len() < 2 {
+                anyhow::bail!("need at least two inputs to compare");
+            }
+            let opts = embed_batch::BatchOpts {
+                batch_size: items.len(),
+                concurrency: 1,
+                rps: None,
             };
+            let vectors = embed_batch::embed_all(provider, &model, &items, opts).await?;
+            let pairs = similarity::all_pairs(&vectors);
+            if cli.json {
+                output::print_json(&pairs)?;
+            } else if file.is_none() {
+                match pairs[0].similarity {
+                    Some(s) => println!("{s:.4}"),
+                    None => {
+                        anyhow::bail!("similarity undefined (zero vector or dimension mismatch)")
+                    }
+                }
+            } else {
+                for p in &pairs {
+                    let score = p
+                        .similarity
+                        .map_or_else(|| "-".to_string(), |s| format!("{s:.4}"));
+                    println!("{score}\t{}\t{}", items[p.a].text, items[p.b].text);
+                }
+            }
+        }
+        Commands::Embed { model, input, .. } => {
+            let (Some(model), Some(input)) = (model, input) else {
+                unreachable!("clap requires --model and --input without a subcommand");
+            };
+            let provider = router.select_embed(&reg, &model)?;
+            let req = EmbedRequest::builder().model(model).input(input).build();
             let resp = provider.embed(req).await?;
-            for (i, v) in resp.vectors.iter().enumerate() {
-                println!("{} -> dim={}", i, v.len());
+            if cli.json {
+                output::print_json(&resp)?;
+            } else {
+                for (i, v) in resp.vectors.iter().enumerate() {
+                    println!("{} -> dim={}", i, v.len());
+                }
             }
         }
     }
@@ -0,0 +1,161 @@
+//! `--render markdown`: line-at-a-time ANSI rendering of streamed markdown.
+//!
+//! Text is held until a newline arrives, then the completed line is styled: fenced code
+//! blocks, headings, list bullets, block quotes, and inline `code`, **bold** and *emphasis*.
+//! Anything unrecognised passes through unchanged.
+
+use std::io::IsTerminal;
+
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Render {
+    #[default]
+    Raw,
+    Markdown,
+}
+
+impl Render {
+    /// A renderer for `Markdown` when stdout is a terminal; `None` means print raw text.
+    pub fn renderer(self) -> Option<MarkdownRenderer> {
+        (self == Render::Markdown && std::io::stdout().is_terminal())
+            .then(MarkdownRenderer::default)
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const CYAN: &str = "\x1b[36m";
+
+#[derive(Debug, Default)]
+pub struct MarkdownRenderer {
+    pending: String,
+    in_code: bool,
+}
+
+impl MarkdownRenderer {
+    /// Feed a delta; returns the rendered form of every line it completed.
+    pub fn push(&mut self, delta: &str) -> String {
+        self.pending.push_str(delta);
+        let mut out = String::new();
+        while let Some(nl) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=nl).collect();
+            out.push_str(&self.render_line(line.trim_end_matches(['\n', '\r'])));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Render whatever is left after the stream ends.
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        let out = if rest.is_empty() {
+            String::new()
+        } else {
+            self.render_line(&rest)
+        };
+        self.in_code = false;
+        out
+    }
+
+    fn render_line(&mut self, line: &str) -> String {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            self.in_code = !self.in_code;
+            return format!("{DIM}{line}{RESET}");
+        }
+        if self.in_code {
+            return format!("{CYAN}{line}{RESET}");
+        }
+        let indent = &line[..line.len() - trimmed.len()];
+        if let Some(rest) = heading(trimmed) {
+            return format!("{BOLD}{UNDERLINE}{}{RESET}", inline(rest));
+        }
+        if let Some(rest) = trimmed.strip_prefix("> ") {
+            return format!("{indent}{DIM}│{RESET} {}", inline(rest));
+        }
+        for bullet in ["- ", "* ", "+ "] {
+            if let Some(rest) = trimmed.strip_prefix(bullet) {
+                return format!("{indent}• {}", inline(rest));
+            }
+        }
+        format!("{indent}{}", inline(trimmed))
+    }
+}
+
+fn heading(line: &str) -> Option<&str> {
+    let hashes = line.bytes().take_while(|b| *b == b'#').count();
+    (1..=6)
+        .contains(&hashes)
+        .then(|| line[hashes..].strip_prefix(' '))
+        .flatten()
+}
+
+/// Inline spans. Unclosed markers are left as typed.
+fn inline(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while !rest.is_empty() {
+        let span = [("`", CYAN), ("**", BOLD), ("*", ITALIC), ("_", ITALIC)]
+            .into_iter()
+            .find_map(|(marker, style)| {
+                let body = rest.strip_prefix(marker)?;
+                let end = body.find(marker).filter(|e| *e > 0)?;
+                Some((marker.len() * 2 + end, style, &body[..end]))
+            });
+        match span {
+            Some((consumed, style, body)) => {
+                out.push_str(style);
+                out.push_str(body);
+                out.push_str(RESET);
+                rest = &rest[consumed..];
+            }
+            None => {
+                let ch = rest.chars().next().unwrap();
+                out.push(ch);
+                rest = &rest[ch.len_utf8()..];
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_render_only_once_complete() {
+        let mut md = MarkdownRenderer::default();
+        assert_eq!(md.push("# Ti"), "");
+        assert_eq!(
+            md.push("tle\n- it"),
+            format!("{BOLD}{UNDERLINE}Title{RESET}\n")
+        );
+        assert_eq!(md.push("em\n"), "• item\n");
+        assert_eq!(md.finish(), "");
+    }
+
+    #[test]
+    fn code_fences_suppress_inline_styling() {
+        let mut md = MarkdownRenderer::default();
+        let out = md.push("```rust\nlet x = a*b*c;\n```\nuse **bold** and `x`\n");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], format!("{CYAN}let x = a*b*c;{RESET}"));
+        assert_eq!(
+            lines[3],
+            format!("use {BOLD}bold{RESET} and {CYAN}x{RESET}")
+        );
+    }
+
+    #[test]
+    fn unclosed_markers_and_trailing_text_pass_through() {
+        let mut md = MarkdownRenderer::default();
+        assert_eq!(md.push("2 * 3 = 6\n"), "2 * 3 = 6\n");
+        md.push("no newline _yet");
+        assert_eq!(md.finish(), "no newline _yet");
+    }
+}
@@ -0,0 +1,155 @@
+//! `aiproxy models`: merge provider model lists with routing decisions.
+
+use std::collections::BTreeMap;
+
+use aiproxy_core::model::ModelInfo;
+use aiproxy_core::provider_factory::ProviderRegistry;
+use aiproxy_core::router::RoutingResolver;
+use serde::Serialize;
+
+/// One output row: a model id, who lists it, and who the router would send it to.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ModelRow {
+    pub id: String,
+    pub listed_by: Vec<String>,
+    pub routed_to: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+}
+
+impl ModelRow {
+    /// True when the routed provider does not itself list the model.
+    pub fn mismatched(&self) -> bool {
+        !self.listed_by.contains(&self.routed_to)
+    }
+}
+
+/// Collapse listings by model id (sorted) and attach the routing decision for each. The
+/// context window is the first one any listing gives.
+pub fn merge(listings: Vec<ModelInfo>, router: &RoutingResolver) -> Vec<ModelRow> {
+    let mut by_id: BTreeMap<String, (Vec<String>, Option<u32>)> = BTreeMap::new();
+    for m in listings {
+        let (providers, window) = by_id.entry(m.id).or_default();
+        if !providers.contains(&m.provider) {
+            providers.push(m.provider);
+        }
+        *window = window.or(m.context_window);
+    }
+    by_id
+        .into_iter()
+        .map(|(id, (mut listed_by, context_window))| {
+            listed_by.sort();
+            ModelRow {
+                routed_to: router.pick_provider_name(&id).to_string(),
+                id,
+                listed_by,
+                context_window,
+            }
+        })
+        .collect()
+}
+
+/// Query every registered provider with `Capability::ListModels` (or just `only`), then print
+/// one line per model (an NDJSON row each with `json`). Providers that fail to answer, and
+/// routing rules that match none of their provider's models, are reported on stderr.
+pub async fn run(
+    reg: &ProviderRegistry,
+    router: &RoutingResolver,
+    only: Option<&str>,
+    json: bool,
+) -> anyhow::Result<()> {
+    if let Some(name) = only
+        && reg.models(name).is_none()
+    {
+        anyhow::bail!("provider '{name}' is not registered or cannot list models");
+    }
+    let listing = reg.list_models(only).await;
+    for (name, e) in &listing.failures {
+        eprintln!("[{name}: {e}]");
+    }
+    let routing = router.routing();
+    for (i, pattern) in router.unmatched_rules(&listing.models) {
+        eprintln!(
+            "[rule {i} '{pattern}' -> {}: matches no model it lists]",
+            routing.rules[i].targets().collect::<Vec<_>>().join(", ")
+        );
+    }
+    let rows = merge(listing.models, router);
+    if json {
+        for r in &rows {
+            crate::output::print_json(r)?;
+        }
+        return Ok(());
+    }
+    let width = rows.iter().map(|r| r.id.len()).max().unwrap_or(0);
+    for r in &rows {
+        let window = r
+            .context_window
+            .map_or(String::new(), |w| format!("  [{w} ctx]"));
+        let flag = if r.mismatched() {
+            "  (not listed by routed provider)"
+        } else {
+            ""
+        };
+        println!(
+            "{:width$}  {} -> {}{}{}",
+            r.id,
+            r.listed_by.join(","),
+            r.routed_to,
+            window,
+            flag
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aiproxy_core::config::RoutingRule;
+
+    fn info(id: &str, provider: &str) -> ModelInfo {
+        ModelInfo {
+            id: id.into(),
+            provider: provider.into(),
+            owned_by: None,
+            context_window: (provider == "openai").then_some(128_000),
+            max_output_tokens: None,
+        }
+    }
+
+    #[test]
+    fn merges_by_id_and_flags_routing_mismatches() {
+        let mut cfg = crate::server::tests::null_cfg();
+        cfg.routing.default = "openai".into();
+        cfg.routing.rules = vec![RoutingRule {
+            model: "^claude-".into(),
+            provider: "anthropic".into(),
+            providers: vec![],
+            split: vec![],
+            shadow: None,
+        }];
+        let router = RoutingResolver::new(&cfg).unwrap();
+        let rows = merge(
+            vec![
+                info("gpt-4o", "openrouter"),
+                info("gpt-4o", "openai"),
+                info("claude-sonnet-4", "anthropic"),
+                info("mistral-large", "openrouter"),
+            ],
+            &router,
+        );
+        assert_eq!(
+            rows.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(),
+            vec!["claude-sonnet-4", "gpt-4o", "mistral-large"]
+        );
+        assert_eq!(rows[0].routed_to, "anthropic");
+        assert!(!rows[0].mismatched());
+        assert_eq!(rows[1].listed_by, vec!["openai", "openrouter"]);
+        assert_eq!(rows[1].context_window, Some(128_000));
+        assert_eq!(rows[0].context_window, None);
+        assert!(!rows[1].mismatched());
+        assert_eq!(rows[2].routed_to, "openai");
+        assert!(rows[2].mismatched());
+    }
+}
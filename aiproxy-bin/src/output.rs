@@ -0,0 +1,9 @@
+//! Machine-readable output for the global `--json` flag.
+
+use serde::Serialize;
+
+/// Print `value` as a single line of JSON on stdout.
+pub fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string(value)?);
+    Ok(())
+}
@@ -0,0 +1,114 @@
+//! `aiproxy route explain` rendering.
+
+use aiproxy_core::router::RouteExplanation;
+
+/// Human-readable form of a routing explanation.
+pub fn render_explanation(ex: &RouteExplanation) -> String {
+    let mut out = match &ex.alias_of {
+        Some(model) => format!("model:     {} (alias of {model})\n", ex.model),
+        None => format!("model:     {}\n", ex.model),
+    };
+    match &ex.matched_rule {
+        Some((i, pattern)) => out.push_str(&format!("rule:      #{i} /{pattern}/\n")),
+        None => out.push_str("rule:      (none; using routing.default)\n"),
+    }
+    let status = if ex.registered {
+        "registered"
+    } else {
+        "NOT registered"
+    };
+    out.push_str(&format!("provider:  {} ({status})\n", ex.provider));
+    if !ex.split.is_empty() {
+        let arms: Vec<String> = ex
+            .split
+            .iter()
+            .map(|w| format!("{} {}", w.provider, w.weight))
+            .collect();
+        out.push_str(&format!("split:     {}\n", arms.join(", ")));
+    }
+    if let Some(shadow) = &ex.shadow {
+        let model = shadow
+            .model
+            .as_deref()
+            .map_or(String::new(), |m| format!(" as {m}"));
+        out.push_str(&format!(
+            "shadow:    {}{model}, {}%\n",
+            shadow.provider, shadow.percent
+        ));
+    }
+    for c in &ex.checks {
+        let mark = match (c.supported, c.model_supported) {
+            (true, _) => "ok",
+            (false, Some(false)) => "MISSING (model)",
+            (false, _) => "MISSING",
+        };
+        out.push_str(&format!("  {:<12} {mark}\n", format!("{:?}", c.capability)));
+    }
+    if ex.fallbacks.is_empty() {
+        out.push_str("fallbacks: (none)\n");
+    } else {
+        out.push_str(&format!("fallbacks: {}\n", ex.fallbacks.join(" -> ")));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aiproxy_core::config::{ShadowCfg, WeightedProvider};
+    use aiproxy_core::provider::Capability;
+    use aiproxy_core::router::CapabilityCheck;
+
+    #[test]
+    fn renders_rule_checks_and_fallbacks() {
+        let ex = RouteExplanation {
+            model: "smart".into(),
+            alias_of: Some("claude-3-opus".into()),
+            matched_rule: Some((0, "^claude-".into())),
+            provider: "anthropic".into(),
+            registered: true,
+            checks: vec![
+                CapabilityCheck {
+                    capability: Capability::Chat,
+                    supported: true,
+                    model_supported: Some(true),
+                },
+                CapabilityCheck {
+                    capability: Capability::ChatStream,
+                    supported: false,
+                    model_supported: None,
+                },
+                CapabilityCheck {
+                    capability: Capability::Tools,
+                    supported: false,
+                    model_supported: Some(false),
+                },
+            ],
+            fallbacks: vec!["openrouter".into(), "null".into()],
+            split: vec![
+                WeightedProvider {
+                    provider: "anthropic".into(),
+                    weight: 90,
+                },
+                WeightedProvider {
+                    provider: "openrouter".into(),
+                    weight: 10,
+                },
+            ],
+            shadow: Some(ShadowCfg {
+                provider: "openrouter".into(),
+                model: Some("claude-next".into()),
+                percent: 5,
+            }),
+        };
+        let text = render_explanation(&ex);
+        assert!(text.contains("model:     smart (alias of claude-3-opus)"));
+        assert!(text.contains("rule:      #0 /^claude-/"));
+        assert!(text.contains("provider:  anthropic (registered)"));
+        assert!(text.contains("ChatStream   MISSING\n"));
+        assert!(text.contains("Tools        MISSING (model)"));
+        assert!(text.contains("split:     anthropic 90, openrouter 10"));
+        assert!(text.contains("shadow:    openrouter as claude-next, 5%"));
+        assert!(text.contains("fallbacks: openrouter -> null"));
+    }
+}
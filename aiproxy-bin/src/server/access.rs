@@ -0,0 +1,269 @@
+//! Access logging: one `AccessLog` telemetry event per request, fed to the installed sinks.
+//! Handlers add the model, token counts and (when the `ContentPolicy` allows) the prompt and
+//! response text through the request's `AccessNote`.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use aiproxy_core::model::ChatMessage;
+use aiproxy_core::stream::StreamEvent;
+use aiproxy_core::telemetry::access::{AccessLog, ContentPolicy};
+use aiproxy_core::telemetry::emit_access;
+use aiproxy_core::usage::key_label;
+use axum::body::Body;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::header;
+use axum::middleware::Next;
+use axum::response::Response;
+use futures_util::StreamExt;
+
+use super::{SharedState, client_key, header_str};
+
+#[derive(Debug, Default)]
+struct Details {
+    model: Option<String>,
+    tokens_prompt: Option<u32>,
+    tokens_completion: Option<u32>,
+    prompt: Option<String>,
+    response: Option<String>,
+}
+
+/// Handler-side details for the current request's access log entry.
+#[derive(Debug, Clone, Default)]
+pub struct AccessNote {
+    policy: ContentPolicy,
+    details: Arc<Mutex<Details>>,
+}
+
+impl AccessNote {
+    fn new(policy: ContentPolicy) -> Self {
+        Self {
+            policy,
+            details: Arc::default(),
+        }
+    }
+
+    pub fn model(&self, model: &str) {
+        self.details.lock().unwrap().model = Some(model.to_string());
+    }
+
+    /// Record provider-reported usage; `None` keeps any earlier value.
+    pub fn tokens(&self, prompt: Option<u32>, completion: Option<u32>) {
+        let mut d = self.details.lock().unwrap();
+        d.tokens_prompt = prompt.or(d.tokens_prompt);
+        d.tokens_completion = completion.or(d.tokens_completion);
+    }
+
+    /// Prompt text as `role: content` lines; nothing is kept when content is omitted.
+    pub fn prompt(&self, messages: &[ChatMessage]) {
+        if self.policy == ContentPolicy::Omit {
+            return;
+        }
+        let text: Vec<String> = messages
+            .iter()
+            .map(|m| format!("{}: {}", format!("{:?}", m.role).to_lowercase(), m.content))
+            .collect();
+        self.details.lock().unwrap().prompt = Some(text.join("\n"));
+    }
+
+    /// Raw input text, for endpoints without chat messages.
+    pub fn prompt_text(&self, text: &str) {
+        if self.policy != ContentPolicy::Omit {
+            self.details.lock().unwrap().prompt = Some(text.to_string());
+        }
+    }
+
+    pub fn response(&self, text: &str) {
+        if self.policy != ContentPolicy::Omit {
+            self.details
+                .lock()
+                .unwrap()
+                .response
+                .get_or_insert_default()
+                .push_str(text);
+        }
+    }
+
+    /// Pick usage and text out of a streamed event.
+    pub fn observe(&self, ev: &StreamEvent) {
+        match ev {
+            StreamEvent::DeltaText(t) => self.response(t),
+            StreamEvent::Usage { prompt, completion } => self.tokens(*prompt, *completion),
+            StreamEvent::Final(resp) => {
+                self.tokens(Some(resp.usage.prompt), Some(resp.usage.completion));
+                let empty = self.details.lock().unwrap().response.is_none();
+                if empty {
+                    self.response(&resp.text);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Entry under construction; emitted when dropped, which for streamed bodies is when the
+/// last chunk has been sent or the client has gone away.
+struct Pending {
+    log: AccessLog,
+    started: Instant,
+    note: AccessNote,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        let d = std::mem::take(&mut *self.note.details.lock().unwrap());
+        let policy = self.note.policy;
+        let mut log = std::mem::take(&mut self.log);
+        log.latency_ms = self.started.elapsed().as_millis() as u64;
+        log.model = d.model;
+        log.tokens_prompt = d.tokens_prompt;
+        log.tokens_completion = d.tokens_completion;
+        log.prompt = d.prompt.and_then(|t| policy.apply(&t));
+        log.response = d.response.and_then(|t| policy.apply(&t));
+        emit_access(log);
+    }
+}
+
+/// Middleware over every route.
+pub async fn log(State(state): State<SharedState>, mut req: Request, next: Next) -> Response {
+    let started = Instant::now();
+    let ts_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64);
+    let log = AccessLog {
+        ts_ms,
+        method: req.method().to_string(),
+        // Unmatched paths share one label so scanners cannot inflate metric cardinality.
+        path: req
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or("unmatched", |p| p.as_str())
+            .to_string(),
+        key_id: client_key(req.headers()).as_deref().map(key_label),
+        request_id: header_str(req.headers(), "x-request-id"),
+        ..Default::default()
+    };
+    let note = AccessNote::new(state.access_content);
+    req.extensions_mut().insert(note.clone());
+    let resp = next.run(req).await;
+    let mut pending = Pending { log, started, note };
+    pending.log.status = resp.status().as_u16();
+    let streamed = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|v| v.as_bytes().starts_with(b"text/event-stream"));
+    if !streamed {
+        return resp;
+    }
+    let (parts, body) = resp.into_parts();
+    let body = body.into_data_stream().map(move |chunk| {
+        let _ = &pending;
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::app;
+    use crate::server::tests::{null_state, send};
+    use aiproxy_core::telemetry::{ProviderTrace, TelemetrySink, set_telemetry_sink};
+    use axum::http::{Request, StatusCode};
+    use std::sync::OnceLock;
+
+    /// Process-wide sink; every server test emits into it, so tests filter by request id.
+    #[derive(Default)]
+    struct Capture(Mutex<Vec<AccessLog>>);
+
+    impl TelemetrySink for Capture {
+        fn record(&self, _trace: ProviderTrace) {}
+
+        fn record_access(&self, log: AccessLog) {
+            self.0.lock().unwrap().push(log);
+        }
+    }
+
+    fn capture() -> &'static Capture {
+        static CAPTURE: OnceLock<Arc<Capture>> = OnceLock::new();
+        CAPTURE.get_or_init(|| {
+            let cap = Arc::new(Capture::default());
+            set_telemetry_sink(cap.clone());
+            cap
+        })
+    }
+
+    fn logged(request_id: &str) -> Vec<AccessLog> {
+        capture()
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|l| l.request_id.as_deref() == Some(request_id))
+            .cloned()
+            .collect()
+    }
+
+    fn chat(path: &str, request_id: &str, body: serde_json::Value) -> Request<Body> {
+        Request::post(path)
+            .header("content-type", "application/json")
+            .header("authorization", "Bearer client-5678")
+            .header("x-request-id", request_id)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn completions_are_logged_with_redacted_content() {
+        capture();
+        let mut state = null_state();
+        state.access_content = ContentPolicy::Redact;
+        let req = chat(
+            "/v1/chat/completions",
+            "access-redact",
+            serde_json::json!({
+                "model": "gpt-4o",
+                "messages": [{"role": "user", "content": "mail a.b@example.com"}]
+            }),
+        );
+        let (status, _) = send(app(Arc::new(state)), req).await;
+        assert_eq!(status, StatusCode::OK);
+        let logs = logged("access-redact");
+        assert_eq!(logs.len(), 1);
+        let log = &logs[0];
+        assert_eq!(log.method, "POST");
+        assert_eq!(log.path, "/v1/chat/completions");
+        assert_eq!(log.status, 200);
+        assert_eq!(log.key_id.as_deref(), Some("***5678"));
+        assert_eq!(log.model.as_deref(), Some("gpt-4o"));
+        assert!(log.tokens_prompt.is_some());
+        assert_eq!(log.prompt.as_deref(), Some("user: mail [REDACTED]"));
+        assert_eq!(log.response.as_deref(), Some("[null provider response]"));
+    }
+
+    #[tokio::test]
+    async fn streams_and_errors_are_logged_without_content_by_default() {
+        capture();
+        let app = app(Arc::new(null_state()));
+        let body = serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 8,
+            "stream": true,
+            "messages": [{"role": "user", "content": "secret"}]
+        });
+        let (status, _) = send(app.clone(), chat("/v1/messages", "access-stream", body)).await;
+        assert_eq!(status, StatusCode::OK);
+        let logs = logged("access-stream");
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].model.as_deref(), Some("claude-sonnet-4"));
+        assert_eq!(logs[0].prompt, None);
+        assert_eq!(logs[0].response, None);
+
+        let req = chat("/v1/nowhere", "access-404", serde_json::json!({}));
+        let (status, _) = send(app, req).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let logs = logged("access-404");
+        assert_eq!(logs[0].path, "unmatched");
+        assert_eq!(logs[0].status, 404);
+    }
+}
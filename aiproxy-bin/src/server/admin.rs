@@ -0,0 +1,322 @@
+//! Runtime admin API under `/admin`, enabled by `serve --admin-key-env`.
+//!
+//! - `GET /admin/routing` / `PUT /admin/routing`: inspect or hot-swap the routing rules.
+//! - `GET /admin/providers`: registered providers, their capabilities and drain state.
+//! - `POST /admin/providers/{name}/drain` and `/undrain`: take a provider out of rotation.
+//! - `DELETE /admin/cache`: flush cached responses.
+//! - `GET /admin/stats`: requests, tokens and cost per provider and model since startup.
+
+use aiproxy_core::config::RoutingCfg;
+use aiproxy_core::router::RoutingResolver;
+use axum::extract::rejection::JsonRejection;
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use serde_json::{Value, json};
+
+use super::error::ApiError;
+use super::{SharedState, client_key};
+
+pub fn routes() -> Router<SharedState> {
+    Router::new()
+        .route("/admin/routing", get(get_routing).put(put_routing))
+        .route("/admin/providers", get(providers))
+        .route("/admin/providers/{name}/drain", post(drain))
+        .route("/admin/providers/{name}/undrain", post(undrain))
+        .route("/admin/cache", delete(flush_cache))
+        .route("/admin/stats", get(stats))
+}
+
+/// Byte comparison that doesn't stop at the first mismatch.
+fn same_key(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// The admin key, sent like a client key (`x-api-key` or `Authorization: Bearer`).
+fn authorize_admin(state: &SharedState, headers: &HeaderMap) -> Result<(), ApiError> {
+    let Some(expected) = &state.admin_key else {
+        return Err(ApiError::not_found("admin API is disabled"));
+    };
+    match client_key(headers) {
+        Some(k) if same_key(&k, expected) => Ok(()),
+        Some(_) => Err(ApiError::unauthorized("invalid admin key")),
+        None => Err(ApiError::unauthorized("missing admin key")),
+    }
+}
+
+fn routing_body(state: &SharedState) -> Value {
+    let router = state.router();
+    let unregistered: Vec<&str> = router
+        .provider_names()
+        .into_iter()
+        .filter(|p| state.registry.caps(p).is_none())
+        .collect();
+    json!({"routing": router.routing(), "unregistered": unregistered})
+}
+
+async fn get_routing(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    authorize_admin(&state, &headers)?;
+    Ok(Json(routing_body(&state)))
+}
+
+/// Replace the routing rules. Invalid regexes are rejected and the old rules stay in place;
+/// targets that aren't registered are accepted but listed under `unregistered`.
+async fn put_routing(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    body: Result<Json<RoutingCfg>, JsonRejection>,
+) -> Result<Json<Value>, ApiError> {
+    authorize_admin(&state, &headers)?;
+    let Json(routing) = body?;
+    state.set_router(RoutingResolver::from_routing(&routing)?);
+    Ok(Json(routing_body(&state)))
+}
+
+async fn providers(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    authorize_admin(&state, &headers)?;
+    let list: Vec<Value> = state
+        .registry
+        .names()
+        .into_iter()
+        .map(|name| {
+            json!({
+                "name": name,
+                "capabilities": state.registry.caps(name).unwrap_or_default(),
+                "drained": state.is_drained(name),
+            })
+        })
+        .collect();
+    Ok(Json(json!({"providers": list})))
+}
+
+fn set_drain(
+    state: &SharedState,
+    headers: &HeaderMap,
+    name: &str,
+    drained: bool,
+) -> Result<Json<Value>, ApiError> {
+    authorize_admin(state, headers)?;
+    if state.registry.caps(name).is_none() {
+        return Err(ApiError::not_found(format!("unknown provider '{name}'")));
+    }
+    let changed = state.set_drained(name, drained);
+    Ok(Json(
+        json!({"name": name, "drained": drained, "changed": changed}),
+    ))
+}
+
+async fn drain(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    set_drain(&state, &headers, &name, true)
+}
+
+async fn undrain(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    set_drain(&state, &headers, &name, false)
+}
+
+/// Drop every entry in the shared registry's response cache and report how many there
+/// were. Tenants with their own credentials answer from their own registries' caches.
+async fn flush_cache(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    authorize_admin(&state, &headers)?;
+    let Some(cache) = state.registry.cache() else {
+        return Ok(Json(json!({"enabled": false, "flushed": 0})));
+    };
+    let flushed = cache.clear().await?;
+    Ok(Json(json!({"enabled": true, "flushed": flushed})))
+}
+
+/// The shared registry's running totals, with their summed cost. Tenants with their own
+/// credentials are metered by their own registries and not included.
+async fn stats(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    authorize_admin(&state, &headers)?;
+    let totals = state.registry.stats();
+    let cost_usd: f64 = totals.iter().map(|t| t.cost_usd).sum();
+    Ok(Json(json!({"totals": totals, "cost_usd": cost_usd})))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server::app;
+    use crate::server::tests::{null_state, send};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use serde_json::{Value, json};
+    use std::sync::Arc;
+
+    fn admin_app() -> axum::Router {
+        let mut state = null_state();
+        state.admin_key = Some("admin-secret".into());
+        app(Arc::new(state))
+    }
+
+    fn req(method: &str, path: &str, key: Option<&str>, body: Option<Value>) -> Request<Body> {
+        let mut b = Request::builder().method(method).uri(path);
+        if let Some(k) = key {
+            b = b.header("authorization", format!("Bearer {k}"));
+        }
+        match body {
+            Some(v) => b
+                .header("content-type", "application/json")
+                .body(Body::from(v.to_string())),
+            None => b.body(Body::empty()),
+        }
+        .unwrap()
+    }
+
+    async fn call(app: &axum::Router, r: Request<Body>) -> (StatusCode, Value) {
+        let (status, text) = send(app.clone(), r).await;
+        (status, serde_json::from_str(&text).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn admin_requires_configured_key() {
+        let disabled = app(Arc::new(null_state()));
+        let (status, _) = call(&disabled, req("GET", "/admin/routing", Some("x"), None)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let app = admin_app();
+        let (status, _) = call(&app, req("GET", "/admin/routing", None, None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = call(&app, req("GET", "/admin/routing", Some("nope"), None)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, body) = call(
+            &app,
+            req("GET", "/admin/routing", Some("admin-secret"), None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["routing"]["default"], "null");
+    }
+
+    #[tokio::test]
+    async fn routing_hot_swap_validates_and_reports_unregistered() {
+        let app = admin_app();
+        let key = Some("admin-secret");
+        let bad = json!({"default": "null", "rules": [{"model": "(", "provider": "null"}]});
+        let (status, _) = call(&app, req("PUT", "/admin/routing", key, Some(bad))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let new = json!({"default": "null", "rules": [{"model": "^x-", "provider": "missing"}]});
+        let (status, body) = call(&app, req("PUT", "/admin/routing", key, Some(new))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["routing"]["rules"][0]["provider"], "missing");
+        assert_eq!(body["unregistered"], json!(["missing"]));
+
+        // The swapped rules apply to the next request.
+        let chat = json!({"model": "x-1", "messages": [{"role": "user", "content": "hi"}]});
+        let (status, _) = call(&app, req("POST", "/v1/chat/completions", None, Some(chat))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn drained_provider_is_unavailable_until_undrained() {
+        let app = admin_app();
+        let key = Some("admin-secret");
+        let chat = || {
+            req(
+                "POST",
+                "/v1/chat/completions",
+                None,
+                Some(json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}]})),
+            )
+        };
+        let (status, body) =
+            call(&app, req("POST", "/admin/providers/null/drain", key, None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["changed"], true);
+        let (status, _) = call(&app, chat()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+
+        let (_, body) = call(&app, req("GET", "/admin/providers", key, None)).await;
+        let null = body["providers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|p| p["name"] == "null")
+            .unwrap();
+        assert_eq!(null["drained"], true);
+
+        call(
+            &app,
+            req("POST", "/admin/providers/null/undrain", key, None),
+        )
+        .await;
+        let (status, _) = call(&app, chat()).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, _) = call(
+            &app,
+            req("POST", "/admin/providers/missing/drain", key, None),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn stats_report_calls_per_provider_and_model() {
+        let app = admin_app();
+        let key = Some("admin-secret");
+        let chat = json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}]});
+        for _ in 0..2 {
+            let r = req("POST", "/v1/chat/completions", None, Some(chat.clone()));
+            let (status, _) = call(&app, r).await;
+            assert_eq!(status, StatusCode::OK);
+        }
+        let (status, body) = call(&app, req("GET", "/admin/stats", key, None)).await;
+        assert_eq!(status, StatusCode::OK);
+        let totals = &body["totals"];
+        assert_eq!(totals.as_array().unwrap().len(), 1);
+        assert_eq!(totals[0]["provider"], "null");
+        assert_eq!(totals[0]["model"], "gpt-4o");
+        assert_eq!(totals[0]["requests"], 2);
+        assert!(body["cost_usd"].is_number());
+    }
+
+    #[tokio::test]
+    async fn flushing_the_cache_sends_the_next_call_upstream() {
+        let app = admin_app();
+        let key = Some("admin-secret");
+        let chat = || {
+            let body = json!({"model": "gpt-4o", "messages": [{"role": "user", "content": "hi"}]});
+            req("POST", "/v1/chat/completions", None, Some(body))
+        };
+        let cached = |body: &Value| body["totals"][0]["cached"].clone();
+        call(&app, chat()).await;
+        call(&app, chat()).await;
+        let (_, body) = call(&app, req("GET", "/admin/stats", key, None)).await;
+        assert_eq!(cached(&body), 1);
+
+        let (status, body) = call(&app, req("DELETE", "/admin/cache", key, None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!({"enabled": true, "flushed": 1}));
+        let (status, _) = call(&app, chat()).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = call(&app, req("GET", "/admin/stats", key, None)).await;
+        assert_eq!(body["totals"][0]["requests"], 3);
+        assert_eq!(cached(&body), 1, "the call after the flush is not cached");
+    }
+}
@@ -0,0 +1,702 @@
+//! Anthropic Messages wire format for `/v1/messages`, including SSE event framing.
+
+use std::convert::Infallible;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aiproxy_core::error::AiProxyError;
+use aiproxy_core::model::{
+    ChatMessage, ChatRequest, ChatResponse, ContentPart, MessageContent, StopReason,
+};
+use aiproxy_core::normalizer::normalize_chat;
+use aiproxy_core::stream::StreamEvent;
+use axum::extract::State;
+use axum::extract::rejection::JsonRejection;
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use futures_util::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::error::{AnthropicError, ApiError, anthropic_kind};
+use super::{AccessNote, SharedState, header_str};
+
+// ---- Inbound wire structs ----
+
+#[derive(Debug, Deserialize)]
+pub struct MessagesRequest {
+    pub model: String,
+    pub max_tokens: u32,
+    pub messages: Vec<WireMessage>,
+    #[serde(default)]
+    pub system: Option<WireContent>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub metadata: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WireMessage {
+    pub role: String,
+    pub content: WireContent,
+}
+
+/// Anthropic accepts either a plain string or an array of typed content blocks.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum WireContent {
+    Text(String),
+    Blocks(Vec<WireBlock>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WireBlock {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Set on `tool_result` blocks.
+    #[serde(default)]
+    pub tool_use_id: Option<String>,
+    /// The result carried by a `tool_result` block.
+    #[serde(default)]
+    pub content: Option<WireContent>,
+    /// Set on `image` blocks.
+    #[serde(default)]
+    pub source: Option<WireImageSource>,
+}
+
+/// An `image` block's bytes (`base64`) or location (`url`).
+#[derive(Debug, Deserialize)]
+pub struct WireImageSource {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub data: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+// ---- Outbound wire structs ----
+
+#[derive(Debug, Serialize)]
+pub struct MessagesResponse {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub role: &'static str,
+    pub model: String,
+    pub content: Vec<TextBlock>,
+    pub stop_reason: Option<&'static str>,
+    pub stop_sequence: Option<String>,
+    pub usage: WireUsage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TextBlock {
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct WireUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+}
+
+// ---- Translation ----
+
+fn image_part(source: Option<WireImageSource>) -> Result<ContentPart, AiProxyError> {
+    let source =
+        source.ok_or_else(|| AiProxyError::Validation("image block is missing source".into()))?;
+    match (
+        source.kind.as_str(),
+        source.media_type,
+        source.data,
+        source.url,
+    ) {
+        ("base64", Some(media_type), Some(data), _) => {
+            Ok(ContentPart::image_base64(media_type, data))
+        }
+        ("url", _, _, Some(url)) => Ok(ContentPart::image_url(url)),
+        (kind, ..) => Err(AiProxyError::Validation(format!(
+            "unsupported or incomplete image source '{kind}'"
+        ))),
+    }
+}
+
+/// Text-only blocks are joined into plain text; with an `image` block the blocks are kept.
+fn flatten_content(content: WireContent) -> Result<MessageContent, AiProxyError> {
+    let blocks = match content {
+        WireContent::Text(s) => return Ok(MessageContent::Text(s)),
+        WireContent::Blocks(blocks) => blocks,
+    };
+    let parts = blocks
+        .into_iter()
+        .map(|b| match b.kind.as_str() {
+            "text" => Ok(ContentPart::text(b.text.unwrap_or_default())),
+            "image" => image_part(b.source),
+            kind => Err(AiProxyError::Validation(format!(
+                "unsupported content block type '{kind}'"
+            ))),
+        })
+        .collect::<Result<Vec<_>, AiProxyError>>()?;
+    let content = MessageContent::Parts(parts);
+    if content.has_images() {
+        return Ok(content);
+    }
+    Ok(MessageContent::Text(content.text().into_owned()))
+}
+
+/// Append a user turn, splitting its `tool_result` blocks out into `Role::Tool` messages and
+/// keeping the surrounding text in order.
+fn push_user_turn(content: WireContent, out: &mut Vec<ChatMessage>) -> Result<(), AiProxyError> {
+    let blocks = match content {
+        WireContent::Text(s) => {
+            out.push(ChatMessage::user(s));
+            return Ok(());
+        }
+        WireContent::Blocks(blocks) => blocks,
+    };
+    let mut text = Vec::new();
+    for b in blocks {
+        if b.kind != "tool_result" {
+            text.push(b);
+            continue;
+        }
+        if !text.is_empty() {
+            let pending = std::mem::take(&mut text);
+            out.push(ChatMessage::user(flatten_content(WireContent::Blocks(
+                pending,
+            ))?));
+        }
+        let id = b.tool_use_id.ok_or_else(|| {
+            AiProxyError::Validation("tool_result block is missing tool_use_id".into())
+        })?;
+        let result = b.content.unwrap_or(WireContent::Text(String::new()));
+        out.push(ChatMessage::tool(id, flatten_content(result)?));
+    }
+    if !text.is_empty() {
+        out.push(ChatMessage::user(flatten_content(WireContent::Blocks(
+            text,
+        ))?));
+    }
+    Ok(())
+}
+
+pub(crate) fn to_chat_request(
+    body: MessagesRequest,
+    headers: &HeaderMap,
+    client_key: Option<String>,
+) -> Result<ChatRequest, AiProxyError> {
+    let mut messages = Vec::with_capacity(body.messages.len() + 1);
+    if let Some(system) = body.system {
+        messages.push(ChatMessage::system(flatten_content(system)?));
+    }
+    for m in body.messages {
+        match m.role.as_str() {
+            "user" => push_user_turn(m.content, &mut messages)?,
+            "assistant" => messages.push(ChatMessage::assistant(flatten_content(m.content)?)),
+            other => {
+                return Err(AiProxyError::Validation(format!(
+                    "unsupported message role '{other}'"
+                )));
+            }
+        }
+    }
+    Ok(ChatRequest {
+        model: body.model,
+        messages,
+        temperature: body.temperature,
+        top_p: body.top_p,
+        metadata: body.metadata,
+        client_key,
+        request_id: header_str(headers, "x-request-id"),
+        trace_id: None,
+        idempotency_key: header_str(headers, "idempotency-key"),
+        max_output_tokens: Some(body.max_tokens),
+        stop_sequences: body.stop_sequences,
+        include_raw: false,
+        n: None,
+        logprobs: false,
+        extra: None,
+        screening: vec![],
+        organization: None,
+        project: None,
+        response_schema: None,
+        tools: vec![],
+        tool_choice: None,
+        stream_idle_timeout_ms: None,
+        stream_deadline_ms: None,
+        requested_model: None,
+    })
+}
+
+pub(crate) fn stop_reason(reason: Option<StopReason>) -> Option<&'static str> {
+    reason.map(|r| match r {
+        StopReason::EndTurn | StopReason::Stop | StopReason::Cancelled | StopReason::Other => {
+            "end_turn"
+        }
+        StopReason::Length => "max_tokens",
+        StopReason::ToolUse => "tool_use",
+        StopReason::ContentFilter | StopReason::Refusal => "refusal",
+    })
+}
+
+fn message_id(resp: &ChatResponse) -> String {
+    match &resp.provider_request_id {
+        Some(id) => id.clone(),
+        None => format!("msg_{}", resp.turn_id),
+    }
+}
+
+/// Id for a streamed message, where no `ChatResponse` exists yet when `message_start` is sent.
+fn fresh_message_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("msg_{nanos:x}")
+}
+
+fn to_messages_response(resp: ChatResponse) -> MessagesResponse {
+    MessagesResponse {
+        id: message_id(&resp),
+        kind: "message",
+        role: "assistant",
+        content: vec![TextBlock {
+            kind: "text",
+            text: resp.text,
+        }],
+        stop_reason: stop_reason(resp.stop_reason),
+        stop_sequence: None,
+        usage: WireUsage {
+            input_tokens: resp.usage.prompt,
+            output_tokens: resp.usage.completion,
+        },
+        model: resp.model,
+    }
+}
+
+// ---- SSE framing ----
+
+/// One Anthropic SSE event: the `event:` name and its JSON `data:` payload.
+pub(crate) type Frame = (&'static str, Value);
+
+/// Turns core `StreamEvent`s into the Anthropic event sequence:
+/// `message_start`, `content_block_start`, `content_block_delta`*, `content_block_stop`,
+/// `message_delta`, `message_stop` (or a single `error` event).
+pub(crate) struct SseFramer {
+    id: String,
+    model: String,
+    block_open: bool,
+    sent_text: bool,
+    finished: bool,
+    stop_reason: Option<StopReason>,
+    input_tokens: u32,
+    output_tokens: u32,
+}
+
+impl SseFramer {
+    pub(crate) fn new(id: String, model: String) -> Self {
+        Self {
+            id,
+            model,
+            block_open: false,
+            sent_text: false,
+            finished: false,
+            stop_reason: None,
+            input_tokens: 0,
+            output_tokens: 0,
+        }
+    }
+
+    pub(crate) fn start(&self) -> Vec<Frame> {
+        vec![(
+            "message_start",
+            json!({
+                "type": "message_start",
+                "message": {
+                    "id": self.id,
+                    "type": "message",
+                    "role": "assistant",
+                    "model": self.model,
+                    "content": [],
+                    "stop_reason": null,
+                    "stop_sequence": null,
+                    "usage": {"input_tokens": 0, "output_tokens": 0},
+                }
+            }),
+        )]
+    }
+
+    fn text(&mut self, text: String, out: &mut Vec<Frame>) {
+        if !self.block_open {
+            self.block_open = true;
+            out.push((
+                "content_block_start",
+                json!({
+                    "type": "content_block_start",
+                    "index": 0,
+                    "content_block": {"type": "text", "text": ""},
+                }),
+            ));
+        }
+        self.sent_text = true;
+        out.push((
+            "content_block_delta",
+            json!({
+                "type": "content_block_delta",
+                "index": 0,
+                "delta": {"type": "text_delta", "text": text},
+            }),
+        ));
+    }
+
+    pub(crate) fn on_event(&mut self, ev: StreamEvent) -> Vec<Frame> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        match ev {
+            StreamEvent::DeltaText(t) => self.text(t, &mut out),
+            StreamEvent::Usage { prompt, completion } => {
+                self.input_tokens = prompt.unwrap_or(self.input_tokens);
+                self.output_tokens = completion.unwrap_or(self.output_tokens);
+            }
+            StreamEvent::Stop { reason } => {
+                self.stop_reason = reason;
+                out.extend(self.finish());
+            }
+            StreamEvent::Final(resp) => {
+                // Non-streaming providers deliver the whole answer here.
+                if !self.sent_text && !resp.text.is_empty() {
+                    self.text(resp.text, &mut out);
+                }
+                self.stop_reason = resp.stop_reason;
+                self.input_tokens = resp.usage.prompt;
+                self.output_tokens = resp.usage.completion;
+                out.extend(self.finish());
+            }
+            StreamEvent::Error(e) => {
+                self.finished = true;
+                let e = ApiError::from(e);
+                out.push((
+                    "error",
+                    json!({
+                        "type": "error",
+                        "error": {"type": anthropic_kind(e.status), "message": e.message},
+                    }),
+                ));
+            }
+            _ => {}
+        }
+        out
+    }
+
+    /// Close the message; also called when the provider stream ends without a terminal event.
+    pub(crate) fn finish(&mut self) -> Vec<Frame> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        self.finished = true;
+        if self.block_open {
+            out.push((
+                "content_block_stop",
+                json!({"type": "content_block_stop", "index": 0}),
+            ));
+        }
+        out.push((
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": {
+                    "stop_reason": stop_reason(self.stop_reason).unwrap_or("end_turn"),
+                    "stop_sequence": null,
+                },
+                "usage": {"input_tokens": self.input_tokens, "output_tokens": self.output_tokens},
+            }),
+        ));
+        out.push(("message_stop", json!({"type": "message_stop"})));
+        out
+    }
+}
+
+// ---- Handler ----
+
+pub async fn messages(
+    State(state): State<SharedState>,
+    Extension(note): Extension<AccessNote>,
+    headers: HeaderMap,
+    body: Result<Json<MessagesRequest>, JsonRejection>,
+) -> Result<Response, AnthropicError> {
+    let client_key = state.authorize(&headers)?;
+    let Json(body) = body?;
+    let streaming = body.stream == Some(true);
+    let mut req = normalize_chat(to_chat_request(body, &headers, client_key.clone())?);
+    state.compress_history(&mut req).await?;
+    let provider = state.select_chat(client_key.as_deref(), &req.model)?;
+    let preflight = state.fit_prompt(&mut req, provider.as_ref()).await;
+    let (truncation, reserved) = (preflight.truncation, preflight.reserved);
+    state.screen(&mut req)?;
+    note.model(&req.model);
+    note.prompt(&req.messages);
+    state.validate(&req, provider.as_ref())?;
+    if streaming {
+        state.allow_stream()?;
+    }
+    let permit = streaming
+        .then(|| state.open_stream(client_key.as_deref()))
+        .transpose()?;
+    state.admit(client_key.as_deref(), reserved)?;
+
+    if !streaming {
+        let resp = provider.chat(req).await;
+        let used = resp.as_ref().map_or(0, |r| u64::from(r.usage.total()));
+        state.settle(client_key.as_deref(), reserved, used);
+        let mut resp = resp?;
+        resp.truncation = truncation;
+        state.guard(client_key.as_deref(), &mut resp).await?;
+        note.tokens(Some(resp.usage.prompt), Some(resp.usage.completion));
+        note.response(&resp.text);
+        return Ok(Json(to_messages_response(resp)).into_response());
+    }
+
+    // Streamed calls keep their up-front reservation as the token count.
+    let framer = SseFramer::new(fresh_message_id(), req.model.clone());
+    let head = stream::iter(framer.start());
+    let events = provider.chat_stream_events(req).await?;
+    // The stream slot is released when the body finishes or the client goes away.
+    let body = stream::unfold(Some((framer, events, permit, note)), |state| async move {
+        let (mut framer, mut events, permit, note) = state?;
+        match events.next().await {
+            Some(ev) => {
+                note.observe(&ev);
+                let frames = framer.on_event(ev);
+                let next = (!framer.finished).then_some((framer, events, permit, note));
+                Some((frames, next))
+            }
+            None => Some((framer.finish(), None)),
+        }
+    })
+    .flat_map(stream::iter);
+    let sse = head.chain(body).map(|(name, data)| {
+        Ok::<_, Infallible>(Event::default().event(name).data(data.to_string()))
+    });
+    Ok(Sse::new(sse).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::{null_app, post_json, send};
+    use aiproxy_core::model::Role;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+
+    fn names(frames: &[Frame]) -> Vec<&'static str> {
+        frames.iter().map(|(n, _)| *n).collect()
+    }
+
+    #[test]
+    fn system_and_blocks_translate_into_chat_request() {
+        let body: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 64,
+            "system": [{"type": "text", "text": "be brief"}],
+            "messages": [{"role": "user", "content": [{"type": "text", "text": "hi"}]}]
+        }))
+        .unwrap();
+        let req = to_chat_request(body, &HeaderMap::new(), Some("k".into())).unwrap();
+        assert_eq!(req.messages[0].role, Role::System);
+        assert_eq!(req.messages[0].content, "be brief");
+        assert_eq!(req.messages[1].content, "hi");
+        assert_eq!(req.max_output_tokens, Some(64));
+        assert_eq!(req.client_key.as_deref(), Some("k"));
+    }
+
+    #[test]
+    fn tool_result_blocks_become_tool_messages() {
+        let body: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 64,
+            "messages": [
+                {"role": "user", "content": "weather?"},
+                {"role": "assistant", "content": "checking"},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "toolu_1", "content": "18C"},
+                    {"type": "tool_result", "tool_use_id": "toolu_2",
+                     "content": [{"type": "text", "text": "24C"}]},
+                    {"type": "text", "text": "which is warmer?"}
+                ]}
+            ]
+        }))
+        .unwrap();
+        let req = to_chat_request(body, &HeaderMap::new(), None).unwrap();
+        assert_eq!(
+            &req.messages[2..],
+            &[
+                ChatMessage::tool("toolu_1", "18C"),
+                ChatMessage::tool("toolu_2", "24C"),
+                ChatMessage::user("which is warmer?"),
+            ]
+        );
+    }
+
+    #[test]
+    fn image_blocks_are_kept_with_their_text() {
+        let body: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 8,
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "compare"},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBO"}},
+                {"type": "image", "source": {"type": "url", "url": "https://x/cat.png"}}
+            ]}]
+        }))
+        .unwrap();
+        let req = to_chat_request(body, &HeaderMap::new(), None).unwrap();
+        assert_eq!(
+            req.messages,
+            [ChatMessage::user(vec![
+                ContentPart::text("compare"),
+                ContentPart::image_base64("image/png", "iVBO"),
+                ContentPart::image_url("https://x/cat.png"),
+            ])]
+        );
+
+        let body: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 8,
+            "messages": [{"role": "user", "content": [{"type": "document"}]}]
+        }))
+        .unwrap();
+        let err = to_chat_request(body, &HeaderMap::new(), None).unwrap_err();
+        assert!(matches!(err, AiProxyError::Validation(msg) if msg.contains("document")));
+    }
+
+    #[test]
+    fn framer_emits_full_event_sequence_for_deltas() {
+        let mut f = SseFramer::new("msg_1".into(), "m".into());
+        let mut frames = f.start();
+        frames.extend(f.on_event(StreamEvent::DeltaText("he".into())));
+        frames.extend(f.on_event(StreamEvent::DeltaText("llo".into())));
+        frames.extend(f.on_event(StreamEvent::Usage {
+            prompt: Some(3),
+            completion: Some(2),
+        }));
+        frames.extend(f.on_event(StreamEvent::Stop {
+            reason: Some(StopReason::Length),
+        }));
+        frames.extend(f.finish());
+        assert_eq!(
+            names(&frames),
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+        let delta = &frames[5].1;
+        assert_eq!(delta["delta"]["stop_reason"], "max_tokens");
+        assert_eq!(delta["usage"]["output_tokens"], 2);
+    }
+
+    #[test]
+    fn framer_reports_errors_as_error_event() {
+        let mut f = SseFramer::new("msg_1".into(), "m".into());
+        let frames = f.on_event(StreamEvent::Error(AiProxyError::RateLimited {
+            provider: "p".into(),
+            retry_after: None,
+            remaining_requests: None,
+            remaining_tokens: None,
+            reset_at_ms: None,
+            upstream: Default::default(),
+        }));
+        assert_eq!(names(&frames), vec!["error"]);
+        assert_eq!(frames[0].1["error"]["type"], "rate_limit_error");
+        assert!(f.finish().is_empty());
+    }
+
+    #[tokio::test]
+    async fn messages_endpoint_returns_anthropic_shape() {
+        let (status, body) = post_json(
+            null_app(),
+            "/v1/messages",
+            json!({
+                "model": "claude-sonnet-4",
+                "max_tokens": 16,
+                "messages": [{"role": "user", "content": "hi"}]
+            }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["type"], "message");
+        assert_eq!(body["role"], "assistant");
+        assert_eq!(body["content"][0]["type"], "text");
+        assert_eq!(body["content"][0]["text"], "[null provider response]");
+    }
+
+    #[tokio::test]
+    async fn missing_max_tokens_uses_anthropic_error_envelope() {
+        let (status, body) = post_json(
+            null_app(),
+            "/v1/messages",
+            json!({"model": "claude-sonnet-4", "messages": []}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+    }
+
+    #[tokio::test]
+    async fn streaming_messages_are_framed_as_sse() {
+        let req = Request::post("/v1/messages")
+            .header("content-type", "application/json")
+            .header("x-api-key", "anything")
+            .body(Body::from(
+                json!({
+                    "model": "claude-sonnet-4",
+                    "max_tokens": 16,
+                    "stream": true,
+                    "messages": [{"role": "user", "content": "hi"}]
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let (status, text) = send(null_app(), req).await;
+        assert_eq!(status, StatusCode::OK);
+        let events: Vec<&str> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("event: "))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "message_start",
+                "content_block_start",
+                "content_block_delta",
+                "content_block_stop",
+                "message_delta",
+                "message_stop",
+            ]
+        );
+    }
+}
@@ -0,0 +1,254 @@
+//! Server-level capacity limits: request body size, concurrent API requests, and concurrent
+//! streams per client key. Rejections are counted and exported on `/metrics`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Request, State};
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+
+use super::SharedState;
+use super::error::{AnthropicError, ApiError};
+
+/// Default request body limit (same as axum's built-in default).
+pub const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Configured limits and live counters.
+#[derive(Debug)]
+pub struct Capacity {
+    pub max_body_bytes: usize,
+    max_concurrent: Option<usize>,
+    max_streams_per_key: Option<usize>,
+    in_flight: AtomicUsize,
+    streams: Mutex<HashMap<String, usize>>,
+    rejected_body: AtomicU64,
+    rejected_concurrency: AtomicU64,
+    rejected_streams: AtomicU64,
+}
+
+impl Default for Capacity {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BODY_BYTES, None, None)
+    }
+}
+
+impl Capacity {
+    pub fn new(
+        max_body_bytes: usize,
+        max_concurrent: Option<usize>,
+        max_streams_per_key: Option<usize>,
+    ) -> Self {
+        Self {
+            max_body_bytes,
+            max_concurrent,
+            max_streams_per_key,
+            in_flight: AtomicUsize::new(0),
+            streams: Mutex::new(HashMap::new()),
+            rejected_body: AtomicU64::new(0),
+            rejected_concurrency: AtomicU64::new(0),
+            rejected_streams: AtomicU64::new(0),
+        }
+    }
+
+    fn enter(&self) -> Option<InFlight<'_>> {
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.max_concurrent.is_some_and(|max| now > max) {
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.rejected_concurrency.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        Some(InFlight(self))
+    }
+
+    /// Reserve one stream slot for `key`; the slot is released when the permit drops.
+    pub fn open_stream(self: &Arc<Self>, key: &str) -> Result<StreamPermit, ApiError> {
+        let mut streams = self.streams.lock().unwrap();
+        let open = streams.entry(key.to_string()).or_default();
+        if let Some(max) = self.max_streams_per_key
+            && *open >= max
+        {
+            self.rejected_streams.fetch_add(1, Ordering::Relaxed);
+            return Err(ApiError::too_many_requests(
+                format!("at most {max} concurrent streams are allowed per API key"),
+                "concurrent_stream_limit",
+            ));
+        }
+        *open += 1;
+        Ok(StreamPermit {
+            capacity: self.clone(),
+            key: key.to_string(),
+        })
+    }
+
+    /// Saturation gauges and rejection counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let gauge = |out: &mut String, name: &str, help: &str, v: usize| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} gauge");
+            let _ = writeln!(out, "{name} {v}");
+        };
+        gauge(
+            &mut out,
+            "aiproxy_server_in_flight_requests",
+            "API requests currently being handled.",
+            self.in_flight.load(Ordering::SeqCst),
+        );
+        if let Some(max) = self.max_concurrent {
+            gauge(
+                &mut out,
+                "aiproxy_server_max_in_flight_requests",
+                "Configured concurrent request limit.",
+                max,
+            );
+        }
+        let streams: usize = self.streams.lock().unwrap().values().sum();
+        gauge(
+            &mut out,
+            "aiproxy_server_active_streams",
+            "Streaming responses currently open.",
+            streams,
+        );
+        let _ = writeln!(
+            out,
+            "# HELP aiproxy_server_rejected_total Requests refused by server capacity limits."
+        );
+        let _ = writeln!(out, "# TYPE aiproxy_server_rejected_total counter");
+        for (reason, n) in [
+            ("body_too_large", &self.rejected_body),
+            ("concurrency", &self.rejected_concurrency),
+            ("streams_per_key", &self.rejected_streams),
+        ] {
+            let _ = writeln!(
+                out,
+                "aiproxy_server_rejected_total{{reason=\"{reason}\"}} {}",
+                n.load(Ordering::Relaxed)
+            );
+        }
+        out
+    }
+}
+
+struct InFlight<'a>(&'a Capacity);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Open stream slot held for the life of a streaming response.
+#[derive(Debug)]
+pub struct StreamPermit {
+    capacity: Arc<Capacity>,
+    key: String,
+}
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        let mut streams = self.capacity.streams.lock().unwrap();
+        if let Some(n) = streams.get_mut(&self.key) {
+            *n -= 1;
+            if *n == 0 {
+                streams.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Middleware for the API routes: enforces the concurrent request limit and counts body-size
+/// rejections. Errors use the envelope of the endpoint that was called.
+pub async fn guard(State(state): State<SharedState>, req: Request, next: Next) -> Response {
+    let anthropic = req.uri().path().starts_with("/v1/messages");
+    let Some(_in_flight) = state.capacity.enter() else {
+        let mut e = ApiError::too_many_requests(
+            "the server is at its concurrent request limit; retry shortly",
+            "server_overloaded",
+        );
+        e.retry_after = Some(1);
+        return if anthropic {
+            AnthropicError(e).into_response()
+        } else {
+            e.into_response()
+        };
+    };
+    let resp = next.run(req).await;
+    if resp.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        state.capacity.rejected_body.fetch_add(1, Ordering::Relaxed);
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::app;
+    use crate::server::tests::{null_state, post_json, send};
+    use axum::body::Body;
+    use axum::http::Request;
+    use serde_json::json;
+
+    #[test]
+    fn stream_permits_are_per_key_and_released_on_drop() {
+        let cap = Arc::new(Capacity::new(DEFAULT_MAX_BODY_BYTES, None, Some(1)));
+        let first = cap.open_stream("a").unwrap();
+        let err = cap.open_stream("a").unwrap_err();
+        assert_eq!(err.status, StatusCode::TOO_MANY_REQUESTS);
+        assert!(cap.open_stream("b").is_ok());
+        drop(first);
+        assert!(cap.open_stream("a").is_ok());
+        assert!(
+            cap.render()
+                .contains("aiproxy_server_rejected_total{reason=\"streams_per_key\"} 1")
+        );
+    }
+
+    #[test]
+    fn concurrency_limit_counts_in_flight() {
+        let cap = Capacity::new(DEFAULT_MAX_BODY_BYTES, Some(1), None);
+        let held = cap.enter().unwrap();
+        assert!(cap.enter().is_none());
+        drop(held);
+        assert!(cap.enter().is_some());
+        assert!(
+            cap.render()
+                .contains("aiproxy_server_rejected_total{reason=\"concurrency\"} 1")
+        );
+    }
+
+    #[tokio::test]
+    async fn oversized_bodies_get_413_in_each_envelope() {
+        let mut state = null_state();
+        state.capacity = Arc::new(Capacity::new(64, None, None));
+        let state = Arc::new(state);
+        let big = "x".repeat(200);
+        let (status, body) = post_json(
+            app(state.clone()),
+            "/v1/chat/completions",
+            json!({"model": "m", "messages": [{"role": "user", "content": big}]}),
+        )
+        .await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(body["error"]["type"], "request_too_large");
+
+        let req = Request::post("/v1/messages")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({"model": "m", "max_tokens": 1, "messages": [], "pad": big}).to_string(),
+            ))
+            .unwrap();
+        let (status, text) = send(app(state.clone()), req).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+        assert!(text.contains("request_too_large"));
+        assert!(
+            state
+                .capacity
+                .render()
+                .contains("reason=\"body_too_large\"} 2")
+        );
+    }
+}
@@ -0,0 +1,172 @@
+//! CORS for browser clients calling `serve` directly.
+
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue, Method, header};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// Request headers allowed when none are configured: everything the endpoints read.
+const DEFAULT_HEADERS: &[&str] = &[
+    "content-type",
+    "authorization",
+    "x-api-key",
+    "anthropic-version",
+    "x-request-id",
+    "idempotency-key",
+];
+
+/// How long browsers may cache a preflight answer.
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(600);
+
+/// CORS policy; `origins` empty means CORS is off (no headers are added).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CorsOpts {
+    /// Exact origins such as `https://app.example.com`, or `*` for any.
+    pub origins: Vec<String>,
+    /// Allowed request headers; empty uses `DEFAULT_HEADERS`.
+    pub headers: Vec<String>,
+    /// Allowed methods; empty allows GET, POST and OPTIONS.
+    pub methods: Vec<String>,
+    pub credentials: bool,
+}
+
+impl CorsOpts {
+    pub fn enabled(&self) -> bool {
+        !self.origins.is_empty()
+    }
+
+    /// Build the layer, rejecting values browsers would refuse (e.g. `*` with credentials).
+    pub fn layer(&self) -> anyhow::Result<CorsLayer> {
+        let any_origin = self.origins.iter().any(|o| o == "*");
+        if any_origin && self.credentials {
+            anyhow::bail!("CORS origin '*' cannot be combined with credentials; list origins");
+        }
+        let origin = if any_origin {
+            AllowOrigin::any()
+        } else {
+            let list = self
+                .origins
+                .iter()
+                .map(|o| {
+                    HeaderValue::from_str(o.trim_end_matches('/'))
+                        .map_err(|_| anyhow::anyhow!("invalid CORS origin '{o}'"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            AllowOrigin::list(list)
+        };
+        let headers: Vec<String> = if self.headers.is_empty() {
+            DEFAULT_HEADERS.iter().map(|h| h.to_string()).collect()
+        } else {
+            self.headers.clone()
+        };
+        let headers = headers
+            .iter()
+            .map(|h| {
+                HeaderName::try_from(h.as_str())
+                    .map_err(|_| anyhow::anyhow!("invalid CORS header '{h}'"))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let methods = if self.methods.is_empty() {
+            vec![Method::GET, Method::POST, Method::OPTIONS]
+        } else {
+            self.methods
+                .iter()
+                .map(|m| {
+                    Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                        .map_err(|_| anyhow::anyhow!("invalid CORS method '{m}'"))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?
+        };
+        Ok(CorsLayer::new()
+            .allow_origin(origin)
+            .allow_headers(headers)
+            .allow_methods(methods)
+            .allow_credentials(self.credentials)
+            // Let scripts read rate-limit hints and correlate requests.
+            .expose_headers([header::RETRY_AFTER, HeaderName::from_static("x-request-id")])
+            .max_age(PREFLIGHT_MAX_AGE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::{null_state, send};
+    use crate::server::{app, with_cors};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn opts(origins: &[&str]) -> CorsOpts {
+        CorsOpts {
+            origins: origins.iter().map(|o| o.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn wildcard_with_credentials_is_rejected() {
+        let mut o = opts(&["*"]);
+        assert!(o.layer().is_ok());
+        o.credentials = true;
+        assert!(o.layer().is_err());
+        assert!(opts(&["not a\norigin"]).layer().is_err());
+    }
+
+    #[tokio::test]
+    async fn preflight_for_streaming_endpoint_is_answered() {
+        let cors = opts(&["https://app.example.com"]).layer().unwrap();
+        let app = with_cors(app(Arc::new(null_state())), Some(cors));
+        let preflight = |origin: &str| {
+            Request::builder()
+                .method("OPTIONS")
+                .uri("/v1/chat/completions")
+                .header("origin", origin)
+                .header("access-control-request-method", "POST")
+                .header(
+                    "access-control-request-headers",
+                    "content-type,authorization",
+                )
+                .body(Body::empty())
+                .unwrap()
+        };
+        let resp = app
+            .clone()
+            .oneshot(preflight("https://app.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let h = resp.headers();
+        assert_eq!(h["access-control-allow-origin"], "https://app.example.com");
+        assert!(
+            h["access-control-allow-methods"]
+                .to_str()
+                .unwrap()
+                .contains("POST")
+        );
+        assert!(
+            h["access-control-allow-headers"]
+                .to_str()
+                .unwrap()
+                .contains("authorization")
+        );
+
+        let resp = app
+            .clone()
+            .oneshot(preflight("https://evil.example.com"))
+            .await
+            .unwrap();
+        assert!(resp.headers().get("access-control-allow-origin").is_none());
+
+        let (status, _) = send(
+            app,
+            Request::get("/healthz")
+                .header("origin", "https://app.example.com")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
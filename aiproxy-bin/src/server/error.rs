@@ -0,0 +1,246 @@
+use aiproxy_core::error::{AiProxyError, http};
+use aiproxy_core::limits::{LimitExceeded, LimitKind};
+use axum::Json;
+use axum::extract::rejection::JsonRejection;
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde_json::json;
+
+/// Error returned by server handlers; renders as an OpenAI-style error envelope.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    /// OpenAI error `type`, e.g. "invalid_request_error".
+    pub kind: &'static str,
+    pub message: String,
+    pub retry_after: Option<u64>,
+    /// OpenAI error `code`, e.g. "rate_limit_exceeded".
+    pub code: Option<&'static str>,
+}
+
+impl ApiError {
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::UNAUTHORIZED,
+            kind: "authentication_error",
+            message: message.into(),
+            retry_after: None,
+            code: None,
+        }
+    }
+
+    /// 429 for server-side limits, with an OpenAI-style `code` naming the limit.
+    pub fn too_many_requests(message: impl Into<String>, code: &'static str) -> Self {
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            kind: "rate_limit_error",
+            message: message.into(),
+            retry_after: None,
+            code: Some(code),
+        }
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            kind: "not_found_error",
+            message: message.into(),
+            retry_after: None,
+            code: None,
+        }
+    }
+}
+
+impl From<AiProxyError> for ApiError {
+    fn from(e: AiProxyError) -> Self {
+        Self {
+            status: http::status(&e),
+            kind: http::error_type(&e),
+            message: e.to_string(),
+            retry_after: e.retry_after(),
+            code: http::error_code(&e),
+        }
+    }
+}
+
+impl From<LimitExceeded> for ApiError {
+    fn from(e: LimitExceeded) -> Self {
+        // OpenAI reports an exhausted quota as a 429 too, distinguished by type and code.
+        let (kind, code) = match e.kind {
+            LimitKind::Requests | LimitKind::Tokens => ("rate_limit_error", "rate_limit_exceeded"),
+            LimitKind::DailyTokens => ("insufficient_quota", "insufficient_quota"),
+        };
+        Self {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            kind,
+            message: e.to_string(),
+            retry_after: Some(e.retry_after),
+            code: Some(code),
+        }
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> Self {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return Self {
+                status: StatusCode::PAYLOAD_TOO_LARGE,
+                kind: "request_too_large",
+                message: "request body exceeds the server's size limit".into(),
+                retry_after: None,
+                code: None,
+            };
+        }
+        AiProxyError::Validation(format!("invalid request body: {}", e.body_text())).into()
+    }
+}
+
+fn with_retry_after(mut resp: Response, retry_after: Option<u64>) -> Response {
+    if let Some(secs) = retry_after
+        && let Ok(v) = HeaderValue::from_str(&secs.to_string())
+    {
+        resp.headers_mut().insert(header::RETRY_AFTER, v);
+    }
+    resp
+}
+
+impl ApiError {
+    /// OpenAI error body, `{"error": {message, type, code}}`; also sent as an SSE payload
+    /// when a stream fails after it has started.
+    pub fn envelope(&self) -> serde_json::Value {
+        json!({
+            "error": {
+                "message": self.message,
+                "type": self.kind,
+                "code": self.code,
+            }
+        })
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = self.envelope();
+        with_retry_after((self.status, Json(body)).into_response(), self.retry_after)
+    }
+}
+
+/// Same error, rendered in the Anthropic Messages API envelope.
+#[derive(Debug)]
+pub struct AnthropicError(pub ApiError);
+
+impl From<ApiError> for AnthropicError {
+    fn from(e: ApiError) -> Self {
+        Self(e)
+    }
+}
+
+impl From<AiProxyError> for AnthropicError {
+    fn from(e: AiProxyError) -> Self {
+        Self(e.into())
+    }
+}
+
+impl From<JsonRejection> for AnthropicError {
+    fn from(e: JsonRejection) -> Self {
+        Self(e.into())
+    }
+}
+
+/// Anthropic error `type` for an HTTP status.
+pub fn anthropic_kind(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST => "invalid_request_error",
+        StatusCode::UNAUTHORIZED => "authentication_error",
+        StatusCode::FORBIDDEN => "permission_error",
+        StatusCode::NOT_FOUND => "not_found_error",
+        StatusCode::PAYLOAD_TOO_LARGE => "request_too_large",
+        StatusCode::TOO_MANY_REQUESTS => "rate_limit_error",
+        StatusCode::SERVICE_UNAVAILABLE => "overloaded_error",
+        _ => "api_error",
+    }
+}
+
+impl IntoResponse for AnthropicError {
+    fn into_response(self) -> Response {
+        let e = self.0;
+        let body = json!({
+            "type": "error",
+            "error": {
+                "type": anthropic_kind(e.status),
+                "message": e.message,
+            }
+        });
+        with_retry_after((e.status, Json(body)).into_response(), e.retry_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_limited_sets_retry_after() {
+        let resp = ApiError::from(AiProxyError::RateLimited {
+            provider: "openai".into(),
+            retry_after: Some(7),
+            remaining_requests: None,
+            remaining_tokens: None,
+            reset_at_ms: None,
+            upstream: Default::default(),
+        })
+        .into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "7");
+    }
+
+    #[test]
+    fn statuses_follow_error_table() {
+        let cases = [
+            (AiProxyError::Validation("x".into()), StatusCode::BAD_REQUEST),
+            (
+                AiProxyError::BudgetExceeded { remaining: 0 },
+                StatusCode::PAYMENT_REQUIRED,
+            ),
+            (
+                AiProxyError::ProviderUnavailable {
+                    provider: "p".into(),
+                    upstream: Default::default(),
+                },
+                StatusCode::SERVICE_UNAVAILABLE,
+            ),
+            (
+                AiProxyError::ProviderError {
+                    provider: "p".into(),
+                    code: "400".into(),
+                    message: "bad".into(),
+                    upstream: Default::default(),
+                },
+                StatusCode::BAD_GATEWAY,
+            ),
+        ];
+        for (err, status) in cases {
+            assert_eq!(ApiError::from(err).into_response().status(), status);
+        }
+    }
+
+    #[test]
+    fn daily_quota_is_insufficient_quota_429() {
+        let e = ApiError::from(LimitExceeded {
+            kind: LimitKind::DailyTokens,
+            limit: 1000,
+            retry_after: 3600,
+        });
+        assert_eq!(e.kind, "insufficient_quota");
+        let resp = e.into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER).unwrap(), "3600");
+    }
+
+    #[test]
+    fn anthropic_envelope_uses_anthropic_types() {
+        let resp = AnthropicError(ApiError::unauthorized("nope")).into_response();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(anthropic_kind(StatusCode::SERVICE_UNAVAILABLE), "overloaded_error");
+    }
+}
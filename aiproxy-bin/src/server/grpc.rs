@@ -0,0 +1,430 @@
+//! gRPC front-end (feature `grpc`): unary chat and embed plus server-streaming chat, served
+//! from the same registry, router, key allowlist and limits as the HTTP endpoints.
+//!
+//! Client keys are read from the `x-api-key` or `authorization: Bearer` metadata entries.
+
+// `tonic::Status` is large, but it is the error type the generated service trait requires.
+#![allow(clippy::result_large_err)]
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use aiproxy_core::error::AiProxyError;
+use aiproxy_core::model::{ChatMessage, ChatRequest, ChatResponse, EmbedRequest, Role, StopReason};
+use aiproxy_core::normalizer::{normalize_chat, normalize_embed_indexed};
+use aiproxy_core::stream::{StreamEvent, cancellable};
+use axum::http::StatusCode;
+use futures_util::{Stream, StreamExt, stream};
+use tonic::{Code, Request, Response, Status};
+
+use super::SharedState;
+use super::error::ApiError;
+
+pub mod pb {
+    tonic::include_proto!("aiproxy.v1");
+}
+
+use pb::ai_proxy_server::{AiProxy, AiProxyServer};
+use pb::chat_stream_event::Event;
+
+/// gRPC status for an error the HTTP endpoints would answer with `e.status`.
+fn to_status(e: ApiError) -> Status {
+    let code = match e.status {
+        StatusCode::BAD_REQUEST => Code::InvalidArgument,
+        StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::PAYMENT_REQUIRED => Code::ResourceExhausted,
+        StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+        StatusCode::BAD_GATEWAY => Code::Unknown,
+        _ => Code::Internal,
+    };
+    let mut status = Status::new(code, e.message);
+    if let Some(secs) = e.retry_after
+        && let Ok(v) = secs.to_string().parse()
+    {
+        status.metadata_mut().insert("retry-after", v);
+    }
+    status
+}
+
+fn core_status(e: AiProxyError) -> Status {
+    to_status(e.into())
+}
+
+fn from_pb_role(role: i32) -> Role {
+    match pb::Role::try_from(role).unwrap_or(pb::Role::Unspecified) {
+        pb::Role::System => Role::System,
+        pb::Role::Assistant => Role::Assistant,
+        pb::Role::Tool => Role::Tool,
+        // Like the CLI, an unmarked message is from the user.
+        pb::Role::User | pb::Role::Unspecified => Role::User,
+    }
+}
+
+fn to_pb_stop(reason: Option<StopReason>) -> i32 {
+    let r = match reason {
+        None => pb::StopReason::Unspecified,
+        Some(StopReason::Stop) => pb::StopReason::Stop,
+        Some(StopReason::Length) => pb::StopReason::Length,
+        Some(StopReason::ToolUse) => pb::StopReason::ToolUse,
+        Some(StopReason::EndTurn) => pb::StopReason::EndTurn,
+        Some(StopReason::ContentFilter) => pb::StopReason::ContentFilter,
+        Some(StopReason::Cancelled) => pb::StopReason::Cancelled,
+        Some(StopReason::Refusal) => pb::StopReason::Refusal,
+        Some(StopReason::Other) => pb::StopReason::Other,
+    };
+    r as i32
+}
+
+fn to_chat_request(req: pb::ChatRequest, client_key: Option<String>) -> ChatRequest {
+    ChatRequest {
+        model: req.model,
+        messages: req
+            .messages
+            .into_iter()
+            .map(|m| ChatMessage {
+                role: from_pb_role(m.role),
+                content: m.content.into(),
+                tool_call_id: m.tool_call_id,
+                name: m.name,
+            })
+            .collect(),
+        temperature: req.temperature,
+        top_p: req.top_p,
+        metadata: None,
+        client_key,
+        request_id: req.request_id,
+        trace_id: None,
+        idempotency_key: req.idempotency_key,
+        max_output_tokens: req.max_output_tokens,
+        stop_sequences: (!req.stop_sequences.is_empty()).then_some(req.stop_sequences),
+        include_raw: false,
+        n: None,
+        logprobs: false,
+        extra: None,
+        screening: vec![],
+        organization: None,
+        project: None,
+        response_schema: None,
+        tools: vec![],
+        tool_choice: None,
+        stream_idle_timeout_ms: None,
+        stream_deadline_ms: None,
+        requested_model: None,
+    }
+}
+
+fn to_pb_response(resp: ChatResponse) -> pb::ChatResponse {
+    pb::ChatResponse {
+        model: resp.model,
+        text: resp.text,
+        usage_prompt: resp.usage.prompt,
+        usage_completion: resp.usage.completion,
+        cached: resp.cached,
+        provider: resp.provider,
+        turn_id: resp.turn_id,
+        stop_reason: to_pb_stop(resp.stop_reason),
+        provider_request_id: resp.provider_request_id,
+        created_at_ms: resp.created_at_ms,
+        latency_ms: resp.latency_ms,
+        refusal: resp.refusal,
+        cost_usd: resp.cost_usd,
+    }
+}
+
+/// `None` for events without a wire form; errors end the stream with a status.
+fn to_pb_event(ev: StreamEvent) -> Option<Result<pb::ChatStreamEvent, Status>> {
+    let event = match ev {
+        StreamEvent::DeltaText(t) => Event::DeltaText(t),
+        StreamEvent::Usage { prompt, completion } => Event::Usage(pb::Usage { prompt, completion }),
+        StreamEvent::Stop { reason } => Event::Stop(pb::Stop {
+            reason: to_pb_stop(reason),
+        }),
+        StreamEvent::Final(resp) => Event::Final(to_pb_response(resp)),
+        StreamEvent::Error(e) => return Some(Err(core_status(e))),
+        _ => return None,
+    };
+    Some(Ok(pb::ChatStreamEvent { event: Some(event) }))
+}
+
+pub struct GrpcService {
+    state: SharedState,
+}
+
+impl GrpcService {
+    pub fn new(state: SharedState) -> Self {
+        Self { state }
+    }
+
+    fn authorize<T>(&self, req: &Request<T>) -> Result<Option<String>, Status> {
+        let headers = req.metadata().clone().into_headers();
+        self.state.authorize(&headers).map_err(to_status)
+    }
+}
+
+type ChatEventStream = Pin<Box<dyn Stream<Item = Result<pb::ChatStreamEvent, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl AiProxy for GrpcService {
+    async fn chat(
+        &self,
+        request: Request<pb::ChatRequest>,
+    ) -> Result<Response<pb::ChatResponse>, Status> {
+        let client_key = self.authorize(&request)?;
+        let mut req = normalize_chat(to_chat_request(request.into_inner(), client_key.clone()));
+        self.state
+            .compress_history(&mut req)
+            .await
+            .map_err(to_status)?;
+        let provider = self
+            .state
+            .select_chat(client_key.as_deref(), &req.model)
+            .map_err(core_status)?;
+        let preflight = self.state.fit_prompt(&mut req, provider.as_ref()).await;
+        let (truncation, reserved) = (preflight.truncation, preflight.reserved);
+        self.state.screen(&mut req).map_err(core_status)?;
+        self.state
+            .validate(&req, provider.as_ref())
+            .map_err(core_status)?;
+        self.state
+            .admit(client_key.as_deref(), reserved)
+            .map_err(to_status)?;
+        let resp = provider.chat(req).await;
+        let used = resp.as_ref().map_or(0, |r| u64::from(r.usage.total()));
+        self.state.settle(client_key.as_deref(), reserved, used);
+        let mut resp = resp.map_err(core_status)?;
+        resp.truncation = truncation;
+        self.state
+            .guard(client_key.as_deref(), &mut resp)
+            .await
+            .map_err(core_status)?;
+        Ok(Response::new(to_pb_response(resp)))
+    }
+
+    type ChatStreamStream = ChatEventStream;
+
+    async fn chat_stream(
+        &self,
+        request: Request<pb::ChatRequest>,
+    ) -> Result<Response<Self::ChatStreamStream>, Status> {
+        let client_key = self.authorize(&request)?;
+        let mut req = normalize_chat(to_chat_request(request.into_inner(), client_key.clone()));
+        self.state
+            .compress_history(&mut req)
+            .await
+            .map_err(to_status)?;
+        let provider = self
+            .state
+            .select_chat(client_key.as_deref(), &req.model)
+            .map_err(core_status)?;
+        let reserved = self
+            .state
+            .fit_prompt(&mut req, provider.as_ref())
+            .await
+            .reserved;
+        self.state.screen(&mut req).map_err(core_status)?;
+        self.state
+            .validate(&req, provider.as_ref())
+            .map_err(core_status)?;
+        self.state.allow_stream().map_err(core_status)?;
+        let permit = self
+            .state
+            .open_stream(client_key.as_deref())
+            .map_err(to_status)?;
+        // Streamed calls keep their up-front reservation as the token count.
+        self.state
+            .admit(client_key.as_deref(), reserved)
+            .map_err(to_status)?;
+        let model = req.model.clone();
+        let events = provider
+            .chat_stream_events(req)
+            .await
+            .map_err(core_status)?;
+        // Dropping the response stream (client gone) drops the provider stream with it.
+        let (_cancel, events) = cancellable(events, provider.name(), &model);
+        let out = stream::unfold(
+            (events, false, permit),
+            |(mut events, done, permit)| async move {
+                if done {
+                    return None;
+                }
+                loop {
+                    let ev = events.next().await?;
+                    let terminal = ev.is_terminal();
+                    if let Some(item) = to_pb_event(ev) {
+                        let done = terminal || item.is_err();
+                        return Some((item, (events, done, permit)));
+                    }
+                    if terminal {
+                        return None;
+                    }
+                }
+            },
+        );
+        Ok(Response::new(Box::pin(out)))
+    }
+
+    async fn embed(
+        &self,
+        request: Request<pb::EmbedRequest>,
+    ) -> Result<Response<pb::EmbedResponse>, Status> {
+        let client_key = self.authorize(&request)?;
+        let body = request.into_inner();
+        let (req, index) = normalize_embed_indexed(EmbedRequest {
+            model: body.model,
+            inputs: body.inputs,
+            client_key: client_key.clone(),
+            requested_model: None,
+        });
+        if index.is_empty() || index.iter().any(Option::is_none) {
+            return Err(Status::invalid_argument(
+                "embedding input must not be empty",
+            ));
+        }
+        let provider = self
+            .state
+            .select_embed(client_key.as_deref(), &req.model)
+            .map_err(core_status)?;
+        let chars: usize = req.inputs.iter().map(|s| s.chars().count()).sum();
+        let reserved = chars.div_ceil(4) as u64;
+        self.state
+            .admit(client_key.as_deref(), reserved)
+            .map_err(to_status)?;
+        let resp = provider.embed(req).await;
+        let used = resp.as_ref().map_or(0, |r| u64::from(r.usage));
+        self.state.settle(client_key.as_deref(), reserved, used);
+        let resp = resp.map_err(core_status)?;
+        let vectors = index
+            .into_iter()
+            .map(|pos| {
+                pos.and_then(|p| resp.vectors.get(p))
+                    .map(|v| pb::Vector { values: v.clone() })
+                    .ok_or_else(|| Status::unknown("provider returned fewer vectors than inputs"))
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+        Ok(Response::new(pb::EmbedResponse {
+            model: resp.model,
+            vectors,
+            usage: resp.usage,
+            cached: resp.cached,
+            provider: resp.provider,
+            cost_usd: resp.cost_usd,
+        }))
+    }
+}
+
+/// Bind `addr` and serve the gRPC API until the process exits.
+pub async fn serve(addr: SocketAddr, state: SharedState) -> anyhow::Result<()> {
+    eprintln!("aiproxy gRPC listening on {addr}");
+    tonic::transport::Server::builder()
+        .add_service(AiProxyServer::new(GrpcService::new(state)))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::null_state;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    fn service() -> GrpcService {
+        GrpcService::new(Arc::new(null_state()))
+    }
+
+    fn chat_request(content: &str) -> pb::ChatRequest {
+        pb::ChatRequest {
+            model: "gpt-4o".into(),
+            messages: vec![pb::ChatMessage {
+                role: pb::Role::User as i32,
+                content: content.into(),
+                tool_call_id: None,
+                name: None,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn tool_messages_keep_their_call_id() {
+        let mut req = chat_request("72F");
+        req.messages[0].role = pb::Role::Tool as i32;
+        req.messages[0].tool_call_id = Some("call_1".into());
+        let chat = to_chat_request(req, None);
+        assert_eq!(chat.messages[0], ChatMessage::tool("call_1", "72F"));
+    }
+
+    #[tokio::test]
+    async fn unary_chat_uses_the_router() {
+        let resp = service()
+            .chat(Request::new(chat_request(" hi ")))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(resp.provider, "null");
+        assert_eq!(resp.text, "[null provider response]");
+        assert_eq!(resp.usage_prompt, 2);
+    }
+
+    #[tokio::test]
+    async fn chat_stream_ends_after_final_event() {
+        let events: Vec<_> = service()
+            .chat_stream(Request::new(chat_request("hi")))
+            .await
+            .unwrap()
+            .into_inner()
+            .collect()
+            .await;
+        assert_eq!(events.len(), 1);
+        let ev = events[0].as_ref().unwrap();
+        assert!(matches!(ev.event, Some(Event::Final(_))));
+    }
+
+    #[tokio::test]
+    async fn embed_and_auth_errors_map_to_grpc_codes() {
+        let resp = service()
+            .embed(Request::new(pb::EmbedRequest {
+                model: "text-embedding-3-small".into(),
+                inputs: vec!["a".into(), " a".into(), "b".into()],
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(resp.vectors.len(), 3);
+
+        let err = service()
+            .embed(Request::new(pb::EmbedRequest {
+                model: "m".into(),
+                inputs: vec![" ".into()],
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        let mut state = null_state();
+        state.api_keys = Some(HashSet::from(["good".to_string()]));
+        let svc = GrpcService::new(Arc::new(state));
+        let mut req = Request::new(chat_request("hi"));
+        req.metadata_mut()
+            .insert("x-api-key", "bad".parse().unwrap());
+        assert_eq!(
+            svc.chat(req).await.unwrap_err().code(),
+            Code::Unauthenticated
+        );
+    }
+
+    #[test]
+    fn rate_limits_carry_retry_after_metadata() {
+        let status = core_status(AiProxyError::RateLimited {
+            provider: "p".into(),
+            retry_after: Some(3),
+            remaining_requests: None,
+            remaining_tokens: None,
+            reset_at_ms: None,
+            upstream: Default::default(),
+        });
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.metadata().get("retry-after").unwrap(), "3");
+    }
+}
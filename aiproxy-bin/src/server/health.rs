@@ -0,0 +1,91 @@
+//! Kubernetes probes: `/healthz` (liveness) and `/readyz` (readiness).
+
+use std::time::{Duration, Instant};
+
+use aiproxy_core::provider_factory::ProviderHealth;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde_json::{Value, json};
+
+use super::SharedState;
+
+/// How long a readiness result is reused, so frequent probes don't hit providers each time.
+const READY_TTL: Duration = Duration::from_secs(10);
+
+/// Last readiness probe; the async lock makes concurrent probes share one round of checks.
+#[derive(Default)]
+pub struct ReadyCache(tokio::sync::Mutex<Option<(Instant, Vec<ProviderHealth>)>>);
+
+/// The process is up and serving requests.
+pub async fn healthz() -> Json<Value> {
+    Json(json!({"status": "ok"}))
+}
+
+/// Ready once the config is loaded (it is, or `serve` would not have started) and at least
+/// one provider that routing can dispatch to is registered and healthy.
+pub async fn readyz(State(state): State<SharedState>) -> (StatusCode, Json<Value>) {
+    let mut cache = state.readiness.0.lock().await;
+    let providers = match cache.as_ref() {
+        Some((at, providers)) if at.elapsed() < READY_TTL => providers.clone(),
+        _ => {
+            let mut providers = Vec::new();
+            for name in state.router().provider_names() {
+                providers.push(state.registry.health(name).await);
+            }
+            *cache = Some((Instant::now(), providers.clone()));
+            providers
+        }
+    };
+    drop(cache);
+    let ready = providers.iter().any(|p| p.healthy);
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "providers": providers,
+    });
+    (status, Json(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::app;
+    use crate::server::tests::{null_app, null_cfg, null_state, send};
+    use aiproxy_core::router::RoutingResolver;
+    use axum::body::Body;
+    use axum::http::Request;
+    use std::sync::Arc;
+
+    fn get(path: &str) -> Request<Body> {
+        Request::get(path).body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn healthz_and_readyz_with_null_provider() {
+        let (status, body) = send(null_app(), get("/healthz")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("\"ok\""));
+
+        let (status, body) = send(null_app(), get("/readyz")).await;
+        assert_eq!(status, StatusCode::OK);
+        let body: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(body["status"], "ready");
+        assert_eq!(body["providers"][0]["provider"], "null");
+    }
+
+    #[tokio::test]
+    async fn readyz_fails_when_no_routed_provider_is_registered() {
+        let mut cfg = null_cfg();
+        cfg.routing.default = "missing".into();
+        let state = null_state();
+        state.set_router(RoutingResolver::new(&cfg).unwrap());
+        let (status, body) = send(app(Arc::new(state)), get("/readyz")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(body.contains("not registered"));
+    }
+}
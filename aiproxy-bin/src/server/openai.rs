@@ -0,0 +1,1171 @@
+//! OpenAI wire format for `/v1/chat/completions`, `/v1/embeddings` and `/v1/models`,
+//! including SSE chunk framing for `stream: true`.
+
+use std::convert::Infallible;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aiproxy_core::error::AiProxyError;
+use aiproxy_core::model::{
+    ChatMessage, ChatRequest, ChatResponse, Citation, ContentPart, EmbedRequest, EmbedResponse,
+    ImageUrl, MessageContent, Role, StopReason, ToolCall, ToolChoice, ToolDialect, ToolSpec,
+};
+use aiproxy_core::normalizer::{normalize_chat, normalize_embed_indexed};
+use aiproxy_core::stream::{StreamEvent, cancellable};
+use axum::extract::State;
+use axum::extract::rejection::JsonRejection;
+use axum::http::HeaderMap;
+use axum::response::sse::{Event, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use futures_util::{StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::error::ApiError;
+use super::{AccessNote, SharedState, header_str};
+
+// ---- Inbound wire structs ----
+
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<WireMessage>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub top_p: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub max_completion_tokens: Option<u32>,
+    #[serde(default)]
+    pub stop: Option<StopParam>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+    #[serde(default)]
+    pub stream_options: Option<StreamOptions>,
+    #[serde(default)]
+    pub metadata: Option<serde_json::Value>,
+    #[serde(default)]
+    pub n: Option<u32>,
+    #[serde(default)]
+    pub logprobs: bool,
+    #[serde(default)]
+    pub tools: Vec<Value>,
+    #[serde(default)]
+    pub tool_choice: Option<Value>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StreamOptions {
+    /// Send a final chunk with empty `choices` carrying token usage before `[DONE]`.
+    #[serde(default)]
+    pub include_usage: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WireMessage {
+    pub role: String,
+    #[serde(default)]
+    pub content: Option<WireContent>,
+    #[serde(default)]
+    pub tool_call_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// OpenAI accepts either a plain string or an array of typed content parts.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum WireContent {
+    Text(String),
+    Parts(Vec<WirePart>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WirePart {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Set on `image_url` parts.
+    #[serde(default)]
+    pub image_url: Option<ImageUrl>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum StopParam {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EmbeddingsRequest {
+    pub model: String,
+    pub input: EmbeddingInput,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum EmbeddingInput {
+    One(String),
+    Many(Vec<String>),
+}
+
+// ---- Outbound wire structs ----
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionResponse {
+    pub id: String,
+    pub object: &'static str,
+    pub created: i64,
+    pub model: String,
+    pub choices: Vec<ChatCompletionChoice>,
+    pub usage: WireUsage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChatCompletionChoice {
+    pub index: u32,
+    pub message: AssistantMessage,
+    pub finish_reason: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Value>,
+}
+
+/// `content` is null when the model refused or called tools without any text, as OpenAI
+/// sends it.
+#[derive(Debug, Serialize)]
+pub struct AssistantMessage {
+    pub role: &'static str,
+    pub content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Value>,
+}
+
+impl AssistantMessage {
+    fn new(
+        text: String,
+        refusal: Option<String>,
+        tool_calls: Vec<ToolCall>,
+        citations: Vec<Citation>,
+    ) -> Self {
+        Self {
+            role: "assistant",
+            content: (!text.is_empty() || (refusal.is_none() && tool_calls.is_empty()))
+                .then_some(text),
+            refusal,
+            tool_calls: tool_calls.into_iter().map(function_call).collect(),
+            annotations: citations.into_iter().filter_map(url_citation).collect(),
+        }
+    }
+}
+
+/// OpenAI's `tool_calls` entry, whose arguments travel as JSON text.
+fn function_call(call: ToolCall) -> Value {
+    let arguments = match call.arguments {
+        Value::String(text) => text,
+        parsed => parsed.to_string(),
+    };
+    json!({
+        "id": call.id,
+        "type": "function",
+        "function": {"name": call.name, "arguments": arguments},
+    })
+}
+
+/// OpenAI's `url_citation` annotation; citations without a URL have no OpenAI equivalent.
+fn url_citation(c: Citation) -> Option<Value> {
+    let mut cite = json!({ "url": c.url? });
+    for (key, value) in [
+        ("title", c.title.map(Value::from)),
+        ("start_index", c.start_index.map(Value::from)),
+        ("end_index", c.end_index.map(Value::from)),
+    ] {
+        if let Some(value) = value {
+            cite[key] = value;
+        }
+    }
+    Some(json!({ "type": "url_citation", "url_citation": cite }))
+}
+
+#[derive(Debug, Serialize)]
+pub struct WireUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddingsResponse {
+    pub object: &'static str,
+    pub data: Vec<EmbeddingObject>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddingObject {
+    pub object: &'static str,
+    pub index: usize,
+    pub embedding: Vec<f32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EmbeddingUsage {
+    pub prompt_tokens: u32,
+    pub total_tokens: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelList {
+    pub object: &'static str,
+    pub data: Vec<ModelObject>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelObject {
+    pub id: String,
+    pub object: &'static str,
+    /// Providers do not all report one; always 0.
+    pub created: u64,
+    pub owned_by: String,
+    /// Routed provider; an aiproxy extension, like the context hints.
+    pub provider: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+}
+
+// ---- Translation ----
+
+pub(crate) fn parse_role(role: &str) -> Result<Role, AiProxyError> {
+    match role {
+        "system" | "developer" => Ok(Role::System),
+        "user" => Ok(Role::User),
+        "assistant" => Ok(Role::Assistant),
+        "tool" => Ok(Role::Tool),
+        other => Err(AiProxyError::Validation(format!(
+            "unsupported message role '{other}'"
+        ))),
+    }
+}
+
+/// Text-only parts are joined into plain text; with an `image_url` part the parts are kept.
+fn flatten_content(content: Option<WireContent>) -> Result<MessageContent, AiProxyError> {
+    let parts = match content {
+        None => return Ok(MessageContent::Text(String::new())),
+        Some(WireContent::Text(s)) => return Ok(MessageContent::Text(s)),
+        Some(WireContent::Parts(parts)) => parts,
+    };
+    let parts = parts
+        .into_iter()
+        .map(|p| match (p.kind.as_str(), p.image_url) {
+            ("text", _) => Ok(ContentPart::text(p.text.unwrap_or_default())),
+            ("image_url", Some(image_url)) => Ok(ContentPart::ImageUrl { image_url }),
+            ("image_url", None) => Err(AiProxyError::Validation(
+                "image_url part is missing image_url".into(),
+            )),
+            (kind, _) => Err(AiProxyError::Validation(format!(
+                "unsupported content part type '{kind}'"
+            ))),
+        })
+        .collect::<Result<Vec<_>, AiProxyError>>()?;
+    let content = MessageContent::Parts(parts);
+    if content.has_images() {
+        return Ok(content);
+    }
+    Ok(MessageContent::Text(content.text().into_owned()))
+}
+
+pub(crate) fn to_chat_request(
+    body: ChatCompletionRequest,
+    headers: &HeaderMap,
+    client_key: Option<String>,
+) -> Result<ChatRequest, AiProxyError> {
+    let messages = body
+        .messages
+        .into_iter()
+        .map(|m| {
+            Ok(ChatMessage {
+                role: parse_role(&m.role)?,
+                content: flatten_content(m.content)?,
+                tool_call_id: m.tool_call_id,
+                name: m.name,
+            })
+        })
+        .collect::<Result<Vec<_>, AiProxyError>>()?;
+    let tools = body
+        .tools
+        .into_iter()
+        .map(|t| ToolSpec::from_wire(t, ToolDialect::OpenAi))
+        .collect::<Result<Vec<_>, AiProxyError>>()?;
+    let tool_choice = body
+        .tool_choice
+        .map(|c| ToolChoice::from_wire(c, ToolDialect::OpenAi))
+        .transpose()?;
+    Ok(ChatRequest {
+        model: body.model,
+        messages,
+        temperature: body.temperature,
+        top_p: body.top_p,
+        metadata: body.metadata,
+        client_key,
+        request_id: header_str(headers, "x-request-id"),
+        trace_id: None,
+        idempotency_key: header_str(headers, "idempotency-key"),
+        max_output_tokens: body.max_completion_tokens.or(body.max_tokens),
+        stop_sequences: body.stop.map(|s| match s {
+            StopParam::One(s) => vec![s],
+            StopParam::Many(v) => v,
+        }),
+        include_raw: false,
+        n: body.n,
+        logprobs: body.logprobs,
+        extra: None,
+        screening: vec![],
+        organization: header_str(headers, "openai-organization"),
+        project: header_str(headers, "openai-project"),
+        response_schema: None,
+        tools,
+        tool_choice,
+        stream_idle_timeout_ms: None,
+        stream_deadline_ms: None,
+        requested_model: None,
+    })
+}
+
+pub(crate) fn finish_reason(reason: Option<StopReason>) -> Option<&'static str> {
+    reason.map(|r| match r {
+        StopReason::Stop
+        | StopReason::EndTurn
+        | StopReason::Cancelled
+        | StopReason::Refusal
+        | StopReason::Other => "stop",
+        StopReason::Length => "length",
+        StopReason::ToolUse => "tool_calls",
+        StopReason::ContentFilter => "content_filter",
+    })
+}
+
+pub(crate) fn completion_id(resp: &ChatResponse) -> String {
+    match &resp.provider_request_id {
+        Some(id) => id.clone(),
+        None => format!("chatcmpl-{}", resp.turn_id),
+    }
+}
+
+fn to_completion_response(resp: ChatResponse) -> ChatCompletionResponse {
+    ChatCompletionResponse {
+        id: completion_id(&resp),
+        object: "chat.completion",
+        created: resp.created_at_ms / 1000,
+        usage: WireUsage {
+            prompt_tokens: resp.usage.prompt,
+            completion_tokens: resp.usage.completion,
+            total_tokens: resp.usage.total(),
+        },
+        choices: if resp.choices.is_empty() {
+            vec![ChatCompletionChoice {
+                index: 0,
+                finish_reason: finish_reason(resp.stop_reason),
+                message: AssistantMessage::new(
+                    resp.text,
+                    resp.refusal,
+                    resp.tool_calls,
+                    resp.annotations,
+                ),
+                logprobs: None,
+            }]
+        } else {
+            resp.choices
+                .into_iter()
+                .map(|c| ChatCompletionChoice {
+                    index: c.index,
+                    finish_reason: finish_reason(c.stop_reason),
+                    message: AssistantMessage::new(c.text, c.refusal, c.tool_calls, c.annotations),
+                    logprobs: c.logprobs.map(|content| json!({ "content": content })),
+                })
+                .collect()
+        },
+        model: resp.model,
+    }
+}
+
+// ---- SSE framing ----
+
+/// Provider events buffered ahead of a slow client; when full, the provider stream is not
+/// polled until the client catches up.
+const STREAM_BUFFER: usize = 16;
+
+/// Id for a streamed completion, where no `ChatResponse` exists yet when the first chunk is sent.
+fn fresh_completion_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    format!("chatcmpl-{nanos:x}")
+}
+
+/// Turns core `StreamEvent`s into `chat.completion.chunk` SSE payloads: text and tool-call
+/// deltas (the first also carrying the assistant role), a chunk with `finish_reason`, an
+/// optional usage chunk, then `[DONE]`. A provider error becomes a single `{"error": ...}` payload instead.
+pub(crate) struct ChunkFramer {
+    id: String,
+    model: String,
+    created: i64,
+    include_usage: bool,
+    sent_role: bool,
+    sent_text: bool,
+    tool_calls: usize,
+    finished: bool,
+    stop_reason: Option<StopReason>,
+    prompt_tokens: u32,
+    completion_tokens: u32,
+}
+
+impl ChunkFramer {
+    pub(crate) fn new(id: String, model: String, include_usage: bool) -> Self {
+        let created = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Self {
+            id,
+            model,
+            created,
+            include_usage,
+            sent_role: false,
+            sent_text: false,
+            tool_calls: 0,
+            finished: false,
+            stop_reason: None,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+        }
+    }
+
+    fn chunk(&self, choices: Value) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": choices,
+        })
+    }
+
+    fn delta(&mut self, mut delta: Value, finish_reason: Option<&str>) -> String {
+        if !self.sent_role {
+            self.sent_role = true;
+            delta["role"] = json!("assistant");
+        }
+        self.chunk(json!([{"index": 0, "delta": delta, "finish_reason": finish_reason}]))
+            .to_string()
+    }
+
+    fn text(&mut self, text: String, out: &mut Vec<String>) {
+        self.sent_text = true;
+        out.push(self.delta(json!({ "content": text }), None));
+    }
+
+    pub(crate) fn on_event(&mut self, ev: StreamEvent) -> Vec<String> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        match ev {
+            StreamEvent::DeltaText(t) => self.text(t, &mut out),
+            StreamEvent::ToolCall(call) => {
+                // Calls arrive whole, so each is one delta carrying all of its arguments.
+                let mut call = function_call(call);
+                call["index"] = json!(self.tool_calls);
+                self.tool_calls += 1;
+                out.push(self.delta(json!({ "tool_calls": [call] }), None));
+            }
+            StreamEvent::Usage { prompt, completion } => {
+                self.prompt_tokens = prompt.unwrap_or(self.prompt_tokens);
+                self.completion_tokens = completion.unwrap_or(self.completion_tokens);
+            }
+            StreamEvent::Stop { reason } => {
+                self.stop_reason = reason;
+                out.extend(self.finish());
+            }
+            StreamEvent::Final(resp) => {
+                // Non-streaming providers deliver the whole answer here.
+                if !self.sent_text && !resp.text.is_empty() {
+                    self.text(resp.text, &mut out);
+                }
+                self.stop_reason = resp.stop_reason;
+                self.prompt_tokens = resp.usage.prompt;
+                self.completion_tokens = resp.usage.completion;
+                out.extend(self.finish());
+            }
+            StreamEvent::Error(e) => {
+                self.finished = true;
+                out.push(ApiError::from(e).envelope().to_string());
+            }
+            _ => {}
+        }
+        out
+    }
+
+    /// Close the stream; also called when the provider stream ends without a terminal event.
+    pub(crate) fn finish(&mut self) -> Vec<String> {
+        let mut out = Vec::new();
+        if self.finished {
+            return out;
+        }
+        self.finished = true;
+        let reason = finish_reason(self.stop_reason).unwrap_or("stop");
+        out.push(self.delta(json!({}), Some(reason)));
+        if self.include_usage {
+            let mut usage = self.chunk(json!([]));
+            usage["usage"] = json!(WireUsage {
+                prompt_tokens: self.prompt_tokens,
+                completion_tokens: self.completion_tokens,
+                total_tokens: self.prompt_tokens.saturating_add(self.completion_tokens),
+            });
+            out.push(usage.to_string());
+        }
+        out.push("[DONE]".to_string());
+        out
+    }
+}
+
+// ---- Handlers ----
+
+pub async fn chat_completions(
+    State(state): State<SharedState>,
+    Extension(note): Extension<AccessNote>,
+    headers: HeaderMap,
+    body: Result<Json<ChatCompletionRequest>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let client_key = state.authorize(&headers)?;
+    let Json(body) = body?;
+    let streaming = body.stream == Some(true);
+    let include_usage = body
+        .stream_options
+        .as_ref()
+        .is_some_and(|o| o.include_usage);
+    let mut req = normalize_chat(to_chat_request(body, &headers, client_key.clone())?);
+    state.compress_history(&mut req).await?;
+    let provider = state.select_chat(client_key.as_deref(), &req.model)?;
+    let preflight = state.fit_prompt(&mut req, provider.as_ref()).await;
+    let (truncation, reserved) = (preflight.truncation, preflight.reserved);
+    state.screen(&mut req)?;
+    note.model(&req.model);
+    note.prompt(&req.messages);
+    state.validate(&req, provider.as_ref())?;
+    if streaming {
+        state.allow_stream()?;
+    }
+    let permit = streaming
+        .then(|| state.open_stream(client_key.as_deref()))
+        .transpose()?;
+    state.admit(client_key.as_deref(), reserved)?;
+
+    if !streaming {
+        let resp = provider.chat(req).await;
+        let used = resp.as_ref().map_or(0, |r| u64::from(r.usage.total()));
+        state.settle(client_key.as_deref(), reserved, used);
+        let mut resp = resp?;
+        resp.truncation = truncation;
+        state.guard(client_key.as_deref(), &mut resp).await?;
+        note.tokens(Some(resp.usage.prompt), Some(resp.usage.completion));
+        note.response(&resp.text);
+        return Ok(Json(to_completion_response(resp)).into_response());
+    }
+
+    // Streamed calls keep their up-front reservation as the token count.
+    let mut framer = ChunkFramer::new(fresh_completion_id(), req.model.clone(), include_usage);
+    let model = req.model.clone();
+    let events = provider.chat_stream_events(req).await?;
+    let (cancel, mut events) = cancellable(events, provider.name(), &model);
+    let (tx, rx) = tokio::sync::mpsc::channel::<String>(STREAM_BUFFER);
+    tokio::spawn(async move {
+        let _permit = permit;
+        while !framer.finished {
+            let next = tokio::select! {
+                ev = events.next() => Some(ev),
+                _ = tx.closed() => None,
+            };
+            let sent = match next {
+                Some(Some(ev)) => {
+                    note.observe(&ev);
+                    send_all(&tx, framer.on_event(ev)).await
+                }
+                Some(None) => send_all(&tx, framer.finish()).await,
+                None => false,
+            };
+            if !sent {
+                // The client disconnected: abort the upstream call. Draining lets the
+                // cancellable wrapper record the partial completion as cancelled.
+                cancel.cancel();
+                while events.next().await.is_some() {}
+                return;
+            }
+        }
+    });
+    let sse = stream::unfold(rx, |mut rx| async move {
+        let data = rx.recv().await?;
+        Some((Ok::<_, Infallible>(Event::default().data(data)), rx))
+    });
+    Ok(Sse::new(sse).into_response())
+}
+
+/// Forward payloads to the client in order; `false` once the client has gone away.
+async fn send_all(tx: &tokio::sync::mpsc::Sender<String>, payloads: Vec<String>) -> bool {
+    for data in payloads {
+        if tx.send(data).await.is_err() {
+            return false;
+        }
+    }
+    true
+}
+
+pub async fn embeddings(
+    State(state): State<SharedState>,
+    Extension(note): Extension<AccessNote>,
+    headers: HeaderMap,
+    body: Result<Json<EmbeddingsRequest>, JsonRejection>,
+) -> Result<Json<EmbeddingsResponse>, ApiError> {
+    let client_key = state.authorize(&headers)?;
+    let Json(body) = body?;
+    let inputs = match body.input {
+        EmbeddingInput::One(s) => vec![s],
+        EmbeddingInput::Many(v) => v,
+    };
+    let (req, index) = normalize_embed_indexed(EmbedRequest {
+        model: body.model,
+        inputs,
+        client_key: client_key.clone(),
+        requested_model: None,
+    });
+    if index.is_empty() || index.iter().any(Option::is_none) {
+        return Err(AiProxyError::Validation("embedding input must not be empty".into()).into());
+    }
+    note.model(&req.model);
+    note.prompt_text(&req.inputs.join("\n"));
+    let provider = state.select_embed(client_key.as_deref(), &req.model)?;
+    let chars: usize = req.inputs.iter().map(|s| s.chars().count()).sum();
+    let reserved = chars.div_ceil(4) as u64;
+    state.admit(client_key.as_deref(), reserved)?;
+    let resp = provider.embed(req).await;
+    let used = resp.as_ref().map_or(0, |r| u64::from(r.usage));
+    state.settle(client_key.as_deref(), reserved, used);
+    let resp: EmbedResponse = resp?;
+    note.tokens(Some(resp.usage), None);
+    let mut data = Vec::with_capacity(index.len());
+    for (i, pos) in index.into_iter().enumerate() {
+        let vector =
+            pos.and_then(|p| resp.vectors.get(p))
+                .ok_or_else(|| AiProxyError::ProviderError {
+                    provider: resp.provider.clone(),
+                    code: "vector_count_mismatch".into(),
+                    message: "provider returned fewer vectors than inputs".into(),
+                    upstream: Default::default(),
+                })?;
+        data.push(EmbeddingObject {
+            object: "embedding",
+            index: i,
+            embedding: vector.clone(),
+        });
+    }
+    Ok(Json(EmbeddingsResponse {
+        object: "list",
+        data,
+        model: resp.model,
+        usage: EmbeddingUsage {
+            prompt_tokens: resp.usage,
+            total_tokens: resp.usage,
+        },
+    }))
+}
+
+/// `GET /v1/models`: the models a caller can use, i.e. those listed by the provider routing
+/// sends them to (drained providers excluded). Tenant keys see their tenant's providers. A
+/// provider that fails to answer is left out; if none answer, the first error is returned.
+pub async fn models(
+    State(state): State<SharedState>,
+    headers: HeaderMap,
+) -> Result<Json<ModelList>, ApiError> {
+    let client_key = state.authorize(&headers)?;
+    let tenant = state.tenant(client_key.as_deref());
+    let global = state.router();
+    let (registry, router) = match &tenant {
+        Some(t) => (&t.registry, &t.router),
+        None => (&state.registry, &*global),
+    };
+    let listing = registry.list_models(None).await;
+    let mut failures = listing.failures.into_iter();
+    if listing.models.is_empty()
+        && let Some((_, e)) = failures.next()
+    {
+        return Err(e.into());
+    }
+    for (name, e) in failures {
+        tracing::warn!(provider = %name, error = %e, "model listing failed");
+    }
+    let mut data: Vec<ModelObject> = Vec::new();
+    for m in listing.models {
+        let routed = router.pick_provider_name(&m.id);
+        if routed != m.provider || state.is_drained(routed) || data.iter().any(|d| d.id == m.id) {
+            continue;
+        }
+        data.push(ModelObject {
+            owned_by: m.owned_by.unwrap_or_else(|| m.provider.clone()),
+            id: m.id,
+            object: "model",
+            created: 0,
+            provider: m.provider,
+            context_window: m.context_window,
+            max_output_tokens: m.max_output_tokens,
+        });
+    }
+    data.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(Json(ModelList {
+        object: "list",
+        data,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn translates_openai_body_into_chat_request() {
+        let body: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "developer", "content": "be brief"},
+                {"role": "user", "content": [{"type": "text", "text": "hi "}, {"type": "text", "text": "there"}]}
+            ],
+            "max_tokens": 10,
+            "max_completion_tokens": 20,
+            "stop": "END"
+        }))
+        .unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("rid-1"));
+        headers.insert("OpenAI-Project", HeaderValue::from_static("proj_team_b"));
+        let req = to_chat_request(body, &headers, Some("client-1".into())).unwrap();
+        assert_eq!(req.messages[0].role, Role::System);
+        assert_eq!(req.messages[1].content, "hi there");
+        assert_eq!(req.max_output_tokens, Some(20));
+        assert_eq!(req.stop_sequences, Some(vec!["END".to_string()]));
+        assert_eq!(req.client_key.as_deref(), Some("client-1"));
+        assert_eq!(req.request_id.as_deref(), Some("rid-1"));
+        assert_eq!(req.project.as_deref(), Some("proj_team_b"));
+        assert_eq!(req.organization, None);
+    }
+
+    #[test]
+    fn image_url_parts_are_kept_with_their_text() {
+        let body: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": [
+                {"type": "text", "text": "what is this?"},
+                {"type": "image_url", "image_url": {"url": "https://x/cat.png", "detail": "low"}}
+            ]}]
+        }))
+        .unwrap();
+        let req = to_chat_request(body, &HeaderMap::new(), None).unwrap();
+        let image = ContentPart::ImageUrl {
+            image_url: ImageUrl {
+                url: "https://x/cat.png".into(),
+                detail: Some("low".into()),
+            },
+        };
+        assert_eq!(
+            req.messages[0].content,
+            MessageContent::Parts(vec![ContentPart::text("what is this?"), image])
+        );
+    }
+
+    #[tokio::test]
+    async fn every_choice_is_returned_with_its_finish_reason() {
+        use aiproxy_core::model::{ChatChoice, TokenLogprob};
+        use aiproxy_core::provider::{ChatProvider, NullProvider};
+
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let mut resp = NullProvider.chat(req).await.unwrap();
+        resp.choices[0].logprobs = Some(vec![TokenLogprob {
+            token: "a".into(),
+            logprob: -1.0,
+            top_logprobs: vec![],
+        }]);
+        resp.choices.push(ChatChoice {
+            index: 1,
+            text: "second".into(),
+            stop_reason: Some(StopReason::Length),
+            ..ChatChoice::default()
+        });
+        let out = serde_json::to_value(to_completion_response(resp)).unwrap();
+        assert_eq!(out["choices"][0]["logprobs"]["content"][0]["token"], "a");
+        assert_eq!(out["choices"][1]["index"], 1);
+        assert_eq!(out["choices"][1]["message"]["content"], "second");
+        assert_eq!(out["choices"][1]["finish_reason"], "length");
+        assert!(out["choices"][1].get("logprobs").is_none());
+    }
+
+    #[tokio::test]
+    async fn refusals_null_the_content_and_finish_with_stop() {
+        use aiproxy_core::provider::{ChatProvider, NullProvider};
+
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let mut resp = NullProvider.chat(req).await.unwrap();
+        resp.choices[0].text.clear();
+        resp.choices[0].stop_reason = Some(StopReason::Refusal);
+        resp.choices[0].refusal = Some("No.".into());
+        let out = serde_json::to_value(to_completion_response(resp)).unwrap();
+        let choice = &out["choices"][0];
+        assert_eq!(choice["finish_reason"], "stop");
+        assert_eq!(choice["message"]["refusal"], "No.");
+        assert!(choice["message"]["content"].is_null());
+    }
+
+    #[tokio::test]
+    async fn url_citations_are_sent_as_annotations() {
+        use aiproxy_core::provider::{ChatProvider, NullProvider};
+
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let mut resp = NullProvider.chat(req).await.unwrap();
+        resp.choices[0].annotations = vec![
+            Citation {
+                url: Some("https://example.com".into()),
+                start_index: Some(3),
+                ..Citation::default()
+            },
+            Citation {
+                title: Some("notes.txt".into()),
+                ..Citation::default()
+            },
+        ];
+        let out = serde_json::to_value(to_completion_response(resp)).unwrap();
+        let annotations = out["choices"][0]["message"]["annotations"]
+            .as_array()
+            .unwrap();
+        assert_eq!(annotations.len(), 1);
+        assert_eq!(annotations[0]["type"], "url_citation");
+        assert_eq!(annotations[0]["url_citation"]["url"], "https://example.com");
+        assert_eq!(annotations[0]["url_citation"]["start_index"], 3);
+        assert!(annotations[0]["url_citation"].get("title").is_none());
+    }
+
+    #[test]
+    fn tool_messages_keep_their_call_id() {
+        let body: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [
+                {"role": "user", "content": "weather?", "name": "alice"},
+                {"role": "tool", "tool_call_id": "call_1", "content": "72F"}
+            ]
+        }))
+        .unwrap();
+        let req = to_chat_request(body, &HeaderMap::new(), None).unwrap();
+        assert_eq!(req.messages[0].name.as_deref(), Some("alice"));
+        assert_eq!(req.messages[1], ChatMessage::tool("call_1", "72F"));
+    }
+
+    #[tokio::test]
+    async fn tools_pass_through_and_calls_return_as_functions() {
+        use aiproxy_core::provider::{ChatProvider, NullProvider};
+
+        let body: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "weather?"}],
+            "tools": [{"type": "function", "function": {"name": "get_weather"}}],
+            "tool_choice": "required"
+        }))
+        .unwrap();
+        let req = to_chat_request(body, &HeaderMap::new(), None).unwrap();
+        assert_eq!(req.tools[0].name, "get_weather");
+        assert_eq!(req.tool_choice, Some(ToolChoice::Required));
+
+        let mut resp = NullProvider.chat(req).await.unwrap();
+        let call = ToolCall {
+            id: "call_1".into(),
+            name: "get_weather".into(),
+            arguments: json!({"city": "Paris"}),
+        };
+        resp.choices[0].text.clear();
+        resp.choices[0].stop_reason = Some(StopReason::ToolUse);
+        resp.choices[0].tool_calls = vec![call.clone()];
+        let out = serde_json::to_value(to_completion_response(resp)).unwrap();
+        let choice = &out["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert!(choice["message"]["content"].is_null());
+        let function = &choice["message"]["tool_calls"][0]["function"];
+        assert_eq!(function["name"], "get_weather");
+        assert_eq!(function["arguments"], r#"{"city":"Paris"}"#);
+
+        let mut framer = ChunkFramer::new("chatcmpl-1".into(), "gpt-4o".into(), false);
+        let out = payloads(&mut framer, vec![StreamEvent::ToolCall(call)]);
+        let chunk: Value = serde_json::from_str(&out[0]).unwrap();
+        let delta = &chunk["choices"][0]["delta"];
+        assert_eq!(delta["role"], "assistant");
+        assert_eq!(delta["tool_calls"][0]["index"], 0);
+        assert_eq!(delta["tool_calls"][0]["id"], "call_1");
+
+        let bad: ChatCompletionRequest = serde_json::from_value(json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tool_choice": "sometimes"
+        }))
+        .unwrap();
+        assert!(to_chat_request(bad, &HeaderMap::new(), None).is_err());
+    }
+
+    fn payloads(framer: &mut ChunkFramer, events: Vec<StreamEvent>) -> Vec<String> {
+        events
+            .into_iter()
+            .flat_map(|ev| framer.on_event(ev))
+            .collect()
+    }
+
+    #[test]
+    fn framer_emits_role_deltas_finish_usage_and_done() {
+        let mut framer = ChunkFramer::new("chatcmpl-1".into(), "gpt-4o".into(), true);
+        let out = payloads(
+            &mut framer,
+            vec![
+                StreamEvent::DeltaText("Hel".into()),
+                StreamEvent::DeltaText("lo".into()),
+                StreamEvent::Usage {
+                    prompt: Some(3),
+                    completion: Some(2),
+                },
+                StreamEvent::Stop {
+                    reason: Some(StopReason::Length),
+                },
+                StreamEvent::DeltaText("ignored".into()),
+            ],
+        );
+        assert_eq!(out.len(), 5);
+        assert_eq!(out[4], "[DONE]");
+        let chunks: Vec<Value> = out[..4]
+            .iter()
+            .map(|s| serde_json::from_str(s).unwrap())
+            .collect();
+        assert_eq!(chunks[0]["object"], "chat.completion.chunk");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[0]["choices"][0]["delta"]["content"], "Hel");
+        assert!(chunks[1]["choices"][0]["delta"].get("role").is_none());
+        assert_eq!(chunks[2]["choices"][0]["finish_reason"], "length");
+        assert_eq!(chunks[3]["choices"], json!([]));
+        assert_eq!(chunks[3]["usage"]["total_tokens"], 5);
+    }
+
+    #[test]
+    fn framer_reports_errors_without_done() {
+        let mut framer = ChunkFramer::new("chatcmpl-1".into(), "m".into(), false);
+        let out = payloads(
+            &mut framer,
+            vec![StreamEvent::Error(AiProxyError::ProviderUnavailable {
+                provider: "p".into(),
+                upstream: Default::default(),
+            })],
+        );
+        assert_eq!(out.len(), 1);
+        let v: Value = serde_json::from_str(&out[0]).unwrap();
+        assert_eq!(v["error"]["type"], "service_unavailable");
+        assert!(framer.finish().is_empty());
+    }
+
+    #[tokio::test]
+    async fn streaming_completions_are_sse_chunks_ending_in_done() {
+        use crate::server::tests::{null_app, send};
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+
+        let req = Request::post("/v1/chat/completions")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                json!({
+                    "model": "gpt-4o",
+                    "stream": true,
+                    "messages": [{"role": "user", "content": "hi"}]
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let (status, text) = send(null_app(), req).await;
+        assert_eq!(status, StatusCode::OK);
+        let data: Vec<&str> = text
+            .lines()
+            .filter_map(|l| l.strip_prefix("data: "))
+            .collect();
+        assert_eq!(data.len(), 3);
+        assert!(data[0].contains("[null provider response]"));
+        assert!(data[1].contains("\"finish_reason\":\"stop\""));
+        assert_eq!(data[2], "[DONE]");
+    }
+
+    #[tokio::test]
+    async fn client_disconnect_cancels_the_provider_stream() {
+        use crate::server::tests::null_state;
+        use aiproxy_core::error::CoreResult;
+        use aiproxy_core::provider::{Capability, ChatProvider};
+        use aiproxy_core::stream::BoxStreamEv;
+        use async_trait::async_trait;
+        use axum::http::Request;
+        use std::sync::Arc;
+        use tokio::sync::oneshot;
+        use tower::ServiceExt;
+
+        /// Sends one delta, then hangs; signals when its stream is dropped.
+        #[derive(Debug)]
+        struct Hanging(std::sync::Mutex<Option<oneshot::Sender<()>>>);
+
+        struct SignalOnDrop(Option<oneshot::Sender<()>>);
+        impl Drop for SignalOnDrop {
+            fn drop(&mut self) {
+                if let Some(tx) = self.0.take() {
+                    let _ = tx.send(());
+                }
+            }
+        }
+
+        #[async_trait]
+        impl ChatProvider for Hanging {
+            fn name(&self) -> &str {
+                "hanging"
+            }
+            async fn chat(&self, _req: ChatRequest) -> CoreResult<ChatResponse> {
+                unreachable!()
+            }
+            async fn chat_stream_events(&self, _req: ChatRequest) -> CoreResult<BoxStreamEv> {
+                let guard = SignalOnDrop(self.0.lock().unwrap().take());
+                let s = stream::iter([StreamEvent::DeltaText("part".into())])
+                    .chain(stream::pending())
+                    .map(move |ev| {
+                        let _ = &guard;
+                        ev
+                    });
+                Ok(s.boxed())
+            }
+        }
+
+        let (dropped_tx, dropped_rx) = oneshot::channel();
+        let mut state = null_state();
+        state.registry.register_chat(
+            "null",
+            Arc::new(Hanging(std::sync::Mutex::new(Some(dropped_tx)))),
+            &[Capability::Chat, Capability::ChatStream],
+        );
+        let body = json!({
+            "model": "m",
+            "stream": true,
+            "messages": [{"role": "user", "content": "hi"}]
+        });
+        let req = Request::post("/v1/chat/completions")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        let resp = crate::server::app(Arc::new(state))
+            .oneshot(req)
+            .await
+            .unwrap();
+        let mut body = resp.into_body().into_data_stream();
+        let first = body.next().await.unwrap().unwrap();
+        assert!(String::from_utf8_lossy(&first).contains("part"));
+        drop(body);
+        tokio::time::timeout(std::time::Duration::from_secs(5), dropped_rx)
+            .await
+            .expect("provider stream dropped after disconnect")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn models_lists_what_routing_can_reach() {
+        use crate::server::tests::{null_state, send};
+        use aiproxy_core::error::CoreResult;
+        use aiproxy_core::model::ModelInfo;
+        use aiproxy_core::provider::{Capability, ModelsProvider, NullProvider};
+        use async_trait::async_trait;
+        use axum::body::Body;
+        use axum::http::{Request, StatusCode};
+        use std::sync::Arc;
+
+        #[derive(Debug)]
+        struct Lister(&'static str, &'static [&'static str]);
+
+        #[async_trait]
+        impl ModelsProvider for Lister {
+            fn name(&self) -> &str {
+                self.0
+            }
+
+            async fn list_models(&self) -> CoreResult<Vec<ModelInfo>> {
+                Ok(self
+                    .1
+                    .iter()
+                    .map(|id| ModelInfo {
+                        id: id.to_string(),
+                        provider: self.0.into(),
+                        owned_by: None,
+                        context_window: None,
+                        max_output_tokens: None,
+                    })
+                    .collect())
+            }
+        }
+
+        let mut state = null_state();
+        state
+            .registry
+            .register_models("null", Arc::new(Lister("null", &["gpt-4o", "in-house"])));
+        // Routing sends everything to `null`, so this provider's models are unreachable.
+        state.registry.register_chat(
+            "spare",
+            Arc::new(NullProvider),
+            &[Capability::Chat, Capability::ListModels],
+        );
+        state
+            .registry
+            .register_models("spare", Arc::new(Lister("spare", &["spare-1"])));
+        let state = Arc::new(state);
+        let get = || Request::get("/v1/models").body(Body::empty()).unwrap();
+
+        let (status, body) = send(crate::server::app(state.clone()), get()).await;
+        assert_eq!(status, StatusCode::OK);
+        let list: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(list["object"], "list");
+        let ids: Vec<&str> = list["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["id"].as_str().unwrap())
+            .collect();
+        assert_eq!(ids, ["gpt-4o", "in-house"]);
+        assert_eq!(list["data"][0]["owned_by"], "null");
+        assert_eq!(list["data"][0]["context_window"], 128_000);
+        assert!(list["data"][1].get("context_window").is_none());
+
+        state.set_drained("null", true);
+        let (_, body) = send(crate::server::app(state), get()).await;
+        assert_eq!(
+            serde_json::from_str::<Value>(&body).unwrap()["data"],
+            json!([])
+        );
+    }
+
+    #[test]
+    fn unknown_role_is_validation_error() {
+        let body: ChatCompletionRequest = serde_json::from_value(serde_json::json!({
+            "model": "gpt-4o",
+            "messages": [{"role": "narrator", "content": "x"}]
+        }))
+        .unwrap();
+        let err = to_chat_request(body, &HeaderMap::new(), None).unwrap_err();
+        assert!(matches!(err, AiProxyError::Validation(msg) if msg.contains("narrator")));
+    }
+}
@@ -0,0 +1,302 @@
+//! `GET /v1/realtime?model=…`: OpenAI Realtime sessions relayed over WebSocket, behind the
+//! same key checks, limits and telemetry as the HTTP endpoints.
+//!
+//! The upstream session is opened before the handshake is answered, so routing and provider
+//! errors reach the client as ordinary HTTP errors. Opening a session and every
+//! `response.create` it sends each count as one request against the caller's limits; each
+//! finished response's tokens are then settled against its token budgets.
+
+use aiproxy_core::error::AiProxyError;
+use aiproxy_core::realtime::ws::{self, Message, Role, WsWriter};
+use aiproxy_core::realtime::{ClientEvent, RealtimeSession, SessionConfig};
+use axum::Extension;
+use axum::body::Body;
+use axum::extract::{Query, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::Response;
+use futures_util::StreamExt;
+use hyper_util::rt::TokioIo;
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::Mutex;
+
+use super::error::ApiError;
+use super::{AccessNote, AppState, SharedState, header_str};
+
+#[derive(Debug, Deserialize)]
+pub struct RealtimeParams {
+    pub model: String,
+}
+
+/// The handshake's `Sec-WebSocket-Key`, if `headers` ask for a WebSocket upgrade.
+fn websocket_key(headers: &HeaderMap) -> Option<String> {
+    let has = |name, value: &str| {
+        header_str(headers, name).is_some_and(|v| {
+            v.split(',')
+                .any(|part| part.trim().eq_ignore_ascii_case(value))
+        })
+    };
+    (has("upgrade", "websocket")
+        && has("connection", "upgrade")
+        && has("sec-websocket-version", "13"))
+    .then(|| header_str(headers, "sec-websocket-key"))
+    .flatten()
+}
+
+pub async fn realtime(
+    State(state): State<SharedState>,
+    Extension(note): Extension<AccessNote>,
+    Query(params): Query<RealtimeParams>,
+    mut req: Request,
+) -> Result<Response, ApiError> {
+    let headers = req.headers();
+    let client_key = state.authorize(headers)?;
+    let accept = websocket_key(headers).ok_or_else(|| {
+        AiProxyError::Validation("/v1/realtime expects a WebSocket upgrade".into())
+    })?;
+    let request_id = header_str(headers, "x-request-id");
+    note.model(&params.model);
+    let provider = state.select_realtime(client_key.as_deref(), &params.model)?;
+    let permit = state.open_stream(client_key.as_deref())?;
+    state.admit(client_key.as_deref(), 0)?;
+    let cfg = SessionConfig {
+        request_id,
+        client_key: client_key.clone(),
+        ..SessionConfig::new(params.model)
+    };
+    let session = provider.connect(&cfg).await?;
+
+    let upgrade = hyper::upgrade::on(&mut req);
+    tokio::spawn(async move {
+        let _permit = permit;
+        match upgrade.await {
+            Ok(io) => relay(&state, client_key.as_deref(), TokioIo::new(io), session).await,
+            Err(e) => eprintln!("warning: realtime upgrade failed: {e}"),
+        }
+    });
+    Ok(Response::builder()
+        .status(StatusCode::SWITCHING_PROTOCOLS)
+        .header(header::UPGRADE, "websocket")
+        .header(header::CONNECTION, "Upgrade")
+        .header(header::SEC_WEBSOCKET_ACCEPT, ws::accept_key(&accept))
+        .body(Body::empty())
+        .unwrap())
+}
+
+/// An `error` event for a client event the proxy refused, in the Realtime schema.
+fn refusal(e: ApiError) -> Message {
+    let mut event = e.envelope();
+    event["type"] = "error".into();
+    Message::Text(event.to_string())
+}
+
+async fn send<W: AsyncWrite + Unpin>(client: &Mutex<WsWriter<W>>, msg: Message) -> bool {
+    client.lock().await.send(&msg).await.is_ok()
+}
+
+/// Pump events both ways until either side closes.
+async fn relay<S: AsyncRead + AsyncWrite>(
+    state: &AppState,
+    key: Option<&str>,
+    io: S,
+    session: RealtimeSession,
+) {
+    let (mut client_rx, client_tx) = ws::split(io, Role::Server);
+    let client_tx = Mutex::new(client_tx);
+    let RealtimeSession {
+        mut sender,
+        mut events,
+    } = session;
+
+    let upstream = async {
+        while let Some(event) = events.next().await {
+            let msg = match event {
+                Ok(event) => {
+                    if let Some(usage) = event.usage() {
+                        state.settle(key, 0, u64::from(usage.total()));
+                    }
+                    Message::Text(event.0.to_string())
+                }
+                Err(e) => refusal(e.into()),
+            };
+            if !send(&client_tx, msg).await {
+                return;
+            }
+        }
+        send(&client_tx, Message::Close(Some((1000, String::new())))).await;
+    };
+    let downstream = async {
+        loop {
+            let text = match client_rx.recv().await {
+                Ok(Some(Message::Text(text))) => text,
+                Ok(Some(Message::Ping(data))) => {
+                    send(&client_tx, Message::Pong(data)).await;
+                    continue;
+                }
+                Ok(Some(Message::Binary(_) | Message::Pong(_))) => continue,
+                Ok(Some(Message::Close(_))) => {
+                    send(&client_tx, Message::Close(None)).await;
+                    break;
+                }
+                Ok(None) | Err(_) => break,
+            };
+            let event = match ClientEvent::parse(&text) {
+                Ok(event) => event,
+                Err(e) => {
+                    send(&client_tx, refusal(e.into())).await;
+                    continue;
+                }
+            };
+            if event.kind() == "response.create"
+                && let Err(e) = state.admit(key, 0)
+            {
+                send(&client_tx, refusal(e)).await;
+                continue;
+            }
+            if sender.send(event).await.is_err() {
+                break;
+            }
+        }
+    };
+    tokio::select! {
+        _ = upstream => {}
+        _ = downstream => {}
+    }
+    let _ = sender.close().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::tests::{null_state, send as send_request};
+    use aiproxy_core::error::CoreResult;
+    use aiproxy_core::limits::{KeyLimits, Limiter};
+    use aiproxy_core::realtime::{EventSink, RealtimeProvider, ServerEvent};
+    use async_trait::async_trait;
+    use std::sync::Arc;
+    use tokio::sync::mpsc;
+
+    /// Answers each `response.create` with a finished response of 3 + 2 tokens and echoes the
+    /// type of every other event.
+    #[derive(Debug)]
+    struct Echo;
+
+    struct EchoSink(Option<mpsc::UnboundedSender<ClientEvent>>);
+
+    #[async_trait]
+    impl EventSink for EchoSink {
+        async fn send(&mut self, event: ClientEvent) -> CoreResult<()> {
+            if let Some(tx) = &self.0 {
+                let _ = tx.send(event);
+            }
+            Ok(())
+        }
+
+        async fn close(&mut self) -> CoreResult<()> {
+            self.0 = None;
+            Ok(())
+        }
+    }
+
+    #[async_trait]
+    impl RealtimeProvider for Echo {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        async fn connect(&self, cfg: &SessionConfig) -> CoreResult<RealtimeSession> {
+            let (tx, rx) = mpsc::unbounded_channel::<ClientEvent>();
+            let events = futures_util::stream::unfold(rx, |mut rx| async move {
+                let event = rx.recv().await?;
+                let reply = match event.kind() {
+                    "response.create" => serde_json::json!({
+                        "type": "response.done",
+                        "response": {"usage": {"input_tokens": 3, "output_tokens": 2}}
+                    }),
+                    kind => serde_json::json!({"type": "echo", "of": kind}),
+                };
+                Some((Ok(ServerEvent(reply)), rx))
+            })
+            .boxed();
+            RealtimeSession::start("echo", cfg, Box::new(EchoSink(Some(tx))), events).await
+        }
+    }
+
+    async fn text(rx: &mut ws::ClientReader) -> serde_json::Value {
+        match rx.recv().await.unwrap() {
+            Some(Message::Text(text)) => serde_json::from_str(&text).unwrap(),
+            other => panic!("expected a text message, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn sessions_relay_events_and_settle_token_budgets() {
+        let mut state = null_state();
+        state.registry.register_realtime("null", Arc::new(Echo));
+        state.limiter = Limiter::new(KeyLimits {
+            daily_tokens: Some(4),
+            ..KeyLimits::default()
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::server::app(Arc::new(state));
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let url = format!("ws://{addr}/v1/realtime?model=gpt-4o-realtime-preview");
+        let auth = [("Authorization".to_string(), "Bearer k1".to_string())];
+        let (mut rx, mut tx) = ws::connect("aiproxy", &url, &auth, None).await.unwrap();
+        let send_text = |text: &'static str| Message::Text(text.into());
+
+        tx.send(&send_text(r#"{"type": "conversation.item.create"}"#))
+            .await
+            .unwrap();
+        assert_eq!(text(&mut rx).await["of"], "conversation.item.create");
+        tx.send(&send_text(r#"{"type": "response.create"}"#))
+            .await
+            .unwrap();
+        assert_eq!(text(&mut rx).await["type"], "response.done");
+        // The 5 tokens just settled exhaust the daily budget of 4.
+        tx.send(&send_text(r#"{"type": "response.create"}"#))
+            .await
+            .unwrap();
+        let refused = text(&mut rx).await;
+        assert_eq!(refused["type"], "error");
+        assert_eq!(refused["error"]["code"], "insufficient_quota");
+        tx.send(&send_text("not json")).await.unwrap();
+        assert_eq!(
+            text(&mut rx).await["error"]["type"],
+            "invalid_request_error"
+        );
+
+        tx.send(&Message::Close(None)).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap(), Some(Message::Close(None)));
+    }
+
+    #[tokio::test]
+    async fn non_upgrade_and_unroutable_requests_fail_as_http_errors() {
+        let app = || crate::server::app(Arc::new(null_state()));
+        let get = |model: &str, upgrade: bool| {
+            let mut req = axum::http::Request::get(format!("/v1/realtime?model={model}"));
+            if upgrade {
+                req = req
+                    .header("upgrade", "websocket")
+                    .header("connection", "keep-alive, Upgrade")
+                    .header("sec-websocket-version", "13")
+                    .header("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ==");
+            }
+            req.body(Body::empty()).unwrap()
+        };
+        let (status, body) = send_request(app(), get("gpt-4o-realtime-preview", false)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("expects a WebSocket upgrade"), "{body}");
+        let (status, body) = send_request(app(), get("gpt-4o", true)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(
+            body.contains("does not support realtime sessions"),
+            "{body}"
+        );
+        let (status, body) = send_request(app(), get("gpt-4o-realtime-preview", true)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("lacks realtime capability"), "{body}");
+    }
+}
@@ -0,0 +1,205 @@
+//! Saved chat sessions for `--session`: conversation history persisted as one JSON file per
+//! session under `$AIPROXY_SESSIONS_DIR` (default `.aiproxy/sessions`).
+
+use std::path::{Path, PathBuf};
+
+use aiproxy_core::model::{ChatMessage, ChatRequest, Role};
+use serde::{Deserialize, Serialize};
+
+pub const DIR_ENV: &str = "AIPROXY_SESSIONS_DIR";
+const DEFAULT_DIR: &str = ".aiproxy/sessions";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Model used for the most recent turn.
+    pub model: Option<String>,
+    pub messages: Vec<ChatMessage>,
+}
+
+impl Session {
+    /// Put saved history ahead of the new turn's messages. A `--system` prompt on the new
+    /// turn replaces the saved one; otherwise the saved system prompt is kept first.
+    pub fn apply(&self, req: &mut ChatRequest) {
+        if self.messages.is_empty() {
+            return;
+        }
+        let (new_system, new_turn): (Vec<_>, Vec<_>) =
+            req.messages.drain(..).partition(|m| m.role == Role::System);
+        let (old_system, history): (Vec<_>, Vec<_>) = self
+            .messages
+            .iter()
+            .cloned()
+            .partition(|m| m.role == Role::System);
+        req.messages = if new_system.is_empty() {
+            old_system
+        } else {
+            new_system
+        };
+        req.messages.extend(history);
+        req.messages.extend(new_turn);
+    }
+
+    /// The session after `req` was answered with `reply`.
+    pub fn after_turn(req: &ChatRequest, reply: String) -> Self {
+        let mut messages = req.messages.clone();
+        messages.push(ChatMessage::assistant(reply));
+        Self {
+            model: Some(req.model.clone()),
+            messages,
+        }
+    }
+}
+
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(std::env::var(DIR_ENV).unwrap_or_else(|_| DEFAULT_DIR.to_string()))
+    }
+
+    /// Names are restricted to `[A-Za-z0-9_-]` so they can never escape the sessions dir.
+    fn path(&self, name: &str) -> anyhow::Result<PathBuf> {
+        let ok = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !ok {
+            anyhow::bail!("invalid session name '{name}': use letters, digits, '-' or '_'");
+        }
+        Ok(self.dir.join(format!("{name}.json")))
+    }
+
+    /// Saved session, or an empty one if `name` has never been used.
+    pub fn load(&self, name: &str) -> anyhow::Result<Session> {
+        let path = self.path(name)?;
+        match std::fs::read_to_string(&path) {
+            Ok(raw) => {
+                serde_json::from_str(&raw).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Session::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write via a temp file and rename so an interrupted save never truncates history.
+    pub fn save(&self, name: &str, session: &Session) -> anyhow::Result<()> {
+        let path = self.path(name)?;
+        std::fs::create_dir_all(&self.dir)?;
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(session)?)?;
+        std::fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Saved session names with their message counts, sorted by name.
+    pub fn list(&self) -> anyhow::Result<Vec<(String, usize)>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+        let mut out = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            out.push((name.to_string(), self.load(name)?.messages.len()));
+        }
+        out.sort();
+        Ok(out)
+    }
+
+    /// Remove a session; `false` if it did not exist.
+    pub fn delete(&self, name: &str) -> anyhow::Result<bool> {
+        match std::fs::remove_file(self.path(name)?) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// Transcript form used by `session show`.
+pub fn render_session(session: &Session) -> String {
+    let mut out = String::new();
+    if let Some(model) = &session.model {
+        out.push_str(&format!("model: {model}\n"));
+    }
+    for m in &session.messages {
+        let role = serde_json::to_value(m.role)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string))
+            .unwrap_or_default();
+        out.push_str(&format!("{role}: {}\n", m.content));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: Role, content: &str) -> ChatMessage {
+        ChatMessage::new(role, content)
+    }
+
+    fn request(messages: Vec<ChatMessage>) -> ChatRequest {
+        ChatRequest::builder().model("m").messages(messages).build()
+    }
+
+    #[test]
+    fn history_goes_before_new_turn_and_system_is_replaced() {
+        let saved = Session {
+            model: Some("m".into()),
+            messages: vec![
+                msg(Role::System, "old"),
+                msg(Role::User, "hi"),
+                msg(Role::Assistant, "hello"),
+            ],
+        };
+        let mut req = request(vec![msg(Role::User, "again")]);
+        saved.apply(&mut req);
+        let contents: Vec<&str> = req
+            .messages
+            .iter()
+            .map(|m| m.content.as_text().unwrap())
+            .collect();
+        assert_eq!(contents, vec!["old", "hi", "hello", "again"]);
+
+        let mut req = request(vec![msg(Role::System, "new"), msg(Role::User, "again")]);
+        saved.apply(&mut req);
+        assert_eq!(req.messages[0].content, "new");
+        assert_eq!(req.messages.len(), 4);
+    }
+
+    #[test]
+    fn store_round_trips_lists_and_deletes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::new(dir.path().join("sessions"));
+        assert_eq!(store.load("work").unwrap(), Session::default());
+        assert!(store.list().unwrap().is_empty());
+
+        let req = request(vec![msg(Role::User, "hi")]);
+        let session = Session::after_turn(&req, "hello".into());
+        store.save("work", &session).unwrap();
+        assert_eq!(store.load("work").unwrap(), session);
+        assert_eq!(store.list().unwrap(), vec![("work".to_string(), 2)]);
+        assert!(render_session(&session).ends_with("user: hi\nassistant: hello\n"));
+
+        assert!(store.delete("work").unwrap());
+        assert!(!store.delete("work").unwrap());
+        assert!(store.load("../escape").is_err());
+    }
+}
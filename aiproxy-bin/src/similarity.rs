@@ -0,0 +1,72 @@
+//! `aiproxy embed similarity`: cosine similarity between embedded texts.
+
+use serde::Serialize;
+
+/// Cosine similarity; `None` for mismatched dimensions or a zero vector.
+pub fn cosine(a: &[f32], b: &[f32]) -> Option<f32> {
+    if a.len() != b.len() || a.is_empty() {
+        return None;
+    }
+    let (mut dot, mut na, mut nb) = (0f64, 0f64, 0f64);
+    for (x, y) in a.iter().zip(b) {
+        let (x, y) = (f64::from(*x), f64::from(*y));
+        dot += x * y;
+        na += x * x;
+        nb += y * y;
+    }
+    (na > 0.0 && nb > 0.0).then(|| (dot / (na.sqrt() * nb.sqrt())) as f32)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PairScore {
+    pub a: usize,
+    pub b: usize,
+    pub similarity: Option<f32>,
+}
+
+/// Every unordered pair, most similar first; unscorable pairs sort last.
+pub fn all_pairs(vectors: &[Vec<f32>]) -> Vec<PairScore> {
+    let mut out = Vec::new();
+    for a in 0..vectors.len() {
+        for b in a + 1..vectors.len() {
+            out.push(PairScore {
+                a,
+                b,
+                similarity: cosine(&vectors[a], &vectors[b]),
+            });
+        }
+    }
+    out.sort_by(|x, y| {
+        let key = |p: &PairScore| p.similarity.unwrap_or(f32::NEG_INFINITY);
+        key(y).total_cmp(&key(x))
+    });
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cosine_handles_basic_and_degenerate_vectors() {
+        assert_eq!(cosine(&[1.0, 0.0], &[2.0, 0.0]), Some(1.0));
+        assert_eq!(cosine(&[1.0, 0.0], &[0.0, 3.0]), Some(0.0));
+        assert_eq!(cosine(&[1.0, 0.0], &[-1.0, 0.0]), Some(-1.0));
+        assert_eq!(cosine(&[0.0, 0.0], &[1.0, 0.0]), None);
+        assert_eq!(cosine(&[1.0], &[1.0, 0.0]), None);
+    }
+
+    #[test]
+    fn pairs_are_sorted_most_similar_first() {
+        let vectors = vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![1.0, 0.1],
+            vec![0.0, 0.0],
+        ];
+        let pairs = all_pairs(&vectors);
+        assert_eq!(pairs.len(), 6);
+        assert_eq!((pairs[0].a, pairs[0].b), (0, 2));
+        assert!(pairs[5].similarity.is_none());
+    }
+}
@@ -0,0 +1,110 @@
+//! `aiproxy usage`: token and cost totals from the usage log.
+
+use std::time::Duration;
+
+use aiproxy_core::usage::UsageSummary;
+
+/// Parse a lookback like `90s`, `30m`, `24h`, `7d` or `2w`.
+pub fn parse_since(raw: &str) -> Result<Duration, String> {
+    let raw = raw.trim();
+    let split = raw
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("'{raw}' needs a unit (s, m, h, d or w)"))?;
+    let (n, unit) = raw.split_at(split);
+    let n: u64 = n
+        .parse()
+        .map_err(|_| format!("'{raw}' must start with a number"))?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        "w" => 604_800,
+        _ => return Err(format!("unknown unit '{unit}' (expected s, m, h, d or w)")),
+    };
+    Ok(Duration::from_secs(n * secs))
+}
+
+/// Fixed-width table with a trailing total row.
+pub fn render_table(group: &str, rows: &[UsageSummary]) -> String {
+    let mut table = vec![vec![
+        group.to_string(),
+        "requests".into(),
+        "prompt".into(),
+        "completion".into(),
+        "cost $".into(),
+    ]];
+    let mut total = (0usize, 0u64, 0u64, 0f64);
+    for r in rows {
+        total = (
+            total.0 + r.requests,
+            total.1 + r.prompt_tokens,
+            total.2 + r.completion_tokens,
+            total.3 + r.cost_usd,
+        );
+        let cost = if r.unpriced > 0 {
+            format!("{:.6} ({} unpriced)", r.cost_usd, r.unpriced)
+        } else {
+            format!("{:.6}", r.cost_usd)
+        };
+        table.push(vec![
+            r.key.clone(),
+            r.requests.to_string(),
+            r.prompt_tokens.to_string(),
+            r.completion_tokens.to_string(),
+            cost,
+        ]);
+    }
+    table.push(vec![
+        "total".into(),
+        total.0.to_string(),
+        total.1.to_string(),
+        total.2.to_string(),
+        format!("{:.6}", total.3),
+    ]);
+    let widths: Vec<usize> = (0..5)
+        .map(|i| table.iter().map(|row| row[i].len()).max().unwrap_or(0))
+        .collect();
+    let mut out = String::new();
+    for row in &table {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{c:w$}"))
+            .collect();
+        out.push_str(line.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lookback_units() {
+        assert_eq!(parse_since("7d"), Ok(Duration::from_secs(7 * 86_400)));
+        assert_eq!(parse_since("90s"), Ok(Duration::from_secs(90)));
+        assert!(parse_since("7").is_err());
+        assert!(parse_since("7y").is_err());
+        assert!(parse_since("d").is_err());
+    }
+
+    #[test]
+    fn table_has_header_rows_and_total() {
+        let rows = vec![UsageSummary {
+            key: "gpt-4o".into(),
+            requests: 2,
+            prompt_tokens: 10,
+            completion_tokens: 20,
+            cost_usd: 0.25,
+            unpriced: 1,
+        }];
+        let text = render_table("model", &rows);
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[0].starts_with("model"));
+        assert!(lines[1].ends_with("(1 unpriced)"));
+        assert!(lines[2].starts_with("total") && lines[2].ends_with("0.250000"));
+    }
+}
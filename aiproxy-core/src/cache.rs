@@ -0,0 +1,293 @@
+//! SQLite-backed response cache configured by `[cache]`.
+//!
+//! Responses are stored under a SHA-256 of the provider name and the normalized request, so
+//! an identical request to the same provider within `ttl_seconds` is answered from the cache
+//! with `cached: true` instead of being sent again. Per-call identifiers (`request_id`,
+//! `trace_id`, `idempotency_key`) are left out of the key; the caller's `client_key` is not,
+//! so one caller never sees another's responses. Streams and `include_raw` requests are
+//! never cached. A `ttl_seconds` of 0 disables the cache.
+//!
+//! SQLite calls block, so `get` and `put` run them on Tokio's blocking pool rather than on
+//! the task that awaits them.
+
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::config::CacheCfg;
+use crate::error::{AiProxyError, CoreResult};
+use crate::model::{ChatRequest, EmbedRequest};
+use crate::normalizer;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS responses (
+    key TEXT PRIMARY KEY,
+    created_ms INTEGER NOT NULL,
+    body TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS responses_created ON responses (created_ms);
+";
+
+fn db_err(e: rusqlite::Error) -> AiProxyError {
+    AiProxyError::Other(e.into())
+}
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+fn digest(kind: &str, provider: &str, body: &impl Serialize) -> String {
+    let mut bytes = format!("{kind}\n{provider}\n").into_bytes();
+    serde_json::to_writer(&mut bytes, body).expect("requests serialize");
+    ring::digest::digest(&ring::digest::SHA256, &bytes)
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Cache key for sending `req` to `provider`, or `None` if it must not be cached.
+pub fn chat_key(provider: &str, req: &ChatRequest) -> Option<String> {
+    if req.include_raw {
+        return None;
+    }
+    let mut req = normalizer::normalize_chat(req.clone());
+    req.request_id = None;
+    req.trace_id = None;
+    req.idempotency_key = None;
+    req.requested_model = None;
+    Some(digest("chat", provider, &req))
+}
+
+/// Cache key for sending `req` to `provider`.
+pub fn embed_key(provider: &str, req: &EmbedRequest) -> String {
+    let mut req = normalizer::normalize_embed(req.clone());
+    req.requested_model = None;
+    digest("embed", provider, &req)
+}
+
+/// Response store shared by every provider a registry wraps in `CachingProvider`.
+pub struct ResponseCache {
+    conn: Arc<Mutex<Connection>>,
+    ttl: Duration,
+}
+
+impl std::fmt::Debug for ResponseCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResponseCache")
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResponseCache {
+    /// Open (creating if needed) the database at `path`.
+    pub fn open(path: &Path, ttl: Duration) -> CoreResult<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path).map_err(db_err)?;
+        conn.busy_timeout(Duration::from_secs(5)).map_err(db_err)?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(db_err)?;
+        Self::init(conn, ttl)
+    }
+
+    pub fn open_in_memory(ttl: Duration) -> CoreResult<Self> {
+        Self::init(Connection::open_in_memory().map_err(db_err)?, ttl)
+    }
+
+    /// The cache `cfg` asks for: in memory for a `path` of `:memory:`, `None` when
+    /// `ttl_seconds` is 0.
+    pub fn from_cfg(cfg: &CacheCfg) -> CoreResult<Option<Arc<Self>>> {
+        if cfg.ttl_seconds == 0 {
+            return Ok(None);
+        }
+        let ttl = Duration::from_secs(cfg.ttl_seconds);
+        let cache = match cfg.path.as_str() {
+            ":memory:" => Self::open_in_memory(ttl)?,
+            path => Self::open(Path::new(path), ttl)?,
+        };
+        Ok(Some(Arc::new(cache)))
+    }
+
+    fn init(conn: Connection, ttl: Duration) -> CoreResult<Self> {
+        conn.execute_batch(SCHEMA).map_err(db_err)?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            ttl,
+        })
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Run `f` on the connection from the blocking pool.
+    async fn with_conn<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<R> + Send + 'static,
+    ) -> CoreResult<R> {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || f(&conn.lock().unwrap()))
+            .await
+            .map_err(|e| AiProxyError::Other(e.into()))?
+            .map_err(db_err)
+    }
+
+    /// The unexpired entry stored under `key`.
+    pub async fn get<T: DeserializeOwned>(&self, key: &str) -> CoreResult<Option<T>> {
+        let cutoff = now_ms().saturating_sub(self.ttl.as_millis() as u64);
+        let key = key.to_string();
+        let body: Option<String> = self
+            .with_conn(move |conn| {
+                conn.query_row(
+                    "SELECT body FROM responses WHERE key = ?1 AND created_ms >= ?2",
+                    params![key, cutoff as i64],
+                    |row| row.get(0),
+                )
+                .optional()
+            })
+            .await?;
+        // An entry written by an incompatible version is a miss, not an error.
+        Ok(body.and_then(|b| serde_json::from_str(&b).ok()))
+    }
+
+    /// Store `value` under `key`, replacing any previous entry, and drop expired ones.
+    pub async fn put<T: Serialize>(&self, key: &str, value: &T) -> CoreResult<()> {
+        let body = serde_json::to_string(value).map_err(|e| AiProxyError::Other(e.into()))?;
+        let now = now_ms();
+        let cutoff = now.saturating_sub(self.ttl.as_millis() as u64);
+        let key = key.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO responses (key, created_ms, body) VALUES (?1, ?2, ?3)",
+                params![key, now as i64, body],
+            )?;
+            conn.execute(
+                "DELETE FROM responses WHERE created_ms < ?1",
+                params![cutoff as i64],
+            )
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Drop every entry, returning how many there were.
+    pub async fn clear(&self) -> CoreResult<u64> {
+        let n = self
+            .with_conn(|conn| conn.execute("DELETE FROM responses", []))
+            .await?;
+        Ok(n as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{ChatProvider, NullProvider};
+
+    use crate::model::ChatResponse;
+
+    fn req(text: &str) -> ChatRequest {
+        ChatRequest::builder().model("m").user(text).build()
+    }
+
+    fn key(text: &str) -> String {
+        chat_key("p", &req(text)).unwrap()
+    }
+
+    async fn resp(text: &str) -> ChatResponse {
+        let mut resp = NullProvider.chat(req("hi")).await.unwrap();
+        resp.text = text.into();
+        resp
+    }
+
+    #[test]
+    fn keys_ignore_per_call_ids_but_not_content_or_caller() {
+        let a = req("hi");
+        let mut b = req("  hi\r\n");
+        b.request_id = Some("r-2".into());
+        b.idempotency_key = Some("i-2".into());
+        assert_eq!(chat_key("p", &a), chat_key("p", &b));
+        assert_ne!(chat_key("p", &a), chat_key("q", &a));
+        assert_ne!(chat_key("p", &a), chat_key("p", &req("bye")));
+        let mut other_caller = req("hi");
+        other_caller.client_key = Some("k2".into());
+        assert_ne!(chat_key("p", &a), chat_key("p", &other_caller));
+        let mut raw = req("hi");
+        raw.include_raw = true;
+        assert_eq!(chat_key("p", &raw), None);
+    }
+
+    #[tokio::test]
+    async fn entries_round_trip_until_they_expire() {
+        let cache = ResponseCache::open_in_memory(Duration::from_secs(60)).unwrap();
+        assert!(
+            cache
+                .get::<ChatResponse>(&key("hi"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+        cache.put(&key("hi"), &resp("hello").await).await.unwrap();
+        let hit: ChatResponse = cache.get(&key("hi")).await.unwrap().unwrap();
+        assert_eq!(hit.text, "hello");
+        assert!(
+            cache
+                .get::<ChatResponse>(&key("bye"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        assert_eq!(cache.clear().await.unwrap(), 1);
+        assert!(
+            cache
+                .get::<ChatResponse>(&key("hi"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let expired = ResponseCache::open_in_memory(Duration::ZERO).unwrap();
+        expired.put(&key("hi"), &resp("hello").await).await.unwrap();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(
+            expired
+                .get::<ChatResponse>(&key("hi"))
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn zero_ttl_disables_the_cache() {
+        let cfg = |ttl_seconds| CacheCfg {
+            path: ":memory:".into(),
+            ttl_seconds,
+        };
+        assert!(ResponseCache::from_cfg(&cfg(0)).unwrap().is_none());
+        assert!(ResponseCache::from_cfg(&cfg(60)).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn file_caches_persist_across_opens() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested/cache.db");
+        let ttl = Duration::from_secs(60);
+        let cache = ResponseCache::open(&path, ttl).unwrap();
+        cache.put(&key("hi"), &resp("hello").await).await.unwrap();
+        drop(cache);
+        let reopened = ResponseCache::open(&path, ttl).unwrap();
+        let hit: ChatResponse = reopened.get(&key("hi")).await.unwrap().unwrap();
+        assert_eq!(hit.text, "hello");
+    }
+}
@@ -0,0 +1,317 @@
+//! Built-in facts about known models: context window, output limit, capabilities, price and
+//! tokenizer. Routing, pre-dispatch validation, cost estimation and token counting all read
+//! from here.
+//!
+//! Entries are matched by longest model-id prefix, so dated snapshots (e.g.
+//! `gpt-4o-2024-08-06`) resolve to their family. OpenRouter-style ids (`vendor/model`) are
+//! matched on the part after the slash. `[catalog.models]` in the config adds models or
+//! overrides fields of built-in ones; see `set_overrides`.
+
+use std::collections::BTreeMap;
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+use crate::cost::{Price, price};
+use crate::provider::Capability;
+use crate::tokenizer::Counter;
+
+/// What is known about one model. Every field is optional: an override only sets the fields
+/// it names, and unknown facts are simply not checked.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ModelSpec {
+    /// Prompt and completion tokens together.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    /// Largest `max_output_tokens` the model accepts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Vec<Capability>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price: Option<Price>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tokenizer: Option<Counter>,
+    /// Most inputs one embedding request may carry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_batch_inputs: Option<u32>,
+    /// Most tokens, across all inputs, one embedding request may carry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_batch_tokens: Option<u32>,
+}
+
+impl ModelSpec {
+    /// `self`'s fields, with `base`'s filling any it leaves unset.
+    fn or(self, base: ModelSpec) -> ModelSpec {
+        ModelSpec {
+            context_window: self.context_window.or(base.context_window),
+            max_output_tokens: self.max_output_tokens.or(base.max_output_tokens),
+            capabilities: self.capabilities.or(base.capabilities),
+            price: self.price.or(base.price),
+            tokenizer: self.tokenizer.or(base.tokenizer),
+            max_batch_inputs: self.max_batch_inputs.or(base.max_batch_inputs),
+            max_batch_tokens: self.max_batch_tokens.or(base.max_batch_tokens),
+        }
+    }
+}
+
+struct Builtin {
+    prefix: &'static str,
+    context_window: u32,
+    max_output_tokens: Option<u32>,
+    capabilities: &'static [Capability],
+    tokenizer: Counter,
+    price: Option<Price>,
+    /// Embedding request limits: inputs, then total tokens.
+    batch: Option<(u32, u32)>,
+}
+
+const CHAT: &[Capability] = &[Capability::Chat, Capability::ChatStream, Capability::Tools];
+
+const fn chat(
+    prefix: &'static str,
+    context_window: u32,
+    max_output_tokens: u32,
+    tokenizer: Counter,
+    price: Price,
+) -> Builtin {
+    Builtin {
+        prefix,
+        context_window,
+        max_output_tokens: Some(max_output_tokens),
+        capabilities: CHAT,
+        tokenizer,
+        price: Some(price),
+        batch: None,
+    }
+}
+
+/// An OpenAI chat model on the current `o200k_base` encoding.
+const fn openai(prefix: &'static str, window: u32, max_out: u32, price: Price) -> Builtin {
+    chat(prefix, window, max_out, Counter::O200kBase, price)
+}
+
+/// An older OpenAI chat model on `cl100k_base`.
+const fn openai_cl100k(prefix: &'static str, window: u32, max_out: u32, price: Price) -> Builtin {
+    chat(prefix, window, max_out, Counter::Cl100kBase, price)
+}
+
+/// A Claude model; all have a 200K window.
+const fn claude(prefix: &'static str, max_out: u32, price: Price) -> Builtin {
+    chat(prefix, 200_000, max_out, Counter::Heuristic, price)
+}
+
+/// An OpenAI embedding model: up to 2048 inputs and 300K tokens per request.
+const fn embed(prefix: &'static str, per_mtok: f64) -> Builtin {
+    Builtin {
+        prefix,
+        context_window: 8_191,
+        max_output_tokens: None,
+        capabilities: &[Capability::Embed],
+        tokenizer: Counter::Cl100kBase,
+        price: Some(price(per_mtok, 0.0)),
+        batch: Some((2_048, 300_000)),
+    }
+}
+
+/// An OpenAI Realtime model; sessions only, and priced per audio token as well, so no price.
+const fn realtime(prefix: &'static str) -> Builtin {
+    Builtin {
+        prefix,
+        context_window: 128_000,
+        max_output_tokens: Some(4_096),
+        capabilities: &[Capability::Realtime],
+        tokenizer: Counter::O200kBase,
+        price: None,
+        batch: None,
+    }
+}
+
+const BUILTIN: &[Builtin] = &[
+    openai_cl100k("gpt-3.5-turbo", 16_385, 4_096, price(0.50, 1.50)),
+    openai_cl100k("gpt-4", 8_192, 8_192, price(30.00, 60.00)),
+    openai_cl100k("gpt-4-turbo", 128_000, 4_096, price(10.00, 30.00)),
+    openai("gpt-4o", 128_000, 16_384, price(2.50, 10.00)),
+    openai("gpt-4o-mini", 128_000, 16_384, price(0.15, 0.60)),
+    openai("gpt-4.1", 1_047_576, 32_768, price(2.00, 8.00)),
+    openai("gpt-4.1-mini", 1_047_576, 32_768, price(0.40, 1.60)),
+    openai("gpt-4.1-nano", 1_047_576, 32_768, price(0.10, 0.40)),
+    openai("gpt-5", 400_000, 128_000, price(1.25, 10.00)),
+    openai("o1", 200_000, 100_000, price(15.00, 60.00)),
+    openai("o3", 200_000, 100_000, price(2.00, 8.00)),
+    openai("o3-mini", 200_000, 100_000, price(1.10, 4.40)),
+    openai("o4-mini", 200_000, 100_000, price(1.10, 4.40)),
+    realtime("gpt-4o-realtime"),
+    realtime("gpt-4o-mini-realtime"),
+    realtime("gpt-realtime"),
+    // Claude 3 models not listed below share its window and output limit, at unknown price.
+    Builtin {
+        price: None,
+        ..claude("claude-3", 4_096, price(0.0, 0.0))
+    },
+    claude("claude-3-haiku", 4_096, price(0.25, 1.25)),
+    claude("claude-3-opus", 4_096, price(15.00, 75.00)),
+    claude("claude-3-5-haiku", 8_192, price(0.80, 4.00)),
+    claude("claude-3-5-sonnet", 8_192, price(3.00, 15.00)),
+    claude("claude-3-7-sonnet", 64_000, price(3.00, 15.00)),
+    claude("claude-sonnet-4", 64_000, price(3.00, 15.00)),
+    claude("claude-opus-4", 32_000, price(15.00, 75.00)),
+    embed("text-embedding-3-small", 0.02),
+    embed("text-embedding-3-large", 0.13),
+    embed("text-embedding-ada-002", 0.10),
+];
+
+static OVERRIDES: OnceCell<BTreeMap<String, ModelSpec>> = OnceCell::new();
+
+/// Install the config's `[catalog.models]` for the process. Returns `false` if overrides were
+/// already installed.
+pub fn set_overrides(models: BTreeMap<String, ModelSpec>) -> bool {
+    OVERRIDES.set(models).is_ok()
+}
+
+fn bare(model: &str) -> &str {
+    model.rsplit_once('/').map_or(model, |(_, m)| m)
+}
+
+fn builtin(model: &str) -> Option<ModelSpec> {
+    let bare = bare(model);
+    BUILTIN
+        .iter()
+        .filter(|b| bare.starts_with(b.prefix))
+        .max_by_key(|b| b.prefix.len())
+        .map(|b| ModelSpec {
+            context_window: Some(b.context_window),
+            max_output_tokens: b.max_output_tokens,
+            capabilities: Some(b.capabilities.to_vec()),
+            price: b.price,
+            tokenizer: Some(b.tokenizer),
+            max_batch_inputs: b.batch.map(|(inputs, _)| inputs),
+            max_batch_tokens: b.batch.map(|(_, tokens)| tokens),
+        })
+}
+
+/// What `overrides` and the built-in table say about `model`. An override matches by exact id
+/// first, then by longest prefix like the built-in entries, and wins field by field.
+pub fn lookup(overrides: &BTreeMap<String, ModelSpec>, model: &str) -> Option<ModelSpec> {
+    let over = overrides.get(model).or_else(|| {
+        overrides
+            .iter()
+            .filter(|(prefix, _)| bare(model).starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, spec)| spec)
+    });
+    match (over.cloned(), builtin(model)) {
+        (Some(o), Some(b)) => Some(o.or(b)),
+        (o, b) => o.or(b),
+    }
+}
+
+/// What the catalog, with any installed overrides, says about `model`.
+pub fn spec(model: &str) -> Option<ModelSpec> {
+    match OVERRIDES.get() {
+        Some(overrides) => lookup(overrides, model),
+        None => builtin(model),
+    }
+}
+
+/// Context window of `model`, if known.
+pub fn context_window(model: &str) -> Option<u32> {
+    spec(model)?.context_window
+}
+
+/// Largest `max_output_tokens` `model` accepts, if known.
+pub fn max_output_tokens(model: &str) -> Option<u32> {
+    spec(model)?.max_output_tokens
+}
+
+/// Price of `model`, if known.
+pub fn price_for(model: &str) -> Option<Price> {
+    spec(model)?.price
+}
+
+/// Tokenizer for `model`, if the catalog names one.
+pub fn tokenizer(model: &str) -> Option<Counter> {
+    spec(model)?.tokenizer
+}
+
+/// Most inputs one embedding request for `model` may carry, if known.
+pub fn max_batch_inputs(model: &str) -> Option<u32> {
+    spec(model)?.max_batch_inputs
+}
+
+/// Most tokens one embedding request for `model` may carry, if known.
+pub fn max_batch_tokens(model: &str) -> Option<u32> {
+    spec(model)?.max_batch_tokens
+}
+
+/// Whether `model` has `capability`; `None` when the catalog does not list its capabilities.
+pub fn supports(model: &str, capability: Capability) -> Option<bool> {
+    let caps = spec(model)?.capabilities?;
+    Some(caps.contains(&capability))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn windows_match_by_longest_prefix() {
+        assert_eq!(context_window("gpt-4o-mini-2024-07-18"), Some(128_000));
+        assert_eq!(context_window("gpt-4-0613"), Some(8_192));
+        assert_eq!(context_window("gpt-4-turbo-preview"), Some(128_000));
+        assert_eq!(context_window("anthropic/claude-3-5-sonnet"), Some(200_000));
+        assert_eq!(max_output_tokens("claude-3-sonnet-20240229"), Some(4_096));
+        assert_eq!(context_window("mystery-model"), None);
+    }
+
+    #[test]
+    fn capabilities_and_tokenizers() {
+        assert_eq!(supports("gpt-4o", Capability::Tools), Some(true));
+        assert_eq!(
+            supports("gpt-4o-realtime-preview", Capability::Realtime),
+            Some(true)
+        );
+        assert_eq!(
+            supports("gpt-4o-mini-realtime-preview", Capability::Chat),
+            Some(false)
+        );
+        assert_eq!(
+            supports("text-embedding-3-small", Capability::Chat),
+            Some(false)
+        );
+        assert_eq!(supports("mystery-model", Capability::Chat), None);
+        assert_eq!(tokenizer("openai/gpt-4.1-mini"), Some(Counter::O200kBase));
+        assert_eq!(tokenizer("claude-opus-4"), Some(Counter::Heuristic));
+        assert_eq!(max_batch_inputs("text-embedding-3-small"), Some(2_048));
+        assert_eq!(max_batch_tokens("text-embedding-ada-002"), Some(300_000));
+        assert_eq!(max_batch_inputs("gpt-4o"), None);
+    }
+
+    #[test]
+    fn overrides_win_field_by_field() {
+        let overrides: BTreeMap<String, ModelSpec> = toml::from_str(
+            r#"
+            [gpt-4o]
+            max_output_tokens = 4096
+
+            [my-finetune]
+            context_window = 32000
+            capabilities = ["chat"]
+            price = { prompt_per_mtok = 1.0, completion_per_mtok = 2.0 }
+            tokenizer = "cl100k_base"
+            "#,
+        )
+        .unwrap();
+        let spec = lookup(&overrides, "gpt-4o-2024-08-06").unwrap();
+        assert_eq!(spec.max_output_tokens, Some(4_096));
+        assert_eq!(spec.context_window, Some(128_000));
+        assert_eq!(spec.price, Some(price(2.50, 10.00)));
+
+        let spec = lookup(&overrides, "my-finetune").unwrap();
+        assert_eq!(spec.context_window, Some(32_000));
+        assert_eq!(spec.max_output_tokens, None);
+        assert_eq!(spec.capabilities, Some(vec![Capability::Chat]));
+        assert_eq!(spec.tokenizer, Some(Counter::Cl100kBase));
+        assert_eq!(lookup(&overrides, "mystery-model"), None);
+    }
+}
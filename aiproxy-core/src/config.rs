@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::{fs, path::Path};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -6,12 +7,129 @@ pub struct Providers {
     pub openai: Option<ProviderCfg>,
     pub anthropic: Option<ProviderCfg>,
     pub openrouter: Option<ProviderCfg>,
+    /// Offline pseudo-embeddings (`providers::hash`); registered as `hash` when present.
+    #[serde(default)]
+    pub hash: Option<HashEmbedCfg>,
+    /// Local models on an Ollama server (`providers::ollama`); registered as `ollama` when
+    /// present. Needs no API key.
+    #[serde(default)]
+    pub ollama: Option<OllamaCfg>,
+    /// Fixture-backed chat and embeddings (`providers::replay`); registered as `replay` when
+    /// present. Needs no API key.
+    #[serde(default)]
+    pub replay: Option<ReplayCfg>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct OllamaCfg {
+    /// Server address (default `http://localhost:11434`).
+    #[serde(default = "default_ollama_base_url")]
+    pub base_url: String,
+    /// Which `ChatRequest.extra` parameters may be passed through to the server.
+    #[serde(default)]
+    pub extra_params: ExtraParamsCfg,
+    /// Transport settings replacing `[http]`'s for this server.
+    #[serde(default)]
+    pub http: HttpOverridesCfg,
+}
+
+impl Default for OllamaCfg {
+    fn default() -> Self {
+        Self {
+            base_url: default_ollama_base_url(),
+            extra_params: ExtraParamsCfg::default(),
+            http: HttpOverridesCfg::default(),
+        }
+    }
+}
+
+fn default_ollama_base_url() -> String {
+    "http://localhost:11434".to_string()
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ReplayCfg {
+    /// Directory holding one fixture file per recorded request.
+    pub dir: String,
+    /// Provider that answers, and records, requests with no fixture yet. Without one, or
+    /// while it is not registered, such requests fail.
+    #[serde(default)]
+    pub upstream: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct HashEmbedCfg {
+    #[serde(default = "default_hash_dimensions")]
+    pub dimensions: usize,
+    /// Different seeds give unrelated vector spaces.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+impl Default for HashEmbedCfg {
+    fn default() -> Self {
+        Self {
+            dimensions: default_hash_dimensions(),
+            seed: 0,
+        }
+    }
+}
+
+fn default_hash_dimensions() -> usize {
+    256
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ProviderCfg {
     /// Name of the environment variable that contains the API key.
     pub api_key_env: String,
+    /// Which `ChatRequest.extra` parameters may be passed through to this provider.
+    #[serde(default)]
+    pub extra_params: ExtraParamsCfg,
+    /// Which organizations and projects a request may bill to instead of the configured
+    /// ones. Only the OpenAI adapter honours these.
+    #[serde(default)]
+    pub billing: BillingOverridesCfg,
+    /// Transport settings replacing `[http]`'s for this provider.
+    #[serde(default)]
+    pub http: HttpOverridesCfg,
+}
+
+/// `[providers.<name>.http]`: the `[http]` timeouts and pool size for one provider. Unset
+/// fields keep `[http]`'s value.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct HttpOverridesCfg {
+    pub connect_timeout_ms: Option<u64>,
+    pub request_timeout_ms: Option<u64>,
+    pub pool_max_idle_per_host: Option<usize>,
+}
+
+/// Values a request may set in `ChatRequest.organization` / `ChatRequest.project`. Empty
+/// lists reject every override.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct BillingOverridesCfg {
+    #[serde(default)]
+    pub organizations: Vec<String>,
+    #[serde(default)]
+    pub projects: Vec<String>,
+}
+
+/// Allow/deny lists for `ChatRequest.extra` keys. An empty `allow` admits every key not in
+/// `deny`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct ExtraParamsCfg {
+    #[serde(default)]
+    pub allow: Vec<String>,
+    #[serde(default)]
+    pub deny: Vec<String>,
+}
+
+impl ExtraParamsCfg {
+    pub fn permits(&self, key: &str) -> bool {
+        !self.deny.iter().any(|k| k == key)
+            && (self.allow.is_empty() || self.allow.iter().any(|k| k == key))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -57,6 +175,89 @@ pub struct HttpCfg {
     /// Optional per-host idle connection pool cap (None = reqwest default)
     #[serde(default)]
     pub pool_max_idle_per_host: Option<usize>,
+    /// End a stream that goes this long without a line (None = wait as long as it takes).
+    #[serde(default)]
+    pub stream_idle_timeout_ms: Option<u64>,
+    /// End a stream still running this long after its request was sent (None = no deadline).
+    #[serde(default)]
+    pub stream_deadline_ms: Option<u64>,
+    /// Record provider traffic to, or replay it from, a cassette file (see `vcr`).
+    #[serde(default)]
+    pub vcr: VcrCfg,
+    /// How provider calls are retried after a 429, a 5xx or a failed connection.
+    #[serde(default)]
+    pub retry: RetryCfg,
+    /// When a provider that keeps failing is taken out of rotation for a while.
+    #[serde(default)]
+    pub circuit: CircuitCfg,
+}
+
+/// `[http.retry]`: exponential backoff applied by `HttpClient` to every provider call.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct RetryCfg {
+    /// Attempts per call, the first included; 1 disables retries.
+    pub max_attempts: u32,
+    /// Wait before the first retry; it doubles with each one after.
+    pub base_backoff_ms: u64,
+    /// Longest wait between two attempts.
+    pub max_backoff_ms: u64,
+    /// Wait a random time up to the backoff instead of the full backoff.
+    pub jitter: bool,
+    /// Wait as long as a `Retry-After` header asks, giving up if that is over
+    /// `max_backoff_ms`.
+    pub respect_retry_after: bool,
+}
+
+impl Default for RetryCfg {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_backoff_ms: 250,
+            max_backoff_ms: 8_000,
+            jitter: true,
+            respect_retry_after: true,
+        }
+    }
+}
+
+/// `[http.circuit]`: the circuit breaker `providers::circuit` puts around every provider.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct CircuitCfg {
+    /// Consecutive rate-limited or unavailable calls that open the circuit; 0 disables it.
+    pub failure_threshold: u32,
+    /// How long an open circuit fails calls before letting one through to test the provider.
+    pub cooldown_ms: u64,
+}
+
+impl Default for CircuitCfg {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 0,
+            cooldown_ms: 30_000,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum VcrMode {
+    #[default]
+    Off,
+    /// Send requests upstream and append each exchange to the cassette.
+    Record,
+    /// Answer requests from the cassette; nothing is sent upstream.
+    Replay,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct VcrCfg {
+    #[serde(default)]
+    pub mode: VcrMode,
+    /// Cassette file; required unless `mode` is `off`.
+    #[serde(default)]
+    pub cassette: Option<String>,
 }
 
 impl Default for HttpCfg {
@@ -65,6 +266,11 @@ impl Default for HttpCfg {
             connect_timeout_ms: default_connect_timeout_ms(),
             request_timeout_ms: default_request_timeout_ms(),
             pool_max_idle_per_host: None,
+            stream_idle_timeout_ms: None,
+            stream_deadline_ms: None,
+            vcr: VcrCfg::default(),
+            retry: RetryCfg::default(),
+            circuit: CircuitCfg::default(),
         }
     }
 }
@@ -76,12 +282,342 @@ fn default_request_timeout_ms() -> u64 {
     60_000
 }
 
+/// What caller-identifying data may leave the proxy.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct PrivacyCfg {
+    /// Forward `ChatRequest.metadata` and a redacted client-key label to providers that accept
+    /// them (OpenAI `user`/`metadata`, Anthropic `metadata.user_id`, OpenRouter `user`).
+    #[serde(default)]
+    pub forward_metadata: bool,
+}
+
+/// Prompt token budget enforced before dispatch by `normalizer::truncate_to_budget`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct TruncationCfg {
+    /// Budget applied to every model.
+    #[serde(default)]
+    pub max_prompt_tokens: Option<u32>,
+    /// Context window per model name; the prompt may use whatever `max_output_tokens` leaves.
+    #[serde(default)]
+    pub context_windows: BTreeMap<String, u32>,
+    /// Measure prompts with the provider's counting endpoint where it has one (Anthropic)
+    /// before truncating and reserving tokens, at the cost of one extra call per request.
+    #[serde(default)]
+    pub exact_counts: bool,
+    /// Fit every request to the context window and output limit of the model it is
+    /// dispatched to, the catalog's when `context_windows` has none: lower
+    /// `max_output_tokens` first, then trim history. Off → requests that do not fit fail
+    /// validation.
+    #[serde(default)]
+    pub auto_fit: bool,
+}
+
+impl TruncationCfg {
+    /// Prompt budget for `model`: the tighter of `max_prompt_tokens` and the model's context
+    /// window minus `max_output_tokens`. The window is only the configured one unless
+    /// `auto_fit` is set. `None` when neither is known.
+    pub fn budget(&self, model: &str, max_output_tokens: Option<u32>) -> Option<u32> {
+        let window = if self.auto_fit {
+            self.context_window(model)
+        } else {
+            self.context_windows.get(model).copied()
+        };
+        let window = window.map(|w| w.saturating_sub(max_output_tokens.unwrap_or(0)));
+        match (self.max_prompt_tokens, window) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Context window for `model`: the configured one, else the built-in catalog's.
+    pub fn context_window(&self, model: &str) -> Option<u32> {
+        self.context_windows
+            .get(model)
+            .copied()
+            .or_else(|| crate::catalog::context_window(model))
+    }
+}
+
+/// Additions and corrections to the built-in model catalog (see `catalog`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct CatalogCfg {
+    /// Specs by model id or id prefix; set fields override the built-in entry's.
+    #[serde(default)]
+    pub models: BTreeMap<String, crate::catalog::ModelSpec>,
+}
+
+/// Fault injection for resilience testing (see `providers::chaos`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ChaosCfg {
+    /// Faults by registered provider name; providers not listed behave normally.
+    #[serde(default)]
+    pub providers: BTreeMap<String, FaultCfg>,
+}
+
+/// Faults injected into one provider's calls. Probabilities are per call, in `0.0..=1.0`, and
+/// at most one fault fires per call.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct FaultCfg {
+    /// Delay added before every call.
+    #[serde(default)]
+    pub latency_ms: u64,
+    /// Fail with a 429 (`RateLimited`).
+    #[serde(default)]
+    pub rate_limit: f64,
+    /// Fail with a 5xx (`ProviderUnavailable`).
+    #[serde(default)]
+    pub server_error: f64,
+    /// Fail as if the response body were not valid JSON.
+    #[serde(default)]
+    pub malformed: f64,
+    /// Drop the connection: streams end with `StreamDisconnected` after their first event.
+    #[serde(default)]
+    pub disconnect: f64,
+    /// Fixed seed for a reproducible fault sequence; missing → seeded from the clock.
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl FaultCfg {
+    fn probabilities(&self) -> [(&'static str, f64); 4] {
+        [
+            ("rate_limit", self.rate_limit),
+            ("server_error", self.server_error),
+            ("malformed", self.malformed),
+            ("disconnect", self.disconnect),
+        ]
+    }
+}
+
+/// One customer served by a shared proxy (see `tenant`): which client keys belong to it, and
+/// the credentials, routing and budget its requests use instead of the process-wide ones.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct TenantCfg {
+    /// Env var holding the tenant's comma-separated client keys.
+    pub keys_env: String,
+    /// Provider name to the env var holding the tenant's own API key for it. Providers not
+    /// listed use the process-wide key.
+    #[serde(default)]
+    pub api_key_env: BTreeMap<String, String>,
+    /// Rules tried before the global `routing.rules`.
+    #[serde(default)]
+    pub routing: Vec<RoutingRule>,
+    /// Replaces `routing.default` for this tenant.
+    #[serde(default)]
+    pub routing_default: Option<String>,
+    /// Requests per minute across all of the tenant's keys.
+    #[serde(default)]
+    pub rpm: Option<u32>,
+    /// Tokens per minute across all of the tenant's keys.
+    #[serde(default)]
+    pub tpm: Option<u32>,
+    /// Tokens per UTC day across all of the tenant's keys.
+    #[serde(default)]
+    pub daily_tokens: Option<u64>,
+}
+
+/// Structural request checks beyond those every request gets (see `normalizer::validate_chat`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct ValidationCfg {
+    /// Most messages accepted in one request, system messages included.
+    #[serde(default)]
+    pub max_messages: Option<usize>,
+}
+
+/// Sharing one upstream call between concurrent identical chats (see `providers::dedup`).
+/// Chats with the same `idempotency_key` always share one.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct DedupCfg {
+    /// Also share between chats without a key that are the same request once normalized.
+    /// Off by default: callers repeating a prompt to sample several answers would get one.
+    #[serde(default)]
+    pub identical: bool,
+}
+
+/// Splitting embedding requests too large for one upstream call (see `providers::chunking`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct EmbeddingsCfg {
+    /// Most inputs per call for models without a catalog `max_batch_inputs`. Never above the
+    /// provider's own limit.
+    #[serde(default)]
+    pub max_batch_inputs: Option<u32>,
+    /// Most tokens per call for models without a catalog `max_batch_tokens`.
+    #[serde(default)]
+    pub max_batch_tokens: Option<u32>,
+    /// Calls one split request may have in flight at once.
+    #[serde(default = "default_embed_concurrency")]
+    pub concurrency: usize,
+}
+
+impl Default for EmbeddingsCfg {
+    fn default() -> Self {
+        Self {
+            max_batch_inputs: None,
+            max_batch_tokens: None,
+            concurrency: default_embed_concurrency(),
+        }
+    }
+}
+
+fn default_embed_concurrency() -> usize {
+    4
+}
+
+/// Replacing older turns with a model-written summary (see `summarize`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SummarizationCfg {
+    /// Model that writes summaries, routed like any other; `None` disables summarization.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Estimated prompt tokens above which older turns are summarized.
+    #[serde(default = "default_summarize_threshold")]
+    pub threshold_tokens: u32,
+    /// Most recent non-system messages always kept verbatim.
+    #[serde(default = "default_keep_recent")]
+    pub keep_recent: usize,
+    /// `max_output_tokens` for the summary call.
+    #[serde(default = "default_summary_tokens")]
+    pub max_summary_tokens: u32,
+}
+
+impl Default for SummarizationCfg {
+    fn default() -> Self {
+        Self {
+            model: None,
+            threshold_tokens: default_summarize_threshold(),
+            keep_recent: default_keep_recent(),
+            max_summary_tokens: default_summary_tokens(),
+        }
+    }
+}
+
+fn default_summarize_threshold() -> u32 {
+    8_000
+}
+fn default_keep_recent() -> usize {
+    6
+}
+fn default_summary_tokens() -> u32 {
+    512
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ScreenMode {
+    #[default]
+    Off,
+    /// Record findings on the request (`ChatRequest.screening`) and dispatch it anyway.
+    Flag,
+    /// Reject requests with any finding as a validation error.
+    Block,
+}
+
+/// Prompt-injection heuristics run before dispatch (see `screening`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ScreeningCfg {
+    #[serde(default)]
+    pub mode: ScreenMode,
+    /// Further phrases, matched case-insensitively, treated as instruction overrides.
+    #[serde(default)]
+    pub extra_phrases: Vec<String>,
+    /// Shortest run of base64 characters reported as an encoded blob.
+    #[serde(default = "default_min_base64_len")]
+    pub min_base64_len: usize,
+}
+
+impl Default for ScreeningCfg {
+    fn default() -> Self {
+        Self {
+            mode: ScreenMode::Off,
+            extra_phrases: Vec::new(),
+            min_base64_len: default_min_base64_len(),
+        }
+    }
+}
+
+fn default_min_base64_len() -> usize {
+    120
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GuardrailAction {
+    #[default]
+    Off,
+    /// Return the response as generated, with the hits in `ChatResponse.guardrail`.
+    Annotate,
+    /// Mask matched text; a moderation hit masks the whole response.
+    Redact,
+    /// Fail the call instead of returning the response.
+    Block,
+}
+
+/// Policies run on generated text before it is returned (see `guardrail`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct GuardrailCfg {
+    #[serde(default)]
+    pub action: GuardrailAction,
+    /// Phrases, matched case-insensitively, the response must not contain.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    /// Regexes the response must not match.
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Provider whose moderation endpoint also checks the response, e.g. `openai`.
+    #[serde(default)]
+    pub moderation: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct RoutingRule {
     /// Regex applied to the model name, e.g. ^gpt-.*
     pub model: String,
     /// Provider to route to when this rule matches
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub provider: String,
+    /// Fallback chain, tried after `provider` (or on its own): each provider gets the chat
+    /// when the one before it is rate limited or unavailable.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub providers: Vec<String>,
+    /// Weighted first choice, in place of `provider`: each request goes to one of these in
+    /// proportion to its weight, then fails over to the others and `providers`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub split: Vec<WeightedProvider>,
+    /// Mirror a share of the rule's chats to another provider, to compare it before a
+    /// cut-over.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowCfg>,
+}
+
+/// Shadow traffic for a routing rule (see `providers::shadow`): mirrored chats are sent in
+/// the background and their responses dropped, but they are recorded like any other call.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ShadowCfg {
+    pub provider: String,
+    /// Model the mirrored chats ask for; missing → the request's own.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Percentage of the rule's chats mirrored, at most 100.
+    pub percent: u32,
+}
+
+/// One arm of a routing rule's `split`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct WeightedProvider {
     pub provider: String,
+    /// Share of requests, relative to the other arms' weights.
+    pub weight: u32,
+}
+
+impl RoutingRule {
+    /// Every provider the rule routes to: the split's arms, then `provider` and `providers`.
+    pub fn targets(&self) -> impl Iterator<Item = &str> {
+        self.split
+            .iter()
+            .map(|w| w.provider.as_str())
+            .chain(std::iter::once(self.provider.as_str()).filter(|p| !p.is_empty()))
+            .chain(self.providers.iter().map(String::as_str))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -89,9 +625,13 @@ pub struct RoutingCfg {
     pub default: String,
     #[serde(default)]
     pub rules: Vec<RoutingRule>,
+    /// Logical model names and the model each stands for, e.g. `fast = "gpt-4o-mini"`.
+    /// Requests for an alias are routed and sent as its model; aliases are applied once.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct Config {
     pub providers: Providers,
     pub cache: CacheCfg,
@@ -100,9 +640,537 @@ pub struct Config {
     /// HTTP client configuration (timeouts, pooling). Missing in older configs → defaults.
     #[serde(default)]
     pub http: HttpCfg,
+    /// Missing → nothing caller-identifying is forwarded.
+    #[serde(default)]
+    pub privacy: PrivacyCfg,
+    /// Missing → prompts are sent as-is, whatever their size.
+    #[serde(default)]
+    pub truncation: TruncationCfg,
+    /// Missing → no screening.
+    #[serde(default)]
+    pub screening: ScreeningCfg,
+    /// Missing → responses are returned unchecked.
+    #[serde(default)]
+    pub guardrail: GuardrailCfg,
+    /// Missing → history is never summarized.
+    #[serde(default)]
+    pub summarization: SummarizationCfg,
+    /// Missing → no message-count limit.
+    #[serde(default)]
+    pub validation: ValidationCfg,
+    /// Missing → embed requests split by catalog and provider limits, 4 calls at a time.
+    #[serde(default)]
+    pub embeddings: EmbeddingsCfg,
+    /// Missing → only chats with the same idempotency key share a call.
+    #[serde(default)]
+    pub dedup: DedupCfg,
+    /// Missing → the built-in model catalog as shipped.
+    #[serde(default)]
+    pub catalog: CatalogCfg,
+    /// Missing → every provider bills the catalog's list prices.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pricing: crate::pricing::PriceTable,
+    /// Missing → no injected faults.
+    #[serde(default)]
+    pub chaos: ChaosCfg,
+    /// Missing → one tenant: every caller shares the process-wide credentials and routing.
+    #[serde(default)]
+    pub tenants: BTreeMap<String, TenantCfg>,
+}
+
+/// Provider names the registry knows how to construct.
+pub const KNOWN_PROVIDERS: &[&str] = &[
+    "openai",
+    "anthropic",
+    "openrouter",
+    "hash",
+    "ollama",
+    "replay",
+    "null",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// One finding from `Config::validate`, addressed by a dotted field path.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub field: String,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+
+    fn warning(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+fn check_dir(field: &str, dir: &str, out: &mut Vec<Diagnostic>) {
+    let p = Path::new(dir);
+    if p.exists() && !p.is_dir() {
+        out.push(Diagnostic::error(
+            field,
+            format!("'{dir}' exists but is not a directory"),
+        ));
+    } else if !p.exists() {
+        out.push(Diagnostic::warning(
+            field,
+            format!("'{dir}' does not exist yet"),
+        ));
+    }
 }
 
 impl Config {
+    /// Env var holding the API key for `provider`: `providers.<name>.api_key_env` when
+    /// configured, else the conventional `<NAME>_API_KEY`. `None` for keyless providers.
+    pub fn api_key_env(&self, provider: &str) -> Option<String> {
+        let configured = match provider {
+            "openai" => &self.providers.openai,
+            "anthropic" => &self.providers.anthropic,
+            "openrouter" => &self.providers.openrouter,
+            _ => return None,
+        };
+        Some(match configured {
+            Some(p) => p.api_key_env.clone(),
+            None => format!("{}_API_KEY", provider.to_ascii_uppercase()),
+        })
+    }
+
+    /// Extra-parameter lists for `provider`; unconfigured providers admit every key.
+    pub fn extra_params(&self, provider: &str) -> ExtraParamsCfg {
+        let configured = match provider {
+            "openai" => &self.providers.openai,
+            "anthropic" => &self.providers.anthropic,
+            "openrouter" => &self.providers.openrouter,
+            "ollama" => {
+                return self
+                    .providers
+                    .ollama
+                    .as_ref()
+                    .map(|o| o.extra_params.clone())
+                    .unwrap_or_default();
+            }
+            _ => return ExtraParamsCfg::default(),
+        };
+        configured
+            .as_ref()
+            .map(|p| p.extra_params.clone())
+            .unwrap_or_default()
+    }
+
+    /// `[http]` with `provider`'s own overrides applied.
+    pub fn http_cfg(&self, provider: &str) -> HttpCfg {
+        let overrides = match provider {
+            "openai" => self.providers.openai.as_ref().map(|p| &p.http),
+            "anthropic" => self.providers.anthropic.as_ref().map(|p| &p.http),
+            "openrouter" => self.providers.openrouter.as_ref().map(|p| &p.http),
+            "ollama" => self.providers.ollama.as_ref().map(|o| &o.http),
+            _ => None,
+        };
+        let mut http = self.http.clone();
+        if let Some(o) = overrides {
+            http.connect_timeout_ms = o.connect_timeout_ms.unwrap_or(http.connect_timeout_ms);
+            http.request_timeout_ms = o.request_timeout_ms.unwrap_or(http.request_timeout_ms);
+            http.pool_max_idle_per_host = o.pool_max_idle_per_host.or(http.pool_max_idle_per_host);
+        }
+        http
+    }
+
+    /// Billing overrides `provider` accepts; none when unconfigured.
+    pub fn billing_overrides(&self, provider: &str) -> BillingOverridesCfg {
+        let configured = match provider {
+            "openai" => &self.providers.openai,
+            "anthropic" => &self.providers.anthropic,
+            "openrouter" => &self.providers.openrouter,
+            _ => return BillingOverridesCfg::default(),
+        };
+        configured
+            .as_ref()
+            .map(|p| p.billing.clone())
+            .unwrap_or_default()
+    }
+
+    /// Cross-field validation beyond what deserialization enforces: routing targets and
+    /// regexes, API key presence for routed providers, directories, and numeric sanity.
+    /// Reads the environment and filesystem but never mutates either.
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut out = Vec::new();
+
+        let mut targets = vec![("routing.default".to_string(), self.routing.default.as_str())];
+        for (alias, model) in &self.routing.aliases {
+            if model.is_empty() {
+                out.push(Diagnostic::error(
+                    format!("routing.aliases.{alias}"),
+                    "must name a model",
+                ));
+            } else if model != alias && self.routing.aliases.contains_key(model) {
+                out.push(Diagnostic::warning(
+                    format!("routing.aliases.{alias}"),
+                    format!("'{model}' is an alias too, but aliases are applied once"),
+                ));
+            }
+        }
+        for (i, rule) in self.routing.rules.iter().enumerate() {
+            if let Err(e) = regex::Regex::new(&rule.model) {
+                out.push(Diagnostic::error(
+                    format!("routing.rules[{i}].model"),
+                    format!("invalid regex '{}': {e}", rule.model),
+                ));
+            }
+            if rule.targets().next().is_none() {
+                out.push(Diagnostic::error(
+                    format!("routing.rules[{i}].provider"),
+                    "set provider, providers or split",
+                ));
+            }
+            if !rule.split.is_empty() && !rule.provider.is_empty() {
+                out.push(Diagnostic::error(
+                    format!("routing.rules[{i}].split"),
+                    "set provider or split, not both",
+                ));
+            }
+            if !rule.split.is_empty() && rule.split.iter().all(|w| w.weight == 0) {
+                out.push(Diagnostic::error(
+                    format!("routing.rules[{i}].split"),
+                    "needs an arm with a weight above 0",
+                ));
+            }
+            for (j, arm) in rule.split.iter().enumerate() {
+                targets.push((
+                    format!("routing.rules[{i}].split[{j}].provider"),
+                    arm.provider.as_str(),
+                ));
+            }
+            if let Some(shadow) = &rule.shadow {
+                if shadow.percent > 100 {
+                    out.push(Diagnostic::error(
+                        format!("routing.rules[{i}].shadow.percent"),
+                        "must be at most 100",
+                    ));
+                }
+                targets.push((
+                    format!("routing.rules[{i}].shadow.provider"),
+                    shadow.provider.as_str(),
+                ));
+            }
+            if !rule.provider.is_empty() {
+                targets.push((
+                    format!("routing.rules[{i}].provider"),
+                    rule.provider.as_str(),
+                ));
+            }
+            for (j, provider) in rule.providers.iter().enumerate() {
+                targets.push((format!("routing.rules[{i}].providers[{j}]"), provider));
+            }
+        }
+        let mut key_checked: Vec<&str> = Vec::new();
+        for (field, provider) in &targets {
+            if !KNOWN_PROVIDERS.contains(provider) {
+                out.push(Diagnostic::error(
+                    field.clone(),
+                    format!(
+                        "unknown provider '{provider}' (expected one of: {})",
+                        KNOWN_PROVIDERS.join(", ")
+                    ),
+                ));
+                continue;
+            }
+            if *provider == "hash" && self.providers.hash.is_none() {
+                out.push(Diagnostic::error(
+                    field.clone(),
+                    "routes to 'hash' but [providers.hash] is not configured",
+                ));
+                continue;
+            }
+            if *provider == "ollama" && self.providers.ollama.is_none() {
+                out.push(Diagnostic::error(
+                    field.clone(),
+                    "routes to 'ollama' but [providers.ollama] is not configured",
+                ));
+                continue;
+            }
+            if *provider == "replay" && self.providers.replay.is_none() {
+                out.push(Diagnostic::error(
+                    field.clone(),
+                    "routes to 'replay' but [providers.replay] is not configured",
+                ));
+                continue;
+            }
+            if key_checked.contains(provider) {
+                continue;
+            }
+            key_checked.push(provider);
+            let Some(env) = self.api_key_env(provider) else {
+                continue;
+            };
+            match std::env::var(&env) {
+                Err(_) => out.push(Diagnostic::error(
+                    field.clone(),
+                    format!("routes to '{provider}' but ${env} is not set"),
+                )),
+                Ok(raw) => {
+                    if let Err(e) = crate::provider_factory::check_key_format(provider, &raw) {
+                        out.push(Diagnostic::error(format!("${env}"), e.to_string()));
+                    }
+                }
+            }
+        }
+        for name in ["openai", "anthropic", "openrouter"] {
+            if key_checked.contains(&name) {
+                continue;
+            }
+            if let Some(env) = self.api_key_env(name)
+                && self.api_key_env_configured(name)
+                && std::env::var(&env).is_err()
+            {
+                out.push(Diagnostic::warning(
+                    format!("providers.{name}.api_key_env"),
+                    format!("${env} is not set; '{name}' will not be registered"),
+                ));
+            }
+        }
+
+        check_dir("transcript.dir", &self.transcript.dir, &mut out);
+        if self.transcript.segment_mb == 0 {
+            out.push(Diagnostic::error(
+                "transcript.segment_mb",
+                "must be greater than 0",
+            ));
+        }
+        if self.cache.path != ":memory:" {
+            let p = Path::new(&self.cache.path);
+            if p.is_dir() {
+                out.push(Diagnostic::error(
+                    "cache.path",
+                    format!(
+                        "'{}' is a directory; expected a database file",
+                        self.cache.path
+                    ),
+                ));
+            } else if let Some(parent) = p.parent().filter(|p| !p.as_os_str().is_empty()) {
+                check_dir("cache.path", &parent.to_string_lossy(), &mut out);
+            }
+        }
+        if self.cache.ttl_seconds == 0 {
+            out.push(Diagnostic::warning(
+                "cache.ttl_seconds",
+                "0 disables caching",
+            ));
+        }
+        if self.http.connect_timeout_ms == 0 {
+            out.push(Diagnostic::error(
+                "http.connect_timeout_ms",
+                "must be greater than 0",
+            ));
+        }
+        if self.http.request_timeout_ms < self.http.connect_timeout_ms {
+            out.push(Diagnostic::warning(
+                "http.request_timeout_ms",
+                "shorter than http.connect_timeout_ms",
+            ));
+        }
+        if self
+            .providers
+            .hash
+            .as_ref()
+            .is_some_and(|h| h.dimensions == 0)
+        {
+            out.push(Diagnostic::error(
+                "providers.hash.dimensions",
+                "must be greater than 0",
+            ));
+        }
+        if let Some(replay) = &self.providers.replay {
+            check_dir("providers.replay.dir", &replay.dir, &mut out);
+            if let Some(upstream) = replay.upstream.as_deref()
+                && (upstream == "replay" || !KNOWN_PROVIDERS.contains(&upstream))
+            {
+                out.push(Diagnostic::error(
+                    "providers.replay.upstream",
+                    format!("'{upstream}' is not a provider replay can record from"),
+                ));
+            }
+        }
+        if self.embeddings.concurrency == 0 {
+            out.push(Diagnostic::error(
+                "embeddings.concurrency",
+                "must be at least 1",
+            ));
+        }
+        if self.embeddings.max_batch_inputs == Some(0) {
+            out.push(Diagnostic::error(
+                "embeddings.max_batch_inputs",
+                "must be greater than 0",
+            ));
+        }
+        if self.http.retry.max_attempts == 0 {
+            out.push(Diagnostic::error(
+                "http.retry.max_attempts",
+                "must be at least 1",
+            ));
+        }
+        if self.http.retry.max_backoff_ms < self.http.retry.base_backoff_ms {
+            out.push(Diagnostic::warning(
+                "http.retry.max_backoff_ms",
+                "shorter than http.retry.base_backoff_ms",
+            ));
+        }
+        if self.http.circuit.failure_threshold > 0 && self.http.circuit.cooldown_ms == 0 {
+            out.push(Diagnostic::warning(
+                "http.circuit.cooldown_ms",
+                "0 lets a call through as soon as the circuit opens",
+            ));
+        }
+        if self.http.vcr.mode != VcrMode::Off && self.http.vcr.cassette.is_none() {
+            out.push(Diagnostic::error(
+                "http.vcr.cassette",
+                "required when http.vcr.mode is record or replay",
+            ));
+        }
+        for (name, fault) in &self.chaos.providers {
+            if !KNOWN_PROVIDERS.contains(&name.as_str()) {
+                out.push(Diagnostic::warning(
+                    format!("chaos.providers.{name}"),
+                    "not a known provider; no faults will be injected",
+                ));
+            }
+            let probabilities = fault.probabilities();
+            for (field, p) in probabilities {
+                if !(0.0..=1.0).contains(&p) {
+                    out.push(Diagnostic::error(
+                        format!("chaos.providers.{name}.{field}"),
+                        format!("{p} is not a probability between 0 and 1"),
+                    ));
+                }
+            }
+            let total: f64 = probabilities.iter().map(|(_, p)| p).sum();
+            if total > 1.0 {
+                out.push(Diagnostic::error(
+                    format!("chaos.providers.{name}"),
+                    format!("fault probabilities sum to {total}, more than 1"),
+                ));
+            }
+        }
+        for (i, pattern) in self.guardrail.patterns.iter().enumerate() {
+            if let Err(e) = regex::Regex::new(pattern) {
+                out.push(Diagnostic::error(
+                    format!("guardrail.patterns[{i}]"),
+                    format!("invalid regex '{pattern}': {e}"),
+                ));
+            }
+        }
+        if let Some(provider) = &self.guardrail.moderation
+            && provider != "openai"
+        {
+            out.push(Diagnostic::error(
+                "guardrail.moderation",
+                format!("'{provider}' has no moderation endpoint (expected openai)"),
+            ));
+        }
+        for (name, tenant) in &self.tenants {
+            let field = |rest: &str| format!("tenants.{name}.{rest}");
+            if std::env::var(&tenant.keys_env).is_err() {
+                out.push(Diagnostic::error(
+                    field("keys_env"),
+                    format!("${} is not set", tenant.keys_env),
+                ));
+            }
+            for (provider, env) in &tenant.api_key_env {
+                if self.api_key_env(provider).is_none() {
+                    out.push(Diagnostic::error(
+                        field(&format!("api_key_env.{provider}")),
+                        format!("'{provider}' does not take an API key"),
+                    ));
+                } else if std::env::var(env).is_err() {
+                    out.push(Diagnostic::error(
+                        field(&format!("api_key_env.{provider}")),
+                        format!("${env} is not set"),
+                    ));
+                }
+            }
+            let mut targets: Vec<(String, &str)> = tenant
+                .routing_default
+                .iter()
+                .map(|p| (field("routing_default"), p.as_str()))
+                .collect();
+            for (i, rule) in tenant.routing.iter().enumerate() {
+                if let Err(e) = regex::Regex::new(&rule.model) {
+                    out.push(Diagnostic::error(
+                        field(&format!("routing[{i}].model")),
+                        format!("invalid regex '{}': {e}", rule.model),
+                    ));
+                }
+                targets.push((field(&format!("routing[{i}].provider")), &rule.provider));
+                for (j, arm) in rule.split.iter().enumerate() {
+                    targets.push((
+                        field(&format!("routing[{i}].split[{j}].provider")),
+                        &arm.provider,
+                    ));
+                }
+            }
+            for (field, provider) in targets {
+                if !KNOWN_PROVIDERS.contains(&provider) {
+                    out.push(Diagnostic::error(
+                        field,
+                        format!("unknown provider '{provider}'"),
+                    ));
+                }
+            }
+        }
+        for (model, spec) in &self.catalog.models {
+            if let (Some(window), Some(max_out)) = (spec.context_window, spec.max_output_tokens)
+                && max_out > window
+            {
+                out.push(Diagnostic::error(
+                    format!("catalog.models.{model}.max_output_tokens"),
+                    format!("{max_out} exceeds context_window {window}"),
+                ));
+            }
+        }
+        for (provider, models) in &self.pricing {
+            for (model, price) in models {
+                for (name, usd) in [
+                    ("prompt_per_1k", price.prompt_per_1k),
+                    ("completion_per_1k", price.completion_per_1k),
+                ] {
+                    if !(usd.is_finite() && usd >= 0.0) {
+                        out.push(Diagnostic::error(
+                            format!("pricing.{provider}.{model}.{name}"),
+                            "must be a price of 0 or more",
+                        ));
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    fn api_key_env_configured(&self, provider: &str) -> bool {
+        match provider {
+            "openai" => self.providers.openai.is_some(),
+            "anthropic" => self.providers.anthropic.is_some(),
+            "openrouter" => self.providers.openrouter.is_some(),
+            _ => false,
+        }
+    }
+
     /// Load a Config from a file path (JSON or TOML by extension). If the
     /// extension is missing or unrecognized, try JSON first, then TOML.
     pub fn from_path<P: AsRef<Path>>(path: P) -> crate::error::CoreResult<Self> {
@@ -124,6 +1192,81 @@ impl Config {
         };
         Ok(cfg)
     }
+
+    /// Override fields from `AIPROXY_<PATH>` variables among `vars`, where `PATH` is the
+    /// field's dotted path upper-cased with `_` for `.`: `AIPROXY_ROUTING_DEFAULT=null` sets
+    /// `routing.default`. Only fields already present can be overridden, so a provider table
+    /// must be in the file before `AIPROXY_PROVIDERS_OPENAI_BASE_URL` applies. String fields
+    /// take the value as is; others parse it as JSON, falling back to a string. Variables
+    /// naming no field are ignored. Returns the overrides applied, in variable order.
+    pub fn apply_env_overrides<I>(&mut self, vars: I) -> crate::error::CoreResult<Vec<EnvOverride>>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut vars: Vec<(String, String)> = vars
+            .into_iter()
+            .filter(|(var, _)| var.starts_with(ENV_PREFIX) && var != CONFIG_ENV)
+            .collect();
+        vars.sort();
+        let mut value = serde_json::to_value(&*self)
+            .map_err(|e| crate::error::AiProxyError::Other(e.into()))?;
+        let mut applied = Vec::new();
+        for (var, raw) in vars {
+            let Some(field) = override_field(&mut value, &var[ENV_PREFIX.len()..], &raw) else {
+                continue;
+            };
+            *self = serde_json::from_value(value.clone())
+                .map_err(|e| crate::error::AiProxyError::Validation(format!("${var}: {e}")))?;
+            applied.push(EnvOverride { var, field });
+        }
+        Ok(applied)
+    }
+}
+
+/// Env var naming the config file when `--config` is not given.
+pub const CONFIG_ENV: &str = "AIPROXY_CONFIG";
+
+/// Prefix of the variables read by `Config::apply_env_overrides`.
+pub const ENV_PREFIX: &str = "AIPROXY_";
+
+/// One variable applied by `Config::apply_env_overrides`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct EnvOverride {
+    pub var: String,
+    /// Dotted path of the field it set.
+    pub field: String,
+}
+
+/// Set the field of `node` that `rest` names to `raw`, returning its dotted path. Keys may
+/// hold `_` themselves, so the longest key `rest` starts with is tried first.
+fn override_field(node: &mut serde_json::Value, rest: &str, raw: &str) -> Option<String> {
+    let serde_json::Value::Object(map) = node else {
+        return None;
+    };
+    let mut keys: Vec<String> = map
+        .keys()
+        .filter(|k| {
+            let up = k.to_ascii_uppercase();
+            rest == up || rest.starts_with(&format!("{up}_"))
+        })
+        .cloned()
+        .collect();
+    keys.sort_by_key(|k| std::cmp::Reverse(k.len()));
+    for key in keys {
+        let child = map.get_mut(&key).expect("a key of this map");
+        if rest.len() == key.len() {
+            *child = match child {
+                serde_json::Value::String(_) => serde_json::Value::String(raw.to_string()),
+                _ => serde_json::from_str(raw)
+                    .unwrap_or_else(|_| serde_json::Value::String(raw.to_string())),
+            };
+            return Some(key);
+        }
+        if let Some(path) = override_field(child, &rest[key.len() + 1..], raw) {
+            return Some(format!("{key}.{path}"));
+        }
+    }
+    None
 }
 
 #[cfg(test)]
@@ -160,6 +1303,322 @@ mod tests {
         assert_eq!(cfg.http.connect_timeout_ms, 5_000);
         assert_eq!(cfg.http.request_timeout_ms, 60_000);
         assert_eq!(cfg.http.pool_max_idle_per_host, None);
+        assert_eq!(cfg.http.retry, RetryCfg::default());
+    }
+
+    #[test]
+    fn provider_http_overrides_replace_only_what_they_set() {
+        let mut cfg: Config = serde_json::from_str(
+            r#"{
+              "providers": {
+                "openai": {"api_key_env":"OPENAI_API_KEY"},
+                "ollama": {"http": {"request_timeout_ms": 600000}}
+              },
+              "cache": {"path":":memory:","ttl_seconds":60},
+              "transcript": {"dir":".tx","segment_mb":64,"fsync":"commit","redact_builtin":true},
+              "routing": {"default": "ollama", "rules": []},
+              "http": {"connect_timeout_ms": 2000, "pool_max_idle_per_host": 4}
+            }"#,
+        )
+        .unwrap();
+        let ollama = cfg.http_cfg("ollama");
+        assert_eq!(ollama.request_timeout_ms, 600_000);
+        assert_eq!(ollama.connect_timeout_ms, 2_000);
+        assert_eq!(ollama.pool_max_idle_per_host, Some(4));
+        assert_eq!(cfg.http_cfg("openai"), cfg.http);
+        cfg.providers
+            .openai
+            .as_mut()
+            .unwrap()
+            .http
+            .pool_max_idle_per_host = Some(16);
+        assert_eq!(cfg.http_cfg("openai").pool_max_idle_per_host, Some(16));
+    }
+
+    fn valid_cfg(dir: &Path) -> Config {
+        Config {
+            providers: Providers {
+                openai: None,
+                anthropic: None,
+                openrouter: None,
+                hash: None,
+                ollama: None,
+                replay: None,
+            },
+            cache: CacheCfg {
+                path: ":memory:".into(),
+                ttl_seconds: 60,
+            },
+            transcript: TranscriptCfg {
+                dir: dir.to_string_lossy().into_owned(),
+                segment_mb: 64,
+                fsync: FsyncPolicy::Commit,
+                redact_builtin: true,
+            },
+            routing: RoutingCfg {
+                default: "null".into(),
+                rules: vec![],
+                aliases: Default::default(),
+            },
+            http: HttpCfg::default(),
+            privacy: PrivacyCfg::default(),
+            truncation: TruncationCfg::default(),
+            screening: ScreeningCfg::default(),
+            guardrail: Default::default(),
+            summarization: SummarizationCfg::default(),
+            validation: ValidationCfg::default(),
+            embeddings: Default::default(),
+            dedup: Default::default(),
+            catalog: CatalogCfg::default(),
+            pricing: Default::default(),
+            chaos: ChaosCfg::default(),
+            tenants: Default::default(),
+        }
+    }
+
+    #[test]
+    fn validate_accepts_minimal_config() {
+        let dir = tempdir().unwrap();
+        assert_eq!(valid_cfg(dir.path()).validate(), vec![]);
+    }
+
+    #[test]
+    fn env_overrides_set_the_fields_they_name() {
+        let dir = tempdir().unwrap();
+        let mut cfg = valid_cfg(dir.path());
+        let vars = [
+            ("AIPROXY_ROUTING_DEFAULT", "openai"),
+            ("AIPROXY_CACHE_TTL_SECONDS", "5"),
+            ("AIPROXY_HTTP_STREAM_IDLE_TIMEOUT_MS", "100"),
+            ("AIPROXY_HTTP_CIRCUIT_FAILURE_THRESHOLD", "3"),
+            ("AIPROXY_CONFIG", "aiproxy.toml"),
+            ("AIPROXY_SESSIONS_DIR", "/tmp"),
+            ("ROUTING_DEFAULT", "anthropic"),
+        ]
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+        let applied = cfg.apply_env_overrides(vars).unwrap();
+        let fields: Vec<&str> = applied.iter().map(|o| o.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            [
+                "cache.ttl_seconds",
+                "http.circuit.failure_threshold",
+                "http.stream_idle_timeout_ms",
+                "routing.default",
+            ]
+        );
+        assert_eq!(cfg.routing.default, "openai");
+        assert_eq!(cfg.cache.ttl_seconds, 5);
+        assert_eq!(cfg.http.stream_idle_timeout_ms, Some(100));
+        assert_eq!(cfg.http.circuit.failure_threshold, 3);
+
+        let bad = [("AIPROXY_CACHE_TTL_SECONDS".to_string(), "soon".to_string())];
+        let err = cfg.apply_env_overrides(bad).unwrap_err();
+        assert!(
+            err.to_string().contains("AIPROXY_CACHE_TTL_SECONDS"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn validate_reports_bad_splits_and_shadows() {
+        let dir = tempdir().unwrap();
+        let mut cfg = valid_cfg(dir.path());
+        cfg.routing.rules = vec![
+            RoutingRule {
+                model: "^a".into(),
+                provider: "null".into(),
+                providers: vec![],
+                split: vec![WeightedProvider {
+                    provider: "nope".into(),
+                    weight: 0,
+                }],
+                shadow: None,
+            },
+            RoutingRule {
+                model: "^b".into(),
+                provider: String::new(),
+                providers: vec![],
+                split: vec![WeightedProvider {
+                    provider: "null".into(),
+                    weight: 1,
+                }],
+                shadow: Some(ShadowCfg {
+                    provider: "nope".into(),
+                    model: None,
+                    percent: 150,
+                }),
+            },
+        ];
+        let fields: Vec<String> = cfg.validate().into_iter().map(|d| d.field).collect();
+        assert_eq!(
+            fields,
+            [
+                "routing.rules[0].split",
+                "routing.rules[0].split",
+                "routing.rules[1].shadow.percent",
+                "routing.rules[0].split[0].provider",
+                "routing.rules[1].shadow.provider",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_reports_empty_and_chained_aliases() {
+        let dir = tempdir().unwrap();
+        let mut cfg = valid_cfg(dir.path());
+        cfg.routing.aliases = [("fast", "gpt-4o-mini"), ("none", ""), ("quick", "fast")]
+            .into_iter()
+            .map(|(a, m)| (a.to_string(), m.to_string()))
+            .collect();
+        let diags = cfg.validate();
+        let fields: Vec<(&str, Severity)> = diags
+            .iter()
+            .map(|d| (d.field.as_str(), d.severity))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("routing.aliases.none", Severity::Error),
+                ("routing.aliases.quick", Severity::Warning),
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_reports_routing_and_key_problems() {
+        let dir = tempdir().unwrap();
+        let mut cfg = valid_cfg(dir.path());
+        cfg.providers.openrouter = Some(ProviderCfg {
+            api_key_env: "AIPROXY_TEST_UNSET_KEY".into(),
+            extra_params: ExtraParamsCfg::default(),
+            billing: Default::default(),
+            http: Default::default(),
+        });
+        cfg.routing.rules = vec![
+            RoutingRule {
+                model: "(".into(),
+                provider: "null".into(),
+                providers: vec![],
+                split: vec![],
+                shadow: None,
+            },
+            RoutingRule {
+                model: "^x".into(),
+                provider: "nope".into(),
+                providers: vec![],
+                split: vec![],
+                shadow: None,
+            },
+            RoutingRule {
+                model: "^or/".into(),
+                provider: "openrouter".into(),
+                providers: vec![],
+                split: vec![],
+                shadow: None,
+            },
+            RoutingRule {
+                model: "^local-embed".into(),
+                provider: "hash".into(),
+                providers: vec![],
+                split: vec![],
+                shadow: None,
+            },
+            RoutingRule {
+                model: "^y".into(),
+                provider: String::new(),
+                providers: vec!["null".into(), "nope".into()],
+                split: vec![],
+                shadow: None,
+            },
+        ];
+        cfg.tenants.insert(
+            "acme".into(),
+            TenantCfg {
+                keys_env: "AIPROXY_TEST_UNSET_KEYS".into(),
+                api_key_env: [("null".to_string(), "X".to_string())].into(),
+                routing_default: Some("nope".into()),
+                ..TenantCfg::default()
+            },
+        );
+        let diags = cfg.validate();
+        let fields: Vec<&str> = diags.iter().map(|d| d.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "routing.rules[0].model",
+                "routing.rules[1].provider",
+                "routing.rules[2].provider",
+                "routing.rules[3].provider",
+                "routing.rules[4].providers[1]",
+                "tenants.acme.keys_env",
+                "tenants.acme.api_key_env.null",
+                "tenants.acme.routing_default"
+            ]
+        );
+        assert!(diags.iter().all(|d| d.severity == Severity::Error));
+        assert!(diags[2].message.contains("$AIPROXY_TEST_UNSET_KEY"));
+    }
+
+    #[test]
+    fn validate_checks_directories_and_numbers() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("not-a-dir");
+        fs::write(&file, "x").unwrap();
+        let mut cfg = valid_cfg(dir.path());
+        cfg.transcript.dir = file.to_string_lossy().into_owned();
+        cfg.transcript.segment_mb = 0;
+        cfg.cache.path = dir
+            .path()
+            .join("missing/cache.db")
+            .to_string_lossy()
+            .into_owned();
+        cfg.catalog.models.insert(
+            "tiny".into(),
+            crate::catalog::ModelSpec {
+                context_window: Some(1_000),
+                max_output_tokens: Some(2_000),
+                ..Default::default()
+            },
+        );
+        cfg.providers.hash = Some(HashEmbedCfg {
+            dimensions: 0,
+            seed: 0,
+        });
+        cfg.http.vcr.mode = VcrMode::Replay;
+        cfg.chaos.providers.insert(
+            "null".into(),
+            FaultCfg {
+                rate_limit: 1.5,
+                ..FaultCfg::default()
+            },
+        );
+        cfg.pricing.entry("null".into()).or_default().insert(
+            "m".into(),
+            crate::pricing::ModelPrice {
+                prompt_per_1k: -1.0,
+                completion_per_1k: 0.0,
+            },
+        );
+        let diags = cfg.validate();
+        let summary: Vec<(&str, Severity)> = diags
+            .iter()
+            .map(|d| (d.field.as_str(), d.severity))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("transcript.dir", Severity::Error),
+                ("transcript.segment_mb", Severity::Error),
+                ("cache.path", Severity::Warning),
+                ("providers.hash.dimensions", Severity::Error),
+                ("http.vcr.cassette", Severity::Error),
+                ("chaos.providers.null.rate_limit", Severity::Error),
+                ("chaos.providers.null", Severity::Error),
+                ("catalog.models.tiny.max_output_tokens", Severity::Error),
+                ("pricing.null.m.prompt_per_1k", Severity::Error),
+            ]
+        );
     }
 
     #[test]
@@ -214,6 +1673,7 @@ api_key_env = "ANTHROPIC_API_KEY"
 
 [providers.openrouter]
 api_key_env = "OPENROUTER_API_KEY"
+extra_params = { deny = ["provider"] }
 
 [cache]
 path = ".aiproxy/cache.db"
@@ -225,6 +1685,25 @@ segment_mb = 64
 fsync = "commit"
 redact_builtin = true
 
+[truncation]
+max_prompt_tokens = 100000
+context_windows = { "gpt-4o" = 128000, "gpt-3.5-turbo" = 16385 }
+
+[summarization]
+model = "gpt-4o-mini"
+threshold_tokens = 4000
+
+[screening]
+mode = "flag"
+extra_phrases = ["act as root"]
+
+[validation]
+max_messages = 200
+
+[catalog.models."my-finetune"]
+context_window = 32000
+capabilities = ["chat", "chat_stream"]
+
 [routing]
 default = "openai"
 [[routing.rules]]
@@ -241,6 +1720,26 @@ provider = "anthropic"
         assert_eq!(cfg.http.connect_timeout_ms, 5_000);
         assert_eq!(cfg.http.request_timeout_ms, 60_000);
         assert_eq!(cfg.http.pool_max_idle_per_host, None);
+        let extra = cfg.extra_params("openrouter");
+        assert!(!extra.permits("provider"));
+        assert!(extra.permits("transforms"));
+        assert_eq!(cfg.extra_params("openai"), ExtraParamsCfg::default());
+        assert_eq!(cfg.truncation.budget("gpt-4o", Some(1_000)), Some(100_000));
+        assert_eq!(
+            cfg.truncation.budget("gpt-3.5-turbo", Some(385)),
+            Some(16_000)
+        );
+        assert_eq!(cfg.truncation.budget("other", None), Some(100_000));
+        assert_eq!(TruncationCfg::default().budget("gpt-4o", None), None);
+        assert_eq!(cfg.screening.mode, ScreenMode::Flag);
+        assert_eq!(cfg.screening.min_base64_len, 120);
+        assert_eq!(cfg.validation.max_messages, Some(200));
+        let finetune = &cfg.catalog.models["my-finetune"];
+        assert_eq!(finetune.context_window, Some(32_000));
+        assert_eq!(finetune.capabilities.as_ref().map(Vec::len), Some(2));
+        assert_eq!(cfg.summarization.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(cfg.summarization.threshold_tokens, 4_000);
+        assert_eq!(cfg.summarization.keep_recent, 6);
     }
 
     #[test]
@@ -0,0 +1,186 @@
+//! Multi-turn history on top of the raw model types.
+//!
+//! System messages are kept apart from the turns so trimming never drops them, and trimmed
+//! history always restarts on a user message so no assistant reply or tool result is left
+//! without the turn that prompted it.
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::{
+    ChatMessage, ChatRequest, ChatRequestBuilder, ChatResponse, MessageContent, Role,
+};
+
+/// Rough token count (~4 chars per token), the same approximation the limiter uses.
+fn approx_tokens(m: &ChatMessage) -> usize {
+    m.content.text().chars().count().div_ceil(4)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Conversation {
+    pub system: Vec<ChatMessage>,
+    pub turns: Vec<ChatMessage>,
+}
+
+impl Conversation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_system(mut self, content: impl Into<MessageContent>) -> Self {
+        self.system.push(ChatMessage::system(content));
+        self
+    }
+
+    /// Append any message; system messages go with the other system prompts.
+    pub fn push(&mut self, message: ChatMessage) -> &mut Self {
+        if message.role == Role::System {
+            self.system.push(message);
+        } else {
+            self.turns.push(message);
+        }
+        self
+    }
+
+    pub fn push_user(&mut self, content: impl Into<MessageContent>) -> &mut Self {
+        self.push(ChatMessage::user(content))
+    }
+
+    /// Append the assistant's reply from `resp`.
+    pub fn push_response(&mut self, resp: &ChatResponse) -> &mut Self {
+        self.push(resp.to_message())
+    }
+
+    /// System prompts first, then the turns in order.
+    pub fn messages(&self) -> impl Iterator<Item = &ChatMessage> {
+        self.system.iter().chain(&self.turns)
+    }
+
+    pub fn len(&self) -> usize {
+        self.system.len() + self.turns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.system.is_empty() && self.turns.is_empty()
+    }
+
+    /// A request builder for `model` carrying the whole history.
+    pub fn request(&self, model: impl Into<String>) -> ChatRequestBuilder {
+        ChatRequest::builder()
+            .model(model)
+            .messages(self.messages().cloned())
+    }
+
+    /// Keep at most the last `n` turns.
+    pub fn trim_to_turns(&mut self, n: usize) -> &mut Self {
+        let excess = self.turns.len().saturating_sub(n);
+        self.drop_oldest(excess)
+    }
+
+    /// Drop the oldest turns until the history is at most `max_tokens` (approximate). The
+    /// latest turn is always kept, even if it alone is over budget.
+    pub fn trim_to_tokens(&mut self, max_tokens: usize) -> &mut Self {
+        let mut total: usize = self.messages().map(approx_tokens).sum();
+        let mut drop = 0;
+        while total > max_tokens && drop + 1 < self.turns.len() {
+            total -= approx_tokens(&self.turns[drop]);
+            drop += 1;
+        }
+        self.drop_oldest(drop)
+    }
+
+    fn drop_oldest(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        self.turns.drain(..n);
+        let orphans = self
+            .turns
+            .iter()
+            .take_while(|m| m.role != Role::User)
+            .count();
+        if orphans < self.turns.len() {
+            self.turns.drain(..orphans);
+        }
+        self
+    }
+}
+
+impl From<Vec<ChatMessage>> for Conversation {
+    fn from(messages: Vec<ChatMessage>) -> Self {
+        let mut c = Self::new();
+        for m in messages {
+            c.push(m);
+        }
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{ChatProvider, NullProvider};
+
+    fn sample() -> Conversation {
+        let mut c = Conversation::new().with_system("be terse");
+        c.push_user("one")
+            .push(ChatMessage::assistant("1"))
+            .push_user("two")
+            .push(ChatMessage::assistant("2"))
+            .push_user("three");
+        c
+    }
+
+    fn texts(c: &Conversation) -> Vec<String> {
+        c.messages()
+            .map(|m| m.content.text().into_owned())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn push_response_appends_the_reply() {
+        let mut c = Conversation::new();
+        c.push_user("hi");
+        let resp = NullProvider.chat(c.request("m").build()).await.unwrap();
+        c.push_response(&resp);
+        assert_eq!(c.turns[1].role, Role::Assistant);
+        assert_eq!(c.turns[1].content, resp.text.as_str());
+        assert_eq!(c.request("m").build().messages.len(), 2);
+
+        let mut refused = resp.clone();
+        refused.text.clear();
+        refused.refusal = Some("No.".into());
+        assert_eq!(refused.to_message().content, "No.");
+    }
+
+    #[test]
+    fn trimming_keeps_system_and_restarts_on_a_user_turn() {
+        let mut c = sample();
+        c.trim_to_turns(2);
+        // Keeping the last two would start on assistant "2", so it goes too.
+        assert_eq!(texts(&c), vec!["be terse", "three"]);
+
+        let mut c = sample();
+        c.trim_to_turns(10);
+        assert_eq!(c.len(), 6);
+    }
+
+    #[test]
+    fn token_trim_always_keeps_the_latest_turn() {
+        let mut c = sample();
+        c.trim_to_tokens(6);
+        assert_eq!(texts(&c), vec!["be terse", "two", "2", "three"]);
+
+        c.trim_to_tokens(0);
+        assert_eq!(texts(&c), vec!["be terse", "three"]);
+    }
+
+    #[test]
+    fn from_messages_separates_system_prompts() {
+        let c = Conversation::from(vec![
+            ChatMessage::user("hi"),
+            ChatMessage::system("late system"),
+        ]);
+        assert_eq!(c.system.len(), 1);
+        assert_eq!(texts(&c), vec!["late system", "hi"]);
+    }
+}
@@ -0,0 +1,88 @@
+//! Per-call cost estimation.
+//!
+//! Prices are USD per 1M tokens and come from the model catalog (`crate::catalog`), which
+//! matches dated snapshots and `vendor/model` ids to their family.
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::Usage;
+
+/// USD per 1M prompt / completion tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Price {
+    pub prompt_per_mtok: f64,
+    pub completion_per_mtok: f64,
+}
+
+pub const fn price(prompt_per_mtok: f64, completion_per_mtok: f64) -> Price {
+    Price {
+        prompt_per_mtok,
+        completion_per_mtok,
+    }
+}
+
+/// Price for `model`, if the catalog knows it.
+pub fn price_for(model: &str) -> Option<Price> {
+    crate::catalog::price_for(model)
+}
+
+/// What one call cost, in USD.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cost {
+    pub prompt_usd: f64,
+    pub completion_usd: f64,
+}
+
+impl Cost {
+    pub fn total_usd(&self) -> f64 {
+        self.prompt_usd + self.completion_usd
+    }
+}
+
+/// Estimated cost of a call to `model` that used `usage`; `None` for models without a known
+/// price.
+pub fn estimate(model: &str, usage: &Usage) -> Option<Cost> {
+    let p = price_for(model)?;
+    Some(Cost {
+        prompt_usd: usage.prompt as f64 * p.prompt_per_mtok / 1_000_000.0,
+        completion_usd: usage.completion as f64 * p.completion_per_mtok / 1_000_000.0,
+    })
+}
+
+/// Estimated total USD cost of a call with the given token counts.
+pub fn estimate_usd(model: &str, prompt_tokens: u32, completion_tokens: u32) -> Option<f64> {
+    estimate(model, &Usage::new(prompt_tokens, completion_tokens)).map(|c| c.total_usd())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_prefix_wins() {
+        assert_eq!(price_for("gpt-4o-mini-2024-07-18"), Some(price(0.15, 0.60)));
+        assert_eq!(price_for("gpt-4o-2024-08-06"), Some(price(2.50, 10.00)));
+        assert_eq!(price_for("o3-mini"), Some(price(1.10, 4.40)));
+    }
+
+    #[test]
+    fn vendor_prefixed_ids_and_unknown_models() {
+        assert_eq!(
+            price_for("anthropic/claude-sonnet-4"),
+            price_for("claude-sonnet-4")
+        );
+        assert_eq!(price_for("mystery-model"), None);
+        assert_eq!(estimate("mystery-model", &Usage::new(10, 10)), None);
+    }
+
+    #[test]
+    fn estimate_scales_per_million_tokens() {
+        let c = estimate("gpt-4o", &Usage::new(1_000_000, 500_000)).unwrap();
+        assert!((c.prompt_usd - 2.5).abs() < 1e-9, "cost {c:?}");
+        assert!((c.total_usd() - 7.5).abs() < 1e-9, "cost {c:?}");
+        assert_eq!(
+            estimate_usd("gpt-4o", 1_000_000, 500_000),
+            Some(c.total_usd())
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Vector similarity and brute-force nearest-neighbor search, enough for small RAG
+//! prototypes without a separate vector library.
+//!
+//! Everything works on `f32` slices as returned in `EmbedResponse.vectors`. Comparing vectors
+//! of different dimensions is a caller bug and panics. Search is a linear scan, fine for
+//! thousands of vectors; beyond that, use a real vector store.
+
+use std::cmp::Ordering;
+
+use crate::model::EmbedResponse;
+
+fn same_dims(a: &[f32], b: &[f32]) {
+    assert_eq!(a.len(), b.len(), "vectors have different dimensions");
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    same_dims(a, b);
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+/// Euclidean length of `v`.
+pub fn l2_norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
+/// Scale `v` to unit length in place; a zero vector is left as it is.
+pub fn l2_normalize(v: &mut [f32]) {
+    let norm = l2_norm(v);
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+/// Cosine similarity in `[-1, 1]`; 0 when either vector is zero.
+pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let norms = l2_norm(a) * l2_norm(b);
+    if norms == 0.0 { 0.0 } else { dot(a, b) / norms }
+}
+
+pub fn euclidean(a: &[f32], b: &[f32]) -> f32 {
+    same_dims(a, b);
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// How `top_k` and `VectorIndex` rank candidates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Metric {
+    /// Highest cosine similarity first.
+    #[default]
+    Cosine,
+    /// Highest dot product first; the same order as `Cosine` for unit-length vectors.
+    Dot,
+    /// Smallest Euclidean distance first.
+    Euclidean,
+}
+
+impl Metric {
+    pub fn score(self, a: &[f32], b: &[f32]) -> f32 {
+        match self {
+            Self::Cosine => cosine(a, b),
+            Self::Dot => dot(a, b),
+            Self::Euclidean => euclidean(a, b),
+        }
+    }
+
+    /// Whether `a` ranks ahead of `b`.
+    fn order(self, a: f32, b: f32) -> Ordering {
+        match self {
+            Self::Euclidean => a.total_cmp(&b),
+            Self::Cosine | Self::Dot => b.total_cmp(&a),
+        }
+    }
+}
+
+/// One search result: the candidate's position and its `Metric::score` against the query.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Neighbor {
+    pub index: usize,
+    pub score: f32,
+}
+
+/// The `k` vectors nearest to `query` under `metric`, best first. Ties keep input order.
+pub fn top_k(query: &[f32], vectors: &[Vec<f32>], k: usize, metric: Metric) -> Vec<Neighbor> {
+    let mut scored: Vec<Neighbor> = vectors
+        .iter()
+        .enumerate()
+        .map(|(index, v)| Neighbor {
+            index,
+            score: metric.score(query, v),
+        })
+        .collect();
+    scored.sort_by(|a, b| metric.order(a.score, b.score));
+    scored.truncate(k);
+    scored
+}
+
+/// An in-memory collection of items and their vectors, searched by linear scan.
+#[derive(Debug, Clone)]
+pub struct VectorIndex<T> {
+    metric: Metric,
+    items: Vec<T>,
+    vectors: Vec<Vec<f32>>,
+}
+
+impl<T> VectorIndex<T> {
+    pub fn new(metric: Metric) -> Self {
+        Self {
+            metric,
+            items: Vec::new(),
+            vectors: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, item: T, vector: Vec<f32>) {
+        if let Some(first) = self.vectors.first() {
+            same_dims(first, &vector);
+        }
+        self.items.push(item);
+        self.vectors.push(vector);
+    }
+
+    /// Add `items` paired in order with `resp`'s vectors, e.g. the chunks that were embedded.
+    pub fn extend_from_response(
+        &mut self,
+        items: impl IntoIterator<Item = T>,
+        resp: EmbedResponse,
+    ) {
+        for (item, vector) in items.into_iter().zip(resp.vectors) {
+            self.insert(item, vector);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// The `k` items nearest to `query`, best first, with their scores.
+    pub fn search(&self, query: &[f32], k: usize) -> Vec<(&T, f32)> {
+        top_k(query, &self.vectors, k, self.metric)
+            .into_iter()
+            .map(|n| (&self.items[n.index], n.score))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-6
+    }
+
+    #[test]
+    fn similarity_measures_and_normalization() {
+        let (a, b) = ([1.0, 0.0], [0.0, 2.0]);
+        assert_eq!(dot(&a, &b), 0.0);
+        assert!(close(cosine(&a, &[3.0, 0.0]), 1.0));
+        assert!(close(cosine(&a, &[-1.0, 0.0]), -1.0));
+        assert_eq!(cosine(&a, &[0.0, 0.0]), 0.0);
+        assert!(close(euclidean(&a, &b), 5.0_f32.sqrt()));
+
+        let mut v = [3.0, 4.0];
+        l2_normalize(&mut v);
+        assert_eq!(v, [0.6, 0.8]);
+        let mut zero = [0.0, 0.0];
+        l2_normalize(&mut zero);
+        assert_eq!(zero, [0.0, 0.0]);
+    }
+
+    #[test]
+    fn nearest_neighbors_rank_by_metric() {
+        let vectors = vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![10.0, 3.0]];
+        let query = [1.0, 0.1];
+        let idx = |ns: Vec<Neighbor>| ns.iter().map(|n| n.index).collect::<Vec<_>>();
+        assert_eq!(idx(top_k(&query, &vectors, 2, Metric::Cosine)), [0, 2]);
+        assert_eq!(idx(top_k(&query, &vectors, 1, Metric::Dot)), [2]);
+        assert_eq!(
+            idx(top_k(&query, &vectors, 3, Metric::Euclidean)),
+            [0, 1, 2]
+        );
+        assert!(top_k(&query, &[], 3, Metric::Cosine).is_empty());
+
+        let mut index = VectorIndex::new(Metric::Cosine);
+        let resp = EmbedResponse {
+            model: "m".into(),
+            vectors,
+            usage: 0,
+            cached: false,
+            provider: "hash".into(),
+            cost_usd: None,
+        };
+        index.extend_from_response(["east", "north", "mostly east"], resp);
+        assert_eq!(index.len(), 3);
+        let hits = index.search(&[0.0, 1.0], 1);
+        assert_eq!(*hits[0].0, "north");
+        assert!(close(hits[0].1, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "different dimensions")]
+    fn mismatched_dimensions_panic() {
+        dot(&[1.0], &[1.0, 2.0]);
+    }
+}
@@ -0,0 +1,3 @@
+//! Working with embedding vectors once a provider has returned them.
+
+pub mod math;
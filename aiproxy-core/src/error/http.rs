@@ -0,0 +1,124 @@
+//! `AiProxyError` as an HTTP response: a status plus an OpenAI-compatible error body, so any
+//! service exposing the proxy over HTTP reports failures the same way (see docs/error.md).
+
+use ::http::StatusCode;
+use serde_json::{Value, json};
+
+use super::{AiProxyError, ErrorCategory};
+
+/// HTTP status for `e`.
+pub fn status(e: &AiProxyError) -> StatusCode {
+    match e.category() {
+        ErrorCategory::Validation => StatusCode::BAD_REQUEST,
+        ErrorCategory::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+        ErrorCategory::BudgetExceeded => StatusCode::PAYMENT_REQUIRED,
+        ErrorCategory::ProviderUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+        ErrorCategory::ProviderError => StatusCode::BAD_GATEWAY,
+        ErrorCategory::Io | ErrorCategory::Other => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+/// OpenAI error `type` for `e`, e.g. "invalid_request_error".
+pub fn error_type(e: &AiProxyError) -> &'static str {
+    match e.category() {
+        ErrorCategory::Validation => "invalid_request_error",
+        ErrorCategory::RateLimited => "rate_limit_error",
+        ErrorCategory::BudgetExceeded => "insufficient_quota",
+        ErrorCategory::ProviderUnavailable => "service_unavailable",
+        ErrorCategory::ProviderError => "upstream_error",
+        ErrorCategory::Io | ErrorCategory::Other => "internal_error",
+    }
+}
+
+/// OpenAI error `code`, for the errors OpenAI itself gives one.
+pub fn error_code(e: &AiProxyError) -> Option<&'static str> {
+    match e.category() {
+        ErrorCategory::RateLimited => Some("rate_limit_exceeded"),
+        ErrorCategory::BudgetExceeded => Some("insufficient_quota"),
+        _ => None,
+    }
+}
+
+/// OpenAI error body, `{"error": {message, type, code}}`. Send it with `status(e)` and, when
+/// `e.retry_after()` is set, a `Retry-After` header.
+pub fn body(e: &AiProxyError) -> Value {
+    json!({
+        "error": {
+            "message": e.to_string(),
+            "type": error_type(e),
+            "code": error_code(e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_category_has_a_status_and_type() {
+        let cases = [
+            (
+                AiProxyError::Validation("bad".into()),
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+            ),
+            (
+                AiProxyError::BudgetExceeded { remaining: 0 },
+                StatusCode::PAYMENT_REQUIRED,
+                "insufficient_quota",
+            ),
+            (
+                AiProxyError::StreamDisconnected {
+                    provider: "p".into(),
+                    bytes_received: 0,
+                },
+                StatusCode::SERVICE_UNAVAILABLE,
+                "service_unavailable",
+            ),
+            (
+                AiProxyError::MalformedChunk {
+                    provider: "p".into(),
+                    message: "m".into(),
+                },
+                StatusCode::BAD_GATEWAY,
+                "upstream_error",
+            ),
+            (
+                AiProxyError::Other(anyhow::anyhow!("boom")),
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "internal_error",
+            ),
+        ];
+        for (e, want_status, want_type) in cases {
+            assert_eq!(status(&e), want_status, "{e}");
+            assert_eq!(error_type(&e), want_type, "{e}");
+        }
+    }
+
+    #[test]
+    fn body_is_the_openai_envelope() {
+        let e = AiProxyError::RateLimited {
+            provider: "openai".into(),
+            retry_after: Some(2),
+            remaining_requests: None,
+            remaining_tokens: None,
+            reset_at_ms: None,
+            upstream: Default::default(),
+        };
+        assert_eq!(
+            body(&e),
+            json!({
+                "error": {
+                    "message": "rate limited by provider openai",
+                    "type": "rate_limit_error",
+                    "code": "rate_limit_exceeded",
+                }
+            })
+        );
+        assert_eq!(
+            body(&AiProxyError::Validation("x".into()))["error"]["code"],
+            Value::Null
+        );
+    }
+}
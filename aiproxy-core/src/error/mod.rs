@@ -0,0 +1,246 @@
+pub mod http;
+
+use serde::Serialize;
+use thiserror::Error;
+
+/// Core error type for ai-proxy.
+/// Internally, modules can use `anyhow::Result<T>` for convenience,
+/// but public boundaries should expose `CoreResult<T>` with this error.
+#[derive(Debug, Error)]
+pub enum AiProxyError {
+    #[error("validation failed: {0}")]
+    Validation(String),
+
+    #[error("rate limited by provider {provider}")]
+    RateLimited {
+        provider: String,
+        retry_after: Option<u64>,
+        /// Requests left in the provider's current window, from its rate-limit headers.
+        remaining_requests: Option<u64>,
+        /// Tokens left in the provider's current window.
+        remaining_tokens: Option<u64>,
+        /// When the exhausted limit resets, in Unix milliseconds.
+        reset_at_ms: Option<u64>,
+        upstream: Box<Upstream>,
+    },
+
+    #[error("budget exceeded: remaining {remaining}")]
+    BudgetExceeded { remaining: u32 },
+
+    #[error("provider unavailable: {provider}")]
+    ProviderUnavailable {
+        provider: String,
+        upstream: Box<Upstream>,
+    },
+
+    #[error("upstream error from {provider}: {code} {message}")]
+    ProviderError {
+        provider: String,
+        code: String,
+        message: String,
+        upstream: Box<Upstream>,
+    },
+
+    /// A stream line grew past `limit` bytes without a newline.
+    #[error("stream from {provider} exceeded the {limit}-byte line buffer")]
+    SseBufferOverflow { provider: String, limit: usize },
+
+    /// The connection dropped mid-stream.
+    #[error("stream from {provider} disconnected after {bytes_received} bytes")]
+    StreamDisconnected {
+        provider: String,
+        bytes_received: u64,
+    },
+
+    /// A stream chunk could not be decoded.
+    #[error("malformed stream chunk from {provider}: {message}")]
+    MalformedChunk { provider: String, message: String },
+
+    /// A stream sent nothing for its idle timeout, or was still going at its deadline; `limit`
+    /// says which, `after_ms` is its length.
+    #[error("stream from {provider} exceeded its {limit} of {after_ms}ms")]
+    Timeout {
+        provider: String,
+        limit: &'static str,
+        after_ms: u64,
+    },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+/// The kind of failure, one per `AiProxyError` variant (see docs/error.md).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    Validation,
+    RateLimited,
+    BudgetExceeded,
+    ProviderUnavailable,
+    ProviderError,
+    Io,
+    Other,
+}
+
+impl ErrorCategory {
+    /// Stable snake_case name, as used in CLI `--json` errors.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Validation => "validation",
+            Self::RateLimited => "rate_limited",
+            Self::BudgetExceeded => "budget_exceeded",
+            Self::ProviderUnavailable => "provider_unavailable",
+            Self::ProviderError => "provider_error",
+            Self::Io => "io",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AiProxyError {
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Self::Validation(_) => ErrorCategory::Validation,
+            Self::RateLimited { .. } => ErrorCategory::RateLimited,
+            Self::BudgetExceeded { .. } => ErrorCategory::BudgetExceeded,
+            Self::ProviderUnavailable { .. } => ErrorCategory::ProviderUnavailable,
+            Self::ProviderError { .. }
+            | Self::SseBufferOverflow { .. }
+            | Self::MalformedChunk { .. } => ErrorCategory::ProviderError,
+            Self::StreamDisconnected { .. } | Self::Timeout { .. } => {
+                ErrorCategory::ProviderUnavailable
+            }
+            Self::Io(_) => ErrorCategory::Io,
+            Self::Other(_) => ErrorCategory::Other,
+        }
+    }
+
+    /// Whether sending the same request again may succeed: rate limits, unreachable
+    /// providers, dropped or stalled streams, and upstream timeouts or server errors (HTTP 408
+    /// or 5xx).
+    /// Bad input, exhausted budgets and other upstream rejections fail the same way every time.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimited { .. }
+            | Self::ProviderUnavailable { .. }
+            | Self::StreamDisconnected { .. }
+            | Self::Timeout { .. } => true,
+            Self::ProviderError { code, upstream, .. } => upstream
+                .status
+                .or_else(|| code.parse().ok())
+                .is_some_and(|status| status == 408 || (500..600).contains(&status)),
+            _ => false,
+        }
+    }
+
+    /// Upstream details for provider-side failures.
+    pub fn upstream(&self) -> Option<&Upstream> {
+        match self {
+            Self::RateLimited { upstream, .. }
+            | Self::ProviderUnavailable { upstream, .. }
+            | Self::ProviderError { upstream, .. } => Some(upstream),
+            _ => None,
+        }
+    }
+
+    /// Seconds the provider asked callers to wait before retrying, when it said.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+/// Where a provider-side failure happened, so it can be matched to the provider's own logs.
+/// Boxed in the error variants to keep `AiProxyError` small.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Upstream {
+    /// Model the failed call was for.
+    pub model: Option<String>,
+    /// Upstream HTTP status, when the provider answered.
+    pub status: Option<u16>,
+    /// The provider's id for the call, from its `x-request-id`-style response header.
+    pub provider_request_id: Option<String>,
+}
+
+pub type CoreResult<T> = std::result::Result<T, AiProxyError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn upstream(code: &str) -> AiProxyError {
+        AiProxyError::ProviderError {
+            provider: "p".into(),
+            code: code.into(),
+            message: "m".into(),
+            upstream: Default::default(),
+        }
+    }
+
+    #[test]
+    fn transient_failures_are_retryable() {
+        let limited = AiProxyError::RateLimited {
+            provider: "p".into(),
+            retry_after: Some(3),
+            remaining_requests: None,
+            remaining_tokens: None,
+            reset_at_ms: None,
+            upstream: Default::default(),
+        };
+        assert!(limited.is_retryable());
+        assert_eq!(limited.retry_after(), Some(3));
+        assert_eq!(limited.category(), ErrorCategory::RateLimited);
+        assert!(
+            AiProxyError::ProviderUnavailable {
+                provider: "p".into(),
+                upstream: Default::default(),
+            }
+            .is_retryable()
+        );
+        assert!(upstream("503").is_retryable());
+        assert!(upstream("408").is_retryable());
+
+        assert!(!upstream("400").is_retryable());
+        assert!(!upstream("vector_count_mismatch").is_retryable());
+        let invalid = AiProxyError::Validation("bad".into());
+        assert!(!invalid.is_retryable());
+        assert_eq!(invalid.retry_after(), None);
+        assert_eq!(invalid.category().as_str(), "validation");
+        assert!(!AiProxyError::BudgetExceeded { remaining: 0 }.is_retryable());
+
+        let dropped = AiProxyError::StreamDisconnected {
+            provider: "p".into(),
+            bytes_received: 10,
+        };
+        assert!(dropped.is_retryable());
+        assert_eq!(dropped.category(), ErrorCategory::ProviderUnavailable);
+        let stalled = AiProxyError::Timeout {
+            provider: "p".into(),
+            limit: "idle timeout",
+            after_ms: 30_000,
+        };
+        assert!(stalled.is_retryable());
+        assert_eq!(stalled.category(), ErrorCategory::ProviderUnavailable);
+        assert_eq!(
+            stalled.to_string(),
+            "stream from p exceeded its idle timeout of 30000ms"
+        );
+        let garbled = AiProxyError::MalformedChunk {
+            provider: "p".into(),
+            message: "m".into(),
+        };
+        assert!(!garbled.is_retryable());
+        assert_eq!(garbled.category(), ErrorCategory::ProviderError);
+    }
+}
@@ -0,0 +1,193 @@
+//! Parallel fan-out: send one prompt to several providers or models at once and collect every
+//! outcome, for side-by-side comparisons and ensembling.
+//!
+//! All targets share one deadline. A target that has not answered by then reports a
+//! `timeout` error; the others keep their results, so one slow provider never costs the rest.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::join_all;
+
+use crate::error::{AiProxyError, CoreResult, Upstream};
+use crate::model::{ChatRequest, ChatResponse};
+use crate::platform::{self, Instant};
+use crate::provider::ChatProvider;
+use crate::provider_factory::ProviderRegistry;
+use crate::router::RoutingResolver;
+
+/// One destination for the fanned-out request.
+pub struct Target {
+    label: String,
+    model: Option<String>,
+    provider: CoreResult<Arc<dyn ChatProvider>>,
+}
+
+impl Target {
+    /// Send the request unchanged to `provider`.
+    pub fn provider(provider: Arc<dyn ChatProvider>) -> Self {
+        Self {
+            label: provider.name().to_string(),
+            model: None,
+            provider: Ok(provider),
+        }
+    }
+
+    /// Send the request with its model replaced by `model`, to the provider `router` picks for
+    /// it. A model that cannot be routed is reported in that target's result.
+    pub fn model(reg: &ProviderRegistry, router: &RoutingResolver, model: &str) -> Self {
+        Self {
+            label: model.to_string(),
+            model: Some(model.to_string()),
+            provider: router.select_chat(reg, model),
+        }
+    }
+
+    /// The provider name or model this target was built from.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+}
+
+/// What one target returned.
+#[derive(Debug)]
+pub struct TargetResult {
+    /// `Target::label`.
+    pub target: String,
+    pub result: CoreResult<ChatResponse>,
+    /// Wall time until the target answered, failed or hit the deadline.
+    pub latency: Duration,
+    /// `ChatResponse.cost_usd` of a successful reply.
+    pub cost_usd: Option<f64>,
+}
+
+impl TargetResult {
+    pub fn is_ok(&self) -> bool {
+        self.result.is_ok()
+    }
+}
+
+/// Send `req` to every target concurrently and wait at most `deadline` for all of them.
+/// Results come back in target order.
+pub async fn ask_all(
+    targets: Vec<Target>,
+    req: ChatRequest,
+    deadline: Duration,
+) -> Vec<TargetResult> {
+    let started = Instant::now();
+    let until = started + deadline;
+    join_all(targets.into_iter().map(|target| {
+        let mut req = req.clone();
+        async move {
+            let result = match target.provider {
+                Err(e) => Err(e),
+                Ok(provider) => {
+                    if let Some(model) = target.model {
+                        req.model = model;
+                    }
+                    let model = req.model.clone();
+                    match platform::timeout_at(until, provider.chat(req)).await {
+                        Some(result) => result,
+                        None => Err(AiProxyError::ProviderError {
+                            provider: provider.name().to_string(),
+                            code: "timeout".into(),
+                            message: format!(
+                                "no reply within the fan-out deadline of {}ms",
+                                deadline.as_millis()
+                            ),
+                            upstream: Box::new(Upstream {
+                                model: Some(model),
+                                ..Upstream::default()
+                            }),
+                        }),
+                    }
+                }
+            };
+            TargetResult {
+                target: target.label,
+                cost_usd: result.as_ref().ok().and_then(|r| r.cost_usd),
+                result,
+                latency: started.elapsed(),
+            }
+        }
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NullProvider;
+    use async_trait::async_trait;
+
+    /// Answers like `NullProvider` after `delay`.
+    #[derive(Debug)]
+    struct Slow {
+        name: &'static str,
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl ChatProvider for Slow {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            tokio::time::sleep(self.delay).await;
+            let mut resp = NullProvider.chat(req).await?;
+            resp.provider = self.name.into();
+            resp.cost_usd = Some(0.5);
+            Ok(resp)
+        }
+    }
+
+    fn slow(name: &'static str, ms: u64) -> Target {
+        Target::provider(Arc::new(Slow {
+            name,
+            delay: Duration::from_millis(ms),
+        }))
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn targets_run_concurrently_and_late_ones_time_out() {
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let targets = vec![slow("fast", 100), slow("late", 5_000), slow("medium", 400)];
+        let results = ask_all(targets, req, Duration::from_secs(1)).await;
+
+        let labels: Vec<&str> = results.iter().map(|r| r.target.as_str()).collect();
+        assert_eq!(labels, ["fast", "late", "medium"]);
+        assert_eq!(results[0].latency, Duration::from_millis(100));
+        assert_eq!(results[0].cost_usd, Some(0.5));
+        // Run one after another, "medium" would have finished at 5.5s.
+        assert_eq!(results[2].latency, Duration::from_millis(400));
+        assert!(results[2].is_ok());
+
+        let late = &results[1];
+        assert_eq!(late.latency, Duration::from_secs(1));
+        assert_eq!(late.cost_usd, None);
+        assert!(
+            matches!(&late.result, Err(AiProxyError::ProviderError { code, .. }) if code == "timeout")
+        );
+    }
+
+    #[tokio::test]
+    async fn model_targets_are_routed_and_route_errors_are_per_target() {
+        let cfg = crate::test_util::minimal_config();
+        let reg = ProviderRegistry::from_config_with_keys(&cfg, |_| None).unwrap();
+        let router = RoutingResolver::new(&cfg).unwrap();
+        let targets = vec![
+            Target::model(&reg, &router, "model-a"),
+            Target::model(&reg, &router, "text-embedding-3-small"),
+        ];
+        assert_eq!(targets[1].label(), "text-embedding-3-small");
+        let req = ChatRequest::builder().model("ignored").user("hi").build();
+        let results = ask_all(targets, req, Duration::from_secs(5)).await;
+
+        assert_eq!(results[0].result.as_ref().unwrap().model, "model-a");
+        assert!(matches!(
+            results[1].result,
+            Err(AiProxyError::Validation(_))
+        ));
+    }
+}
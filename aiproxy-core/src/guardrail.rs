@@ -0,0 +1,334 @@
+//! Output guardrails: policies run on generated text before a response is returned.
+//!
+//! A response is checked against deny-listed phrases, regex patterns and, when configured, a
+//! provider's moderation endpoint. `GuardrailAction` decides what a hit does: `Annotate`
+//! records it on the response, `Redact` masks the offending text as well, and `Block` fails
+//! the call. Every response with hits is reported to telemetry.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use regex::Regex;
+
+use crate::config::{GuardrailAction, GuardrailCfg};
+use crate::error::{AiProxyError, CoreResult};
+use crate::middleware::DispatchMiddleware;
+use crate::model::{ChatRequest, ChatResponse, GuardrailHit, GuardrailKind};
+use crate::provider::ModerationProvider;
+use crate::telemetry::{self, GuardrailLog};
+
+/// Replaces matched text in `Redact` mode.
+const MASK: &str = "[REDACTED]";
+
+/// `GuardrailCfg` with its phrases and patterns compiled.
+#[derive(Debug, Clone, Default)]
+pub struct Guardrail {
+    action: GuardrailAction,
+    rules: Vec<(GuardrailKind, String, Regex)>,
+    moderation: Option<String>,
+}
+
+impl Guardrail {
+    pub fn from_cfg(cfg: &GuardrailCfg) -> CoreResult<Self> {
+        let mut rules = Vec::new();
+        for phrase in cfg.deny.iter().filter(|p| !p.is_empty()) {
+            let re = Regex::new(&format!("(?i){}", regex::escape(phrase)))
+                .expect("escaped phrase compiles");
+            rules.push((GuardrailKind::Deny, phrase.clone(), re));
+        }
+        for pattern in &cfg.patterns {
+            let re = Regex::new(pattern).map_err(|e| {
+                AiProxyError::Validation(format!("invalid guardrail pattern '{pattern}': {e}"))
+            })?;
+            rules.push((GuardrailKind::Pattern, pattern.clone(), re));
+        }
+        Ok(Self {
+            action: cfg.action,
+            rules,
+            moderation: cfg.moderation.clone(),
+        })
+    }
+
+    pub fn action(&self) -> GuardrailAction {
+        self.action
+    }
+
+    /// Refuse a streamed chat unless the guardrail is off: a streamed reply is sent as it is
+    /// generated, so its text could be neither checked, masked nor taken back.
+    pub fn allow_stream(&self) -> CoreResult<()> {
+        if self.action != GuardrailAction::Off {
+            return Err(AiProxyError::Validation(
+                "streaming is disabled while the output guardrail is on; \
+                 send the request without stream"
+                    .into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Name of the provider whose moderation endpoint `apply` should be given, if any.
+    pub fn moderation_provider(&self) -> Option<&str> {
+        self.moderation
+            .as_deref()
+            .filter(|_| self.action != GuardrailAction::Off)
+    }
+
+    /// Policies `text` trips, deny phrases and patterns first, then moderation categories.
+    pub async fn check(
+        &self,
+        text: &str,
+        moderator: Option<&dyn ModerationProvider>,
+    ) -> CoreResult<Vec<GuardrailHit>> {
+        let mut hits: Vec<GuardrailHit> = self
+            .rules
+            .iter()
+            .filter(|(_, _, re)| re.is_match(text))
+            .map(|(kind, rule, _)| GuardrailHit {
+                kind: *kind,
+                rule: rule.clone(),
+            })
+            .collect();
+        if let Some(moderator) = moderator
+            && !text.is_empty()
+        {
+            hits.extend(
+                moderator
+                    .moderate(text)
+                    .await?
+                    .into_iter()
+                    .map(|rule| GuardrailHit {
+                        kind: GuardrailKind::Moderation,
+                        rule,
+                    }),
+            );
+        }
+        Ok(hits)
+    }
+
+    /// `text` with every deny phrase and pattern match masked.
+    pub fn redact(&self, text: &str) -> String {
+        self.rules.iter().fold(text.to_string(), |acc, (_, _, re)| {
+            re.replace_all(&acc, MASK).into_owned()
+        })
+    }
+
+    /// Check `resp` (its text and every choice) and act per the configured action. A
+    /// failing moderation call fails the response rather than letting it through unchecked.
+    pub async fn apply(
+        &self,
+        resp: &mut ChatResponse,
+        moderator: Option<&dyn ModerationProvider>,
+    ) -> CoreResult<()> {
+        if self.action == GuardrailAction::Off {
+            return Ok(());
+        }
+        let mut hits = self.check(&resp.text, moderator).await?;
+        let mut flagged = vec![hits.iter().any(|h| h.kind == GuardrailKind::Moderation)];
+        // Choice 0 mirrors `resp.text`; the others are checked on their own.
+        for choice in resp.choices.iter().skip(1) {
+            let found = self.check(&choice.text, moderator).await?;
+            flagged.push(found.iter().any(|h| h.kind == GuardrailKind::Moderation));
+            for hit in found {
+                if !hits.contains(&hit) {
+                    hits.push(hit);
+                }
+            }
+        }
+        if hits.is_empty() {
+            return Ok(());
+        }
+        telemetry::emit_guardrail(GuardrailLog {
+            provider: resp.provider.clone(),
+            model: resp.model.clone(),
+            action: self.action,
+            hits: hits.clone(),
+        });
+        match self.action {
+            GuardrailAction::Off | GuardrailAction::Annotate => {}
+            GuardrailAction::Redact => {
+                let mask = |text: &str, flagged: bool| {
+                    if flagged {
+                        MASK.to_string()
+                    } else {
+                        self.redact(text)
+                    }
+                };
+                resp.text = mask(&resp.text, flagged[0]);
+                for (i, choice) in resp.choices.iter_mut().enumerate() {
+                    choice.text = if i == 0 {
+                        resp.text.clone()
+                    } else {
+                        mask(&choice.text, flagged[i])
+                    };
+                }
+            }
+            GuardrailAction::Block => {
+                let hit = &hits[0];
+                let kind = match hit.kind {
+                    GuardrailKind::Deny => "denied phrase",
+                    GuardrailKind::Pattern => "pattern",
+                    GuardrailKind::Moderation => "moderation",
+                };
+                return Err(AiProxyError::Validation(format!(
+                    "response blocked by output guardrail ({kind}: '{}')",
+                    hit.rule
+                )));
+            }
+        }
+        resp.guardrail = hits;
+        Ok(())
+    }
+}
+
+/// A `Guardrail` as dispatch middleware: `apply` runs on every non-streaming response.
+/// Streams pass through unchecked, so callers refuse them first with `allow_stream`.
+#[derive(Debug)]
+pub struct GuardrailMiddleware {
+    guardrail: Guardrail,
+    moderator: Option<Arc<dyn ModerationProvider>>,
+}
+
+impl GuardrailMiddleware {
+    pub fn new(guardrail: Guardrail, moderator: Option<Arc<dyn ModerationProvider>>) -> Self {
+        Self {
+            guardrail,
+            moderator,
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl DispatchMiddleware for GuardrailMiddleware {
+    fn name(&self) -> &str {
+        "guardrail"
+    }
+
+    async fn after_response(&self, _req: &ChatRequest, resp: &mut ChatResponse) -> CoreResult<()> {
+        self.guardrail.apply(resp, self.moderator.as_deref()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::MiddlewareProvider;
+    use crate::model::ChatChoice;
+    use crate::provider::{ChatProvider, NullProvider};
+
+    /// Flags any text containing "attack" as violence.
+    #[derive(Debug)]
+    struct Moderator;
+
+    #[async_trait]
+    impl ModerationProvider for Moderator {
+        fn name(&self) -> &str {
+            "moderator"
+        }
+
+        async fn moderate(&self, text: &str) -> CoreResult<Vec<String>> {
+            Ok(if text.contains("attack") {
+                vec!["violence".into()]
+            } else {
+                vec![]
+            })
+        }
+    }
+
+    fn guardrail(action: GuardrailAction) -> Guardrail {
+        Guardrail::from_cfg(&GuardrailCfg {
+            action,
+            deny: vec!["Project Nightingale".into()],
+            patterns: vec![r"\b\d{3}-\d{2}-\d{4}\b".into()],
+            moderation: Some("openai".into()),
+        })
+        .unwrap()
+    }
+
+    async fn response(text: &str) -> ChatResponse {
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let mut resp = NullProvider.chat(req).await.unwrap();
+        resp.text = text.into();
+        resp.choices = vec![ChatChoice {
+            text: text.into(),
+            ..ChatChoice::default()
+        }];
+        resp
+    }
+
+    #[tokio::test]
+    async fn redact_masks_matches_and_annotate_only_records_them() {
+        let text = "Re project nightingale: SSN 123-45-6789.";
+        let mut resp = response(text).await;
+        guardrail(GuardrailAction::Redact)
+            .apply(&mut resp, Some(&Moderator))
+            .await
+            .unwrap();
+        assert_eq!(resp.text, "Re [REDACTED]: SSN [REDACTED].");
+        assert_eq!(resp.choices[0].text, resp.text);
+        let kinds: Vec<GuardrailKind> = resp.guardrail.iter().map(|h| h.kind).collect();
+        assert_eq!(kinds, [GuardrailKind::Deny, GuardrailKind::Pattern]);
+
+        let mut resp = response("plan the attack").await;
+        guardrail(GuardrailAction::Redact)
+            .apply(&mut resp, Some(&Moderator))
+            .await
+            .unwrap();
+        assert_eq!(resp.text, MASK);
+        assert_eq!(resp.guardrail[0].rule, "violence");
+
+        let mut resp = response(text).await;
+        guardrail(GuardrailAction::Annotate)
+            .apply(&mut resp, None)
+            .await
+            .unwrap();
+        assert_eq!(resp.text, text);
+        assert_eq!(resp.guardrail.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn block_fails_the_call_and_clean_or_off_responses_pass() {
+        let mut resp = response("plan the attack").await;
+        let err = guardrail(GuardrailAction::Block)
+            .apply(&mut resp, Some(&Moderator))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AiProxyError::Validation(_)));
+        assert!(err.to_string().contains("moderation: 'violence'"));
+
+        let mut clean = response("all good").await;
+        guardrail(GuardrailAction::Block)
+            .apply(&mut clean, Some(&Moderator))
+            .await
+            .unwrap();
+        assert!(clean.guardrail.is_empty());
+
+        let off = guardrail(GuardrailAction::Off);
+        assert!(off.moderation_provider().is_none());
+        let mut resp = response("123-45-6789").await;
+        off.apply(&mut resp, None).await.unwrap();
+        assert_eq!(resp.text, "123-45-6789");
+
+        let guarded = MiddlewareProvider::new(
+            Arc::new(NullProvider),
+            Arc::new(GuardrailMiddleware::new(
+                Guardrail::from_cfg(&GuardrailCfg {
+                    action: GuardrailAction::Block,
+                    deny: vec!["null provider".into()],
+                    ..GuardrailCfg::default()
+                })
+                .unwrap(),
+                None,
+            )),
+        );
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let err = guarded.chat(req).await.unwrap_err();
+        assert!(err.to_string().contains("denied phrase: 'null provider'"));
+
+        let bad = GuardrailCfg {
+            patterns: vec!["(".into()],
+            ..GuardrailCfg::default()
+        };
+        assert!(Guardrail::from_cfg(&bad).is_err());
+    }
+}
@@ -1,28 +1,107 @@
 // SSE buffer growth guard: 2 MiB
 const MAX_SSE_BUFFER: usize = 2 * 1024 * 1024;
 
+/// Largest raw response body kept by `post_json_keep_raw`; bigger bodies are replaced by a
+/// `{"truncated": true, "size_bytes": n}` marker.
+pub const MAX_RAW_BYTES: usize = 256 * 1024;
+
 // DRY helper to apply request-context headers.
 fn apply_ctx_headers(mut req: reqwest::RequestBuilder, ctx: &RequestCtx<'_>) -> reqwest::RequestBuilder {
     if let Some(rid) = ctx.request_id { req = req.header("X-Request-Id", rid); }
     if let Some(tid) = ctx.turn_id { req = req.header("X-Turn-Id", tid); }
     if let Some(ik) = ctx.idempotency_key { req = req.header("Idempotency-Key", ik); }
+    #[cfg(feature = "otel")]
+    if let Some(cx) = crate::telemetry::otel::current() { req = req.header("traceparent", cx.header()); }
     req
 }
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
 
 use reqwest::{Client, StatusCode};
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 
 use tracing::Instrument;
 
-use crate::error::{AiProxyError, CoreResult};
+use crate::config::{HttpCfg, RetryCfg};
+use crate::error::{AiProxyError, CoreResult, Upstream};
+use crate::platform::{self, Instant, Sleep};
+use crate::retry::{self, RetryBudget};
+use crate::vcr::Cassette;
 
-/// Request context carries tracing IDs and idempotency key.
+/// Request context carries tracing IDs and idempotency key, plus the model for error reports.
 #[derive(Clone, Copy, Default)]
 pub struct RequestCtx<'a> {
     pub request_id: Option<&'a str>,
     pub turn_id: Option<&'a str>,
     pub idempotency_key: Option<&'a str>,
+    pub model: Option<&'a str>,
+    /// The client's name for `model` when an alias replaced it.
+    pub requested_model: Option<&'a str>,
+    /// Collects the retries made for the call, for its `CompletionLog`.
+    pub retries: Option<&'a RetryLog>,
+    /// Limits on a streamed response (`post_sse_lines`) in place of the client's.
+    pub stream_timeouts: StreamTimeouts,
+}
+
+/// How long a streamed response may go without a line, and how long it may run in all,
+/// before it ends with `AiProxyError::Timeout`. `None` leaves that limit to the client's
+/// `HttpCfg`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamTimeouts {
+    pub idle_ms: Option<u64>,
+    pub deadline_ms: Option<u64>,
+}
+
+impl StreamTimeouts {
+    /// The limits `req` asks for.
+    pub fn of(req: &crate::model::ChatRequest) -> Self {
+        Self { idle_ms: req.stream_idle_timeout_ms, deadline_ms: req.stream_deadline_ms }
+    }
+
+    /// `self`, with each unset limit taken from `fallback`.
+    fn or(self, fallback: Self) -> Self {
+        Self {
+            idle_ms: self.idle_ms.or(fallback.idle_ms),
+            deadline_ms: self.deadline_ms.or(fallback.deadline_ms),
+        }
+    }
+}
+
+/// Why each retry of one call was made (`rate_limited`, `503`, `connect`) and how long was
+/// spent waiting between attempts.
+#[derive(Debug, Default)]
+pub struct RetryLog(Mutex<(Vec<String>, u64)>);
+
+impl RetryLog {
+    fn push(&self, reason: String, backoff_ms: u64) {
+        let mut log = self.0.lock().unwrap();
+        log.0.push(reason);
+        log.1 += backoff_ms;
+    }
+
+    /// The reasons and total backoff so far, as `CompletionLog::retried` takes them.
+    pub fn take(&self) -> (Vec<String>, u64) {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+/// Decodes `R` while keeping the JSON it came from.
+struct WithRaw<R>(R, serde_json::Value);
+
+impl<'de, R: DeserializeOwned> Deserialize<'de> for WithRaw<R> {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        let raw = serde_json::Value::deserialize(d)?;
+        let parsed = R::deserialize(&raw).map_err(serde::de::Error::custom)?;
+        Ok(WithRaw(parsed, raw))
+    }
+}
+
+fn bound_raw(raw: serde_json::Value) -> serde_json::Value {
+    let size = serde_json::to_vec(&raw).map_or(0, |b| b.len());
+    if size > MAX_RAW_BYTES {
+        serde_json::json!({"truncated": true, "size_bytes": size})
+    } else {
+        raw
+    }
 }
 
 /// Represents a single Server-Sent-Event line (already split on `\n`).
@@ -31,31 +110,156 @@ pub struct SseLine {
     pub line: String,
 }
 
-/// A boxed stream of `SseLine` results.
+/// A boxed stream of `SseLine` results; `Send` except on wasm32 (see `platform`).
+#[cfg(not(target_arch = "wasm32"))]
 pub type SseStream =
     std::pin::Pin<Box<dyn futures_util::stream::Stream<Item = crate::error::CoreResult<SseLine>> + Send>>;
+#[cfg(target_arch = "wasm32")]
+pub type SseStream =
+    std::pin::Pin<Box<dyn futures_util::stream::Stream<Item = crate::error::CoreResult<SseLine>>>>;
+
+/// A response body as reqwest streams it.
+#[cfg(not(target_arch = "wasm32"))]
+type ByteStream =
+    std::pin::Pin<Box<dyn futures_util::stream::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>>;
+#[cfg(target_arch = "wasm32")]
+type ByteStream =
+    std::pin::Pin<Box<dyn futures_util::stream::Stream<Item = Result<bytes::Bytes, reqwest::Error>>>>;
 
 /// Thin wrapper around reqwest::Client with defaults and helpers.
 #[derive(Debug, Clone)]
 pub struct HttpClient {
     inner: Client,
     user_agent: String,
+    vcr: Option<Arc<Cassette>>,
+    retry: RetryCfg,
+    budget: Arc<RetryBudget>,
+    stream: StreamTimeouts,
 }
 
 impl HttpClient {
+    /// A client with `HttpCfg`'s default timeouts and pool.
     pub fn new_default() -> CoreResult<Self> {
-        let inner = Client::builder()
-            .connect_timeout(std::time::Duration::from_secs(5))
-            .timeout(std::time::Duration::from_secs(60))
-            .pool_max_idle_per_host(8)
+        Self::from_cfg(&HttpCfg::default())
+    }
+
+    /// A client with `cfg`'s timeouts and pool size. Retries and the cassette are shared
+    /// between clients, so they are set with `with_retry` and `with_vcr` instead.
+    #[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+    pub fn from_cfg(cfg: &HttpCfg) -> CoreResult<Self> {
+        // On wasm32 reqwest goes through `fetch`, which owns connections and timeouts.
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = {
+            let builder = Client::builder()
+                .connect_timeout(std::time::Duration::from_millis(cfg.connect_timeout_ms))
+                .timeout(std::time::Duration::from_millis(cfg.request_timeout_ms));
+            match cfg.pool_max_idle_per_host {
+                Some(n) => builder.pool_max_idle_per_host(n),
+                None => builder,
+            }
+        };
+        #[cfg(target_arch = "wasm32")]
+        let builder = Client::builder();
+        let inner = builder
             .build()
             .map_err(|e| AiProxyError::Other(anyhow::anyhow!("http client build failed: {e}")))?;
         Ok(Self {
             inner,
             user_agent: "ai-proxy/0.1".to_string(),
+            vcr: None,
+            // One attempt until `with_retry` says otherwise.
+            retry: RetryCfg { max_attempts: 1, ..RetryCfg::default() },
+            budget: Arc::default(),
+            stream: StreamTimeouts { idle_ms: cfg.stream_idle_timeout_ms, deadline_ms: cfg.stream_deadline_ms },
         })
     }
 
+    /// Retry 429s, 5xx responses and failed connections under `cfg`, within `budget`
+    /// (meant to be shared by every client, so an outage cannot multiply traffic).
+    pub fn with_retry(mut self, cfg: RetryCfg, budget: Arc<RetryBudget>) -> Self {
+        self.retry = cfg;
+        self.budget = budget;
+        self
+    }
+
+    /// Record every exchange to, or answer every request from, `cassette`.
+    pub fn with_vcr(mut self, cassette: Arc<Cassette>) -> Self {
+        self.vcr = Some(cassette);
+        self
+    }
+
+    /// Send `req`, through the cassette when one is set. Transport failures become
+    /// `ProviderUnavailable`.
+    async fn send(
+        &self,
+        req: reqwest::RequestBuilder,
+        model: Option<&str>,
+    ) -> CoreResult<reqwest::Response> {
+        let unavailable = || AiProxyError::ProviderUnavailable {
+            provider: "http".into(),
+            upstream: Box::new(Upstream {
+                model: model.map(str::to_string),
+                ..Upstream::default()
+            }),
+        };
+        match &self.vcr {
+            None => req.send().await.map_err(|_| unavailable()),
+            Some(vcr) => {
+                let req = req.build().map_err(|_| unavailable())?;
+                vcr.execute(&self.inner, req)
+                    .await?
+                    .map_err(|_| unavailable())
+            }
+        }
+    }
+
+    /// `send`, repeated while the attempt fails with a 429, a 5xx or no response at all and
+    /// the retry policy and budget allow another. The last response is returned whatever
+    /// its status, for the caller to report.
+    async fn send_retrying(
+        &self,
+        req: reqwest::RequestBuilder,
+        ctx: &RequestCtx<'_>,
+    ) -> CoreResult<reqwest::Response> {
+        if self.retry.max_attempts <= 1 {
+            return self.send(req, ctx.model).await;
+        }
+        self.budget.record_request();
+        let mut next = req;
+        let mut retry = 0;
+        loop {
+            retry += 1;
+            // A body that cannot be cloned (none of ours) gets a single attempt.
+            let again = next.try_clone();
+            let result = self.send(next, ctx.model).await;
+            let (reason, retry_after) = match &result {
+                Ok(resp) if resp.status() == StatusCode::TOO_MANY_REQUESTS => {
+                    ("rate_limited".to_string(), parse_retry_after(resp.headers()))
+                }
+                Ok(resp) if resp.status().is_server_error() => {
+                    (resp.status().as_u16().to_string(), parse_retry_after(resp.headers()))
+                }
+                Err(AiProxyError::ProviderUnavailable { .. }) => ("connect".to_string(), None),
+                _ => return result,
+            };
+            let Some(again) = again.filter(|_| retry < self.retry.max_attempts) else {
+                return result;
+            };
+            let Some(wait) = retry::backoff(&self.retry, retry, retry_after) else {
+                return result;
+            };
+            if !self.budget.try_retry() {
+                return result;
+            }
+            tracing::debug!(reason = %reason, wait_ms = wait.as_millis() as u64, "http.retry");
+            if let Some(log) = ctx.retries {
+                log.push(reason, wait.as_millis() as u64);
+            }
+            platform::sleep(wait).await;
+            next = again;
+        }
+    }
+
     pub async fn post_json<T: Serialize, R: DeserializeOwned>(
         &self,
         url: &str,
@@ -91,12 +295,7 @@ impl HttpClient {
             }
             req = apply_ctx_headers(req, ctx);
 
-            let resp = req
-                .send()
-                .await
-                .map_err(|_e| AiProxyError::ProviderUnavailable {
-                    provider: "http".into(),
-                })?;
+            let resp = self.send_retrying(req, ctx).await?;
 
             let status = resp.status();
             tracing::Span::current().record("status", tracing::field::display(status.as_u16()));
@@ -108,12 +307,13 @@ impl HttpClient {
 
             if !status.is_success() {
                 let text = resp.text().await.unwrap_or_default();
-                let ra = parse_retry_after(&headers);
                 let latency = start.elapsed().as_millis() as u32;
                 // Telemetry: HTTP error
                 {
                     let trace = crate::telemetry::ProviderTrace::new()
                         .provider("http")
+                        .model_opt(ctx.model)
+                        .requested_model_opt(ctx.requested_model)
                         .latency_ms(latency as u64)
                         .provider_request_id_opt(provider_request_id.as_deref())
                         .error_kind("http_error")
@@ -123,7 +323,7 @@ impl HttpClient {
                 tracing::Span::current().record("error_kind", tracing::field::display("http_error"));
                 tracing::Span::current().record("error_message", tracing::field::display(truncate(&text, 200)));
                 tracing::Span::current().record("latency_ms", latency);
-                return Err(map_http_error("http", status, ra, &text));
+                return Err(map_http_error("http", status, &headers, &text, provider_request_id, ctx.model));
             }
 
             let parsed = resp.json::<R>().await.map_err(|e| {
@@ -131,6 +331,8 @@ impl HttpClient {
                 // Telemetry: decode error
                 let trace = crate::telemetry::ProviderTrace::new()
                     .provider("http")
+                    .model_opt(ctx.model)
+                    .requested_model_opt(ctx.requested_model)
                     .latency_ms(latency as u64)
                     .provider_request_id_opt(provider_request_id.as_deref())
                     .error_kind("decode_error")
@@ -143,6 +345,11 @@ impl HttpClient {
                     provider: "http".into(),
                     code: status.as_u16().to_string(),
                     message: format!("json decode error: {e}"),
+                    upstream: Box::new(Upstream {
+                        model: ctx.model.map(str::to_string),
+                        status: Some(status.as_u16()),
+                        provider_request_id: provider_request_id.clone(),
+                    }),
                 }
             })?;
             let latency = start.elapsed().as_millis() as u32;
@@ -150,6 +357,8 @@ impl HttpClient {
             {
                 let trace = crate::telemetry::ProviderTrace::new()
                     .provider("http")
+                    .model_opt(ctx.model)
+                    .requested_model_opt(ctx.requested_model)
                     .latency_ms(latency as u64)
                     .provider_request_id_opt(provider_request_id.as_deref());
                 crate::telemetry::emit(trace);
@@ -161,8 +370,31 @@ impl HttpClient {
         .await
     }
 
+    /// `post_json`, also returning the response body as JSON (bounded by `MAX_RAW_BYTES`) when
+    /// `keep_raw` is set.
+    pub async fn post_json_keep_raw<T: Serialize, R: DeserializeOwned>(
+        &self,
+        url: &str,
+        body: &T,
+        headers: &[(&str, &str)],
+        ctx: &RequestCtx<'_>,
+        keep_raw: bool,
+    ) -> CoreResult<(R, Option<serde_json::Value>, Option<String>, u32)> {
+        if !keep_raw {
+            let (parsed, provider_request_id, latency) =
+                self.post_json(url, body, headers, ctx).await?;
+            return Ok((parsed, None, provider_request_id, latency));
+        }
+        let (WithRaw(parsed, raw), provider_request_id, latency) = self
+            .post_json::<_, WithRaw<R>>(url, body, headers, ctx)
+            .await?;
+        Ok((parsed, Some(bound_raw(raw)), provider_request_id, latency))
+    }
+
     /// POST JSON and return an SSE (Server-Sent Events) line stream.
-    /// Each yielded item is one raw line (trim not applied) from the SSE channel.
+    /// Each yielded item is one raw line (trim not applied) from the SSE channel. A stream
+    /// that outlasts `ctx.stream_timeouts` (or the client's) yields `AiProxyError::Timeout`
+    /// and closes the connection.
     pub async fn post_sse_lines<T: Serialize + ?Sized>(
         &self,
         url: &str,
@@ -201,9 +433,7 @@ impl HttpClient {
         let resp = {
             let req = req;
             async move {
-                let resp = req.send().await.map_err(|_| AiProxyError::ProviderUnavailable {
-                    provider: "http".into(),
-                })?;
+                let resp = self.send_retrying(req, ctx).await?;
                 let status = resp.status();
                 tracing::Span::current().record("status", tracing::field::display(status.as_u16()));
                 let headers = resp.headers().clone();
@@ -212,13 +442,14 @@ impl HttpClient {
                     tracing::Span::current().record("provider_request_id", tracing::field::display(rid));
                 }
                 if !status.is_success() {
-                    let ra = parse_retry_after(&headers);
                     let body = resp.text().await.unwrap_or_default();
                     let latency = start.elapsed().as_millis() as u64;
                     // Telemetry: HTTP error
                     {
                         let trace = crate::telemetry::ProviderTrace::new()
                             .provider("http")
+                            .model_opt(ctx.model)
+                            .requested_model_opt(ctx.requested_model)
                             .latency_ms(latency)
                             .provider_request_id_opt(provider_request_id.as_deref())
                             .error_kind("http_error")
@@ -228,7 +459,7 @@ impl HttpClient {
                     tracing::Span::current().record("error_kind", tracing::field::display("http_error"));
                     tracing::Span::current().record("error_message", tracing::field::display(truncate(&body, 200)));
                     tracing::Span::current().record("latency_ms", latency);
-                    return Err(map_http_error("http", status, ra, &body));
+                    return Err(map_http_error("http", status, &headers, &body, provider_request_id, ctx.model));
                 }
                 let latency = start.elapsed().as_millis() as u64;
                 tracing::Span::current().record("latency_ms", latency);
@@ -241,7 +472,7 @@ impl HttpClient {
         // Stream body as bytes and split on '\n'
         let provider_request_id = extract_request_id(resp.headers());
         let byte_stream = resp.bytes_stream();
-        let line_stream = LineStream::new(Box::pin(byte_stream));
+        let line_stream = TimeoutStream::new(LineStream::new(Box::pin(byte_stream)), ctx.stream_timeouts.or(self.stream), start);
         let sse_span = tracing::info_span!(
             "sse.stream",
             provider = "http",
@@ -286,10 +517,7 @@ impl HttpClient {
             for (k, v) in headers { req = req.header(*k, *v); }
             req = apply_ctx_headers(req, ctx);
 
-            let resp = req
-                .send()
-                .await
-                .map_err(|_e| AiProxyError::ProviderUnavailable { provider: "http".into() })?;
+            let resp = self.send_retrying(req, ctx).await?;
 
             let status = resp.status();
             tracing::Span::current().record("status", tracing::field::display(status.as_u16()));
@@ -301,12 +529,13 @@ impl HttpClient {
 
             if !status.is_success() {
                 let text = resp.text().await.unwrap_or_default();
-                let ra = parse_retry_after(&headers);
                 let latency = start.elapsed().as_millis() as u32;
                 // Telemetry: HTTP error
                 {
                     let trace = crate::telemetry::ProviderTrace::new()
                         .provider("http")
+                        .model_opt(ctx.model)
+                        .requested_model_opt(ctx.requested_model)
                         .latency_ms(latency as u64)
                         .provider_request_id_opt(provider_request_id.as_deref())
                         .error_kind("http_error")
@@ -316,7 +545,7 @@ impl HttpClient {
                 tracing::Span::current().record("error_kind", tracing::field::display("http_error"));
                 tracing::Span::current().record("error_message", tracing::field::display(truncate(&text, 200)));
                 tracing::Span::current().record("latency_ms", latency);
-                return Err(map_http_error("http", status, ra, &text));
+                return Err(map_http_error("http", status, &headers, &text, provider_request_id, ctx.model));
             }
 
             let parsed = resp.json::<R>().await.map_err(|e| {
@@ -324,6 +553,8 @@ impl HttpClient {
                 // Telemetry: decode error
                 let trace = crate::telemetry::ProviderTrace::new()
                     .provider("http")
+                    .model_opt(ctx.model)
+                    .requested_model_opt(ctx.requested_model)
                     .latency_ms(latency as u64)
                     .provider_request_id_opt(provider_request_id.as_deref())
                     .error_kind("decode_error")
@@ -336,6 +567,11 @@ impl HttpClient {
                     provider: "http".into(),
                     code: status.as_u16().to_string(),
                     message: format!("json decode error: {e}"),
+                    upstream: Box::new(Upstream {
+                        model: ctx.model.map(str::to_string),
+                        status: Some(status.as_u16()),
+                        provider_request_id: provider_request_id.clone(),
+                    }),
                 }
             })?;
             let latency = start.elapsed().as_millis() as u32;
@@ -343,6 +579,8 @@ impl HttpClient {
             {
                 let trace = crate::telemetry::ProviderTrace::new()
                     .provider("http")
+                    .model_opt(ctx.model)
+                    .requested_model_opt(ctx.requested_model)
                     .latency_ms(latency as u64)
                     .provider_request_id_opt(provider_request_id.as_deref());
                 crate::telemetry::emit(trace);
@@ -355,7 +593,7 @@ impl HttpClient {
     }
 }
 
-fn extract_request_id(headers: &reqwest::header::HeaderMap) -> Option<String> {
+pub(crate) fn extract_request_id(headers: &reqwest::header::HeaderMap) -> Option<String> {
     static CANDIDATES: [&str; 5] = [
         "x-request-id",
         "request-id",
@@ -373,7 +611,7 @@ fn extract_request_id(headers: &reqwest::header::HeaderMap) -> Option<String> {
     None
 }
 
-fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<u64> {
+pub(crate) fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<u64> {
     if let Some(v) = headers.get("retry-after")
         && let Ok(s) = v.to_str()
         && let Ok(secs) = s.trim().parse::<u64>()
@@ -385,19 +623,173 @@ fn parse_retry_after(headers: &reqwest::header::HeaderMap) -> Option<u64> {
     None
 }
 
-fn map_http_error(provider: &str, status: StatusCode, retry_after: Option<u64>, body: &str) -> AiProxyError {
+/// Rate-limit state from response headers. OpenAI and Groq send `x-ratelimit-*` with reset
+/// durations such as `6m0s`; Anthropic sends `anthropic-ratelimit-*` with RFC 3339 resets.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct RateLimitHeaders {
+    pub remaining_requests: Option<u64>,
+    pub remaining_tokens: Option<u64>,
+    /// Reset of the exhausted limit, or the later reset if neither is reported exhausted.
+    pub reset_at_ms: Option<u64>,
+}
+
+pub(crate) fn parse_rate_limit(
+    headers: &reqwest::header::HeaderMap,
+    now_ms: u64,
+) -> RateLimitHeaders {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+    };
+    let number = |openai: &str, anthropic: &str| {
+        header(openai)
+            .or(header(anthropic))
+            .and_then(|v| v.parse::<u64>().ok())
+    };
+    let reset = |openai: &str, anthropic: &str| {
+        header(openai)
+            .and_then(parse_reset_duration_ms)
+            .map(|ms| now_ms + ms)
+            .or_else(|| header(anthropic).and_then(parse_rfc3339_ms))
+    };
+    let requests = number(
+        "x-ratelimit-remaining-requests",
+        "anthropic-ratelimit-requests-remaining",
+    );
+    let tokens = number(
+        "x-ratelimit-remaining-tokens",
+        "anthropic-ratelimit-tokens-remaining",
+    );
+    let requests_reset = reset(
+        "x-ratelimit-reset-requests",
+        "anthropic-ratelimit-requests-reset",
+    );
+    let tokens_reset = reset(
+        "x-ratelimit-reset-tokens",
+        "anthropic-ratelimit-tokens-reset",
+    );
+    let exhausted = [(requests, requests_reset), (tokens, tokens_reset)]
+        .into_iter()
+        .filter(|(remaining, _)| *remaining == Some(0))
+        .filter_map(|(_, reset)| reset)
+        .max();
+    RateLimitHeaders {
+        remaining_requests: requests,
+        remaining_tokens: tokens,
+        reset_at_ms: exhausted.or(requests_reset.max(tokens_reset)),
+    }
+}
+
+/// Milliseconds in an OpenAI-style reset duration: `20ms`, `1s`, `6m0s`, `2m59.56s`, `1h2m`.
+fn parse_reset_duration_ms(s: &str) -> Option<u64> {
+    if let Ok(secs) = s.parse::<f64>() {
+        return (secs >= 0.0).then(|| (secs * 1000.0).round() as u64);
+    }
+    let mut rest = s;
+    let mut total = 0.0;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(rest.len());
+        let value: f64 = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        let unit = rest
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        total += value
+            * match &rest[..unit] {
+                "ms" => 1.0,
+                "s" => 1_000.0,
+                "m" => 60_000.0,
+                "h" => 3_600_000.0,
+                _ => return None,
+            };
+        rest = &rest[unit..];
+    }
+    Some(total.round() as u64)
+}
+
+/// Unix milliseconds of an RFC 3339 timestamp such as `2025-01-01T00:00:30.5Z`.
+fn parse_rfc3339_ms(s: &str) -> Option<u64> {
+    let (date, time) = s.split_once(['T', 't', ' '])?;
+    let mut ymd = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (ymd.next()??, ymd.next()??, ymd.next()??);
+    let (clock, offset_secs) = match time.strip_suffix(['Z', 'z']) {
+        Some(clock) => (clock, 0),
+        None => {
+            let at = time.rfind(['+', '-'])?;
+            let (clock, offset) = time.split_at(at);
+            let (hours, minutes) = offset[1..].split_once(':')?;
+            let secs = hours.parse::<i64>().ok()? * 3_600 + minutes.parse::<i64>().ok()? * 60;
+            (clock, if offset.starts_with('-') { -secs } else { secs })
+        }
+    };
+    let (hms, fraction) = clock.split_once('.').unwrap_or((clock, ""));
+    let mut hms = hms.splitn(3, ':').map(|p| p.parse::<i64>().ok());
+    let (hour, minute, second) = (hms.next()??, hms.next()??, hms.next()??);
+    let millis: i64 = format!("{fraction:0<3}").get(..3)?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    // Days-from-civil, the inverse of `usage::utc_day`.
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+    let secs = days * 86_400 + hour * 3_600 + minute * 60 + second - offset_secs;
+    u64::try_from(secs * 1_000 + millis).ok()
+}
+
+fn now_ms() -> u64 {
+    platform::SystemTime::now()
+        .duration_since(platform::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+pub(crate) fn map_http_error(
+    provider: &str,
+    status: StatusCode,
+    headers: &reqwest::header::HeaderMap,
+    body: &str,
+    provider_request_id: Option<String>,
+    model: Option<&str>,
+) -> AiProxyError {
+    let upstream = Box::new(Upstream {
+        model: model.map(str::to_string),
+        status: Some(status.as_u16()),
+        provider_request_id,
+    });
     match status {
-        StatusCode::TOO_MANY_REQUESTS => AiProxyError::RateLimited {
-            provider: provider.to_string(),
-            retry_after,
-        },
+        StatusCode::TOO_MANY_REQUESTS => {
+            let now = now_ms();
+            let limits = parse_rate_limit(headers, now);
+            // Without Retry-After, the reset time still says how long to wait.
+            let retry_after = parse_retry_after(headers).or_else(|| {
+                limits
+                    .reset_at_ms
+                    .map(|at| at.saturating_sub(now).div_ceil(1_000).max(1))
+            });
+            AiProxyError::RateLimited {
+                provider: provider.to_string(),
+                retry_after,
+                remaining_requests: limits.remaining_requests,
+                remaining_tokens: limits.remaining_tokens,
+                reset_at_ms: limits.reset_at_ms,
+                upstream,
+            }
+        }
         s if s.is_server_error() => AiProxyError::ProviderUnavailable {
             provider: provider.to_string(),
+            upstream,
         },
         s => AiProxyError::ProviderError {
             provider: provider.to_string(),
             code: s.as_u16().to_string(),
             message: truncate(body, 300),
+            upstream,
         },
     }
 }
@@ -414,23 +806,19 @@ fn truncate(s: &str, max: usize) -> String {
 
 /// Internal line splitter over a bytes stream; yields `SseLine`s separated by '\n'.
 struct LineStream {
-    inner: std::pin::Pin<
-        Box<dyn futures_util::stream::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>,
-    >,
+    inner: ByteStream,
     buf: String,
     flushed_tail: bool,
+    bytes_received: u64,
 }
 
 impl LineStream {
-    fn new(
-        inner: std::pin::Pin<
-            Box<dyn futures_util::stream::Stream<Item = Result<bytes::Bytes, reqwest::Error>> + Send>,
-        >,
-    ) -> Self {
+    fn new(inner: ByteStream) -> Self {
         Self {
             inner,
             buf: String::new(),
             flushed_tail: false,
+            bytes_received: 0,
         }
     }
 }
@@ -460,20 +848,21 @@ impl futures_util::stream::Stream for LineStream {
             // Otherwise, poll the inner stream for more bytes
             match self.inner.as_mut().poll_next(cx) {
                 Poll::Ready(Some(Ok(chunk))) => {
+                    self.bytes_received += chunk.len() as u64;
                     let s = String::from_utf8_lossy(&chunk);
                     self.buf.push_str(&s);
                     if self.buf.len() > MAX_SSE_BUFFER {
-                        return Poll::Ready(Some(Err(AiProxyError::ProviderError {
+                        return Poll::Ready(Some(Err(AiProxyError::SseBufferOverflow {
                             provider: "http".into(),
-                            code: "sse_buffer_overflow".into(),
-                            message: "SSE buffer exceeded 2MiB without a newline".into(),
+                            limit: MAX_SSE_BUFFER,
                         })));
                     }
                     continue;
                 }
                 Poll::Ready(Some(Err(_e))) => {
-                    return Poll::Ready(Some(Err(AiProxyError::ProviderUnavailable {
+                    return Poll::Ready(Some(Err(AiProxyError::StreamDisconnected {
                         provider: "http".into(),
+                        bytes_received: self.bytes_received,
                     })));
                 }
                 Poll::Ready(None) => {
@@ -491,6 +880,71 @@ impl futures_util::stream::Stream for LineStream {
     }
 }
 
+/// Ends `inner` with `AiProxyError::Timeout` once it goes `idle_ms` without a line or runs
+/// past `deadline_ms` after `start`. Dropping `inner` closes the connection.
+struct TimeoutStream<S> {
+    inner: Option<S>,
+    idle: Option<(u64, Sleep)>,
+    deadline: Option<(u64, Sleep)>,
+}
+
+impl<S> TimeoutStream<S> {
+    fn new(inner: S, limits: StreamTimeouts, start: Instant) -> Self {
+        let sleep = |from: Instant, ms: u64| Sleep::until(from + std::time::Duration::from_millis(ms));
+        Self {
+            inner: Some(inner),
+            idle: limits.idle_ms.map(|ms| (ms, sleep(Instant::now(), ms))),
+            deadline: limits.deadline_ms.map(|ms| (ms, sleep(start, ms))),
+        }
+    }
+}
+
+impl<S> futures_util::stream::Stream for TimeoutStream<S>
+where
+    S: futures_util::stream::Stream<Item = CoreResult<SseLine>> + Unpin,
+{
+    type Item = CoreResult<SseLine>;
+
+    fn poll_next(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        use std::future::Future;
+        use std::task::Poll;
+        let Some(inner) = self.inner.as_mut() else {
+            return Poll::Ready(None);
+        };
+        match std::pin::Pin::new(inner).poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                if let Some((ms, sleep)) = &mut self.idle {
+                    let at = Instant::now() + std::time::Duration::from_millis(*ms);
+                    sleep.reset(at);
+                }
+                return Poll::Ready(Some(item));
+            }
+            Poll::Ready(None) => {
+                self.inner = None;
+                return Poll::Ready(None);
+            }
+            Poll::Pending => {}
+        }
+        let this = &mut *self;
+        for (limit, timer) in [("deadline", &mut this.deadline), ("idle timeout", &mut this.idle)] {
+            if let Some((ms, sleep)) = timer
+                && std::pin::Pin::new(sleep).poll(cx).is_ready()
+            {
+                this.inner = None;
+                return Poll::Ready(Some(Err(AiProxyError::Timeout {
+                    provider: "http".into(),
+                    limit,
+                    after_ms: *ms,
+                })));
+            }
+        }
+        Poll::Pending
+    }
+}
+
 /// Adapter that emits a single telemetry record when the inner stream completes or is dropped.
 struct TelemetryOnDrop<S> {
     inner: std::pin::Pin<Box<S>>, // keep pinned
@@ -535,6 +989,10 @@ where
                         AiProxyError::Io(_) => "io",
                         AiProxyError::Other(_) => "other",
                         AiProxyError::BudgetExceeded { .. } => "budget_exceeded",
+                        AiProxyError::SseBufferOverflow { .. } => "sse_buffer_overflow",
+                        AiProxyError::StreamDisconnected { .. } => "stream_disconnected",
+                        AiProxyError::MalformedChunk { .. } => "malformed_chunk",
+                        AiProxyError::Timeout { .. } => "timeout",
                     };
                     let _enter = self.span.enter();
                     tracing::Span::current().record("error_kind", tracing::field::display(kind));
@@ -730,6 +1188,10 @@ mod tests {
             request_id: Some("rid"),
             turn_id: Some("tid"),
             idempotency_key: None,
+            model: None,
+            requested_model: None,
+            retries: None,
+            stream_timeouts: StreamTimeouts::default(),
         };
         let (resp, provider_id, latency) = client
             .post_json::<_, Resp>(
@@ -783,6 +1245,11 @@ mod tests {
             when.method(POST).path("/chat");
             then.status(429)
                 .header("Retry-After", "1")
+                .header("x-request-id", "req_upstream_1")
+                .header("x-ratelimit-remaining-requests", "0")
+                .header("x-ratelimit-remaining-tokens", "1500")
+                .header("x-ratelimit-reset-requests", "6m0s")
+                .header("x-ratelimit-reset-tokens", "20ms")
                 .body("slow down");
         });
         let client = HttpClient::new_default().expect("client");
@@ -790,6 +1257,10 @@ mod tests {
             request_id: None,
             turn_id: None,
             idempotency_key: None,
+            model: Some("gpt-x"),
+            requested_model: None,
+            retries: None,
+            stream_timeouts: StreamTimeouts::default(),
         };
         let err = client
             .post_json::<_, serde_json::Value>(
@@ -805,15 +1276,73 @@ mod tests {
             AiProxyError::RateLimited {
                 provider,
                 retry_after,
+                remaining_requests,
+                remaining_tokens,
+                reset_at_ms,
+                upstream,
             } => {
                 assert_eq!(provider, "http");
-                // (We didn't parse Retry-After yet; once we do, assert_eq!(retry_after, Some(1));
-                let _ = retry_after;
+                assert_eq!(
+                    *upstream,
+                    Upstream {
+                        model: Some("gpt-x".into()),
+                        status: Some(429),
+                        provider_request_id: Some("req_upstream_1".into()),
+                    }
+                );
+                assert_eq!(retry_after, Some(1));
+                assert_eq!(remaining_requests, Some(0));
+                assert_eq!(remaining_tokens, Some(1500));
+                // The request limit is the exhausted one, so its reset (6 minutes out) wins.
+                let wait = reset_at_ms.unwrap() - now_ms();
+                assert!((359_000..=360_000).contains(&wait), "{wait}");
             }
             other => panic!("expected RateLimited, got: {:?}", other),
         }
     }
 
+    #[test]
+    fn rate_limit_headers_parse_for_each_vendor() {
+        use reqwest::header::{HeaderMap, HeaderValue};
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut h = HeaderMap::new();
+            for (k, v) in pairs {
+                h.insert(*k, HeaderValue::from_static(v));
+            }
+            h
+        };
+        // Groq reports fractional seconds; nothing exhausted, so the later reset is used.
+        let groq = headers(&[
+            ("x-ratelimit-remaining-requests", "14"),
+            ("x-ratelimit-reset-requests", "2m59.56s"),
+            ("x-ratelimit-reset-tokens", "7.66s"),
+        ]);
+        assert_eq!(
+            parse_rate_limit(&groq, 1_000),
+            RateLimitHeaders {
+                remaining_requests: Some(14),
+                remaining_tokens: None,
+                reset_at_ms: Some(1_000 + 179_560),
+            }
+        );
+        let anthropic = headers(&[
+            ("anthropic-ratelimit-requests-remaining", "3"),
+            ("anthropic-ratelimit-tokens-remaining", "0"),
+            ("anthropic-ratelimit-requests-reset", "2025-01-01T00:01:00Z"),
+            ("anthropic-ratelimit-tokens-reset", "2025-01-01T01:00:30.250+01:00"),
+        ]);
+        let parsed = parse_rate_limit(&anthropic, 0);
+        assert_eq!(parsed.remaining_tokens, Some(0));
+        assert_eq!(parsed.reset_at_ms, Some(1_735_689_630_250));
+        assert_eq!(parse_rate_limit(&HeaderMap::new(), 0), RateLimitHeaders::default());
+
+        assert_eq!(parse_reset_duration_ms("1h2m3s"), Some(3_723_000));
+        assert_eq!(parse_reset_duration_ms("250ms"), Some(250));
+        assert_eq!(parse_reset_duration_ms("soon"), None);
+        assert_eq!(parse_rfc3339_ms("1970-01-01T00:00:01Z"), Some(1_000));
+        assert_eq!(parse_rfc3339_ms("2025-13-01T00:00:00Z"), None);
+    }
+
     #[tokio::test(flavor = "current_thread")]
     async fn post_json_503_maps_to_unavailable() {
         install_trace_sink();
@@ -828,6 +1357,10 @@ mod tests {
             request_id: None,
             turn_id: None,
             idempotency_key: None,
+            model: None,
+            requested_model: None,
+            retries: None,
+            stream_timeouts: StreamTimeouts::default(),
         };
         let err = client
             .post_json::<_, serde_json::Value>(
@@ -1040,7 +1573,7 @@ data: [DONE]\n\n";
 
         use futures_util::StreamExt;
         let first = stream.next().await.expect("one item");
-        assert!(matches!(first, Err(AiProxyError::ProviderError { code, .. }) if code == "sse_buffer_overflow"));
+        assert!(matches!(first, Err(AiProxyError::SseBufferOverflow { limit, .. }) if limit == super::MAX_SSE_BUFFER));
         drop(stream); // trigger Drop and span close
 
         let spans = span_store.spans.lock().unwrap();
@@ -1136,6 +1669,30 @@ data: [DONE]\n\n";
         }
     }
 
+    #[tokio::test(flavor = "current_thread")]
+    async fn traces_name_the_model_and_the_alias_asked_for() {
+        install_trace_sink();
+        let server = MockServer::start();
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/aliased");
+            then.status(200)
+                .header("x-request-id", "alias-1")
+                .json_body(json!({"ok": true}));
+        });
+        let client = HttpClient::new_default().expect("client");
+        let ctx = RequestCtx { model: Some("gpt-4o-mini"), requested_model: Some("fast"), ..RequestCtx::default() };
+        client
+            .post_json::<_, serde_json::Value>(&format!("{}/aliased", server.base_url()), &json!({}), &[], &ctx)
+            .await
+            .expect("ok");
+
+        let traces = TRACE_LOGS.lock().unwrap();
+        let hit = traces.iter().rev().find(|t| t.provider_request_id.as_deref() == Some("alias-1"));
+        let hit = hit.unwrap_or_else(|| panic!("trace for alias-1 not found; have: {:?}", *traces));
+        assert_eq!(hit.model.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(hit.requested_model.as_deref(), Some("fast"));
+    }
+
     #[tokio::test]
     async fn sse_headers_include_accept_and_ctx_ids() {
         let server = MockServer::start();
@@ -1152,7 +1709,7 @@ data: [DONE]\n\n";
                 .body("data: {\"ok\":true}\n\n");
         });
         let client = HttpClient::new_default().expect("client");
-        let ctx = RequestCtx { request_id: Some("rid-1"), turn_id: Some("tid-1"), idempotency_key: None };
+        let ctx = RequestCtx { request_id: Some("rid-1"), turn_id: Some("tid-1"), idempotency_key: None, model: None, requested_model: None, retries: None, stream_timeouts: StreamTimeouts::default() };
         let (mut stream, _pid) = client.post_sse_lines(
             &format!("{}/sse-headers", server.base_url()),
             &serde_json::json!({"stream": true}),
@@ -1190,4 +1747,193 @@ data: [DONE]\n\n";
             assert_eq!(provider_id.as_deref(), Some(*val));
         }
     }
+
+    #[tokio::test]
+    async fn keep_raw_returns_bounded_body_alongside_parsed() {
+        #[derive(serde::Deserialize)]
+        struct Typed {
+            id: String,
+        }
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/small");
+            then.status(200)
+                .json_body(json!({"id": "r1", "vendor_extra": {"x": 1}}));
+        });
+        let big = "a".repeat(MAX_RAW_BYTES);
+        server.mock(|when, then| {
+            when.method(POST).path("/big");
+            then.status(200).json_body(json!({"id": "r2", "blob": big}));
+        });
+        let client = HttpClient::new_default().expect("client");
+        let ctx = RequestCtx::default();
+        let url = |p: &str| format!("{}{p}", server.base_url());
+
+        let (typed, raw, _, _) = client
+            .post_json_keep_raw::<_, Typed>(&url("/small"), &json!({}), &[], &ctx, true)
+            .await
+            .unwrap();
+        assert_eq!(typed.id, "r1");
+        assert_eq!(raw.unwrap()["vendor_extra"]["x"], 1);
+
+        let (_, raw, _, _) = client
+            .post_json_keep_raw::<_, Typed>(&url("/small"), &json!({}), &[], &ctx, false)
+            .await
+            .unwrap();
+        assert!(raw.is_none());
+
+        let (typed, raw, _, _) = client
+            .post_json_keep_raw::<_, Typed>(&url("/big"), &json!({}), &[], &ctx, true)
+            .await
+            .unwrap();
+        assert_eq!(typed.id, "r2");
+        assert_eq!(raw.unwrap()["truncated"], true);
+    }
+
+    #[tokio::test]
+    async fn request_timeout_comes_from_the_config() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/slow");
+            then.status(200)
+                .delay(std::time::Duration::from_millis(500))
+                .json_body(json!({"ok": true}));
+        });
+        let cfg = HttpCfg { request_timeout_ms: 50, ..HttpCfg::default() };
+        let client = HttpClient::from_cfg(&cfg).unwrap();
+        let started = Instant::now();
+        let err = client
+            .get_json::<serde_json::Value>(&format!("{}/slow", server.base_url()), &[], &RequestCtx::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AiProxyError::ProviderUnavailable { .. }), "{err:?}");
+        assert!(started.elapsed() < std::time::Duration::from_millis(400));
+    }
+
+    /// Serves every connection one SSE line, then holds it open without sending more.
+    async fn stalling() -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            loop {
+                let (mut sock, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let _ = sock.read(&mut [0u8; 4096]).await;
+                    let line = "data: hi\n";
+                    let head = "HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\ntransfer-encoding: chunked\r\n\r\n";
+                    let _ = sock.write_all(format!("{head}{:x}\r\n{line}\r\n", line.len()).as_bytes()).await;
+                    tokio::time::sleep(std::time::Duration::from_secs(30)).await;
+                });
+            }
+        });
+        base
+    }
+
+    #[tokio::test]
+    async fn stalled_streams_end_with_a_timeout() {
+        use futures_util::StreamExt;
+        let base = stalling().await;
+        let ctx = RequestCtx { stream_timeouts: StreamTimeouts { idle_ms: Some(50), deadline_ms: None }, ..RequestCtx::default() };
+        let (stream, _pid) = HttpClient::new_default().unwrap().post_sse_lines(&base, &json!({}), &[], &ctx).await.unwrap();
+        let items: Vec<_> = stream.collect().await;
+        assert_eq!(items.len(), 2, "{items:?}");
+        assert_eq!(items[0].as_ref().unwrap().line, "data: hi");
+        assert!(matches!(items[1], Err(AiProxyError::Timeout { limit: "idle timeout", after_ms: 50, .. })), "{items:?}");
+
+        // The client's deadline applies when the request sets none.
+        let cfg = HttpCfg { stream_deadline_ms: Some(80), ..HttpCfg::default() };
+        let started = Instant::now();
+        let (stream, _pid) = HttpClient::from_cfg(&cfg).unwrap().post_sse_lines(&base, &json!({}), &[], &RequestCtx::default()).await.unwrap();
+        let items: Vec<_> = stream.collect().await;
+        assert!(matches!(items.last(), Some(Err(AiProxyError::Timeout { limit: "deadline", .. }))), "{items:?}");
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
+    }
+
+    /// Answers one connection per entry of `responses` (status, extra header lines) in order,
+    /// counting the requests served.
+    async fn scripted(responses: Vec<(u16, &'static str)>) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let served = hits.clone();
+        tokio::spawn(async move {
+            for (status, headers) in responses {
+                let (mut sock, _) = listener.accept().await.unwrap();
+                let mut req = Vec::new();
+                let mut buf = [0u8; 4096];
+                // Read the head, then as much body as it announces.
+                loop {
+                    let n = sock.read(&mut buf).await.unwrap();
+                    req.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&req).to_lowercase();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let len = text
+                            .lines()
+                            .find_map(|l| l.strip_prefix("content-length:"))
+                            .map_or(0, |v| v.trim().parse::<usize>().unwrap());
+                        if req.len() >= end + 4 + len || n == 0 {
+                            break;
+                        }
+                    }
+                }
+                served.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                let body = if status == 200 { r#"{"ok":true}"# } else { "busy" };
+                let resp = format!(
+                    "HTTP/1.1 {status} X\r\n{headers}content-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                sock.write_all(resp.as_bytes()).await.unwrap();
+            }
+        });
+        (base, hits)
+    }
+
+    fn retrying() -> HttpClient {
+        let cfg = RetryCfg { max_attempts: 3, base_backoff_ms: 1, max_backoff_ms: 5_000, jitter: false, respect_retry_after: true };
+        HttpClient::new_default().expect("client").with_retry(cfg, Arc::default())
+    }
+
+    #[tokio::test]
+    async fn server_errors_and_rate_limits_are_retried_until_success() {
+        let (base, hits) = scripted(vec![(503, ""), (429, "retry-after: 0\r\n"), (200, "")]).await;
+        let log = RetryLog::default();
+        let ctx = RequestCtx { retries: Some(&log), ..RequestCtx::default() };
+        let (resp, _, _) = retrying()
+            .post_json::<_, serde_json::Value>(&format!("{base}/chat"), &serde_json::json!({"msg": "hi"}), &[], &ctx)
+            .await
+            .expect("third attempt succeeds");
+        assert_eq!(resp["ok"], true);
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 3);
+        assert_eq!(log.take(), (vec!["503".to_string(), "rate_limited".to_string()], 1));
+    }
+
+    #[tokio::test]
+    async fn a_retry_after_past_the_backoff_cap_fails_at_once() {
+        let (base, hits) = scripted(vec![(429, "retry-after: 30\r\n"), (200, "")]).await;
+        let log = RetryLog::default();
+        let ctx = RequestCtx { retries: Some(&log), ..RequestCtx::default() };
+        let err = retrying()
+            .post_json::<_, serde_json::Value>(&format!("{base}/chat"), &serde_json::json!({}), &[], &ctx)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AiProxyError::RateLimited { retry_after: Some(30), .. }), "{err:?}");
+        assert_eq!(hits.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert!(log.take().0.is_empty());
+    }
+
+    #[tokio::test]
+    async fn failed_connections_are_retried_up_to_max_attempts() {
+        // Nothing listens on a port just released.
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let log = RetryLog::default();
+        let ctx = RequestCtx { retries: Some(&log), ..RequestCtx::default() };
+        let err = retrying()
+            .get_json::<serde_json::Value>(&format!("http://{addr}/models"), &[], &ctx)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AiProxyError::ProviderUnavailable { .. }), "{err:?}");
+        assert_eq!(log.take().0, ["connect", "connect"]);
+    }
 }
@@ -1,13 +1,36 @@
+#[cfg(feature = "native")]
+pub mod cache;
+pub mod catalog;
 pub mod config;
+pub mod conversation;
+pub mod cost;
+pub mod embeddings;
 pub mod error;
+pub mod fanout;
+pub mod guardrail;
 pub mod http_client;
+pub mod limits;
+pub mod middleware;
 pub mod model;
 pub mod normalizer;
+pub mod platform;
+pub mod pricing;
 pub mod provider;
 pub mod provider_factory;
 pub mod providers;
+pub mod realtime;
+pub mod retry;
 pub mod router;
+pub mod screening;
+pub mod session;
 pub mod stream;
+pub mod structured;
+pub mod summarize;
 pub mod telemetry;
-#[cfg(test)]
+pub mod tenant;
+pub mod tokenizer;
+pub mod transcript;
+pub mod usage;
+pub mod vcr;
+#[cfg(any(test, feature = "test-utils"))]
 pub mod test_util;
@@ -0,0 +1,286 @@
+//! Per-key request and token rate limits plus daily token quotas.
+//!
+//! Requests-per-minute and tokens-per-minute use a sliding 60s window; the daily quota
+//! resets at UTC midnight. Token counts are reserved up front from an estimate and then
+//! corrected with `settle` once the provider reports actual usage.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::platform::{Instant, SystemTime, UNIX_EPOCH};
+
+const WINDOW: Duration = Duration::from_secs(60);
+const DAY_SECS: u64 = 86_400;
+
+/// Limits applied to each client key independently. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyLimits {
+    pub rpm: Option<u32>,
+    pub tpm: Option<u32>,
+    pub daily_tokens: Option<u64>,
+}
+
+impl KeyLimits {
+    pub fn is_unlimited(&self) -> bool {
+        self.rpm.is_none() && self.tpm.is_none() && self.daily_tokens.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    Requests,
+    Tokens,
+    DailyTokens,
+}
+
+/// A request was refused; `retry_after` is whole seconds until it could be admitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    pub kind: LimitKind,
+    pub limit: u64,
+    pub retry_after: u64,
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let what = match self.kind {
+            LimitKind::Requests => "requests per minute",
+            LimitKind::Tokens => "tokens per minute",
+            LimitKind::DailyTokens => "tokens per day",
+        };
+        write!(
+            f,
+            "rate limit of {} {what} exceeded; retry after {}s",
+            self.limit, self.retry_after
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct KeyUsage {
+    requests: VecDeque<Instant>,
+    tokens: VecDeque<(Instant, u64)>,
+    day: u64,
+    day_tokens: u64,
+}
+
+impl KeyUsage {
+    fn expire(&mut self, now: Instant, day: u64) {
+        while self.requests.front().is_some_and(|t| now - *t >= WINDOW) {
+            self.requests.pop_front();
+        }
+        while self.tokens.front().is_some_and(|(t, _)| now - *t >= WINDOW) {
+            self.tokens.pop_front();
+        }
+        if self.day != day {
+            self.day = day;
+            self.day_tokens = 0;
+        }
+    }
+
+    /// Seconds until the oldest entry leaves the window (at least 1).
+    fn window_wait(oldest: Option<Instant>, now: Instant) -> u64 {
+        oldest.map_or(1, |t| {
+            (WINDOW - (now - t)).as_secs_f64().ceil().max(1.0) as u64
+        })
+    }
+}
+
+/// Thread-safe limiter keyed by client key.
+#[derive(Debug, Default)]
+pub struct Limiter {
+    limits: KeyLimits,
+    usage: Mutex<HashMap<String, KeyUsage>>,
+}
+
+impl Limiter {
+    pub fn new(limits: KeyLimits) -> Self {
+        Self {
+            limits,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn limits(&self) -> KeyLimits {
+        self.limits
+    }
+
+    /// Admit one request for `key`, reserving `tokens` against the token limits.
+    pub fn admit(&self, key: &str, tokens: u64) -> Result<(), LimitExceeded> {
+        self.admit_at(key, tokens, Instant::now(), unix_secs())
+    }
+
+    /// Replace a reservation with the actual token count once it is known.
+    pub fn settle(&self, key: &str, reserved: u64, actual: u64) {
+        if self.limits.is_unlimited() || reserved == actual {
+            return;
+        }
+        let mut usage = self.usage.lock().unwrap();
+        let Some(u) = usage.get_mut(key) else {
+            return;
+        };
+        u.day_tokens = (u.day_tokens + actual).saturating_sub(reserved);
+        // Record the correction as its own window entry; negative deltas shrink the newest.
+        if actual > reserved {
+            u.tokens.push_back((Instant::now(), actual - reserved));
+        } else {
+            let mut refund = reserved - actual;
+            while refund > 0 {
+                let Some((_, n)) = u.tokens.back_mut() else {
+                    break;
+                };
+                let take = refund.min(*n);
+                *n -= take;
+                refund -= take;
+                if *n == 0 {
+                    u.tokens.pop_back();
+                }
+            }
+        }
+    }
+
+    /// Count `tokens` already spent by `key` today (UTC) against its daily quota, e.g. from the
+    /// usage store at startup so quotas survive restarts.
+    pub fn preload_day_tokens(&self, key: &str, tokens: u64) {
+        self.preload_at(key, tokens, unix_secs());
+    }
+
+    fn preload_at(&self, key: &str, tokens: u64, unix_secs: u64) {
+        let mut usage = self.usage.lock().unwrap();
+        let u = usage.entry(key.to_string()).or_default();
+        let day = unix_secs / DAY_SECS;
+        if u.day != day {
+            u.day = day;
+            u.day_tokens = 0;
+        }
+        u.day_tokens += tokens;
+    }
+
+    fn admit_at(
+        &self,
+        key: &str,
+        tokens: u64,
+        now: Instant,
+        unix_secs: u64,
+    ) -> Result<(), LimitExceeded> {
+        if self.limits.is_unlimited() {
+            return Ok(());
+        }
+        let day = unix_secs / DAY_SECS;
+        let mut usage = self.usage.lock().unwrap();
+        let u = usage.entry(key.to_string()).or_default();
+        u.expire(now, day);
+
+        if let Some(rpm) = self.limits.rpm
+            && u.requests.len() as u64 >= u64::from(rpm)
+        {
+            return Err(LimitExceeded {
+                kind: LimitKind::Requests,
+                limit: rpm.into(),
+                retry_after: KeyUsage::window_wait(u.requests.front().copied(), now),
+            });
+        }
+        if let Some(tpm) = self.limits.tpm {
+            let used: u64 = u.tokens.iter().map(|(_, n)| n).sum();
+            if used > 0 && used + tokens > u64::from(tpm) {
+                return Err(LimitExceeded {
+                    kind: LimitKind::Tokens,
+                    limit: tpm.into(),
+                    retry_after: KeyUsage::window_wait(u.tokens.front().map(|(t, _)| *t), now),
+                });
+            }
+        }
+        if let Some(daily) = self.limits.daily_tokens
+            && u.day_tokens + tokens > daily
+        {
+            return Err(LimitExceeded {
+                kind: LimitKind::DailyTokens,
+                limit: daily,
+                retry_after: DAY_SECS - unix_secs % DAY_SECS,
+            });
+        }
+
+        u.requests.push_back(now);
+        if tokens > 0 {
+            u.tokens.push_back((now, tokens));
+        }
+        u.day_tokens += tokens;
+        Ok(())
+    }
+}
+
+fn unix_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpm_is_per_key_and_slides() {
+        let lim = Limiter::new(KeyLimits {
+            rpm: Some(2),
+            ..Default::default()
+        });
+        let t0 = Instant::now();
+        assert!(lim.admit_at("a", 0, t0, 0).is_ok());
+        assert!(
+            lim.admit_at("a", 0, t0 + Duration::from_secs(10), 0)
+                .is_ok()
+        );
+        let err = lim
+            .admit_at("a", 0, t0 + Duration::from_secs(20), 0)
+            .unwrap_err();
+        assert_eq!(err.kind, LimitKind::Requests);
+        assert_eq!(err.retry_after, 40);
+        assert!(
+            lim.admit_at("b", 0, t0 + Duration::from_secs(20), 0)
+                .is_ok()
+        );
+        assert!(
+            lim.admit_at("a", 0, t0 + Duration::from_secs(60), 0)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn tpm_counts_reservations_and_settlements() {
+        let lim = Limiter::new(KeyLimits {
+            tpm: Some(100),
+            ..Default::default()
+        });
+        let t0 = Instant::now();
+        assert!(lim.admit_at("k", 80, t0, 0).is_ok());
+        assert_eq!(
+            lim.admit_at("k", 30, t0, 0).unwrap_err().kind,
+            LimitKind::Tokens
+        );
+        // The call used fewer tokens than reserved; the refund frees room.
+        lim.settle("k", 80, 50);
+        assert!(lim.admit_at("k", 30, t0, 0).is_ok());
+    }
+
+    #[test]
+    fn daily_quota_resets_at_utc_midnight() {
+        let lim = Limiter::new(KeyLimits {
+            daily_tokens: Some(1_000),
+            ..Default::default()
+        });
+        let t0 = Instant::now();
+        let noon = 10 * DAY_SECS + DAY_SECS / 2;
+        assert!(lim.admit_at("k", 900, t0, noon).is_ok());
+        let err = lim.admit_at("k", 200, t0, noon).unwrap_err();
+        assert_eq!(err.kind, LimitKind::DailyTokens);
+        assert_eq!(err.retry_after, DAY_SECS / 2);
+        assert!(lim.admit_at("k", 200, t0, 11 * DAY_SECS).is_ok());
+
+        // Tokens spent before a restart count against the same day only.
+        lim.preload_at("p", 950, noon);
+        assert!(lim.admit_at("p", 100, t0, noon).is_err());
+        assert!(lim.admit_at("p", 100, t0, 11 * DAY_SECS).is_ok());
+    }
+}
@@ -0,0 +1,274 @@
+//! Dispatch middleware: hooks that run around every call to a chat provider, for policy,
+//! audit or enrichment that should not live in each adapter.
+//!
+//! A `DispatchMiddleware` is installed by wrapping a provider in a `MiddlewareProvider`,
+//! either directly or for every registered provider with `ProviderRegistry::layer`. Wrappers
+//! nest: the outermost layer sees the request first and the response last. A layer can also
+//! answer a call itself, as a cache or budget check would, so nothing inside it runs.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+
+use crate::error::CoreResult;
+use crate::model::{ChatRequest, ChatResponse};
+use crate::provider::ChatProvider;
+use crate::stream::{BoxStreamEv, StreamEvent};
+
+/// Hooks around a provider call. Every hook defaults to passing its input through, so an
+/// implementation only overrides what it needs.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait DispatchMiddleware: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &str;
+
+    /// Inspect or rewrite `req` before it reaches `provider`. An error rejects the call
+    /// without contacting the provider; a response answers it instead, and neither the
+    /// layers inside this one nor this layer's response hooks run. A streamed call answered
+    /// this way streams the response as its only, `Final`, event.
+    async fn before_request(
+        &self,
+        _provider: &str,
+        _req: &mut ChatRequest,
+    ) -> CoreResult<Option<ChatResponse>> {
+        Ok(None)
+    }
+
+    /// Inspect or rewrite a non-streaming response. `req` is the request as sent. An error
+    /// fails the call in place of the response.
+    async fn after_response(&self, _req: &ChatRequest, _resp: &mut ChatResponse) -> CoreResult<()> {
+        Ok(())
+    }
+
+    /// Inspect or rewrite one stream event; `None` drops it. Terminal events must be passed
+    /// on (possibly rewritten) so the stream still ends with exactly one.
+    fn on_stream_event(&self, _req: &ChatRequest, ev: StreamEvent) -> Option<StreamEvent> {
+        Some(ev)
+    }
+}
+
+/// Decorator running `middleware` around every call to `inner`.
+#[derive(Debug)]
+pub struct MiddlewareProvider {
+    inner: Arc<dyn ChatProvider>,
+    middleware: Arc<dyn DispatchMiddleware>,
+}
+
+impl MiddlewareProvider {
+    pub fn new(inner: Arc<dyn ChatProvider>, middleware: Arc<dyn DispatchMiddleware>) -> Self {
+        Self { inner, middleware }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for MiddlewareProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn chat(&self, mut req: ChatRequest) -> CoreResult<ChatResponse> {
+        let answer = self.middleware.before_request(self.inner.name(), &mut req);
+        if let Some(resp) = answer.await? {
+            return Ok(resp);
+        }
+        let mut resp = self.inner.chat(req.clone()).await?;
+        self.middleware.after_response(&req, &mut resp).await?;
+        Ok(resp)
+    }
+
+    async fn chat_stream_events(&self, mut req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        let answer = self.middleware.before_request(self.inner.name(), &mut req);
+        if let Some(resp) = answer.await? {
+            let answered = StreamEvent::Final(resp);
+            return Ok(Box::pin(futures_util::stream::iter([answered])));
+        }
+        let events = self.inner.chat_stream_events(req.clone()).await?;
+        let middleware = self.middleware.clone();
+        Ok(Box::pin(events.filter_map(move |ev| {
+            std::future::ready(middleware.on_stream_event(&req, ev))
+        })))
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.inner.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.inner.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.inner.count_tokens(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::AiProxyError;
+    use crate::provider::NullProvider;
+    use std::sync::Mutex;
+
+    /// Tags requests and responses with its name and records the order hooks ran in.
+    #[derive(Debug)]
+    struct Tagger {
+        name: &'static str,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl DispatchMiddleware for Tagger {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn before_request(
+            &self,
+            provider: &str,
+            req: &mut ChatRequest,
+        ) -> CoreResult<Option<ChatResponse>> {
+            if req.messages[0].content == "forbidden" {
+                return Err(AiProxyError::Validation(format!("{} refused", self.name)));
+            }
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} before {provider}", self.name));
+            req.metadata = Some(serde_json::json!({ "tag": self.name }));
+            Ok(None)
+        }
+
+        async fn after_response(
+            &self,
+            req: &ChatRequest,
+            resp: &mut ChatResponse,
+        ) -> CoreResult<()> {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} after", self.name));
+            resp.text = format!("{} [{}]", resp.text, req.metadata.as_ref().unwrap()["tag"]);
+            Ok(())
+        }
+
+        fn on_stream_event(&self, _req: &ChatRequest, ev: StreamEvent) -> Option<StreamEvent> {
+            match ev {
+                StreamEvent::Final(mut resp) => {
+                    resp.text = resp.text.to_uppercase();
+                    Some(StreamEvent::Final(resp))
+                }
+                ev => Some(ev),
+            }
+        }
+    }
+
+    fn layered(log: &Arc<Mutex<Vec<String>>>) -> MiddlewareProvider {
+        let tag = |name| {
+            Arc::new(Tagger {
+                name,
+                log: log.clone(),
+            })
+        };
+        let inner = Arc::new(MiddlewareProvider::new(
+            Arc::new(NullProvider),
+            tag("inner"),
+        ));
+        MiddlewareProvider::new(inner, tag("outer"))
+    }
+
+    #[tokio::test]
+    async fn layers_run_outside_in_then_inside_out() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let provider = layered(&log);
+        assert_eq!(provider.name(), "null");
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let resp = provider.chat(req).await.unwrap();
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                "outer before null",
+                "inner before null",
+                "inner after",
+                "outer after"
+            ]
+        );
+        // Each layer's `after_response` sees the request as that layer sent it.
+        assert_eq!(
+            resp.text,
+            "[null provider response] [\"inner\"] [\"outer\"]"
+        );
+
+        let req = ChatRequest::builder().model("m").user("forbidden").build();
+        let err = provider.chat(req).await.unwrap_err();
+        assert_eq!(err.to_string(), "validation failed: outer refused");
+    }
+
+    #[tokio::test]
+    async fn stream_events_pass_through_every_layer() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let mut events = layered(&log).chat_stream_events(req).await.unwrap();
+        match events.next().await {
+            Some(StreamEvent::Final(resp)) => assert_eq!(resp.text, "[NULL PROVIDER RESPONSE]"),
+            other => panic!("expected final, got {other:?}"),
+        }
+        assert!(events.next().await.is_none());
+        assert_eq!(
+            log.lock().unwrap().len(),
+            2,
+            "only before_request runs when streaming"
+        );
+    }
+
+    /// Answers every request itself, as a cache hit would.
+    #[derive(Debug)]
+    struct Canned;
+
+    #[async_trait]
+    impl DispatchMiddleware for Canned {
+        fn name(&self) -> &str {
+            "canned"
+        }
+
+        async fn before_request(
+            &self,
+            _provider: &str,
+            req: &mut ChatRequest,
+        ) -> CoreResult<Option<ChatResponse>> {
+            let mut resp = NullProvider.chat(req.clone()).await?;
+            resp.text = "canned".into();
+            Ok(Some(resp))
+        }
+    }
+
+    #[tokio::test]
+    async fn a_layer_can_answer_without_calling_inner_layers() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let tag = |name| {
+            Arc::new(Tagger {
+                name,
+                log: log.clone(),
+            })
+        };
+        let inner = Arc::new(MiddlewareProvider::new(
+            Arc::new(NullProvider),
+            tag("inner"),
+        ));
+        let canned = Arc::new(MiddlewareProvider::new(inner, Arc::new(Canned)));
+        let provider = MiddlewareProvider::new(canned, tag("outer"));
+
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let resp = provider.chat(req.clone()).await.unwrap();
+        assert_eq!(resp.text, "canned [\"outer\"]");
+        assert_eq!(*log.lock().unwrap(), ["outer before null", "outer after"]);
+
+        let mut events = provider.chat_stream_events(req).await.unwrap();
+        match events.next().await {
+            Some(StreamEvent::Final(resp)) => assert_eq!(resp.text, "CANNED"),
+            other => panic!("expected final, got {other:?}"),
+        }
+        assert!(events.next().await.is_none());
+    }
+}
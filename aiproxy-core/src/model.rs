@@ -1,4 +1,10 @@
-use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::fmt;
+
+use serde::de::{self, SeqAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::AiProxyError;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -17,16 +23,251 @@ pub enum StopReason {
     ToolUse,
     EndTurn,
     ContentFilter,
+    /// The caller cancelled the stream (see `stream::cancellable`).
+    Cancelled,
+    /// The model declined on policy grounds; the explanation is in `ChatResponse.refusal`.
+    Refusal,
     Other,
 }
 
+/// Body of a chat message: plain text, or a list of typed parts.
+///
+/// Plain text (de)serializes as a bare JSON string, so existing payloads and saved sessions
+/// keep working; parts use the OpenAI-style `[{"type": "text", "text": ...}]` array.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+/// One typed piece of a multi-part message.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ContentPart {
+    Text {
+        text: String,
+    },
+    /// An image the provider fetches from `url`, or carries inline as a `data:` URL.
+    ImageUrl {
+        image_url: ImageUrl,
+    },
+    /// Inline image bytes, base64-encoded, of type `media_type` (e.g. `image/png`).
+    ImageBase64 {
+        media_type: String,
+        data: String,
+    },
+}
+
+/// An image reference in OpenAI's `image_url` part shape.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ImageUrl {
+    pub url: String,
+    /// Resolution hint (`low`, `high` or `auto`); providers without one ignore it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ContentPart {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text { text: text.into() }
+    }
+
+    pub fn image_url(url: impl Into<String>) -> Self {
+        Self::ImageUrl {
+            image_url: ImageUrl {
+                url: url.into(),
+                detail: None,
+            },
+        }
+    }
+
+    pub fn image_base64(media_type: impl Into<String>, data: impl Into<String>) -> Self {
+        Self::ImageBase64 {
+            media_type: media_type.into(),
+            data: data.into(),
+        }
+    }
+
+    /// Media type and base64 data of an inline image: an `ImageBase64` part or a
+    /// `data:<type>;base64,<data>` URL.
+    pub fn inline_image(&self) -> Option<(&str, &str)> {
+        match self {
+            Self::ImageBase64 { media_type, data } => Some((media_type, data)),
+            Self::ImageUrl { image_url } => {
+                image_url.url.strip_prefix("data:")?.split_once(";base64,")
+            }
+            Self::Text { .. } => None,
+        }
+    }
+}
+
+impl MessageContent {
+    /// All text in the message; text parts are concatenated in order and images skipped.
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Self::Text(s) => Cow::Borrowed(s),
+            Self::Parts(parts) => Cow::Owned(
+                parts
+                    .iter()
+                    .filter_map(|p| match p {
+                        ContentPart::Text { text } => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Whether any part is an image.
+    pub fn has_images(&self) -> bool {
+        match self {
+            Self::Text(_) => false,
+            Self::Parts(parts) => parts.iter().any(|p| !matches!(p, ContentPart::Text { .. })),
+        }
+    }
+
+    /// The text of a plain-text message; `None` for multi-part content.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(s) => Some(s),
+            Self::Parts(_) => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            Self::Text(s) => s.is_empty(),
+            Self::Parts(parts) => parts.is_empty(),
+        }
+    }
+}
+
+impl From<String> for MessageContent {
+    fn from(s: String) -> Self {
+        Self::Text(s)
+    }
+}
+
+impl From<&str> for MessageContent {
+    fn from(s: &str) -> Self {
+        Self::Text(s.to_string())
+    }
+}
+
+impl From<Vec<ContentPart>> for MessageContent {
+    fn from(parts: Vec<ContentPart>) -> Self {
+        Self::Parts(parts)
+    }
+}
+
+impl PartialEq<str> for MessageContent {
+    fn eq(&self, other: &str) -> bool {
+        self.as_text() == Some(other)
+    }
+}
+
+impl PartialEq<&str> for MessageContent {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_text() == Some(*other)
+    }
+}
+
+impl fmt::Display for MessageContent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text())
+    }
+}
+
+impl Serialize for MessageContent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Text(s) => serializer.serialize_str(s),
+            Self::Parts(parts) => parts.serialize(serializer),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MessageContent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ContentVisitor;
+
+        impl<'de> Visitor<'de> for ContentVisitor {
+            type Value = MessageContent;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a string or an array of content parts")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(MessageContent::Text(v.to_string()))
+            }
+
+            fn visit_string<E: de::Error>(self, v: String) -> Result<Self::Value, E> {
+                Ok(MessageContent::Text(v))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                Vec::<ContentPart>::deserialize(de::value::SeqAccessDeserializer::new(seq))
+                    .map(MessageContent::Parts)
+            }
+        }
+
+        deserializer.deserialize_any(ContentVisitor)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ChatMessage {
     pub role: Role,
-    pub content: String,
+    pub content: MessageContent,
+    /// For `Role::Tool` messages, the id of the tool call this result answers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Participant name. OpenAI-compatible providers send it as `name`; others prefix it to
+    /// the message text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+impl ChatMessage {
+    pub fn new(role: Role, content: impl Into<MessageContent>) -> Self {
+        Self {
+            role,
+            content: content.into(),
+            tool_call_id: None,
+            name: None,
+        }
+    }
+
+    /// Attribute the message to the participant `name`.
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn system(content: impl Into<MessageContent>) -> Self {
+        Self::new(Role::System, content)
+    }
+
+    pub fn user(content: impl Into<MessageContent>) -> Self {
+        Self::new(Role::User, content)
+    }
+
+    pub fn assistant(content: impl Into<MessageContent>) -> Self {
+        Self::new(Role::Assistant, content)
+    }
+
+    /// The result of the tool call `tool_call_id`.
+    pub fn tool(tool_call_id: impl Into<String>, content: impl Into<MessageContent>) -> Self {
+        Self {
+            tool_call_id: Some(tool_call_id.into()),
+            ..Self::new(Role::Tool, content)
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 pub struct ChatRequest {
     pub model: String,
     pub messages: Vec<ChatMessage>,
@@ -39,14 +280,411 @@ pub struct ChatRequest {
     pub idempotency_key: Option<String>,
     pub max_output_tokens: Option<u32>,
     pub stop_sequences: Option<Vec<String>>,
+    /// Attach the provider's unparsed response to `ChatResponse.raw` (non-streaming calls).
+    #[serde(default)]
+    pub include_raw: bool,
+    /// Number of choices to generate; providers without support return one.
+    pub n: Option<u32>,
+    /// Ask for per-token log probabilities where the provider supports them.
+    #[serde(default)]
+    pub logprobs: bool,
+    /// Provider parameters ai-proxy does not model, merged into the upstream payload subject
+    /// to the provider's `config::ExtraParamsCfg`.
+    #[serde(default)]
+    pub extra: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Prompt-screening findings recorded in `flag` mode, for downstream policy.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub screening: Vec<ScreenFinding>,
+    /// Bill this call to another organization than the provider's configured one; must be
+    /// on the provider's `config::BillingOverridesCfg` allowlist.
+    #[serde(default)]
+    pub organization: Option<String>,
+    /// Bill this call to another project, under the same allowlist rule.
+    #[serde(default)]
+    pub project: Option<String>,
+    /// Constrain the reply to this JSON Schema on providers with native structured outputs
+    /// (`ChatProvider::supports_response_schema`); others ignore it.
+    #[serde(default)]
+    pub response_schema: Option<ResponseSchema>,
+    /// Tools the model may call; calls come back as `ChatResponse.tool_calls`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tools: Vec<ToolSpec>,
+    /// Whether, and which, of `tools` the model must call; the provider decides when unset.
+    #[serde(default)]
+    pub tool_choice: Option<ToolChoice>,
+    /// End a streamed reply that goes this long without an event, instead of
+    /// `config::HttpCfg::stream_idle_timeout_ms`.
+    #[serde(default)]
+    pub stream_idle_timeout_ms: Option<u64>,
+    /// End a streamed reply still running this long after the call, instead of
+    /// `config::HttpCfg::stream_deadline_ms`.
+    #[serde(default)]
+    pub stream_deadline_ms: Option<u64>,
+    /// The name the client asked for when a `routing.aliases` entry replaced `model`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_model: Option<String>,
+}
+
+/// A named JSON Schema the reply must follow.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ResponseSchema {
+    pub name: String,
+    pub schema: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ScreenKind {
+    /// Text telling the model to ignore or replace its instructions.
+    OverridePhrase,
+    /// A long base64 run that could carry encoded instructions.
+    Base64Blob,
+    /// A URL whose host hides where it leads (IP literal, punycode, shortener, userinfo).
+    SuspiciousUrl,
+}
+
+/// One heuristic match from `screening::screen`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ScreenFinding {
+    /// Index into `ChatRequest.messages`.
+    pub message: usize,
+    pub kind: ScreenKind,
+    /// The matched text, cut to a short prefix.
+    pub excerpt: String,
+}
+
+/// Which output policy a `GuardrailHit` came from.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuardrailKind {
+    /// A `guardrail.deny` phrase.
+    Deny,
+    /// A `guardrail.patterns` regex.
+    Pattern,
+    /// A category flagged by the moderation endpoint.
+    Moderation,
+}
+
+/// One output policy the response tripped, from `guardrail::Guardrail::check`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct GuardrailHit {
+    pub kind: GuardrailKind,
+    /// The phrase, pattern or moderation category that matched.
+    pub rule: String,
+}
+
+impl ChatRequest {
+    /// Start a request; unset options stay `None`.
+    pub fn builder() -> ChatRequestBuilder {
+        ChatRequestBuilder::default()
+    }
+}
+
+/// Fluent construction of a `ChatRequest`, e.g.
+/// `ChatRequest::builder().model("gpt-4o").user("hi").temperature(0.2).build()`.
+#[derive(Debug, Clone, Default)]
+pub struct ChatRequestBuilder {
+    req: ChatRequest,
+}
+
+impl ChatRequestBuilder {
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.req.model = model.into();
+        self
+    }
+
+    pub fn message(mut self, message: ChatMessage) -> Self {
+        self.req.messages.push(message);
+        self
+    }
+
+    pub fn messages(mut self, messages: impl IntoIterator<Item = ChatMessage>) -> Self {
+        self.req.messages.extend(messages);
+        self
+    }
+
+    pub fn system(self, content: impl Into<MessageContent>) -> Self {
+        self.message(ChatMessage::system(content))
+    }
+
+    pub fn user(self, content: impl Into<MessageContent>) -> Self {
+        self.message(ChatMessage::user(content))
+    }
+
+    pub fn assistant(self, content: impl Into<MessageContent>) -> Self {
+        self.message(ChatMessage::assistant(content))
+    }
+
+    pub fn tool(self, tool_call_id: impl Into<String>, content: impl Into<MessageContent>) -> Self {
+        self.message(ChatMessage::tool(tool_call_id, content))
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        self.req.temperature = Some(temperature);
+        self
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        self.req.top_p = Some(top_p);
+        self
+    }
+
+    pub fn metadata(mut self, metadata: serde_json::Value) -> Self {
+        self.req.metadata = Some(metadata);
+        self
+    }
+
+    pub fn client_key(mut self, key: impl Into<String>) -> Self {
+        self.req.client_key = Some(key.into());
+        self
+    }
+
+    pub fn request_id(mut self, id: impl Into<String>) -> Self {
+        self.req.request_id = Some(id.into());
+        self
+    }
+
+    pub fn trace_id(mut self, id: impl Into<String>) -> Self {
+        self.req.trace_id = Some(id.into());
+        self
+    }
+
+    pub fn idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.req.idempotency_key = Some(key.into());
+        self
+    }
+
+    pub fn max_output_tokens(mut self, n: u32) -> Self {
+        self.req.max_output_tokens = Some(n);
+        self
+    }
+
+    pub fn include_raw(mut self, on: bool) -> Self {
+        self.req.include_raw = on;
+        self
+    }
+
+    pub fn n(mut self, n: u32) -> Self {
+        self.req.n = Some(n);
+        self
+    }
+
+    pub fn logprobs(mut self, on: bool) -> Self {
+        self.req.logprobs = on;
+        self
+    }
+
+    /// Pass `key` through to the provider payload unmodelled.
+    pub fn extra(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.req
+            .extra
+            .get_or_insert_with(Default::default)
+            .insert(key.into(), value);
+        self
+    }
+
+    pub fn organization(mut self, id: impl Into<String>) -> Self {
+        self.req.organization = Some(id.into());
+        self
+    }
+
+    pub fn project(mut self, id: impl Into<String>) -> Self {
+        self.req.project = Some(id.into());
+        self
+    }
+
+    pub fn response_schema(mut self, name: impl Into<String>, schema: serde_json::Value) -> Self {
+        self.req.response_schema = Some(ResponseSchema {
+            name: name.into(),
+            schema,
+        });
+        self
+    }
+
+    /// Offer `tools` to the model, after any already offered.
+    pub fn tools(mut self, tools: impl IntoIterator<Item = ToolSpec>) -> Self {
+        self.req.tools.extend(tools);
+        self
+    }
+
+    pub fn tool_choice(mut self, choice: ToolChoice) -> Self {
+        self.req.tool_choice = Some(choice);
+        self
+    }
+
+    /// End the reply's stream after `ms` without an event.
+    pub fn stream_idle_timeout_ms(mut self, ms: u64) -> Self {
+        self.req.stream_idle_timeout_ms = Some(ms);
+        self
+    }
+
+    /// End the reply's stream if it is still running `ms` after the call.
+    pub fn stream_deadline_ms(mut self, ms: u64) -> Self {
+        self.req.stream_deadline_ms = Some(ms);
+        self
+    }
+
+    /// Add one stop sequence; repeat for more.
+    pub fn stop(mut self, sequence: impl Into<String>) -> Self {
+        self.req
+            .stop_sequences
+            .get_or_insert_default()
+            .push(sequence.into());
+        self
+    }
+
+    pub fn build(self) -> ChatRequest {
+        self.req
+    }
+}
+
+/// Token counts for one call. Detail counts are `None` when the provider does not report them.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Usage {
+    /// All prompt tokens, including those served from the provider's prompt cache.
+    pub prompt: u32,
+    /// All completion tokens, including reasoning tokens.
+    pub completion: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_prompt: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_prompt: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio_completion: Option<u32>,
+}
+
+impl Usage {
+    pub fn new(prompt: u32, completion: u32) -> Self {
+        Self {
+            prompt,
+            completion,
+            ..Self::default()
+        }
+    }
+
+    pub fn total(&self) -> u32 {
+        self.prompt.saturating_add(self.completion)
+    }
+}
+
+/// `ChatResponse.usage` on the wire: the `usage` object plus the flat `usage_prompt` /
+/// `usage_completion` pair that older readers expect. Either form is accepted on input.
+mod usage_compat {
+    use super::Usage;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    struct Out<'a> {
+        usage: &'a Usage,
+        usage_prompt: u32,
+        usage_completion: u32,
+    }
+
+    #[derive(Deserialize)]
+    struct In {
+        #[serde(default)]
+        usage: Option<Usage>,
+        #[serde(default)]
+        usage_prompt: u32,
+        #[serde(default)]
+        usage_completion: u32,
+    }
+
+    pub fn serialize<S: Serializer>(usage: &Usage, serializer: S) -> Result<S::Ok, S::Error> {
+        Out {
+            usage,
+            usage_prompt: usage.prompt,
+            usage_completion: usage.completion,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Usage, D::Error> {
+        let i = In::deserialize(deserializer)?;
+        Ok(i.usage
+            .unwrap_or_else(|| Usage::new(i.usage_prompt, i.usage_completion)))
+    }
+}
+
+/// A tool invocation requested by the model.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    /// Parsed arguments; a JSON string when the provider sent arguments that do not parse.
+    pub arguments: serde_json::Value,
+}
+
+impl ToolCall {
+    /// From OpenAI-style calls, whose arguments arrive as JSON text.
+    pub fn from_json_text(id: String, name: String, arguments: &str) -> Self {
+        let arguments = serde_json::from_str(arguments)
+            .unwrap_or_else(|_| serde_json::Value::String(arguments.to_string()));
+        Self {
+            id,
+            name,
+            arguments,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// A source the model cited: an OpenAI/OpenRouter `url_citation`, a Perplexity citation URL,
+/// or an Anthropic text-block citation.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct Citation {
+    /// Absent for citations of caller-supplied documents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Character range of the answer text the citation supports.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_index: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_index: Option<u32>,
+    /// The quoted passage from the source, when the provider returns it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cited_text: Option<String>,
+}
+
+/// One generated alternative.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ChatChoice {
+    pub index: u32,
+    pub text: String,
+    pub stop_reason: Option<StopReason>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub logprobs: Option<Vec<TokenLogprob>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Citation>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct ChatResponse {
     pub model: String,
     pub text: String,
-    pub usage_prompt: u32,
-    pub usage_completion: u32,
+    #[serde(flatten, with = "usage_compat")]
+    pub usage: Usage,
     pub cached: bool,
     pub provider: String,
     pub transcript_id: Option<String>,
@@ -55,13 +693,112 @@ pub struct ChatResponse {
     pub provider_request_id: Option<String>,
     pub created_at_ms: i64,
     pub latency_ms: u32,
+    /// The provider's response JSON when `ChatRequest.include_raw` was set, capped at
+    /// `http_client::MAX_RAW_BYTES`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+    /// Every choice the provider returned; `text`, `stop_reason`, `tool_calls`, `refusal` and
+    /// `annotations` mirror choice 0.
+    #[serde(default)]
+    pub choices: Vec<ChatChoice>,
+    /// Tools the model called, with their arguments (`stop_reason` is then `ToolUse`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCall>,
+    /// The model's explanation when it refused (`stop_reason` is then `Refusal`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal: Option<String>,
+    /// Sources cited in `text` (web search, grounding, document citations).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub annotations: Vec<Citation>,
+    /// What was cut from the prompt to fit its token budget, if anything.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncation: Option<TruncationReport>,
+    /// Estimated USD cost of the call from the built-in price table; `None` when the model
+    /// has no known price.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+    /// Output guardrail hits when `guardrail.action` is `annotate` or `redact`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub guardrail: Vec<GuardrailHit>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+/// Messages removed or shortened by `normalizer::truncate_to_budget`. Indices refer to the
+/// request's messages as they were before truncation.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, Default)]
+pub struct TruncationReport {
+    pub budget: u32,
+    /// Estimated prompt tokens before and after; `after` can still exceed `budget` when the
+    /// system prompt alone does.
+    pub tokens_before: u32,
+    pub tokens_after: u32,
+    /// Messages dropped whole, oldest first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dropped: Vec<usize>,
+    /// Message whose text was cut to its most recent part.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<usize>,
+    /// The `max_output_tokens` the request asked for, when `truncation.auto_fit` lowered it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+}
+
+impl ChatResponse {
+    /// The reply as an assistant message for the next turn's history. A refusal without
+    /// text is recorded as its explanation.
+    pub fn to_message(&self) -> ChatMessage {
+        match (&self.refusal, self.text.is_empty()) {
+            (Some(refusal), true) => ChatMessage::assistant(refusal.clone()),
+            _ => ChatMessage::assistant(self.text.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
 pub struct EmbedRequest {
     pub model: String,
     pub inputs: Vec<String>,
     pub client_key: Option<String>,
+    /// The name the client asked for when a `routing.aliases` entry replaced `model`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_model: Option<String>,
+}
+
+impl EmbedRequest {
+    pub fn builder() -> EmbedRequestBuilder {
+        EmbedRequestBuilder::default()
+    }
+}
+
+/// Fluent construction of an `EmbedRequest`.
+#[derive(Debug, Clone, Default)]
+pub struct EmbedRequestBuilder {
+    req: EmbedRequest,
+}
+
+impl EmbedRequestBuilder {
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.req.model = model.into();
+        self
+    }
+
+    pub fn input(mut self, input: impl Into<String>) -> Self {
+        self.req.inputs.push(input.into());
+        self
+    }
+
+    pub fn inputs<S: Into<String>>(mut self, inputs: impl IntoIterator<Item = S>) -> Self {
+        self.req.inputs.extend(inputs.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn client_key(mut self, key: impl Into<String>) -> Self {
+        self.req.client_key = Some(key.into());
+        self
+    }
+
+    pub fn build(self) -> EmbedRequest {
+        self.req
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
@@ -71,6 +808,200 @@ pub struct EmbedResponse {
     pub usage: u32,
     pub cached: bool,
     pub provider: String,
+    /// Estimated USD cost of the call; `None` when the model has no known price.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_usd: Option<f64>,
+}
+
+/// JSON Schema document describing a tool's arguments.
+pub type JsonSchema = serde_json::Value;
+
+/// A tool the model may call.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ToolSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Defaults to an object schema with no properties.
+    #[serde(default = "empty_object_schema")]
+    pub parameters: JsonSchema,
+}
+
+fn empty_object_schema() -> JsonSchema {
+    serde_json::json!({"type": "object", "properties": {}})
+}
+
+/// Whether, and which, tool the model must call.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolChoice {
+    /// The model decides.
+    Auto,
+    /// No tool calls.
+    None,
+    /// At least one tool call, of the model's choosing.
+    Required,
+    /// Exactly this tool.
+    Tool { name: String },
+}
+
+/// Wire format for tools. OpenRouter and other OpenAI-compatible APIs use `OpenAi`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolDialect {
+    OpenAi,
+    Anthropic,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum OpenAiTool {
+    Function { function: ToolSpec },
+}
+
+#[derive(Serialize, Deserialize)]
+struct AnthropicTool {
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    input_schema: JsonSchema,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum OpenAiToolChoice {
+    Mode(String),
+    Function {
+        #[serde(rename = "type")]
+        kind: String,
+        function: OpenAiFunctionName,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct OpenAiFunctionName {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicToolChoice {
+    Auto,
+    Any,
+    None,
+    Tool { name: String },
+}
+
+fn wire_error(what: &str, dialect: ToolDialect, e: impl fmt::Display) -> AiProxyError {
+    AiProxyError::Validation(format!("invalid {dialect:?} {what}: {e}"))
+}
+
+impl ToolSpec {
+    pub fn to_wire(&self, dialect: ToolDialect) -> serde_json::Value {
+        let v = match dialect {
+            ToolDialect::OpenAi => serde_json::to_value(OpenAiTool::Function {
+                function: self.clone(),
+            }),
+            ToolDialect::Anthropic => serde_json::to_value(AnthropicTool {
+                name: self.name.clone(),
+                description: self.description.clone(),
+                input_schema: self.parameters.clone(),
+            }),
+        };
+        v.expect("tool specs always serialize")
+    }
+
+    pub fn from_wire(v: serde_json::Value, dialect: ToolDialect) -> Result<Self, AiProxyError> {
+        let err = |e| wire_error("tool", dialect, e);
+        match dialect {
+            ToolDialect::OpenAi => match serde_json::from_value(v).map_err(err)? {
+                OpenAiTool::Function { function } => Ok(function),
+            },
+            ToolDialect::Anthropic => {
+                let t: AnthropicTool = serde_json::from_value(v).map_err(err)?;
+                Ok(Self {
+                    name: t.name,
+                    description: t.description,
+                    parameters: t.input_schema,
+                })
+            }
+        }
+    }
+}
+
+impl ToolChoice {
+    pub fn to_wire(&self, dialect: ToolDialect) -> serde_json::Value {
+        let v = match (dialect, self) {
+            (ToolDialect::OpenAi, Self::Tool { name }) => {
+                serde_json::to_value(OpenAiToolChoice::Function {
+                    kind: "function".into(),
+                    function: OpenAiFunctionName { name: name.clone() },
+                })
+            }
+            (ToolDialect::OpenAi, mode) => serde_json::to_value(OpenAiToolChoice::Mode(
+                match mode {
+                    Self::None => "none",
+                    Self::Required => "required",
+                    _ => "auto",
+                }
+                .into(),
+            )),
+            (ToolDialect::Anthropic, choice) => serde_json::to_value(match choice {
+                Self::Auto => AnthropicToolChoice::Auto,
+                Self::None => AnthropicToolChoice::None,
+                Self::Required => AnthropicToolChoice::Any,
+                Self::Tool { name } => AnthropicToolChoice::Tool { name: name.clone() },
+            }),
+        };
+        v.expect("tool choices always serialize")
+    }
+
+    pub fn from_wire(v: serde_json::Value, dialect: ToolDialect) -> Result<Self, AiProxyError> {
+        let err = |e| wire_error("tool_choice", dialect, e);
+        match dialect {
+            ToolDialect::OpenAi => match serde_json::from_value(v).map_err(err)? {
+                OpenAiToolChoice::Mode(m) => match m.as_str() {
+                    "auto" => Ok(Self::Auto),
+                    "none" => Ok(Self::None),
+                    "required" => Ok(Self::Required),
+                    other => Err(wire_error(
+                        "tool_choice",
+                        dialect,
+                        format!("unknown mode '{other}'"),
+                    )),
+                },
+                OpenAiToolChoice::Function { kind, function } if kind == "function" => {
+                    Ok(Self::Tool {
+                        name: function.name,
+                    })
+                }
+                OpenAiToolChoice::Function { kind, .. } => Err(wire_error(
+                    "tool_choice",
+                    dialect,
+                    format!("unknown type '{kind}'"),
+                )),
+            },
+            ToolDialect::Anthropic => Ok(match serde_json::from_value(v).map_err(err)? {
+                AnthropicToolChoice::Auto => Self::Auto,
+                AnthropicToolChoice::Any => Self::Required,
+                AnthropicToolChoice::None => Self::None,
+                AnthropicToolChoice::Tool { name } => Self::Tool { name },
+            }),
+        }
+    }
+}
+
+/// One entry from a provider's model-list endpoint.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModelInfo {
+    pub id: String,
+    pub provider: String,
+    pub owned_by: Option<String>,
+    /// Context window in tokens, as the provider reports it or else from the model catalog.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+    /// Most tokens one response may generate; sourced like `context_window`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
 }
 
 #[cfg(test)]
@@ -79,28 +1010,140 @@ mod tests {
 
     #[test]
     fn chat_request_roundtrip() {
-        let req = ChatRequest {
-            model: "gpt-4o".to_string(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hello".to_string(),
-            }],
-            temperature: Some(0.7),
-            top_p: Some(0.9),
-            metadata: None,
-            client_key: Some("test-client".to_string()),
-            request_id: Some("req-123".to_string()),
-            trace_id: Some("trace-abc".to_string()),
-            idempotency_key: Some("idem-xyz".to_string()),
-            max_output_tokens: Some(256),
-            stop_sequences: Some(vec!["\n\n".to_string()]),
-        };
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("Hello")
+            .temperature(0.7)
+            .top_p(0.9)
+            .client_key("test-client")
+            .request_id("req-123")
+            .trace_id("trace-abc")
+            .idempotency_key("idem-xyz")
+            .max_output_tokens(256)
+            .stop("\n\n")
+            .build();
 
         let json = serde_json::to_string(&req).unwrap();
         let de: ChatRequest = serde_json::from_str(&json).unwrap();
         assert_eq!(req, de);
     }
 
+    #[test]
+    fn builder_matches_the_struct_literal() {
+        let built = ChatRequest::builder()
+            .model("m")
+            .system("be brief")
+            .user("hi")
+            .assistant("hello")
+            .stop("END")
+            .stop("STOP")
+            .build();
+        let literal = ChatRequest {
+            model: "m".into(),
+            messages: vec![
+                ChatMessage {
+                    role: Role::System,
+                    content: "be brief".into(),
+                    tool_call_id: None,
+                    name: None,
+                },
+                ChatMessage {
+                    role: Role::User,
+                    content: "hi".into(),
+                    tool_call_id: None,
+                    name: None,
+                },
+                ChatMessage {
+                    role: Role::Assistant,
+                    content: "hello".into(),
+                    tool_call_id: None,
+                    name: None,
+                },
+            ],
+            temperature: None,
+            top_p: None,
+            metadata: None,
+            client_key: None,
+            request_id: None,
+            trace_id: None,
+            idempotency_key: None,
+            max_output_tokens: None,
+            stop_sequences: Some(vec!["END".into(), "STOP".into()]),
+            include_raw: false,
+            n: None,
+            logprobs: false,
+            extra: None,
+            screening: vec![],
+            organization: None,
+            project: None,
+            response_schema: None,
+            tools: vec![],
+            tool_choice: None,
+            stream_idle_timeout_ms: None,
+            stream_deadline_ms: None,
+            requested_model: None,
+        };
+        assert_eq!(built, literal);
+
+        let embed = EmbedRequest::builder()
+            .model("e")
+            .input("a")
+            .inputs(["b", "c"])
+            .build();
+        assert_eq!(embed.inputs, vec!["a", "b", "c"]);
+        assert_eq!(embed.client_key, None);
+    }
+
+    #[test]
+    fn message_content_accepts_strings_and_parts() {
+        let plain: ChatMessage = serde_json::from_str(r#"{"role":"user","content":"hi"}"#).unwrap();
+        assert_eq!(plain.content, "hi");
+        assert_eq!(
+            serde_json::to_string(&plain).unwrap(),
+            r#"{"role":"user","content":"hi"}"#
+        );
+
+        let json =
+            r#"{"role":"user","content":[{"type":"text","text":"a"},{"type":"text","text":"b"}]}"#;
+        let parts: ChatMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(parts.content.as_text(), None);
+        assert_eq!(parts.content.text(), "ab");
+        assert_eq!(serde_json::to_string(&parts).unwrap(), json);
+
+        let err = serde_json::from_str::<ChatMessage>(r#"{"role":"user","content":7}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("a string or an array of content parts"),
+            "{err}"
+        );
+        assert!(
+            serde_json::from_str::<ChatMessage>(
+                r#"{"role":"user","content":[{"type":"video","url":"x"}]}"#
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn image_parts_round_trip_and_expose_inline_data() {
+        let json = r#"{"role":"user","content":[{"type":"text","text":"what is this?"},{"type":"image_url","image_url":{"url":"https://example.com/cat.png","detail":"low"}},{"type":"image_base64","media_type":"image/png","data":"iVBORw0"}]}"#;
+        let msg: ChatMessage = serde_json::from_str(json).unwrap();
+        assert_eq!(serde_json::to_string(&msg).unwrap(), json);
+        assert_eq!(msg.content.text(), "what is this?");
+        assert!(msg.content.has_images());
+        let MessageContent::Parts(parts) = &msg.content else {
+            panic!("parts")
+        };
+        assert_eq!(parts[1].inline_image(), None);
+        assert_eq!(parts[2].inline_image(), Some(("image/png", "iVBORw0")));
+        assert_eq!(
+            ContentPart::image_url("data:image/jpeg;base64,/9j/4AAQ").inline_image(),
+            Some(("image/jpeg", "/9j/4AAQ"))
+        );
+        assert!(!MessageContent::from("hi").has_images());
+    }
+
     #[test]
     fn role_json_roundtrip_lowercase() {
         let json = r#"{"role":"assistant","content":"ok"}"#;
@@ -115,8 +1158,11 @@ mod tests {
         let resp = ChatResponse {
             model: "gpt-4o".to_string(),
             text: "Hello back".to_string(),
-            usage_prompt: 10,
-            usage_completion: 20,
+            usage: Usage {
+                cached_prompt: Some(4),
+                reasoning: Some(8),
+                ..Usage::new(10, 20)
+            },
             cached: false,
             provider: "openai".to_string(),
             transcript_id: Some("transcript-1".to_string()),
@@ -125,20 +1171,90 @@ mod tests {
             provider_request_id: Some("prov-123".to_string()),
             created_at_ms: 1234567890,
             latency_ms: 42,
+            raw: Some(serde_json::json!({"id": "prov-123", "system_fingerprint": "fp_1"})),
+            choices: vec![ChatChoice {
+                index: 0,
+                text: "Hello back".to_string(),
+                stop_reason: Some(StopReason::Stop),
+                tool_calls: vec![ToolCall {
+                    id: "call_1".into(),
+                    name: "lookup".into(),
+                    arguments: serde_json::json!({"q": "x"}),
+                }],
+                logprobs: Some(vec![TokenLogprob {
+                    token: "Hello".into(),
+                    logprob: -0.25,
+                    top_logprobs: vec![],
+                }]),
+                refusal: None,
+                annotations: vec![],
+            }],
+            tool_calls: vec![ToolCall {
+                id: "call_1".into(),
+                name: "lookup".into(),
+                arguments: serde_json::json!({"q": "x"}),
+            }],
+            refusal: None,
+            annotations: vec![Citation {
+                url: Some("https://example.com".into()),
+                title: Some("Example".into()),
+                start_index: Some(0),
+                end_index: Some(5),
+                cited_text: None,
+            }],
+            truncation: Some(TruncationReport {
+                budget: 100,
+                tokens_before: 180,
+                tokens_after: 90,
+                dropped: vec![1, 2],
+                truncated: None,
+                max_output_tokens: None,
+            }),
+            cost_usd: Some(0.000225),
+            guardrail: vec![],
         };
 
         let json = serde_json::to_string(&resp).unwrap();
+        assert!(!json.contains("refusal"));
         let de: ChatResponse = serde_json::from_str(&json).unwrap();
         assert_eq!(resp, de);
     }
 
+    #[test]
+    fn tool_call_arguments_parse_or_stay_text() {
+        let call = ToolCall::from_json_text("c".into(), "f".into(), r#"{"city":"Paris"}"#);
+        assert_eq!(call.arguments["city"], "Paris");
+        let bad = ToolCall::from_json_text("c".into(), "f".into(), "{not json");
+        assert_eq!(bad.arguments, serde_json::json!("{not json"));
+    }
+
+    #[test]
+    fn chat_response_accepts_and_emits_flat_usage_fields() {
+        let legacy = serde_json::json!({
+            "model": "gpt-4o", "text": "hi", "usage_prompt": 3, "usage_completion": 5,
+            "cached": false, "provider": "openai", "transcript_id": null, "turn_id": "t",
+            "stop_reason": null, "provider_request_id": null, "created_at_ms": 0, "latency_ms": 1
+        });
+        let resp: ChatResponse = serde_json::from_value(legacy).unwrap();
+        assert_eq!(resp.usage, Usage::new(3, 5));
+        assert_eq!(resp.usage.total(), 8);
+
+        let v = serde_json::to_value(&resp).unwrap();
+        assert_eq!(
+            v["usage"],
+            serde_json::json!({"prompt": 3, "completion": 5})
+        );
+        assert_eq!(v["usage_prompt"], 3);
+        assert_eq!(v["usage_completion"], 5);
+    }
+
     #[test]
     fn embed_request_roundtrip() {
-        let req = EmbedRequest {
-            model: "text-embedding-ada-002".to_string(),
-            inputs: vec!["hello".to_string(), "world".to_string()],
-            client_key: Some("client-1".to_string()),
-        };
+        let req = EmbedRequest::builder()
+            .model("text-embedding-ada-002")
+            .inputs(["hello", "world"])
+            .client_key("client-1")
+            .build();
 
         let json = serde_json::to_string(&req).unwrap();
         let de: EmbedRequest = serde_json::from_str(&json).unwrap();
@@ -153,10 +1269,133 @@ mod tests {
             usage: 123,
             cached: true,
             provider: "openai".to_string(),
+            cost_usd: Some(0.0000123),
         };
 
         let json = serde_json::to_string(&resp).unwrap();
         let de: EmbedResponse = serde_json::from_str(&json).unwrap();
         assert_eq!(resp, de);
     }
+    fn weather() -> ToolSpec {
+        ToolSpec {
+            name: "get_weather".into(),
+            description: Some("Current weather".into()),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {"city": {"type": "string"}},
+                "required": ["city"]
+            }),
+        }
+    }
+
+    #[test]
+    fn tool_spec_defaults_to_an_empty_object_schema() {
+        let t: ToolSpec = serde_json::from_str(r#"{"name":"ping"}"#).unwrap();
+        assert_eq!(t.description, None);
+        assert_eq!(t.parameters["type"], "object");
+        let c: ToolChoice = serde_json::from_str(r#"{"tool":{"name":"ping"}}"#).unwrap();
+        assert_eq!(
+            c,
+            ToolChoice::Tool {
+                name: "ping".into()
+            }
+        );
+        assert_eq!(
+            serde_json::to_string(&ToolChoice::Auto).unwrap(),
+            r#""auto""#
+        );
+    }
+
+    #[test]
+    fn openai_tool_dialect() {
+        let wire = weather().to_wire(ToolDialect::OpenAi);
+        assert_eq!(wire["type"], "function");
+        assert_eq!(wire["function"]["name"], "get_weather");
+        assert_eq!(wire["function"]["parameters"]["required"][0], "city");
+        assert_eq!(
+            ToolSpec::from_wire(wire, ToolDialect::OpenAi).unwrap(),
+            weather()
+        );
+
+        let cases = [
+            (ToolChoice::Auto, serde_json::json!("auto")),
+            (ToolChoice::None, serde_json::json!("none")),
+            (ToolChoice::Required, serde_json::json!("required")),
+            (
+                ToolChoice::Tool {
+                    name: "get_weather".into(),
+                },
+                serde_json::json!({"type": "function", "function": {"name": "get_weather"}}),
+            ),
+        ];
+        for (choice, wire) in cases {
+            assert_eq!(choice.to_wire(ToolDialect::OpenAi), wire);
+            assert_eq!(
+                ToolChoice::from_wire(wire, ToolDialect::OpenAi).unwrap(),
+                choice
+            );
+        }
+        let err = ToolChoice::from_wire(serde_json::json!("sometimes"), ToolDialect::OpenAi);
+        assert!(matches!(err, Err(AiProxyError::Validation(m)) if m.contains("sometimes")));
+    }
+
+    #[test]
+    fn anthropic_tool_dialect() {
+        let wire = weather().to_wire(ToolDialect::Anthropic);
+        assert_eq!(wire["name"], "get_weather");
+        assert_eq!(wire["input_schema"]["properties"]["city"]["type"], "string");
+        assert!(wire.get("parameters").is_none());
+        assert_eq!(
+            ToolSpec::from_wire(wire, ToolDialect::Anthropic).unwrap(),
+            weather()
+        );
+
+        let cases = [
+            (ToolChoice::Auto, serde_json::json!({"type": "auto"})),
+            (ToolChoice::None, serde_json::json!({"type": "none"})),
+            (ToolChoice::Required, serde_json::json!({"type": "any"})),
+            (
+                ToolChoice::Tool {
+                    name: "get_weather".into(),
+                },
+                serde_json::json!({"type": "tool", "name": "get_weather"}),
+            ),
+        ];
+        for (choice, wire) in cases {
+            assert_eq!(choice.to_wire(ToolDialect::Anthropic), wire);
+            assert_eq!(
+                ToolChoice::from_wire(wire, ToolDialect::Anthropic).unwrap(),
+                choice
+            );
+        }
+        assert!(
+            ToolSpec::from_wire(serde_json::json!({"name": "x"}), ToolDialect::Anthropic).is_err()
+        );
+    }
+
+    #[test]
+    fn tool_call_id_is_only_serialized_for_tool_results() {
+        let result = ChatMessage::tool("call_1", "72F");
+        let v = serde_json::to_value(&result).unwrap();
+        assert_eq!(
+            v,
+            serde_json::json!({"role": "tool", "content": "72F", "tool_call_id": "call_1"})
+        );
+        assert_eq!(serde_json::from_value::<ChatMessage>(v).unwrap(), result);
+        let plain = serde_json::to_value(ChatMessage::user("hi")).unwrap();
+        assert!(plain.get("tool_call_id").is_none());
+    }
+
+    #[test]
+    fn name_round_trips_and_is_omitted_when_unset() {
+        let named = ChatMessage::user("hi").with_name("alice");
+        let v = serde_json::to_value(&named).unwrap();
+        assert_eq!(
+            v,
+            serde_json::json!({"role": "user", "content": "hi", "name": "alice"})
+        );
+        assert_eq!(serde_json::from_value::<ChatMessage>(v).unwrap(), named);
+        let plain = serde_json::to_value(ChatMessage::user("hi")).unwrap();
+        assert!(plain.get("name").is_none());
+    }
 }
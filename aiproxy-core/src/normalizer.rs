@@ -1,5 +1,12 @@
-use crate::model::{ChatRequest, EmbedRequest};
-use std::collections::HashSet;
+use crate::catalog;
+use crate::config::{TruncationCfg, ValidationCfg};
+use crate::error::{AiProxyError, CoreResult};
+use crate::model::{
+    ChatRequest, ContentPart, EmbedRequest, MessageContent, Role, TruncationReport,
+};
+use crate::provider::ChatProvider;
+use crate::tokenizer;
+use std::collections::{HashMap, HashSet};
 use unicode_normalization::UnicodeNormalization;
 
 fn clean_text(s: &str) -> String {
@@ -15,6 +22,19 @@ fn clean_text(s: &str) -> String {
     t.trim().to_string()
 }
 
+fn clean_content(content: &mut MessageContent) {
+    match content {
+        MessageContent::Text(s) => *s = clean_text(s),
+        MessageContent::Parts(parts) => {
+            for part in parts {
+                if let ContentPart::Text { text } = part {
+                    *text = clean_text(text);
+                }
+            }
+        }
+    }
+}
+
 fn clamp_round_f32(x: f32, lo: f32, hi: f32, dp: u32) -> f32 {
     let clamped = x.clamp(lo, hi);
     let p = 10f32.powi(dp as i32);
@@ -23,7 +43,15 @@ fn clamp_round_f32(x: f32, lo: f32, hi: f32, dp: u32) -> f32 {
 
 pub fn normalize_chat(mut req: ChatRequest) -> ChatRequest {
     for msg in &mut req.messages {
-        msg.content = clean_text(&msg.content);
+        clean_content(&mut msg.content);
+        if let Some(id) = &mut msg.tool_call_id {
+            *id = id.trim().to_string();
+        }
+        msg.name = msg
+            .name
+            .take()
+            .map(|n| n.trim().to_string())
+            .filter(|n| !n.is_empty());
     }
     // Default and clamp numeric params
     req.temperature = Some(match req.temperature {
@@ -49,6 +77,244 @@ pub fn normalize_chat(mut req: ChatRequest) -> ChatRequest {
     req
 }
 
+/// Structural rules for `validate_chat`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChatRules {
+    /// User and assistant turns must alternate, starting with a user turn.
+    pub alternate_turns: bool,
+    pub max_messages: Option<usize>,
+    /// The model's context window, which the prompt plus `max_output_tokens` must fit.
+    pub context_window: Option<u32>,
+    /// Largest `max_output_tokens` the model accepts.
+    pub output_limit: Option<u32>,
+}
+
+impl ChatRules {
+    /// The rules for sending a request to `provider` under `cfg`.
+    pub fn for_provider(cfg: &ValidationCfg, provider: &dyn ChatProvider) -> Self {
+        Self {
+            alternate_turns: provider.requires_alternation(),
+            max_messages: cfg.max_messages,
+            context_window: None,
+            output_limit: None,
+        }
+    }
+
+    /// The rules for sending `req` to `provider`: `for_provider`'s, plus the model's context
+    /// window (from `truncation.context_windows`, else the catalog) and the catalog's output
+    /// limit.
+    pub fn for_request(
+        cfg: &ValidationCfg,
+        truncation: &TruncationCfg,
+        provider: &dyn ChatProvider,
+        req: &ChatRequest,
+    ) -> Self {
+        Self {
+            context_window: truncation.context_window(&req.model),
+            output_limit: catalog::max_output_tokens(&req.model),
+            ..Self::for_provider(cfg, provider)
+        }
+    }
+
+    pub fn with_context_window(mut self, window: Option<u32>) -> Self {
+        self.context_window = window;
+        self
+    }
+}
+
+fn role_name(role: Role) -> &'static str {
+    match role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::Tool => "tool",
+    }
+}
+
+/// Reject requests a provider would refuse, naming the offending message: no messages, blank
+/// content, too many messages, `max_output_tokens` over the model's limit, a prompt plus
+/// `max_output_tokens` over the context window, and (when `rules.alternate_turns`) turns out
+/// of order. Tool results count as the user side of a
+/// turn: they must follow an assistant turn or another tool result, and the assistant speaks
+/// next. Run after `normalize_chat`, whose trimming is what leaves content blank.
+pub fn validate_chat(req: &ChatRequest, rules: &ChatRules) -> CoreResult<()> {
+    let invalid = |msg: String| Err(AiProxyError::Validation(msg));
+    if req.messages.is_empty() {
+        return invalid("messages must not be empty".into());
+    }
+    if let Some(max) = rules.max_messages
+        && req.messages.len() > max
+    {
+        return invalid(format!(
+            "{} messages exceeds the limit of {max}",
+            req.messages.len()
+        ));
+    }
+    for (i, m) in req.messages.iter().enumerate() {
+        if m.content.text().trim().is_empty() {
+            return invalid(format!(
+                "messages[{i}] ({}) has empty content",
+                role_name(m.role)
+            ));
+        }
+    }
+    if let (Some(limit), Some(requested)) = (rules.output_limit, req.max_output_tokens)
+        && requested > limit
+    {
+        return invalid(format!(
+            "max_output_tokens {requested} exceeds the {limit}-token output limit of {}",
+            req.model
+        ));
+    }
+    if let Some(window) = rules.context_window {
+        let prompt = tokenizer::count_tokens(&req.model, &req.messages) as u64;
+        let output = u64::from(req.max_output_tokens.unwrap_or(0));
+        if prompt + output > u64::from(window) {
+            return invalid(format!(
+                "estimated {prompt} prompt tokens + {output} max_output_tokens = {} exceeds \
+                 the {window}-token context window of {}",
+                prompt + output,
+                req.model
+            ));
+        }
+    }
+    if !rules.alternate_turns {
+        return Ok(());
+    }
+    let mut prev: Option<Role> = None;
+    for (i, m) in req.messages.iter().enumerate() {
+        let ok = match (prev, m.role) {
+            (_, Role::System) => continue,
+            (None, Role::User) => true,
+            (None, _) => {
+                return invalid(format!(
+                    "messages[{i}] ({}): the first non-system message must be from the user",
+                    role_name(m.role)
+                ));
+            }
+            (Some(Role::Assistant), Role::User | Role::Tool) => true,
+            (Some(Role::Tool), Role::Tool) => true,
+            (Some(Role::User | Role::Tool), Role::Assistant) => true,
+            _ => false,
+        };
+        if !ok {
+            return invalid(format!(
+                "messages[{i}] ({}) follows a {} message; user and assistant turns must alternate",
+                role_name(m.role),
+                role_name(prev.unwrap_or(Role::System)),
+            ));
+        }
+        prev = Some(m.role);
+    }
+    if prev.is_none() {
+        return invalid("messages must include at least one user message".into());
+    }
+    Ok(())
+}
+
+/// Fit the prompt into `budget` tokens: drop the oldest non-system messages, then cut the
+/// oldest remaining one down to its most recent text. System messages and the final message
+/// are never dropped, and tool results go with the call they answer. `None` if nothing had
+/// to change.
+pub fn truncate_to_budget(req: &mut ChatRequest, budget: u32) -> Option<TruncationReport> {
+    let model = req.model.clone();
+    let limit = budget as usize;
+    let counts: Vec<usize> = req
+        .messages
+        .iter()
+        .map(|m| tokenizer::count_message(&model, m))
+        .collect();
+    let before: usize = counts.iter().sum();
+    if before <= limit {
+        return None;
+    }
+    let turns: Vec<usize> = (0..req.messages.len())
+        .filter(|&i| req.messages[i].role != Role::System)
+        .collect();
+    let mut total = before;
+    let mut dropped = Vec::new();
+    let last = turns.len().saturating_sub(1);
+    for &i in &turns[..last] {
+        // Dropping is contiguous, so a tool result here answers a call just dropped.
+        let orphaned = req.messages[i].role == Role::Tool && !dropped.is_empty();
+        if total <= limit && !orphaned {
+            break;
+        }
+        total -= counts[i];
+        dropped.push(i);
+    }
+    let mut truncated = None;
+    if total > limit
+        && let Some(&i) = turns.iter().find(|i| !dropped.contains(i))
+    {
+        let others = total - counts[i];
+        let keep_tokens = limit.saturating_sub(others + tokenizer::MESSAGE_OVERHEAD);
+        let text = req.messages[i].content.text().into_owned();
+        let tail = tokenizer::tail_within(&model, &text, keep_tokens).to_string();
+        req.messages[i].content = tail.into();
+        total = others + tokenizer::count_message(&model, &req.messages[i]);
+        truncated = Some(i);
+    }
+    let mut index = 0;
+    req.messages.retain(|_| {
+        index += 1;
+        !dropped.contains(&(index - 1))
+    });
+    Some(TruncationReport {
+        budget,
+        tokens_before: before as u32,
+        tokens_after: total as u32,
+        dropped,
+        truncated,
+        max_output_tokens: None,
+    })
+}
+
+/// `truncate_to_budget` for a prompt measured at `measured` tokens, e.g. exactly by
+/// `tokenizer::count_prompt`. Messages are still sized locally, scaled so they sum to
+/// `measured`, and the report is in measured tokens.
+pub fn truncate_measured(
+    req: &mut ChatRequest,
+    budget: u32,
+    measured: u32,
+) -> Option<TruncationReport> {
+    if measured <= budget {
+        return None;
+    }
+    let estimated = tokenizer::count_tokens(&req.model, &req.messages) as u64;
+    if estimated == 0 {
+        return truncate_to_budget(req, budget);
+    }
+    let rescale = |tokens: u64, from: u64, to: u64| (tokens * to / from) as u32;
+    let mut report = truncate_to_budget(req, rescale(budget.into(), measured.into(), estimated))?;
+    report.budget = budget;
+    report.tokens_before = measured;
+    report.tokens_after = rescale(report.tokens_after.into(), estimated, measured.into());
+    Some(report)
+}
+
+/// Least `max_output_tokens` `fit_output` leaves a request (or what it asked for, if less)
+/// before trimming history instead.
+pub const MIN_FITTED_OUTPUT: u32 = 1024;
+
+/// Lower `req.max_output_tokens` for `truncation.auto_fit`: to the catalog's output limit
+/// for the model, then to what its context window leaves a `prompt`-token prompt, but no
+/// lower than `MIN_FITTED_OUTPUT` or half the window. A prompt still too long is left for
+/// truncation to trim. Returns the value asked for if it changed.
+pub fn fit_output(req: &mut ChatRequest, truncation: &TruncationCfg, prompt: u32) -> Option<u32> {
+    let requested = req.max_output_tokens?;
+    let mut fitted = catalog::max_output_tokens(&req.model).map_or(requested, |l| requested.min(l));
+    if let Some(window) = truncation.context_window(&req.model) {
+        let floor = fitted.min(MIN_FITTED_OUTPUT).min(window / 2);
+        fitted = fitted.min(window.saturating_sub(prompt).max(floor));
+    }
+    if fitted == requested {
+        return None;
+    }
+    req.max_output_tokens = Some(fitted);
+    Some(requested)
+}
+
 pub fn normalize_embed(mut req: EmbedRequest) -> EmbedRequest {
     req.inputs = req
         .inputs
@@ -61,37 +327,50 @@ pub fn normalize_embed(mut req: EmbedRequest) -> EmbedRequest {
     req
 }
 
+/// Like `normalize_embed`, but also returns, for every original input, the index of its
+/// cleaned counterpart in the deduplicated request (`None` if the input was empty after cleaning).
+/// Callers that must answer with one vector per original input use this to fan results back out.
+pub fn normalize_embed_indexed(mut req: EmbedRequest) -> (EmbedRequest, Vec<Option<usize>>) {
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut unique = Vec::new();
+    let mut index = Vec::with_capacity(req.inputs.len());
+    for raw in std::mem::take(&mut req.inputs) {
+        let cleaned = clean_text(&raw);
+        if cleaned.is_empty() {
+            index.push(None);
+            continue;
+        }
+        let pos = *positions.entry(cleaned.clone()).or_insert_with(|| {
+            unique.push(cleaned);
+            unique.len() - 1
+        });
+        index.push(Some(pos));
+    }
+    req.inputs = unique;
+    (req, index)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::model::{ChatMessage, Role};
 
     fn mk_chat_req(msgs: Vec<(&'static str, &'static str)>) -> ChatRequest {
-        ChatRequest {
-            model: "gpt-4o".to_string(),
-            messages: msgs
-                .into_iter()
-                .map(|(role, content)| ChatMessage {
-                    role: match role {
-                        "user" => Role::User,
-                        "assistant" => Role::Assistant,
-                        "system" => Role::System,
-                        "tool" => Role::Tool,
-                        _ => Role::User,
-                    },
-                    content: content.to_string(),
-                })
-                .collect(),
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        }
+        ChatRequest::builder()
+            .model("gpt-4o")
+            .messages(msgs.into_iter().map(|(role, content)| ChatMessage {
+                role: match role {
+                    "user" => Role::User,
+                    "assistant" => Role::Assistant,
+                    "system" => Role::System,
+                    "tool" => Role::Tool,
+                    _ => Role::User,
+                },
+                content: content.into(),
+                tool_call_id: None,
+                name: None,
+            }))
+            .build()
     }
 
     #[test]
@@ -136,11 +415,10 @@ mod tests {
 
     #[test]
     fn normalize_embed_trims_and_drops_empty() {
-        let req = EmbedRequest {
-            model: "text-embedding-3-small".to_string(),
-            inputs: vec!["  one  ".into(), "".into(), " two".into(), "three ".into()],
-            client_key: None,
-        };
+        let req = EmbedRequest::builder()
+            .model("text-embedding-3-small")
+            .inputs(["  one  ", "", " two", "three "])
+            .build();
         let out = normalize_embed(req);
         assert_eq!(out.inputs, vec!["one", "two", "three"]);
     }
@@ -160,21 +438,25 @@ mod tests {
 
     #[test]
     fn dedup_embedding_inputs_after_clean() {
-        let req = EmbedRequest {
-            model: "text-embedding-3-small".to_string(),
-            inputs: vec![
-                " a ".into(),
-                "a".into(),
-                "a".into(),
-                "b".into(),
-                " b".into(),
-            ],
-            client_key: None,
-        };
+        let req = EmbedRequest::builder()
+            .model("text-embedding-3-small")
+            .inputs([" a ", "a", "a", "b", " b"])
+            .build();
         let out = normalize_embed(req);
         assert_eq!(out.inputs, vec!["a", "b"]);
     }
 
+    #[test]
+    fn normalize_embed_indexed_maps_originals_to_unique_inputs() {
+        let req = EmbedRequest::builder()
+            .model("text-embedding-3-small")
+            .inputs([" a ", "b", "  ", "a"])
+            .build();
+        let (out, index) = normalize_embed_indexed(req);
+        assert_eq!(out.inputs, vec!["a", "b"]);
+        assert_eq!(index, vec![Some(0), Some(1), None, Some(0)]);
+    }
+
     #[test]
     fn clamp_and_round_floats() {
         let mut req = mk_chat_req(vec![("user", "go")]);
@@ -184,4 +466,209 @@ mod tests {
         assert_eq!(out.temperature, Some(2.0));
         assert_eq!(out.top_p, Some(1.0));
     }
+
+    #[test]
+    fn keeps_tool_results_and_their_call_ids() {
+        let mut req = mk_chat_req(vec![("user", "weather?")]);
+        req.messages.push(ChatMessage::tool(" call_1 ", " 72F\r\n"));
+        let out = normalize_chat(req);
+        assert_eq!(out.messages[1].role, Role::Tool);
+        assert_eq!(out.messages[1].tool_call_id.as_deref(), Some("call_1"));
+        assert_eq!(out.messages[1].content, "72F");
+    }
+
+    #[test]
+    fn trims_names_and_drops_blank_ones() {
+        let mut req = mk_chat_req(vec![("user", "hi"), ("user", "yo")]);
+        req.messages[0].name = Some(" alice ".into());
+        req.messages[1].name = Some("  ".into());
+        let out = normalize_chat(req);
+        assert_eq!(out.messages[0].name.as_deref(), Some("alice"));
+        assert_eq!(out.messages[1].name, None);
+    }
+
+    #[test]
+    fn truncation_drops_oldest_turns_and_keeps_system() {
+        // Each message is 4 framing tokens + 10 text tokens.
+        let text = "x".repeat(40);
+        let mut req = ChatRequest::builder()
+            .model("m")
+            .system(text.as_str())
+            .user(text.as_str())
+            .assistant(text.as_str())
+            .user(text.as_str())
+            .build();
+        assert_eq!(truncate_to_budget(&mut req, 56), None);
+        let report = truncate_to_budget(&mut req, 30).unwrap();
+        assert_eq!(report.dropped, vec![1, 2]);
+        assert_eq!(report.truncated, None);
+        assert_eq!((report.tokens_before, report.tokens_after), (56, 28));
+        let roles: Vec<Role> = req.messages.iter().map(|m| m.role).collect();
+        assert_eq!(roles, vec![Role::System, Role::User]);
+    }
+
+    #[test]
+    fn truncation_takes_tool_results_with_their_call() {
+        let text = "x".repeat(40);
+        let mut req = ChatRequest::builder()
+            .model("m")
+            .assistant(text.as_str())
+            .tool("call_1", "72F")
+            .user("thanks")
+            .build();
+        let report = truncate_to_budget(&mut req, 20).unwrap();
+        assert_eq!(report.dropped, vec![0, 1]);
+        assert_eq!(req.messages.len(), 1);
+    }
+
+    #[test]
+    fn truncation_cuts_the_latest_message_to_its_tail() {
+        let mut req = ChatRequest::builder()
+            .model("m")
+            .user(format!("{}END", "a".repeat(97)))
+            .build();
+        let report = truncate_to_budget(&mut req, 5).unwrap();
+        assert_eq!(report.truncated, Some(0));
+        assert_eq!(req.messages[0].content, "aEND");
+        assert_eq!(report.tokens_after, 5);
+    }
+
+    #[test]
+    fn measured_truncation_scales_to_the_exact_count() {
+        // Estimated at 14 tokens per message, counted at twice that.
+        let text = "x".repeat(40);
+        let mut req = ChatRequest::builder()
+            .model("m")
+            .user(text.as_str())
+            .assistant(text.as_str())
+            .user(text.as_str())
+            .build();
+        assert_eq!(truncate_measured(&mut req, 84, 84), None);
+        let report = truncate_measured(&mut req, 60, 84).unwrap();
+        assert_eq!(report.budget, 60);
+        assert_eq!(report.dropped, vec![0]);
+        assert_eq!((report.tokens_before, report.tokens_after), (84, 56));
+        assert_eq!(req.messages.len(), 2);
+    }
+
+    #[test]
+    fn fit_output_lowers_max_output_tokens_to_the_window() {
+        let mut truncation = TruncationCfg::default();
+        truncation.context_windows.insert("m".into(), 10_000);
+        let fit = |max: u32, prompt: u32| {
+            let mut req = ChatRequest::builder()
+                .model("m")
+                .user("hi")
+                .max_output_tokens(max)
+                .build();
+            let requested = fit_output(&mut req, &truncation, prompt);
+            (requested, req.max_output_tokens.unwrap())
+        };
+        assert_eq!(fit(2_000, 1_000), (None, 2_000));
+        assert_eq!(fit(9_500, 1_000), (Some(9_500), 9_000));
+        // Past the floor the prompt is left for truncation.
+        assert_eq!(fit(4_000, 9_800), (Some(4_000), MIN_FITTED_OUTPUT));
+        assert_eq!(fit(500, 9_800), (None, 500));
+
+        // The catalog's output limit applies whatever the window.
+        let mut req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("hi")
+            .max_output_tokens(50_000)
+            .build();
+        let limit = catalog::max_output_tokens("gpt-4o").unwrap();
+        assert_eq!(fit_output(&mut req, &truncation, 10), Some(50_000));
+        assert_eq!(req.max_output_tokens, Some(limit));
+    }
+
+    #[test]
+    fn validation_reports_empty_and_oversized_requests() {
+        let rules = ChatRules::default();
+        let err = validate_chat(&mk_chat_req(vec![]), &rules).unwrap_err();
+        assert!(err.to_string().contains("must not be empty"));
+
+        let req = normalize_chat(mk_chat_req(vec![("user", "hi"), ("assistant", "  \r\n")]));
+        let err = validate_chat(&req, &rules).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("messages[1] (assistant) has empty content")
+        );
+
+        let capped = ChatRules {
+            max_messages: Some(1),
+            ..rules
+        };
+        let err = validate_chat(&req, &capped).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("2 messages exceeds the limit of 1")
+        );
+    }
+
+    #[test]
+    fn validation_checks_model_limits() {
+        let mut req = mk_chat_req(vec![("user", "abcdefgh")]);
+        req.model = "m".into();
+        req.max_output_tokens = Some(10);
+        // 2 text tokens + MESSAGE_OVERHEAD framing, plus the output budget.
+        let fits = ChatRules::default().with_context_window(Some(16));
+        validate_chat(&req, &fits).unwrap();
+        let err = validate_chat(&req, &fits.with_context_window(Some(15))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "validation failed: estimated 6 prompt tokens + 10 max_output_tokens = 16 exceeds \
+             the 15-token context window of m"
+        );
+
+        let mut req = mk_chat_req(vec![("user", "hi")]);
+        req.max_output_tokens = Some(20_000);
+        let rules = ChatRules::for_request(
+            &ValidationCfg::default(),
+            &TruncationCfg::default(),
+            &crate::provider::NullProvider,
+            &req,
+        );
+        assert_eq!(rules.context_window, Some(128_000));
+        let err = validate_chat(&req, &rules).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("max_output_tokens 20000 exceeds the 16384-token output limit of gpt-4o")
+        );
+    }
+
+    #[test]
+    fn alternation_rules_allow_tool_rounds() {
+        let strict = ChatRules {
+            alternate_turns: true,
+            ..ChatRules::default()
+        };
+        let mut ok = mk_chat_req(vec![
+            ("system", "s"),
+            ("user", "q"),
+            ("assistant", "calling"),
+        ]);
+        ok.messages.push(ChatMessage::tool("c1", "r1"));
+        ok.messages.push(ChatMessage::tool("c2", "r2"));
+        ok.messages.push(ChatMessage::assistant("done"));
+        validate_chat(&ok, &strict).unwrap();
+        ok.messages.insert(5, ChatMessage::user("and?"));
+        let err = validate_chat(&ok, &strict).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("messages[5] (user) follows a tool message")
+        );
+
+        let twice = mk_chat_req(vec![("user", "a"), ("user", "b")]);
+        let err = validate_chat(&twice, &strict).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("messages[1] (user) follows a user message")
+        );
+        validate_chat(&twice, &ChatRules::default()).unwrap();
+
+        let err = validate_chat(&mk_chat_req(vec![("assistant", "hi")]), &strict).unwrap_err();
+        assert!(err.to_string().contains("must be from the user"));
+        let err = validate_chat(&mk_chat_req(vec![("system", "s")]), &strict).unwrap_err();
+        assert!(err.to_string().contains("at least one user message"));
+    }
 }
@@ -0,0 +1,104 @@
+//! What differs between native and `wasm32` builds, behind one interface.
+//!
+//! `wasm32-unknown-unknown` runs on one thread, its `fetch` futures are not `Send`,
+//! `std::time::Instant::now` panics there and there is no Tokio timer. So on wasm32
+//! `MaybeSend` asks for nothing, the clock types come from `web-time` (the host's
+//! `performance.now()` and `Date.now()`) and `Sleep` waits on `setTimeout`. Native builds get
+//! `Send`, `std::time` and Tokio's timer and `Instant`, which follows a paused test clock.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+pub use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(not(target_arch = "wasm32"))]
+pub use tokio::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub use web_time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// `Send` on native targets, where futures and streams may move between runtime threads.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSend: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// Nothing on wasm32, where everything runs on one thread.
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// Wait for `duration`.
+pub async fn sleep(duration: Duration) {
+    Sleep::until(Instant::now() + duration).await
+}
+
+/// Run `fut` until `at`; `None` when `at` passes first.
+pub async fn timeout_at<F: Future>(at: Instant, fut: F) -> Option<F::Output> {
+    use futures::future::{Either, select};
+    match select(std::pin::pin!(fut), Sleep::until(at)).await {
+        Either::Left((out, _)) => Some(out),
+        Either::Right(_) => None,
+    }
+}
+
+/// A timer firing at a deadline, which `reset` can move.
+#[derive(Debug)]
+pub struct Sleep {
+    #[cfg(not(target_arch = "wasm32"))]
+    inner: Pin<Box<tokio::time::Sleep>>,
+    #[cfg(target_arch = "wasm32")]
+    inner: gloo_timers::future::TimeoutFuture,
+}
+
+impl Sleep {
+    pub fn until(at: Instant) -> Self {
+        #[cfg(not(target_arch = "wasm32"))]
+        let inner = Box::pin(tokio::time::sleep_until(at));
+        #[cfg(target_arch = "wasm32")]
+        let inner = timeout_future(at);
+        Self { inner }
+    }
+
+    pub fn reset(&mut self, at: Instant) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.inner.as_mut().reset(at);
+        #[cfg(target_arch = "wasm32")]
+        {
+            self.inner = timeout_future(at);
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn timeout_future(at: Instant) -> gloo_timers::future::TimeoutFuture {
+    let ms = at.saturating_duration_since(Instant::now()).as_millis();
+    gloo_timers::future::TimeoutFuture::new(u32::try_from(ms).unwrap_or(u32::MAX))
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.inner.as_mut().poll(cx);
+        #[cfg(target_arch = "wasm32")]
+        return Pin::new(&mut self.inner).poll(cx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn timeouts_end_at_the_deadline() {
+        let soon = Instant::now() + Duration::from_millis(20);
+        assert_eq!(timeout_at(soon, async { 1 }).await, Some(1));
+        let never = std::future::pending::<()>();
+        assert_eq!(timeout_at(soon, never).await, None);
+        assert!(Instant::now() >= soon);
+    }
+}
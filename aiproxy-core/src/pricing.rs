@@ -0,0 +1,113 @@
+//! Per-provider model prices, in USD per 1K prompt and completion tokens, and the cost
+//! estimates adapters put on `ChatResponse`, `EmbedResponse` and `CompletionLog`.
+//!
+//! `[pricing.<provider>]` sets the price a provider bills for a model id or id prefix; the
+//! longest matching prefix wins. Models a provider has no entry for are billed at the model
+//! catalog's list price (`crate::catalog`), so configured prices are only needed where a
+//! provider's rates differ, e.g. a reseller's margin or a negotiated discount. See
+//! `set_overrides`.
+
+use std::collections::BTreeMap;
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+/// USD per 1K prompt / completion tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub prompt_per_1k: f64,
+    pub completion_per_1k: f64,
+}
+
+impl ModelPrice {
+    /// USD for a call that used `prompt` and `completion` tokens.
+    pub fn cost_usd(&self, prompt: u32, completion: u32) -> f64 {
+        (f64::from(prompt) * self.prompt_per_1k + f64::from(completion) * self.completion_per_1k)
+            / 1_000.0
+    }
+}
+
+/// Prices by provider name, then model id or id prefix.
+pub type PriceTable = BTreeMap<String, BTreeMap<String, ModelPrice>>;
+
+static OVERRIDES: OnceCell<PriceTable> = OnceCell::new();
+
+/// Install the config's `[pricing]` table for the whole process. Only the first call takes
+/// effect; it returns false when a table was already installed.
+pub fn set_overrides(table: PriceTable) -> bool {
+    OVERRIDES.set(table).is_ok()
+}
+
+/// The price `provider` bills for `model` under `table`: the provider's entry with the
+/// longest matching prefix, else the catalog's list price.
+pub fn lookup(table: &PriceTable, provider: &str, model: &str) -> Option<ModelPrice> {
+    let configured = table.get(provider).and_then(|models| {
+        models
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| *price)
+    });
+    configured.or_else(|| {
+        crate::catalog::price_for(model).map(|p| ModelPrice {
+            prompt_per_1k: p.prompt_per_mtok / 1_000.0,
+            completion_per_1k: p.completion_per_mtok / 1_000.0,
+        })
+    })
+}
+
+/// As `lookup`, with the installed table.
+pub fn price(provider: &str, model: &str) -> Option<ModelPrice> {
+    match OVERRIDES.get() {
+        Some(table) => lookup(table, provider, model),
+        None => lookup(&PriceTable::new(), provider, model),
+    }
+}
+
+/// Estimated USD cost of a call `provider` served for `model`; `None` when no price is known.
+pub fn cost_usd(provider: &str, model: &str, prompt: u32, completion: u32) -> Option<f64> {
+    price(provider, model).map(|p| p.cost_usd(prompt, completion))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table() -> PriceTable {
+        let price = |prompt_per_1k, completion_per_1k| ModelPrice {
+            prompt_per_1k,
+            completion_per_1k,
+        };
+        BTreeMap::from([(
+            "openrouter".to_string(),
+            BTreeMap::from([
+                ("openai/".to_string(), price(0.01, 0.02)),
+                ("openai/gpt-4o".to_string(), price(0.003, 0.012)),
+            ]),
+        )])
+    }
+
+    #[test]
+    fn provider_prices_take_the_longest_prefix_over_the_catalog() {
+        let t = table();
+        let p = lookup(&t, "openrouter", "openai/gpt-4o-2024-08-06").unwrap();
+        assert_eq!(p.prompt_per_1k, 0.003);
+        assert_eq!(
+            lookup(&t, "openrouter", "openai/o3").unwrap().prompt_per_1k,
+            0.01
+        );
+        // Other providers, and models without an entry, bill the catalog's list price.
+        let list = lookup(&t, "openai", "gpt-4o").unwrap();
+        assert_eq!((list.prompt_per_1k, list.completion_per_1k), (0.0025, 0.01));
+        assert!(lookup(&t, "openrouter", "mistral/unknown").is_none());
+    }
+
+    #[test]
+    fn cost_is_per_thousand_tokens() {
+        let p = ModelPrice {
+            prompt_per_1k: 0.0025,
+            completion_per_1k: 0.01,
+        };
+        assert!((p.cost_usd(1_000, 500) - 0.0075).abs() < 1e-12);
+    }
+}
@@ -1,12 +1,15 @@
 use async_trait::async_trait;
 
 use crate::error::CoreResult;
-use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::model::{
+    ChatChoice, ChatRequest, ChatResponse, EmbedRequest, EmbedResponse, ModelInfo, Usage,
+};
 use crate::stream::{BoxStreamEv, StreamEvent};
 
 /// Capability marker for providers.
 /// Used to advertise what verbs a provider supports.
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Capability {
     Chat,
     ChatStream,
@@ -14,9 +17,14 @@ pub enum Capability {
     Transcribe,
     Moderate,
     Rerank,
+    ListModels,
+    Tools,
+    /// Bidirectional audio/text sessions (`realtime::RealtimeProvider`).
+    Realtime,
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait ChatProvider: Send + Sync + std::fmt::Debug {
     fn name(&self) -> &str;
     async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse>;
@@ -28,18 +36,63 @@ pub trait ChatProvider: Send + Sync + std::fmt::Debug {
     }
 
     /// Unified streaming API: returns a stream of `StreamEvent`s.
-    /// Default: produce a single `Final` event by calling `chat()` once.
+    /// Default: call `chat()` once and emit its tool calls, then a single `Final` event.
     async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
         let resp = self.chat(req).await?;
-        let s = futures::stream::iter(vec![StreamEvent::Final(resp)]);
-        Ok(Box::pin(s))
+        let mut events: Vec<StreamEvent> = resp
+            .tool_calls
+            .iter()
+            .cloned()
+            .map(StreamEvent::ToolCall)
+            .collect();
+        events.push(StreamEvent::Final(resp));
+        Ok(Box::pin(futures::stream::iter(events)))
+    }
+
+    /// Whether user and assistant turns must strictly alternate, starting with a user turn.
+    /// Checked before dispatch by `normalizer::validate_chat`.
+    fn requires_alternation(&self) -> bool {
+        false
+    }
+
+    /// Whether `ChatRequest.response_schema` is enforced natively (structured outputs).
+    /// When it is not, `structured::chat_typed` describes the schema in the prompt instead.
+    fn supports_response_schema(&self) -> bool {
+        false
+    }
+
+    /// Exact prompt tokens for `req`, from the provider's own counting endpoint. `None` when
+    /// the provider has none; callers fall back to `tokenizer::count_tokens`.
+    async fn count_tokens(&self, _req: &ChatRequest) -> CoreResult<Option<u32>> {
+        Ok(None)
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 pub trait EmbedProvider: Send + Sync + std::fmt::Debug {
     fn name(&self) -> &str;
     async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse>;
+    /// Most inputs accepted in a single `embed` call. Default: OpenAI's documented limit.
+    fn max_batch_inputs(&self) -> usize {
+        2048
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait ModelsProvider: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &str;
+    /// Models the provider currently offers, as reported by its model-list endpoint.
+    async fn list_models(&self) -> CoreResult<Vec<ModelInfo>>;
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait ModerationProvider: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &str;
+    /// Categories the provider's moderation endpoint flags `text` for; empty when clean.
+    async fn moderate(&self, text: &str) -> CoreResult<Vec<String>>;
 }
 
 /// Providers can expose their supported capabilities
@@ -52,18 +105,25 @@ pub trait ProviderCaps {
 #[derive(Debug)]
 pub struct NullProvider;
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl ChatProvider for NullProvider {
     fn name(&self) -> &str {
         "null"
     }
 
     async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let text = "[null provider response]".to_string();
         Ok(ChatResponse {
             model: req.model,
-            text: "[null provider response]".into(),
-            usage_prompt: req.messages.iter().map(|m| m.content.len() as u32).sum(),
-            usage_completion: 0,
+            text: text.clone(),
+            usage: Usage::new(
+                req.messages
+                    .iter()
+                    .map(|m| m.content.text().len() as u32)
+                    .sum(),
+                0,
+            ),
             cached: false,
             provider: "null".into(),
             transcript_id: None,
@@ -72,11 +132,23 @@ impl ChatProvider for NullProvider {
             provider_request_id: None,
             created_at_ms: 0,
             latency_ms: 0,
+            raw: None,
+            choices: vec![ChatChoice {
+                text,
+                ..ChatChoice::default()
+            }],
+            tool_calls: vec![],
+            refusal: None,
+            annotations: vec![],
+            truncation: None,
+            cost_usd: None,
+            guardrail: vec![],
         })
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl EmbedProvider for NullProvider {
     fn name(&self) -> &str {
         "null"
@@ -89,6 +161,7 @@ impl EmbedProvider for NullProvider {
             usage: req.inputs.len() as u32,
             cached: false,
             provider: "null".into(),
+            cost_usd: None,
         })
     }
 }
@@ -102,42 +175,30 @@ impl ProviderCaps for NullProvider {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::model::{ChatMessage, Role};
     use futures_util::StreamExt;
 
     #[tokio::test]
     async fn null_provider_chat() {
         let prov = NullProvider;
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "hi".into(),
-            }],
-            temperature: Some(1.0),
-            top_p: Some(1.0),
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("hi")
+            .temperature(1.0)
+            .top_p(1.0)
+            .build();
         let resp = prov.chat(req).await.expect("chat ok");
         assert_eq!(resp.provider, "null");
         assert_eq!(resp.text, "[null provider response]");
-        assert_eq!(resp.usage_prompt, 2); // "hi" length
+        assert_eq!(resp.usage.prompt, 2); // "hi" length
     }
 
     #[tokio::test]
     async fn null_provider_embed() {
         let prov = NullProvider;
-        let req = EmbedRequest {
-            model: "text-embedding-3-small".into(),
-            inputs: vec!["a".into(), "b".into()],
-            client_key: None,
-        };
+        let req = EmbedRequest::builder()
+            .model("text-embedding-3-small")
+            .inputs(["a", "b"])
+            .build();
         let resp = prov.embed(req).await.expect("embed ok");
         assert_eq!(resp.provider, "null");
         assert_eq!(resp.vectors.len(), 2);
@@ -147,19 +208,7 @@ mod tests {
     #[tokio::test]
     async fn default_stream_events_emits_final() {
         let prov = NullProvider;
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage { role: Role::User, content: "hi".into() }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("hi").build();
         let stream = prov.chat_stream_events(req).await.expect("stream ok");
         let evs: Vec<_> = stream.collect().await;
         assert_eq!(evs.len(), 1);
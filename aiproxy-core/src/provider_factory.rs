@@ -1,12 +1,34 @@
 use secrecy::ExposeSecret;
 use secrecy::SecretString;
+use serde::Serialize;
 use std::{collections::HashMap, sync::Arc};
 
+use crate::catalog;
 use crate::config::{Config, Providers};
-use crate::error::CoreResult;
-use crate::provider::{Capability, ChatProvider, EmbedProvider, NullProvider, ProviderCaps};
+use crate::error::{AiProxyError, CoreResult};
+use crate::middleware::{DispatchMiddleware, MiddlewareProvider};
+use crate::model::ModelInfo;
+use crate::provider::{
+    Capability, ChatProvider, EmbedProvider, ModelsProvider, ModerationProvider, NullProvider,
+    ProviderCaps,
+};
+use crate::providers::anthropic::Anthropic;
+#[cfg(feature = "native")]
+use crate::providers::caching::CachingProvider;
+use crate::providers::chaos::ChaosProvider;
+use crate::providers::chunking::ChunkingEmbedProvider;
+use crate::providers::circuit::{CircuitBreaker, CircuitBreakerProvider};
+#[cfg(feature = "native")]
+use crate::providers::dedup::DedupProvider;
+use crate::providers::hash::HashEmbedProvider;
+use crate::providers::metered::{MeteredProvider, ProviderStats, UsageStats};
+use crate::providers::ollama::Ollama;
 use crate::providers::openai::OpenAI;
 use crate::providers::openrouter::OpenRouter as OrAdapter;
+use crate::providers::recording::RecordingProvider;
+#[cfg(feature = "native")]
+use crate::providers::replay::ReplayProvider;
+use crate::realtime::RealtimeProvider;
 
 fn redact_tail(s: &str) -> String {
     let tail: String = s
@@ -25,6 +47,9 @@ fn looks_like_openai_key(s: &str) -> bool {
 fn looks_like_openrouter_key(s: &str) -> bool {
     s.starts_with("sk-or-") && s.len() >= 20
 }
+fn looks_like_anthropic_key(s: &str) -> bool {
+    s.starts_with("sk-ant-") && s.len() >= 20
+}
 fn is_openai_project_key(s: &str) -> bool {
     s.starts_with("sk-proj-")
 }
@@ -49,11 +74,34 @@ fn validate_openrouter_key(s: &str) -> crate::error::CoreResult<SecretString> {
     Ok(SecretString::new(s.into()))
 }
 
+fn validate_anthropic_key(s: &str) -> crate::error::CoreResult<SecretString> {
+    if !looks_like_anthropic_key(s) {
+        return Err(crate::error::AiProxyError::Validation(format!(
+            "ANTHROPIC_API_KEY looks invalid: {}",
+            redact_tail(s)
+        )));
+    }
+    Ok(SecretString::new(s.into()))
+}
+
+/// Check the shape of a provider API key without registering anything.
+pub(crate) fn check_key_format(provider: &str, raw: &str) -> CoreResult<()> {
+    match provider {
+        "openai" => validate_openai_key(raw).map(drop),
+        "anthropic" => validate_anthropic_key(raw).map(drop),
+        "openrouter" => validate_openrouter_key(raw).map(drop),
+        _ => Ok(()),
+    }
+}
+
 fn is_provider_referenced(cfg: &Config, name: &str) -> bool {
     if cfg.routing.default == name {
         return true;
     }
-    cfg.routing.rules.iter().any(|r| r.provider == name)
+    cfg.routing
+        .rules
+        .iter()
+        .any(|r| r.targets().any(|t| t == name))
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -69,15 +117,51 @@ pub enum ProviderKind {
 pub struct ProviderRegistry {
     chat: HashMap<String, Arc<dyn ChatProvider>>, // name -> chat provider
     embed: HashMap<String, Arc<dyn EmbedProvider>>, // name -> embed provider
+    models: HashMap<String, Arc<dyn ModelsProvider>>, // name -> model lister
+    moderation: HashMap<String, Arc<dyn ModerationProvider>>, // name -> moderation endpoint
+    realtime: HashMap<String, Arc<dyn RealtimeProvider>>, // name -> realtime sessions
     caps: HashMap<String, &'static [Capability]>, // name -> capabilities
+    stats: Arc<UsageStats>,
+    #[cfg(feature = "native")]
+    cache: Option<Arc<crate::cache::ResponseCache>>,
 }
 
 impl ProviderRegistry {
     /// Build a registry from configuration. For now, we always register a `null` provider.
     /// Future: register real providers when adapters land and API keys are present.
     pub fn from_config(cfg: &Config) -> CoreResult<Self> {
+        Self::from_config_with_keys(cfg, |provider| {
+            std::env::var(format!("{}_API_KEY", provider.to_ascii_uppercase())).ok()
+        })
+    }
+
+    /// As `from_config`, but provider API keys come from `api_key(provider)` rather than
+    /// the `<NAME>_API_KEY` variables, e.g. a tenant's own credentials.
+    pub fn from_config_with_keys(
+        cfg: &Config,
+        api_key: impl Fn(&str) -> Option<String>,
+    ) -> CoreResult<Self> {
+        // The response cache is SQLite, which only native builds carry.
+        #[cfg(not(feature = "native"))]
+        if cfg.cache.ttl_seconds > 0 {
+            return Err(crate::error::AiProxyError::Validation(
+                "cache.ttl_seconds: the response cache needs the `native` feature; set it to 0"
+                    .to_string(),
+            ));
+        }
+        // So do replay's fixture files.
+        #[cfg(not(feature = "native"))]
+        if cfg.providers.replay.is_some() {
+            return Err(crate::error::AiProxyError::Validation(
+                "providers.replay: fixture replay needs the `native` feature".to_string(),
+            ));
+        }
         let mut chat: HashMap<String, Arc<dyn ChatProvider>> = HashMap::new();
         let mut embed: HashMap<String, Arc<dyn EmbedProvider>> = HashMap::new();
+        let mut models: HashMap<String, Arc<dyn ModelsProvider>> = HashMap::new();
+        let mut moderation: HashMap<String, Arc<dyn ModerationProvider>> = HashMap::new();
+        #[cfg_attr(not(feature = "native"), allow(unused_mut))]
+        let mut realtime: HashMap<String, Arc<dyn RealtimeProvider>> = HashMap::new();
         let mut caps: HashMap<String, &'static [Capability]> = HashMap::new();
 
         // Always provide a fallback null provider
@@ -86,8 +170,21 @@ impl ProviderRegistry {
         embed.insert("null".into(), null.clone());
         caps.insert("null".into(), null.capabilities());
 
+        // Every adapter shares one cassette when VCR record/replay is on, and one retry budget;
+        // timeouts and pool size are `[http]`'s with the provider's own overrides.
+        let vcr = crate::vcr::Cassette::from_cfg(&cfg.http.vcr)?;
+        let budget = Arc::new(crate::retry::RetryBudget::default());
+        let new_http = |provider: &str| -> CoreResult<crate::http_client::HttpClient> {
+            let http = crate::http_client::HttpClient::from_cfg(&cfg.http_cfg(provider))?
+                .with_retry(cfg.http.retry.clone(), budget.clone());
+            Ok(match &vcr {
+                Some(cassette) => http.with_vcr(cassette.clone()),
+                None => http,
+            })
+        };
+
         // --- OpenAI registration (enabled if OPENAI_API_KEY is present) ---
-        if let Ok(api_key_raw) = std::env::var("OPENAI_API_KEY") {
+        if let Some(api_key_raw) = api_key("openai") {
             let api_key = validate_openai_key(&api_key_raw)?;
             let base = std::env::var("OPENAI_BASE")
                 .unwrap_or_else(|_| "https://api.openai.com".to_string());
@@ -102,25 +199,146 @@ impl ProviderRegistry {
                     // OpenAI skipped: project key without OPENAI_PROJECT, and not referenced by routing
                 }
             } else {
-                let http = crate::http_client::HttpClient::new_default()?;
-                let openai = Arc::new(OpenAI::new(http, api_key, base, org, project));
+                let http = new_http("openai")?;
+                let openai = Arc::new(
+                    OpenAI::new(http, api_key, base, org, project)
+                        .with_forward_metadata(cfg.privacy.forward_metadata)
+                        .with_extra_params(cfg.extra_params("openai"))
+                        .with_billing_overrides(cfg.billing_overrides("openai")),
+                );
 
                 chat.insert("openai".to_string(), openai.clone());
                 embed.insert("openai".to_string(), openai.clone());
+                models.insert("openai".to_string(), openai.clone());
+                moderation.insert("openai".to_string(), openai.clone());
+                #[cfg(feature = "native")]
+                realtime.insert("openai".to_string(), openai.clone());
                 caps.insert("openai".to_string(), openai.capabilities());
             }
         }
         // --- OpenRouter registration (enabled if OPENAI_API_KEY is present)---
-        if let Ok(api_key_raw) = std::env::var("OPENROUTER_API_KEY") {
+        if let Some(api_key_raw) = api_key("openrouter") {
             let api_key = validate_openrouter_key(&api_key_raw)?;
             let base = std::env::var("OPENROUTER_BASE")
                 .unwrap_or_else(|_| "https://openrouter.ai/api".to_string());
-            let http = crate::http_client::HttpClient::new_default()?;
-            let orp = Arc::new(OrAdapter::new(http, api_key, base));
+            let http = new_http("openrouter")?;
+            let orp = Arc::new(
+                OrAdapter::new(http, api_key, base)
+                    .with_forward_metadata(cfg.privacy.forward_metadata)
+                    .with_extra_params(cfg.extra_params("openrouter")),
+            );
             chat.insert("openrouter".to_string(), orp.clone());
             embed.insert("openrouter".to_string(), orp.clone());
+            models.insert("openrouter".to_string(), orp.clone());
             caps.insert("openrouter".to_string(), orp.capabilities());
         }
+        // --- Anthropic registration (enabled if ANTHROPIC_API_KEY is present; chat only) ---
+        if let Some(api_key_raw) = api_key("anthropic") {
+            let api_key = validate_anthropic_key(&api_key_raw)?;
+            let base = std::env::var("ANTHROPIC_BASE")
+                .unwrap_or_else(|_| "https://api.anthropic.com".to_string());
+            let http = new_http("anthropic")?;
+            let anthropic = Arc::new(
+                Anthropic::new(http, api_key, base)
+                    .with_forward_metadata(cfg.privacy.forward_metadata)
+                    .with_extra_params(cfg.extra_params("anthropic")),
+            );
+            chat.insert("anthropic".to_string(), anthropic.clone());
+            models.insert("anthropic".to_string(), anthropic.clone());
+            caps.insert("anthropic".to_string(), anthropic.capabilities());
+        }
+
+        // --- Ollama registration (enabled by [providers.ollama]; no API key) ---
+        if let Some(ollama_cfg) = &cfg.providers.ollama {
+            let ollama = Arc::new(
+                Ollama::new(new_http("ollama")?, ollama_cfg.base_url.clone())
+                    .with_extra_params(cfg.extra_params("ollama")),
+            );
+            chat.insert("ollama".to_string(), ollama.clone());
+            embed.insert("ollama".to_string(), ollama.clone());
+            models.insert("ollama".to_string(), ollama.clone());
+            caps.insert("ollama".to_string(), ollama.capabilities());
+        }
+
+        // --- Offline hash embeddings (enabled by [providers.hash]; embed only) ---
+        if let Some(hash_cfg) = &cfg.providers.hash {
+            let hash = Arc::new(HashEmbedProvider::from_cfg(hash_cfg));
+            embed.insert("hash".to_string(), hash.clone());
+            caps.insert("hash".to_string(), hash.capabilities());
+        }
+
+        // --- Fixture replay (enabled by [providers.replay]; records from its upstream) ---
+        #[cfg(feature = "native")]
+        if let Some(replay_cfg) = &cfg.providers.replay {
+            let mut replay = ReplayProvider::from_cfg(replay_cfg);
+            if let Some(upstream) = replay_cfg.upstream.as_deref() {
+                if let Some(p) = chat.get(upstream) {
+                    replay = replay.with_chat_upstream(p.clone());
+                }
+                if let Some(p) = embed.get(upstream) {
+                    replay = replay.with_embed_upstream(p.clone());
+                }
+            }
+            let replay = Arc::new(replay);
+            chat.insert("replay".to_string(), replay.clone());
+            embed.insert("replay".to_string(), replay.clone());
+            caps.insert("replay".to_string(), replay.capabilities());
+        }
+
+        // Fault injection wraps whatever was registered under each configured name.
+        for (name, fault) in &cfg.chaos.providers {
+            if let Some(p) = chat.get(name).cloned() {
+                chat.insert(name.clone(), Arc::new(ChaosProvider::new(p, fault.clone())));
+            }
+            if let Some(p) = embed.get(name).cloned() {
+                embed.insert(name.clone(), Arc::new(ChaosProvider::new(p, fault.clone())));
+            }
+        }
+        // Breakers see injected faults, so chaos can trip them; cache hits never reach them.
+        if cfg.http.circuit.failure_threshold > 0 {
+            let mut breakers: HashMap<String, Arc<CircuitBreaker>> = HashMap::new();
+            let mut breaker = |name: &str| {
+                breakers
+                    .entry(name.to_string())
+                    .or_insert_with(|| Arc::new(CircuitBreaker::new(name, &cfg.http.circuit)))
+                    .clone()
+            };
+            for (name, p) in chat.iter_mut() {
+                *p = Arc::new(CircuitBreakerProvider::new(p.clone(), breaker(name)));
+            }
+            for (name, p) in embed.iter_mut() {
+                *p = Arc::new(CircuitBreakerProvider::new(p.clone(), breaker(name)));
+            }
+        }
+        for p in embed.values_mut() {
+            *p = Arc::new(ChunkingEmbedProvider::new(p.clone()).with_cfg(&cfg.embeddings));
+        }
+        // The response cache sits outside them, so a hit skips faults and chunking alike.
+        #[cfg(feature = "native")]
+        let cache = crate::cache::ResponseCache::from_cfg(&cfg.cache)?;
+        #[cfg(feature = "native")]
+        if let Some(cache) = &cache {
+            for p in chat.values_mut() {
+                *p = Arc::new(CachingProvider::new(p.clone(), cache.clone()));
+            }
+            for p in embed.values_mut() {
+                *p = Arc::new(CachingProvider::new(p.clone(), cache.clone()));
+            }
+        }
+        // Concurrent duplicates wait for one call, cache lookup included.
+        #[cfg(feature = "native")]
+        for p in chat.values_mut() {
+            *p = Arc::new(DedupProvider::new(p.clone(), &cfg.dedup));
+        }
+        // Recording wraps everything, so cache hits and injected faults are transcribed too.
+        if let Some(writer) = crate::transcript::installed() {
+            for p in chat.values_mut() {
+                *p = Arc::new(RecordingProvider::new(p.clone(), writer.clone()));
+            }
+            for p in embed.values_mut() {
+                *p = Arc::new(RecordingProvider::new(p.clone(), writer.clone()));
+            }
+        }
 
         // Stubs for future wiring: once adapters exist, we'll construct them here and insert under their key names.
         // Validate presence of API keys if providers are configured, but return a clear not-implemented error for now.
@@ -131,12 +349,22 @@ impl ProviderRegistry {
             // return Err(AiProxyError::Validation("configured providers not implemented yet".to_string()));
         }
 
-        Ok(Self { chat, embed, caps })
+        Ok(Self {
+            chat,
+            embed,
+            models,
+            moderation,
+            realtime,
+            caps,
+            stats: Arc::default(),
+            #[cfg(feature = "native")]
+            cache,
+        })
     }
 
     /// Test-only helper to build a registry with a single OpenAI provider wired in.
     /// This avoids touching environment variables in integration tests.
-    #[cfg(test)]
+    #[cfg(any(test, feature = "test-utils"))]
     pub fn with_openai_for_tests(openai: Arc<OpenAI>) -> Self {
         let mut chat: HashMap<String, Arc<dyn ChatProvider>> = HashMap::new();
         let mut embed: HashMap<String, Arc<dyn EmbedProvider>> = HashMap::new();
@@ -148,39 +376,417 @@ impl ProviderRegistry {
         embed.insert("null".into(), null.clone());
         caps.insert("null".into(), null.capabilities());
 
-        // Register the provided OpenAI instance for both chat and embed
+        // Register the provided OpenAI instance for chat, embed, model listing and moderation
         chat.insert("openai".to_string(), openai.clone());
         embed.insert("openai".to_string(), openai.clone());
-        const OAI_CAPS: &[Capability] = &[Capability::Chat, Capability::Embed];
+        let mut models: HashMap<String, Arc<dyn ModelsProvider>> = HashMap::new();
+        models.insert("openai".to_string(), openai.clone());
+        let mut moderation: HashMap<String, Arc<dyn ModerationProvider>> = HashMap::new();
+        moderation.insert("openai".to_string(), openai.clone());
+        const OAI_CAPS: &[Capability] =
+            &[Capability::Chat, Capability::Embed, Capability::ListModels];
         caps.insert("openai".to_string(), OAI_CAPS);
 
-        Self { chat, embed, caps }
+        Self {
+            chat,
+            embed,
+            models,
+            moderation,
+            realtime: HashMap::new(),
+            caps,
+            stats: Arc::default(),
+            #[cfg(feature = "native")]
+            cache: None,
+        }
+    }
+
+    /// A builder starting from a registry with only the `null` provider, for applications
+    /// that plug in their own adapters instead of (or alongside) configured ones.
+    pub fn builder() -> ProviderRegistryBuilder {
+        ProviderRegistryBuilder::default()
     }
 
-    /// Get a chat provider by name (e.g., "openai", "anthropic", "null").
+    /// Register (or replace) a chat provider under `name`, e.g. a custom adapter built by an
+    /// embedding application. `caps` replaces whatever `name` advertised before, so pass the
+    /// provider's full set when it also embeds or lists models.
+    pub fn register_chat(
+        &mut self,
+        name: &str,
+        provider: Arc<dyn ChatProvider>,
+        caps: &'static [Capability],
+    ) {
+        self.chat.insert(name.to_string(), provider);
+        self.caps.insert(name.to_string(), caps);
+    }
+
+    /// Register (or replace) an embedding provider under `name`; `caps` as for
+    /// `register_chat`. Requests too large for one call are split (`ChunkingEmbedProvider`).
+    pub fn register_embed(
+        &mut self,
+        name: &str,
+        provider: Arc<dyn EmbedProvider>,
+        caps: &'static [Capability],
+    ) {
+        let provider = Arc::new(ChunkingEmbedProvider::new(provider));
+        self.embed.insert(name.to_string(), provider);
+        self.caps.insert(name.to_string(), caps);
+    }
+
+    /// Register (or replace) the model lister `health` and `/v1/models` use for `name`.
+    /// Register its chat or embed side too, so the name advertises capabilities.
+    pub fn register_models(&mut self, name: &str, provider: Arc<dyn ModelsProvider>) {
+        self.models.insert(name.to_string(), provider);
+    }
+
+    /// Register (or replace) the moderation endpoint output guardrails call for `name`.
+    pub fn register_moderation(&mut self, name: &str, provider: Arc<dyn ModerationProvider>) {
+        self.moderation.insert(name.to_string(), provider);
+    }
+
+    /// Register (or replace) the realtime session provider for `name`.
+    pub fn register_realtime(&mut self, name: &str, provider: Arc<dyn RealtimeProvider>) {
+        self.realtime.insert(name.to_string(), provider);
+    }
+
+    /// Wrap every chat provider registered so far in `middleware`. Layers added later run
+    /// outside earlier ones: their `before_request` sees the request first.
+    pub fn layer(&mut self, middleware: Arc<dyn DispatchMiddleware>) {
+        for provider in self.chat.values_mut() {
+            *provider = Arc::new(MiddlewareProvider::new(
+                provider.clone(),
+                middleware.clone(),
+            ));
+        }
+    }
+
+    /// Get a chat provider by name (e.g., "openai", "anthropic", "null"). Its calls count
+    /// towards `stats`.
     pub fn chat(&self, name: &str) -> Option<Arc<dyn ChatProvider>> {
-        self.chat.get(name).cloned()
+        let p = self.chat.get(name)?.clone();
+        Some(Arc::new(MeteredProvider::new(p, name, self.stats.clone())))
     }
 
-    /// Get an embed provider by name.
+    /// Get an embed provider by name; its calls count towards `stats`.
     pub fn embed(&self, name: &str) -> Option<Arc<dyn EmbedProvider>> {
-        self.embed.get(name).cloned()
+        let p = self.embed.get(name)?.clone();
+        Some(Arc::new(MeteredProvider::new(p, name, self.stats.clone())))
+    }
+
+    /// Requests, tokens and estimated cost per provider and model, for every call made
+    /// through this registry's providers so far.
+    pub fn stats(&self) -> Vec<ProviderStats> {
+        self.stats.snapshot()
+    }
+
+    /// The response cache every chat and embed provider answers from, when `[cache]`
+    /// enables one.
+    #[cfg(feature = "native")]
+    pub fn cache(&self) -> Option<&Arc<crate::cache::ResponseCache>> {
+        self.cache.as_ref()
+    }
+
+    /// Model lister by name; `None` if the provider lacks `Capability::ListModels`.
+    pub fn models(&self, name: &str) -> Option<Arc<dyn ModelsProvider>> {
+        self.models.get(name).cloned()
+    }
+
+    /// Moderation endpoint by name; `None` if the provider lacks `Capability::Moderate`.
+    pub fn moderation(&self, name: &str) -> Option<Arc<dyn ModerationProvider>> {
+        self.moderation.get(name).cloned()
+    }
+
+    /// Realtime session provider by name; `None` if the provider lacks
+    /// `Capability::Realtime`.
+    pub fn realtime(&self, name: &str) -> Option<Arc<dyn RealtimeProvider>> {
+        self.realtime.get(name).cloned()
+    }
+
+    /// Names of all registered providers, sorted.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.caps.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        names
     }
 
     /// Capabilities advertised for a given provider name.
     pub fn caps(&self, name: &str) -> Option<&'static [Capability]> {
         self.caps.get(name).copied()
     }
+
+    /// Probe `name`. Providers that list models must answer that authenticated call; the
+    /// rest (e.g. `null`) are healthy once registered.
+    pub async fn health(&self, name: &str) -> ProviderHealth {
+        let (healthy, detail) = if self.caps(name).is_none() {
+            (false, Some("not registered".to_string()))
+        } else if let Some(lister) = self.models(name) {
+            match lister.list_models().await {
+                Ok(_) => (true, None),
+                Err(e) => (false, Some(e.to_string())),
+            }
+        } else {
+            (true, None)
+        };
+        ProviderHealth {
+            provider: name.to_string(),
+            healthy,
+            detail,
+        }
+    }
+
+    /// Query every model lister (or just `only`) concurrently. Context hints the provider
+    /// does not report are filled in from the model catalog.
+    pub async fn list_models(&self, only: Option<&str>) -> ModelListing {
+        let listers = self
+            .names()
+            .into_iter()
+            .filter(|name| only.is_none_or(|o| o == *name))
+            .filter_map(|name| Some((name.to_string(), self.models(name)?)));
+        let answers = futures::future::join_all(
+            listers.map(|(name, lister)| async move { (name, lister.list_models().await) }),
+        )
+        .await;
+        let mut listing = ModelListing::default();
+        for (name, answer) in answers {
+            match answer {
+                Ok(models) => listing.models.extend(models.into_iter().map(|mut m| {
+                    m.context_window = m.context_window.or_else(|| catalog::context_window(&m.id));
+                    m.max_output_tokens = m
+                        .max_output_tokens
+                        .or_else(|| catalog::max_output_tokens(&m.id));
+                    m
+                })),
+                Err(e) => listing.failures.push((name, e)),
+            }
+        }
+        listing
+    }
+}
+
+/// Fluent construction of a `ProviderRegistry` with application-supplied providers, e.g.
+/// `ProviderRegistryBuilder::from_config(&cfg)?.chat("in-house", adapter, caps).build()`.
+pub struct ProviderRegistryBuilder {
+    registry: ProviderRegistry,
+}
+
+impl Default for ProviderRegistryBuilder {
+    fn default() -> Self {
+        let null = Arc::new(NullProvider);
+        let mut registry = ProviderRegistry {
+            chat: HashMap::new(),
+            embed: HashMap::new(),
+            models: HashMap::new(),
+            moderation: HashMap::new(),
+            realtime: HashMap::new(),
+            caps: HashMap::new(),
+            stats: Arc::default(),
+            #[cfg(feature = "native")]
+            cache: None,
+        };
+        registry.register_chat("null", null.clone(), null.capabilities());
+        registry.register_embed("null", null.clone(), null.capabilities());
+        Self { registry }
+    }
+}
+
+impl ProviderRegistryBuilder {
+    /// Start from the providers `cfg` configures.
+    pub fn from_config(cfg: &Config) -> CoreResult<Self> {
+        Ok(Self {
+            registry: ProviderRegistry::from_config(cfg)?,
+        })
+    }
+
+    pub fn chat(
+        mut self,
+        name: &str,
+        provider: Arc<dyn ChatProvider>,
+        caps: &'static [Capability],
+    ) -> Self {
+        self.registry.register_chat(name, provider, caps);
+        self
+    }
+
+    pub fn embed(
+        mut self,
+        name: &str,
+        provider: Arc<dyn EmbedProvider>,
+        caps: &'static [Capability],
+    ) -> Self {
+        self.registry.register_embed(name, provider, caps);
+        self
+    }
+
+    pub fn models(mut self, name: &str, provider: Arc<dyn ModelsProvider>) -> Self {
+        self.registry.register_models(name, provider);
+        self
+    }
+
+    pub fn moderation(mut self, name: &str, provider: Arc<dyn ModerationProvider>) -> Self {
+        self.registry.register_moderation(name, provider);
+        self
+    }
+
+    pub fn realtime(mut self, name: &str, provider: Arc<dyn RealtimeProvider>) -> Self {
+        self.registry.register_realtime(name, provider);
+        self
+    }
+
+    /// `ProviderRegistry::layer`: wraps the chat providers added before this call.
+    pub fn layer(mut self, middleware: Arc<dyn DispatchMiddleware>) -> Self {
+        self.registry.layer(middleware);
+        self
+    }
+
+    pub fn build(self) -> ProviderRegistry {
+        self.registry
+    }
+}
+
+/// Outcome of `ProviderRegistry::health` for one provider.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ProviderHealth {
+    pub provider: String,
+    pub healthy: bool,
+    pub detail: Option<String>,
+}
+
+/// Outcome of `ProviderRegistry::list_models`: every listed model, plus the providers that
+/// failed to answer.
+#[derive(Debug, Default)]
+pub struct ModelListing {
+    pub models: Vec<ModelInfo>,
+    pub failures: Vec<(String, AiProxyError)>,
+}
+
+/// Outcome of verifying one provider credential (see `check_keys`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyState {
+    /// An authenticated call succeeded and the registry will use the key.
+    Valid,
+    /// The env var is not set; the provider is not registered.
+    Missing,
+    /// The key fails the local format check and was not sent upstream.
+    Malformed,
+    /// The provider rejected the key (401/403): invalid, revoked or expired.
+    Rejected,
+    /// The key works, but it is an OpenAI project key and `OPENAI_PROJECT` is unset, so the
+    /// registry skips OpenAI unless routing references it (and then refuses to start).
+    ProjectScoped,
+    /// The call failed for a reason other than authentication (network, 5xx, rate limit).
+    Unverified,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyCheck {
+    pub provider: String,
+    pub env: String,
+    pub state: KeyState,
+    pub detail: Option<String>,
+}
+
+/// Provider name, key env var and base-URL env var/default for each keyed provider.
+const KEYED_PROVIDERS: &[(&str, &str, &str, &str)] = &[
+    (
+        "openai",
+        "OPENAI_API_KEY",
+        "OPENAI_BASE",
+        "https://api.openai.com",
+    ),
+    (
+        "anthropic",
+        "ANTHROPIC_API_KEY",
+        "ANTHROPIC_BASE",
+        "https://api.anthropic.com",
+    ),
+    (
+        "openrouter",
+        "OPENROUTER_API_KEY",
+        "OPENROUTER_BASE",
+        "https://openrouter.ai/api",
+    ),
+];
+
+/// Verify every provider key found in the environment with one cheap authenticated call
+/// (listing models). Reads the same env vars as `ProviderRegistry::from_config`.
+pub async fn check_keys() -> Vec<KeyCheck> {
+    let mut out = Vec::with_capacity(KEYED_PROVIDERS.len());
+    for (provider, env, base_env, default_base) in KEYED_PROVIDERS {
+        let base = std::env::var(base_env).unwrap_or_else(|_| default_base.to_string());
+        out.push(check_key(provider, std::env::var(env).ok(), base).await);
+    }
+    out
+}
+
+async fn check_key(provider: &str, raw: Option<String>, base: String) -> KeyCheck {
+    let env = format!("{}_API_KEY", provider.to_ascii_uppercase());
+    let result = |state, detail: Option<String>| KeyCheck {
+        provider: provider.to_string(),
+        env: env.clone(),
+        state,
+        detail,
+    };
+    let Some(raw) = raw else {
+        return result(KeyState::Missing, None);
+    };
+    if let Err(e) = check_key_format(provider, &raw) {
+        return result(KeyState::Malformed, Some(e.to_string()));
+    }
+    let http = match crate::http_client::HttpClient::new_default() {
+        Ok(http) => http,
+        Err(e) => return result(KeyState::Unverified, Some(e.to_string())),
+    };
+    let key = SecretString::new(raw.as_str().into());
+    let project = std::env::var("OPENAI_PROJECT").ok();
+    let project_scoped = provider == "openai" && is_openai_project_key(&raw) && project.is_none();
+    let lister: Arc<dyn ModelsProvider> = match provider {
+        "openai" => Arc::new(OpenAI::new(
+            http,
+            key,
+            base,
+            std::env::var("OPENAI_ORG").ok(),
+            project,
+        )),
+        "anthropic" => Arc::new(Anthropic::new(http, key, base)),
+        _ => Arc::new(OrAdapter::new(http, key, base)),
+    };
+    match lister.list_models().await {
+        Ok(models) if project_scoped => result(
+            KeyState::ProjectScoped,
+            Some(format!(
+                "{} models visible; set OPENAI_PROJECT=<project_id> to enable openai",
+                models.len()
+            )),
+        ),
+        Ok(models) => result(
+            KeyState::Valid,
+            Some(format!("{} models visible", models.len())),
+        ),
+        Err(AiProxyError::ProviderError { code, message, .. })
+            if code == "401" || code == "403" =>
+        {
+            result(KeyState::Rejected, Some(format!("HTTP {code}: {message}")))
+        }
+        Err(e) => result(KeyState::Unverified, Some(e.to_string())),
+    }
 }
 
 fn has_any_provider(p: &Providers) -> bool {
-    p.openai.is_some() || p.anthropic.is_some() || p.openrouter.is_some()
+    p.openai.is_some()
+        || p.anthropic.is_some()
+        || p.openrouter.is_some()
+        || p.hash.is_some()
+        || p.ollama.is_some()
+        || p.replay.is_some()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{CacheCfg, FsyncPolicy, HttpCfg, RoutingCfg, TranscriptCfg};
+    use crate::config::{
+        CacheCfg, CatalogCfg, ChaosCfg, FsyncPolicy, HttpCfg, PrivacyCfg, RoutingCfg, ScreeningCfg,
+        SummarizationCfg, TranscriptCfg, TruncationCfg, ValidationCfg,
+    };
 
     fn minimal_cfg() -> Config {
         Config {
@@ -188,10 +794,13 @@ mod tests {
                 openai: None,
                 anthropic: None,
                 openrouter: None,
+                hash: None,
+                ollama: None,
+                replay: None,
             },
             cache: CacheCfg {
                 path: ":memory:".into(),
-                ttl_seconds: 60,
+                ttl_seconds: if cfg!(feature = "native") { 60 } else { 0 },
             },
             transcript: TranscriptCfg {
                 dir: ".tx".into(),
@@ -202,8 +811,21 @@ mod tests {
             routing: RoutingCfg {
                 default: "null".into(),
                 rules: vec![],
+                aliases: Default::default(),
             },
             http: HttpCfg::default(),
+            privacy: PrivacyCfg::default(),
+            truncation: TruncationCfg::default(),
+            screening: ScreeningCfg::default(),
+            guardrail: Default::default(),
+            summarization: SummarizationCfg::default(),
+            validation: ValidationCfg::default(),
+            embeddings: Default::default(),
+            dedup: Default::default(),
+            catalog: CatalogCfg::default(),
+            pricing: Default::default(),
+            chaos: ChaosCfg::default(),
+            tenants: Default::default(),
         }
     }
 
@@ -215,6 +837,227 @@ mod tests {
         let caps = reg.caps("null").unwrap();
         assert!(caps.contains(&Capability::Chat));
         assert!(caps.contains(&Capability::Embed));
+        assert!(reg.names().contains(&"null"));
+        // The null provider accepts any model, so it has nothing to list
+        assert!(reg.models("null").is_none());
+        assert!(reg.embed("hash").is_none());
+    }
+
+    #[tokio::test]
+    async fn chaos_wraps_configured_providers() {
+        let mut cfg = minimal_cfg();
+        cfg.chaos.providers.insert(
+            "null".into(),
+            crate::config::FaultCfg {
+                server_error: 1.0,
+                ..Default::default()
+            },
+        );
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let req = crate::model::ChatRequest::builder()
+            .model("m")
+            .user("hi")
+            .build();
+        let err = reg.chat("null").unwrap().chat(req).await.unwrap_err();
+        assert!(matches!(err, AiProxyError::ProviderUnavailable { .. }));
+        let embed = crate::model::EmbedRequest::builder()
+            .model("m")
+            .input("x")
+            .build();
+        assert!(reg.embed("null").unwrap().embed(embed).await.is_err());
+        assert_eq!(reg.chat("null").unwrap().name(), "null");
+    }
+
+    #[tokio::test]
+    async fn circuit_breakers_share_a_provider_between_chat_and_embed() {
+        let mut cfg = minimal_cfg();
+        cfg.chaos.providers.insert(
+            "null".into(),
+            crate::config::FaultCfg {
+                rate_limit: 1.0,
+                ..Default::default()
+            },
+        );
+        cfg.http.circuit.failure_threshold = 1;
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let req = crate::model::ChatRequest::builder()
+            .model("m")
+            .user("hi")
+            .build();
+        let err = reg.chat("null").unwrap().chat(req).await.unwrap_err();
+        assert!(matches!(err, AiProxyError::RateLimited { .. }));
+        // The chat failure opened the circuit the embed calls go through too.
+        let embed = crate::model::EmbedRequest::builder()
+            .model("m")
+            .input("x")
+            .build();
+        let err = reg.embed("null").unwrap().embed(embed).await.unwrap_err();
+        assert!(matches!(err, AiProxyError::ProviderUnavailable { .. }));
+    }
+
+    #[cfg(feature = "native")]
+    #[tokio::test]
+    async fn repeated_requests_hit_the_configured_cache() {
+        let req = || {
+            crate::model::ChatRequest::builder()
+                .model("m")
+                .user("hi")
+                .build()
+        };
+        let reg = ProviderRegistry::from_config(&minimal_cfg()).unwrap();
+        let null = reg.chat("null").unwrap();
+        assert!(!null.chat(req()).await.unwrap().cached);
+        assert!(null.chat(req()).await.unwrap().cached);
+        let embed = || {
+            crate::model::EmbedRequest::builder()
+                .model("m")
+                .input("x")
+                .build()
+        };
+        let null = reg.embed("null").unwrap();
+        assert!(!null.embed(embed()).await.unwrap().cached);
+        assert!(null.embed(embed()).await.unwrap().cached);
+
+        let mut cfg = minimal_cfg();
+        cfg.cache.ttl_seconds = 0;
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let null = reg.chat("null").unwrap();
+        null.chat(req()).await.unwrap();
+        assert!(!null.chat(req()).await.unwrap().cached);
+    }
+
+    #[cfg(not(feature = "native"))]
+    #[test]
+    fn cache_config_needs_native() {
+        let mut cfg = minimal_cfg();
+        cfg.cache.ttl_seconds = 60;
+        let err = ProviderRegistry::from_config(&cfg).err().unwrap();
+        assert!(matches!(err, AiProxyError::Validation(m) if m.starts_with("cache.ttl_seconds")));
+    }
+
+    #[cfg(not(feature = "native"))]
+    #[test]
+    fn replay_config_needs_native() {
+        let mut cfg = minimal_cfg();
+        cfg.providers.replay = Some(crate::config::ReplayCfg {
+            dir: ".fixtures".into(),
+            upstream: None,
+        });
+        let err = ProviderRegistry::from_config(&cfg).err().unwrap();
+        assert!(matches!(err, AiProxyError::Validation(m) if m.starts_with("providers.replay")));
+    }
+
+    #[tokio::test]
+    async fn stats_add_up_calls_through_the_registry() {
+        let mut cfg = minimal_cfg();
+        cfg.cache.ttl_seconds = 0;
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        assert!(reg.stats().is_empty());
+        for model in ["a", "b", "b"] {
+            let req = crate::model::ChatRequest::builder()
+                .model(model)
+                .user("hi")
+                .build();
+            reg.chat("null").unwrap().chat(req).await.unwrap();
+        }
+        let stats = reg.stats();
+        let calls: Vec<_> = stats
+            .iter()
+            .map(|s| (s.provider.as_str(), s.model.as_str(), s.requests))
+            .collect();
+        assert_eq!(calls, vec![("null", "a", 1), ("null", "b", 2)]);
+        assert!(stats[1].prompt_tokens >= stats[0].prompt_tokens);
+    }
+
+    #[tokio::test]
+    async fn hash_embeddings_register_from_config() {
+        let mut cfg = minimal_cfg();
+        cfg.providers.hash = Some(crate::config::HashEmbedCfg {
+            dimensions: 8,
+            seed: 1,
+        });
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        assert_eq!(reg.caps("hash"), Some(&[Capability::Embed][..]));
+        assert!(reg.chat("hash").is_none());
+        let resp = reg
+            .embed("hash")
+            .unwrap()
+            .embed(
+                crate::model::EmbedRequest::builder()
+                    .model("local")
+                    .input("hi")
+                    .build(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.vectors[0].len(), 8);
+    }
+
+    #[cfg(feature = "native")]
+    #[tokio::test]
+    async fn replay_records_from_its_upstream_then_serves_fixtures() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut cfg = minimal_cfg();
+        cfg.providers.replay = Some(crate::config::ReplayCfg {
+            dir: dir.path().to_string_lossy().into_owned(),
+            upstream: Some("null".into()),
+        });
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let req = || {
+            crate::model::ChatRequest::builder()
+                .model("m")
+                .user("hi")
+                .build()
+        };
+        let recorded = reg.chat("replay").unwrap().chat(req()).await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        cfg.providers.replay.as_mut().unwrap().upstream = None;
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let replayed = reg.chat("replay").unwrap().chat(req()).await.unwrap();
+        assert_eq!(replayed.text, recorded.text);
+        assert!(reg.embed("replay").is_some());
+    }
+
+    #[test]
+    fn ollama_registers_from_config_without_a_key() {
+        let mut cfg = minimal_cfg();
+        cfg.providers.ollama = Some(crate::config::OllamaCfg::default());
+        let reg = ProviderRegistry::from_config(&cfg).unwrap();
+        assert!(reg.chat("ollama").is_some());
+        assert!(reg.embed("ollama").is_some());
+        assert!(
+            reg.caps("ollama")
+                .unwrap()
+                .contains(&Capability::ChatStream)
+        );
+    }
+
+    #[tokio::test]
+    async fn applications_register_their_own_providers() {
+        const CUSTOM: &[Capability] = &[Capability::Chat, Capability::Embed];
+        let mut reg = ProviderRegistry::builder()
+            .chat("in-house", Arc::new(NullProvider), CUSTOM)
+            .embed("in-house", Arc::new(NullProvider), CUSTOM)
+            .build();
+        assert_eq!(reg.names(), vec!["in-house", "null"]);
+        assert_eq!(reg.caps("in-house"), Some(CUSTOM));
+        let req = crate::model::ChatRequest::builder()
+            .model("m")
+            .user("hi")
+            .build();
+        assert!(reg.chat("in-house").unwrap().chat(req).await.is_ok());
+        assert!(reg.models("in-house").is_none());
+
+        reg.register_embed("vectors", Arc::new(NullProvider), &[Capability::Embed]);
+        assert!(reg.chat("vectors").is_none());
+        assert!(reg.embed("vectors").is_some());
+
+        let from_cfg = ProviderRegistryBuilder::from_config(&minimal_cfg())
+            .unwrap()
+            .chat("in-house", Arc::new(NullProvider), CUSTOM)
+            .build();
+        assert!(from_cfg.chat("null").is_some() && from_cfg.chat("in-house").is_some());
     }
 
     #[test]
@@ -222,10 +1065,54 @@ mod tests {
         let reg = ProviderRegistry::from_config(&minimal_cfg()).unwrap();
         assert!(reg.chat("missing").is_none());
         assert!(reg.embed("missing").is_none());
+        assert!(reg.models("missing").is_none());
         assert!(reg.caps("missing").is_none());
     }
 
-    use crate::error::AiProxyError;
+    #[tokio::test]
+    async fn check_key_classifies_missing_malformed_and_rejected() {
+        use httpmock::prelude::*;
+
+        let missing = check_key("other", None, "http://unused".into()).await;
+        assert_eq!(missing.state, KeyState::Missing);
+
+        let malformed = check_key(
+            "openrouter",
+            Some("or-weak-secret".into()),
+            "http://unused".into(),
+        )
+        .await;
+        assert_eq!(malformed.state, KeyState::Malformed);
+        assert!(!malformed.detail.unwrap().contains("or-weak"));
+
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET).path("/v1/models");
+            then.status(401).body(r#"{"error":{"message":"expired"}}"#);
+        });
+        let key = format!("sk-or-{}", "x".repeat(20));
+        let rejected = check_key("openrouter", Some(key), server.base_url()).await;
+        m.assert();
+        assert_eq!(rejected.state, KeyState::Rejected);
+        assert_eq!(rejected.env, "OPENROUTER_API_KEY");
+    }
+
+    #[tokio::test]
+    async fn check_key_reports_valid_keys() {
+        use httpmock::prelude::*;
+
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/v1/models");
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(r#"{"data":[{"id":"a/m1"},{"id":"b/m2"}]}"#);
+        });
+        let key = format!("sk-or-{}", "x".repeat(20));
+        let check = check_key("openrouter", Some(key), server.base_url()).await;
+        assert_eq!(check.state, KeyState::Valid);
+        assert_eq!(check.detail.as_deref(), Some("2 models visible"));
+    }
 
     #[test]
     fn invalid_openai_key_rejected_and_redacted() {
@@ -257,6 +1144,96 @@ mod tests {
         }
     }
 
+    #[test]
+    fn invalid_anthropic_key_rejected_and_redacted() {
+        let res = super::validate_anthropic_key("ant-weak-key");
+        match res {
+            Err(AiProxyError::Validation(msg)) => {
+                assert!(
+                    msg.contains("ANTHROPIC_API_KEY looks invalid"),
+                    "msg: {}",
+                    msg
+                );
+                assert!(msg.contains("***"), "msg: {}", msg);
+                assert!(!msg.contains("ant-weak"), "should be redacted: {}", msg);
+            }
+            _ => panic!("expected Validation error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn health_probes_model_listing() {
+        use httpmock::prelude::*;
+
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(GET).path("/v1/models");
+            then.status(503);
+        });
+        let http = crate::http_client::HttpClient::new_default().unwrap();
+        let openai = Arc::new(OpenAI::new(
+            http,
+            SecretString::new("test-key".into()),
+            server.base_url(),
+            None,
+            None,
+        ));
+        let reg = ProviderRegistry::with_openai_for_tests(openai);
+
+        assert!(reg.health("null").await.healthy);
+        let missing = reg.health("missing").await;
+        assert!(!missing.healthy);
+        assert_eq!(missing.detail.as_deref(), Some("not registered"));
+        let down = reg.health("openai").await;
+        assert!(!down.healthy);
+        assert!(down.detail.is_some());
+    }
+
+    #[tokio::test]
+    async fn list_models_fills_catalog_hints_and_reports_failures() {
+        use httpmock::prelude::*;
+
+        let up = MockServer::start();
+        up.mock(|when, then| {
+            when.method(GET).path("/v1/models");
+            then.status(200).json_body(serde_json::json!({
+                "data": [{"id": "gpt-4o", "owned_by": "openai"}, {"id": "in-house-ft"}]
+            }));
+        });
+        let down = MockServer::start();
+        down.mock(|when, then| {
+            when.method(GET).path("/v1/models");
+            then.status(503);
+        });
+        let lister = |base: String| {
+            let http = crate::http_client::HttpClient::new_default().unwrap();
+            Arc::new(OpenAI::new(
+                http,
+                SecretString::new("k".into()),
+                base,
+                None,
+                None,
+            ))
+        };
+        let reg = ProviderRegistry::builder()
+            .models("up", lister(up.base_url()))
+            .models("down", lister(down.base_url()))
+            .chat("up", Arc::new(NullProvider), &[Capability::ListModels])
+            .chat("down", Arc::new(NullProvider), &[Capability::ListModels])
+            .build();
+
+        let listing = reg.list_models(None).await;
+        assert_eq!(listing.models.len(), 2);
+        assert_eq!(listing.models[0].context_window, Some(128_000));
+        assert_eq!(listing.models[0].max_output_tokens, Some(16_384));
+        assert_eq!(listing.models[1].context_window, None);
+        assert_eq!(listing.failures.len(), 1);
+        assert_eq!(listing.failures[0].0, "down");
+
+        let only = reg.list_models(Some("down")).await;
+        assert!(only.models.is_empty() && only.failures.len() == 1);
+    }
+
     // NOTE: Env-driven invalid-key tests omitted due to environment mutations
     // requiring unsafe in this project setup. Validation helpers are covered
     // above and `from_config` simply forwards those errors.
@@ -0,0 +1,144 @@
+//! Model aliases configured by `routing.aliases`: `AliasProvider` sends every request to
+//! `inner` as the alias's model, so clients keep a stable logical name (`fast`, `gpt-4`)
+//! while the model behind it changes.
+//!
+//! The router matches its rules against the rewritten model and wraps what it selects. The
+//! provider's `ProviderTrace`, completion log and transcript record name the model that was
+//! called; the trace and completion log also carry the alias as `requested_model`, copied from
+//! the request. The access log keeps the name the client sent, as does the debug event logged
+//! for each rewrite.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::error::CoreResult;
+use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::provider::{ChatProvider, EmbedProvider};
+use crate::realtime::{RealtimeProvider, RealtimeSession, SessionConfig};
+use crate::stream::BoxStreamEv;
+
+/// Decorator sending `inner`'s requests for `model`.
+#[derive(Debug)]
+pub struct AliasProvider<P: ?Sized> {
+    inner: Arc<P>,
+    model: String,
+}
+
+impl<P: ?Sized> AliasProvider<P> {
+    pub fn new(inner: Arc<P>, model: impl Into<String>) -> Self {
+        Self {
+            inner,
+            model: model.into(),
+        }
+    }
+
+    /// `alias`, replaced by the model it stands for.
+    fn rewrite(&self, alias: &mut String) {
+        tracing::debug!(alias = %alias, model = %self.model, "model alias");
+        *alias = self.model.clone();
+    }
+
+    /// As `rewrite`, keeping the first name asked for in `requested`.
+    fn rewrite_requested(&self, model: &mut String, requested: &mut Option<String>) {
+        requested.get_or_insert_with(|| model.clone());
+        self.rewrite(model);
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for AliasProvider<dyn ChatProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn chat(&self, mut req: ChatRequest) -> CoreResult<ChatResponse> {
+        self.rewrite_requested(&mut req.model, &mut req.requested_model);
+        self.inner.chat(req).await
+    }
+
+    async fn chat_stream_events(&self, mut req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        self.rewrite_requested(&mut req.model, &mut req.requested_model);
+        self.inner.chat_stream_events(req).await
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.inner.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.inner.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        let mut req = req.clone();
+        req.requested_model.get_or_insert_with(|| req.model.clone());
+        req.model = self.model.clone();
+        self.inner.count_tokens(&req).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for AliasProvider<dyn EmbedProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn embed(&self, mut req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        self.rewrite_requested(&mut req.model, &mut req.requested_model);
+        self.inner.embed(req).await
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl RealtimeProvider for AliasProvider<dyn RealtimeProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn connect(&self, cfg: &SessionConfig) -> CoreResult<RealtimeSession> {
+        let mut cfg = cfg.clone();
+        self.rewrite(&mut cfg.model);
+        self.inner.connect(&cfg).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::Seen;
+
+    #[tokio::test]
+    async fn requests_reach_the_provider_as_the_aliased_model() {
+        let seen = Arc::new(Seen::default());
+        let chat = AliasProvider::new(seen.clone() as Arc<dyn ChatProvider>, "gpt-4o-mini");
+        let req = ChatRequest::builder().model("fast").user("hi").build();
+        chat.chat(req.clone()).await.unwrap();
+        let _stream = chat.chat_stream_events(req).await.unwrap();
+
+        let embed = AliasProvider::new(seen.clone() as Arc<dyn EmbedProvider>, "embed-small");
+        let req = EmbedRequest {
+            model: "vectors".into(),
+            inputs: vec!["a".into()],
+            client_key: None,
+            requested_model: None,
+        };
+        embed.embed(req).await.unwrap();
+        let named = |model: &str, alias: &str| (model.to_string(), Some(alias.to_string()));
+        assert_eq!(
+            seen.requests(),
+            [
+                named("gpt-4o-mini", "fast"),
+                named("gpt-4o-mini", "fast"),
+                named("embed-small", "vectors"),
+            ]
+        );
+    }
+}
@@ -1,14 +1,20 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::borrow::Cow;
 
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    config::ExtraParamsCfg,
+    pricing,
     error::{AiProxyError, CoreResult},
-    http_client::{HttpClient, RequestCtx},
-    model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse, StopReason},
-    provider::{ChatProvider, EmbedProvider, ProviderCaps},
+    http_client::{HttpClient, RequestCtx, RetryLog, StreamTimeouts},
+    model::{
+        ChatChoice, ChatMessage, ChatRequest, ChatResponse, Citation, ContentPart, EmbedRequest,
+        EmbedResponse, MessageContent, ModelInfo, StopReason, ToolCall, ToolDialect, Usage,
+    },
+    provider::{ChatProvider, EmbedProvider, ModelsProvider, ProviderCaps},
 };
+use crate::platform::{SystemTime, UNIX_EPOCH};
 use async_trait::async_trait;
 
 /// Default Anthropic API version header required by the Messages API.
@@ -20,6 +26,8 @@ pub struct Anthropic {
     api_key: SecretString,
     base: String,
     name: String,
+    forward_metadata: bool,
+    extra_params: ExtraParamsCfg,
 }
 
 impl Anthropic {
@@ -29,9 +37,38 @@ impl Anthropic {
             api_key,
             base,
             name: "anthropic".into(),
+            forward_metadata: false,
+            extra_params: ExtraParamsCfg::default(),
         }
     }
 
+    /// Send `metadata.user_id` (see `PrivacyCfg`): the request's own `metadata.user_id` when it
+    /// is a string, else a redacted client-key label. Other metadata keys are not accepted.
+    pub fn with_forward_metadata(mut self, on: bool) -> Self {
+        self.forward_metadata = on;
+        self
+    }
+
+    /// Limit which `ChatRequest.extra` parameters reach the provider.
+    pub fn with_extra_params(mut self, cfg: ExtraParamsCfg) -> Self {
+        self.extra_params = cfg;
+        self
+    }
+
+    fn metadata(&self, req: &ChatRequest) -> Option<AMetadata> {
+        if !self.forward_metadata {
+            return None;
+        }
+        let user_id = req
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get("user_id"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .or_else(|| req.client_key.as_deref().map(crate::usage::key_label))?;
+        Some(AMetadata { user_id })
+    }
+
     fn headers(&self, _ctx: &RequestCtx<'_>) -> Vec<(String, String)> {
         vec![
             (
@@ -51,6 +88,7 @@ impl Anthropic {
             Some("max_tokens") => Some(StopReason::Length),
             Some("tool_use") => Some(StopReason::ToolUse),
             Some("stop_sequence") => Some(StopReason::Stop),
+            Some("refusal") => Some(StopReason::Refusal),
             _ => None,
         }
     }
@@ -60,6 +98,7 @@ impl ProviderCaps for Anthropic {
     fn capabilities(&self) -> &'static [crate::provider::Capability] {
         &[
             crate::provider::Capability::Chat,
+            crate::provider::Capability::ListModels,
             // Embeddings unsupported in MVP; omit Capability::Embed
         ]
     }
@@ -78,6 +117,34 @@ struct AMsgReq<'a> {
     temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<AMetadata>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+}
+
+/// Body of `/v1/messages/count_tokens`.
+#[derive(Serialize)]
+struct ACountReq<'a> {
+    model: &'a str,
+    messages: Vec<AMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    /// Tool definitions count towards the prompt.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
+}
+
+#[derive(Deserialize)]
+struct ACountResp {
+    input_tokens: u32,
+}
+
+#[derive(Serialize)]
+struct AMetadata {
+    user_id: String,
 }
 
 #[derive(Serialize)]
@@ -89,7 +156,117 @@ struct AMessage<'a> {
 #[derive(Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 enum AContent<'a> {
-    Text { text: &'a str },
+    Text {
+        text: Cow<'a, str>,
+    },
+    Image {
+        source: AImageSource<'a>,
+    },
+    ToolResult {
+        tool_use_id: &'a str,
+        content: Vec<AContent<'a>>,
+    },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AImageSource<'a> {
+    Base64 { media_type: &'a str, data: &'a str },
+    Url { url: &'a str },
+}
+
+/// One content block per part; plain text is a single text block. Images given as `data:`
+/// URLs are sent inline, like base64 parts.
+fn blocks(content: &MessageContent) -> Vec<AContent<'_>> {
+    match content {
+        MessageContent::Text(text) => vec![AContent::Text { text: text.into() }],
+        MessageContent::Parts(parts) => parts
+            .iter()
+            .map(|p| match p {
+                ContentPart::Text { text } => AContent::Text { text: text.into() },
+                ContentPart::ImageUrl { image_url } => AContent::Image {
+                    source: match p.inline_image() {
+                        Some((media_type, data)) => AImageSource::Base64 { media_type, data },
+                        None => AImageSource::Url {
+                            url: &image_url.url,
+                        },
+                    },
+                },
+                ContentPart::ImageBase64 { media_type, data } => AContent::Image {
+                    source: AImageSource::Base64 { media_type, data },
+                },
+            })
+            .collect(),
+    }
+}
+
+/// Anthropic has no per-message name, so a named message reads `name: text`.
+fn named_blocks(m: &ChatMessage) -> Vec<AContent<'_>> {
+    let mut out = blocks(&m.content);
+    if let Some(name) = &m.name {
+        match out.first_mut() {
+            Some(AContent::Text { text }) => *text = format!("{name}: {text}").into(),
+            _ => out.insert(
+                0,
+                AContent::Text {
+                    text: format!("{name}:").into(),
+                },
+            ),
+        }
+    }
+    out
+}
+
+/// Split out the system prompt and map the rest onto Anthropic turns. Tool results become
+/// `tool_result` blocks in a user turn; consecutive results share one turn, as Anthropic requires.
+fn to_messages(req: &ChatRequest) -> CoreResult<(Option<String>, Vec<AMessage<'_>>)> {
+    let mut system_prompts: Vec<Cow<'_, str>> = Vec::new();
+    let mut msgs: Vec<AMessage> = Vec::new();
+
+    for m in &req.messages {
+        match m.role {
+            crate::model::Role::System => system_prompts.push(m.content.text()),
+            crate::model::Role::User => msgs.push(AMessage {
+                role: "user",
+                content: named_blocks(m),
+            }),
+            crate::model::Role::Assistant => msgs.push(AMessage {
+                role: "assistant",
+                content: named_blocks(m),
+            }),
+            crate::model::Role::Tool => {
+                let tool_use_id = m.tool_call_id.as_deref().ok_or_else(|| {
+                    AiProxyError::Validation("tool message is missing tool_call_id".into())
+                })?;
+                let result = AContent::ToolResult {
+                    tool_use_id,
+                    content: blocks(&m.content),
+                };
+                match msgs.last_mut() {
+                    Some(last)
+                        if last.role == "user"
+                            && last
+                                .content
+                                .iter()
+                                .all(|b| matches!(b, AContent::ToolResult { .. })) =>
+                    {
+                        last.content.push(result)
+                    }
+                    _ => msgs.push(AMessage {
+                        role: "user",
+                        content: vec![result],
+                    }),
+                }
+            }
+        }
+    }
+
+    let system = if system_prompts.is_empty() {
+        None
+    } else {
+        Some(system_prompts.join("\n"))
+    };
+    Ok((system, msgs))
 }
 
 #[derive(Deserialize)]
@@ -106,50 +283,128 @@ struct AMsgResp {
 
 #[derive(Deserialize)]
 struct ARespContent {
-    #[allow(dead_code)]
     r#type: String,
     text: Option<String>,
+    /// `tool_use` blocks carry the call id, tool name and parsed input.
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    input: Option<serde_json::Value>,
+    /// Text blocks backed by web search or cited documents.
+    #[serde(default)]
+    citations: Vec<ACitation>,
+}
+
+/// Web-search citations carry `url`; document citations carry `document_title` instead.
+#[derive(Deserialize)]
+struct ACitation {
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    document_title: Option<String>,
+    #[serde(default)]
+    cited_text: Option<String>,
+}
+
+impl From<&ACitation> for Citation {
+    fn from(c: &ACitation) -> Self {
+        Citation {
+            url: c.url.clone(),
+            title: c.title.clone().or_else(|| c.document_title.clone()),
+            cited_text: c.cited_text.clone(),
+            ..Citation::default()
+        }
+    }
+}
+
+impl ARespContent {
+    fn tool_call(&self) -> Option<ToolCall> {
+        if self.r#type != "tool_use" {
+            return None;
+        }
+        Some(ToolCall {
+            id: self.id.clone()?,
+            name: self.name.clone()?,
+            arguments: self.input.clone().unwrap_or_default(),
+        })
+    }
 }
 
 #[derive(Deserialize, Default)]
 struct AUsage {
     input_tokens: Option<u32>,
     output_tokens: Option<u32>,
+    #[serde(default)]
+    cache_read_input_tokens: Option<u32>,
+    #[serde(default)]
+    cache_creation_input_tokens: Option<u32>,
 }
 
-#[async_trait]
+impl From<AUsage> for Usage {
+    /// Anthropic's `input_tokens` excludes cache reads and writes; `prompt` counts all three.
+    fn from(u: AUsage) -> Self {
+        let prompt = [
+            u.input_tokens,
+            u.cache_read_input_tokens,
+            u.cache_creation_input_tokens,
+        ]
+        .into_iter()
+        .flatten()
+        .fold(0u32, u32::saturating_add);
+        Usage {
+            cached_prompt: u.cache_read_input_tokens,
+            ..Usage::new(prompt, u.output_tokens.unwrap_or(0))
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl ChatProvider for Anthropic {
     fn name(&self) -> &str {
         &self.name
     }
 
-    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
-        // Map our ChatRequest to Anthropic Messages format.
-        let mut system_prompts: Vec<&str> = Vec::new();
-        let mut msgs: Vec<AMessage> = Vec::new();
-
-        for m in &req.messages {
-            match m.role {
-                crate::model::Role::System => system_prompts.push(m.content.as_str()),
-                crate::model::Role::User => msgs.push(AMessage {
-                    role: "user",
-                    content: vec![AContent::Text { text: &m.content }],
-                }),
-                crate::model::Role::Assistant => msgs.push(AMessage {
-                    role: "assistant",
-                    content: vec![AContent::Text { text: &m.content }],
-                }),
-                _ => { /* ignore Tool/others in MVP */ }
-            }
-        }
+    fn requires_alternation(&self) -> bool {
+        true
+    }
 
-        let system = if system_prompts.is_empty() {
-            None
-        } else {
-            Some(system_prompts.join("\n"))
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        let (system, messages) = to_messages(req)?;
+        let payload = ACountReq {
+            model: &req.model,
+            messages,
+            system,
+            tools: super::tool_params(req, ToolDialect::Anthropic).0,
         };
+        let url = format!("{}/v1/messages/count_tokens", self.base);
+        let ctx = RequestCtx {
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            ..RequestCtx::default()
+        };
+        let headers = self.headers(&ctx);
+        let header_pairs: Vec<(&str, &str)> = headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let (resp, _provider_request_id, _latency_ms) = self
+            .http
+            .post_json::<_, ACountResp>(&url, &payload, &header_pairs, &ctx)
+            .await?;
+        Ok(Some(resp.input_tokens))
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        // Map our ChatRequest to Anthropic Messages format.
+        let (system, msgs) = to_messages(&req)?;
 
         let max_tokens = req.max_output_tokens.unwrap_or(1024).max(1);
+        let (tools, tool_choice) = super::tool_params(&req, ToolDialect::Anthropic);
 
         let payload = AMsgReq {
             model: &req.model,
@@ -158,10 +413,24 @@ impl ChatProvider for Anthropic {
             max_tokens,
             temperature: req.temperature,
             top_p: req.top_p,
+            metadata: self.metadata(&req),
+            tools,
+            tool_choice,
         };
+        let payload =
+            super::with_extra(&self.name, &payload, req.extra.as_ref(), &self.extra_params)?;
 
         let url = format!("{}/v1/messages", self.base);
-        let ctx = RequestCtx::default();
+        let retries = RetryLog::default();
+        let ctx = RequestCtx {
+            request_id: req.request_id.as_deref(),
+            turn_id: req.trace_id.as_deref(),
+            idempotency_key: req.idempotency_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: Some(&retries),
+            stream_timeouts: StreamTimeouts::default(),
+        };
         let headers = self.headers(&ctx);
         let header_pairs: Vec<(&str, &str)> = headers
             .iter()
@@ -172,9 +441,9 @@ impl ChatProvider for Anthropic {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_default()
             .as_millis() as u64;
-        let (resp, provider_request_id, latency_ms) = self
+        let (resp, raw, provider_request_id, latency_ms) = self
             .http
-            .post_json::<_, AMsgResp>(&url, &payload, &header_pairs, &ctx)
+            .post_json_keep_raw::<_, AMsgResp>(&url, &payload, &header_pairs, &ctx, req.include_raw)
             .await?;
 
         let text = resp
@@ -184,22 +453,34 @@ impl ChatProvider for Anthropic {
             .unwrap_or_default();
 
         let stop = Anthropic::map_stop(resp.stop_reason.as_deref());
-        let usage_in = resp
-            .usage
-            .as_ref()
-            .and_then(|u| u.input_tokens)
-            .unwrap_or(0) as u64;
-        let usage_out = resp
-            .usage
-            .as_ref()
-            .and_then(|u| u.output_tokens)
-            .unwrap_or(0) as u64;
+        // A refusal has no separate field; whatever text came back is the explanation.
+        let refusal = (stop == Some(StopReason::Refusal) && !text.is_empty()).then(|| text.clone());
+        let annotations: Vec<Citation> = resp
+            .content
+            .iter()
+            .flat_map(|c| c.citations.iter().map(Citation::from))
+            .collect();
+        // Anthropic has no `n`: the message is the only choice.
+        let choices = vec![ChatChoice {
+            index: 0,
+            text: text.clone(),
+            stop_reason: stop,
+            tool_calls: resp.content.iter().filter_map(|c| c.tool_call()).collect(),
+            logprobs: None,
+            refusal: refusal.clone(),
+            annotations: annotations.clone(),
+        }];
+        let tool_calls = choices
+            .first()
+            .map(|c| c.tool_calls.clone())
+            .unwrap_or_default();
+        let usage = resp.usage.map(Usage::from).unwrap_or_default();
+        let cost_usd = pricing::cost_usd(&self.name, &req.model, usage.prompt, usage.completion);
 
         let resp = ChatResponse {
-            model: req.model,
+            model: req.model.clone(),
             text,
-            usage_prompt: usage_in as u32,
-            usage_completion: usage_out as u32,
+            usage,
             cached: false,
             provider: self.name.clone(),
             transcript_id: None,
@@ -208,35 +489,50 @@ impl ChatProvider for Anthropic {
             provider_request_id,
             created_at_ms: started as i64,
             latency_ms,
+            raw,
+            choices,
+            tool_calls,
+            refusal,
+            annotations,
+            truncation: None,
+            cost_usd,
+            guardrail: vec![],
         };
         // Emit structured completion log (non-streaming)
-        let tokens_total = resp.usage_prompt.checked_add(resp.usage_completion);
+        let tokens_total = resp.usage.prompt.checked_add(resp.usage.completion);
         let stop_code = match resp.stop_reason {
             Some(crate::model::StopReason::Stop) => Some("stop"),
             Some(crate::model::StopReason::Length) => Some("length"),
             Some(crate::model::StopReason::ToolUse) => Some("tool_use"),
             Some(crate::model::StopReason::EndTurn) => Some("end_turn"),
             Some(crate::model::StopReason::ContentFilter) => Some("content_filter"),
+            Some(crate::model::StopReason::Cancelled) => Some("cancelled"),
+            Some(crate::model::StopReason::Refusal) => Some("refusal"),
             Some(crate::model::StopReason::Other) => Some("other"),
             None => None,
         };
         let clog = crate::telemetry::CompletionLog::new()
             .provider("anthropic")
             .model(&resp.model)
+            .requested_model_opt(req.requested_model.as_deref())
             .request_id_opt(None)
             .turn_id_opt(None)
             .provider_request_id_opt(resp.provider_request_id.as_deref())
+            .client_key_opt(req.client_key.as_deref())
             .created_at_ms(resp.created_at_ms as u64)
             .latency_ms(resp.latency_ms as u64)
             .stop_reason_opt(stop_code)
             .text_opt(Some(&resp.text))
-            .tokens(Some(resp.usage_prompt), Some(resp.usage_completion), tokens_total);
-        crate::telemetry::emit_completion(clog);
+            .cost_usd_opt(resp.cost_usd)
+            .tokens(Some(resp.usage.prompt), Some(resp.usage.completion), tokens_total);
+        let (reasons, backoff_ms) = retries.take();
+        crate::telemetry::emit_completion(clog.retried(reasons, backoff_ms));
         Ok(resp)
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl EmbedProvider for Anthropic {
     fn name(&self) -> &str {
         &self.name
@@ -249,6 +545,50 @@ impl EmbedProvider for Anthropic {
     }
 }
 
+#[derive(Deserialize)]
+struct AModelList {
+    data: Vec<AModel>,
+}
+
+#[derive(Deserialize)]
+struct AModel {
+    id: String,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ModelsProvider for Anthropic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn list_models(&self) -> CoreResult<Vec<ModelInfo>> {
+        // Single page; the API default (20) is too small to be useful, 1000 is its maximum.
+        let url = format!("{}/v1/models?limit=1000", self.base);
+        let ctx = RequestCtx::default();
+        let headers = self.headers(&ctx);
+        let header_pairs: Vec<(&str, &str)> = headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let (resp, _provider_request_id, _latency_ms) = self
+            .http
+            .get_json::<AModelList>(&url, &header_pairs, &ctx)
+            .await?;
+        Ok(resp
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.id,
+                provider: self.name.clone(),
+                owned_by: Some("anthropic".into()),
+                context_window: None,
+                max_output_tokens: None,
+            })
+            .collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -270,7 +610,7 @@ mod tests {
     }
 
     fn ensure_cl_sink_installed() {
-        let _ = crate::telemetry::set_telemetry_sink(Arc::new(CLTestSink::default()));
+        let _ = crate::telemetry::set_telemetry_sink(Arc::new(CLTestSink));
     }
 
     #[tokio::test]
@@ -301,29 +641,17 @@ mod tests {
             server.base_url(),
         );
 
-        let req = ChatRequest {
-            model: "claude-3-haiku".into(),
-            messages: vec![crate::model::ChatMessage {
-                role: crate::model::Role::User,
-                content: "hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: Some(128),
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .user("hi")
+            .max_output_tokens(128)
+            .build();
 
         let resp = provider.chat(req).await.expect("chat ok");
         assert_eq!(resp.text, "hello from claude");
         assert_eq!(resp.stop_reason, Some(StopReason::EndTurn));
         assert_eq!(resp.provider, "anthropic");
-        assert_eq!(resp.usage_prompt, 9);
-        assert_eq!(resp.usage_completion, 3);
+        assert_eq!(resp.usage, Usage::new(9, 3));
 
         let logs = COMPLETION_LOGS.lock().unwrap().clone();
         if !logs.is_empty() {
@@ -340,6 +668,128 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn refusal_stop_reason_carries_the_text() {
+        let server = MockServer::start();
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/v1/messages");
+            then.status(200).json_body(serde_json::json!({
+                "id": "msg_1",
+                "content": [{"type": "text", "text": "I can't help with that."}],
+                "stop_reason": "refusal"
+            }));
+        });
+        let provider = Anthropic::new(
+            HttpClient::new_default().unwrap(),
+            SecretString::new("test-key".into()),
+            server.base_url(),
+        );
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .user("hi")
+            .build();
+        let resp = provider.chat(req).await.expect("chat ok");
+        assert_eq!(resp.stop_reason, Some(StopReason::Refusal));
+        assert_eq!(resp.refusal.as_deref(), Some("I can't help with that."));
+        assert_eq!(resp.choices[0].refusal, resp.refusal);
+    }
+
+    #[tokio::test]
+    async fn text_block_citations_become_annotations() {
+        let server = MockServer::start();
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/v1/messages");
+            then.status(200).json_body(serde_json::json!({
+                "id": "msg_1",
+                "content": [
+                    {"type": "text", "text": "Per the docs, "},
+                    {"type": "text", "text": "it is stable.", "citations": [{
+                        "type": "web_search_result_location",
+                        "url": "https://doc.rust-lang.org/",
+                        "title": "Docs",
+                        "cited_text": "stable since 1.0"
+                    }, {
+                        "type": "char_location",
+                        "document_title": "notes.txt",
+                        "cited_text": "stable"
+                    }]}
+                ],
+                "stop_reason": "end_turn"
+            }));
+        });
+        let provider = Anthropic::new(
+            HttpClient::new_default().unwrap(),
+            SecretString::new("test-key".into()),
+            server.base_url(),
+        );
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .user("hi")
+            .build();
+        let resp = provider.chat(req).await.expect("chat ok");
+        assert_eq!(resp.annotations.len(), 2);
+        let first = &resp.annotations[0];
+        assert_eq!(first.url.as_deref(), Some("https://doc.rust-lang.org/"));
+        assert_eq!(first.cited_text.as_deref(), Some("stable since 1.0"));
+        assert_eq!(resp.annotations[1].url, None);
+        assert_eq!(resp.annotations[1].title.as_deref(), Some("notes.txt"));
+        assert_eq!(resp.choices[0].annotations, resp.annotations);
+    }
+
+    #[tokio::test]
+    async fn list_models_sends_auth_headers() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(GET)
+                .path("/v1/models")
+                .query_param("limit", "1000")
+                .header("x-api-key", "test-key")
+                .header("anthropic-version", ANTHROPIC_API_VERSION);
+            then.status(200).json_body(serde_json::json!({
+                "data": [{"id": "claude-sonnet-4-20250514", "type": "model", "display_name": "Claude Sonnet 4"}],
+                "has_more": false
+            }));
+        });
+        let provider = Anthropic::new(
+            HttpClient::new_default().unwrap(),
+            SecretString::new("test-key".into()),
+            server.base_url(),
+        );
+        let models = provider.list_models().await.expect("models ok");
+        m.assert();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].id, "claude-sonnet-4-20250514");
+        assert_eq!(models[0].provider, "anthropic");
+    }
+
+    #[tokio::test]
+    async fn count_tokens_uses_the_counting_endpoint() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/messages/count_tokens")
+                .header("x-api-key", "test-key")
+                .json_body(serde_json::json!({
+                    "model": "claude-sonnet-4-20250514",
+                    "system": "be brief",
+                    "messages": [{"role": "user", "content": [{"type": "text", "text": "hi"}]}]
+                }));
+            then.status(200).json_body(serde_json::json!({"input_tokens": 14}));
+        });
+        let provider = Anthropic::new(
+            HttpClient::new_default().unwrap(),
+            SecretString::new("test-key".into()),
+            server.base_url(),
+        );
+        let req = ChatRequest::builder()
+            .model("claude-sonnet-4-20250514")
+            .system("be brief")
+            .user("hi")
+            .build();
+        assert_eq!(provider.count_tokens(&req).await.unwrap(), Some(14));
+        m.assert();
+    }
+
     #[tokio::test]
     async fn embed_is_unsupported() {
         let provider = Anthropic::new(
@@ -348,11 +798,7 @@ mod tests {
             "http://localhost".to_string(),
         );
 
-        let req = EmbedRequest {
-            model: "dummy".into(),
-            inputs: vec!["x".into()],
-            client_key: None,
-        };
+        let req = EmbedRequest::builder().model("dummy").input("x").build();
         let err = provider.embed(req).await.unwrap_err();
         match err {
             AiProxyError::Validation(_) => {}
@@ -362,8 +808,6 @@ mod tests {
 
     #[tokio::test]
     async fn chat_sends_joined_system_prompt() {
-        use crate::model::{ChatMessage, Role};
-
         let server = MockServer::start();
         let m = server.mock(|when, then| {
             when.method(POST)
@@ -381,32 +825,13 @@ mod tests {
             server.base_url(),
         );
 
-        let req = ChatRequest {
-            model: "claude-3-haiku".into(),
-            messages: vec![
-                ChatMessage {
-                    role: Role::System,
-                    content: "A".into(),
-                },
-                ChatMessage {
-                    role: Role::System,
-                    content: "B".into(),
-                },
-                ChatMessage {
-                    role: Role::User,
-                    content: "hi".into(),
-                },
-            ],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: Some(128),
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .system("A")
+            .system("B")
+            .user("hi")
+            .max_output_tokens(128)
+            .build();
 
         let _ = provider.chat(req).await.unwrap();
 
@@ -414,10 +839,183 @@ mod tests {
         m.assert();
     }
 
+    #[test]
+    fn multi_part_content_becomes_one_block_per_part() {
+        let content = MessageContent::Parts(vec![
+            ContentPart::Text { text: "a".into() },
+            ContentPart::Text { text: "b".into() },
+        ]);
+        assert_eq!(
+            serde_json::to_value(blocks(&content)).unwrap(),
+            serde_json::json!([{"type": "text", "text": "a"}, {"type": "text", "text": "b"}])
+        );
+    }
+
+    #[test]
+    fn images_become_image_blocks() {
+        let content = MessageContent::Parts(vec![
+            ContentPart::image_url("https://example.com/cat.png"),
+            ContentPart::image_url("data:image/jpeg;base64,/9j/4AAQ"),
+            ContentPart::image_base64("image/png", "iVBORw0"),
+        ]);
+        assert_eq!(
+            serde_json::to_value(blocks(&content)).unwrap(),
+            serde_json::json!([
+                {"type": "image", "source": {"type": "url", "url": "https://example.com/cat.png"}},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/jpeg", "data": "/9j/4AAQ"}},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0"}}
+            ])
+        );
+    }
+
+    #[test]
+    fn tool_results_share_one_user_turn() {
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .user("weather in Paris and Rome?")
+            .assistant("checking")
+            .tool("toolu_1", "18C")
+            .tool("toolu_2", "24C")
+            .user("thanks")
+            .build();
+        let (_, msgs) = to_messages(&req).unwrap();
+        assert_eq!(
+            serde_json::to_value(&msgs).unwrap()[2],
+            serde_json::json!({"role": "user", "content": [
+                {"type": "tool_result", "tool_use_id": "toolu_1",
+                 "content": [{"type": "text", "text": "18C"}]},
+                {"type": "tool_result", "tool_use_id": "toolu_2",
+                 "content": [{"type": "text", "text": "24C"}]}
+            ]})
+        );
+        assert_eq!(msgs.len(), 4);
+
+        let mut missing_id = req.clone();
+        missing_id.messages[2].tool_call_id = None;
+        assert!(matches!(
+            to_messages(&missing_id),
+            Err(AiProxyError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn names_are_folded_into_the_text() {
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .message(ChatMessage::user("ship it?").with_name("alice"))
+            .message(ChatMessage::assistant("on it").with_name("bot"))
+            .build();
+        let (_, msgs) = to_messages(&req).unwrap();
+        let v = serde_json::to_value(&msgs).unwrap();
+        assert_eq!(v[0]["content"][0]["text"], "alice: ship it?");
+        assert_eq!(v[1]["content"][0]["text"], "bot: on it");
+    }
+
+    #[test]
+    fn metadata_user_id_is_forwarded_only_when_enabled() {
+        let provider = Anthropic::new(
+            HttpClient::new_default().unwrap(),
+            SecretString::new("k".into()),
+            "http://unused".into(),
+        );
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .user("hi")
+            .client_key("client-abcd")
+            .build();
+        assert!(provider.metadata(&req).is_none());
+
+        let provider = provider.with_forward_metadata(true);
+        assert_eq!(provider.metadata(&req).unwrap().user_id, "***abcd");
+        let own = ChatRequest {
+            metadata: Some(serde_json::json!({"user_id": "u-42", "team": "search"})),
+            ..req
+        };
+        assert_eq!(provider.metadata(&own).unwrap().user_id, "u-42");
+    }
+
     #[tokio::test]
-    async fn stop_reason_matrix() {
-        use crate::model::{ChatMessage, Role};
+    async fn tool_use_blocks_become_tool_calls() {
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST).path("/v1/messages").json_body_partial(
+                r#"{"tools": [{"name": "get_weather", "input_schema": {"type": "object"}}],
+                    "tool_choice": {"type": "any"}}"#,
+            );
+            then.status(200)
+                .header("content-type", "application/json")
+                .body(
+                    r#"{
+                    "id": "msg_1",
+                    "content": [
+                        { "type": "text", "text": "checking" },
+                        { "type": "tool_use", "id": "toolu_1", "name": "get_weather",
+                          "input": { "city": "Paris" } }
+                    ],
+                    "stop_reason": "tool_use"
+                }"#,
+                );
+        });
+        let provider = Anthropic::new(
+            HttpClient::new_default().unwrap(),
+            SecretString::new("k".into()),
+            server.base_url(),
+        );
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .user("weather?")
+            .tools([crate::model::ToolSpec {
+                name: "get_weather".into(),
+                description: None,
+                parameters: serde_json::json!({"type": "object"}),
+            }])
+            .tool_choice(crate::model::ToolChoice::Required)
+            .build();
+
+        let resp = provider.chat(req.clone()).await.unwrap();
+        assert_eq!(resp.choices.len(), 1);
+        let choice = &resp.choices[0];
+        assert_eq!(choice.text, "checking");
+        assert_eq!(choice.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(
+            choice.tool_calls,
+            vec![ToolCall {
+                id: "toolu_1".into(),
+                name: "get_weather".into(),
+                arguments: serde_json::json!({"city": "Paris"}),
+            }]
+        );
+        assert_eq!(resp.tool_calls, choice.tool_calls);
+
+        // Streams announce each call before the final response.
+        use futures_util::StreamExt;
+        let events: Vec<_> = provider
+            .chat_stream_events(req)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert!(matches!(&events[0], crate::stream::StreamEvent::ToolCall(c) if c.id == "toolu_1"));
+        assert!(matches!(events[1], crate::stream::StreamEvent::Final(_)));
+    }
 
+    #[test]
+    fn cache_tokens_count_towards_prompt() {
+        let u: AUsage = serde_json::from_value(serde_json::json!({
+            "input_tokens": 10,
+            "output_tokens": 3,
+            "cache_read_input_tokens": 200,
+            "cache_creation_input_tokens": 50
+        }))
+        .unwrap();
+        let usage = Usage::from(u);
+        assert_eq!(usage.prompt, 260);
+        assert_eq!(usage.cached_prompt, Some(200));
+        assert_eq!(usage.reasoning, None);
+    }
+
+    #[tokio::test]
+    async fn stop_reason_matrix() {
         let cases = [
             (r#""end_turn""#, Some(StopReason::EndTurn)),
             (r#""max_tokens""#, Some(StopReason::Length)),
@@ -448,22 +1046,11 @@ mod tests {
                 server.base_url(),
             );
 
-            let req = ChatRequest {
-                model: "claude-3-haiku".into(),
-                messages: vec![ChatMessage {
-                    role: Role::User,
-                    content: "hi".into(),
-                }],
-                temperature: None,
-                top_p: None,
-                metadata: None,
-                client_key: None,
-                request_id: None,
-                trace_id: None,
-                idempotency_key: None,
-                max_output_tokens: Some(32),
-                stop_sequences: None,
-            };
+            let req = ChatRequest::builder()
+                .model("claude-3-haiku")
+                .user("hi")
+                .max_output_tokens(32)
+                .build();
 
             let resp = provider.chat(req).await.unwrap();
             assert_eq!(resp.stop_reason, expect);
@@ -472,8 +1059,6 @@ mod tests {
 
     #[tokio::test]
     async fn headers_present() {
-        use crate::model::{ChatMessage, Role};
-
         let server = MockServer::start();
         let m = server.mock(|when, then| {
             when.method(POST)
@@ -491,24 +1076,66 @@ mod tests {
             server.base_url(),
         );
 
-        let req = ChatRequest {
-            model: "claude-3-haiku".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: Some(16),
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder()
+            .model("claude-3-haiku")
+            .user("hi")
+            .max_output_tokens(16)
+            .build();
 
         let _ = provider.chat(req).await.unwrap();
         m.assert(); // verifies headers matched
     }
+
+    struct AnthropicFixture;
+
+    impl crate::providers::conformance::ChatFixture for AnthropicFixture {
+        type Provider = Anthropic;
+
+        fn provider(&self, base_url: &str) -> Anthropic {
+            Anthropic::new(
+                HttpClient::new_default().unwrap(),
+                SecretString::new("test-key".into()),
+                base_url.to_string(),
+            )
+        }
+
+        fn chat_path(&self) -> &str {
+            "/v1/messages"
+        }
+
+        fn model(&self) -> &str {
+            "claude-3-haiku"
+        }
+
+        fn stop_reasons(&self) -> Vec<(Option<&'static str>, Option<StopReason>)> {
+            vec![
+                (Some("end_turn"), Some(StopReason::EndTurn)),
+                (Some("max_tokens"), Some(StopReason::Length)),
+                (Some("tool_use"), Some(StopReason::ToolUse)),
+                (Some("stop_sequence"), Some(StopReason::Stop)),
+                (Some("refusal"), Some(StopReason::Refusal)),
+                (None, None),
+            ]
+        }
+
+        fn success_body(
+            &self,
+            text: &str,
+            stop: Option<&str>,
+            prompt: u32,
+            completion: u32,
+        ) -> serde_json::Value {
+            serde_json::json!({
+                "id": "msg_conformance",
+                "content": [{"type": "text", "text": text}],
+                "stop_reason": stop,
+                "usage": {"input_tokens": prompt, "output_tokens": completion}
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_the_conformance_suite() {
+        crate::providers::conformance::check_chat(&AnthropicFixture).await;
+    }
 }
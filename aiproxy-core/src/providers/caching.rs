@@ -0,0 +1,183 @@
+//! Response caching: `CachingProvider` answers repeated chat and embed requests from a
+//! `cache::ResponseCache` and stores what `inner` returns for the next caller. Streams pass
+//! through uncached.
+//!
+//! Every cacheable call emits a `CacheLog` hit or miss. A cache that cannot be read or
+//! written is treated as a miss, so it only ever costs the call it would have saved.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::cache::{self, ResponseCache};
+use crate::error::CoreResult;
+use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::provider::{ChatProvider, EmbedProvider};
+use crate::stream::BoxStreamEv;
+use crate::telemetry::{self, CacheLog};
+
+/// Decorator serving `inner`'s repeated requests from `cache`.
+#[derive(Debug)]
+pub struct CachingProvider<P: ?Sized> {
+    inner: Arc<P>,
+    cache: Arc<ResponseCache>,
+}
+
+impl<P: ?Sized> CachingProvider<P> {
+    pub fn new(inner: Arc<P>, cache: Arc<ResponseCache>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+fn log(provider: &str, model: &str, kind: &'static str, hit: bool) {
+    telemetry::emit_cache(CacheLog {
+        provider: provider.to_string(),
+        model: model.to_string(),
+        kind,
+        hit,
+    });
+}
+
+#[async_trait]
+impl ChatProvider for CachingProvider<dyn ChatProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let name = self.inner.name();
+        let Some(key) = cache::chat_key(name, &req) else {
+            return self.inner.chat(req).await;
+        };
+        if let Ok(Some(mut resp)) = self.cache.get::<ChatResponse>(&key).await {
+            log(name, &req.model, "chat", true);
+            resp.cached = true;
+            return Ok(resp);
+        }
+        log(name, &req.model, "chat", false);
+        let resp = self.inner.chat(req).await?;
+        let _ = self.cache.put(&key, &resp).await;
+        Ok(resp)
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        self.inner.chat_stream_events(req).await
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.inner.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.inner.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.inner.count_tokens(req).await
+    }
+}
+
+#[async_trait]
+impl EmbedProvider for CachingProvider<dyn EmbedProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        let name = self.inner.name();
+        let key = cache::embed_key(name, &req);
+        if let Ok(Some(mut resp)) = self.cache.get::<EmbedResponse>(&key).await {
+            log(name, &req.model, "embed", true);
+            resp.cached = true;
+            return Ok(resp);
+        }
+        log(name, &req.model, "embed", false);
+        let resp = self.inner.embed(req).await?;
+        let _ = self.cache.put(&key, &resp).await;
+        Ok(resp)
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NullProvider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// `NullProvider` that counts the calls reaching it.
+    #[derive(Debug, Default)]
+    struct Counted(AtomicUsize);
+
+    #[async_trait]
+    impl ChatProvider for Counted {
+        fn name(&self) -> &str {
+            "counted"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            NullProvider.chat(req).await
+        }
+    }
+
+    #[async_trait]
+    impl EmbedProvider for Counted {
+        fn name(&self) -> &str {
+            "counted"
+        }
+
+        async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            NullProvider.embed(req).await
+        }
+    }
+
+    fn cache() -> Arc<ResponseCache> {
+        Arc::new(ResponseCache::open_in_memory(Duration::from_secs(60)).unwrap())
+    }
+
+    #[tokio::test]
+    async fn repeated_chats_are_served_from_the_cache() {
+        let inner = Arc::new(Counted::default());
+        let p = CachingProvider::new(inner.clone() as Arc<dyn ChatProvider>, cache());
+        let req = |id: &str| {
+            ChatRequest::builder()
+                .model("m")
+                .user("hi")
+                .request_id(id)
+                .build()
+        };
+        assert!(!p.chat(req("r1")).await.unwrap().cached);
+        let again = p.chat(req("r2")).await.unwrap();
+        assert!(again.cached);
+        assert_eq!(again.text, "[null provider response]");
+        assert_eq!(inner.0.load(Ordering::SeqCst), 1);
+
+        let mut raw = req("r3");
+        raw.include_raw = true;
+        assert!(!p.chat(raw).await.unwrap().cached);
+        assert_eq!(inner.0.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn repeated_embeds_are_served_from_the_cache() {
+        let inner = Arc::new(Counted::default());
+        let p = CachingProvider::new(inner.clone() as Arc<dyn EmbedProvider>, cache());
+        let req = EmbedRequest {
+            model: "m".into(),
+            inputs: vec!["a".into(), "b".into()],
+            client_key: None,
+            requested_model: None,
+        };
+        let first = p.embed(req.clone()).await.unwrap();
+        let again = p.embed(req).await.unwrap();
+        assert!(again.cached);
+        assert_eq!(again.vectors, first.vectors);
+        assert_eq!(inner.0.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,259 @@
+//! Fault injection: `ChaosProvider` wraps a registered provider and makes a configured share
+//! of its calls slow, rate limited, failing, malformed or cut off mid-stream, so retries,
+//! fallbacks and error mapping can be exercised end to end without a misbehaving upstream.
+//!
+//! Injected errors are the same `AiProxyError` values the HTTP adapters produce for the real
+//! faults, so everything downstream handles them exactly as it would in production.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+
+use super::hash::splitmix;
+use crate::config::FaultCfg;
+use crate::error::{AiProxyError, CoreResult, Upstream};
+use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::provider::{ChatProvider, EmbedProvider};
+use crate::stream::{BoxStreamEv, StreamEvent};
+
+/// One injected fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    RateLimit,
+    ServerError,
+    Malformed,
+    Disconnect,
+}
+
+/// Decorator injecting `FaultCfg`'s faults into `inner`'s calls.
+#[derive(Debug)]
+pub struct ChaosProvider<P: ?Sized> {
+    inner: Arc<P>,
+    cfg: FaultCfg,
+    rng: Mutex<u64>,
+}
+
+impl<P: ?Sized> ChaosProvider<P> {
+    pub fn new(inner: Arc<P>, cfg: FaultCfg) -> Self {
+        let seed = cfg.seed.unwrap_or_else(|| {
+            crate::platform::SystemTime::now()
+                .duration_since(crate::platform::UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64)
+        });
+        Self {
+            inner,
+            cfg,
+            rng: Mutex::new(seed),
+        }
+    }
+
+    /// Draw the fault, if any, for the next call.
+    pub fn next_fault(&self) -> Option<Fault> {
+        let draw = (splitmix(&mut self.rng.lock().unwrap()) >> 11) as f64 / (1u64 << 53) as f64;
+        let mut bound = 0.0;
+        for (fault, p) in [
+            (Fault::RateLimit, self.cfg.rate_limit),
+            (Fault::ServerError, self.cfg.server_error),
+            (Fault::Malformed, self.cfg.malformed),
+            (Fault::Disconnect, self.cfg.disconnect),
+        ] {
+            bound += p;
+            if draw < bound {
+                return Some(fault);
+            }
+        }
+        None
+    }
+
+    async fn delay(&self) {
+        if self.cfg.latency_ms > 0 {
+            crate::platform::sleep(Duration::from_millis(self.cfg.latency_ms)).await;
+        }
+    }
+}
+
+/// The error a call hit by `fault` fails with, before any response arrives.
+fn fault_error(fault: Fault, provider: &str, model: &str) -> AiProxyError {
+    let upstream = |status| {
+        Box::new(Upstream {
+            model: Some(model.to_string()),
+            status,
+            provider_request_id: None,
+        })
+    };
+    match fault {
+        Fault::RateLimit => AiProxyError::RateLimited {
+            provider: provider.to_string(),
+            retry_after: Some(1),
+            remaining_requests: None,
+            remaining_tokens: None,
+            reset_at_ms: None,
+            upstream: upstream(Some(429)),
+        },
+        Fault::ServerError => AiProxyError::ProviderUnavailable {
+            provider: provider.to_string(),
+            upstream: upstream(Some(503)),
+        },
+        Fault::Malformed => AiProxyError::ProviderError {
+            provider: provider.to_string(),
+            code: "200".into(),
+            message: "json decode error: injected malformed body".into(),
+            upstream: upstream(Some(200)),
+        },
+        // Without a stream to cut, a dropped connection looks like an unreachable provider.
+        Fault::Disconnect => AiProxyError::ProviderUnavailable {
+            provider: provider.to_string(),
+            upstream: upstream(None),
+        },
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for ChaosProvider<dyn ChatProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        self.delay().await;
+        match self.next_fault() {
+            Some(fault) => Err(fault_error(fault, self.inner.name(), &req.model)),
+            None => self.inner.chat(req).await,
+        }
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        self.delay().await;
+        let provider = self.inner.name().to_string();
+        match self.next_fault() {
+            None => return self.inner.chat_stream_events(req).await,
+            Some(Fault::Disconnect) => {}
+            Some(fault) => return Err(fault_error(fault, &provider, &req.model)),
+        }
+        let mut inner = self.inner.chat_stream_events(req).await?;
+        let first = inner.next().await;
+        let bytes_received = first
+            .as_ref()
+            .and_then(StreamEvent::as_text_delta)
+            .map_or(0, |t| t.len() as u64);
+        let head = first.filter(|ev| !ev.is_terminal());
+        let cut = StreamEvent::Error(AiProxyError::StreamDisconnected {
+            provider,
+            bytes_received,
+        });
+        Ok(Box::pin(futures::stream::iter(
+            head.into_iter().chain([cut]),
+        )))
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.inner.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.inner.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.inner.count_tokens(req).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for ChaosProvider<dyn EmbedProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        self.delay().await;
+        match self.next_fault() {
+            Some(fault) => Err(fault_error(fault, self.inner.name(), &req.model)),
+            None => self.inner.embed(req).await,
+        }
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NullProvider;
+
+    fn chaos(cfg: FaultCfg) -> ChaosProvider<dyn ChatProvider> {
+        ChaosProvider::new(Arc::new(NullProvider) as Arc<dyn ChatProvider>, cfg)
+    }
+
+    fn req() -> ChatRequest {
+        ChatRequest::builder().model("m").user("hi").build()
+    }
+
+    #[tokio::test]
+    async fn faults_fire_at_their_configured_rates() {
+        let p = chaos(FaultCfg {
+            rate_limit: 0.25,
+            server_error: 0.25,
+            seed: Some(42),
+            ..FaultCfg::default()
+        });
+        let (mut limited, mut unavailable, mut ok) = (0, 0, 0);
+        for _ in 0..2_000 {
+            match p.chat(req()).await {
+                Ok(_) => ok += 1,
+                Err(AiProxyError::RateLimited { .. }) => limited += 1,
+                Err(AiProxyError::ProviderUnavailable { .. }) => unavailable += 1,
+                Err(e) => panic!("unexpected {e}"),
+            }
+        }
+        for n in [limited, unavailable] {
+            assert!((400..600).contains(&n), "{n}");
+        }
+        assert!((900..1_100).contains(&ok), "{ok}");
+
+        // The same seed replays the same sequence.
+        let cfg = FaultCfg {
+            malformed: 0.5,
+            seed: Some(7),
+            ..FaultCfg::default()
+        };
+        let (a, b) = (chaos(cfg.clone()), chaos(cfg));
+        let draws = |p: &ChaosProvider<dyn ChatProvider>| {
+            (0..50).map(|_| p.next_fault()).collect::<Vec<_>>()
+        };
+        assert_eq!(draws(&a), draws(&b));
+        assert!(chaos(FaultCfg::default()).next_fault().is_none());
+    }
+
+    #[tokio::test]
+    async fn disconnects_cut_streams_and_malformed_bodies_fail_decoding() {
+        let p = chaos(FaultCfg {
+            disconnect: 1.0,
+            latency_ms: 5,
+            ..FaultCfg::default()
+        });
+        let started = std::time::Instant::now();
+        let events: Vec<StreamEvent> = p.chat_stream_events(req()).await.unwrap().collect().await;
+        assert!(started.elapsed() >= Duration::from_millis(5));
+        let last = events.last().unwrap();
+        assert!(matches!(
+            last,
+            StreamEvent::Error(AiProxyError::StreamDisconnected { .. })
+        ));
+        assert_eq!(events.iter().filter(|e| e.is_terminal()).count(), 1);
+
+        let p = chaos(FaultCfg {
+            malformed: 1.0,
+            ..FaultCfg::default()
+        });
+        let err = p.chat(req()).await.unwrap_err();
+        assert!(matches!(err, AiProxyError::ProviderError { .. }));
+        assert!(err.to_string().contains("json decode error"));
+    }
+}
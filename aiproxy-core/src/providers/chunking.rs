@@ -0,0 +1,232 @@
+//! Embedding batches larger than one upstream call allows: `ChunkingEmbedProvider` splits
+//! them by the model's per-request input and token limits (from the catalog, else
+//! `[embeddings]`, else the provider's `max_batch_inputs`), sends the pieces
+//! `[embeddings].concurrency` at a time and merges the vectors back in input order.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt, stream};
+
+use crate::catalog;
+use crate::config::EmbeddingsCfg;
+use crate::error::CoreResult;
+use crate::model::{EmbedRequest, EmbedResponse};
+use crate::provider::EmbedProvider;
+use crate::tokenizer;
+
+/// Decorator splitting `inner`'s oversized embed requests into several calls.
+#[derive(Debug)]
+pub struct ChunkingEmbedProvider {
+    inner: Arc<dyn EmbedProvider>,
+    cfg: EmbeddingsCfg,
+}
+
+impl ChunkingEmbedProvider {
+    /// Split with `EmbeddingsCfg::default()`.
+    pub fn new(inner: Arc<dyn EmbedProvider>) -> Self {
+        Self {
+            inner,
+            cfg: EmbeddingsCfg::default(),
+        }
+    }
+
+    pub fn with_cfg(mut self, cfg: &EmbeddingsCfg) -> Self {
+        self.cfg = cfg.clone();
+        self
+    }
+}
+
+/// Split `inputs` into consecutive runs of at most `max_inputs` inputs and `max_tokens`
+/// tokens. An input over `max_tokens` on its own still gets a call, for the provider to
+/// reject.
+fn plan(
+    model: &str,
+    inputs: &[String],
+    max_inputs: usize,
+    max_tokens: Option<usize>,
+) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    let (mut count, mut tokens) = (0, 0);
+    for input in inputs {
+        let t = max_tokens.map_or(0, |_| tokenizer::count_text(model, input));
+        if count > 0 && (count == max_inputs || max_tokens.is_some_and(|max| tokens + t > max)) {
+            sizes.push(count);
+            (count, tokens) = (0, 0);
+        }
+        count += 1;
+        tokens += t;
+    }
+    if count > 0 {
+        sizes.push(count);
+    }
+    sizes
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for ChunkingEmbedProvider {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        let provider_max = self.inner.max_batch_inputs().max(1);
+        let max_inputs = catalog::max_batch_inputs(&req.model)
+            .or(self.cfg.max_batch_inputs)
+            .map_or(provider_max, |n| (n as usize).clamp(1, provider_max));
+        let max_tokens = catalog::max_batch_tokens(&req.model)
+            .or(self.cfg.max_batch_tokens)
+            .map(|n| n as usize);
+        let sizes = plan(&req.model, &req.inputs, max_inputs, max_tokens);
+        if sizes.len() <= 1 {
+            return self.inner.embed(req).await;
+        }
+
+        let mut inputs = req.inputs.into_iter();
+        let chunks: Vec<EmbedRequest> = sizes
+            .into_iter()
+            .map(|n| EmbedRequest {
+                model: req.model.clone(),
+                inputs: inputs.by_ref().take(n).collect(),
+                client_key: req.client_key.clone(),
+                requested_model: req.requested_model.clone(),
+            })
+            .collect();
+        // `buffered` yields results in submission order, so vectors stay in input order.
+        let parts: Vec<EmbedResponse> = stream::iter(chunks)
+            .map(|chunk| self.inner.embed(chunk))
+            .buffered(self.cfg.concurrency.max(1))
+            .try_collect()
+            .await?;
+        let mut parts = parts.into_iter();
+        let mut merged = parts.next().expect("at least two chunks");
+        for part in parts {
+            merged.vectors.extend(part.vectors);
+            merged.usage += part.usage;
+            merged.cached &= part.cached;
+            merged.cost_usd = match (merged.cost_usd, part.cost_usd) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+            };
+        }
+        Ok(merged)
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Answers each input with `[the input as a number, call size]` and records call sizes.
+    #[derive(Debug, Default)]
+    struct Recorder {
+        calls: Mutex<Vec<usize>>,
+    }
+
+    #[async_trait]
+    impl EmbedProvider for Recorder {
+        fn name(&self) -> &str {
+            "recorder"
+        }
+
+        async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+            let n = req.inputs.len();
+            self.calls.lock().unwrap().push(n);
+            Ok(EmbedResponse {
+                model: req.model,
+                vectors: req
+                    .inputs
+                    .iter()
+                    .map(|s| vec![s.parse::<f32>().unwrap(), n as f32])
+                    .collect(),
+                usage: n as u32,
+                cached: false,
+                provider: "recorder".into(),
+                cost_usd: Some(0.5),
+            })
+        }
+
+        fn max_batch_inputs(&self) -> usize {
+            3
+        }
+    }
+
+    fn request(model: &str, n: usize) -> EmbedRequest {
+        EmbedRequest {
+            model: model.into(),
+            inputs: (0..n).map(|i| i.to_string()).collect(),
+            client_key: None,
+            requested_model: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_requests_split_and_merge_in_order() {
+        let inner = Arc::new(Recorder::default());
+        let chunking = ChunkingEmbedProvider::new(inner.clone());
+        let resp = chunking.embed(request("custom-embedder", 8)).await.unwrap();
+        assert_eq!(*inner.calls.lock().unwrap(), [3, 3, 2]);
+        let order: Vec<f32> = resp.vectors.iter().map(|v| v[0]).collect();
+        assert_eq!(order, [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0]);
+        assert_eq!(resp.usage, 8);
+        assert_eq!(resp.cost_usd, Some(1.5));
+
+        inner.calls.lock().unwrap().clear();
+        chunking.embed(request("custom-embedder", 3)).await.unwrap();
+        assert_eq!(
+            *inner.calls.lock().unwrap(),
+            [3],
+            "requests that fit pass through"
+        );
+    }
+
+    #[tokio::test]
+    async fn configured_limits_apply_below_the_provider_limit() {
+        let inner = Arc::new(Recorder::default());
+        let chunking = ChunkingEmbedProvider::new(inner.clone()).with_cfg(&EmbeddingsCfg {
+            max_batch_inputs: Some(2),
+            concurrency: 1,
+            ..EmbeddingsCfg::default()
+        });
+        let resp = chunking.embed(request("custom-embedder", 5)).await.unwrap();
+        assert_eq!(*inner.calls.lock().unwrap(), [2, 2, 1]);
+        assert_eq!(resp.vectors.len(), 5);
+
+        inner.calls.lock().unwrap().clear();
+        let capped = ChunkingEmbedProvider::new(inner.clone()).with_cfg(&EmbeddingsCfg {
+            max_batch_inputs: Some(10),
+            ..EmbeddingsCfg::default()
+        });
+        capped.embed(request("custom-embedder", 5)).await.unwrap();
+        assert_eq!(
+            *inner.calls.lock().unwrap(),
+            [3, 2],
+            "the provider's limit still holds"
+        );
+    }
+
+    #[test]
+    fn plans_respect_the_token_limit() {
+        let inputs: Vec<String> = ["a b c d", "e f g h", "i", "j k l m n o p q"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let tokens: Vec<usize> = inputs
+            .iter()
+            .map(|s| tokenizer::count_text("text-embedding-3-small", s))
+            .collect();
+        let limit = tokens[0] + tokens[1];
+        assert_eq!(
+            plan("text-embedding-3-small", &inputs, 10, Some(limit)),
+            [2, 1, 1]
+        );
+        assert_eq!(plan("m", &inputs, 10, None), [4]);
+        assert!(plan("m", &[], 10, None).is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+//! Circuit breaking configured by `[http.circuit]`: once a provider has been rate limited or
+//! unavailable `failure_threshold` calls in a row, its circuit opens and `CircuitBreakerProvider`
+//! fails every call at once with `ProviderUnavailable` for `cooldown_ms`. A fallback chain
+//! (`providers::failover`) then moves straight on to its next provider; a lone provider
+//! fails fast instead of waiting out timeouts and retries.
+//!
+//! After the cooldown the circuit is half-open and lets one call through. Success closes it.
+//! Another failure opens it for a new cooldown. Any other outcome, a rejected request
+//! included, shows the provider is up and resets the count. Streams count as succeeded
+//! once they start.
+//!
+//! A provider's chat and embed calls share one `CircuitBreaker`. Each transition is emitted
+//! as a `telemetry::CircuitLog`.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::config::CircuitCfg;
+use crate::error::{AiProxyError, CoreResult, Upstream};
+use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::platform::Instant;
+use crate::provider::{ChatProvider, EmbedProvider};
+use crate::stream::BoxStreamEv;
+use crate::telemetry::{self, CircuitLog, CircuitState};
+
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    Closed,
+    Open(Instant),
+    /// A test call has been in flight since then.
+    HalfOpen(Instant),
+}
+
+#[derive(Debug)]
+struct Breaker {
+    phase: Phase,
+    failures: u32,
+}
+
+/// One provider's circuit, shared by the decorators wrapping its chat and embed calls.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    provider: String,
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<Breaker>,
+}
+
+impl CircuitBreaker {
+    pub fn new(provider: &str, cfg: &CircuitCfg) -> Self {
+        Self {
+            provider: provider.to_string(),
+            threshold: cfg.failure_threshold.max(1),
+            cooldown: Duration::from_millis(cfg.cooldown_ms),
+            state: Mutex::new(Breaker {
+                phase: Phase::Closed,
+                failures: 0,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match self.state.lock().unwrap().phase {
+            Phase::Closed => CircuitState::Closed,
+            Phase::Open(_) => CircuitState::Open,
+            Phase::HalfOpen(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Let a call for `model` through, or fail it while the circuit is open. A test call
+    /// that never reported back (its future was dropped) is replaced after a cooldown.
+    fn admit(&self, model: &str) -> CoreResult<()> {
+        let mut st = self.state.lock().unwrap();
+        match st.phase {
+            Phase::Closed => return Ok(()),
+            Phase::Open(since) | Phase::HalfOpen(since) if since.elapsed() < self.cooldown => {
+                return Err(AiProxyError::ProviderUnavailable {
+                    provider: self.provider.clone(),
+                    upstream: Box::new(Upstream {
+                        model: Some(model.to_string()),
+                        ..Upstream::default()
+                    }),
+                });
+            }
+            Phase::Open(_) => self.emit(CircuitState::HalfOpen, st.failures),
+            Phase::HalfOpen(_) => {}
+        }
+        st.phase = Phase::HalfOpen(Instant::now());
+        Ok(())
+    }
+
+    /// Count the outcome of an admitted call.
+    fn settle<T>(&self, result: &CoreResult<T>) {
+        let failed = matches!(
+            result,
+            Err(AiProxyError::RateLimited { .. } | AiProxyError::ProviderUnavailable { .. })
+        );
+        let mut st = self.state.lock().unwrap();
+        if !failed {
+            if !matches!(st.phase, Phase::Closed) {
+                self.emit(CircuitState::Closed, st.failures);
+            }
+            *st = Breaker {
+                phase: Phase::Closed,
+                failures: 0,
+            };
+            return;
+        }
+        st.failures += 1;
+        let reopen = match st.phase {
+            Phase::Closed => st.failures >= self.threshold,
+            Phase::HalfOpen(_) => true,
+            Phase::Open(_) => false,
+        };
+        if reopen {
+            st.phase = Phase::Open(Instant::now());
+            tracing::warn!(provider = %self.provider, failures = st.failures, "circuit opened");
+            self.emit(CircuitState::Open, st.failures);
+        }
+    }
+
+    fn emit(&self, state: CircuitState, failures: u32) {
+        telemetry::emit_circuit(CircuitLog {
+            provider: self.provider.clone(),
+            state,
+            failures,
+        });
+    }
+}
+
+/// Decorator failing `inner`'s calls fast while `breaker` is open.
+#[derive(Debug)]
+pub struct CircuitBreakerProvider<P: ?Sized> {
+    inner: Arc<P>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl<P: ?Sized> CircuitBreakerProvider<P> {
+    pub fn new(inner: Arc<P>, breaker: Arc<CircuitBreaker>) -> Self {
+        Self { inner, breaker }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for CircuitBreakerProvider<dyn ChatProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        self.breaker.admit(&req.model)?;
+        let result = self.inner.chat(req).await;
+        self.breaker.settle(&result);
+        result
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        self.breaker.admit(&req.model)?;
+        let result = self.inner.chat_stream_events(req).await;
+        self.breaker.settle(&result);
+        result
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.inner.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.inner.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.inner.count_tokens(req).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for CircuitBreakerProvider<dyn EmbedProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        self.breaker.admit(&req.model)?;
+        let result = self.inner.embed(req).await;
+        self.breaker.settle(&result);
+        result
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NullProvider;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    /// Answers like `NullProvider`, or fails with `ProviderUnavailable` while `down`.
+    #[derive(Debug, Default)]
+    struct Flaky {
+        down: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ChatProvider for Flaky {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.down.load(Ordering::SeqCst) {
+                return Err(AiProxyError::ProviderUnavailable {
+                    provider: "flaky".into(),
+                    upstream: Box::new(Upstream::default()),
+                });
+            }
+            NullProvider.chat(req).await
+        }
+    }
+
+    fn breaker(cooldown_ms: u64) -> Arc<CircuitBreaker> {
+        Arc::new(CircuitBreaker::new(
+            "flaky",
+            &CircuitCfg {
+                failure_threshold: 2,
+                cooldown_ms,
+            },
+        ))
+    }
+
+    fn req() -> ChatRequest {
+        ChatRequest::builder().model("m").user("hi").build()
+    }
+
+    #[tokio::test]
+    async fn consecutive_failures_open_the_circuit_until_the_cooldown() {
+        let flaky = Arc::new(Flaky::default());
+        let breaker = breaker(60_000);
+        let p =
+            CircuitBreakerProvider::new(flaky.clone() as Arc<dyn ChatProvider>, breaker.clone());
+
+        flaky.down.store(true, Ordering::SeqCst);
+        assert!(p.chat(req()).await.is_err());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(p.chat(req()).await.is_err());
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // Open: calls fail without reaching the provider, even once it has recovered.
+        flaky.down.store(false, Ordering::SeqCst);
+        let err = p.chat(req()).await.unwrap_err();
+        assert!(matches!(err, AiProxyError::ProviderUnavailable { .. }));
+        let err = p.chat_stream_events(req()).await.err().unwrap();
+        assert!(matches!(err, AiProxyError::ProviderUnavailable { .. }));
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn a_half_open_call_closes_or_reopens_the_circuit() {
+        let flaky = Arc::new(Flaky::default());
+        let breaker = breaker(20);
+        let p =
+            CircuitBreakerProvider::new(flaky.clone() as Arc<dyn ChatProvider>, breaker.clone());
+        flaky.down.store(true, Ordering::SeqCst);
+        for _ in 0..2 {
+            let _ = p.chat(req()).await;
+        }
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // The test call after the cooldown fails: open again, straight away.
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(p.chat(req()).await.is_err());
+        assert_eq!(flaky.calls.load(Ordering::SeqCst), 3);
+        assert_eq!(breaker.state(), CircuitState::Open);
+
+        // It succeeds: closed, and failures count from zero.
+        flaky.down.store(false, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(p.chat(req()).await.is_ok());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        flaky.down.store(true, Ordering::SeqCst);
+        let _ = p.chat(req()).await;
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
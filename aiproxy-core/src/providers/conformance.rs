@@ -0,0 +1,340 @@
+//! Conformance suite for provider adapters. An adapter describes its wire format once, as a
+//! `ChatFixture` (and an `EmbedFixture` if it embeds), and `check_chat` / `check_embed` run it
+//! against a mock server: stop-reason mapping, usage parsing, the error taxonomy, header
+//! propagation and the streaming terminal-event contract. A new adapter gets the same
+//! coverage as the built-in ones by adding one fixture to its tests.
+//!
+//! The checks panic on the first violation, so they are meant to be called from `#[test]`s.
+
+use futures_util::StreamExt;
+use httpmock::prelude::*;
+use serde_json::Value;
+
+use crate::error::AiProxyError;
+use crate::model::{ChatRequest, EmbedRequest, StopReason};
+use crate::provider::{ChatProvider, EmbedProvider};
+use crate::stream::StreamEvent;
+
+const TEXT: &str = "Hello there";
+const PROMPT_TOKENS: u32 = 11;
+const COMPLETION_TOKENS: u32 = 7;
+
+/// How a chat adapter talks to its upstream.
+pub trait ChatFixture {
+    type Provider: ChatProvider;
+
+    /// The adapter pointed at `base_url`.
+    fn provider(&self, base_url: &str) -> Self::Provider;
+
+    /// The chat endpoint, e.g. `/v1/chat/completions`.
+    fn chat_path(&self) -> &str;
+
+    /// A model name the adapter accepts.
+    fn model(&self) -> &str {
+        "conformance-model"
+    }
+
+    /// Vendor stop reasons (`None` for an absent or null one) and what each must map to.
+    /// The first entry is used as the stop reason of the other checks' responses.
+    fn stop_reasons(&self) -> Vec<(Option<&'static str>, Option<StopReason>)>;
+
+    /// A successful non-streaming response carrying `text`, `stop` and the token counts.
+    fn success_body(&self, text: &str, stop: Option<&str>, prompt: u32, completion: u32) -> Value;
+
+    /// A streamed response that sends `deltas` then stops with `stop`, or `None` if the
+    /// adapter streams by falling back to a single non-streaming call.
+    fn stream_body(&self, _deltas: &[&str], _stop: Option<&str>) -> Option<String> {
+        None
+    }
+}
+
+/// How an embedding adapter talks to its upstream.
+pub trait EmbedFixture {
+    type Provider: EmbedProvider;
+
+    fn provider(&self, base_url: &str) -> Self::Provider;
+
+    /// The embeddings endpoint, e.g. `/v1/embeddings`.
+    fn embed_path(&self) -> &str;
+
+    fn model(&self) -> &str {
+        "conformance-embed"
+    }
+
+    /// A successful response carrying `vectors` in input order and `prompt` tokens.
+    fn success_body(&self, vectors: &[Vec<f32>], prompt: u32) -> Value;
+}
+
+/// Run every chat check against `fixture`.
+pub async fn check_chat<F: ChatFixture>(fixture: &F) {
+    check_stop_reasons(fixture).await;
+    check_usage(fixture).await;
+    check_chat_errors(fixture).await;
+    check_header_propagation(fixture).await;
+    check_stream_contract(fixture).await;
+}
+
+/// Run every embedding check against `fixture`.
+pub async fn check_embed<F: EmbedFixture>(fixture: &F) {
+    let server = MockServer::start_async().await;
+    let vectors = vec![vec![0.25, -0.5], vec![1.0, 0.0]];
+    server
+        .mock_async(|when, then| {
+            when.method(POST).path(fixture.embed_path());
+            then.status(200)
+                .json_body(fixture.success_body(&vectors, PROMPT_TOKENS));
+        })
+        .await;
+    let provider = fixture.provider(&server.base_url());
+    let resp = provider
+        .embed(embed_request(fixture))
+        .await
+        .expect("embed succeeds");
+    assert_eq!(resp.vectors, vectors, "vectors keep input order");
+    assert_eq!(resp.usage, PROMPT_TOKENS, "embedding usage");
+
+    for (status, retry_after) in error_cases() {
+        let server = error_server(fixture.embed_path(), status, retry_after).await;
+        let provider = fixture.provider(&server.base_url());
+        let err = provider.embed(embed_request(fixture)).await.unwrap_err();
+        assert_error_taxonomy(&err, status, retry_after);
+    }
+}
+
+fn chat_request<F: ChatFixture>(fixture: &F) -> ChatRequest {
+    ChatRequest::builder()
+        .model(fixture.model())
+        .user("Hi")
+        .build()
+}
+
+fn embed_request<F: EmbedFixture>(fixture: &F) -> EmbedRequest {
+    EmbedRequest::builder()
+        .model(fixture.model())
+        .inputs(["a", "b"])
+        .build()
+}
+
+fn first_stop<F: ChatFixture>(fixture: &F) -> (Option<&'static str>, Option<StopReason>) {
+    fixture
+        .stop_reasons()
+        .first()
+        .copied()
+        .expect("stop_reasons has at least one entry")
+}
+
+async fn success_server<F: ChatFixture>(fixture: &F, stop: Option<&str>) -> MockServer {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(POST).path(fixture.chat_path());
+            then.status(200).json_body(fixture.success_body(
+                TEXT,
+                stop,
+                PROMPT_TOKENS,
+                COMPLETION_TOKENS,
+            ));
+        })
+        .await;
+    server
+}
+
+async fn check_stop_reasons<F: ChatFixture>(fixture: &F) {
+    for (wire, expected) in fixture.stop_reasons() {
+        let server = success_server(fixture, wire).await;
+        let provider = fixture.provider(&server.base_url());
+        let resp = provider
+            .chat(chat_request(fixture))
+            .await
+            .expect("chat succeeds");
+        assert_eq!(resp.stop_reason, expected, "stop reason {wire:?}");
+        assert_eq!(resp.text, TEXT, "response text");
+    }
+}
+
+async fn check_usage<F: ChatFixture>(fixture: &F) {
+    let server = success_server(fixture, first_stop(fixture).0).await;
+    let provider = fixture.provider(&server.base_url());
+    let resp = provider.chat(chat_request(fixture)).await.unwrap();
+    assert_eq!(resp.usage.prompt, PROMPT_TOKENS, "prompt tokens");
+    assert_eq!(
+        resp.usage.completion, COMPLETION_TOKENS,
+        "completion tokens"
+    );
+    assert_eq!(resp.provider, provider.name(), "response provider");
+}
+
+/// Statuses every adapter must classify the same way, with the `Retry-After` each sends.
+fn error_cases() -> [(u16, Option<u64>); 4] {
+    [(429, Some(3)), (429, None), (503, None), (400, None)]
+}
+
+async fn error_server(path: &str, status: u16, retry_after: Option<u64>) -> MockServer {
+    let server = MockServer::start_async().await;
+    server
+        .mock_async(|when, then| {
+            when.method(POST).path(path);
+            let then = then
+                .status(status)
+                .header("x-request-id", "conformance-error")
+                .body("conformance failure body");
+            if let Some(secs) = retry_after {
+                then.header("retry-after", secs.to_string());
+            }
+        })
+        .await;
+    server
+}
+
+fn assert_error_taxonomy(err: &AiProxyError, status: u16, retry_after: Option<u64>) {
+    let upstream = match (status, err) {
+        (
+            429,
+            AiProxyError::RateLimited {
+                retry_after: got,
+                upstream,
+                ..
+            },
+        ) => {
+            assert_eq!(*got, retry_after, "retry-after on 429");
+            upstream
+        }
+        (500..=599, AiProxyError::ProviderUnavailable { upstream, .. }) => upstream,
+        (
+            400..=499,
+            AiProxyError::ProviderError {
+                code,
+                message,
+                upstream,
+                ..
+            },
+        ) => {
+            assert_eq!(code, &status.to_string(), "error code");
+            assert!(message.contains("conformance failure body"), "{message}");
+            upstream
+        }
+        _ => panic!("HTTP {status} mapped to {err:?}"),
+    };
+    assert_eq!(upstream.status, Some(status), "upstream status");
+    assert_eq!(
+        upstream.provider_request_id.as_deref(),
+        Some("conformance-error"),
+        "upstream request id"
+    );
+}
+
+async fn check_chat_errors<F: ChatFixture>(fixture: &F) {
+    for (status, retry_after) in error_cases() {
+        let server = error_server(fixture.chat_path(), status, retry_after).await;
+        let provider = fixture.provider(&server.base_url());
+        let err = provider.chat(chat_request(fixture)).await.unwrap_err();
+        assert_error_taxonomy(&err, status, retry_after);
+
+        // A stream that cannot start fails the same way, up front or as its only event.
+        match provider.chat_stream_events(chat_request(fixture)).await {
+            Err(err) => assert_error_taxonomy(&err, status, retry_after),
+            Ok(stream) => {
+                let events: Vec<StreamEvent> = stream.collect().await;
+                match events.as_slice() {
+                    [StreamEvent::Error(err)] => assert_error_taxonomy(err, status, retry_after),
+                    other => panic!("HTTP {status} streamed {other:?}"),
+                }
+            }
+        }
+    }
+}
+
+async fn check_header_propagation<F: ChatFixture>(fixture: &F) {
+    let server = MockServer::start_async().await;
+    let m = server
+        .mock_async(|when, then| {
+            when.method(POST)
+                .path(fixture.chat_path())
+                .header("X-Request-Id", "conformance-req")
+                .header("X-Turn-Id", "conformance-turn")
+                .header("Idempotency-Key", "conformance-idem");
+            then.status(200)
+                .header("x-request-id", "conformance-upstream")
+                .json_body(fixture.success_body(
+                    TEXT,
+                    first_stop(fixture).0,
+                    PROMPT_TOKENS,
+                    COMPLETION_TOKENS,
+                ));
+        })
+        .await;
+    let provider = fixture.provider(&server.base_url());
+    let req = ChatRequest::builder()
+        .model(fixture.model())
+        .user("Hi")
+        .request_id("conformance-req")
+        .trace_id("conformance-turn")
+        .idempotency_key("conformance-idem")
+        .build();
+    let resp = provider.chat(req).await.expect("request headers forwarded");
+    m.assert_async().await;
+    assert_eq!(
+        resp.provider_request_id.as_deref(),
+        Some("conformance-upstream"),
+        "provider request id from the response header"
+    );
+}
+
+async fn check_stream_contract<F: ChatFixture>(fixture: &F) {
+    let (wire, expected) = first_stop(fixture);
+    let deltas = ["Hello", " ", "there"];
+    let server = MockServer::start_async().await;
+    match fixture.stream_body(&deltas, wire) {
+        Some(body) => {
+            server
+                .mock_async(|when, then| {
+                    when.method(POST).path(fixture.chat_path());
+                    then.status(200)
+                        .header("content-type", "text/event-stream")
+                        .body(body);
+                })
+                .await;
+        }
+        None => {
+            server
+                .mock_async(|when, then| {
+                    when.method(POST).path(fixture.chat_path());
+                    then.status(200).json_body(fixture.success_body(
+                        TEXT,
+                        wire,
+                        PROMPT_TOKENS,
+                        COMPLETION_TOKENS,
+                    ));
+                })
+                .await;
+        }
+    }
+    let provider = fixture.provider(&server.base_url());
+    let events: Vec<StreamEvent> = provider
+        .chat_stream_events(chat_request(fixture))
+        .await
+        .expect("stream starts")
+        .collect()
+        .await;
+
+    let terminals = events.iter().filter(|e| e.is_terminal()).count();
+    assert_eq!(terminals, 1, "exactly one terminal event in {events:?}");
+    let (last, body) = events.split_last().expect("stream yields events");
+    assert!(last.is_terminal(), "terminal event comes last");
+    let text: String = body.iter().filter_map(StreamEvent::as_text_delta).collect();
+    match last {
+        StreamEvent::Stop { reason } => {
+            assert_eq!(text, TEXT, "deltas concatenate to the text");
+            assert_eq!(*reason, expected, "streamed stop reason");
+        }
+        StreamEvent::Final(resp) => {
+            assert!(
+                text.is_empty() || text == TEXT,
+                "deltas before the final response"
+            );
+            assert_eq!(resp.text, TEXT, "final text");
+            assert_eq!(resp.stop_reason, expected, "final stop reason");
+        }
+        other => panic!("stream ended with {other:?}"),
+    }
+}
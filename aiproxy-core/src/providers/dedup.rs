@@ -0,0 +1,216 @@
+//! In-flight deduplication: `DedupProvider` lets concurrent chats that are the same request
+//! share one upstream call, and hands every caller the same `ChatResponse`.
+//!
+//! Requests are the same when they carry the same `idempotency_key` (and caller). With
+//! `[dedup].identical`, requests that normalize to the same `cache::chat_key` are too. Only
+//! calls in flight are shared; a request arriving after the response is sent starts afresh.
+//!
+//! `AiProxyError` cannot be copied to several callers, so only successes are shared. When
+//! the shared call fails, or its caller goes away, the callers still waiting pick a new
+//! leader among themselves and try again. Streams are never shared.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use tokio::sync::watch;
+
+use crate::cache;
+use crate::config::DedupCfg;
+use crate::error::CoreResult;
+use crate::model::{ChatRequest, ChatResponse};
+use crate::provider::ChatProvider;
+use crate::stream::BoxStreamEv;
+
+type InFlight = Mutex<HashMap<String, watch::Receiver<Option<ChatResponse>>>>;
+
+/// Decorator sharing `inner`'s calls between concurrent identical chats.
+#[derive(Debug)]
+pub struct DedupProvider<P: ?Sized> {
+    inner: Arc<P>,
+    identical: bool,
+    in_flight: InFlight,
+}
+
+impl<P: ?Sized> DedupProvider<P> {
+    pub fn new(inner: Arc<P>, cfg: &DedupCfg) -> Self {
+        Self {
+            inner,
+            identical: cfg.identical,
+            in_flight: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+enum Role {
+    Lead(watch::Sender<Option<ChatResponse>>),
+    Follow(watch::Receiver<Option<ChatResponse>>),
+}
+
+/// Removes the leader's entry however its call ends, dropped futures included.
+struct Leading<'a> {
+    in_flight: &'a InFlight,
+    key: &'a str,
+}
+
+impl Drop for Leading<'_> {
+    fn drop(&mut self) {
+        self.in_flight.lock().unwrap().remove(self.key);
+    }
+}
+
+impl DedupProvider<dyn ChatProvider> {
+    fn key(&self, req: &ChatRequest) -> Option<String> {
+        let caller = req.client_key.as_deref().unwrap_or_default();
+        match &req.idempotency_key {
+            Some(key) => Some(format!("idempotency\n{caller}\n{key}")),
+            None if self.identical => cache::chat_key(self.inner.name(), req),
+            None => None,
+        }
+    }
+}
+
+#[async_trait]
+impl ChatProvider for DedupProvider<dyn ChatProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let Some(key) = self.key(&req) else {
+            return self.inner.chat(req).await;
+        };
+        loop {
+            let role = {
+                let mut in_flight = self.in_flight.lock().unwrap();
+                match in_flight.get(&key) {
+                    Some(rx) => Role::Follow(rx.clone()),
+                    None => {
+                        let (tx, rx) = watch::channel(None);
+                        in_flight.insert(key.clone(), rx);
+                        Role::Lead(tx)
+                    }
+                }
+            };
+            match role {
+                Role::Lead(tx) => {
+                    let _leading = Leading {
+                        in_flight: &self.in_flight,
+                        key: &key,
+                    };
+                    let result = self.inner.chat(req).await;
+                    if let Ok(resp) = &result {
+                        let _ = tx.send(Some(resp.clone()));
+                    }
+                    return result;
+                }
+                Role::Follow(mut rx) => {
+                    // An error means the leader ended without a response: try to lead.
+                    if let Ok(resp) = rx.wait_for(Option::is_some).await {
+                        tracing::debug!(provider = self.inner.name(), "shared an in-flight call");
+                        return Ok(resp.clone().expect("waited for a response"));
+                    }
+                }
+            }
+        }
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        self.inner.chat_stream_events(req).await
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.inner.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.inner.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.inner.count_tokens(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{AiProxyError, Upstream};
+    use crate::provider::NullProvider;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Answers "call <n>" after a pause, or fails once when `down`, counting calls.
+    #[derive(Debug, Default)]
+    struct Slow {
+        down: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ChatProvider for Slow {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            let n = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if self.down.swap(false, Ordering::SeqCst) {
+                return Err(AiProxyError::ProviderUnavailable {
+                    provider: "slow".into(),
+                    upstream: Box::new(Upstream::default()),
+                });
+            }
+            let mut resp = NullProvider.chat(req).await?;
+            resp.text = format!("call {n}");
+            Ok(resp)
+        }
+    }
+
+    fn dedup(inner: Arc<Slow>, identical: bool) -> DedupProvider<dyn ChatProvider> {
+        DedupProvider::new(inner as Arc<dyn ChatProvider>, &DedupCfg { identical })
+    }
+
+    fn req(idempotency_key: Option<&str>) -> ChatRequest {
+        let b = ChatRequest::builder().model("m").user("hi");
+        match idempotency_key {
+            Some(k) => b.idempotency_key(k).build(),
+            None => b.build(),
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_calls_with_one_key_share_the_response() {
+        let inner = Arc::new(Slow::default());
+        let p = dedup(inner.clone(), false);
+        let (a, b, c) = tokio::join!(
+            p.chat(req(Some("k"))),
+            p.chat(req(Some("k"))),
+            p.chat(req(None)),
+        );
+        assert_eq!(a.unwrap().text, b.unwrap().text);
+        assert!(c.is_ok());
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2, "no key: not shared");
+
+        let (a, b) = tokio::join!(p.chat(req(None)), p.chat(req(None)));
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 4);
+        assert_ne!(a.unwrap().text, b.unwrap().text);
+
+        let p = dedup(inner.clone(), true);
+        let (a, b) = tokio::join!(p.chat(req(None)), p.chat(req(None)));
+        assert_eq!(a.unwrap().text, b.unwrap().text);
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[tokio::test]
+    async fn a_failed_leader_hands_over_to_a_waiting_caller() {
+        let inner = Arc::new(Slow::default());
+        inner.down.store(true, Ordering::SeqCst);
+        let p = dedup(inner.clone(), false);
+        let (a, b) = tokio::join!(p.chat(req(Some("k"))), p.chat(req(Some("k"))));
+        assert!(matches!(a, Err(AiProxyError::ProviderUnavailable { .. })));
+        assert!(b.is_ok());
+        assert_eq!(inner.calls.load(Ordering::SeqCst), 2);
+    }
+}
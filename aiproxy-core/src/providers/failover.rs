@@ -0,0 +1,186 @@
+//! Fallback chains: `FailoverProvider` sends each chat to the first provider of a routing
+//! rule's `providers` and moves on to the next one when it is rate limited or unavailable.
+//! Any other error, and the last provider's error, is returned as is.
+//!
+//! Streams fail over only before their first event; once events flow, a failure ends the
+//! stream. The response's `provider` names the provider that served it.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+
+use crate::error::{AiProxyError, CoreResult};
+use crate::model::{ChatRequest, ChatResponse};
+use crate::provider::ChatProvider;
+use crate::stream::BoxStreamEv;
+
+/// Chat provider trying each of `chain` in turn.
+#[derive(Debug)]
+pub struct FailoverProvider {
+    chain: Vec<Arc<dyn ChatProvider>>,
+}
+
+impl FailoverProvider {
+    /// `chain` in the order providers are tried; it must not be empty.
+    pub fn new(chain: Vec<Arc<dyn ChatProvider>>) -> Self {
+        assert!(!chain.is_empty(), "a fallback chain needs a provider");
+        Self { chain }
+    }
+
+    /// All but the last provider, which gets whatever the others left.
+    fn split(&self) -> (&[Arc<dyn ChatProvider>], &Arc<dyn ChatProvider>) {
+        let (last, rest) = self.chain.split_last().expect("chain is not empty");
+        (rest, last)
+    }
+}
+
+/// Whether another provider may do better: the error is about this provider, not the request.
+fn fails_over(e: &AiProxyError) -> bool {
+    matches!(
+        e,
+        AiProxyError::RateLimited { .. } | AiProxyError::ProviderUnavailable { .. }
+    )
+}
+
+fn skipped(p: &dyn ChatProvider, model: &str, e: &AiProxyError) {
+    tracing::warn!(provider = p.name(), model, error = %e, "failing over to the next provider");
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for FailoverProvider {
+    fn name(&self) -> &str {
+        self.chain[0].name()
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let (rest, last) = self.split();
+        for p in rest {
+            match p.chat(req.clone()).await {
+                Err(e) if fails_over(&e) => skipped(p.as_ref(), &req.model, &e),
+                result => return result,
+            }
+        }
+        last.chat(req).await
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        let (rest, last) = self.split();
+        for p in rest {
+            match p.chat_stream_events(req.clone()).await {
+                Err(e) if fails_over(&e) => skipped(p.as_ref(), &req.model, &e),
+                result => return result,
+            }
+        }
+        last.chat_stream_events(req).await
+    }
+
+    /// Any provider may get the request, so it must suit the strictest of them.
+    fn requires_alternation(&self) -> bool {
+        self.chain.iter().any(|p| p.requires_alternation())
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.chain.iter().all(|p| p.supports_response_schema())
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.chain[0].count_tokens(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Upstream;
+    use crate::provider::NullProvider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Fails every chat with `error()` (or answers like `NullProvider` when it is `None`),
+    /// counting calls.
+    #[derive(Debug)]
+    struct Scripted {
+        name: &'static str,
+        error: fn() -> Option<AiProxyError>,
+        calls: AtomicUsize,
+    }
+
+    impl Scripted {
+        fn new(name: &'static str, error: fn() -> Option<AiProxyError>) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                error,
+                calls: AtomicUsize::new(0),
+            })
+        }
+    }
+
+    #[async_trait]
+    impl ChatProvider for Scripted {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if let Some(e) = (self.error)() {
+                return Err(e);
+            }
+            let mut resp = NullProvider.chat(req).await?;
+            resp.provider = self.name.into();
+            Ok(resp)
+        }
+    }
+
+    fn rate_limited() -> Option<AiProxyError> {
+        Some(AiProxyError::RateLimited {
+            provider: "a".into(),
+            retry_after: Some(1),
+            remaining_requests: None,
+            remaining_tokens: None,
+            reset_at_ms: None,
+            upstream: Box::new(Upstream::default()),
+        })
+    }
+
+    fn invalid() -> Option<AiProxyError> {
+        Some(AiProxyError::Validation("bad request".into()))
+    }
+
+    fn req() -> ChatRequest {
+        ChatRequest::builder().model("m").user("hi").build()
+    }
+
+    #[tokio::test]
+    async fn rate_limited_providers_hand_over_to_the_next() {
+        let a = Scripted::new("a", rate_limited);
+        let b = Scripted::new("b", || None);
+        let p = FailoverProvider::new(vec![a.clone() as Arc<dyn ChatProvider>, b.clone()]);
+        assert_eq!(p.chat(req()).await.unwrap().provider, "b");
+        let events = p.chat_stream_events(req()).await.unwrap();
+        assert_eq!(futures_util::StreamExt::count(events).await, 1);
+        assert_eq!(a.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(b.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn other_errors_and_the_last_error_are_returned() {
+        let a = Scripted::new("a", invalid);
+        let b = Scripted::new("b", || None);
+        let p = FailoverProvider::new(vec![a as Arc<dyn ChatProvider>, b.clone()]);
+        assert!(matches!(
+            p.chat(req()).await,
+            Err(AiProxyError::Validation(_))
+        ));
+        assert_eq!(b.calls.load(Ordering::SeqCst), 0);
+
+        let p = FailoverProvider::new(vec![
+            Scripted::new("a", rate_limited) as Arc<dyn ChatProvider>,
+            Scripted::new("b", rate_limited),
+        ]);
+        assert!(matches!(
+            p.chat(req()).await,
+            Err(AiProxyError::RateLimited { .. })
+        ));
+    }
+}
@@ -0,0 +1,150 @@
+//! Offline embedding provider: deterministic pseudo-embeddings computed locally, for vector
+//! pipelines in development and CI with no network and no cost.
+//!
+//! Each lowercase word of the input is hashed (FNV-1a, seeded) into one dimension with a ±1
+//! sign, and the sum is scaled to unit length. The same text, seed and dimension always give
+//! the same vector on every platform, and texts sharing words score a higher cosine
+//! similarity than unrelated ones. Inputs without words hash whole. The vectors carry no
+//! meaning beyond word overlap.
+
+use async_trait::async_trait;
+
+use crate::config::HashEmbedCfg;
+use crate::embeddings::math;
+use crate::error::CoreResult;
+use crate::model::{EmbedRequest, EmbedResponse};
+use crate::provider::{Capability, EmbedProvider, ProviderCaps};
+use crate::tokenizer;
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+fn fnv1a(seed: u64, bytes: &[u8]) -> u64 {
+    seed.to_le_bytes()
+        .iter()
+        .chain(bytes)
+        .fold(FNV_OFFSET, |h, b| {
+            (h ^ u64::from(*b)).wrapping_mul(FNV_PRIME)
+        })
+}
+
+/// SplitMix64 step, used to spread one hash over every dimension.
+pub(crate) fn splitmix(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[derive(Debug, Clone)]
+pub struct HashEmbedProvider {
+    dimensions: usize,
+    seed: u64,
+}
+
+impl HashEmbedProvider {
+    pub fn new(dimensions: usize, seed: u64) -> Self {
+        Self {
+            dimensions: dimensions.max(1),
+            seed,
+        }
+    }
+
+    pub fn from_cfg(cfg: &HashEmbedCfg) -> Self {
+        Self::new(cfg.dimensions, cfg.seed)
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    /// The unit-length vector for `text`.
+    pub fn vector(&self, text: &str) -> Vec<f32> {
+        let mut v = vec![0.0_f32; self.dimensions];
+        let mut words = 0;
+        for word in text.split(|c: char| !c.is_alphanumeric()) {
+            if word.is_empty() {
+                continue;
+            }
+            let h = fnv1a(self.seed, word.to_lowercase().as_bytes());
+            let sign = if h >> 63 == 0 { 1.0 } else { -1.0 };
+            v[(h % self.dimensions as u64) as usize] += sign;
+            words += 1;
+        }
+        if words == 0 {
+            let mut state = fnv1a(self.seed, text.as_bytes());
+            for x in &mut v {
+                *x = (splitmix(&mut state) >> 11) as f32 / (1u64 << 53) as f32 * 2.0 - 1.0;
+            }
+        }
+        math::l2_normalize(&mut v);
+        v
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for HashEmbedProvider {
+    fn name(&self) -> &str {
+        "hash"
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        let usage = req
+            .inputs
+            .iter()
+            .map(|s| tokenizer::count_text(&req.model, s) as u32)
+            .sum();
+        Ok(EmbedResponse {
+            vectors: req.inputs.iter().map(|s| self.vector(s)).collect(),
+            model: req.model,
+            usage,
+            cached: false,
+            provider: "hash".into(),
+            cost_usd: Some(0.0),
+        })
+    }
+}
+
+impl ProviderCaps for HashEmbedProvider {
+    fn capabilities(&self) -> &'static [Capability] {
+        &[Capability::Embed]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::embeddings::math::cosine;
+
+    #[tokio::test]
+    async fn vectors_are_stable_unit_length_and_seeded() {
+        let p = HashEmbedProvider::new(64, 7);
+        let req = EmbedRequest::builder()
+            .model("local")
+            .inputs(["The cat sat", "the CAT sat", ""])
+            .build();
+        let resp = p.embed(req.clone()).await.unwrap();
+        assert_eq!(resp.vectors.len(), 3);
+        assert_eq!(resp.vectors[0], resp.vectors[1]);
+        assert_eq!(resp.cost_usd, Some(0.0));
+        for v in &resp.vectors {
+            assert_eq!(v.len(), 64);
+            assert!((cosine(v, v) - 1.0).abs() < 1e-5);
+        }
+        assert_eq!(p.embed(req.clone()).await.unwrap().vectors, resp.vectors);
+        let reseeded = HashEmbedProvider::new(64, 8).embed(req).await.unwrap();
+        assert_ne!(reseeded.vectors[0], resp.vectors[0]);
+    }
+
+    #[test]
+    fn shared_words_score_closer_than_unrelated_text() {
+        let p = HashEmbedProvider::new(256, 0);
+        let a = p.vector("rust async runtime tokio");
+        let b = p.vector("tokio is an async runtime");
+        let c = p.vector("banana bread recipe");
+        assert!(cosine(&a, &b) > cosine(&a, &c));
+    }
+}
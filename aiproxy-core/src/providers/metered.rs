@@ -0,0 +1,186 @@
+//! Running usage totals behind `ProviderRegistry::stats`: `MeteredProvider` adds each call's
+//! tokens and `cost_usd` to a shared `UsageStats`, under the provider's registry name and the
+//! requested model.
+//!
+//! Cache hits count as requests but add no tokens or cost. Streams count once their `Final`
+//! event arrives; failed calls and streams that end early are not counted.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::Serialize;
+
+use crate::error::CoreResult;
+use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::provider::{ChatProvider, EmbedProvider};
+use crate::stream::{BoxStreamEv, StreamEvent};
+
+/// Totals for one provider and model since the registry was built.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ProviderStats {
+    pub provider: String,
+    pub model: String,
+    pub requests: u64,
+    /// Of `requests`, those answered from the response cache.
+    pub cached: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Spend in USD on calls to models with a known price.
+    pub cost_usd: f64,
+}
+
+/// Totals shared by every provider of one registry.
+#[derive(Debug, Default)]
+pub struct UsageStats {
+    totals: Mutex<BTreeMap<(String, String), ProviderStats>>,
+}
+
+impl UsageStats {
+    fn add(
+        &self,
+        provider: &str,
+        model: &str,
+        cached: bool,
+        tokens: (u32, u32),
+        cost: Option<f64>,
+    ) {
+        let mut totals = self.totals.lock().unwrap();
+        let s = totals
+            .entry((provider.to_string(), model.to_string()))
+            .or_insert_with(|| ProviderStats {
+                provider: provider.to_string(),
+                model: model.to_string(),
+                ..ProviderStats::default()
+            });
+        s.requests += 1;
+        if cached {
+            s.cached += 1;
+            return;
+        }
+        s.prompt_tokens += u64::from(tokens.0);
+        s.completion_tokens += u64::from(tokens.1);
+        s.cost_usd += cost.unwrap_or(0.0);
+    }
+
+    fn add_chat(&self, provider: &str, model: &str, resp: &ChatResponse) {
+        let tokens = (resp.usage.prompt, resp.usage.completion);
+        self.add(provider, model, resp.cached, tokens, resp.cost_usd);
+    }
+
+    /// The totals so far, sorted by provider and model.
+    pub fn snapshot(&self) -> Vec<ProviderStats> {
+        self.totals.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Decorator adding `inner`'s calls to `stats` under `name`.
+#[derive(Debug)]
+pub struct MeteredProvider<P: ?Sized> {
+    inner: Arc<P>,
+    name: String,
+    stats: Arc<UsageStats>,
+}
+
+impl<P: ?Sized> MeteredProvider<P> {
+    pub fn new(inner: Arc<P>, name: &str, stats: Arc<UsageStats>) -> Self {
+        Self {
+            inner,
+            name: name.to_string(),
+            stats,
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for MeteredProvider<dyn ChatProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let model = req.model.clone();
+        let resp = self.inner.chat(req).await?;
+        self.stats.add_chat(&self.name, &model, &resp);
+        Ok(resp)
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        let model = req.model.clone();
+        let events = self.inner.chat_stream_events(req).await?;
+        let (name, stats) = (self.name.clone(), self.stats.clone());
+        Ok(Box::pin(events.inspect(move |ev| {
+            if let StreamEvent::Final(resp) = ev {
+                stats.add_chat(&name, &model, resp);
+            }
+        })))
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.inner.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.inner.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.inner.count_tokens(req).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for MeteredProvider<dyn EmbedProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        let model = req.model.clone();
+        let resp = self.inner.embed(req).await?;
+        let tokens = (resp.usage, 0);
+        self.stats
+            .add(&self.name, &model, resp.cached, tokens, resp.cost_usd);
+        Ok(resp)
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NullProvider;
+
+    #[tokio::test]
+    async fn calls_add_up_per_provider_and_model() {
+        let stats = Arc::new(UsageStats::default());
+        let chat: Arc<dyn ChatProvider> = Arc::new(NullProvider);
+        let p = MeteredProvider::new(chat, "null", stats.clone());
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let resp = p.chat(req.clone()).await.unwrap();
+        let mut events = p.chat_stream_events(req).await.unwrap();
+        while events.next().await.is_some() {}
+
+        let embed: Arc<dyn EmbedProvider> = Arc::new(NullProvider);
+        let e = MeteredProvider::new(embed, "null", stats.clone());
+        let req = EmbedRequest {
+            model: "e".into(),
+            inputs: vec!["a".into()],
+            client_key: None,
+            requested_model: None,
+        };
+        e.embed(req).await.unwrap();
+
+        let totals = stats.snapshot();
+        assert_eq!(totals.len(), 2);
+        assert_eq!((totals[1].model.as_str(), totals[1].requests), ("m", 2));
+        assert_eq!(totals[1].prompt_tokens, 2 * u64::from(resp.usage.prompt));
+        assert_eq!((totals[0].model.as_str(), totals[0].requests), ("e", 1));
+    }
+}
@@ -1,3 +1,176 @@
+pub mod alias;
 pub mod anthropic;
+#[cfg(feature = "native")]
+pub mod caching;
+pub mod chaos;
+pub mod chunking;
+pub mod circuit;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod conformance;
+#[cfg(feature = "native")]
+pub mod dedup;
+pub mod failover;
+pub mod hash;
+pub mod metered;
+pub mod ollama;
 pub mod openai;
 pub mod openrouter;
+pub mod recording;
+#[cfg(feature = "native")]
+pub mod replay;
+#[cfg(feature = "native")]
+pub mod shadow;
+
+use std::borrow::Cow;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::config::ExtraParamsCfg;
+use crate::error::{AiProxyError, CoreResult};
+use crate::model::{
+    ChatMessage, ChatRequest, ContentPart, ImageUrl, MessageContent, ResponseSchema, ToolDialect,
+};
+
+/// An adapter payload with the permitted `ChatRequest.extra` entries appended after its own
+/// fields.
+#[derive(Serialize)]
+pub(crate) struct WithExtra<'a, T: Serialize> {
+    #[serde(flatten)]
+    payload: &'a T,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+}
+
+/// Attach `ChatRequest.extra` to a payload. Keys the policy rejects, and keys the adapter
+/// already set, are validation errors rather than being dropped or overwritten.
+pub(crate) fn with_extra<'a, T: Serialize>(
+    provider: &str,
+    payload: &'a T,
+    extra: Option<&Map<String, Value>>,
+    policy: &ExtraParamsCfg,
+) -> CoreResult<WithExtra<'a, T>> {
+    let extra = extra.cloned().unwrap_or_default();
+    if !extra.is_empty() {
+        let own = serde_json::to_value(payload).map_err(anyhow::Error::from)?;
+        for key in extra.keys() {
+            if !policy.permits(key) {
+                return Err(AiProxyError::Validation(format!(
+                    "extra parameter '{key}' is not allowed for {provider}"
+                )));
+            }
+            if own.get(key).is_some() {
+                return Err(AiProxyError::Validation(format!(
+                    "extra parameter '{key}' conflicts with a field ai-proxy sets"
+                )));
+            }
+        }
+    }
+    Ok(WithExtra { payload, extra })
+}
+
+/// OpenAI-style `response_format` for a requested schema, shared by the compatible adapters.
+pub(crate) fn response_format(schema: Option<&ResponseSchema>) -> Option<Value> {
+    schema.map(|s| {
+        serde_json::json!({
+            "type": "json_schema",
+            "json_schema": {"name": s.name, "schema": s.schema},
+        })
+    })
+}
+
+/// `messages` as OpenAI-compatible APIs take them: inline images become `data:` URLs.
+pub(crate) fn openai_messages(messages: &[ChatMessage]) -> Cow<'_, [ChatMessage]> {
+    let inline = |p: &ContentPart| matches!(p, ContentPart::ImageBase64 { .. });
+    let any_inline = messages
+        .iter()
+        .any(|m| matches!(&m.content, MessageContent::Parts(parts) if parts.iter().any(inline)));
+    if !any_inline {
+        return Cow::Borrowed(messages);
+    }
+    let mut messages = messages.to_vec();
+    for m in &mut messages {
+        if let MessageContent::Parts(parts) = &mut m.content {
+            for part in parts.iter_mut() {
+                if let ContentPart::ImageBase64 { media_type, data } = part {
+                    let url = format!("data:{media_type};base64,{data}");
+                    *part = ContentPart::ImageUrl {
+                        image_url: ImageUrl { url, detail: None },
+                    };
+                }
+            }
+        }
+    }
+    Cow::Owned(messages)
+}
+
+/// `req.tools` and `req.tool_choice` in `dialect`'s wire format. Without tools there is
+/// nothing to choose from, so the choice is dropped too.
+pub(crate) fn tool_params(
+    req: &ChatRequest,
+    dialect: ToolDialect,
+) -> (Option<Vec<Value>>, Option<Value>) {
+    if req.tools.is_empty() {
+        return (None, None);
+    }
+    let tools = req.tools.iter().map(|t| t.to_wire(dialect)).collect();
+    let choice = req.tool_choice.as_ref().map(|c| c.to_wire(dialect));
+    (Some(tools), choice)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn extra_keys_merge_subject_to_policy() {
+        let payload = json!({"model": "m", "temperature": 0.5});
+        let extra = json!({"seed": 7}).as_object().cloned();
+        let merged = |extra: Option<&Map<String, Value>>, policy: &ExtraParamsCfg| {
+            with_extra("openai", &payload, extra, policy)
+                .map(|body| serde_json::to_value(body).unwrap())
+        };
+        let policy = ExtraParamsCfg::default();
+        assert_eq!(
+            merged(extra.as_ref(), &policy).unwrap(),
+            json!({"model": "m", "temperature": 0.5, "seed": 7})
+        );
+
+        let deny = ExtraParamsCfg {
+            allow: vec![],
+            deny: vec!["seed".into()],
+        };
+        let err = merged(extra.as_ref(), &deny).unwrap_err();
+        assert!(err.to_string().contains("not allowed for openai"));
+
+        let only_top_k = ExtraParamsCfg {
+            allow: vec!["top_k".into()],
+            deny: vec![],
+        };
+        assert!(merged(extra.as_ref(), &only_top_k).is_err());
+
+        let clash = json!({"model": "other"}).as_object().cloned();
+        assert!(merged(clash.as_ref(), &policy).is_err());
+        assert_eq!(merged(None, &deny).unwrap(), payload);
+    }
+
+    #[test]
+    fn inline_images_become_data_urls_for_openai() {
+        let plain = vec![ChatMessage::user("hi")];
+        assert!(matches!(openai_messages(&plain), Cow::Borrowed(_)));
+
+        let parts = vec![
+            ContentPart::text("what is this?"),
+            ContentPart::image_base64("image/png", "iVBORw0"),
+        ];
+        let messages = vec![ChatMessage::user(parts)];
+        assert_eq!(
+            serde_json::to_value(openai_messages(&messages)).unwrap(),
+            json!([{"role": "user", "content": [
+                {"type": "text", "text": "what is this?"},
+                {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0"}}
+            ]}])
+        );
+    }
+}
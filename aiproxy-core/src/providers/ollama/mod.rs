@@ -0,0 +1,790 @@
+//! Ollama adapter for locally served models: chat over `/api/chat`, embeddings over
+//! `/api/embed` (the batch successor of `/api/embeddings`) and model listing over
+//! `/api/tags`. Ollama takes no API key.
+//!
+//! Streams are newline-delimited JSON rather than SSE: each line is a partial message, and
+//! the line with `done: true` carries the stop reason and token counts.
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+
+use crate::config::ExtraParamsCfg;
+use crate::error::{AiProxyError, CoreResult, Upstream};
+use crate::http_client::{HttpClient, RequestCtx, RetryLog, StreamTimeouts};
+use crate::model::{
+    ChatChoice, ChatMessage, ChatRequest, ChatResponse, ContentPart, EmbedRequest, EmbedResponse,
+    MessageContent, ModelInfo, Role, StopReason, ToolCall, ToolDialect, Usage,
+};
+use crate::platform::{SystemTime, UNIX_EPOCH};
+use crate::provider::{Capability, ChatProvider, EmbedProvider, ModelsProvider, ProviderCaps};
+use crate::stream::{BoxStreamEv, StreamEvent};
+
+#[derive(Debug, Clone)]
+pub struct Ollama {
+    http: HttpClient,
+    base: String,
+    name: String, // "ollama"
+    extra_params: ExtraParamsCfg,
+}
+
+impl Ollama {
+    pub fn new(http: HttpClient, base: String) -> Self {
+        Self {
+            http,
+            base,
+            name: "ollama".into(),
+            extra_params: ExtraParamsCfg::default(),
+        }
+    }
+
+    /// Limit which `ChatRequest.extra` parameters reach the provider.
+    pub fn with_extra_params(mut self, cfg: ExtraParamsCfg) -> Self {
+        self.extra_params = cfg;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn new_for_tests(server_base: &str) -> Self {
+        Ollama::new(HttpClient::new_default().unwrap(), server_base.to_string())
+    }
+
+    fn headers(&self, _ctx: &RequestCtx<'_>) -> Vec<(String, String)> {
+        vec![("Content-Type".to_string(), "application/json".to_string())]
+    }
+
+    fn now_ms() -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64
+    }
+
+    fn payload<'a>(&self, req: &'a ChatRequest, stream: bool) -> CoreResult<OChatReq<'a>> {
+        let (tools, _tool_choice) = super::tool_params(req, ToolDialect::OpenAi);
+        Ok(OChatReq {
+            model: &req.model,
+            messages: req
+                .messages
+                .iter()
+                .map(OMessage::try_from)
+                .collect::<CoreResult<_>>()?,
+            stream,
+            options: OOptions::from_request(req),
+            format: req.response_schema.as_ref().map(|s| &s.schema),
+            tools,
+        })
+    }
+}
+
+// ----- Wire structs -----
+#[derive(Serialize)]
+struct OChatReq<'a> {
+    model: &'a str,
+    messages: Vec<OMessage<'a>>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<OOptions>,
+    /// A JSON schema the reply must follow.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<&'a serde_json::Value>,
+    /// Ollama has no `tool_choice`; the model decides whether to call.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
+}
+/// Ollama has no participant names, so a name is prefixed to the text.
+#[derive(Serialize)]
+struct OMessage<'a> {
+    role: Role,
+    content: Cow<'a, str>,
+    /// Base64 image data; Ollama does not fetch images by URL.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    images: Vec<&'a str>,
+}
+impl<'a> TryFrom<&'a ChatMessage> for OMessage<'a> {
+    type Error = AiProxyError;
+
+    fn try_from(m: &'a ChatMessage) -> CoreResult<Self> {
+        let mut images = vec![];
+        if let MessageContent::Parts(parts) = &m.content {
+            for part in parts
+                .iter()
+                .filter(|p| !matches!(p, ContentPart::Text { .. }))
+            {
+                let (_media_type, data) = part.inline_image().ok_or_else(|| {
+                    AiProxyError::Validation(
+                        "ollama takes inline images only (base64 or data: URLs)".into(),
+                    )
+                })?;
+                images.push(data);
+            }
+        }
+        let text = m.content.text();
+        Ok(OMessage {
+            role: m.role,
+            content: match &m.name {
+                Some(name) => format!("{name}: {text}").into(),
+                None => text,
+            },
+            images,
+        })
+    }
+}
+/// Sampling settings; Ollama takes them under `options` rather than top level.
+#[derive(Serialize)]
+struct OOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    num_predict: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stop: Option<Vec<String>>,
+}
+impl OOptions {
+    fn from_request(req: &ChatRequest) -> Option<Self> {
+        let opts = OOptions {
+            temperature: req.temperature,
+            top_p: req.top_p,
+            num_predict: req.max_output_tokens,
+            stop: req.stop_sequences.clone(),
+        };
+        let unset = opts.temperature.is_none()
+            && opts.top_p.is_none()
+            && opts.num_predict.is_none()
+            && opts.stop.is_none();
+        (!unset).then_some(opts)
+    }
+}
+/// A whole response, or one line of a stream.
+#[derive(Deserialize)]
+struct OChatChunk {
+    #[serde(default)]
+    message: Option<OReply>,
+    #[serde(default)]
+    done: bool,
+    #[serde(default)]
+    done_reason: Option<String>,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+    #[serde(default)]
+    eval_count: Option<u32>,
+    /// Set instead of a message when generation fails mid-stream.
+    #[serde(default)]
+    error: Option<String>,
+}
+#[derive(Deserialize)]
+struct OReply {
+    #[serde(default)]
+    content: String,
+    #[serde(default)]
+    tool_calls: Vec<OToolCall>,
+}
+#[derive(Deserialize)]
+struct OToolCall {
+    function: OFunctionCall,
+}
+#[derive(Deserialize)]
+struct OFunctionCall {
+    name: String,
+    /// Already an object, unlike OpenAI's JSON text.
+    #[serde(default)]
+    arguments: serde_json::Value,
+}
+
+/// Ollama calls carry no ids; they are numbered from `first` in the order they arrive.
+fn tool_calls(calls: Vec<OToolCall>, first: usize) -> Vec<ToolCall> {
+    calls
+        .into_iter()
+        .enumerate()
+        .map(|(i, c)| ToolCall {
+            id: format!("call_{}", first + i),
+            name: c.function.name,
+            arguments: c.function.arguments,
+        })
+        .collect()
+}
+
+/// Ollama reports `stop` even when the model called a tool.
+fn map_done(s: Option<&str>, called_tools: bool) -> Option<StopReason> {
+    match s {
+        Some("stop") if called_tools => Some(StopReason::ToolUse),
+        Some("stop") => Some(StopReason::Stop),
+        Some("length") => Some(StopReason::Length),
+        Some(_) => Some(StopReason::Other),
+        None => None,
+    }
+}
+
+fn malformed_chunk(provider: &str, json: &str, e: serde_json::Error) -> AiProxyError {
+    let excerpt: String = json.chars().take(120).collect();
+    AiProxyError::MalformedChunk {
+        provider: provider.to_string(),
+        message: format!("{e} in `{excerpt}`"),
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for Ollama {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let payload = self.payload(&req, false)?;
+        let payload =
+            super::with_extra(&self.name, &payload, req.extra.as_ref(), &self.extra_params)?;
+        let retries = RetryLog::default();
+        let ctx = RequestCtx {
+            request_id: req.request_id.as_deref(),
+            turn_id: req.trace_id.as_deref(),
+            idempotency_key: req.idempotency_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: Some(&retries),
+            stream_timeouts: StreamTimeouts::default(),
+        };
+        let owned_headers = self.headers(&ctx);
+        let hdrs: Vec<(&str, &str)> = owned_headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let url = format!("{}/api/chat", self.base);
+        let (resp, raw, provider_id, latency_ms) = self
+            .http
+            .post_json_keep_raw::<_, OChatChunk>(&url, &payload, &hdrs, &ctx, req.include_raw)
+            .await?;
+
+        let reply = resp.message.unwrap_or(OReply {
+            content: String::new(),
+            tool_calls: vec![],
+        });
+        let tool_calls = tool_calls(reply.tool_calls, 0);
+        let stop_reason = map_done(resp.done_reason.as_deref(), !tool_calls.is_empty());
+        let usage = Usage::new(
+            resp.prompt_eval_count.unwrap_or(0),
+            resp.eval_count.unwrap_or(0),
+        );
+        let choices = vec![ChatChoice {
+            index: 0,
+            text: reply.content.clone(),
+            stop_reason,
+            tool_calls: tool_calls.clone(),
+            logprobs: None,
+            refusal: None,
+            annotations: vec![],
+        }];
+
+        // Local models cost nothing per token, so no cost is estimated.
+        let resp_out = ChatResponse {
+            model: req.model,
+            text: reply.content,
+            usage,
+            cached: false,
+            provider: self.name.clone(),
+            transcript_id: None,
+            turn_id: req.request_id.unwrap_or_else(|| "turn".into()),
+            stop_reason,
+            provider_request_id: provider_id,
+            created_at_ms: Self::now_ms(),
+            latency_ms,
+            raw,
+            choices,
+            tool_calls,
+            refusal: None,
+            annotations: vec![],
+            truncation: None,
+            cost_usd: None,
+            guardrail: vec![],
+        };
+        // Emit structured completion log (non-streaming)
+        let tokens_total = resp_out.usage.prompt.checked_add(resp_out.usage.completion);
+        let stop_code = match resp_out.stop_reason {
+            Some(StopReason::Stop) => Some("stop"),
+            Some(StopReason::Length) => Some("length"),
+            Some(StopReason::ToolUse) => Some("tool_use"),
+            Some(StopReason::EndTurn) => Some("end_turn"),
+            Some(StopReason::ContentFilter) => Some("content_filter"),
+            Some(StopReason::Cancelled) => Some("cancelled"),
+            Some(StopReason::Refusal) => Some("refusal"),
+            Some(StopReason::Other) => Some("other"),
+            None => None,
+        };
+        let clog = crate::telemetry::CompletionLog::new()
+            .provider("ollama")
+            .model(&resp_out.model)
+            .requested_model_opt(req.requested_model.as_deref())
+            .request_id_opt(None)
+            .turn_id_opt(None)
+            .provider_request_id_opt(resp_out.provider_request_id.as_deref())
+            .client_key_opt(req.client_key.as_deref())
+            .created_at_ms(resp_out.created_at_ms as u64)
+            .latency_ms(resp_out.latency_ms as u64)
+            .stop_reason_opt(stop_code)
+            .text_opt(Some(&resp_out.text))
+            .tokens(
+                Some(resp_out.usage.prompt),
+                Some(resp_out.usage.completion),
+                tokens_total,
+            );
+        let (reasons, backoff_ms) = retries.take();
+        crate::telemetry::emit_completion(clog.retried(reasons, backoff_ms));
+        Ok(resp_out)
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        let payload = self.payload(&req, true)?;
+        let payload =
+            super::with_extra(&self.name, &payload, req.extra.as_ref(), &self.extra_params)?;
+        let ctx = RequestCtx {
+            request_id: req.request_id.as_deref(),
+            turn_id: req.trace_id.as_deref(),
+            idempotency_key: req.idempotency_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: None,
+            stream_timeouts: StreamTimeouts::of(&req),
+        };
+        let owned_headers = self.headers(&ctx);
+        let hdrs: Vec<(&str, &str)> = owned_headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let url = format!("{}/api/chat", self.base);
+
+        // The line reader serves NDJSON as well as SSE; every line is one JSON object.
+        let (mut lines, _provider_request_id) = self
+            .http
+            .post_sse_lines(&url, &payload, &hdrs, &ctx)
+            .await?;
+
+        use futures::channel::mpsc;
+        use futures_util::{SinkExt, StreamExt};
+        use tracing::Instrument;
+        let (mut tx, rx) = mpsc::channel::<StreamEvent>(1024);
+
+        // A full channel waits for the consumer; only a closed one ends the bridge early.
+        let bridge_span = tracing::info_span!("ollama.ndjson.bridge");
+        let provider = self.name.clone();
+        let bridge = async move {
+            let mut calls = 0;
+            while let Some(line_res) = lines.next().await {
+                let line = match line_res {
+                    Ok(line) => line,
+                    Err(e) => {
+                        let _ = tx.send(StreamEvent::Error(e)).await;
+                        return; // terminal
+                    }
+                };
+                let json = line.line.trim();
+                if json.is_empty() {
+                    continue;
+                }
+                let chunk = match serde_json::from_str::<OChatChunk>(json) {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        let _ = tx
+                            .send(StreamEvent::Error(malformed_chunk(&provider, json, e)))
+                            .await;
+                        return; // terminal
+                    }
+                };
+                if let Some(message) = chunk.error {
+                    let err = AiProxyError::ProviderError {
+                        provider: provider.clone(),
+                        code: "stream_error".into(),
+                        message,
+                        upstream: Box::new(Upstream::default()),
+                    };
+                    let _ = tx.send(StreamEvent::Error(err)).await;
+                    return; // terminal
+                }
+                if let Some(reply) = chunk.message {
+                    if !reply.content.is_empty()
+                        && tx.send(StreamEvent::DeltaText(reply.content)).await.is_err()
+                    {
+                        return; // receiver gone
+                    }
+                    // Ollama sends each call whole, in a single line.
+                    let new_calls = tool_calls(reply.tool_calls, calls);
+                    calls += new_calls.len();
+                    for call in new_calls {
+                        if tx.send(StreamEvent::ToolCall(call)).await.is_err() {
+                            return; // receiver gone
+                        }
+                    }
+                }
+                if chunk.done {
+                    let usage = StreamEvent::Usage {
+                        prompt: chunk.prompt_eval_count,
+                        completion: chunk.eval_count,
+                    };
+                    if tx.send(usage).await.is_err() {
+                        return; // receiver gone
+                    }
+                    let reason = map_done(chunk.done_reason.as_deref(), calls > 0);
+                    let _ = tx.send(StreamEvent::Stop { reason }).await;
+                    return;
+                }
+            }
+            let _ = tx.send(StreamEvent::Stop { reason: None }).await;
+        }
+        .instrument(bridge_span);
+
+        Ok(crate::stream::with_feeder(bridge, Box::pin(rx)))
+    }
+}
+
+#[derive(Serialize)]
+struct OEmbedReq<'a> {
+    model: &'a str,
+    input: &'a [String],
+}
+#[derive(Deserialize)]
+struct OEmbedResp {
+    embeddings: Vec<Vec<f32>>,
+    #[serde(default)]
+    prompt_eval_count: Option<u32>,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for Ollama {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        let payload = OEmbedReq {
+            model: &req.model,
+            input: &req.inputs,
+        };
+        let retries = RetryLog::default();
+        let ctx = RequestCtx {
+            request_id: None,
+            turn_id: None,
+            idempotency_key: req.client_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: Some(&retries),
+            stream_timeouts: StreamTimeouts::default(),
+        };
+        let owned_headers = self.headers(&ctx);
+        let hdrs: Vec<(&str, &str)> = owned_headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let url = format!("{}/api/embed", self.base);
+        let (resp, provider_id, latency_ms) = self
+            .http
+            .post_json::<_, OEmbedResp>(&url, &payload, &hdrs, &ctx)
+            .await?;
+        let prompt_tokens = resp.prompt_eval_count.unwrap_or(0);
+        let clog = crate::telemetry::CompletionLog::new()
+            .provider("ollama")
+            .model(&req.model)
+            .requested_model_opt(req.requested_model.as_deref())
+            .provider_request_id_opt(provider_id.as_deref())
+            .client_key_opt(req.client_key.as_deref())
+            .created_at_ms(Self::now_ms() as u64)
+            .latency_ms(latency_ms as u64)
+            .tokens(Some(prompt_tokens), Some(0), Some(prompt_tokens));
+        let (reasons, backoff_ms) = retries.take();
+        crate::telemetry::emit_completion(clog.retried(reasons, backoff_ms));
+        Ok(EmbedResponse {
+            model: req.model,
+            vectors: resp.embeddings,
+            usage: prompt_tokens,
+            cached: false,
+            provider: self.name.clone(),
+            cost_usd: None,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct OModelList {
+    models: Vec<OModel>,
+}
+#[derive(Deserialize)]
+struct OModel {
+    name: String,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ModelsProvider for Ollama {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The models pulled onto the Ollama host.
+    async fn list_models(&self) -> CoreResult<Vec<ModelInfo>> {
+        let ctx = RequestCtx {
+            request_id: None,
+            turn_id: None,
+            idempotency_key: None,
+            model: None,
+            requested_model: None,
+            retries: None,
+            stream_timeouts: StreamTimeouts::default(),
+        };
+        let owned_headers = self.headers(&ctx);
+        let hdrs: Vec<(&str, &str)> = owned_headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let url = format!("{}/api/tags", self.base);
+        let (resp, _provider_id, _lat) =
+            self.http.get_json::<OModelList>(&url, &hdrs, &ctx).await?;
+        Ok(resp
+            .models
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.name,
+                provider: self.name.clone(),
+                owned_by: None,
+                context_window: None,
+                max_output_tokens: None,
+            })
+            .collect())
+    }
+}
+
+impl ProviderCaps for Ollama {
+    fn capabilities(&self) -> &'static [Capability] {
+        &[
+            Capability::Chat,
+            Capability::ChatStream,
+            Capability::Embed,
+            Capability::ListModels,
+            Capability::Tools,
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use httpmock::{Method::POST, MockServer};
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn chat_sends_options_and_named_messages() {
+        let server = MockServer::start();
+        let provider = Ollama::new_for_tests(&server.base_url());
+        let m = server.mock(|when, then| {
+            when.method(POST).path("/api/chat").json_body(json!({
+                "model": "llama3.1",
+                "messages": [{"role": "user", "content": "ann: Hi"}],
+                "stream": false,
+                "options": {"temperature": 0.2, "num_predict": 64}
+            }));
+            then.status(200).json_body(json!({
+                "model": "llama3.1",
+                "message": {"role": "assistant", "content": "Hello!"},
+                "done": true,
+                "done_reason": "stop",
+                "prompt_eval_count": 9,
+                "eval_count": 2
+            }));
+        });
+        let mut msg = ChatMessage::user("Hi");
+        msg.name = Some("ann".into());
+        let req = ChatRequest::builder()
+            .model("llama3.1")
+            .message(msg)
+            .temperature(0.2)
+            .max_output_tokens(64)
+            .build();
+        let resp = provider.chat(req).await.expect("chat ok");
+        m.assert();
+        assert_eq!(resp.text, "Hello!");
+        assert_eq!(resp.provider, "ollama");
+        assert_eq!(resp.usage, Usage::new(9, 2));
+        assert_eq!(resp.cost_usd, None);
+    }
+
+    #[test]
+    fn images_are_sent_inline_only() {
+        let parts = vec![
+            ContentPart::text("what is this?"),
+            ContentPart::image_base64("image/png", "iVBORw0"),
+        ];
+        let msg = ChatMessage::user(parts);
+        assert_eq!(
+            serde_json::to_value(OMessage::try_from(&msg).unwrap()).unwrap(),
+            json!({"role": "user", "content": "what is this?", "images": ["iVBORw0"]})
+        );
+        let remote = ChatMessage::user(vec![ContentPart::image_url("https://example.com/a.png")]);
+        assert!(matches!(
+            OMessage::try_from(&remote),
+            Err(AiProxyError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn tool_calls_are_numbered_and_stop_as_tool_use() {
+        let server = MockServer::start();
+        let provider = Ollama::new_for_tests(&server.base_url());
+        let call = json!({"function": {"name": "get_weather", "arguments": {"city": "Oslo"}}});
+        let line = |body: serde_json::Value| format!("{body}\n");
+        let stream_body = [
+            line(json!({"message": {"role": "assistant", "content": "", "tool_calls": [call]}, "done": false})),
+            line(json!({"message": {"role": "assistant", "content": ""}, "done": true, "done_reason": "stop", "prompt_eval_count": 5, "eval_count": 3})),
+        ]
+        .concat();
+        let _m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/api/chat")
+                .body_contains("\"stream\":true");
+            then.status(200).body(stream_body);
+        });
+        let req = ChatRequest::builder()
+            .model("qwen2.5")
+            .user("Weather?")
+            .build();
+        let events: Vec<StreamEvent> = provider
+            .chat_stream_events(req)
+            .await
+            .expect("stream starts")
+            .collect()
+            .await;
+        let expected = ToolCall {
+            id: "call_0".into(),
+            name: "get_weather".into(),
+            arguments: json!({"city": "Oslo"}),
+        };
+        assert!(matches!(&events[0], StreamEvent::ToolCall(c) if *c == expected));
+        assert!(matches!(
+            events[1],
+            StreamEvent::Usage {
+                prompt: Some(5),
+                completion: Some(3)
+            }
+        ));
+        assert!(matches!(
+            events[2],
+            StreamEvent::Stop {
+                reason: Some(StopReason::ToolUse)
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn stream_errors_end_the_stream() {
+        let server = MockServer::start();
+        let provider = Ollama::new_for_tests(&server.base_url());
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/api/chat");
+            then.status(200).body(format!(
+                "{}\n{}\n",
+                json!({"message": {"role": "assistant", "content": "Hel"}, "done": false}),
+                json!({"error": "model runner has unexpectedly stopped"})
+            ));
+        });
+        let req = ChatRequest::builder().model("llama3.1").user("Hi").build();
+        let events: Vec<StreamEvent> = provider
+            .chat_stream_events(req)
+            .await
+            .expect("stream starts")
+            .collect()
+            .await;
+        assert_eq!(events.len(), 2);
+        assert!(matches!(
+            &events[1],
+            StreamEvent::Error(AiProxyError::ProviderError { message, .. })
+                if message.contains("unexpectedly stopped")
+        ));
+    }
+
+    struct OllamaFixture;
+
+    impl crate::providers::conformance::ChatFixture for OllamaFixture {
+        type Provider = Ollama;
+
+        fn provider(&self, base_url: &str) -> Ollama {
+            Ollama::new_for_tests(base_url)
+        }
+
+        fn chat_path(&self) -> &str {
+            "/api/chat"
+        }
+
+        fn stop_reasons(&self) -> Vec<(Option<&'static str>, Option<StopReason>)> {
+            vec![
+                (Some("stop"), Some(StopReason::Stop)),
+                (Some("length"), Some(StopReason::Length)),
+                (Some("unload"), Some(StopReason::Other)),
+                (None, None),
+            ]
+        }
+
+        fn success_body(
+            &self,
+            text: &str,
+            stop: Option<&str>,
+            prompt: u32,
+            completion: u32,
+        ) -> serde_json::Value {
+            json!({
+                "model": "conformance-model",
+                "message": {"role": "assistant", "content": text},
+                "done": true,
+                "done_reason": stop,
+                "prompt_eval_count": prompt,
+                "eval_count": completion
+            })
+        }
+
+        fn stream_body(&self, deltas: &[&str], stop: Option<&str>) -> Option<String> {
+            let mut body: String = deltas
+                .iter()
+                .map(|d| {
+                    format!(
+                        "{}\n",
+                        json!({"message": {"role": "assistant", "content": d}, "done": false})
+                    )
+                })
+                .collect();
+            body.push_str(&format!(
+                "{}\n",
+                json!({"message": {"role": "assistant", "content": ""}, "done": true, "done_reason": stop})
+            ));
+            Some(body)
+        }
+    }
+
+    impl crate::providers::conformance::EmbedFixture for OllamaFixture {
+        type Provider = Ollama;
+
+        fn provider(&self, base_url: &str) -> Ollama {
+            Ollama::new_for_tests(base_url)
+        }
+
+        fn embed_path(&self) -> &str {
+            "/api/embed"
+        }
+
+        fn success_body(&self, vectors: &[Vec<f32>], prompt: u32) -> serde_json::Value {
+            json!({
+                "model": "conformance-model",
+                "embeddings": vectors,
+                "prompt_eval_count": prompt
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_the_conformance_suite() {
+        crate::providers::conformance::check_chat(&OllamaFixture).await;
+        crate::providers::conformance::check_embed(&OllamaFixture).await;
+    }
+}
@@ -1,17 +1,26 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::borrow::Cow;
 
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
-use crate::error::CoreResult;
-use crate::http_client::{HttpClient, RequestCtx};
+use crate::config::{BillingOverridesCfg, ExtraParamsCfg};
+use crate::platform::{SystemTime, UNIX_EPOCH};
+use crate::pricing;
+use crate::error::{AiProxyError, CoreResult};
+use crate::http_client::{HttpClient, RequestCtx, RetryLog, StreamTimeouts};
 use crate::model::{
-    ChatMessage, ChatRequest, ChatResponse, EmbedRequest, EmbedResponse, StopReason,
+    ChatChoice, ChatMessage, ChatRequest, ChatResponse, Citation, EmbedRequest, EmbedResponse,
+    MessageContent, ModelInfo, StopReason, TokenLogprob, ToolCall, ToolDialect, Usage,
+};
+use crate::provider::{
+    Capability, ChatProvider, EmbedProvider, ModelsProvider, ModerationProvider, ProviderCaps,
 };
-use crate::provider::{Capability, ChatProvider, EmbedProvider, ProviderCaps};
 use crate::stream::{BoxStreamEv, StreamEvent};
 use secrecy::{ExposeSecret, SecretString};
 
+#[cfg(feature = "native")]
+mod realtime;
+
 #[derive(Debug, Clone)]
 pub struct OpenAI {
     http: HttpClient,
@@ -20,6 +29,9 @@ pub struct OpenAI {
     project: Option<String>,
     name: String, // usually "openai"
     api_key: SecretString,
+    forward_metadata: bool,
+    extra_params: ExtraParamsCfg,
+    billing: BillingOverridesCfg,
 }
 
 impl OpenAI {
@@ -37,10 +49,32 @@ impl OpenAI {
             org,
             project,
             name: "openai".into(),
+            forward_metadata: false,
+            extra_params: ExtraParamsCfg::default(),
+            billing: BillingOverridesCfg::default(),
         }
     }
 
-    #[cfg(test)]
+    /// Send `metadata` and a redacted client-key label as `user` (see `PrivacyCfg`).
+    pub fn with_forward_metadata(mut self, on: bool) -> Self {
+        self.forward_metadata = on;
+        self
+    }
+
+    /// Limit which `ChatRequest.extra` parameters reach the provider.
+    pub fn with_extra_params(mut self, cfg: ExtraParamsCfg) -> Self {
+        self.extra_params = cfg;
+        self
+    }
+
+    /// Let requests bill to the listed organizations and projects (see
+    /// `ChatRequest.organization`).
+    pub fn with_billing_overrides(mut self, cfg: BillingOverridesCfg) -> Self {
+        self.billing = cfg;
+        self
+    }
+
+    #[cfg(any(test, feature = "test-utils"))]
     pub fn new_for_tests(server_base: &str) -> Self {
         OpenAI::new(
             HttpClient::new_default().unwrap(),
@@ -52,19 +86,57 @@ impl OpenAI {
     }
 
     fn headers(&self, _ctx: &RequestCtx<'_>) -> Vec<(String, String)> {
+        self.billed_headers(self.org.as_deref(), self.project.as_deref())
+    }
+
+    /// Headers for a chat call, billed to `req`'s organization and project overrides when
+    /// the allowlist admits them.
+    fn chat_headers(&self, req: &ChatRequest) -> CoreResult<Vec<(String, String)>> {
+        let check = |kind: &str, value: Option<&String>, allowed: &[String]| match value {
+            Some(v) if !allowed.contains(v) => Err(AiProxyError::Validation(format!(
+                "{kind} '{v}' is not allowed for {}",
+                self.name
+            ))),
+            _ => Ok(()),
+        };
+        check(
+            "organization",
+            req.organization.as_ref(),
+            &self.billing.organizations,
+        )?;
+        check("project", req.project.as_ref(), &self.billing.projects)?;
+        Ok(self.billed_headers(
+            req.organization.as_deref().or(self.org.as_deref()),
+            req.project.as_deref().or(self.project.as_deref()),
+        ))
+    }
+
+    fn billed_headers(&self, org: Option<&str>, project: Option<&str>) -> Vec<(String, String)> {
         let mut h = vec![(
             "Authorization".to_string(),
             format!("Bearer {}", self.api_key.expose_secret()),
         )];
-        if let Some(org) = &self.org {
-            h.push(("OpenAI-Organization".into(), org.clone()));
+        if let Some(org) = org {
+            h.push(("OpenAI-Organization".into(), org.to_string()));
         }
-        if let Some(project) = &self.project {
-            h.push(("OpenAI-Project".into(), project.clone()));
+        if let Some(project) = project {
+            h.push(("OpenAI-Project".into(), project.to_string()));
         }
         h
     }
 
+    fn user(&self, req: &ChatRequest) -> Option<String> {
+        self.forward_metadata
+            .then(|| req.client_key.as_deref().map(crate::usage::key_label))
+            .flatten()
+    }
+
+    fn metadata<'a>(&self, req: &'a ChatRequest) -> Option<&'a serde_json::Value> {
+        self.forward_metadata
+            .then_some(req.metadata.as_ref())
+            .flatten()
+    }
+
     fn now_ms() -> i64 {
         SystemTime::now()
             .duration_since(UNIX_EPOCH)
@@ -77,7 +149,7 @@ impl OpenAI {
 #[derive(Serialize)]
 struct OAChatReq<'a> {
     model: &'a str,
-    messages: &'a [ChatMessage],
+    messages: Cow<'a, [ChatMessage]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -88,6 +160,28 @@ struct OAChatReq<'a> {
     stop: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<OAStreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    metadata: Option<&'a serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
+}
+
+/// Asks for a final chunk carrying the stream's usage, with empty `choices`.
+#[derive(Serialize)]
+struct OAStreamOptions {
+    include_usage: bool,
 }
 
 #[derive(Deserialize)]
@@ -99,21 +193,152 @@ struct OAChatResp {
 
 #[derive(Deserialize)]
 struct OAChoice {
-    message: ChatMessage,
+    #[serde(default)]
+    index: Option<u32>,
+    message: OAMessage,
     #[serde(default)]
     finish_reason: Option<String>,
+    #[serde(default)]
+    logprobs: Option<OALogprobs>,
+}
+
+/// Assistant message; `content` is null when the model only calls tools.
+#[derive(Deserialize)]
+struct OAMessage {
+    #[serde(default)]
+    content: Option<MessageContent>,
+    #[serde(default)]
+    tool_calls: Vec<OAToolCall>,
+    #[serde(default)]
+    refusal: Option<String>,
+    #[serde(default)]
+    annotations: Vec<OAAnnotation>,
+}
+
+
+/// `message.annotations` entry; only `url_citation` annotations name a source.
+#[derive(Deserialize)]
+struct OAAnnotation {
+    #[serde(default)]
+    url_citation: Option<OAUrlCitation>,
+}
+
+#[derive(Deserialize)]
+struct OAUrlCitation {
+    url: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    start_index: Option<u32>,
+    #[serde(default)]
+    end_index: Option<u32>,
+}
+
+impl From<OAUrlCitation> for Citation {
+    fn from(c: OAUrlCitation) -> Self {
+        Citation {
+            url: Some(c.url),
+            title: c.title,
+            start_index: c.start_index,
+            end_index: c.end_index,
+            cited_text: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct OAToolCall {
+    id: String,
+    function: OAFunctionCall,
+}
+
+#[derive(Deserialize)]
+struct OAFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: String,
+}
+
+#[derive(Deserialize)]
+struct OALogprobs {
+    #[serde(default)]
+    content: Option<Vec<TokenLogprob>>,
+}
+
+impl OAChoice {
+    fn into_choice(self, position: usize) -> ChatChoice {
+        ChatChoice {
+            index: self.index.unwrap_or(position as u32),
+            text: self
+                .message
+                .content
+                .map(|c| c.text().into_owned())
+                .unwrap_or_default(),
+            stop_reason: match self.message.refusal {
+                Some(_) => Some(StopReason::Refusal),
+                None => map_finish(self.finish_reason.as_deref()),
+            },
+            tool_calls: self
+                .message
+                .tool_calls
+                .into_iter()
+                .map(|t| ToolCall::from_json_text(t.id, t.function.name, &t.function.arguments))
+                .collect(),
+            logprobs: self.logprobs.and_then(|l| l.content),
+            refusal: self.message.refusal,
+            annotations: self
+                .message
+                .annotations
+                .into_iter()
+                .filter_map(|a| a.url_citation.map(Citation::from))
+                .collect(),
+        }
+    }
 }
 
 #[derive(Deserialize)]
 struct OAUsage {
     prompt_tokens: u32,
     completion_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<OATokenDetails>,
+    #[serde(default)]
+    completion_tokens_details: Option<OATokenDetails>,
+}
+
+#[derive(Deserialize)]
+struct OATokenDetails {
+    #[serde(default)]
+    cached_tokens: Option<u32>,
+    #[serde(default)]
+    reasoning_tokens: Option<u32>,
+    #[serde(default)]
+    audio_tokens: Option<u32>,
+}
+
+impl From<OAUsage> for Usage {
+    fn from(u: OAUsage) -> Self {
+        let prompt = u.prompt_tokens_details.as_ref();
+        let completion = u.completion_tokens_details.as_ref();
+        Usage {
+            prompt: u.prompt_tokens,
+            completion: u.completion_tokens,
+            cached_prompt: prompt.and_then(|d| d.cached_tokens),
+            reasoning: completion.and_then(|d| d.reasoning_tokens),
+            audio_prompt: prompt.and_then(|d| d.audio_tokens),
+            audio_completion: completion.and_then(|d| d.audio_tokens),
+        }
+    }
 }
 
 // ---- Streaming wire structs (SSE "chunk" shape) — actively used by drive_openai_sse ----
 #[derive(Deserialize)]
 struct OAChatStreamChunk {
+    #[serde(default)]
     choices: Vec<OAStreamChoice>,
+    /// Only on the last chunk, when `stream_options.include_usage` was sent.
+    #[serde(default)]
+    usage: Option<OAUsage>,
 }
 
 #[derive(Deserialize)]
@@ -128,7 +353,66 @@ struct OAStreamChoice {
 struct OAStreamDelta {
     #[serde(default)]
     content: Option<String>,
-    // NOTE: extend here if/when we support tool calls, role changes, etc.
+    #[serde(default)]
+    tool_calls: Vec<OAToolCallDelta>,
+}
+
+/// A fragment of a streamed tool call. The first fragment for an `index` carries the id and
+/// name; the arguments arrive as JSON text split across fragments.
+#[derive(Deserialize)]
+struct OAToolCallDelta {
+    #[serde(default)]
+    index: usize,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    function: Option<OAFunctionDelta>,
+}
+
+#[derive(Deserialize)]
+struct OAFunctionDelta {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    arguments: Option<String>,
+}
+
+/// Streamed tool calls being assembled: `(id, name, arguments)` by `index`.
+#[derive(Default)]
+struct ToolCallDeltas(Vec<(String, String, String)>);
+
+impl ToolCallDeltas {
+    fn push(&mut self, delta: &OAToolCallDelta) {
+        if self.0.len() <= delta.index {
+            self.0.resize_with(delta.index + 1, Default::default);
+        }
+        let (id, name, arguments) = &mut self.0[delta.index];
+        if let Some(i) = &delta.id {
+            id.push_str(i);
+        }
+        if let Some(f) = &delta.function {
+            name.push_str(f.name.as_deref().unwrap_or_default());
+            arguments.push_str(f.arguments.as_deref().unwrap_or_default());
+        }
+    }
+
+    /// The calls assembled so far, leaving none behind.
+    fn take(&mut self) -> Vec<ToolCall> {
+        std::mem::take(&mut self.0)
+            .into_iter()
+            .filter(|(_, name, _)| !name.is_empty())
+            .map(|(id, name, arguments)| ToolCall::from_json_text(id, name, &arguments))
+            .collect()
+    }
+}
+
+/// A stream chunk that is not the expected JSON, quoting the start of it.
+fn malformed_chunk(provider: &str, json: &str, e: serde_json::Error) -> AiProxyError {
+    let excerpt: String = json.chars().take(120).collect();
+    AiProxyError::MalformedChunk {
+        provider: provider.to_string(),
+        message: format!("{e} in `{excerpt}`"),
+    }
 }
 
 fn map_finish(s: Option<&str>) -> Option<StopReason> {
@@ -142,12 +426,17 @@ fn map_finish(s: Option<&str>) -> Option<StopReason> {
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl ChatProvider for OpenAI {
     fn name(&self) -> &str {
         &self.name
     }
 
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
+
     async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
         use tracing::{field, info_span, Instrument};
         let model_for_span = req.model.clone();
@@ -163,22 +452,38 @@ impl ChatProvider for OpenAI {
             finish_reason = field::Empty,
         );
         async move {
+        let (tools, tool_choice) = super::tool_params(&req, ToolDialect::OpenAi);
         let payload = OAChatReq {
             model: &req.model,
-            messages: &req.messages,
+            messages: super::openai_messages(&req.messages),
             temperature: req.temperature,
             top_p: req.top_p,
             max_tokens: req.max_output_tokens,
             stop: req.stop_sequences.clone(),
             stream: None,
+            stream_options: None,
+            user: self.user(&req),
+            metadata: self.metadata(&req),
+            n: req.n,
+            logprobs: req.logprobs.then_some(true),
+            response_format: super::response_format(req.response_schema.as_ref()),
+            tools,
+            tool_choice,
         };
-        let started = std::time::Instant::now();
+        let payload =
+            super::with_extra(&self.name, &payload, req.extra.as_ref(), &self.extra_params)?;
+        let started = crate::platform::Instant::now();
+        let retries = RetryLog::default();
         let ctx = RequestCtx {
             request_id: req.request_id.as_deref(),
             turn_id: req.trace_id.as_deref(),
             idempotency_key: req.idempotency_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: Some(&retries),
+            stream_timeouts: StreamTimeouts::default(),
         };
-        let owned_headers = self.headers(&ctx);
+        let owned_headers = self.chat_headers(&req)?;
         let hdrs: Vec<(&str, &str)> = owned_headers
             .iter()
             .map(|(k, v)| (k.as_str(), v.as_str()))
@@ -204,30 +509,35 @@ impl ChatProvider for OpenAI {
                 serde_json::to_string(&payload).unwrap_or_default()
             );
         }
-        let (resp, provider_id, latency_ms) = self
+        let (resp, raw, provider_id, latency_ms) = self
             .http
-            .post_json::<_, OAChatResp>(&url, &payload, &hdrs, &ctx)
+            .post_json_keep_raw::<_, OAChatResp>(&url, &payload, &hdrs, &ctx, req.include_raw)
             .await?;
 
-        let text = resp
+        let choices: Vec<ChatChoice> = resp
             .choices
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| c.into_choice(i))
+            .collect();
+        let text = choices.first().map(|c| c.text.clone()).unwrap_or_default();
+        let stop_reason = choices.first().and_then(|c| c.stop_reason);
+        let refusal = choices.first().and_then(|c| c.refusal.clone());
+        let annotations = choices
             .first()
-            .map(|c| c.message.content.clone())
+            .map(|c| c.annotations.clone())
             .unwrap_or_default();
-        let stop_reason = resp
-            .choices
+        let tool_calls = choices
             .first()
-            .and_then(|c| map_finish(c.finish_reason.as_deref()));
-        let (usage_p, usage_c) = resp
-            .usage
-            .map(|u| (u.prompt_tokens, u.completion_tokens))
-            .unwrap_or((0, 0));
+            .map(|c| c.tool_calls.clone())
+            .unwrap_or_default();
+        let usage = resp.usage.map(Usage::from).unwrap_or_default();
+        let cost_usd = pricing::cost_usd(&self.name, &req.model, usage.prompt, usage.completion);
 
         let resp = ChatResponse {
             model: req.model,
             text,
-            usage_prompt: usage_p,
-            usage_completion: usage_c,
+            usage,
             cached: false,
             provider: self.name.clone(),
             transcript_id: None,
@@ -236,6 +546,14 @@ impl ChatProvider for OpenAI {
             provider_request_id: provider_id.or(Some(resp.id)),
             created_at_ms: Self::now_ms(),
             latency_ms,
+            raw,
+            choices,
+            tool_calls,
+            refusal,
+            annotations,
+            truncation: None,
+            cost_usd,
+            guardrail: vec![],
         };
         if let Some(fr) = resp.stop_reason.as_ref() {
             let s = stop_to_string(*fr);
@@ -243,20 +561,24 @@ impl ChatProvider for OpenAI {
         }
         tracing::Span::current().record("latency_ms", started.elapsed().as_millis() as u64);
         // Emit structured completion log (non-streaming)
-        let tokens_total = resp.usage_prompt.checked_add(resp.usage_completion);
+        let tokens_total = resp.usage.prompt.checked_add(resp.usage.completion);
         let stop_lc = resp.stop_reason.as_ref().map(|s| stop_to_code(*s));
         let clog = crate::telemetry::CompletionLog::new()
             .provider("openai")
             .model(&resp.model)
+            .requested_model_opt(req.requested_model.as_deref())
             .request_id_opt(req.request_id.as_deref())
             .turn_id_opt(req.trace_id.as_deref())
             .provider_request_id_opt(resp.provider_request_id.as_deref())
+            .client_key_opt(req.client_key.as_deref())
             .created_at_ms(resp.created_at_ms as u64)
             .latency_ms(resp.latency_ms as u64)
             .stop_reason_opt(stop_lc)
             .text_opt(Some(&resp.text))
-            .tokens(Some(resp.usage_prompt), Some(resp.usage_completion), tokens_total);
-        crate::telemetry::emit_completion(clog);
+            .cost_usd_opt(resp.cost_usd)
+            .tokens(Some(resp.usage.prompt), Some(resp.usage.completion), tokens_total);
+        let (reasons, backoff_ms) = retries.take();
+        crate::telemetry::emit_completion(clog.retried(reasons, backoff_ms));
         Ok(resp)
         }
         .instrument(span)
@@ -265,21 +587,36 @@ impl ChatProvider for OpenAI {
 
     async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
         // Build payload with stream=true, initiate SSE
+        let (tools, tool_choice) = super::tool_params(&req, ToolDialect::OpenAi);
         let payload = OAChatReq {
             model: &req.model,
-            messages: &req.messages,
+            messages: super::openai_messages(&req.messages),
             temperature: req.temperature,
             top_p: req.top_p,
             max_tokens: req.max_output_tokens,
             stop: req.stop_sequences.clone(),
             stream: Some(true),
+            stream_options: Some(OAStreamOptions { include_usage: true }),
+            user: self.user(&req),
+            metadata: self.metadata(&req),
+            n: None,
+            logprobs: None,
+            response_format: super::response_format(req.response_schema.as_ref()),
+            tools,
+            tool_choice,
         };
+        let payload =
+            super::with_extra(&self.name, &payload, req.extra.as_ref(), &self.extra_params)?;
         let ctx = RequestCtx {
             request_id: req.request_id.as_deref(),
             turn_id: req.trace_id.as_deref(),
             idempotency_key: req.idempotency_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: None,
+            stream_timeouts: StreamTimeouts::of(&req),
         };
-        let owned_headers = self.headers(&ctx);
+        let owned_headers = self.chat_headers(&req)?;
         let hdrs: Vec<(&str, &str)> = owned_headers
             .iter()
             .map(|(k, v)| (k.as_str(), v.as_str()))
@@ -290,13 +627,17 @@ impl ChatProvider for OpenAI {
 
         // Bridge SSE → StreamEvent via bounded mpsc channel
         use futures::channel::mpsc;
-        use futures_util::StreamExt;
+        use futures_util::{SinkExt, StreamExt};
         use tracing::Instrument;
         let (mut tx, rx) = mpsc::channel::<StreamEvent>(1024);
 
+        // A full channel waits for the consumer; only a closed one ends the bridge early.
         let bridge_span = tracing::info_span!("openai.sse.bridge");
-        tokio::spawn(async move {
-            let mut sent_stop = false;
+        let provider = self.name.clone();
+        let bridge = async move {
+            // The usage chunk follows the finish reason, so the stop waits for the end.
+            let mut finish: Option<Option<StopReason>> = None;
+            let mut tool_calls = ToolCallDeltas::default();
             while let Some(line_res) = sse.next().await {
                 match line_res {
                     Ok(line) => {
@@ -305,35 +646,58 @@ impl ChatProvider for OpenAI {
                         if let Some(rest) = raw.strip_prefix("data:") {
                             let json = rest.trim_start();
                             if json.is_empty() { continue; }
-                            if let Ok(chunk) = serde_json::from_str::<OAChatStreamChunk>(json)
-                                && let Some(choice) = chunk.choices.first()
-                            {
+                            let chunk = match serde_json::from_str::<OAChatStreamChunk>(json) {
+                                Ok(chunk) => chunk,
+                                Err(e) => {
+                                    let _ = tx.send(StreamEvent::Error(malformed_chunk(&provider, json, e))).await;
+                                    return; // terminal
+                                }
+                            };
+                            if let Some(choice) = chunk.choices.first() {
                                 if let Some(ref txt) = choice.delta.content
-                                    && tx.try_send(StreamEvent::DeltaText(txt.clone())).is_err()
+                                    && tx.send(StreamEvent::DeltaText(txt.clone())).await.is_err()
                                 {
-                                    tracing::debug!("openai.sse.bridge: dropped delta due to backpressure");
+                                    return; // receiver gone
                                 }
-                                if !sent_stop && choice.finish_reason.is_some() {
-                                    if tx.try_send(StreamEvent::Stop { reason: map_finish(choice.finish_reason.as_deref()) }).is_err() {
-                                        tracing::debug!("openai.sse.bridge: dropped stop due to backpressure");
+                                for delta in &choice.delta.tool_calls {
+                                    tool_calls.push(delta);
+                                }
+                                if finish.is_none() && choice.finish_reason.is_some() {
+                                    // A call is complete once its choice finishes.
+                                    for call in tool_calls.take() {
+                                        if tx.send(StreamEvent::ToolCall(call)).await.is_err() {
+                                            return; // receiver gone
+                                        }
                                     }
-                                    sent_stop = true;
+                                    finish = Some(map_finish(choice.finish_reason.as_deref()));
+                                }
+                            }
+                            if let Some(usage) = chunk.usage {
+                                let usage = StreamEvent::Usage {
+                                    prompt: Some(usage.prompt_tokens),
+                                    completion: Some(usage.completion_tokens),
+                                };
+                                if tx.send(usage).await.is_err() {
+                                    return; // receiver gone
                                 }
                             }
                         }
                     }
                     Err(e) => {
-                        let _ = tx.try_send(StreamEvent::Error(e));
+                        let _ = tx.send(StreamEvent::Error(e)).await;
                         return; // terminal
                     }
                 }
             }
-            if !sent_stop {
-                let _ = tx.try_send(StreamEvent::Stop { reason: None });
+            for call in tool_calls.take() {
+                if tx.send(StreamEvent::ToolCall(call)).await.is_err() {
+                    return; // receiver gone
+                }
             }
-        }.instrument(bridge_span));
+            let _ = tx.send(StreamEvent::Stop { reason: finish.flatten() }).await;
+        }.instrument(bridge_span);
 
-        Ok(Box::pin(rx))
+        Ok(crate::stream::with_feeder(bridge, Box::pin(rx)))
     }
 }
 
@@ -352,6 +716,16 @@ struct OAEmbedReq<'a> {
 #[derive(Deserialize)]
 struct OAEmbedResp {
     data: Vec<OAVector>,
+    #[serde(default)]
+    usage: Option<OAEmbedUsage>,
+}
+
+#[derive(Deserialize)]
+struct OAEmbedUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    total_tokens: u32,
 }
 
 #[derive(Deserialize)]
@@ -359,7 +733,8 @@ struct OAVector {
     embedding: Vec<f32>,
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl EmbedProvider for OpenAI {
     fn name(&self) -> &str {
         &self.name
@@ -371,10 +746,15 @@ impl EmbedProvider for OpenAI {
             model: &req.model,
             input: OAInput::Many(&req.inputs),
         };
+        let retries = RetryLog::default();
         let ctx = RequestCtx {
             request_id: None,
             turn_id: None,
             idempotency_key: req.client_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: Some(&retries),
+            stream_timeouts: StreamTimeouts::default(),
         };
         let owned_headers = self.headers(&ctx);
         let hdrs: Vec<(&str, &str)> = owned_headers
@@ -402,104 +782,692 @@ impl EmbedProvider for OpenAI {
                 serde_json::to_string(&payload).unwrap_or_default()
             );
         }
-        let (resp, _provider_id, _lat) = self
+        let (resp, provider_id, latency_ms) = self
             .http
             .post_json::<_, OAEmbedResp>(&url, &payload, &hdrs, &ctx)
             .await?;
         let vectors = resp.data.into_iter().map(|d| d.embedding).collect();
+        let (prompt_tokens, total_tokens) = resp.usage.map_or((0, 0), |u| {
+            (u.prompt_tokens, u.total_tokens.max(u.prompt_tokens))
+        });
+        let cost_usd = pricing::cost_usd(&self.name, &req.model, prompt_tokens, 0);
+        // Embeddings bill prompt tokens only; the log feeds metrics and the usage ledger.
+        let clog = crate::telemetry::CompletionLog::new()
+            .provider("openai")
+            .model(&req.model)
+            .requested_model_opt(req.requested_model.as_deref())
+            .provider_request_id_opt(provider_id.as_deref())
+            .client_key_opt(req.client_key.as_deref())
+            .created_at_ms(Self::now_ms() as u64)
+            .latency_ms(latency_ms as u64)
+            .cost_usd_opt(cost_usd)
+            .tokens(Some(prompt_tokens), Some(0), Some(total_tokens));
+        let (reasons, backoff_ms) = retries.take();
+        crate::telemetry::emit_completion(clog.retried(reasons, backoff_ms));
         Ok(EmbedResponse {
             model: req.model,
             vectors,
-            usage: 0,
+            usage: prompt_tokens,
             cached: false,
             provider: self.name.clone(),
+            cost_usd,
         })
     }
-}
+}
+
+#[derive(Deserialize)]
+struct OAModelList {
+    data: Vec<OAModel>,
+}
+
+#[derive(Deserialize)]
+struct OAModel {
+    id: String,
+    #[serde(default)]
+    owned_by: Option<String>,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ModelsProvider for OpenAI {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn list_models(&self) -> CoreResult<Vec<ModelInfo>> {
+        let ctx = RequestCtx {
+            request_id: None,
+            turn_id: None,
+            idempotency_key: None,
+            model: None,
+            requested_model: None,
+            retries: None,
+            stream_timeouts: StreamTimeouts::default(),
+        };
+        let owned_headers = self.headers(&ctx);
+        let hdrs: Vec<(&str, &str)> = owned_headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let url = format!("{}/v1/models", self.base);
+        let (resp, _provider_id, _lat) =
+            self.http.get_json::<OAModelList>(&url, &hdrs, &ctx).await?;
+        Ok(resp
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                id: m.id,
+                provider: self.name.clone(),
+                owned_by: m.owned_by,
+                context_window: None,
+                max_output_tokens: None,
+            })
+            .collect())
+    }
+}
+
+/// Model behind `/v1/moderations`; free of charge, so it is not configurable.
+const MODERATION_MODEL: &str = "omni-moderation-latest";
+
+#[derive(Serialize)]
+struct OAModerationReq<'a> {
+    model: &'a str,
+    input: &'a str,
+}
+
+#[derive(Deserialize)]
+struct OAModerationResp {
+    results: Vec<OAModerationResult>,
+}
+
+#[derive(Deserialize)]
+struct OAModerationResult {
+    flagged: bool,
+    #[serde(default)]
+    categories: std::collections::BTreeMap<String, bool>,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ModerationProvider for OpenAI {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn moderate(&self, text: &str) -> CoreResult<Vec<String>> {
+        let ctx = RequestCtx {
+            request_id: None,
+            turn_id: None,
+            idempotency_key: None,
+            model: Some(MODERATION_MODEL),
+            requested_model: None,
+            retries: None,
+            stream_timeouts: StreamTimeouts::default(),
+        };
+        let owned_headers = self.headers(&ctx);
+        let hdrs: Vec<(&str, &str)> = owned_headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let url = format!("{}/v1/moderations", self.base);
+        let payload = OAModerationReq {
+            model: MODERATION_MODEL,
+            input: text,
+        };
+        let (resp, _provider_id, _lat) = self
+            .http
+            .post_json::<_, OAModerationResp>(&url, &payload, &hdrs, &ctx)
+            .await?;
+        let mut flagged = Vec::new();
+        for r in resp.results.into_iter().filter(|r| r.flagged) {
+            let before = flagged.len();
+            flagged.extend(
+                r.categories
+                    .into_iter()
+                    .filter(|(_, hit)| *hit)
+                    .map(|(c, _)| c),
+            );
+            if flagged.len() == before {
+                flagged.push("flagged".to_string());
+            }
+        }
+        flagged.dedup();
+        Ok(flagged)
+    }
+}
+
+impl ProviderCaps for OpenAI {
+    fn capabilities(&self) -> &'static [Capability] {
+        &[
+            Capability::Chat,
+            Capability::ChatStream,
+            Capability::Embed,
+            Capability::ListModels,
+            Capability::Moderate,
+            #[cfg(feature = "native")]
+            Capability::Realtime,
+        ]
+    }
+}
+
+#[tokio::test]
+async fn embed_posts_model_and_input_shape() {
+    use httpmock::prelude::*;
+
+    let server = MockServer::start();
+    let provider = OpenAI::new_for_tests(&server.base_url());
+
+    let m = server.mock(|when, then| {
+        when.method(POST)
+            .path("/v1/embeddings")
+            .body_contains("\"model\":\"text-embedding-3-small\"")
+            .body_contains("\"input\"");
+        then.status(200)
+            .header("content-type", "application/json")
+            .body(r#"{ "data": [ { "embedding": [0.1, 0.2] } ] }"#);
+    });
+
+    let req = EmbedRequest::builder()
+        .model("text-embedding-3-small")
+        .input("hello")
+        .build();
+    let _ = provider.embed(req).await.expect("embed ok");
+
+    m.assert();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use httpmock::Method::POST;
+    use httpmock::MockServer;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn chat_200_maps_fields() {
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
+
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200).json_body(json!({
+                "id": "cmpl_123",
+                "choices": [{
+                    "message": {"role":"assistant", "content":"Hello!"},
+                    "finish_reason": "stop"
+                }],
+                "usage": {"prompt_tokens": 10, "completion_tokens": 5}
+            }));
+        });
+
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("Hi")
+            .temperature(1.0)
+            .top_p(1.0)
+            .request_id("turn-1")
+            .max_output_tokens(128)
+            .build();
+
+        let resp = provider.chat(req).await.expect("chat ok");
+        assert_eq!(resp.text, "Hello!");
+        assert_eq!(resp.stop_reason, Some(StopReason::Stop));
+        assert_eq!(resp.usage, Usage::new(10, 5));
+        let cost = resp.cost_usd.expect("gpt-4o is priced");
+        assert!((cost - 7.5e-5).abs() < 1e-12, "cost {cost}");
+        assert_eq!(resp.provider, "openai");
+        assert_eq!(resp.provider_request_id, Some("cmpl_123".into()));
+    }
+
+    #[tokio::test]
+    async fn chat_forwards_tool_results_as_tool_messages() {
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
+
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .body_contains(r#"{"role":"tool","content":"72F","tool_call_id":"call_1"}"#);
+            then.status(200).json_body(json!({
+                "id": "cmpl_1",
+                "choices": [{"message": {"role":"assistant", "content":"It is 72F."}}]
+            }));
+        });
+
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("weather?")
+            .tool("call_1", "72F")
+            .build();
+
+        provider.chat(req).await.expect("chat ok");
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn tools_are_offered_and_calls_come_back_parsed() {
+        use crate::model::{ToolChoice, ToolSpec};
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let m = server.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions").json_body_partial(
+                r#"{"tools": [{"type": "function", "function": {"name": "lookup", "parameters": {"type": "object"}}}],
+                    "tool_choice": {"type": "function", "function": {"name": "lookup"}}}"#,
+            );
+            then.status(200).json_body(json!({
+                "id": "cmpl_1",
+                "choices": [{
+                    "message": {"role": "assistant", "content": null, "tool_calls": [
+                        {"id": "call_1", "type": "function",
+                         "function": {"name": "lookup", "arguments": "{\"q\":\"x\"}"}}
+                    ]},
+                    "finish_reason": "tool_calls"
+                }]
+            }));
+        });
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("look it up")
+            .tools([ToolSpec {
+                name: "lookup".into(),
+                description: None,
+                parameters: json!({"type": "object"}),
+            }])
+            .tool_choice(ToolChoice::Tool {
+                name: "lookup".into(),
+            })
+            .build();
+
+        let resp = provider.chat(req).await.expect("chat ok");
+        m.assert();
+        assert_eq!(resp.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(
+            resp.tool_calls,
+            [ToolCall {
+                id: "call_1".into(),
+                name: "lookup".into(),
+                arguments: json!({"q": "x"}),
+            }]
+        );
+        assert_eq!(resp.choices[0].tool_calls, resp.tool_calls);
+    }
+
+    #[tokio::test]
+    async fn streamed_tool_call_fragments_are_assembled() {
+        use futures_util::StreamExt;
+        let server = MockServer::start();
+        let chunk = |json: &str| format!("data: {json}\n\n");
+        let sse_body = [
+            chunk(r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"id":"call_1","function":{"name":"lookup","arguments":""}}]}}]}"#),
+            chunk(r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"{\"q\":"}}]}}]}"#),
+            chunk(r#"{"choices":[{"delta":{"tool_calls":[{"index":0,"function":{"arguments":"\"x\"}"}}]}}]}"#),
+            chunk(r#"{"choices":[{"finish_reason":"tool_calls"}]}"#),
+            "data: [DONE]\n\n".to_string(),
+        ]
+        .concat();
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200)
+                .header("content-type", "text/event-stream")
+                .body(sse_body);
+        });
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
+
+        let events: Vec<StreamEvent> = provider
+            .chat_stream_events(req)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(events.len(), 2, "{events:?}");
+        let StreamEvent::ToolCall(call) = &events[0] else {
+            panic!("expected a tool call, got {:?}", events[0]);
+        };
+        assert_eq!((call.id.as_str(), call.name.as_str()), ("call_1", "lookup"));
+        assert_eq!(call.arguments, json!({"q": "x"}));
+        assert!(matches!(
+            events[1],
+            StreamEvent::Stop {
+                reason: Some(StopReason::ToolUse)
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn a_slow_consumer_still_gets_every_delta() {
+        use futures_util::StreamExt;
+        let server = MockServer::start();
+        let mut sse_body = r#"data: {"choices":[{"delta":{"content":"x"}}]}"#.to_string() + "\n\n";
+        sse_body = sse_body.repeat(3_000) + "data: [DONE]\n\n";
+        server.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200)
+                .header("content-type", "text/event-stream")
+                .body(sse_body);
+        });
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
+
+        let events = provider.chat_stream_events(req).await.unwrap();
+        // Let the bridge run ahead until the channel is full.
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        let events: Vec<StreamEvent> = events.collect().await;
+        let deltas = events.iter().filter(|e| matches!(e, StreamEvent::DeltaText(_))).count();
+        assert_eq!(deltas, 3_000);
+        assert!(matches!(events.last(), Some(StreamEvent::Stop { .. })));
+    }
+
+    #[tokio::test]
+    async fn streams_request_usage_and_emit_it_before_the_stop() {
+        use futures_util::StreamExt;
+        let server = MockServer::start();
+        let chunk = |json: &str| format!("data: {json}\n\n");
+        let sse_body = [
+            chunk(r#"{"choices":[{"delta":{"content":"Hi"}}]}"#),
+            chunk(r#"{"choices":[{"delta":{},"finish_reason":"stop"}]}"#),
+            chunk(r#"{"choices":[],"usage":{"prompt_tokens":9,"completion_tokens":1}}"#),
+            "data: [DONE]\n\n".to_string(),
+        ]
+        .concat();
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .json_body_partial(r#"{"stream":true,"stream_options":{"include_usage":true}}"#);
+            then.status(200)
+                .header("content-type", "text/event-stream")
+                .body(sse_body);
+        });
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
+
+        let events: Vec<StreamEvent> = provider
+            .chat_stream_events(req)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        m.assert();
+        assert_eq!(events.len(), 3, "{events:?}");
+        assert!(matches!(
+            events[1],
+            StreamEvent::Usage {
+                prompt: Some(9),
+                completion: Some(1)
+            }
+        ));
+        assert!(matches!(
+            events[2],
+            StreamEvent::Stop {
+                reason: Some(StopReason::Stop)
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn chat_maps_message_refusal() {
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
+
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200).json_body(json!({
+                "id": "cmpl_1",
+                "choices": [{
+                    "message": {"role": "assistant", "content": null, "refusal": "No."},
+                    "finish_reason": "stop"
+                }]
+            }));
+        });
+
+        let req = ChatRequest::builder().model("gpt-4o").user("hi").build();
+        let resp = provider.chat(req).await.expect("chat ok");
+        assert_eq!(resp.stop_reason, Some(StopReason::Refusal));
+        assert_eq!(resp.refusal.as_deref(), Some("No."));
+        assert_eq!(resp.text, "");
+    }
 
-impl ProviderCaps for OpenAI {
-    fn capabilities(&self) -> &'static [Capability] {
-        &[Capability::Chat, Capability::ChatStream, Capability::Embed]
+    #[tokio::test]
+    async fn chat_maps_url_citation_annotations() {
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
+
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200).json_body(json!({
+                "id": "cmpl_1",
+                "choices": [{
+                    "message": {
+                        "role": "assistant",
+                        "content": "Rust 1.0 shipped in 2015.",
+                        "annotations": [{
+                            "type": "url_citation",
+                            "url_citation": {
+                                "url": "https://blog.rust-lang.org/",
+                                "title": "Rust Blog",
+                                "start_index": 0,
+                                "end_index": 24
+                            }
+                        }]
+                    },
+                    "finish_reason": "stop"
+                }]
+            }));
+        });
+
+        let req = ChatRequest::builder().model("gpt-4o").user("hi").build();
+        let resp = provider.chat(req).await.expect("chat ok");
+        assert_eq!(resp.annotations.len(), 1);
+        let c = &resp.annotations[0];
+        assert_eq!(c.url.as_deref(), Some("https://blog.rust-lang.org/"));
+        assert_eq!(c.title.as_deref(), Some("Rust Blog"));
+        assert_eq!((c.start_index, c.end_index), (Some(0), Some(24)));
+        assert_eq!(resp.choices[0].annotations, resp.annotations);
     }
-}
 
-#[tokio::test]
-async fn embed_posts_model_and_input_shape() {
-    use httpmock::prelude::*;
+    #[tokio::test]
+    async fn chat_merges_permitted_extra_params() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .body_contains(r#""seed":7"#);
+            then.status(200).json_body(json!({
+                "id": "cmpl_1",
+                "choices": [{"message": {"role":"assistant", "content":"ok"}}]
+            }));
+        });
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("hi")
+            .extra("seed", json!(7))
+            .build();
 
-    let server = MockServer::start();
-    let provider = OpenAI::new_for_tests(&server.base_url());
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        provider.chat(req.clone()).await.expect("chat ok");
+        m.assert();
 
-    let m = server.mock(|when, then| {
-        when.method(POST)
-            .path("/v1/embeddings")
-            .body_contains("\"model\":\"text-embedding-3-small\"")
-            .body_contains("\"input\"");
-        then.status(200)
-            .header("content-type", "application/json")
-            .body(r#"{ "data": [ { "embedding": [0.1, 0.2] } ] }"#);
-    });
+        let denied = OpenAI::new_for_tests(&server.base_url()).with_extra_params(ExtraParamsCfg {
+            allow: vec![],
+            deny: vec!["seed".into()],
+        });
+        let err = denied.chat(req).await.unwrap_err();
+        assert!(matches!(err, crate::error::AiProxyError::Validation(_)));
+        m.assert_hits(1);
+    }
 
-    let req = EmbedRequest {
-        model: "text-embedding-3-small".into(),
-        inputs: vec!["hello".into()],
-        client_key: None,
-    };
-    let _ = provider.embed(req).await.expect("embed ok");
+    #[tokio::test]
+    async fn chat_sends_participant_names() {
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
 
-    m.assert();
-}
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .body_contains(r#"{"role":"user","content":"hi","name":"alice"}"#);
+            then.status(200).json_body(json!({
+                "id": "cmpl_1",
+                "choices": [{"message": {"role":"assistant", "content":"hello"}}]
+            }));
+        });
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use httpmock::Method::POST;
-    use httpmock::MockServer;
-    use serde_json::json;
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .message(ChatMessage::user("hi").with_name("alice"))
+            .build();
 
-    use crate::model::{ChatMessage, Role};
+        provider.chat(req).await.expect("chat ok");
+        m.assert();
+    }
 
     #[tokio::test]
-    async fn chat_200_maps_fields() {
+    async fn chat_forwards_user_and_metadata_only_when_enabled() {
         let server = MockServer::start();
+        let forwarded = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .body_contains(r#""user":"***abcd""#)
+                .body_contains(r#""metadata":{"team":"search"}"#);
+            then.status(200).json_body(json!({
+                "id": "cmpl_1",
+                "choices": [{"message": {"role":"assistant", "content":"ok"}}]
+            }));
+        });
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("hi")
+            .client_key("client-abcd")
+            .metadata(json!({"team": "search"}))
+            .build();
+
+        let provider = OpenAI::new_for_tests(&server.base_url()).with_forward_metadata(true);
+        provider.chat(req.clone()).await.expect("chat ok");
+        forwarded.assert();
+
+        // Off by default: the same request must not match the forwarding mock.
         let provider = OpenAI::new_for_tests(&server.base_url());
+        assert!(provider.chat(req).await.is_err());
+        forwarded.assert_hits(1);
+    }
 
-        let _m = server.mock(|when, then| {
+    #[tokio::test]
+    async fn chat_maps_every_choice() {
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .body_contains(r#""n":2,"logprobs":true"#);
+            then.status(200).json_body(json!({
+                "id": "cmpl_1",
+                "choices": [
+                    {
+                        "index": 0,
+                        "message": {"role": "assistant", "content": "first"},
+                        "finish_reason": "stop",
+                        "logprobs": {"content": [
+                            {"token": "first", "logprob": -0.5, "bytes": [102],
+                             "top_logprobs": [{"token": "first", "logprob": -0.5}]}
+                        ]}
+                    },
+                    {
+                        "index": 1,
+                        "message": {"role": "assistant", "content": null, "tool_calls": [
+                            {"id": "call_1", "type": "function",
+                             "function": {"name": "lookup", "arguments": "{\"q\":\"x\"}"}}
+                        ]},
+                        "finish_reason": "tool_calls"
+                    }
+                ]
+            }));
+        });
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("hi")
+            .n(2)
+            .logprobs(true)
+            .build();
+
+        let resp = provider.chat(req).await.unwrap();
+        m.assert();
+        assert_eq!(resp.choices.len(), 2);
+        assert_eq!(resp.text, "first");
+        assert_eq!(resp.stop_reason, Some(StopReason::Stop));
+        let lp = resp.choices[0].logprobs.as_ref().unwrap();
+        assert_eq!(lp[0].token, "first");
+        assert_eq!(lp[0].top_logprobs.len(), 1);
+        let second = &resp.choices[1];
+        assert_eq!(second.index, 1);
+        assert_eq!(second.text, "");
+        assert_eq!(second.stop_reason, Some(StopReason::ToolUse));
+        assert_eq!(second.tool_calls[0].name, "lookup");
+        assert_eq!(second.tool_calls[0].arguments["q"], "x");
+    }
+
+    #[tokio::test]
+    async fn chat_include_raw_attaches_provider_json() {
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        server.mock(|when, then| {
             when.method(POST).path("/v1/chat/completions");
             then.status(200).json_body(json!({
-                "id": "cmpl_123",
-                "choices": [{
-                    "message": {"role":"assistant", "content":"Hello!"},
-                    "finish_reason": "stop"
-                }],
-                "usage": {"prompt_tokens": 10, "completion_tokens": 5}
+                "id": "cmpl_1",
+                "system_fingerprint": "fp_abc",
+                "choices": [{"message": {"role":"assistant", "content":"ok"}}]
             }));
         });
+        let req = ChatRequest::builder().model("gpt-4o").user("hi");
 
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: Some(1.0),
-            top_p: Some(1.0),
-            metadata: None,
-            client_key: None,
-            request_id: Some("turn-1".into()),
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: Some(128),
-            stop_sequences: None,
-        };
+        let plain = provider.chat(req.clone().build()).await.unwrap();
+        assert_eq!(plain.raw, None);
+        let resp = provider.chat(req.include_raw(true).build()).await.unwrap();
+        assert_eq!(resp.raw.unwrap()["system_fingerprint"], "fp_abc");
+    }
 
-        let resp = provider.chat(req).await.expect("chat ok");
-        assert_eq!(resp.text, "Hello!");
-        assert_eq!(resp.stop_reason, Some(StopReason::Stop));
-        assert_eq!(resp.usage_prompt, 10);
-        assert_eq!(resp.usage_completion, 5);
-        assert_eq!(resp.provider, "openai");
-        assert_eq!(resp.provider_request_id, Some("cmpl_123".into()));
+    #[test]
+    fn usage_details_are_captured() {
+        let u: OAUsage = serde_json::from_value(json!({
+            "prompt_tokens": 100,
+            "completion_tokens": 40,
+            "prompt_tokens_details": {"cached_tokens": 64, "audio_tokens": 0},
+            "completion_tokens_details": {"reasoning_tokens": 32, "audio_tokens": 5}
+        }))
+        .unwrap();
+        assert_eq!(
+            Usage::from(u),
+            Usage {
+                cached_prompt: Some(64),
+                reasoning: Some(32),
+                audio_prompt: Some(0),
+                audio_completion: Some(5),
+                ..Usage::new(100, 40)
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn list_models_maps_ids() {
+        let server = MockServer::start();
+        let provider = OpenAI::new_for_tests(&server.base_url());
+
+        let m = server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/v1/models");
+            then.status(200).json_body(json!({
+                "object": "list",
+                "data": [
+                    {"id": "gpt-4o", "object": "model", "owned_by": "openai"},
+                    {"id": "text-embedding-3-small", "object": "model"}
+                ]
+            }));
+        });
+
+        let models = provider.list_models().await.expect("models ok");
+        m.assert();
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].id, "gpt-4o");
+        assert_eq!(models[0].provider, "openai");
+        assert_eq!(models[0].owned_by.as_deref(), Some("openai"));
+        assert_eq!(models[1].owned_by, None);
     }
 
     #[tokio::test]
@@ -513,19 +1481,24 @@ mod tests {
                 "data": [
                     {"embedding": [0.1, 0.2]},
                     {"embedding": [0.3, 0.4]}
-                ]
+                ],
+                "usage": {"prompt_tokens": 4, "total_tokens": 4}
             }));
         });
 
-        let req = EmbedRequest {
-            model: "text-embedding-3-small".into(),
-            inputs: vec!["hello".into(), "world".into()],
-            client_key: None,
-        };
+        let req = EmbedRequest::builder()
+            .model("text-embedding-3-small")
+            .inputs(["hello", "world"])
+            .build();
         let resp = provider.embed(req).await.expect("embed ok");
         assert_eq!(resp.vectors.len(), 2);
         assert_eq!(resp.vectors[0].len(), 2);
         assert_eq!(resp.provider, "openai");
+        assert_eq!(resp.usage, 4);
+        assert_eq!(
+            resp.cost_usd,
+            pricing::cost_usd("openai", "text-embedding-3-small", 4, 0)
+        );
     }
 
     #[tokio::test]
@@ -538,22 +1511,7 @@ mod tests {
             then.status(429).body("limit");
         });
 
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
 
         let err = provider.chat(req).await.unwrap_err();
         matches!(err, crate::error::AiProxyError::RateLimited { .. });
@@ -575,22 +1533,7 @@ mod tests {
                     }]
                 }));
             });
-            let req = ChatRequest {
-                model: "gpt-4o".into(),
-                messages: vec![ChatMessage {
-                    role: Role::User,
-                    content: "Hi".into(),
-                }],
-                temperature: None,
-                top_p: None,
-                metadata: None,
-                client_key: None,
-                request_id: None,
-                trace_id: None,
-                idempotency_key: None,
-                max_output_tokens: None,
-                stop_sequences: None,
-            };
+            let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
             let resp = provider.chat(req).await.expect("chat ok");
             assert_eq!(resp.stop_reason, Some(expected));
         }
@@ -617,22 +1560,7 @@ mod tests {
             }));
         });
 
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
         let resp = provider.chat(req).await.expect("chat ok");
         assert_eq!(resp.text, "");
         assert_eq!(resp.stop_reason, None);
@@ -655,26 +1583,10 @@ mod tests {
             }));
         });
 
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
 
         let resp = provider.chat(req).await.expect("chat ok");
-        assert_eq!(resp.usage_prompt, 0);
-        assert_eq!(resp.usage_completion, 0);
+        assert_eq!(resp.usage, Usage::default());
     }
 
     use crate::error::AiProxyError;
@@ -687,30 +1599,18 @@ mod tests {
             when.method(POST).path("/v1/chat/completions");
             then.status(429).body("limit");
         });
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
         let err = provider.chat(req).await.unwrap_err();
         match err {
             AiProxyError::RateLimited {
                 provider,
                 retry_after,
+                upstream,
+                ..
             } => {
                 assert_eq!(provider, "http");
                 assert_eq!(retry_after, None);
+                assert_eq!(upstream.model.as_deref(), Some("gpt-4o"));
             }
             other => panic!("expected RateLimited, got: {:?}", other),
         }
@@ -724,22 +1624,7 @@ mod tests {
             when.method(POST).path("/v1/chat/completions");
             then.status(429).header("Retry-After", "2").body("limit");
         });
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
         let err = provider.chat(req).await.unwrap_err();
         match err {
             AiProxyError::RateLimited { retry_after, .. } => assert_eq!(retry_after, Some(2)),
@@ -755,22 +1640,7 @@ mod tests {
             when.method(POST).path("/v1/chat/completions");
             then.status(503).body("down");
         });
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
         let err = provider.chat(req).await.unwrap_err();
         assert!(matches!(err, AiProxyError::ProviderUnavailable { .. }));
     }
@@ -784,22 +1654,7 @@ mod tests {
             when.method(POST).path("/v1/chat/completions");
             then.status(400).body(big);
         });
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
         let err = provider.chat(req).await.unwrap_err();
         match err {
             AiProxyError::ProviderError { code, message, .. } => {
@@ -819,22 +1674,7 @@ mod tests {
             when.method(POST).path("/v1/chat/completions");
             then.status(200).body("not-json");
         });
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
         let err = provider.chat(req).await.unwrap_err();
         match err {
             AiProxyError::ProviderError { code, message, .. } => {
@@ -849,19 +1689,7 @@ mod tests {
     async fn chat_network_error_maps_to_unavailable() {
         // Use a guaranteed-invalid domain per RFC 2606
         let provider = OpenAI::new_for_tests("http://nonexistent.invalid");
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage { role: Role::User, content: "Hi".into() }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
         let err = provider.chat(req).await.unwrap_err();
         assert!(matches!(err, crate::error::AiProxyError::ProviderUnavailable { .. }));
     }
@@ -887,19 +1715,7 @@ mod tests {
         });
 
         let provider = OpenAI::new_for_tests(&server.base_url());
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage { role: Role::User, content: "Hi".into() }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
 
         let deltas: Arc<Mutex<Vec<String>>> = Arc::new(Mutex::new(Vec::new()));
         let seen_stop: Arc<Mutex<Option<StopReason>>> = Arc::new(Mutex::new(None));
@@ -945,19 +1761,12 @@ mod tests {
         });
 
         let provider = OpenAI::new_for_tests(&server.base_url());
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage { role: Role::User, content: "Hi".into() }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: Some("rid-1".into()),
-            trace_id: Some("tid-1".into()),
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("Hi")
+            .request_id("rid-1")
+            .trace_id("tid-1")
+            .build();
 
         // Use non-streaming chat to ensure provider.call span is emitted
         let _ = provider.chat(req).await.expect("chat ok");
@@ -1079,7 +1888,7 @@ mod tests {
 
         // Build a stream that yields a line, then an error
         let s1 = stream::iter(vec![Ok(crate::http_client::SseLine { line: "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}".into() })]);
-        let s2 = stream::iter(vec![Err(AiProxyError::ProviderUnavailable { provider: "http".into() })]);
+        let s2 = stream::iter(vec![Err(AiProxyError::ProviderUnavailable { provider: "http".into(), upstream: Default::default() })]);
         let sse = s1.chain(s2);
 
         let mut saw_stop = false;
@@ -1093,6 +1902,205 @@ mod tests {
         assert!(res.is_err());
         assert!(!saw_stop, "on_stop should not be called on error path");
     }
+
+    #[tokio::test]
+    async fn chat_streaming_sse_malformed_chunk_is_a_typed_error() {
+        use futures_util::stream;
+
+        let lines = vec![
+            Ok(crate::http_client::SseLine { line: "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}".into() }),
+            Ok(crate::http_client::SseLine { line: "data: {\"choices\": [tru".into() }),
+        ];
+        let mut deltas = Vec::new();
+        let err = OpenAI::drive_openai_sse(stream::iter(lines), |txt| deltas.push(txt.to_string()), |_| {})
+            .await
+            .unwrap_err();
+        assert_eq!(deltas, vec!["Hi".to_string()]);
+        match err {
+            AiProxyError::MalformedChunk { message, .. } => assert!(message.contains("[tru")),
+            other => panic!("expected MalformedChunk, got: {:?}", other),
+        }
+    }
+
+    struct OpenAIFixture;
+
+    impl crate::providers::conformance::ChatFixture for OpenAIFixture {
+        type Provider = OpenAI;
+
+        fn provider(&self, base_url: &str) -> OpenAI {
+            OpenAI::new_for_tests(base_url)
+        }
+
+        fn chat_path(&self) -> &str {
+            "/v1/chat/completions"
+        }
+
+        fn stop_reasons(&self) -> Vec<(Option<&'static str>, Option<StopReason>)> {
+            vec![
+                (Some("stop"), Some(StopReason::Stop)),
+                (Some("length"), Some(StopReason::Length)),
+                (Some("content_filter"), Some(StopReason::ContentFilter)),
+                (Some("tool_calls"), Some(StopReason::ToolUse)),
+                (Some("weird_reason"), Some(StopReason::Other)),
+                (None, None),
+            ]
+        }
+
+        fn success_body(
+            &self,
+            text: &str,
+            stop: Option<&str>,
+            prompt: u32,
+            completion: u32,
+        ) -> serde_json::Value {
+            json!({
+                "id": "cmpl_conformance",
+                "choices": [{"message": {"role": "assistant", "content": text}, "finish_reason": stop}],
+                "usage": {"prompt_tokens": prompt, "completion_tokens": completion}
+            })
+        }
+
+        fn stream_body(&self, deltas: &[&str], stop: Option<&str>) -> Option<String> {
+            let mut body: String = deltas
+                .iter()
+                .map(|d| {
+                    format!(
+                        "data: {}\n\n",
+                        json!({"choices": [{"delta": {"content": d}}]})
+                    )
+                })
+                .collect();
+            body.push_str(&format!(
+                "data: {}\n\n",
+                json!({"choices": [{"delta": {}, "finish_reason": stop}]})
+            ));
+            body.push_str("data: [DONE]\n\n");
+            Some(body)
+        }
+    }
+
+    impl crate::providers::conformance::EmbedFixture for OpenAIFixture {
+        type Provider = OpenAI;
+
+        fn provider(&self, base_url: &str) -> OpenAI {
+            OpenAI::new_for_tests(base_url)
+        }
+
+        fn embed_path(&self) -> &str {
+            "/v1/embeddings"
+        }
+
+        fn success_body(&self, vectors: &[Vec<f32>], prompt: u32) -> serde_json::Value {
+            json!({
+                "data": vectors.iter().map(|v| json!({"embedding": v})).collect::<Vec<_>>(),
+                "usage": {"prompt_tokens": prompt, "total_tokens": prompt}
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_the_conformance_suite() {
+        crate::providers::conformance::check_chat(&OpenAIFixture).await;
+        crate::providers::conformance::check_embed(&OpenAIFixture).await;
+    }
+
+    #[tokio::test]
+    async fn requests_bill_to_allowlisted_projects_only() {
+        let server = MockServer::start();
+        let billed = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat/completions")
+                .header("OpenAI-Organization", "org-default")
+                .header("OpenAI-Project", "proj_b");
+            then.status(200).json_body(json!({
+                "id": "cmpl_x",
+                "choices": [{"message": {"role": "assistant", "content": "hi"}, "finish_reason": "stop"}]
+            }));
+        });
+        let provider = OpenAI::new(
+            HttpClient::new_default().unwrap(),
+            SecretString::new("test-key".into()),
+            server.base_url(),
+            Some("org-default".into()),
+            Some("proj_a".into()),
+        )
+        .with_billing_overrides(BillingOverridesCfg {
+            organizations: vec![],
+            projects: vec!["proj_b".into()],
+        });
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("Hi")
+            .project("proj_b")
+            .build();
+        provider.chat(req).await.expect("allowlisted project");
+        billed.assert();
+
+        for req in [
+            ChatRequest::builder()
+                .model("gpt-4o")
+                .user("Hi")
+                .project("proj_c")
+                .build(),
+            ChatRequest::builder()
+                .model("gpt-4o")
+                .user("Hi")
+                .organization("org-other")
+                .build(),
+        ] {
+            let Err(err) = provider.chat_stream_events(req).await else {
+                panic!("disallowed override was sent");
+            };
+            assert!(
+                matches!(err, AiProxyError::Validation(ref m) if m.contains("not allowed for openai")),
+                "{err}"
+            );
+        }
+        assert_eq!(billed.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn moderation_returns_flagged_categories() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/moderations")
+                .json_body_partial(r#"{"model": "omni-moderation-latest", "input": "text"}"#);
+            then.status(200).json_body(json!({
+                "results": [{
+                    "flagged": true,
+                    "categories": {"harassment": false, "violence": true, "hate": true}
+                }]
+            }));
+        });
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let flagged = provider.moderate("text").await.unwrap();
+        m.assert();
+        assert_eq!(flagged, ["hate", "violence"]);
+    }
+
+    #[tokio::test]
+    async fn response_schema_is_sent_as_response_format() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions").json_body_partial(
+                r#"{"response_format": {"type": "json_schema", "json_schema": {"name": "Answer", "schema": {"type": "object"}}}}"#,
+            );
+            then.status(200).json_body(json!({
+                "id": "cmpl_x",
+                "choices": [{"message": {"role": "assistant", "content": "{}"}, "finish_reason": "stop"}]
+            }));
+        });
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        assert!(provider.supports_response_schema());
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("Hi")
+            .response_schema("Answer", json!({"type": "object"}))
+            .build();
+        provider.chat(req).await.unwrap();
+        m.assert();
+    }
 }
 
 impl OpenAI {
@@ -1123,21 +2131,36 @@ impl OpenAI {
         );
         async move {
         // Build payload with stream=true (inside move so we can borrow req safely)
+        let (tools, tool_choice) = super::tool_params(&req, ToolDialect::OpenAi);
         let payload = OAChatReq {
             model: &req.model,
-            messages: &req.messages,
+            messages: super::openai_messages(&req.messages),
             temperature: req.temperature,
             top_p: req.top_p,
             max_tokens: req.max_output_tokens,
             stop: req.stop_sequences.clone(),
             stream: Some(true),
+            stream_options: None,
+            user: self.user(&req),
+            metadata: self.metadata(&req),
+            n: None,
+            logprobs: None,
+            response_format: super::response_format(req.response_schema.as_ref()),
+            tools,
+            tool_choice,
         };
+        let payload =
+            super::with_extra(&self.name, &payload, req.extra.as_ref(), &self.extra_params)?;
         let ctx = RequestCtx {
             request_id: req.request_id.as_deref(),
             turn_id: req.trace_id.as_deref(),
             idempotency_key: req.idempotency_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: None,
+            stream_timeouts: StreamTimeouts::of(&req),
         };
-        let owned_headers = self.headers(&ctx);
+        let owned_headers = self.chat_headers(&req)?;
         let hdrs: Vec<(&str, &str)> = owned_headers
             .iter()
             .map(|(k, v)| (k.as_str(), v.as_str()))
@@ -1185,9 +2208,11 @@ impl OpenAI {
         let clog = crate::telemetry::CompletionLog::new()
             .provider("openai")
             .model(&req.model)
+            .requested_model_opt(req.requested_model.as_deref())
             .request_id_opt(req.request_id.as_deref())
             .turn_id_opt(req.trace_id.as_deref())
             .provider_request_id_opt(provider_request_id.as_deref())
+            .client_key_opt(req.client_key.as_deref())
             .created_at_ms(Self::now_ms() as u64)
             .latency_ms(started.elapsed().as_millis() as u64)
             .stop_reason_opt(stop_lc)
@@ -1227,9 +2252,9 @@ impl OpenAI {
             if let Some(rest) = raw.strip_prefix("data:") {
                 let json = rest.trim_start();
                 if json.is_empty() { continue; }
-                if let Ok(chunk) = serde_json::from_str::<OAChatStreamChunk>(json)
-                    && let Some(choice) = chunk.choices.first()
-                {
+                let chunk = serde_json::from_str::<OAChatStreamChunk>(json)
+                    .map_err(|e| malformed_chunk("openai", json, e))?;
+                if let Some(choice) = chunk.choices.first() {
                     if let Some(ref txt) = choice.delta.content {
                         on_text_delta(txt);
                     }
@@ -1256,6 +2281,8 @@ fn stop_to_string(s: StopReason) -> String {
         StopReason::ToolUse => "ToolUse".into(),
         StopReason::EndTurn => "EndTurn".into(),
         StopReason::ContentFilter => "ContentFilter".into(),
+        StopReason::Cancelled => "Cancelled".into(),
+        StopReason::Refusal => "Refusal".into(),
         StopReason::Other => "Other".into(),
     }
 }
@@ -1267,6 +2294,8 @@ fn stop_to_code(s: StopReason) -> &'static str {
         StopReason::ToolUse => "tool_use",
         StopReason::EndTurn => "end_turn",
         StopReason::ContentFilter => "content_filter",
+        StopReason::Cancelled => "cancelled",
+        StopReason::Refusal => "refusal",
         StopReason::Other => "other",
     }
 }
@@ -1274,7 +2303,6 @@ fn stop_to_code(s: StopReason) -> &'static str {
 #[cfg(test)]
 mod completion_log_tests {
     use super::*;
-    use crate::model::{ChatMessage, Role};
     use crate::telemetry::set_telemetry_sink;
     use once_cell::sync::Lazy;
     use std::sync::{Arc, Mutex};
@@ -1293,7 +2321,7 @@ mod completion_log_tests {
     }
 
     fn ensure_cl_sink_installed() {
-        let _ = set_telemetry_sink(Arc::new(CLTestSink::default()));
+        let _ = set_telemetry_sink(Arc::new(CLTestSink));
     }
 
     #[tokio::test]
@@ -1317,19 +2345,12 @@ mod completion_log_tests {
                 }));
         });
         let provider = OpenAI::new_for_tests(&server.base_url());
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage { role: Role::User, content: "Hi".into() }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: Some("rid-2".into()),
-            trace_id: Some("tid-2".into()),
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("Hi")
+            .request_id("rid-2")
+            .trace_id("tid-2")
+            .build();
         let resp = provider.chat(req).await.expect("chat ok");
         assert_eq!(resp.text, "Hello NL!");
 
@@ -1348,6 +2369,32 @@ mod completion_log_tests {
         }
     }
 
+    #[tokio::test]
+    async fn completion_log_names_the_alias_asked_for() {
+        ensure_cl_sink_installed();
+        let server = httpmock::MockServer::start();
+        let _m = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/v1/chat/completions");
+            then.status(200)
+                .header("content-type", "application/json")
+                .json_body(serde_json::json!({
+                    "id": "cmpl_alias",
+                    "choices": [{"message": {"role":"assistant", "content":"ok"}, "finish_reason": "stop"}],
+                    "usage": {"prompt_tokens": 1, "completion_tokens": 1}
+                }));
+        });
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let mut req = ChatRequest::builder().model("gpt-4o-mini").user("Hi").request_id("rid-alias").build();
+        req.requested_model = Some("fast".into());
+        provider.chat(req).await.expect("chat ok");
+
+        let logs = COMPLETION_LOGS.lock().unwrap().clone();
+        if let Some(log) = logs.iter().find(|l| l.request_id.as_deref() == Some("rid-alias")) {
+            assert_eq!(log.model.as_deref(), Some("gpt-4o-mini"));
+            assert_eq!(log.requested_model.as_deref(), Some("fast"));
+        }
+    }
+
     #[tokio::test]
     async fn completion_log_streaming_emitted() {
         ensure_cl_sink_installed();
@@ -1368,19 +2415,12 @@ mod completion_log_tests {
                 .body(sse_body);
         });
         let provider = OpenAI::new_for_tests(&server.base_url());
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage { role: Role::User, content: "Hi".into() }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: Some("rid-3".into()),
-            trace_id: Some("tid-3".into()),
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder()
+            .model("gpt-4o")
+            .user("Hi")
+            .request_id("rid-3")
+            .trace_id("tid-3")
+            .build();
 
         // Use the high-level streaming helper to exercise accumulation + emit
         let mut acc = String::new();
@@ -1405,4 +2445,35 @@ mod completion_log_tests {
             assert_eq!(log.text.as_deref(), Some("Hello"));
         }
     }
+
+    #[tokio::test]
+    async fn completion_log_embed_carries_prompt_tokens() {
+        ensure_cl_sink_installed();
+
+        let server = httpmock::MockServer::start();
+        let _m = server.mock(|when, then| {
+            when.method(httpmock::Method::POST).path("/v1/embeddings");
+            then.status(200).json_body(serde_json::json!({
+                "data": [{"embedding": [0.5]}],
+                "usage": {"prompt_tokens": 6, "total_tokens": 6}
+            }));
+        });
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let req = EmbedRequest::builder()
+            .model("text-embedding-3-large")
+            .input("count me")
+            .build();
+        assert_eq!(provider.embed(req).await.expect("embed ok").usage, 6);
+
+        let logs = COMPLETION_LOGS.lock().unwrap().clone();
+        if let Some(log) = logs
+            .iter()
+            .find(|l| l.model.as_deref() == Some("text-embedding-3-large"))
+        {
+            assert_eq!(log.provider.as_deref(), Some("openai"));
+            assert_eq!(log.tokens_prompt, Some(6));
+            assert_eq!(log.tokens_completion, Some(0));
+            assert_eq!(log.tokens_total, Some(6));
+        }
+    }
 }
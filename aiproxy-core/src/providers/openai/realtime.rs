@@ -0,0 +1,252 @@
+//! OpenAI Realtime sessions over a WebSocket to `/v1/realtime`.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::sync::Mutex;
+
+use super::OpenAI;
+use crate::error::{AiProxyError, CoreResult, Upstream};
+use crate::realtime::ws::{self, ClientReader, ClientWriter, Message};
+use crate::realtime::{
+    ClientEvent, EventSink, RealtimeProvider, RealtimeSession, ServerEvent, SessionConfig,
+};
+
+/// The connection dropped or broke the protocol mid-session.
+fn dropped(provider: &str, model: &str, e: std::io::Error) -> AiProxyError {
+    AiProxyError::ProviderError {
+        provider: provider.to_string(),
+        code: "connection".into(),
+        message: format!("realtime connection failed: {e}"),
+        upstream: Box::new(Upstream {
+            model: Some(model.to_string()),
+            ..Upstream::default()
+        }),
+    }
+}
+
+/// Sending half; shared with the reader, which answers pings and close frames.
+struct Sink {
+    provider: String,
+    model: String,
+    writer: Arc<Mutex<ClientWriter>>,
+}
+
+#[async_trait]
+impl EventSink for Sink {
+    async fn send(&mut self, event: ClientEvent) -> CoreResult<()> {
+        let msg = Message::Text(event.0.to_string());
+        self.writer
+            .lock()
+            .await
+            .send(&msg)
+            .await
+            .map_err(|e| dropped(&self.provider, &self.model, e))
+    }
+
+    async fn close(&mut self) -> CoreResult<()> {
+        let msg = Message::Close(Some((1000, String::new())));
+        self.writer
+            .lock()
+            .await
+            .send(&msg)
+            .await
+            .map_err(|e| dropped(&self.provider, &self.model, e))
+    }
+}
+
+struct Reader {
+    provider: String,
+    model: String,
+    reader: ClientReader,
+    writer: Arc<Mutex<ClientWriter>>,
+    done: bool,
+}
+
+impl Reader {
+    async fn next(&mut self) -> Option<CoreResult<ServerEvent>> {
+        while !self.done {
+            let msg = match self.reader.recv().await {
+                Ok(msg) => msg,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(dropped(&self.provider, &self.model, e)));
+                }
+            };
+            match msg {
+                Some(Message::Text(text)) => {
+                    return Some(ServerEvent::parse(&self.provider, &text));
+                }
+                Some(Message::Ping(data)) => {
+                    // A failed pong surfaces on the next read or send.
+                    let _ = self.writer.lock().await.send(&Message::Pong(data)).await;
+                }
+                Some(Message::Binary(_) | Message::Pong(_)) => {}
+                Some(Message::Close(_)) => {
+                    let _ = self.writer.lock().await.send(&Message::Close(None)).await;
+                    self.done = true;
+                }
+                None => self.done = true,
+            }
+        }
+        None
+    }
+}
+
+#[async_trait]
+impl RealtimeProvider for OpenAI {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn connect(&self, cfg: &SessionConfig) -> CoreResult<RealtimeSession> {
+        let url = format!("{}/v1/realtime?model={}", self.base, cfg.model);
+        let mut headers = self.billed_headers(self.org.as_deref(), self.project.as_deref());
+        headers.push(("OpenAI-Beta".into(), "realtime=v1".into()));
+        if let Some(rid) = &cfg.request_id {
+            headers.push(("X-Request-Id".into(), rid.clone()));
+        }
+        let (reader, writer) = ws::connect(&self.name, &url, &headers, Some(&cfg.model)).await?;
+        let writer = Arc::new(Mutex::new(writer));
+        let sink = Sink {
+            provider: self.name.clone(),
+            model: cfg.model.clone(),
+            writer: writer.clone(),
+        };
+        let reader = Reader {
+            provider: self.name.clone(),
+            model: cfg.model.clone(),
+            reader,
+            writer,
+            done: false,
+        };
+        let events = futures::stream::unfold(reader, |mut reader| async move {
+            let event = reader.next().await?;
+            Some((event, reader))
+        })
+        .boxed();
+        RealtimeSession::start(&self.name, cfg, Box::new(sink), events).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::realtime::ws::{Role, accept_key};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Accepts one WebSocket, pings the client, then answers `response.create` with a finished
+    /// response and closes. Returns the handshake (lowercased) and the event types received.
+    async fn fake_realtime() -> (String, tokio::task::JoinHandle<(String, Vec<String>)>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(tcp.read_u8().await.unwrap());
+            }
+            let head = String::from_utf8(head).unwrap();
+            let key = head
+                .lines()
+                .filter_map(|l| l.split_once(": "))
+                .find(|(name, _)| name.eq_ignore_ascii_case("sec-websocket-key"))
+                .map(|(_, key)| key.to_string())
+                .unwrap();
+            let reply = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+                accept_key(&key)
+            );
+            tcp.write_all(reply.as_bytes()).await.unwrap();
+            let (mut rx, mut tx) = ws::split(tcp, Role::Server);
+            tx.send(&Message::Text(r#"{"type":"session.created"}"#.into()))
+                .await
+                .unwrap();
+            tx.send(&Message::Ping(b"hb".to_vec())).await.unwrap();
+            let mut kinds = Vec::new();
+            while let Some(msg) = rx.recv().await.unwrap() {
+                let text = match msg {
+                    Message::Text(text) => text,
+                    Message::Pong(data) => {
+                        assert_eq!(data, b"hb");
+                        continue;
+                    }
+                    Message::Close(_) => break,
+                    other => panic!("unexpected {other:?}"),
+                };
+                let event: serde_json::Value = serde_json::from_str(&text).unwrap();
+                kinds.push(event["type"].as_str().unwrap().to_string());
+                if event["type"] == "response.create" {
+                    let done = serde_json::json!({
+                        "type": "response.done",
+                        "response": {
+                            "status": "completed",
+                            "usage": {"input_tokens": 7, "output_tokens": 3}
+                        }
+                    });
+                    tx.send(&Message::Text(done.to_string())).await.unwrap();
+                    tx.send(&Message::Close(Some((1000, String::new()))))
+                        .await
+                        .unwrap();
+                }
+            }
+            (head.to_ascii_lowercase(), kinds)
+        });
+        (base, server)
+    }
+
+    #[tokio::test]
+    async fn session_runs_over_a_websocket() {
+        let (base, server) = fake_realtime().await;
+        let provider = OpenAI::new_for_tests(&base);
+        let mut cfg = SessionConfig::new("gpt-4o-realtime-preview");
+        cfg.instructions = Some("Be brief.".into());
+        let RealtimeSession {
+            mut sender,
+            mut events,
+        } = provider.connect(&cfg).await.unwrap();
+
+        let created = events.next().await.unwrap().unwrap();
+        assert_eq!(created.kind(), "session.created");
+        sender.send(ClientEvent::user_text("hi")).await.unwrap();
+        sender.send(ClientEvent::create_response()).await.unwrap();
+        let done = events.next().await.unwrap().unwrap();
+        assert_eq!(done.usage().unwrap().total(), 10);
+        assert!(events.next().await.is_none(), "server closed the session");
+        assert_eq!(events.usage().prompt, 7);
+
+        let (head, kinds) = server.await.unwrap();
+        assert_eq!(
+            kinds,
+            [
+                "session.update",
+                "conversation.item.create",
+                "response.create"
+            ]
+        );
+        assert!(head.starts_with("get /v1/realtime?model=gpt-4o-realtime-preview http/1.1"));
+        assert!(head.contains("authorization: bearer test-key"));
+        assert!(head.contains("openai-beta: realtime=v1"));
+    }
+
+    #[tokio::test]
+    async fn refused_handshake_maps_like_an_http_error() {
+        let server = httpmock::MockServer::start();
+        server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/v1/realtime");
+            then.status(401).body("invalid api key");
+        });
+        let provider = OpenAI::new_for_tests(&server.base_url());
+        let err = provider
+            .connect(&SessionConfig::new("gpt-4o-realtime-preview"))
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            matches!(&err, AiProxyError::ProviderError { code, message, .. } if code == "401" && message == "invalid api key"),
+            "{err}"
+        );
+    }
+}
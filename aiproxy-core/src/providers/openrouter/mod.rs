@@ -1,14 +1,18 @@
 use async_trait::async_trait;
 use secrecy::{ExposeSecret, SecretString};
 use serde::{Deserialize, Serialize};
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::borrow::Cow;
 
+use crate::config::ExtraParamsCfg;
+use crate::platform::{SystemTime, UNIX_EPOCH};
+use crate::pricing;
 use crate::error::CoreResult;
-use crate::http_client::{HttpClient, RequestCtx};
+use crate::http_client::{HttpClient, RequestCtx, RetryLog, StreamTimeouts};
 use crate::model::{
-    ChatMessage, ChatRequest, ChatResponse, EmbedRequest, EmbedResponse, StopReason,
+    ChatChoice, ChatMessage, ChatRequest, ChatResponse, Citation, EmbedRequest, EmbedResponse,
+    MessageContent, ModelInfo, StopReason, TokenLogprob, ToolCall, ToolDialect, Usage,
 };
-use crate::provider::{Capability, ChatProvider, EmbedProvider, ProviderCaps};
+use crate::provider::{Capability, ChatProvider, EmbedProvider, ModelsProvider, ProviderCaps};
 
 #[derive(Debug, Clone)]
 pub struct OpenRouter {
@@ -16,6 +20,8 @@ pub struct OpenRouter {
     base: String,
     name: String, // "openrouter"
     api_key: SecretString,
+    forward_metadata: bool,
+    extra_params: ExtraParamsCfg,
 }
 
 impl OpenRouter {
@@ -25,9 +31,23 @@ impl OpenRouter {
             api_key,
             base,
             name: "openrouter".into(),
+            forward_metadata: false,
+            extra_params: ExtraParamsCfg::default(),
         }
     }
 
+    /// Send a redacted client-key label as `user` (see `PrivacyCfg`).
+    pub fn with_forward_metadata(mut self, on: bool) -> Self {
+        self.forward_metadata = on;
+        self
+    }
+
+    /// Limit which `ChatRequest.extra` parameters reach the provider.
+    pub fn with_extra_params(mut self, cfg: ExtraParamsCfg) -> Self {
+        self.extra_params = cfg;
+        self
+    }
+
     #[cfg(test)]
     pub fn new_for_tests(server_base: &str) -> Self {
         OpenRouter::new(
@@ -59,7 +79,7 @@ impl OpenRouter {
 #[derive(Serialize)]
 struct ORChatReq<'a> {
     model: &'a str,
-    messages: &'a [ChatMessage],
+    messages: Cow<'a, [ChatMessage]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -68,23 +88,146 @@ struct ORChatReq<'a> {
     max_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response_format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tools: Option<Vec<serde_json::Value>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_choice: Option<serde_json::Value>,
 }
 #[derive(Deserialize)]
 struct ORChatResp {
     id: String,
     choices: Vec<ORChoice>,
     usage: Option<ORUsage>,
+    /// Perplexity models list their sources here rather than as message annotations.
+    #[serde(default)]
+    citations: Vec<String>,
 }
 #[derive(Deserialize)]
 struct ORChoice {
-    message: ChatMessage,
+    #[serde(default)]
+    index: Option<u32>,
+    message: ORMessage,
     #[serde(default)]
     finish_reason: Option<String>,
+    #[serde(default)]
+    logprobs: Option<ORLogprobs>,
+}
+#[derive(Deserialize)]
+struct ORMessage {
+    #[serde(default)]
+    content: Option<MessageContent>,
+    #[serde(default)]
+    tool_calls: Vec<ORToolCall>,
+    #[serde(default)]
+    refusal: Option<String>,
+    #[serde(default)]
+    annotations: Vec<ORAnnotation>,
+}
+/// `message.annotations` entry; only `url_citation` annotations name a source.
+#[derive(Deserialize)]
+struct ORAnnotation {
+    #[serde(default)]
+    url_citation: Option<ORUrlCitation>,
+}
+#[derive(Deserialize)]
+struct ORUrlCitation {
+    url: String,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    start_index: Option<u32>,
+    #[serde(default)]
+    end_index: Option<u32>,
+}
+impl From<ORUrlCitation> for Citation {
+    fn from(c: ORUrlCitation) -> Self {
+        Citation {
+            url: Some(c.url),
+            title: c.title,
+            start_index: c.start_index,
+            end_index: c.end_index,
+            cited_text: None,
+        }
+    }
+}
+#[derive(Deserialize)]
+struct ORToolCall {
+    id: String,
+    function: ORFunctionCall,
+}
+#[derive(Deserialize)]
+struct ORFunctionCall {
+    name: String,
+    #[serde(default)]
+    arguments: String,
+}
+#[derive(Deserialize)]
+struct ORLogprobs {
+    #[serde(default)]
+    content: Option<Vec<TokenLogprob>>,
+}
+impl ORChoice {
+    fn into_choice(self, position: usize) -> ChatChoice {
+        ChatChoice {
+            index: self.index.unwrap_or(position as u32),
+            text: self
+                .message
+                .content
+                .map(|c| c.text().into_owned())
+                .unwrap_or_default(),
+            stop_reason: match self.message.refusal {
+                Some(_) => Some(StopReason::Refusal),
+                None => map_finish(self.finish_reason.as_deref()),
+            },
+            tool_calls: self
+                .message
+                .tool_calls
+                .into_iter()
+                .map(|t| ToolCall::from_json_text(t.id, t.function.name, &t.function.arguments))
+                .collect(),
+            logprobs: self.logprobs.and_then(|l| l.content),
+            refusal: self.message.refusal,
+            annotations: self
+                .message
+                .annotations
+                .into_iter()
+                .filter_map(|a| a.url_citation.map(Citation::from))
+                .collect(),
+        }
+    }
 }
 #[derive(Deserialize)]
 struct ORUsage {
     prompt_tokens: u32,
     completion_tokens: u32,
+    #[serde(default)]
+    prompt_tokens_details: Option<ORTokenDetails>,
+    #[serde(default)]
+    completion_tokens_details: Option<ORTokenDetails>,
+}
+#[derive(Deserialize)]
+struct ORTokenDetails {
+    #[serde(default)]
+    cached_tokens: Option<u32>,
+    #[serde(default)]
+    reasoning_tokens: Option<u32>,
+}
+impl From<ORUsage> for Usage {
+    fn from(u: ORUsage) -> Self {
+        Usage {
+            cached_prompt: u.prompt_tokens_details.and_then(|d| d.cached_tokens),
+            reasoning: u.completion_tokens_details.and_then(|d| d.reasoning_tokens),
+            ..Usage::new(u.prompt_tokens, u.completion_tokens)
+        }
+    }
 }
 
 fn map_finish(s: Option<&str>) -> Option<StopReason> {
@@ -98,25 +241,47 @@ fn map_finish(s: Option<&str>) -> Option<StopReason> {
     }
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl ChatProvider for OpenRouter {
     fn name(&self) -> &str {
         &self.name
     }
 
+    fn supports_response_schema(&self) -> bool {
+        true
+    }
+
     async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let (tools, tool_choice) = super::tool_params(&req, ToolDialect::OpenAi);
         let payload = ORChatReq {
             model: &req.model,
-            messages: &req.messages,
+            messages: super::openai_messages(&req.messages),
             temperature: req.temperature,
             top_p: req.top_p,
             max_tokens: req.max_output_tokens,
             stop: req.stop_sequences.clone(),
+            user: self
+                .forward_metadata
+                .then(|| req.client_key.as_deref().map(crate::usage::key_label))
+                .flatten(),
+            n: req.n,
+            logprobs: req.logprobs.then_some(true),
+            response_format: super::response_format(req.response_schema.as_ref()),
+            tools,
+            tool_choice,
         };
+        let payload =
+            super::with_extra(&self.name, &payload, req.extra.as_ref(), &self.extra_params)?;
+        let retries = RetryLog::default();
         let ctx = RequestCtx {
             request_id: req.request_id.as_deref(),
             turn_id: req.trace_id.as_deref(),
             idempotency_key: req.idempotency_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: Some(&retries),
+            stream_timeouts: StreamTimeouts::default(),
         };
         let owned_headers = self.headers(&ctx);
         let hdrs: Vec<(&str, &str)> = owned_headers
@@ -124,30 +289,49 @@ impl ChatProvider for OpenRouter {
             .map(|(k, v)| (k.as_str(), v.as_str()))
             .collect();
         let url = format!("{}/v1/chat/completions", self.base);
-        let (resp, provider_id, latency_ms) = self
+        let (resp, raw, provider_id, latency_ms) = self
             .http
-            .post_json::<_, ORChatResp>(&url, &payload, &hdrs, &ctx)
+            .post_json_keep_raw::<_, ORChatResp>(&url, &payload, &hdrs, &ctx, req.include_raw)
             .await?;
 
-        let text = resp
+        let sources: Vec<Citation> = resp
+            .citations
+            .into_iter()
+            .map(|url| Citation {
+                url: Some(url),
+                ..Citation::default()
+            })
+            .collect();
+        let choices: Vec<ChatChoice> = resp
             .choices
+            .into_iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let mut choice = c.into_choice(i);
+                if choice.annotations.is_empty() {
+                    choice.annotations = sources.clone();
+                }
+                choice
+            })
+            .collect();
+        let text = choices.first().map(|c| c.text.clone()).unwrap_or_default();
+        let stop_reason = choices.first().and_then(|c| c.stop_reason);
+        let refusal = choices.first().and_then(|c| c.refusal.clone());
+        let annotations = choices
             .first()
-            .map(|c| c.message.content.clone())
+            .map(|c| c.annotations.clone())
             .unwrap_or_default();
-        let stop_reason = resp
-            .choices
+        let tool_calls = choices
             .first()
-            .and_then(|c| map_finish(c.finish_reason.as_deref()));
-        let (usage_p, usage_c) = resp
-            .usage
-            .map(|u| (u.prompt_tokens, u.completion_tokens))
-            .unwrap_or((0, 0));
+            .map(|c| c.tool_calls.clone())
+            .unwrap_or_default();
+        let usage = resp.usage.map(Usage::from).unwrap_or_default();
+        let cost_usd = pricing::cost_usd(&self.name, &req.model, usage.prompt, usage.completion);
 
         let resp_out = ChatResponse {
             model: req.model,
             text,
-            usage_prompt: usage_p,
-            usage_completion: usage_c,
+            usage,
             cached: false,
             provider: self.name.clone(),
             transcript_id: None,
@@ -156,34 +340,48 @@ impl ChatProvider for OpenRouter {
             provider_request_id: provider_id.or(Some(resp.id)),
             created_at_ms: Self::now_ms(),
             latency_ms,
+            raw,
+            choices,
+            tool_calls,
+            refusal,
+            annotations,
+            truncation: None,
+            cost_usd,
+            guardrail: vec![],
         };
         // Emit structured completion log (non-streaming)
-        let tokens_total = resp_out.usage_prompt.checked_add(resp_out.usage_completion);
+        let tokens_total = resp_out.usage.prompt.checked_add(resp_out.usage.completion);
         let stop_code = match resp_out.stop_reason {
             Some(crate::model::StopReason::Stop) => Some("stop"),
             Some(crate::model::StopReason::Length) => Some("length"),
             Some(crate::model::StopReason::ToolUse) => Some("tool_use"),
             Some(crate::model::StopReason::EndTurn) => Some("end_turn"),
             Some(crate::model::StopReason::ContentFilter) => Some("content_filter"),
+            Some(crate::model::StopReason::Cancelled) => Some("cancelled"),
+            Some(crate::model::StopReason::Refusal) => Some("refusal"),
             Some(crate::model::StopReason::Other) => Some("other"),
             None => None,
         };
         let clog = crate::telemetry::CompletionLog::new()
             .provider("openrouter")
             .model(&resp_out.model)
+            .requested_model_opt(req.requested_model.as_deref())
             .request_id_opt(None)
             .turn_id_opt(None)
             .provider_request_id_opt(resp_out.provider_request_id.as_deref())
+            .client_key_opt(req.client_key.as_deref())
             .created_at_ms(resp_out.created_at_ms as u64)
             .latency_ms(resp_out.latency_ms as u64)
             .stop_reason_opt(stop_code)
             .text_opt(Some(&resp_out.text))
+            .cost_usd_opt(resp_out.cost_usd)
             .tokens(
-                Some(resp_out.usage_prompt),
-                Some(resp_out.usage_completion),
+                Some(resp_out.usage.prompt),
+                Some(resp_out.usage.completion),
                 tokens_total,
             );
-        crate::telemetry::emit_completion(clog);
+        let (reasons, backoff_ms) = retries.take();
+        crate::telemetry::emit_completion(clog.retried(reasons, backoff_ms));
         Ok(resp_out)
     }
 }
@@ -196,13 +394,23 @@ struct OREmbedReq<'a> {
 #[derive(Deserialize)]
 struct OREmbedResp {
     data: Vec<ORVector>,
+    #[serde(default)]
+    usage: Option<OREmbedUsage>,
+}
+#[derive(Deserialize)]
+struct OREmbedUsage {
+    #[serde(default)]
+    prompt_tokens: u32,
+    #[serde(default)]
+    total_tokens: u32,
 }
 #[derive(Deserialize)]
 struct ORVector {
     embedding: Vec<f32>,
 }
 
-#[async_trait]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl EmbedProvider for OpenRouter {
     fn name(&self) -> &str {
         &self.name
@@ -213,10 +421,15 @@ impl EmbedProvider for OpenRouter {
             model: &req.model,
             input: &req.inputs,
         };
+        let retries = RetryLog::default();
         let ctx = RequestCtx {
             request_id: None,
             turn_id: None,
             idempotency_key: req.client_key.as_deref(),
+            model: Some(&req.model),
+            requested_model: req.requested_model.as_deref(),
+            retries: Some(&retries),
+            stream_timeouts: StreamTimeouts::default(),
         };
         let owned_headers = self.headers(&ctx);
         let hdrs: Vec<(&str, &str)> = owned_headers
@@ -224,31 +437,106 @@ impl EmbedProvider for OpenRouter {
             .map(|(k, v)| (k.as_str(), v.as_str()))
             .collect();
         let url = format!("{}/v1/embeddings", self.base);
-        let (resp, _provider_id, _lat) = self
+        let (resp, provider_id, latency_ms) = self
             .http
             .post_json::<_, OREmbedResp>(&url, &payload, &hdrs, &ctx)
             .await?;
         let vectors = resp.data.into_iter().map(|d| d.embedding).collect();
+        let (prompt_tokens, total_tokens) = resp.usage.map_or((0, 0), |u| {
+            (u.prompt_tokens, u.total_tokens.max(u.prompt_tokens))
+        });
+        let cost_usd = pricing::cost_usd(&self.name, &req.model, prompt_tokens, 0);
+        // Embeddings bill prompt tokens only; the log feeds metrics and the usage ledger.
+        let clog = crate::telemetry::CompletionLog::new()
+            .provider("openrouter")
+            .model(&req.model)
+            .requested_model_opt(req.requested_model.as_deref())
+            .provider_request_id_opt(provider_id.as_deref())
+            .client_key_opt(req.client_key.as_deref())
+            .created_at_ms(Self::now_ms() as u64)
+            .latency_ms(latency_ms as u64)
+            .cost_usd_opt(cost_usd)
+            .tokens(Some(prompt_tokens), Some(0), Some(total_tokens));
+        let (reasons, backoff_ms) = retries.take();
+        crate::telemetry::emit_completion(clog.retried(reasons, backoff_ms));
         Ok(EmbedResponse {
             model: req.model,
             vectors,
-            usage: 0,
+            usage: prompt_tokens,
             cached: false,
             provider: self.name.clone(),
+            cost_usd,
         })
     }
 }
 
+#[derive(Deserialize)]
+struct ORModelList {
+    data: Vec<ORModel>,
+}
+#[derive(Deserialize)]
+struct ORModel {
+    id: String,
+    #[serde(default)]
+    context_length: Option<u32>,
+    #[serde(default)]
+    top_provider: Option<ORTopProvider>,
+}
+#[derive(Deserialize)]
+struct ORTopProvider {
+    #[serde(default)]
+    max_completion_tokens: Option<u32>,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ModelsProvider for OpenRouter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn list_models(&self) -> CoreResult<Vec<ModelInfo>> {
+        let ctx = RequestCtx {
+            request_id: None,
+            turn_id: None,
+            idempotency_key: None,
+            model: None,
+            requested_model: None,
+            retries: None,
+            stream_timeouts: StreamTimeouts::default(),
+        };
+        let owned_headers = self.headers(&ctx);
+        let hdrs: Vec<(&str, &str)> = owned_headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect();
+        let url = format!("{}/v1/models", self.base);
+        let (resp, _provider_id, _lat) =
+            self.http.get_json::<ORModelList>(&url, &hdrs, &ctx).await?;
+        // OpenRouter ids are "<vendor>/<model>"; the vendor prefix doubles as the owner.
+        Ok(resp
+            .data
+            .into_iter()
+            .map(|m| ModelInfo {
+                owned_by: m.id.split_once('/').map(|(vendor, _)| vendor.to_string()),
+                id: m.id,
+                provider: self.name.clone(),
+                context_window: m.context_length,
+                max_output_tokens: m.top_provider.and_then(|p| p.max_completion_tokens),
+            })
+            .collect())
+    }
+}
+
 impl ProviderCaps for OpenRouter {
     fn capabilities(&self) -> &'static [Capability] {
-        &[Capability::Chat, Capability::Embed]
+        &[Capability::Chat, Capability::Embed, Capability::ListModels]
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::model::{ChatMessage, Role};
     use httpmock::{Method::POST, MockServer};
     use serde_json::json;
     use once_cell::sync::Lazy;
@@ -268,7 +556,7 @@ mod tests {
     }
 
     fn ensure_cl_sink_installed() {
-        let _ = crate::telemetry::set_telemetry_sink(Arc::new(CLTestSink::default()));
+        let _ = crate::telemetry::set_telemetry_sink(Arc::new(CLTestSink));
     }
 
     #[tokio::test]
@@ -285,28 +573,12 @@ mod tests {
                 "usage": {"prompt_tokens": 7, "completion_tokens": 3}
             }));
         });
-        let req = ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![ChatMessage {
-                role: Role::User,
-                content: "Hi".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = ChatRequest::builder().model("gpt-4o").user("Hi").build();
         let resp = provider.chat(req).await.expect("chat ok");
         assert_eq!(resp.text, "Hello via OR!");
         assert_eq!(resp.stop_reason, Some(StopReason::Stop));
         assert_eq!(resp.provider, "openrouter");
-        assert_eq!(resp.usage_prompt, 7);
-        assert_eq!(resp.usage_completion, 3);
+        assert_eq!(resp.usage, Usage::new(7, 3));
 
         let logs = COMPLETION_LOGS.lock().unwrap().clone();
         if !logs.is_empty() {
@@ -323,6 +595,54 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn top_level_citations_become_url_annotations() {
+        let server = MockServer::start();
+        let provider = OpenRouter::new_for_tests(&server.base_url());
+        let _m = server.mock(|when, then| {
+            when.method(POST).path("/v1/chat/completions");
+            then.status(200).json_body(json!({
+                "id": "req_1",
+                "choices": [{ "message": {"role":"assistant", "content":"See [1]."}, "finish_reason": "stop" }],
+                "citations": ["https://example.com/a", "https://example.com/b"]
+            }));
+        });
+        let req = ChatRequest::builder()
+            .model("perplexity/sonar")
+            .user("Hi")
+            .build();
+        let resp = provider.chat(req).await.expect("chat ok");
+        let urls: Vec<_> = resp.annotations.iter().map(|c| c.url.as_deref()).collect();
+        assert_eq!(
+            urls,
+            vec![Some("https://example.com/a"), Some("https://example.com/b")]
+        );
+        assert!(resp.annotations.iter().all(|c| c.title.is_none()));
+    }
+
+    #[tokio::test]
+    async fn list_models_uses_vendor_prefix_as_owner() {
+        let server = MockServer::start();
+        let provider = OpenRouter::new_for_tests(&server.base_url());
+        let _m = server.mock(|when, then| {
+            when.method(httpmock::Method::GET).path("/v1/models");
+            then.status(200).json_body(json!({
+                "data": [ {
+                    "id": "anthropic/claude-3.5-sonnet",
+                    "name": "Claude 3.5 Sonnet",
+                    "context_length": 200000,
+                    "top_provider": {"max_completion_tokens": 8192}
+                } ]
+            }));
+        });
+        let models = provider.list_models().await.expect("models ok");
+        assert_eq!(models[0].id, "anthropic/claude-3.5-sonnet");
+        assert_eq!(models[0].owned_by.as_deref(), Some("anthropic"));
+        assert_eq!(models[0].provider, "openrouter");
+        assert_eq!(models[0].context_window, Some(200000));
+        assert_eq!(models[0].max_output_tokens, Some(8192));
+    }
+
     #[tokio::test]
     async fn embed_200_maps_vectors() {
         let server = MockServer::start();
@@ -330,17 +650,85 @@ mod tests {
         let _m = server.mock(|when, then| {
             when.method(POST).path("/v1/embeddings");
             then.status(200).json_body(json!({
-                "data": [ {"embedding": [0.11, 0.22]} ]
+                "data": [ {"embedding": [0.11, 0.22]} ],
+                "usage": {"prompt_tokens": 2, "total_tokens": 2}
             }));
         });
-        let req = EmbedRequest {
-            model: "text-embedding-3-small".into(),
-            inputs: vec!["hello".into()],
-            client_key: None,
-        };
+        let req = EmbedRequest::builder()
+            .model("text-embedding-3-small")
+            .input("hello")
+            .build();
         let resp = provider.embed(req).await.expect("embed ok");
         assert_eq!(resp.vectors.len(), 1);
         assert_eq!(resp.vectors[0].len(), 2);
         assert_eq!(resp.provider, "openrouter");
+        assert_eq!(resp.usage, 2);
+    }
+
+    struct OpenRouterFixture;
+
+    impl crate::providers::conformance::ChatFixture for OpenRouterFixture {
+        type Provider = OpenRouter;
+
+        fn provider(&self, base_url: &str) -> OpenRouter {
+            OpenRouter::new_for_tests(base_url)
+        }
+
+        fn chat_path(&self) -> &str {
+            "/v1/chat/completions"
+        }
+
+        fn stop_reasons(&self) -> Vec<(Option<&'static str>, Option<StopReason>)> {
+            vec![
+                (Some("stop"), Some(StopReason::Stop)),
+                (Some("length"), Some(StopReason::Length)),
+                (Some("content_filter"), Some(StopReason::ContentFilter)),
+                (Some("tool_calls"), Some(StopReason::ToolUse)),
+                (Some("error"), Some(StopReason::Other)),
+                (None, None),
+            ]
+        }
+
+        fn success_body(
+            &self,
+            text: &str,
+            stop: Option<&str>,
+            prompt: u32,
+            completion: u32,
+        ) -> serde_json::Value {
+            json!({
+                "id": "gen_conformance",
+                "choices": [{
+                    "message": {"role": "assistant", "content": text},
+                    "finish_reason": stop
+                }],
+                "usage": {"prompt_tokens": prompt, "completion_tokens": completion}
+            })
+        }
+    }
+
+    impl crate::providers::conformance::EmbedFixture for OpenRouterFixture {
+        type Provider = OpenRouter;
+
+        fn provider(&self, base_url: &str) -> OpenRouter {
+            OpenRouter::new_for_tests(base_url)
+        }
+
+        fn embed_path(&self) -> &str {
+            "/v1/embeddings"
+        }
+
+        fn success_body(&self, vectors: &[Vec<f32>], prompt: u32) -> serde_json::Value {
+            json!({
+                "data": vectors.iter().map(|v| json!({"embedding": v})).collect::<Vec<_>>(),
+                "usage": {"prompt_tokens": prompt, "total_tokens": prompt}
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_the_conformance_suite() {
+        crate::providers::conformance::check_chat(&OpenRouterFixture).await;
+        crate::providers::conformance::check_embed(&OpenRouterFixture).await;
     }
 }
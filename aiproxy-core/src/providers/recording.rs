@@ -0,0 +1,318 @@
+//! Transcript recording: `RecordingProvider` writes each chat, stream and embed call made
+//! through `inner` to a `transcript::TranscriptWriter`, and stamps the record's id on the
+//! returned `ChatResponse.transcript_id`.
+//!
+//! A stream dropped before its terminal event is recorded with the text it had produced and
+//! the error `cancelled`.
+//!
+//! A record that cannot be queued never fails the call it describes; the response simply
+//! goes out without a `transcript_id`. One the writer thread then fails to write is logged.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures_util::StreamExt;
+
+use crate::error::CoreResult;
+use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::platform::Instant;
+use crate::provider::{ChatProvider, EmbedProvider};
+use crate::stream::{BoxStreamEv, StreamEvent};
+use crate::transcript::{TranscriptRecord, TranscriptWriter};
+
+/// Decorator recording `inner`'s calls to `writer`.
+#[derive(Debug)]
+pub struct RecordingProvider<P: ?Sized> {
+    inner: Arc<P>,
+    writer: Arc<TranscriptWriter>,
+}
+
+impl<P: ?Sized> RecordingProvider<P> {
+    pub fn new(inner: Arc<P>, writer: Arc<TranscriptWriter>) -> Self {
+        Self { inner, writer }
+    }
+}
+
+fn record<T: serde::Serialize>(
+    mut rec: TranscriptRecord,
+    started: Instant,
+    result: &CoreResult<T>,
+) -> TranscriptRecord {
+    rec.latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(resp) => rec.response = serde_json::to_value(resp).ok(),
+        Err(e) => rec.error = Some(e.to_string()),
+    }
+    rec
+}
+
+/// The record of a stream in progress, built up from its events.
+struct PendingStream {
+    writer: Arc<TranscriptWriter>,
+    rec: TranscriptRecord,
+    started: Instant,
+    text: String,
+    tool_calls: Vec<crate::model::ToolCall>,
+    usage: serde_json::Map<String, serde_json::Value>,
+    done: bool,
+}
+
+impl PendingStream {
+    /// Add `ev` to the record, writing it at the terminal event.
+    fn observe(&mut self, ev: &mut StreamEvent) {
+        match ev {
+            StreamEvent::DeltaText(delta) => self.text.push_str(delta),
+            StreamEvent::ToolCall(call) => self.tool_calls.push(call.clone()),
+            StreamEvent::Usage { prompt, completion } => {
+                if let Some(n) = prompt {
+                    self.usage.insert("prompt".into(), (*n).into());
+                }
+                if let Some(n) = completion {
+                    self.usage.insert("completion".into(), (*n).into());
+                }
+            }
+            StreamEvent::Stop { reason } => {
+                self.rec.response = Some(serde_json::json!({
+                    "text": self.text,
+                    "tool_calls": self.tool_calls,
+                    "stop_reason": reason,
+                    "usage": self.usage,
+                }));
+            }
+            StreamEvent::Final(resp) => {
+                self.rec.response = serde_json::to_value(&*resp).ok();
+            }
+            StreamEvent::Error(e) => {
+                self.rec.response = Some(serde_json::json!({ "text": self.text }));
+                self.rec.error = Some(e.to_string());
+            }
+        }
+        if ev.is_terminal() {
+            let id = self.finish();
+            if let StreamEvent::Final(resp) = ev {
+                resp.transcript_id = id;
+            }
+        }
+    }
+
+    fn finish(&mut self) -> Option<String> {
+        self.done = true;
+        self.rec.latency_ms = self.started.elapsed().as_millis() as u64;
+        self.writer.append(self.rec.clone()).ok()
+    }
+}
+
+impl Drop for PendingStream {
+    /// A stream dropped before its terminal event (a cancelled command, a disconnected
+    /// client) is recorded with the text it had produced.
+    fn drop(&mut self) {
+        if !self.done {
+            self.rec.response = Some(serde_json::json!({ "text": self.text }));
+            self.rec.error = Some("cancelled".into());
+            self.finish();
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for RecordingProvider<dyn ChatProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let rec = TranscriptRecord::new("chat", self.inner.name(), &req.model, &req);
+        let started = Instant::now();
+        let mut result = self.inner.chat(req).await;
+        let id = self.writer.append(record(rec, started, &result)).ok();
+        if let Ok(resp) = &mut result {
+            resp.transcript_id = id;
+        }
+        result
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        let mut rec = TranscriptRecord::new("chat_stream", self.inner.name(), &req.model, &req);
+        let started = Instant::now();
+        let inner = match self.inner.chat_stream_events(req).await {
+            Ok(inner) => inner,
+            Err(e) => {
+                rec.latency_ms = started.elapsed().as_millis() as u64;
+                rec.error = Some(e.to_string());
+                let _ = self.writer.append(rec);
+                return Err(e);
+            }
+        };
+        // The record is written at the terminal event, or with what arrived so far when the
+        // stream is dropped before one.
+        let mut pending = PendingStream {
+            writer: self.writer.clone(),
+            rec,
+            started,
+            text: String::new(),
+            tool_calls: Vec::new(),
+            usage: serde_json::Map::new(),
+            done: false,
+        };
+        let stream = inner.map(move |mut ev| {
+            if !pending.done {
+                pending.observe(&mut ev);
+            }
+            ev
+        });
+        Ok(Box::pin(stream))
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.inner.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.inner.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.inner.count_tokens(req).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for RecordingProvider<dyn EmbedProvider> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        let rec = TranscriptRecord::new("embed", self.inner.name(), &req.model, &req);
+        let started = Instant::now();
+        let result = self.inner.embed(req).await;
+        let mut rec = record(rec, started, &result);
+        if let (Ok(resp), Some(saved)) = (&result, rec.response.as_mut()) {
+            saved["vectors"] = serde_json::json!({
+                "count": resp.vectors.len(),
+                "dimensions": resp.vectors.first().map_or(0, Vec::len),
+            });
+        }
+        let _ = self.writer.append(rec);
+        result
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.inner.max_batch_inputs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{FsyncPolicy, TranscriptCfg};
+    use crate::provider::NullProvider;
+    use crate::transcript::read_segment;
+
+    fn writer(dir: &std::path::Path) -> Arc<TranscriptWriter> {
+        let cfg = TranscriptCfg {
+            dir: dir.to_string_lossy().into_owned(),
+            segment_mb: 64,
+            fsync: FsyncPolicy::Commit,
+            redact_builtin: false,
+        };
+        Arc::new(TranscriptWriter::open(&cfg).unwrap())
+    }
+
+    #[tokio::test]
+    async fn chats_and_streams_are_recorded_with_their_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = writer(dir.path());
+        let p = RecordingProvider::new(
+            Arc::new(NullProvider) as Arc<dyn ChatProvider>,
+            writer.clone(),
+        );
+        let req = || ChatRequest::builder().model("m").user("hi").build();
+
+        let resp = p.chat(req()).await.unwrap();
+        let chat_id = resp.transcript_id.expect("chat is recorded");
+        let events: Vec<StreamEvent> = p.chat_stream_events(req()).await.unwrap().collect().await;
+        let streamed = events
+            .iter()
+            .filter_map(|ev| match ev {
+                StreamEvent::Final(resp) => resp.transcript_id.clone(),
+                _ => None,
+            })
+            .next()
+            .expect("the final response carries its id");
+
+        writer.flush().unwrap();
+        let saved = read_segment(&writer.segment_path(1)).unwrap();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[0].id, chat_id);
+        assert_eq!(saved[0].kind, "chat");
+        assert_eq!(saved[0].response.as_ref().unwrap()["text"], resp.text);
+        assert_eq!(saved[1].kind, "chat_stream");
+        assert_eq!(saved[1].id, streamed);
+    }
+
+    /// Streams one delta, then waits forever.
+    #[derive(Debug)]
+    struct Stalls;
+
+    #[async_trait]
+    impl ChatProvider for Stalls {
+        fn name(&self) -> &str {
+            "stalls"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            NullProvider.chat(req).await
+        }
+
+        async fn chat_stream_events(&self, _req: ChatRequest) -> CoreResult<BoxStreamEv> {
+            let first = futures_util::stream::iter([StreamEvent::DeltaText("partial".into())]);
+            Ok(Box::pin(first.chain(futures_util::stream::pending())))
+        }
+    }
+
+    #[tokio::test]
+    async fn streams_dropped_early_are_recorded_as_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = writer(dir.path());
+        let p = RecordingProvider::new(Arc::new(Stalls) as Arc<dyn ChatProvider>, writer.clone());
+        let req = ChatRequest::builder().model("m").user("hi").build();
+        let mut events = p.chat_stream_events(req).await.unwrap();
+        assert!(matches!(
+            events.next().await,
+            Some(StreamEvent::DeltaText(_))
+        ));
+        drop(events);
+
+        writer.flush().unwrap();
+        let saved = read_segment(&writer.segment_path(1)).unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].kind, "chat_stream");
+        assert_eq!(saved[0].error.as_deref(), Some("cancelled"));
+        assert_eq!(saved[0].response.as_ref().unwrap()["text"], "partial");
+    }
+
+    #[tokio::test]
+    async fn embeds_are_recorded_without_their_vectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = writer(dir.path());
+        let p = RecordingProvider::new(
+            Arc::new(NullProvider) as Arc<dyn EmbedProvider>,
+            writer.clone(),
+        );
+        let req = EmbedRequest {
+            model: "m".into(),
+            inputs: vec!["a".into(), "b".into()],
+            client_key: None,
+            requested_model: None,
+        };
+        let resp = p.embed(req).await.unwrap();
+        writer.flush().unwrap();
+        let saved = read_segment(&writer.segment_path(1)).unwrap();
+        let vectors = &saved[0].response.as_ref().unwrap()["vectors"];
+        assert_eq!(vectors["count"], 2);
+        assert_eq!(vectors["dimensions"], resp.vectors[0].len());
+    }
+}
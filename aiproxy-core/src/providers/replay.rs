@@ -0,0 +1,224 @@
+//! Fixture replay configured by `[providers.replay]`: `ReplayProvider` answers each chat and
+//! embed request from a JSON fixture in `dir`, named after the request's hash, so tests and
+//! demos route to `replay` and run with no network and no API keys.
+//!
+//! A request with no fixture goes to `upstream` when it is registered, and its response is
+//! saved as the fixture for next time; without an upstream the request fails. Requests hash
+//! like `cache::chat_key` and `cache::embed_key`, under the name `replay`, so the fixtures
+//! stay valid whichever provider recorded them. Streams replay as one `Final` event.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::cache;
+use crate::config::ReplayCfg;
+use crate::error::{AiProxyError, CoreResult};
+use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::provider::{Capability, ChatProvider, EmbedProvider, ProviderCaps};
+
+/// Chat and embed provider serving fixtures from `dir`, recording misses from an upstream.
+#[derive(Debug)]
+pub struct ReplayProvider {
+    dir: PathBuf,
+    chat: Option<Arc<dyn ChatProvider>>,
+    embed: Option<Arc<dyn EmbedProvider>>,
+}
+
+impl ReplayProvider {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            chat: None,
+            embed: None,
+        }
+    }
+
+    pub fn from_cfg(cfg: &ReplayCfg) -> Self {
+        Self::new(&cfg.dir)
+    }
+
+    /// Record chats with no fixture from `upstream`.
+    pub fn with_chat_upstream(mut self, upstream: Arc<dyn ChatProvider>) -> Self {
+        self.chat = Some(upstream);
+        self
+    }
+
+    /// Record embeds with no fixture from `upstream`.
+    pub fn with_embed_upstream(mut self, upstream: Arc<dyn EmbedProvider>) -> Self {
+        self.embed = Some(upstream);
+        self
+    }
+
+    fn fixture(&self, kind: &str, key: &str) -> PathBuf {
+        self.dir.join(format!("{kind}-{key}.json"))
+    }
+
+    fn missing(&self, kind: &str, path: &Path) -> AiProxyError {
+        AiProxyError::Other(anyhow::anyhow!(
+            "no replay fixture for this {kind} request ({}) and no upstream to record it",
+            path.display()
+        ))
+    }
+}
+
+fn load<T: DeserializeOwned>(path: &Path) -> CoreResult<Option<T>> {
+    match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)
+            .map(Some)
+            .map_err(|e| AiProxyError::Other(anyhow::anyhow!("{}: {e}", path.display()))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write `value` to `path` through a temporary file, so a fixture is never seen half written.
+fn save<T: Serialize>(path: &Path, value: &T) -> CoreResult<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let body = serde_json::to_vec_pretty(value).map_err(|e| AiProxyError::Other(e.into()))?;
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, body)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// A fixture that cannot be written never fails the call that produced it.
+fn record<T: Serialize>(path: &Path, value: &T) {
+    if let Err(e) = save(path, value) {
+        tracing::warn!(fixture = %path.display(), error = %e, "could not save replay fixture");
+    }
+}
+
+#[async_trait]
+impl ChatProvider for ReplayProvider {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        let keyed = ChatRequest {
+            include_raw: false,
+            ..req.clone()
+        };
+        let key = cache::chat_key("replay", &keyed).expect("requests without raw are keyed");
+        let path = self.fixture("chat", &key);
+        if let Some(resp) = load(&path)? {
+            return Ok(resp);
+        }
+        let Some(upstream) = &self.chat else {
+            return Err(self.missing("chat", &path));
+        };
+        let resp = upstream.chat(req).await?;
+        record(&path, &resp);
+        Ok(resp)
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.chat.as_ref().is_some_and(|p| p.requires_alternation())
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.chat
+            .as_ref()
+            .is_some_and(|p| p.supports_response_schema())
+    }
+}
+
+#[async_trait]
+impl EmbedProvider for ReplayProvider {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        let path = self.fixture("embed", &cache::embed_key("replay", &req));
+        if let Some(resp) = load(&path)? {
+            return Ok(resp);
+        }
+        let Some(upstream) = &self.embed else {
+            return Err(self.missing("embed", &path));
+        };
+        let resp = upstream.embed(req).await?;
+        record(&path, &resp);
+        Ok(resp)
+    }
+
+    fn max_batch_inputs(&self) -> usize {
+        self.embed.as_ref().map_or(2048, |p| p.max_batch_inputs())
+    }
+}
+
+impl ProviderCaps for ReplayProvider {
+    fn capabilities(&self) -> &'static [Capability] {
+        &[Capability::Chat, Capability::ChatStream, Capability::Embed]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NullProvider;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// `NullProvider` that counts the chats reaching it.
+    #[derive(Debug, Default)]
+    struct Counted(AtomicUsize);
+
+    #[async_trait]
+    impl ChatProvider for Counted {
+        fn name(&self) -> &str {
+            "counted"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            NullProvider.chat(req).await
+        }
+    }
+
+    fn req(id: &str) -> ChatRequest {
+        ChatRequest::builder()
+            .model("m")
+            .user("hi")
+            .request_id(id)
+            .build()
+    }
+
+    #[tokio::test]
+    async fn misses_are_recorded_once_and_replayed_without_the_upstream() {
+        let dir = tempfile::tempdir().unwrap();
+        let upstream = Arc::new(Counted::default());
+        let recorder = ReplayProvider::new(dir.path()).with_chat_upstream(upstream.clone());
+        let first = recorder.chat(req("r1")).await.unwrap();
+        let again = recorder.chat(req("r2")).await.unwrap();
+        assert_eq!(again.text, first.text);
+        assert_eq!(upstream.0.load(Ordering::SeqCst), 1);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let offline = ReplayProvider::new(dir.path());
+        assert_eq!(offline.chat(req("r3")).await.unwrap().text, first.text);
+        let other = ChatRequest::builder().model("m").user("bye").build();
+        let err = offline.chat(other).await.unwrap_err();
+        assert!(err.to_string().contains("no replay fixture"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn embeds_replay_from_their_fixture() {
+        let dir = tempfile::tempdir().unwrap();
+        let req = EmbedRequest {
+            model: "m".into(),
+            inputs: vec!["a".into()],
+            client_key: None,
+            requested_model: None,
+        };
+        let recorder = ReplayProvider::new(dir.path()).with_embed_upstream(Arc::new(NullProvider));
+        let first = recorder.embed(req.clone()).await.unwrap();
+        let replayed = ReplayProvider::new(dir.path()).embed(req).await.unwrap();
+        assert_eq!(replayed.vectors, first.vectors);
+    }
+}
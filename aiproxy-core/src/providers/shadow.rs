@@ -0,0 +1,193 @@
+//! Shadow traffic configured by a routing rule's `shadow`: `ShadowProvider` answers every
+//! chat from `primary` and mirrors `percent` of them to `shadow` in the background, to
+//! compare a new provider or model on real traffic before cutting over.
+//!
+//! The mirrored call is a plain chat, streams included, and its response is dropped. It is
+//! made through the registry's provider, so it emits its own `ProviderTrace` and transcript
+//! record; the mirrored request keeps the original's `request_id` to join the two. The
+//! primary never waits for it, and its failures are only logged. A command about to exit
+//! awaits `ShadowProvider::drain`, so calls still in flight are not cut off with the runtime.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use tokio::task::JoinSet;
+
+use crate::error::CoreResult;
+use crate::model::{ChatRequest, ChatResponse};
+use crate::provider::ChatProvider;
+use crate::providers::hash::splitmix;
+use crate::stream::BoxStreamEv;
+
+/// Mirrored calls not yet awaited by `drain`. Routers build a `ShadowProvider` per
+/// selection, so the calls are tracked here rather than on the provider.
+static IN_FLIGHT: Lazy<Mutex<JoinSet<()>>> = Lazy::new(Mutex::default);
+
+/// Decorator mirroring a share of `primary`'s chats to `shadow`.
+#[derive(Debug)]
+pub struct ShadowProvider {
+    primary: Arc<dyn ChatProvider>,
+    shadow: Arc<dyn ChatProvider>,
+    model: Option<String>,
+    percent: u32,
+    seed: u64,
+    draws: AtomicU64,
+}
+
+impl ShadowProvider {
+    /// Which chats are mirrored follows a SplitMix64 sequence from `seed`.
+    pub fn new(
+        primary: Arc<dyn ChatProvider>,
+        shadow: Arc<dyn ChatProvider>,
+        percent: u32,
+        seed: u64,
+    ) -> Self {
+        Self {
+            primary,
+            shadow,
+            model: None,
+            percent,
+            seed,
+            draws: AtomicU64::new(0),
+        }
+    }
+
+    /// Wait for every mirrored call started so far.
+    pub async fn drain() {
+        let mut in_flight = std::mem::take(&mut *IN_FLIGHT.lock().unwrap());
+        while in_flight.join_next().await.is_some() {}
+    }
+
+    /// Ask the shadow for `model` instead of the request's own.
+    pub fn with_model(mut self, model: Option<String>) -> Self {
+        self.model = model;
+        self
+    }
+
+    fn mirror(&self, req: &ChatRequest) {
+        let n = self.draws.fetch_add(1, Ordering::Relaxed);
+        let mut state = self
+            .seed
+            .wrapping_add(n.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        if splitmix(&mut state) % 100 >= u64::from(self.percent) {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let mut req = req.clone();
+        if let Some(model) = &self.model {
+            req.model = model.clone();
+        }
+        let shadow = self.shadow.clone();
+        let mut in_flight = IN_FLIGHT.lock().unwrap();
+        // Finished calls are reaped here, so a long-running server does not accumulate them.
+        while in_flight.try_join_next().is_some() {}
+        let call = async move {
+            if let Err(e) = shadow.chat(req).await {
+                tracing::warn!(provider = shadow.name(), error = %e, "shadow chat failed");
+            }
+        };
+        in_flight.spawn_on(call, &runtime);
+    }
+}
+
+#[async_trait]
+impl ChatProvider for ShadowProvider {
+    fn name(&self) -> &str {
+        self.primary.name()
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        self.mirror(&req);
+        self.primary.chat(req).await
+    }
+
+    async fn chat_stream_events(&self, req: ChatRequest) -> CoreResult<BoxStreamEv> {
+        self.mirror(&req);
+        self.primary.chat_stream_events(req).await
+    }
+
+    fn requires_alternation(&self) -> bool {
+        self.primary.requires_alternation()
+    }
+
+    fn supports_response_schema(&self) -> bool {
+        self.primary.supports_response_schema()
+    }
+
+    async fn count_tokens(&self, req: &ChatRequest) -> CoreResult<Option<u32>> {
+        self.primary.count_tokens(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NullProvider;
+    use crate::test_util::Seen;
+    use std::time::Duration;
+
+    fn req() -> ChatRequest {
+        ChatRequest::builder().model("m").user("hi").build()
+    }
+
+    #[tokio::test]
+    async fn a_share_of_chats_is_mirrored_with_the_shadow_model() {
+        let shadow = Arc::new(Seen::default());
+        let p = ShadowProvider::new(Arc::new(NullProvider), shadow.clone(), 25, 3)
+            .with_model(Some("m-next".into()));
+        for _ in 0..400 {
+            assert_eq!(p.chat(req()).await.unwrap().provider, "null");
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let seen = shadow.models();
+        assert!(
+            (60..=140).contains(&seen.len()),
+            "{} of 400 mirrored",
+            seen.len()
+        );
+        assert!(seen.iter().all(|m| m == "m-next"));
+    }
+
+    #[tokio::test]
+    async fn zero_and_full_percentages_mirror_none_and_all() {
+        for (percent, expected) in [(0, 0), (100, 10)] {
+            let shadow = Arc::new(Seen::default());
+            let p = ShadowProvider::new(Arc::new(NullProvider), shadow.clone(), percent, 0);
+            for _ in 0..10 {
+                let _stream = p.chat_stream_events(req()).await.unwrap();
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(shadow.models().len(), expected);
+        }
+    }
+
+    /// `Seen`, after a delay.
+    #[derive(Debug, Default)]
+    struct Slow(Seen);
+
+    #[async_trait]
+    impl ChatProvider for Slow {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            self.0.chat(req).await
+        }
+    }
+
+    #[tokio::test]
+    async fn drain_waits_for_mirrored_calls() {
+        let shadow = Arc::new(Slow::default());
+        let p = ShadowProvider::new(Arc::new(NullProvider), shadow.clone(), 100, 0);
+        p.chat(req()).await.unwrap();
+        assert!(shadow.0.models().is_empty());
+        ShadowProvider::drain().await;
+        assert_eq!(shadow.0.models(), ["m"]);
+    }
+}
@@ -0,0 +1,435 @@
+//! Realtime sessions: long-lived, bidirectional connections to speech-capable models, for
+//! voice agents that stream microphone audio in and play synthesized speech back.
+//!
+//! Events use OpenAI's Realtime schema (`session.update`, `input_audio_buffer.append`,
+//! `response.audio.delta`, …) as the common format, the way the HTTP front-end uses the Chat
+//! Completions schema. `ClientEvent` and `ServerEvent` wrap the JSON with constructors and
+//! accessors for the common events; anything else passes through untouched.
+//!
+//! Every finished response (`response.done`) is reported as a `CompletionLog` and added to
+//! `RealtimeEvents::usage`, so token budgets can be settled while the conversation goes on.
+
+#[cfg(feature = "native")]
+pub mod ws;
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures::stream::{BoxStream, Stream, StreamExt};
+use serde_json::{Value, json};
+
+use crate::error::{AiProxyError, CoreResult};
+use crate::model::Usage;
+use crate::platform::Instant;
+
+/// Settings applied when a session opens.
+#[derive(Debug, Clone, Default)]
+pub struct SessionConfig {
+    pub model: String,
+    /// System instructions for the whole session.
+    pub instructions: Option<String>,
+    /// Output voice, e.g. `"alloy"`.
+    pub voice: Option<String>,
+    /// Output modalities, e.g. `["text", "audio"]`; empty keeps the provider default.
+    pub modalities: Vec<String>,
+    pub request_id: Option<String>,
+    /// Caller's key, carried into completion logs.
+    pub client_key: Option<String>,
+}
+
+impl SessionConfig {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            model: model.into(),
+            ..Self::default()
+        }
+    }
+
+    /// The `session.update` carrying these settings, if any are set.
+    pub fn update_event(&self) -> Option<ClientEvent> {
+        let mut session = serde_json::Map::new();
+        if let Some(instructions) = &self.instructions {
+            session.insert("instructions".into(), instructions.as_str().into());
+        }
+        if let Some(voice) = &self.voice {
+            session.insert("voice".into(), voice.as_str().into());
+        }
+        if !self.modalities.is_empty() {
+            session.insert("modalities".into(), self.modalities.clone().into());
+        }
+        (!session.is_empty()).then(|| ClientEvent::session_update(session.into()))
+    }
+}
+
+fn event_type(event: &Value) -> &str {
+    event["type"].as_str().unwrap_or_default()
+}
+
+/// An event sent to the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientEvent(pub Value);
+
+impl ClientEvent {
+    /// Parse an event received from a client; it must be a JSON object with a `type`.
+    pub fn parse(text: &str) -> CoreResult<Self> {
+        let event: Value = serde_json::from_str(text)
+            .map_err(|e| AiProxyError::Validation(format!("realtime event is not JSON: {e}")))?;
+        if !event["type"].is_string() {
+            return Err(AiProxyError::Validation(
+                "realtime event has no \"type\"".into(),
+            ));
+        }
+        Ok(Self(event))
+    }
+
+    pub fn kind(&self) -> &str {
+        event_type(&self.0)
+    }
+
+    /// Change session settings: `session` holds the fields to update.
+    pub fn session_update(session: Value) -> Self {
+        Self(json!({"type": "session.update", "session": session}))
+    }
+
+    /// Append raw audio (in the session's input format, 16-bit PCM by default) to the input
+    /// buffer.
+    pub fn input_audio(audio: &[u8]) -> Self {
+        Self(json!({"type": "input_audio_buffer.append", "audio": STANDARD.encode(audio)}))
+    }
+
+    /// Turn the buffered audio into a user message; only needed without server-side voice
+    /// activity detection.
+    pub fn commit_audio() -> Self {
+        Self(json!({"type": "input_audio_buffer.commit"}))
+    }
+
+    /// Add a user text message to the conversation.
+    pub fn user_text(text: &str) -> Self {
+        Self(json!({
+            "type": "conversation.item.create",
+            "item": {
+                "type": "message",
+                "role": "user",
+                "content": [{"type": "input_text", "text": text}]
+            }
+        }))
+    }
+
+    /// Ask the model to respond to the conversation so far.
+    pub fn create_response() -> Self {
+        Self(json!({"type": "response.create"}))
+    }
+
+    pub fn cancel_response() -> Self {
+        Self(json!({"type": "response.cancel"}))
+    }
+}
+
+/// An event received from the model.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerEvent(pub Value);
+
+impl ServerEvent {
+    /// Parse an event received from `provider`.
+    pub fn parse(provider: &str, text: &str) -> CoreResult<Self> {
+        serde_json::from_str(text)
+            .map(Self)
+            .map_err(|e| AiProxyError::ProviderError {
+                provider: provider.to_string(),
+                code: "decode_error".into(),
+                message: format!("realtime event is not JSON: {e}"),
+                upstream: Default::default(),
+            })
+    }
+
+    pub fn kind(&self) -> &str {
+        event_type(&self.0)
+    }
+
+    fn delta(&self, kinds: &[&str]) -> Option<&str> {
+        kinds
+            .contains(&self.kind())
+            .then(|| self.0["delta"].as_str())
+            .flatten()
+    }
+
+    /// A chunk of response text.
+    pub fn text_delta(&self) -> Option<&str> {
+        self.delta(&["response.text.delta", "response.output_text.delta"])
+    }
+
+    /// A chunk of the transcript of response audio.
+    pub fn transcript_delta(&self) -> Option<&str> {
+        self.delta(&[
+            "response.audio_transcript.delta",
+            "response.output_audio_transcript.delta",
+        ])
+    }
+
+    /// A chunk of response audio, decoded (in the session's output format).
+    pub fn audio_delta(&self) -> Option<Vec<u8>> {
+        let delta = self.delta(&["response.audio.delta", "response.output_audio.delta"])?;
+        STANDARD.decode(delta).ok()
+    }
+
+    /// Token usage of a finished response (`response.done`).
+    pub fn usage(&self) -> Option<Usage> {
+        if self.kind() != "response.done" {
+            return None;
+        }
+        let usage = self.0["response"].get("usage")?;
+        let count = |v: &Value| v.as_u64().map(|n| n as u32);
+        Some(Usage {
+            cached_prompt: count(&usage["input_token_details"]["cached_tokens"]),
+            audio_prompt: count(&usage["input_token_details"]["audio_tokens"]),
+            audio_completion: count(&usage["output_token_details"]["audio_tokens"]),
+            ..Usage::new(
+                count(&usage["input_tokens"]).unwrap_or(0),
+                count(&usage["output_tokens"]).unwrap_or(0),
+            )
+        })
+    }
+
+    /// The message of an `error` event.
+    pub fn error_message(&self) -> Option<&str> {
+        (self.kind() == "error")
+            .then(|| self.0["error"]["message"].as_str())
+            .flatten()
+    }
+}
+
+/// Sends events into a provider's session.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait EventSink: Send {
+    async fn send(&mut self, event: ClientEvent) -> CoreResult<()>;
+    /// End the session; the provider's event stream then finishes.
+    async fn close(&mut self) -> CoreResult<()>;
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait RealtimeProvider: Send + Sync + std::fmt::Debug {
+    fn name(&self) -> &str;
+    /// Open a session for `cfg.model` with `cfg`'s settings applied.
+    async fn connect(&self, cfg: &SessionConfig) -> CoreResult<RealtimeSession>;
+}
+
+/// An open session, as two halves so audio can be sent while events are read.
+pub struct RealtimeSession {
+    pub sender: RealtimeSender,
+    pub events: RealtimeEvents,
+}
+
+impl RealtimeSession {
+    /// Assemble a session from a provider's halves and send `cfg`'s `session.update`, if any.
+    pub async fn start(
+        provider: &str,
+        cfg: &SessionConfig,
+        sink: Box<dyn EventSink>,
+        events: BoxStream<'static, CoreResult<ServerEvent>>,
+    ) -> CoreResult<Self> {
+        let mut sender = RealtimeSender { sink };
+        if let Some(update) = cfg.update_event() {
+            sender.send(update).await?;
+        }
+        Ok(Self {
+            sender,
+            events: RealtimeEvents {
+                inner: events,
+                provider: provider.to_string(),
+                model: cfg.model.clone(),
+                request_id: cfg.request_id.clone(),
+                client_key: cfg.client_key.clone(),
+                usage: Usage::default(),
+                response_started: None,
+            },
+        })
+    }
+}
+
+pub struct RealtimeSender {
+    sink: Box<dyn EventSink>,
+}
+
+impl RealtimeSender {
+    pub async fn send(&mut self, event: ClientEvent) -> CoreResult<()> {
+        self.sink.send(event).await
+    }
+
+    pub async fn close(&mut self) -> CoreResult<()> {
+        self.sink.close().await
+    }
+}
+
+/// The model's events, in order; the stream ends when the session closes.
+pub struct RealtimeEvents {
+    inner: BoxStream<'static, CoreResult<ServerEvent>>,
+    provider: String,
+    model: String,
+    request_id: Option<String>,
+    client_key: Option<String>,
+    usage: Usage,
+    /// When the response in progress was created, for its latency.
+    response_started: Option<Instant>,
+}
+
+impl RealtimeEvents {
+    /// Tokens used by every response finished so far.
+    pub fn usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    fn observe(&mut self, event: &ServerEvent) {
+        if event.kind() == "response.created" {
+            self.response_started = Some(Instant::now());
+        }
+        let Some(usage) = event.usage() else {
+            return;
+        };
+        let add = |a: Option<u32>, b: Option<u32>| match (a, b) {
+            (None, None) => None,
+            (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+        };
+        self.usage = Usage {
+            prompt: self.usage.prompt + usage.prompt,
+            completion: self.usage.completion + usage.completion,
+            cached_prompt: add(self.usage.cached_prompt, usage.cached_prompt),
+            reasoning: add(self.usage.reasoning, usage.reasoning),
+            audio_prompt: add(self.usage.audio_prompt, usage.audio_prompt),
+            audio_completion: add(self.usage.audio_completion, usage.audio_completion),
+        };
+        let response = &event.0["response"];
+        let mut clog = crate::telemetry::CompletionLog::new()
+            .provider(&self.provider)
+            .model(&self.model)
+            .request_id_opt(self.request_id.as_deref())
+            .provider_request_id_opt(response["id"].as_str())
+            .client_key_opt(self.client_key.as_deref())
+            .stop_reason_opt(response["status"].as_str())
+            .cost_usd_opt(crate::pricing::cost_usd(
+                &self.provider,
+                &self.model,
+                usage.prompt,
+                usage.completion,
+            ))
+            .tokens(
+                Some(usage.prompt),
+                Some(usage.completion),
+                Some(usage.total()),
+            );
+        if let Some(started) = self.response_started.take() {
+            clog = clog.latency_ms(started.elapsed().as_millis() as u64);
+        }
+        crate::telemetry::emit_completion(clog);
+    }
+}
+
+impl Stream for RealtimeEvents {
+    type Item = CoreResult<ServerEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let next = self.inner.poll_next_unpin(cx);
+        if let Poll::Ready(Some(Ok(event))) = &next {
+            self.observe(event);
+        }
+        next
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Records what is sent to it.
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl EventSink for Recorder {
+        async fn send(&mut self, event: ClientEvent) -> CoreResult<()> {
+            self.0.lock().unwrap().push(event.kind().to_string());
+            Ok(())
+        }
+
+        async fn close(&mut self) -> CoreResult<()> {
+            self.0.lock().unwrap().push("closed".into());
+            Ok(())
+        }
+    }
+
+    fn done(input: u64, output: u64) -> CoreResult<ServerEvent> {
+        Ok(ServerEvent(json!({
+            "type": "response.done",
+            "response": {
+                "id": "resp_1",
+                "status": "completed",
+                "usage": {
+                    "input_tokens": input,
+                    "output_tokens": output,
+                    "output_token_details": {"audio_tokens": output}
+                }
+            }
+        })))
+    }
+
+    #[tokio::test]
+    async fn session_applies_settings_and_totals_usage_across_responses() {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let mut cfg = SessionConfig::new("gpt-4o-realtime-preview");
+        assert!(cfg.update_event().is_none());
+        cfg.voice = Some("alloy".into());
+        let audio = STANDARD.encode([1u8, 2, 3]);
+        let events = futures::stream::iter(vec![
+            Ok(ServerEvent(json!({"type": "response.created"}))),
+            Ok(ServerEvent(
+                json!({"type": "response.audio.delta", "delta": audio}),
+            )),
+            done(10, 4),
+            done(5, 2),
+        ])
+        .boxed();
+        let RealtimeSession {
+            mut sender,
+            mut events,
+        } = RealtimeSession::start("fake", &cfg, Box::new(Recorder(sent.clone())), events)
+            .await
+            .unwrap();
+        sender
+            .send(ClientEvent::input_audio(&[0; 4]))
+            .await
+            .unwrap();
+        sender.close().await.unwrap();
+        assert_eq!(
+            *sent.lock().unwrap(),
+            ["session.update", "input_audio_buffer.append", "closed"]
+        );
+
+        let all: Vec<ServerEvent> = (&mut events).map(Result::unwrap).collect().await;
+        assert_eq!(all[1].audio_delta(), Some(vec![1, 2, 3]));
+        assert_eq!(all[2].usage().unwrap().total(), 14);
+        let usage = events.usage();
+        assert_eq!((usage.prompt, usage.completion), (15, 6));
+        assert_eq!(usage.audio_completion, Some(6));
+        assert_eq!(usage.audio_prompt, None);
+    }
+
+    #[test]
+    fn client_events_must_be_typed_objects() {
+        assert_eq!(
+            ClientEvent::parse(r#"{"type": "response.create"}"#).unwrap(),
+            ClientEvent::create_response()
+        );
+        let err = ClientEvent::parse(r#"{"audio": "AAAA"}"#).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "validation failed: realtime event has no \"type\""
+        );
+        assert!(ClientEvent::parse("not json").is_err());
+        let error = json!({"type": "error", "error": {"message": "slow down"}});
+        assert_eq!(ServerEvent(error).error_message(), Some("slow down"));
+    }
+}
@@ -0,0 +1,419 @@
+//! Minimal RFC 6455 WebSocket transport: the client handshake over an HTTP/1.1 upgrade, and
+//! message framing for either end of a connection.
+//!
+//! Only what realtime sessions need is implemented: text, binary, ping, pong and close
+//! messages, and fragmented messages on receipt. No extensions are negotiated, so frames are
+//! never compressed.
+
+use std::borrow::Cow;
+use std::io;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use bytes::{Buf, BytesMut};
+use ring::rand::{SecureRandom, SystemRandom};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+
+use crate::error::{AiProxyError, CoreResult, Upstream};
+use crate::http_client::{extract_request_id, map_http_error};
+
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message accepted, after reassembling fragments. Realtime audio deltas are a few
+/// hundred KiB at most.
+pub const MAX_MESSAGE_BYTES: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    /// Closing handshake, with the peer's status code and reason if it sent one.
+    Close(Option<(u16, String)>),
+}
+
+/// Which end of the connection this is: clients mask the frames they send, servers must not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Client,
+    Server,
+}
+
+/// `Sec-WebSocket-Accept` for a handshake's `Sec-WebSocket-Key`.
+pub fn accept_key(key: &str) -> String {
+    let digest = ring::digest::digest(
+        &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{key}{ACCEPT_GUID}").as_bytes(),
+    );
+    STANDARD.encode(digest.as_ref())
+}
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random source is available");
+    bytes
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// `msg` as a single final frame, masked when sent by a client.
+pub fn encode(msg: &Message, role: Role) -> Vec<u8> {
+    let (opcode, payload): (u8, Cow<[u8]>) = match msg {
+        Message::Text(text) => (0x1, text.as_bytes().into()),
+        Message::Binary(data) => (0x2, data.into()),
+        Message::Close(None) => (0x8, Cow::Borrowed(&[])),
+        Message::Close(Some((code, reason))) => {
+            let mut body = code.to_be_bytes().to_vec();
+            body.extend_from_slice(reason.as_bytes());
+            (0x8, body.into())
+        }
+        Message::Ping(data) => (0x9, data.into()),
+        Message::Pong(data) => (0xA, data.into()),
+    };
+    let mask_bit = if role == Role::Client { 0x80 } else { 0 };
+    let mut out = Vec::with_capacity(payload.len() + 14);
+    out.push(0x80 | opcode);
+    match payload.len() {
+        n if n < 126 => out.push(mask_bit | n as u8),
+        n if n <= usize::from(u16::MAX) => {
+            out.push(mask_bit | 126);
+            out.extend_from_slice(&(n as u16).to_be_bytes());
+        }
+        n => {
+            out.push(mask_bit | 127);
+            out.extend_from_slice(&(n as u64).to_be_bytes());
+        }
+    }
+    if role == Role::Client {
+        let mask: [u8; 4] = random();
+        out.extend_from_slice(&mask);
+        out.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+    } else {
+        out.extend_from_slice(&payload);
+    }
+    out
+}
+
+struct Frame {
+    fin: bool,
+    opcode: u8,
+    payload: Vec<u8>,
+}
+
+/// Take one complete frame off the front of `buf`, or `None` if it holds only part of one.
+fn decode_frame(buf: &mut BytesMut, role: Role) -> io::Result<Option<Frame>> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    let (b0, b1) = (buf[0], buf[1]);
+    if b0 & 0x70 != 0 {
+        return Err(invalid(
+            "reserved frame bits set without a negotiated extension",
+        ));
+    }
+    let masked = b1 & 0x80 != 0;
+    if masked != (role == Role::Server) {
+        return Err(invalid(match role {
+            Role::Server => "client frame is not masked",
+            Role::Client => "server frame is masked",
+        }));
+    }
+    let (len, header) = match b1 & 0x7f {
+        126 if buf.len() < 4 => return Ok(None),
+        126 => (u64::from(u16::from_be_bytes([buf[2], buf[3]])), 4),
+        127 if buf.len() < 10 => return Ok(None),
+        127 => (u64::from_be_bytes(buf[2..10].try_into().unwrap()), 10),
+        n => (u64::from(n), 2),
+    };
+    if len > MAX_MESSAGE_BYTES as u64 {
+        return Err(invalid(format!(
+            "frame of {len} bytes exceeds the message limit"
+        )));
+    }
+    let len = len as usize;
+    let mask_len = if masked { 4 } else { 0 };
+    if buf.len() < header + mask_len + len {
+        return Ok(None);
+    }
+    buf.advance(header);
+    let mask = masked.then(|| {
+        let mask = [buf[0], buf[1], buf[2], buf[3]];
+        buf.advance(4);
+        mask
+    });
+    let mut payload = buf.split_to(len).to_vec();
+    if let Some(mask) = mask {
+        payload
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, b)| *b ^= mask[i % 4]);
+    }
+    Ok(Some(Frame {
+        fin: b0 & 0x80 != 0,
+        opcode: b0 & 0x0f,
+        payload,
+    }))
+}
+
+fn data_message(opcode: u8, data: Vec<u8>) -> io::Result<Message> {
+    if opcode == 0x1 {
+        String::from_utf8(data)
+            .map(Message::Text)
+            .map_err(|_| invalid("text message is not valid UTF-8"))
+    } else {
+        Ok(Message::Binary(data))
+    }
+}
+
+/// Receiving half of a WebSocket.
+#[derive(Debug)]
+pub struct WsReader<R> {
+    io: R,
+    role: Role,
+    buf: BytesMut,
+    /// Opcode and payload so far of a fragmented message.
+    partial: Option<(u8, Vec<u8>)>,
+}
+
+impl<R: AsyncRead + Unpin> WsReader<R> {
+    pub fn new(io: R, role: Role) -> Self {
+        Self {
+            io,
+            role,
+            buf: BytesMut::new(),
+            partial: None,
+        }
+    }
+
+    /// The next complete message; `None` once the connection ends. Cancel-safe: bytes read
+    /// before a cancellation stay buffered for the next call. Pings are returned rather than
+    /// answered, since replying needs the sending half.
+    pub async fn recv(&mut self) -> io::Result<Option<Message>> {
+        loop {
+            while let Some(frame) = decode_frame(&mut self.buf, self.role)? {
+                if let Some(msg) = self.assemble(frame)? {
+                    return Ok(Some(msg));
+                }
+            }
+            if self.io.read_buf(&mut self.buf).await? == 0 {
+                return if self.buf.is_empty() && self.partial.is_none() {
+                    Ok(None)
+                } else {
+                    Err(io::ErrorKind::UnexpectedEof.into())
+                };
+            }
+        }
+    }
+
+    fn assemble(&mut self, frame: Frame) -> io::Result<Option<Message>> {
+        let Frame {
+            fin,
+            opcode,
+            payload,
+        } = frame;
+        if opcode >= 0x8 && !fin {
+            return Err(invalid("control frame is fragmented"));
+        }
+        match opcode {
+            0x0 => {
+                let Some((_, data)) = &mut self.partial else {
+                    return Err(invalid("continuation frame without a message to continue"));
+                };
+                if data.len() + payload.len() > MAX_MESSAGE_BYTES {
+                    return Err(invalid("message exceeds the size limit"));
+                }
+                data.extend_from_slice(&payload);
+                if !fin {
+                    return Ok(None);
+                }
+                let (opcode, data) = self.partial.take().unwrap();
+                data_message(opcode, data).map(Some)
+            }
+            0x1 | 0x2 if self.partial.is_some() => {
+                Err(invalid("new message before the fragmented one finished"))
+            }
+            0x1 | 0x2 if !fin => {
+                self.partial = Some((opcode, payload));
+                Ok(None)
+            }
+            0x1 | 0x2 => data_message(opcode, payload).map(Some),
+            0x8 => {
+                let status = (payload.len() >= 2).then(|| {
+                    (
+                        u16::from_be_bytes([payload[0], payload[1]]),
+                        String::from_utf8_lossy(&payload[2..]).into_owned(),
+                    )
+                });
+                Ok(Some(Message::Close(status)))
+            }
+            0x9 => Ok(Some(Message::Ping(payload))),
+            0xA => Ok(Some(Message::Pong(payload))),
+            op => Err(invalid(format!("unknown opcode {op:#x}"))),
+        }
+    }
+}
+
+/// Sending half of a WebSocket.
+#[derive(Debug)]
+pub struct WsWriter<W> {
+    io: W,
+    role: Role,
+}
+
+impl<W: AsyncWrite + Unpin> WsWriter<W> {
+    pub fn new(io: W, role: Role) -> Self {
+        Self { io, role }
+    }
+
+    pub async fn send(&mut self, msg: &Message) -> io::Result<()> {
+        self.io.write_all(&encode(msg, self.role)).await?;
+        self.io.flush().await
+    }
+}
+
+/// Split an established connection (after the handshake) into its two halves, so sending
+/// and receiving can run in separate tasks.
+pub fn split<S: AsyncRead + AsyncWrite>(
+    io: S,
+    role: Role,
+) -> (WsReader<ReadHalf<S>>, WsWriter<WriteHalf<S>>) {
+    let (read, write) = tokio::io::split(io);
+    (WsReader::new(read, role), WsWriter::new(write, role))
+}
+
+/// A client connection opened by `connect`.
+pub type ClientReader = WsReader<ReadHalf<reqwest::Upgraded>>;
+pub type ClientWriter = WsWriter<WriteHalf<reqwest::Upgraded>>;
+
+/// Open a WebSocket to `url` (`ws`, `wss`, `http` or `https`), sending `headers` with the
+/// handshake. A refused handshake is mapped like an HTTP error from `provider`; a connection
+/// failure is `ProviderUnavailable`.
+pub async fn connect(
+    provider: &str,
+    url: &str,
+    headers: &[(String, String)],
+    model: Option<&str>,
+) -> CoreResult<(ClientReader, ClientWriter)> {
+    let url = match url.split_once("://") {
+        Some(("ws", rest)) => format!("http://{rest}"),
+        Some(("wss", rest)) => format!("https://{rest}"),
+        _ => url.to_string(),
+    };
+    let unavailable = || AiProxyError::ProviderUnavailable {
+        provider: provider.to_string(),
+        upstream: Box::new(Upstream {
+            model: model.map(str::to_string),
+            ..Upstream::default()
+        }),
+    };
+    // Upgrades only exist in HTTP/1.1, so the client must not negotiate HTTP/2.
+    let client = reqwest::Client::builder()
+        .http1_only()
+        .connect_timeout(std::time::Duration::from_secs(5))
+        .build()
+        .map_err(|e| AiProxyError::Other(anyhow::anyhow!("http client build failed: {e}")))?;
+    let key = STANDARD.encode(random::<16>());
+    let mut req = client
+        .get(&url)
+        .header("Connection", "Upgrade")
+        .header("Upgrade", "websocket")
+        .header("Sec-WebSocket-Version", "13")
+        .header("Sec-WebSocket-Key", &key)
+        .header("User-Agent", "ai-proxy/0.1");
+    for (name, value) in headers {
+        req = req.header(name, value);
+    }
+    let resp = req.send().await.map_err(|_| unavailable())?;
+    let status = resp.status();
+    let request_id = extract_request_id(resp.headers());
+    if status != reqwest::StatusCode::SWITCHING_PROTOCOLS {
+        let headers = resp.headers().clone();
+        let body = resp.text().await.unwrap_or_default();
+        return Err(map_http_error(
+            provider, status, &headers, &body, request_id, model,
+        ));
+    }
+    let accept = resp
+        .headers()
+        .get("sec-websocket-accept")
+        .and_then(|v| v.to_str().ok());
+    if accept != Some(accept_key(&key).as_str()) {
+        return Err(AiProxyError::ProviderError {
+            provider: provider.to_string(),
+            code: "handshake".into(),
+            message: "WebSocket handshake returned a wrong Sec-WebSocket-Accept".into(),
+            upstream: Box::new(Upstream {
+                model: model.map(str::to_string),
+                status: Some(status.as_u16()),
+                provider_request_id: request_id,
+            }),
+        });
+    }
+    let io = resp.upgrade().await.map_err(|_| unavailable())?;
+    Ok(split(io, Role::Client))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_key_matches_the_rfc_example() {
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[tokio::test]
+    async fn frames_round_trip_in_both_directions() {
+        let long = "x".repeat(70_000);
+        let messages = [
+            Message::Text("hello".into()),
+            Message::Binary(vec![1; 300]),
+            Message::Text(long),
+            Message::Ping(b"p".to_vec()),
+            Message::Close(Some((1000, "bye".into()))),
+        ];
+        for (sender, receiver) in [(Role::Client, Role::Server), (Role::Server, Role::Client)] {
+            let wire: Vec<u8> = messages.iter().flat_map(|m| encode(m, sender)).collect();
+            let mut reader = WsReader::new(wire.as_slice(), receiver);
+            for expected in &messages {
+                assert_eq!(reader.recv().await.unwrap().as_ref(), Some(expected));
+            }
+            assert_eq!(reader.recv().await.unwrap(), None);
+        }
+    }
+
+    #[tokio::test]
+    async fn fragments_are_reassembled_and_bad_frames_rejected() {
+        // "Hel" + "lo" as an unmasked text message in two frames, with a ping between them.
+        let mut wire = vec![0x01, 3];
+        wire.extend_from_slice(b"Hel");
+        wire.extend_from_slice(&[0x89, 0]);
+        wire.extend_from_slice(&[0x80, 2]);
+        wire.extend_from_slice(b"lo");
+        let mut reader = WsReader::new(wire.as_slice(), Role::Client);
+        assert_eq!(reader.recv().await.unwrap(), Some(Message::Ping(vec![])));
+        assert_eq!(
+            reader.recv().await.unwrap(),
+            Some(Message::Text("Hello".into()))
+        );
+
+        let unmasked = encode(&Message::Text("hi".into()), Role::Server);
+        let err = WsReader::new(unmasked.as_slice(), Role::Server)
+            .recv()
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "client frame is not masked");
+
+        let cut = &encode(&Message::Text("hello".into()), Role::Server)[..4];
+        let err = WsReader::new(cut, Role::Client).recv().await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,231 @@
+//! Retry budgets: a process-wide cap on the share of provider traffic that may be retries.
+//!
+//! Every first attempt is recorded with `record_request`; every retry must first be granted
+//! by `try_retry`. Over a sliding window, retries may make up at most `ratio` of requests
+//! (with a floor of `min_retries`, so quiet periods can still retry). Once a retry is
+//! refused the budget trips: every retry fails fast for one window, so an outage is not
+//! met with a retry storm.
+//!
+//! `backoff` computes the wait before each retry from a `config::RetryCfg`.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::config::RetryCfg;
+use crate::platform::Instant;
+
+/// Sizing of a `RetryBudget`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryBudgetCfg {
+    /// Largest share of requests in the window that may be retries, e.g. 0.2 for 20%.
+    pub ratio: f64,
+    /// Retries always allowed per window, whatever the traffic.
+    pub min_retries: u32,
+    pub window: Duration,
+}
+
+impl Default for RetryBudgetCfg {
+    fn default() -> Self {
+        Self {
+            ratio: 0.2,
+            min_retries: 10,
+            window: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Window {
+    requests: VecDeque<Instant>,
+    retries: VecDeque<Instant>,
+    tripped_until: Option<Instant>,
+}
+
+impl Window {
+    fn expire(&mut self, now: Instant, window: Duration) {
+        for q in [&mut self.requests, &mut self.retries] {
+            while q.front().is_some_and(|t| now - *t >= window) {
+                q.pop_front();
+            }
+        }
+        if self.tripped_until.is_some_and(|t| now >= t) {
+            self.tripped_until = None;
+        }
+    }
+}
+
+/// Thread-safe retry budget shared by every caller that retries provider calls.
+#[derive(Debug, Default)]
+pub struct RetryBudget {
+    cfg: RetryBudgetCfg,
+    window: Mutex<Window>,
+}
+
+impl RetryBudget {
+    pub fn new(cfg: RetryBudgetCfg) -> Self {
+        Self {
+            cfg,
+            window: Mutex::new(Window::default()),
+        }
+    }
+
+    pub fn cfg(&self) -> RetryBudgetCfg {
+        self.cfg
+    }
+
+    /// Count one first attempt; retries are not recorded here.
+    pub fn record_request(&self) {
+        self.record_at(Instant::now());
+    }
+
+    /// Ask to retry once. `false` means the budget is spent (or tripped) and the caller
+    /// should fail with the error it has.
+    pub fn try_retry(&self) -> bool {
+        self.try_retry_at(Instant::now())
+    }
+
+    /// Whether retries currently fail fast.
+    pub fn is_tripped(&self) -> bool {
+        let now = Instant::now();
+        let mut w = self.window.lock().unwrap();
+        w.expire(now, self.cfg.window);
+        w.tripped_until.is_some()
+    }
+
+    fn record_at(&self, now: Instant) {
+        let mut w = self.window.lock().unwrap();
+        w.expire(now, self.cfg.window);
+        w.requests.push_back(now);
+    }
+
+    fn try_retry_at(&self, now: Instant) -> bool {
+        let mut w = self.window.lock().unwrap();
+        w.expire(now, self.cfg.window);
+        if w.tripped_until.is_some() {
+            return false;
+        }
+        let allowed = (w.requests.len() as f64 * self.cfg.ratio)
+            .floor()
+            .max(f64::from(self.cfg.min_retries));
+        if w.retries.len() as f64 + 1.0 > allowed {
+            w.tripped_until = Some(now + self.cfg.window);
+            return false;
+        }
+        w.retries.push_back(now);
+        true
+    }
+}
+
+static JITTER: AtomicU64 = AtomicU64::new(0);
+
+/// A uniform draw from `0..=max`.
+fn jitter(max: u64) -> u64 {
+    let mut state = JITTER.fetch_add(1, Ordering::Relaxed)
+        ^ crate::platform::SystemTime::now()
+            .duration_since(crate::platform::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+    crate::providers::hash::splitmix(&mut state) % (max + 1)
+}
+
+/// The wait before retry number `retry` (1 for the first): `base_backoff_ms` doubled per
+/// retry and capped at `max_backoff_ms`, drawn from `0..=` that with `jitter`. With
+/// `respect_retry_after`, a server's `Retry-After` of `retry_after` seconds is waited out
+/// instead; `None` means it asked for more than `max_backoff_ms`, so the call should fail.
+pub fn backoff(cfg: &RetryCfg, retry: u32, retry_after: Option<u64>) -> Option<Duration> {
+    if cfg.respect_retry_after
+        && let Some(secs) = retry_after
+    {
+        let ms = secs.saturating_mul(1_000);
+        return (ms <= cfg.max_backoff_ms).then(|| Duration::from_millis(ms));
+    }
+    let exp = cfg
+        .base_backoff_ms
+        .saturating_mul(1u64 << retry.saturating_sub(1).min(32))
+        .min(cfg.max_backoff_ms);
+    let ms = if cfg.jitter { jitter(exp) } else { exp };
+    Some(Duration::from_millis(ms))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(ratio: f64, min_retries: u32) -> RetryBudget {
+        RetryBudget::new(RetryBudgetCfg {
+            ratio,
+            min_retries,
+            window: Duration::from_secs(10),
+        })
+    }
+
+    #[test]
+    fn retries_are_capped_at_a_share_of_requests() {
+        let b = budget(0.2, 1);
+        let t0 = Instant::now();
+        for _ in 0..10 {
+            b.record_at(t0);
+        }
+        assert!(b.try_retry_at(t0));
+        assert!(b.try_retry_at(t0));
+        assert!(
+            !b.try_retry_at(t0),
+            "a third retry is over 20% of 10 requests"
+        );
+    }
+
+    #[test]
+    fn min_retries_apply_without_traffic() {
+        let b = budget(0.1, 2);
+        let t0 = Instant::now();
+        assert!(b.try_retry_at(t0));
+        assert!(b.try_retry_at(t0));
+        assert!(!b.try_retry_at(t0));
+    }
+
+    #[test]
+    fn a_refused_retry_trips_fail_fast_for_one_window() {
+        let b = budget(0.5, 0);
+        let t0 = Instant::now();
+        b.record_at(t0);
+        b.record_at(t0);
+        assert!(b.try_retry_at(t0));
+        assert!(!b.try_retry_at(t0));
+        // More traffic would make room, but the budget stays tripped for the window.
+        for _ in 0..10 {
+            b.record_at(t0 + Duration::from_secs(1));
+        }
+        assert!(!b.try_retry_at(t0 + Duration::from_secs(1)));
+        let later = t0 + Duration::from_secs(10);
+        b.record_at(later);
+        b.record_at(later);
+        assert!(b.try_retry_at(later));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_and_honors_retry_after() {
+        let cfg = RetryCfg {
+            max_attempts: 5,
+            base_backoff_ms: 100,
+            max_backoff_ms: 300,
+            jitter: false,
+            respect_retry_after: true,
+        };
+        let waits: Vec<u64> = (1..=3)
+            .map(|n| backoff(&cfg, n, None).unwrap().as_millis() as u64)
+            .collect();
+        assert_eq!(waits, [100, 200, 300]);
+        assert_eq!(backoff(&cfg, 1, Some(0)), Some(Duration::ZERO));
+        assert_eq!(backoff(&cfg, 1, Some(1)), None, "1s is over the 300ms cap");
+
+        let jittered = RetryCfg {
+            jitter: true,
+            respect_retry_after: false,
+            ..cfg
+        };
+        assert!(
+            (0..50).all(|_| backoff(&jittered, 2, Some(1)).unwrap() <= Duration::from_millis(200))
+        );
+    }
+}
@@ -1,88 +1,512 @@
+use std::collections::{BTreeMap, HashSet};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use regex::Regex;
+use serde::Serialize;
 
-use crate::config::{Config, RoutingRule};
+use crate::catalog;
+use crate::config::{Config, RoutingCfg, RoutingRule, ShadowCfg, WeightedProvider};
 use crate::error::{AiProxyError, CoreResult};
-use crate::provider::{ChatProvider, EmbedProvider};
+use crate::model::ModelInfo;
+use crate::provider::{Capability, ChatProvider, EmbedProvider};
 use crate::provider_factory::ProviderRegistry;
+use crate::providers::alias::AliasProvider;
+use crate::providers::failover::FailoverProvider;
+#[cfg(feature = "native")]
+use crate::providers::shadow::ShadowProvider;
+use crate::realtime::RealtimeProvider;
 
 /// Compiled routing rule
 #[derive(Debug)]
 struct CompiledRule {
     regex: Regex,
-    provider: String,
+    /// Weighted first choices, in config order; their weights never all 0.
+    split: Vec<WeightedProvider>,
+    /// Tried after the split's arms, in order. Never empty without a split.
+    providers: Vec<String>,
+    shadow: Option<ShadowCfg>,
+}
+
+impl CompiledRule {
+    /// Targets in the order tried when the split draws the arm at `first`: that arm, the
+    /// split's others, then `providers`.
+    fn chain(&self, first: Option<usize>) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        let all = first
+            .map(|i| self.split[i].provider.as_str())
+            .into_iter()
+            .chain(self.split.iter().map(|w| w.provider.as_str()))
+            .chain(self.providers.iter().map(String::as_str));
+        for name in all {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Targets as reported outside dispatch: the heaviest arm first, where most requests go.
+    fn targets(&self) -> Vec<&str> {
+        let heaviest =
+            (0..self.split.len()).max_by_key(|&i| (self.split[i].weight, std::cmp::Reverse(i)));
+        self.chain(heaviest)
+    }
+
+    fn provider(&self) -> &str {
+        self.targets()[0]
+    }
+}
+
+/// Whether the chosen provider advertises one required capability, and the model has it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CapabilityCheck {
+    pub capability: Capability,
+    pub supported: bool,
+    /// What the model catalog says about the model; `None` when it does not describe it.
+    pub model_supported: Option<bool>,
+}
+
+/// Trace of how a model would be routed; produced by `RoutingResolver::explain`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RouteExplanation {
+    pub model: String,
+    /// Index into `routing.rules` and pattern of the first matching rule; `None` = default.
+    pub matched_rule: Option<(usize, String)>,
+    pub provider: String,
+    /// False when the chosen provider is not in the registry (e.g. its API key is unset).
+    pub registered: bool,
+    pub checks: Vec<CapabilityCheck>,
+    /// The rule's other providers, tried in order when `provider` is rate limited or
+    /// unavailable, then other providers that also match (later rules, then the default).
+    pub fallbacks: Vec<String>,
+    /// The matched rule's weighted arms, of which `provider` is the heaviest; requests draw
+    /// among them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub split: Vec<WeightedProvider>,
+    /// The model `model` is an alias for, which the rules matched and the provider is sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
+    /// The matched rule's shadow, mirrored a share of the chats.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowCfg>,
+}
+
+impl RouteExplanation {
+    /// True when the chosen provider exists and passes every capability check.
+    pub fn routable(&self) -> bool {
+        self.registered && self.checks.iter().all(|c| c.supported)
+    }
+}
+
+/// Fail when the catalog says `model` lacks `capability`, e.g. chat with an embedding model.
+fn check_model(model: &str, capability: Capability) -> CoreResult<()> {
+    if catalog::supports(model, capability) == Some(false) {
+        return Err(AiProxyError::Validation(format!(
+            "model '{model}' does not support {}",
+            match capability {
+                Capability::Chat => "chat",
+                Capability::Embed => "embeddings",
+                Capability::Realtime => "realtime sessions",
+                _ => "this request",
+            }
+        )));
+    }
+    Ok(())
+}
+
+/// Error for a route whose every target is out of rotation.
+fn unavailable(provider: &str) -> AiProxyError {
+    AiProxyError::ProviderUnavailable {
+        provider: provider.to_string(),
+        upstream: Default::default(),
+    }
 }
 
 /// Resolves a model string to a provider name, then fetches the provider
 /// from the registry, validating the capability.
+///
+/// A model named in `routing.aliases` is replaced by its alias's model first: rules match
+/// the replacement, and selected providers are wrapped in an `AliasProvider` sending it.
+///
+/// Rules with a `split` draw their first provider on each `select_*` call, from a SplitMix64
+/// sequence: the same seed and the same calls give the same picks.
 #[derive(Debug)]
 pub struct RoutingResolver {
     rules: Vec<CompiledRule>,
     default_provider: String,
+    aliases: BTreeMap<String, String>,
+    seed: u64,
+    draws: AtomicU64,
 }
 
 impl RoutingResolver {
     /// Build a resolver by compiling regexes from config.
     pub fn new(cfg: &Config) -> CoreResult<Self> {
+        Self::from_routing(&cfg.routing)
+    }
+
+    /// Build a resolver from just the `routing` section, e.g. to swap rules at runtime.
+    pub fn from_routing(routing: &RoutingCfg) -> CoreResult<Self> {
         let mut rules = Vec::new();
-        for RoutingRule { model, provider } in &cfg.routing.rules {
+        for rule in &routing.rules {
+            let model = &rule.model;
             let regex = Regex::new(model).map_err(|e| {
                 AiProxyError::Validation(format!("invalid routing regex '{model}': {e}"))
             })?;
+            if rule.targets().next().is_none() {
+                return Err(AiProxyError::Validation(format!(
+                    "routing rule '{model}' names no provider"
+                )));
+            }
+            if !rule.split.is_empty() && !rule.provider.is_empty() {
+                return Err(AiProxyError::Validation(format!(
+                    "routing rule '{model}' sets both provider and split"
+                )));
+            }
+            if !rule.split.is_empty() && rule.split.iter().all(|w| w.weight == 0) {
+                return Err(AiProxyError::Validation(format!(
+                    "routing rule '{model}' has a split without weight"
+                )));
+            }
             rules.push(CompiledRule {
                 regex,
-                provider: provider.clone(),
+                split: rule.split.clone(),
+                providers: rule
+                    .targets()
+                    .skip(rule.split.len())
+                    .map(str::to_string)
+                    .collect(),
+                shadow: rule.shadow.clone(),
             });
         }
         Ok(Self {
             rules,
-            default_provider: cfg.routing.default.clone(),
+            default_provider: routing.default.clone(),
+            aliases: routing.aliases.clone(),
+            seed: 0,
+            draws: AtomicU64::new(0),
         })
     }
 
-    fn pick_provider_name<'a>(&'a self, model: &str) -> &'a str {
-        for r in &self.rules {
-            if r.regex.is_match(model) {
-                return &r.provider;
+    /// Draw weighted routes from `seed`'s sequence instead of 0's, e.g. to vary them
+    /// between processes.
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self.draws = AtomicU64::new(0);
+        self
+    }
+
+    /// The rules this resolver applies, in config form.
+    pub fn routing(&self) -> RoutingCfg {
+        RoutingCfg {
+            default: self.default_provider.clone(),
+            rules: self
+                .rules
+                .iter()
+                .map(|r| match r.providers.as_slice() {
+                    [one] if r.split.is_empty() => RoutingRule {
+                        model: r.regex.as_str().to_string(),
+                        provider: one.clone(),
+                        providers: vec![],
+                        split: vec![],
+                        shadow: r.shadow.clone(),
+                    },
+                    chain => RoutingRule {
+                        model: r.regex.as_str().to_string(),
+                        provider: String::new(),
+                        providers: chain.to_vec(),
+                        split: r.split.clone(),
+                        shadow: r.shadow.clone(),
+                    },
+                })
+                .collect(),
+            aliases: self.aliases.clone(),
+        }
+    }
+
+    /// The model requests for `model` are routed and sent as: its alias's model, else itself.
+    pub fn resolve_alias<'a>(&'a self, model: &'a str) -> &'a str {
+        self.aliases.get(model).map_or(model, String::as_str)
+    }
+
+    /// Name of the provider the routing rules pick for `model` (first match, else the default).
+    /// For a weighted split, the arm with the most weight.
+    pub fn pick_provider_name<'a>(&'a self, model: &str) -> &'a str {
+        self.pick_provider_names(model)[0]
+    }
+
+    /// Every provider routing would try for `model`: the first matching rule's chain, else
+    /// the default.
+    pub fn pick_provider_names<'a>(&'a self, model: &str) -> Vec<&'a str> {
+        let model = self.resolve_alias(model);
+        match self.rules.iter().find(|r| r.regex.is_match(model)) {
+            Some(r) => r.targets(),
+            None => vec![&self.default_provider],
+        }
+    }
+
+    /// As `pick_provider_names`, for one dispatch: a weighted split draws its first provider.
+    fn route<'a>(&'a self, model: &str) -> Vec<&'a str> {
+        match self.rules.iter().find(|r| r.regex.is_match(model)) {
+            Some(r) if !r.split.is_empty() => {
+                let names = r.chain(Some(self.draw(&r.split)));
+                tracing::debug!(model, provider = names[0], "weighted route");
+                names
             }
+            Some(r) => r.targets(),
+            None => vec![&self.default_provider],
         }
-        &self.default_provider
     }
 
-    /// Select a chat provider for the given model.
-    pub fn select_chat(
+    /// The first target of one dispatch of `model` that is not in `drained`.
+    fn first_live<'a>(&'a self, model: &str, drained: &HashSet<String>) -> CoreResult<&'a str> {
+        let names = self.route(model);
+        names
+            .iter()
+            .find(|name| !drained.contains(**name))
+            .copied()
+            .ok_or_else(|| unavailable(names[0]))
+    }
+
+    /// The next number of the resolver's SplitMix64 sequence.
+    fn next_draw(&self) -> u64 {
+        let n = self.draws.fetch_add(1, Ordering::Relaxed);
+        let mut state = self
+            .seed
+            .wrapping_add(n.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+        crate::providers::hash::splitmix(&mut state)
+    }
+
+    /// Index of the arm of `split` the next draw lands on.
+    fn draw(&self, split: &[WeightedProvider]) -> usize {
+        let total: u64 = split.iter().map(|w| u64::from(w.weight)).sum();
+        let mut point = self.next_draw() % total;
+        for (i, arm) in split.iter().enumerate() {
+            let weight = u64::from(arm.weight);
+            if point < weight {
+                return i;
+            }
+            point -= weight;
+        }
+        unreachable!("the point is below the total weight")
+    }
+
+    /// Every provider routing can dispatch to: rule targets in order, then the default.
+    pub fn provider_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = Vec::new();
+        let all = self
+            .rules
+            .iter()
+            .flat_map(|r| r.targets())
+            .chain(std::iter::once(self.default_provider.as_str()));
+        for name in all {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    /// Rules (index and pattern) that match no model their provider lists in `listings`, e.g.
+    /// a pattern left over from a retired model family. Rules for providers with no listings
+    /// (no lister, or it failed) are not judged.
+    pub fn unmatched_rules(&self, listings: &[ModelInfo]) -> Vec<(usize, String)> {
+        self.rules
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| {
+                let mut listed = listings
+                    .iter()
+                    .filter(|m| r.targets().contains(&m.provider.as_str()));
+                listed.clone().next().is_some() && !listed.any(|m| r.regex.is_match(&m.id))
+            })
+            .map(|(i, r)| (i, r.regex.as_str().to_string()))
+            .collect()
+    }
+
+    /// Explain routing for `model` without dispatching: matched rule, chosen provider,
+    /// whether it advertises each of `required`, and the remaining candidates.
+    pub fn explain(
         &self,
         reg: &ProviderRegistry,
         model: &str,
+        required: &[Capability],
+    ) -> RouteExplanation {
+        let requested = model;
+        let model = self.resolve_alias(requested);
+        let mut matches = self
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.regex.is_match(model));
+        let first = matches.next();
+        let provider = first.map_or(self.default_provider.as_str(), |(_, r)| r.provider());
+        let caps = reg.caps(provider);
+        let mut fallbacks: Vec<String> = Vec::new();
+        let rest = first
+            .into_iter()
+            .flat_map(|(_, r)| r.targets().into_iter().skip(1))
+            .chain(matches.flat_map(|(_, r)| r.targets()))
+            .chain(std::iter::once(self.default_provider.as_str()));
+        for name in rest {
+            if name != provider && !fallbacks.iter().any(|f| f == name) {
+                fallbacks.push(name.to_string());
+            }
+        }
+        RouteExplanation {
+            model: requested.to_string(),
+            matched_rule: first.map(|(i, r)| (i, r.regex.as_str().to_string())),
+            provider: provider.to_string(),
+            registered: caps.is_some(),
+            checks: required
+                .iter()
+                .map(|c| {
+                    let model_supported = catalog::supports(model, *c);
+                    CapabilityCheck {
+                        capability: *c,
+                        supported: caps.is_some_and(|caps| caps.contains(c))
+                            && model_supported != Some(false),
+                        model_supported,
+                    }
+                })
+                .collect(),
+            fallbacks,
+            split: first.map_or(vec![], |(_, r)| r.split.clone()),
+            alias_of: (model != requested).then(|| model.to_string()),
+            shadow: first.and_then(|(_, r)| r.shadow.clone()),
+        }
+    }
+
+    /// Select a chat provider for the given model. A rule with several registered providers
+    /// yields a `FailoverProvider` over them; unregistered ones in a chain are skipped. A
+    /// registered `shadow` on the rule wraps the result in a `ShadowProvider`.
+    pub fn select_chat(
+        &self,
+        reg: &ProviderRegistry,
+        requested: &str,
     ) -> CoreResult<Arc<dyn ChatProvider>> {
-        let name = self.pick_provider_name(model);
-        reg.chat(name).ok_or_else(|| {
-            AiProxyError::Validation(format!(
-                "provider '{name}' not found or lacks chat capability"
-            ))
-        })
+        self.select_chat_excluding(reg, requested, &HashSet::new())
+    }
+
+    /// As `select_chat`, leaving out the providers in `drained`: a drawn split arm gives way
+    /// to the next target, a chain fails over among the rest, and a drained shadow is not
+    /// mirrored to. Fails as unavailable when every registered target is drained.
+    pub fn select_chat_excluding(
+        &self,
+        reg: &ProviderRegistry,
+        requested: &str,
+        drained: &HashSet<String>,
+    ) -> CoreResult<Arc<dyn ChatProvider>> {
+        let model = self.resolve_alias(requested);
+        check_model(model, Capability::Chat)?;
+        let names = self.route(model);
+        let mut chain: Vec<Arc<dyn ChatProvider>> = names
+            .iter()
+            .filter(|name| !drained.contains(**name))
+            .filter_map(|name| reg.chat(name))
+            .collect();
+        let mut selected: Arc<dyn ChatProvider> = match chain.len() {
+            0 => {
+                if let Some(name) = names.iter().find(|name| drained.contains(**name)) {
+                    return Err(unavailable(name));
+                }
+                return Err(AiProxyError::Validation(format!(
+                    "provider '{}' not found or lacks chat capability",
+                    names[0]
+                )));
+            }
+            1 => chain.remove(0),
+            _ => Arc::new(FailoverProvider::new(chain)),
+        };
+        #[cfg(feature = "native")]
+        if let Some(cfg) = self
+            .rules
+            .iter()
+            .find(|r| r.regex.is_match(model))
+            .and_then(|r| r.shadow.as_ref())
+            && !drained.contains(&cfg.provider)
+            && let Some(shadow) = reg.chat(&cfg.provider)
+        {
+            let mirrored = ShadowProvider::new(selected, shadow, cfg.percent, self.next_draw())
+                .with_model(cfg.model.clone());
+            selected = Arc::new(mirrored);
+        }
+        if model != requested {
+            selected = Arc::new(AliasProvider::new(selected, model));
+        }
+        Ok(selected)
     }
 
     /// Select an embed provider for the given model.
     pub fn select_embed(
         &self,
         reg: &ProviderRegistry,
-        model: &str,
+        requested: &str,
+    ) -> CoreResult<Arc<dyn EmbedProvider>> {
+        self.select_embed_excluding(reg, requested, &HashSet::new())
+    }
+
+    /// As `select_embed`, from the first of the route's targets not in `drained`.
+    pub fn select_embed_excluding(
+        &self,
+        reg: &ProviderRegistry,
+        requested: &str,
+        drained: &HashSet<String>,
     ) -> CoreResult<Arc<dyn EmbedProvider>> {
-        let name = self.pick_provider_name(model);
-        reg.embed(name).ok_or_else(|| {
+        let model = self.resolve_alias(requested);
+        check_model(model, Capability::Embed)?;
+        let name = self.first_live(model, drained)?;
+        let selected = reg.embed(name).ok_or_else(|| {
             AiProxyError::Validation(format!(
                 "provider '{name}' not found or lacks embed capability"
             ))
-        })
+        })?;
+        if model == requested {
+            return Ok(selected);
+        }
+        Ok(Arc::new(AliasProvider::new(selected, model)))
+    }
+
+    /// Select a realtime session provider for the given model.
+    pub fn select_realtime(
+        &self,
+        reg: &ProviderRegistry,
+        requested: &str,
+    ) -> CoreResult<Arc<dyn RealtimeProvider>> {
+        self.select_realtime_excluding(reg, requested, &HashSet::new())
+    }
+
+    /// As `select_realtime`, from the first of the route's targets not in `drained`.
+    pub fn select_realtime_excluding(
+        &self,
+        reg: &ProviderRegistry,
+        requested: &str,
+        drained: &HashSet<String>,
+    ) -> CoreResult<Arc<dyn RealtimeProvider>> {
+        let model = self.resolve_alias(requested);
+        check_model(model, Capability::Realtime)?;
+        let name = self.first_live(model, drained)?;
+        let selected = reg.realtime(name).ok_or_else(|| {
+            AiProxyError::Validation(format!(
+                "provider '{name}' not found or lacks realtime capability"
+            ))
+        })?;
+        if model == requested {
+            return Ok(selected);
+        }
+        Ok(Arc::new(AliasProvider::new(selected, model)))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::config::{CacheCfg, FsyncPolicy, HttpCfg, Providers, RoutingCfg, TranscriptCfg};
+    use crate::config::{
+        CacheCfg, CatalogCfg, ChaosCfg, FsyncPolicy, HttpCfg, PrivacyCfg, Providers, RoutingCfg,
+        ScreeningCfg, SummarizationCfg, TranscriptCfg, TruncationCfg, ValidationCfg,
+    };
+    use crate::test_util::Seen;
     use secrecy::SecretString;
 
     fn cfg_with_rules(default: &str, rules: Vec<(&str, &str)>) -> Config {
@@ -91,6 +515,9 @@ mod tests {
             .map(|(model, provider)| RoutingRule {
                 model: model.into(),
                 provider: provider.into(),
+                providers: vec![],
+                split: vec![],
+                shadow: None,
             })
             .collect::<Vec<_>>();
         Config {
@@ -98,10 +525,13 @@ mod tests {
                 openai: None,
                 anthropic: None,
                 openrouter: None,
+                hash: None,
+                ollama: None,
+                replay: None,
             },
             cache: CacheCfg {
                 path: ":memory:".into(),
-                ttl_seconds: 60,
+                ttl_seconds: if cfg!(feature = "native") { 60 } else { 0 },
             },
             transcript: TranscriptCfg {
                 dir: ".tx".into(),
@@ -112,8 +542,21 @@ mod tests {
             routing: RoutingCfg {
                 default: default.into(),
                 rules: compiled_rules,
+                aliases: Default::default(),
             },
             http: HttpCfg::default(),
+            privacy: PrivacyCfg::default(),
+            truncation: TruncationCfg::default(),
+            screening: ScreeningCfg::default(),
+            guardrail: Default::default(),
+            summarization: SummarizationCfg::default(),
+            validation: ValidationCfg::default(),
+            embeddings: Default::default(),
+            dedup: Default::default(),
+            catalog: CatalogCfg::default(),
+            pricing: Default::default(),
+            chaos: ChaosCfg::default(),
+            tenants: Default::default(),
         }
     }
 
@@ -174,6 +617,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn routing_round_trips_through_config_form() {
+        let cfg = cfg_with_rules("null", vec![("^gpt-.*", "openai")]);
+        let router = RoutingResolver::new(&cfg).unwrap();
+        assert_eq!(router.routing(), cfg.routing);
+        let rebuilt = RoutingResolver::from_routing(&router.routing()).unwrap();
+        assert_eq!(rebuilt.pick_provider_name("gpt-4o"), "openai");
+    }
+
+    #[test]
+    fn provider_names_lists_each_target_once() {
+        let cfg = cfg_with_rules(
+            "null",
+            vec![("^a", "other"), ("^b", "null"), ("^c", "other")],
+        );
+        let router = RoutingResolver::new(&cfg).unwrap();
+        assert_eq!(router.provider_names(), vec!["other", "null"]);
+    }
+
+    #[test]
+    fn unmatched_rules_flags_patterns_no_listed_model_matches() {
+        let cfg = cfg_with_rules(
+            "null",
+            vec![
+                ("^gpt-", "openai"),
+                ("^claude-2", "anthropic"),
+                ("^x-", "other"),
+            ],
+        );
+        let router = RoutingResolver::new(&cfg).unwrap();
+        let listed = |id: &str, provider: &str| ModelInfo {
+            id: id.into(),
+            provider: provider.into(),
+            owned_by: None,
+            context_window: None,
+            max_output_tokens: None,
+        };
+        let listings = [
+            listed("gpt-4o", "openai"),
+            listed("claude-sonnet-4", "anthropic"),
+        ];
+        // "other" lists nothing, so its rule is not judged.
+        assert_eq!(
+            router.unmatched_rules(&listings),
+            vec![(1, "^claude-2".to_string())]
+        );
+    }
+
     #[test]
     fn first_match_wins_rule_order() {
         // Two rules could match; ensure first in list wins
@@ -186,6 +677,254 @@ mod tests {
         assert_eq!(chat.name(), "null"); // proves first rule took precedence over later more-specific rule
     }
 
+    #[test]
+    fn explain_reports_rule_checks_and_fallbacks() {
+        let cfg = cfg_with_rules(
+            "null",
+            vec![("^claude-", "missing"), ("^claude-3", "other")],
+        );
+        let reg = ProviderRegistry::from_config(&cfg).expect("should build provider registry");
+        let router = RoutingResolver::new(&cfg).expect("should build routing resolver");
+
+        let ex = router.explain(&reg, "claude-3-opus", &[Capability::Chat]);
+        assert_eq!(ex.matched_rule, Some((0, "^claude-".to_string())));
+        assert_eq!(ex.provider, "missing");
+        assert!(!ex.registered);
+        assert!(!ex.routable());
+        assert_eq!(ex.fallbacks, vec!["other", "null"]);
+
+        let ex = router.explain(&reg, "gpt-4o", &[Capability::Chat, Capability::Tools]);
+        assert_eq!(ex.matched_rule, None);
+        assert_eq!(ex.provider, "null");
+        assert!(ex.checks[0].supported);
+        assert!(!ex.checks[1].supported);
+        assert_eq!(ex.checks[1].model_supported, Some(true));
+        assert!(ex.fallbacks.is_empty());
+
+        // The null provider embeds, but the catalog knows this model cannot chat.
+        let ex = router.explain(&reg, "text-embedding-3-small", &[Capability::Chat]);
+        assert_eq!(ex.checks[0].model_supported, Some(false));
+        assert!(!ex.routable());
+        let err = router
+            .select_chat(&reg, "text-embedding-3-small")
+            .unwrap_err();
+        assert!(err.to_string().contains("does not support chat"));
+        assert!(router.select_embed(&reg, "text-embedding-3-small").is_ok());
+        let err = router
+            .select_realtime(&reg, "gpt-4o-realtime-preview")
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("null' not found or lacks realtime")
+        );
+    }
+
+    #[tokio::test]
+    async fn provider_chains_fail_over_and_skip_unregistered_providers() {
+        use crate::config::FaultCfg;
+        use crate::provider::NullProvider;
+        use crate::providers::chaos::ChaosProvider;
+
+        let mut cfg = cfg_with_rules("null", vec![]);
+        cfg.routing.rules = vec![RoutingRule {
+            model: "^gpt-".into(),
+            provider: "flaky".into(),
+            providers: vec!["missing".into(), "null".into()],
+            split: vec![],
+            shadow: None,
+        }];
+        let mut reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let flaky = ChaosProvider::new(
+            Arc::new(NullProvider) as Arc<dyn ChatProvider>,
+            FaultCfg {
+                rate_limit: 1.0,
+                ..FaultCfg::default()
+            },
+        );
+        reg.register_chat("flaky", Arc::new(flaky), &[Capability::Chat]);
+        let router = RoutingResolver::new(&cfg).unwrap();
+
+        assert_eq!(
+            router.pick_provider_names("gpt-4o"),
+            ["flaky", "missing", "null"]
+        );
+        let chat = router.select_chat(&reg, "gpt-4o").unwrap();
+        let req = crate::model::ChatRequest::builder()
+            .model("gpt-4o")
+            .user("hi")
+            .build();
+        assert_eq!(chat.chat(req).await.unwrap().provider, "null");
+        assert_eq!(
+            router.explain(&reg, "gpt-4o", &[]).fallbacks,
+            ["missing", "null"]
+        );
+        // A drained primary is left out of the chain, so its fallbacks serve the request.
+        let drained: HashSet<String> = ["flaky".to_string()].into();
+        let chat = router
+            .select_chat_excluding(&reg, "gpt-4o", &drained)
+            .unwrap();
+        assert_eq!(chat.name(), "null");
+        let drained: HashSet<String> = ["null".to_string()].into();
+        let embed = router.select_embed_excluding(&reg, "text-embedding-3-small", &drained);
+        assert!(matches!(
+            embed,
+            Err(AiProxyError::ProviderUnavailable { .. })
+        ));
+        let targets: Vec<String> = router.routing().rules[0]
+            .targets()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(targets, ["flaky", "missing", "null"]);
+    }
+
+    #[test]
+    fn weighted_splits_draw_in_proportion_and_repeat_per_seed() {
+        /// `NullProvider` under its own name.
+        #[derive(Debug)]
+        struct Named(&'static str);
+
+        #[async_trait::async_trait]
+        impl ChatProvider for Named {
+            fn name(&self) -> &str {
+                self.0
+            }
+
+            async fn chat(
+                &self,
+                req: crate::model::ChatRequest,
+            ) -> CoreResult<crate::model::ChatResponse> {
+                crate::provider::NullProvider.chat(req).await
+            }
+        }
+
+        let mut cfg = cfg_with_rules("null", vec![]);
+        let split = vec![
+            WeightedProvider {
+                provider: "b".into(),
+                weight: 10,
+            },
+            WeightedProvider {
+                provider: "a".into(),
+                weight: 90,
+            },
+        ];
+        cfg.routing.rules = vec![RoutingRule {
+            model: "^gpt-".into(),
+            provider: String::new(),
+            providers: vec![],
+            split: split.clone(),
+            shadow: None,
+        }];
+        let mut reg = ProviderRegistry::from_config(&cfg).unwrap();
+        for name in ["a", "b"] {
+            reg.register_chat(name, Arc::new(Named(name)), &[Capability::Chat]);
+        }
+        let picks = |seed: u64| -> Vec<String> {
+            let router = RoutingResolver::new(&cfg).unwrap().with_seed(seed);
+            (0..1000)
+                .map(|_| {
+                    router
+                        .select_chat(&reg, "gpt-4o")
+                        .unwrap()
+                        .name()
+                        .to_string()
+                })
+                .collect()
+        };
+        let first = picks(7);
+        let to_a = first.iter().filter(|p| *p == "a").count();
+        assert!((850..=950).contains(&to_a), "{to_a} of 1000 went to a");
+        assert_eq!(picks(7), first);
+        assert_ne!(picks(8), first);
+
+        let router = RoutingResolver::new(&cfg).unwrap();
+        assert_eq!(router.pick_provider_names("gpt-4o"), ["a", "b"]);
+        let ex = router.explain(&reg, "gpt-4o", &[]);
+        assert_eq!(ex.provider, "a");
+        assert_eq!(ex.split, split);
+        assert_eq!(ex.fallbacks, ["b", "null"]);
+        assert_eq!(router.routing().rules[0].split, split);
+
+        // A drained arm gives way to the others, whichever one is drawn.
+        let drained: HashSet<String> = ["a".to_string()].into();
+        for _ in 0..20 {
+            let p = router.select_chat_excluding(&reg, "gpt-4o", &drained);
+            assert_eq!(p.unwrap().name(), "b");
+        }
+        let drained: HashSet<String> = ["a".to_string(), "b".to_string()].into();
+        let err = router
+            .select_chat_excluding(&reg, "gpt-4o", &drained)
+            .unwrap_err();
+        assert!(
+            matches!(err, AiProxyError::ProviderUnavailable { provider, .. } if provider == "a")
+        );
+    }
+
+    #[cfg(feature = "native")]
+    #[tokio::test]
+    async fn shadowed_rules_mirror_chats_to_the_shadow_provider() {
+        let mut cfg = cfg_with_rules("null", vec![]);
+        let shadow = ShadowCfg {
+            provider: "seen".into(),
+            model: Some("gpt-next".into()),
+            percent: 100,
+        };
+        cfg.routing.rules = vec![RoutingRule {
+            model: "^gpt-".into(),
+            provider: "null".into(),
+            providers: vec![],
+            split: vec![],
+            shadow: Some(shadow.clone()),
+        }];
+        let mut reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let seen = Arc::new(Seen::default());
+        reg.register_chat("seen", seen.clone(), &[Capability::Chat]);
+        let router = RoutingResolver::new(&cfg).unwrap();
+
+        let p = router.select_chat(&reg, "gpt-4o").unwrap();
+        assert_eq!(p.name(), "null");
+        let req = crate::model::ChatRequest::builder()
+            .model("gpt-4o")
+            .user("hi")
+            .build();
+        assert_eq!(p.chat(req).await.unwrap().provider, "null");
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert_eq!(seen.models(), ["gpt-next"]);
+
+        assert_eq!(
+            router.explain(&reg, "gpt-4o", &[]).shadow,
+            Some(shadow.clone())
+        );
+        assert_eq!(router.routing().rules[0].shadow, Some(shadow));
+    }
+
+    #[tokio::test]
+    async fn aliases_are_routed_and_sent_as_their_model() {
+        let mut cfg = cfg_with_rules("null", vec![("^gpt-4o-mini$", "seen")]);
+        cfg.routing.aliases = [("fast".to_string(), "gpt-4o-mini".to_string())].into();
+        let mut reg = ProviderRegistry::from_config(&cfg).unwrap();
+        let seen = Arc::new(Seen::default());
+        reg.register_chat("seen", seen.clone(), &[Capability::Chat]);
+        let router = RoutingResolver::new(&cfg).unwrap();
+
+        assert_eq!(router.pick_provider_name("fast"), "seen");
+        let p = router.select_chat(&reg, "fast").unwrap();
+        assert_eq!(p.name(), "seen");
+        let req = crate::model::ChatRequest::builder()
+            .model("fast")
+            .user("hi")
+            .build();
+        p.chat(req).await.unwrap();
+        assert_eq!(seen.models(), ["gpt-4o-mini"]);
+
+        let ex = router.explain(&reg, "fast", &[]);
+        assert_eq!(ex.model, "fast");
+        assert_eq!(ex.alias_of.as_deref(), Some("gpt-4o-mini"));
+        assert_eq!(ex.matched_rule.unwrap().0, 0);
+        assert_eq!(router.explain(&reg, "gpt-4o-mini", &[]).alias_of, None);
+        assert_eq!(router.routing().aliases, cfg.routing.aliases);
+    }
+
     #[tokio::test]
     async fn router_selects_openai_and_calls_chat() {
         use crate::providers::openai::OpenAI;
@@ -221,22 +960,10 @@ mod tests {
         let reg = ProviderRegistry::with_openai_for_tests(oi);
 
         let chat = router.select_chat(&reg, "gpt-4o").expect("chat provider");
-        let req = crate::model::ChatRequest {
-            model: "gpt-4o".into(),
-            messages: vec![crate::model::ChatMessage {
-                role: crate::model::Role::User,
-                content: "ping".into(),
-            }],
-            temperature: None,
-            top_p: None,
-            metadata: None,
-            client_key: None,
-            request_id: None,
-            trace_id: None,
-            idempotency_key: None,
-            max_output_tokens: None,
-            stop_sequences: None,
-        };
+        let req = crate::model::ChatRequest::builder()
+            .model("gpt-4o")
+            .user("ping")
+            .build();
 
         let resp = chat.chat(req).await.expect("chat resp");
         assert_eq!(resp.text, "pong");
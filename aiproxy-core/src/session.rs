@@ -0,0 +1,315 @@
+//! High-level multi-turn chat: a `ChatSession` owns a `Conversation`, keeps it within a
+//! trimming policy, optionally summarizes older turns, dispatches each turn through the
+//! router and appends the reply, so library users only supply the next user message.
+//!
+//! A session can also be persisted under the transcript directory, one message per NDJSON
+//! line in `sessions/<id>.ndjson`. The file is an append-only log of every message sent and
+//! received; trimming and summaries only shape what is sent upstream, so reopening a session
+//! replays its full history.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::config::{FsyncPolicy, SummarizationCfg, TranscriptCfg};
+use crate::conversation::Conversation;
+use crate::error::{AiProxyError, CoreResult};
+use crate::model::{ChatMessage, ChatResponse, MessageContent};
+use crate::provider_factory::ProviderRegistry;
+use crate::router::RoutingResolver;
+use crate::summarize;
+use crate::telemetry::access::redact;
+
+/// How much history a session sends with each turn.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TrimPolicy {
+    /// Send everything.
+    #[default]
+    Keep,
+    /// At most the last `n` turns (see `Conversation::trim_to_turns`).
+    Turns(usize),
+    /// At most this many tokens, approximately (see `Conversation::trim_to_tokens`).
+    Tokens(usize),
+}
+
+/// Where a persisted session appends its messages.
+#[derive(Debug)]
+struct SessionLog {
+    path: PathBuf,
+    file: File,
+    fsync: FsyncPolicy,
+    redact: bool,
+}
+
+impl SessionLog {
+    fn append(&mut self, messages: &[ChatMessage]) -> CoreResult<()> {
+        for m in messages {
+            let mut m = m.clone();
+            if self.redact {
+                m.content = MessageContent::Text(redact(&m.content.text()));
+            }
+            let mut line = serde_json::to_vec(&m).map_err(|e| AiProxyError::Other(e.into()))?;
+            line.push(b'\n');
+            self.file.write_all(&line)?;
+            if self.fsync == FsyncPolicy::Always {
+                self.file.sync_data()?;
+            }
+        }
+        if self.fsync == FsyncPolicy::Commit {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+fn read_log(path: &Path) -> CoreResult<Vec<ChatMessage>> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut messages = Vec::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        messages.push(
+            serde_json::from_str(&line).map_err(|e| {
+                AiProxyError::Validation(format!("{}:{}: {e}", path.display(), i + 1))
+            })?,
+        );
+    }
+    Ok(messages)
+}
+
+/// A multi-turn chat with one model.
+pub struct ChatSession {
+    registry: Arc<ProviderRegistry>,
+    router: Arc<RoutingResolver>,
+    model: String,
+    conversation: Conversation,
+    trim: TrimPolicy,
+    summarization: Option<SummarizationCfg>,
+    log: Option<SessionLog>,
+}
+
+impl ChatSession {
+    pub fn new(
+        registry: Arc<ProviderRegistry>,
+        router: Arc<RoutingResolver>,
+        model: impl Into<String>,
+    ) -> Self {
+        Self {
+            registry,
+            router,
+            model: model.into(),
+            conversation: Conversation::new(),
+            trim: TrimPolicy::Keep,
+            summarization: None,
+            log: None,
+        }
+    }
+
+    pub fn with_system(mut self, content: impl Into<MessageContent>) -> Self {
+        self.conversation = self.conversation.with_system(content);
+        self
+    }
+
+    /// Start from existing history instead of an empty conversation.
+    pub fn with_conversation(mut self, conversation: Conversation) -> Self {
+        self.conversation = conversation;
+        self
+    }
+
+    pub fn with_trim(mut self, trim: TrimPolicy) -> Self {
+        self.trim = trim;
+        self
+    }
+
+    /// Summarize older turns once the prompt passes `cfg.threshold_tokens`, using
+    /// `cfg.model` routed like any other model. Ignored when `cfg.model` is unset.
+    pub fn with_summarization(mut self, cfg: SummarizationCfg) -> Self {
+        self.summarization = Some(cfg);
+        self
+    }
+
+    /// Persist the session as `<cfg.dir>/sessions/<id>.ndjson`. If the file already has
+    /// messages they replace the current history, so reopening an id resumes it; otherwise
+    /// the current history (e.g. the system prompt) is written first.
+    pub fn persist(mut self, cfg: &TranscriptCfg, id: &str) -> CoreResult<Self> {
+        if id.is_empty() || id.contains(['/', '\\']) || id.starts_with('.') {
+            return Err(AiProxyError::Validation(format!(
+                "invalid session id '{id}'"
+            )));
+        }
+        let dir = Path::new(&cfg.dir).join("sessions");
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{id}.ndjson"));
+        let saved = read_log(&path)?;
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut log = SessionLog {
+            path,
+            file,
+            fsync: cfg.fsync.clone(),
+            redact: cfg.redact_builtin,
+        };
+        if saved.is_empty() {
+            let current: Vec<ChatMessage> = self.conversation.messages().cloned().collect();
+            log.append(&current)?;
+        } else {
+            self.conversation = Conversation::from(saved);
+        }
+        self.log = Some(log);
+        Ok(self)
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// Switch models for later turns; the history carries over.
+    pub fn set_model(&mut self, model: impl Into<String>) {
+        self.model = model.into();
+    }
+
+    /// History as it will be sent next, after any trimming and summaries so far.
+    pub fn conversation(&self) -> &Conversation {
+        &self.conversation
+    }
+
+    /// The file the session is persisted to, if any.
+    pub fn transcript_path(&self) -> Option<&Path> {
+        self.log.as_ref().map(|l| l.path.as_path())
+    }
+
+    /// Send `content` as the next user turn and append the reply. On error the history is
+    /// left without the failed turn, so the call can simply be retried.
+    pub async fn send(&mut self, content: impl Into<MessageContent>) -> CoreResult<ChatResponse> {
+        let user = ChatMessage::user(content);
+        let before = self.conversation.clone();
+        self.conversation.push(user.clone());
+        match self.dispatch().await {
+            Ok(resp) => {
+                let reply = resp.to_message();
+                self.conversation.push(reply.clone());
+                if let Some(log) = &mut self.log {
+                    log.append(&[user, reply])?;
+                }
+                Ok(resp)
+            }
+            Err(e) => {
+                self.conversation = before;
+                Err(e)
+            }
+        }
+    }
+
+    async fn dispatch(&mut self) -> CoreResult<ChatResponse> {
+        match self.trim {
+            TrimPolicy::Keep => {}
+            TrimPolicy::Turns(n) => {
+                self.conversation.trim_to_turns(n);
+            }
+            TrimPolicy::Tokens(n) => {
+                self.conversation.trim_to_tokens(n);
+            }
+        }
+        let mut req = self.conversation.request(self.model.clone()).build();
+        if let Some(cfg) = &self.summarization
+            && let Some(model) = &cfg.model
+        {
+            let summarizer = self.router.select_chat(&self.registry, model)?;
+            if summarize::compress_history(&mut req, cfg, summarizer.as_ref())
+                .await?
+                .is_some()
+            {
+                self.conversation = Conversation::from(req.messages.clone());
+            }
+        }
+        let provider = self.router.select_chat(&self.registry, &self.model)?;
+        provider.chat(req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Role;
+
+    fn session() -> ChatSession {
+        let cfg = crate::test_util::minimal_config();
+        ChatSession::new(
+            Arc::new(ProviderRegistry::from_config_with_keys(&cfg, |_| None).unwrap()),
+            Arc::new(RoutingResolver::new(&cfg).unwrap()),
+            "m",
+        )
+    }
+
+    fn transcript(dir: &Path, redact_builtin: bool) -> TranscriptCfg {
+        TranscriptCfg {
+            dir: dir.to_string_lossy().into_owned(),
+            segment_mb: 64,
+            fsync: FsyncPolicy::Commit,
+            redact_builtin,
+        }
+    }
+
+    #[tokio::test]
+    async fn turns_are_trimmed_dispatched_and_appended() {
+        let mut s = session()
+            .with_system("be terse")
+            .with_trim(TrimPolicy::Turns(3));
+        for prompt in ["one", "two", "three"] {
+            let resp = s.send(prompt).await.unwrap();
+            assert_eq!(resp.provider, "null");
+        }
+        // Before the third turn, five turns were cut to three and restarted on "two".
+        let roles: Vec<Role> = s.conversation().messages().map(|m| m.role).collect();
+        assert_eq!(
+            roles,
+            [
+                Role::System,
+                Role::User,
+                Role::Assistant,
+                Role::User,
+                Role::Assistant
+            ]
+        );
+        assert_eq!(s.conversation().turns[0].content, "two");
+
+        s.set_model("text-embedding-3-small");
+        assert!(s.send("four").await.is_err());
+        assert_eq!(s.conversation().turns.len(), 4, "failed turn rolled back");
+    }
+
+    #[tokio::test]
+    async fn persisted_sessions_resume_from_their_transcript() {
+        let dir = tempfile::tempdir().unwrap();
+        let cfg = transcript(dir.path(), true);
+        let mut s = session()
+            .with_system("be terse")
+            .persist(&cfg, "chat-1")
+            .unwrap();
+        s.send("my key is sk-abcdefghijklmnop").await.unwrap();
+        let path = s.transcript_path().unwrap().to_path_buf();
+        assert!(path.ends_with("sessions/chat-1.ndjson"));
+        assert!(
+            !std::fs::read_to_string(&path)
+                .unwrap()
+                .contains("sk-abcdefghijklmnop")
+        );
+
+        let mut resumed = session()
+            .with_system("ignored")
+            .persist(&cfg, "chat-1")
+            .unwrap();
+        assert_eq!(resumed.conversation().len(), 3);
+        assert_eq!(resumed.conversation().system[0].content, "be terse");
+        resumed.send("again").await.unwrap();
+        assert_eq!(read_log(&path).unwrap().len(), 5);
+
+        assert!(session().persist(&cfg, "../escape").is_err());
+    }
+}
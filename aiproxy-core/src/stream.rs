@@ -1,14 +1,20 @@
 //! Streaming primitives exposed by ai-proxy.
 //!
 //! Contract:
-//! - Providers may emit 0..n `DeltaText` events followed by an optional `Usage` update.
+//! - Providers may emit 0..n `DeltaText` and `ToolCall` events followed by an optional `Usage`
+//!   update.
 //! - The stream **must** terminate with exactly one terminal event: `Stop`, `Final`, or `Error`.
 //! - After a terminal event, no further events are emitted.
 //!
 //! This module intentionally avoids deriving `Clone` / `PartialEq` because `Error` contains
 //! `AiProxyError`, which is not (and should not be) `Clone` or `Eq`.
 
+use crate::platform::{Instant, MaybeSend, SystemTime, UNIX_EPOCH};
+
 /// What the caller receives incrementally.
+// `Final` dwarfs the other variants, but it is sent once per stream; boxing it would only
+// churn every match on the public enum.
+#[allow(clippy::large_enum_variant)]
 #[non_exhaustive]
 #[derive(Debug)]
 pub enum StreamEvent {
@@ -19,6 +25,8 @@ pub enum StreamEvent {
         prompt: Option<u32>,
         completion: Option<u32>,
     },
+    /// A complete tool call; sent once its arguments have fully arrived.
+    ToolCall(crate::model::ToolCall),
     /// Provider has decided to stop (with reason).
     Stop {
         reason: Option<crate::model::StopReason>,
@@ -42,10 +50,172 @@ impl StreamEvent {
             _ => None,
         }
     }
+
+    /// NDJSON form of the event, tagged by `type`.
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::json;
+        match self {
+            Self::DeltaText(text) => json!({"type": "delta", "text": text}),
+            Self::Usage { prompt, completion } => {
+                json!({"type": "usage", "prompt": prompt, "completion": completion})
+            }
+            Self::ToolCall(call) => json!({"type": "tool_call", "call": call}),
+            Self::Stop { reason } => json!({"type": "stop", "reason": reason}),
+            Self::Final(resp) => json!({"type": "final", "response": resp}),
+            Self::Error(e) => json!({"type": "error", "message": e.to_string()}),
+        }
+    }
+}
+
+/// Archives a stream as NDJSON: one `StreamEvent::to_json` line per event, stamped with
+/// `ts_ms` (Unix milliseconds) and `elapsed_ms` (since the recorder was created), so a
+/// session can be replayed or timed afterwards.
+#[derive(Debug)]
+pub struct StreamRecorder<W: std::io::Write> {
+    out: W,
+    started: Instant,
+}
+
+impl StreamRecorder<std::io::BufWriter<std::fs::File>> {
+    /// Record to a new file at `path`, replacing any existing one.
+    pub fn create(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        Ok(Self::new(std::io::BufWriter::new(file)))
+    }
+}
+
+impl<W: std::io::Write> StreamRecorder<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            started: Instant::now(),
+        }
+    }
+
+    /// Append `ev`. Terminal events are flushed, so a finished stream is on disk even if
+    /// the recorder is never `finish`ed.
+    pub fn record(&mut self, ev: &StreamEvent) -> std::io::Result<()> {
+        let ts_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis() as u64);
+        let mut line = ev.to_json();
+        line["ts_ms"] = ts_ms.into();
+        line["elapsed_ms"] = (self.started.elapsed().as_millis() as u64).into();
+        serde_json::to_writer(&mut self.out, &line)?;
+        self.out.write_all(b"\n")?;
+        if ev.is_terminal() {
+            self.out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Flush and hand back the writer.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
 }
 
-/// Boxed stream of streaming events. Providers that support streaming return this.
+/// Boxed stream of streaming events. Providers that support streaming return this. It is
+/// `Send` except on wasm32, where `fetch` bodies are not (see `platform`).
+#[cfg(not(target_arch = "wasm32"))]
 pub type BoxStreamEv = futures::stream::BoxStream<'static, StreamEvent>;
+#[cfg(target_arch = "wasm32")]
+pub type BoxStreamEv = futures::stream::LocalBoxStream<'static, StreamEvent>;
+
+/// Cancels a stream wrapped by `cancellable`. Cheap to clone and safe to use from any task.
+#[derive(Debug, Clone)]
+pub struct CancelHandle(futures::stream::AbortHandle);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.abort();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.is_aborted()
+    }
+}
+
+/// Pair `events` with the future that feeds them, e.g. an SSE bridge writing into a channel.
+///
+/// With the `native` feature the feeder is spawned onto the Tokio runtime. Without it there
+/// may be no runtime to spawn on (wasm32), so the feeder is polled as part of the returned
+/// stream and stops when the stream is dropped.
+pub(crate) fn with_feeder<F>(feeder: F, events: BoxStreamEv) -> BoxStreamEv
+where
+    F: std::future::Future<Output = ()> + MaybeSend + 'static,
+{
+    #[cfg(feature = "native")]
+    {
+        tokio::spawn(feeder);
+        events
+    }
+    #[cfg(not(feature = "native"))]
+    {
+        use futures::StreamExt;
+        let feeder = futures::stream::once(feeder).filter_map(|()| std::future::ready(None));
+        Box::pin(futures::stream::select(events, feeder))
+    }
+}
+
+/// Wrap a provider stream so it can be cancelled mid-flight.
+///
+/// On cancel the inner stream is dropped (aborting the underlying request), a completion
+/// log marked `cancelled` with the partial text is emitted, and the stream ends with
+/// `Stop { reason: Some(StopReason::Cancelled) }` so the terminal-event contract holds.
+pub fn cancellable(inner: BoxStreamEv, provider: &str, model: &str) -> (CancelHandle, BoxStreamEv) {
+    use futures::StreamExt;
+    use futures::stream::{AbortHandle, Abortable};
+
+    let (abort, registration) = AbortHandle::new_pair();
+    let handle = CancelHandle(abort);
+    let started = Instant::now();
+    let (provider, model) = (provider.to_string(), model.to_string());
+    let state = (
+        Abortable::new(inner, registration),
+        handle.clone(),
+        String::new(),
+        false,
+    );
+    let events = futures::stream::unfold(state, move |(mut events, handle, mut text, done)| {
+        let (provider, model) = (provider.clone(), model.clone());
+        async move {
+            if done {
+                return None;
+            }
+            match events.next().await {
+                Some(ev) => {
+                    if let Some(t) = ev.as_text_delta() {
+                        text.push_str(t);
+                    }
+                    let terminal = ev.is_terminal();
+                    Some((ev, (events, handle, text, terminal)))
+                }
+                None if handle.is_cancelled() => {
+                    let now_ms = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |d| d.as_millis() as u64);
+                    let clog = crate::telemetry::CompletionLog::new()
+                        .provider(&provider)
+                        .model(&model)
+                        .created_at_ms(now_ms)
+                        .latency_ms(started.elapsed().as_millis() as u64)
+                        .stop_reason_opt(Some("cancelled"))
+                        .error_kind_opt(Some("cancelled"))
+                        .text_opt(Some(&text));
+                    crate::telemetry::emit_completion(clog);
+                    let stop = StreamEvent::Stop {
+                        reason: Some(crate::model::StopReason::Cancelled),
+                    };
+                    Some((stop, (events, handle, text, true)))
+                }
+                None => None,
+            }
+        }
+    });
+    (handle, Box::pin(events))
+}
 
 #[cfg(test)]
 mod tests {
@@ -61,4 +231,96 @@ mod tests {
         assert!(s.is_terminal());
         assert_eq!(s.as_text_delta(), None);
     }
+
+    #[test]
+    fn events_are_tagged_by_type() {
+        use serde_json::json;
+
+        assert_eq!(
+            StreamEvent::DeltaText("hi".into()).to_json(),
+            json!({"type": "delta", "text": "hi"})
+        );
+        let stop = StreamEvent::Stop {
+            reason: Some(crate::model::StopReason::EndTurn),
+        };
+        assert_eq!(
+            stop.to_json(),
+            json!({"type": "stop", "reason": "end_turn"})
+        );
+        let err = StreamEvent::Error(crate::error::AiProxyError::Validation("bad".into()));
+        assert_eq!(err.to_json()["type"], "error");
+        assert!(err.to_json()["message"].as_str().unwrap().contains("bad"));
+    }
+
+    #[test]
+    fn recorder_writes_timestamped_ndjson() {
+        let mut recorder = StreamRecorder::new(Vec::new());
+        recorder
+            .record(&StreamEvent::DeltaText("a".into()))
+            .unwrap();
+        recorder
+            .record(&StreamEvent::Stop { reason: None })
+            .unwrap();
+        let out = String::from_utf8(recorder.finish().unwrap()).unwrap();
+        let lines: Vec<serde_json::Value> = out
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["type"], "delta");
+        assert_eq!(lines[0]["text"], "a");
+        assert_eq!(lines[1]["type"], "stop");
+        assert!(lines[1]["ts_ms"].as_u64().unwrap() > 0);
+        assert!(lines[1]["elapsed_ms"].as_u64() >= lines[0]["elapsed_ms"].as_u64());
+    }
+
+    #[tokio::test]
+    async fn cancel_ends_stream_with_cancelled_stop() {
+        use futures::StreamExt;
+
+        let inner = futures::stream::iter([StreamEvent::DeltaText("par".into())])
+            .chain(futures::stream::pending())
+            .boxed();
+        let (handle, mut events) = cancellable(inner, "p", "m");
+        assert_eq!(events.next().await.unwrap().as_text_delta(), Some("par"));
+        handle.cancel();
+        match events.next().await {
+            Some(StreamEvent::Stop { reason }) => {
+                assert_eq!(reason, Some(crate::model::StopReason::Cancelled))
+            }
+            other => panic!("expected cancelled stop, got {other:?}"),
+        }
+        assert!(events.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn uncancelled_stream_passes_through() {
+        use futures::StreamExt;
+
+        let inner = futures::stream::iter([
+            StreamEvent::DeltaText("a".into()),
+            StreamEvent::Stop { reason: None },
+        ])
+        .boxed();
+        let (handle, events) = cancellable(inner, "p", "m");
+        assert_eq!(events.count().await, 2);
+        assert!(!handle.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn fed_stream_yields_everything_the_feeder_sends() {
+        use futures::{SinkExt, StreamExt};
+
+        let (mut tx, rx) = futures::channel::mpsc::channel(1);
+        let feeder = async move {
+            for t in ["a", "b", "c"] {
+                tx.send(StreamEvent::DeltaText(t.into())).await.unwrap();
+            }
+            tx.send(StreamEvent::Stop { reason: None }).await.unwrap();
+        };
+        let events: Vec<StreamEvent> = with_feeder(feeder, rx.boxed()).collect().await;
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[2].as_text_delta(), Some("c"));
+        assert!(events[3].is_terminal());
+    }
 }
@@ -0,0 +1,244 @@
+//! Typed structured output: `chat_typed::<T>` asks for a reply matching `T`'s JSON Schema,
+//! parses it into `T`, and on a parse failure shows the model its reply and the error and asks
+//! again.
+//!
+//! Providers with native structured outputs (`ChatProvider::supports_response_schema`) get the
+//! schema as `ChatRequest.response_schema`; the rest get it in a system message. Either way
+//! the reply is parsed here, since a native constraint can still be cut off by the token limit.
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::error::{AiProxyError, CoreResult, Upstream};
+use crate::model::{ChatMessage, ChatRequest, ChatResponse, ResponseSchema, Role};
+use crate::provider::ChatProvider;
+
+/// Retries `chat_typed` allows after the first attempt.
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// A type that can describe itself as a JSON Schema. Implement it by hand, or forward to a
+/// schema generator such as `schemars`.
+pub trait JsonSchema {
+    /// Name sent alongside the schema, e.g. `"WeatherReport"`.
+    fn schema_name() -> String;
+    fn json_schema() -> Value;
+}
+
+/// A parsed reply and the response it came from.
+#[derive(Debug, Clone)]
+pub struct Typed<T> {
+    pub value: T,
+    /// The response `value` was parsed from (the last attempt's).
+    pub response: ChatResponse,
+    /// Calls made, 1 when the first reply parsed.
+    pub attempts: u32,
+}
+
+/// `chat_typed_with_retries` with `DEFAULT_RETRIES`.
+pub async fn chat_typed<T: DeserializeOwned + JsonSchema>(
+    provider: &dyn ChatProvider,
+    req: ChatRequest,
+) -> CoreResult<Typed<T>> {
+    chat_typed_with_retries(provider, req, DEFAULT_RETRIES).await
+}
+
+/// Send `req` asking for a `T`, retrying up to `retries` times while the reply fails to parse.
+/// Exhausted retries fail as a `ProviderError` with code `invalid_output`.
+pub async fn chat_typed_with_retries<T: DeserializeOwned + JsonSchema>(
+    provider: &dyn ChatProvider,
+    mut req: ChatRequest,
+    retries: u32,
+) -> CoreResult<Typed<T>> {
+    let name = T::schema_name();
+    let schema = T::json_schema();
+    if provider.supports_response_schema() {
+        req.response_schema = Some(ResponseSchema {
+            name: name.clone(),
+            schema,
+        });
+    } else {
+        let insert_at = req
+            .messages
+            .iter()
+            .take_while(|m| m.role == Role::System)
+            .count();
+        req.messages.insert(
+            insert_at,
+            ChatMessage::system(format!(
+                "Reply with only a JSON value matching this JSON Schema, without prose or \
+                 code fences:\n{schema}"
+            )),
+        );
+    }
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let response = provider.chat(req.clone()).await?;
+        let err = match parse_reply::<T>(&response.text) {
+            Ok(value) => {
+                return Ok(Typed {
+                    value,
+                    response,
+                    attempts,
+                });
+            }
+            Err(e) => e,
+        };
+        if attempts > retries {
+            return Err(AiProxyError::ProviderError {
+                provider: response.provider.clone(),
+                code: "invalid_output".into(),
+                message: format!(
+                    "reply did not parse as {name} after {attempts} attempt(s): {err}"
+                ),
+                upstream: Box::new(Upstream {
+                    model: Some(response.model),
+                    status: None,
+                    provider_request_id: response.provider_request_id,
+                }),
+            });
+        }
+        req.messages.push(response.to_message());
+        req.messages.push(ChatMessage::user(format!(
+            "That reply could not be parsed as {name}: {err}. Reply again with only the \
+             corrected JSON."
+        )));
+    }
+}
+
+/// Parse `text` as JSON, tolerating surrounding whitespace and a Markdown code fence.
+pub fn parse_reply<T: DeserializeOwned>(text: &str) -> Result<T, serde_json::Error> {
+    let mut body = text.trim();
+    if let Some(rest) = body.strip_prefix("```") {
+        let rest = rest.strip_prefix("json").unwrap_or(rest);
+        body = rest.strip_suffix("```").unwrap_or(rest).trim();
+    }
+    serde_json::from_str(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::NullProvider;
+    use async_trait::async_trait;
+    use serde::Deserialize;
+    use serde_json::json;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Weather {
+        city: String,
+        celsius: f32,
+    }
+
+    impl JsonSchema for Weather {
+        fn schema_name() -> String {
+            "Weather".into()
+        }
+
+        fn json_schema() -> Value {
+            json!({
+                "type": "object",
+                "properties": {"city": {"type": "string"}, "celsius": {"type": "number"}},
+                "required": ["city", "celsius"]
+            })
+        }
+    }
+
+    /// Replies from a script and remembers every request.
+    #[derive(Debug)]
+    struct Scripted {
+        native: bool,
+        replies: Mutex<Vec<&'static str>>,
+        seen: Mutex<Vec<ChatRequest>>,
+    }
+
+    impl Scripted {
+        fn new(native: bool, replies: &[&'static str]) -> Self {
+            Self {
+                native,
+                replies: Mutex::new(replies.iter().rev().copied().collect()),
+                seen: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ChatProvider for Scripted {
+        fn name(&self) -> &str {
+            "scripted"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            self.seen.lock().unwrap().push(req.clone());
+            let mut resp = NullProvider.chat(req).await?;
+            resp.text = self.replies.lock().unwrap().pop().unwrap_or("").into();
+            Ok(resp)
+        }
+
+        fn supports_response_schema(&self) -> bool {
+            self.native
+        }
+    }
+
+    fn req() -> ChatRequest {
+        ChatRequest::builder()
+            .model("m")
+            .system("You are a weather service.")
+            .user("Weather in Oslo?")
+            .build()
+    }
+
+    #[tokio::test]
+    async fn prompt_based_schema_retries_with_the_parse_error() {
+        let provider = Scripted::new(
+            false,
+            &[
+                "It is 4 degrees in Oslo.",
+                "```json\n{\"city\": \"Oslo\", \"celsius\": 4}\n```",
+            ],
+        );
+        let typed = chat_typed::<Weather>(&provider, req()).await.unwrap();
+        assert_eq!(
+            typed.value,
+            Weather {
+                city: "Oslo".into(),
+                celsius: 4.0
+            }
+        );
+        assert_eq!(typed.attempts, 2);
+
+        let seen = provider.seen.lock().unwrap();
+        assert!(seen[0].response_schema.is_none());
+        assert_eq!(seen[0].messages[1].role, Role::System);
+        assert!(seen[0].messages[1].content.text().contains("\"celsius\""));
+        let retry = &seen[1].messages;
+        assert_eq!(retry[retry.len() - 2].content, "It is 4 degrees in Oslo.");
+        assert!(
+            retry
+                .last()
+                .unwrap()
+                .content
+                .text()
+                .contains("could not be parsed")
+        );
+    }
+
+    #[tokio::test]
+    async fn native_schema_is_sent_and_retries_run_out() {
+        let provider = Scripted::new(true, &["nope", "still nope"]);
+        let err = chat_typed_with_retries::<Weather>(&provider, req(), 1)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, AiProxyError::ProviderError { code, .. } if code == "invalid_output"),
+            "{err}"
+        );
+        assert!(err.to_string().contains("after 2 attempt(s)"));
+
+        let seen = provider.seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].response_schema.as_ref().unwrap().name, "Weather");
+        assert_eq!(seen[0].messages.len(), 2, "no schema prompt when native");
+    }
+}
@@ -0,0 +1,227 @@
+//! History compression: once a prompt passes `SummarizationCfg::threshold_tokens`, older
+//! turns are summarized by the configured model and replaced with one system message.
+//!
+//! System prompts and the most recent `keep_recent` messages are kept verbatim. The kept tail
+//! always starts on a user message, so an assistant reply or tool result is never separated
+//! from the turn that prompted it.
+
+use crate::config::SummarizationCfg;
+use crate::error::CoreResult;
+use crate::model::{ChatMessage, ChatRequest, Role};
+use crate::provider::ChatProvider;
+use crate::tokenizer;
+
+/// Heads the summary message so later compressions (and readers) can recognize it.
+pub const SUMMARY_PREFIX: &str = "Summary of the earlier conversation:";
+
+const INSTRUCTIONS: &str = "Summarize the conversation below for the assistant that will \
+continue it. Keep facts, decisions, names, numbers and open questions; drop pleasantries. \
+Write plain prose, no preamble.";
+
+fn role_label(role: Role) -> &'static str {
+    match role {
+        Role::System => "system",
+        Role::User => "user",
+        Role::Assistant => "assistant",
+        Role::Tool => "tool",
+    }
+}
+
+/// Index of the first message to keep verbatim, or `None` when there is nothing older to
+/// summarize.
+fn split_point(messages: &[ChatMessage], keep_recent: usize) -> Option<usize> {
+    let turns: Vec<usize> = (0..messages.len())
+        .filter(|&i| messages[i].role != Role::System)
+        .collect();
+    let mut keep_from = turns.len().checked_sub(keep_recent.max(1))?;
+    while keep_from > 0 && messages[turns[keep_from]].role != Role::User {
+        keep_from -= 1;
+    }
+    (keep_from > 0).then(|| turns[keep_from])
+}
+
+/// Whether `compress_history` would call the summarizer for `req`: a model is configured,
+/// the prompt is over the threshold and some turns are old enough to summarize.
+pub fn is_due(req: &ChatRequest, cfg: &SummarizationCfg) -> bool {
+    cfg.model.is_some()
+        && tokenizer::count_tokens(&req.model, &req.messages) > cfg.threshold_tokens as usize
+        && split_point(&req.messages, cfg.keep_recent).is_some()
+}
+
+/// Summarize `req`'s older turns with `summarizer` if the prompt is over the threshold.
+/// The summary call is made on behalf of `req.client_key`.
+/// Returns how many messages the summary replaced, or `None` when the history was left alone
+/// (under threshold, summarization disabled, or nothing old enough to summarize).
+pub async fn compress_history(
+    req: &mut ChatRequest,
+    cfg: &SummarizationCfg,
+    summarizer: &dyn ChatProvider,
+) -> CoreResult<Option<usize>> {
+    let Some(model) = &cfg.model else {
+        return Ok(None);
+    };
+    if !is_due(req, cfg) {
+        return Ok(None);
+    }
+    let Some(split) = split_point(&req.messages, cfg.keep_recent) else {
+        return Ok(None);
+    };
+    let older: Vec<&ChatMessage> = req.messages[..split]
+        .iter()
+        .filter(|m| m.role != Role::System || m.content.text().starts_with(SUMMARY_PREFIX))
+        .collect();
+    let transcript = older
+        .iter()
+        .map(|m| format!("{}: {}", role_label(m.role), m.content.text()))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let mut summary_req = ChatRequest::builder()
+        .model(model.clone())
+        .system(INSTRUCTIONS)
+        .user(transcript)
+        .temperature(0.0)
+        .max_output_tokens(cfg.max_summary_tokens)
+        .build();
+    summary_req.client_key = req.client_key.clone();
+    let summary = summarizer.chat(summary_req).await?;
+    let replaced = older.len();
+    let tail = req.messages.split_off(split);
+    req.messages
+        .retain(|m| m.role == Role::System && !m.content.text().starts_with(SUMMARY_PREFIX));
+    req.messages.push(ChatMessage::system(format!(
+        "{SUMMARY_PREFIX}\n{}",
+        summary.text.trim()
+    )));
+    req.messages.extend(tail);
+    Ok(Some(replaced))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CoreResult;
+    use crate::model::ChatResponse;
+    use crate::provider::NullProvider;
+    use async_trait::async_trait;
+    use std::sync::Mutex;
+
+    /// Answers every call with a fixed summary and remembers the prompt it was given.
+    #[derive(Debug, Default)]
+    struct Summarizer {
+        seen: Mutex<Option<ChatRequest>>,
+    }
+
+    #[async_trait]
+    impl ChatProvider for Summarizer {
+        fn name(&self) -> &str {
+            "summarizer"
+        }
+
+        async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+            *self.seen.lock().unwrap() = Some(req.clone());
+            let mut resp = NullProvider.chat(req).await?;
+            resp.text = " they discussed A and B ".into();
+            Ok(resp)
+        }
+    }
+
+    fn cfg(threshold_tokens: u32) -> SummarizationCfg {
+        SummarizationCfg {
+            model: Some("cheap".into()),
+            threshold_tokens,
+            keep_recent: 2,
+            ..SummarizationCfg::default()
+        }
+    }
+
+    fn history() -> ChatRequest {
+        ChatRequest::builder()
+            .model("m")
+            .client_key("team-a")
+            .system("be kind")
+            .user("first question")
+            .assistant("first answer")
+            .user("second question")
+            .assistant("second answer")
+            .user("third question")
+            .build()
+    }
+
+    #[tokio::test]
+    async fn older_turns_become_one_summary_message() {
+        let summarizer = Summarizer::default();
+        let mut req = history();
+        let replaced = compress_history(&mut req, &cfg(10), &summarizer)
+            .await
+            .unwrap();
+        // Keeping the last two would start on an assistant reply, so its question stays too.
+        assert_eq!(replaced, Some(2));
+        let texts: Vec<String> = req
+            .messages
+            .iter()
+            .map(|m| m.content.text().into_owned())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                "be kind".to_string(),
+                format!("{SUMMARY_PREFIX}\nthey discussed A and B"),
+                "second question".into(),
+                "second answer".into(),
+                "third question".into(),
+            ]
+        );
+        let seen = summarizer.seen.lock().unwrap().clone().unwrap();
+        assert_eq!(seen.model, "cheap");
+        assert_eq!(seen.client_key.as_deref(), Some("team-a"));
+        assert!(
+            seen.messages[1]
+                .content
+                .text()
+                .starts_with("user: first question")
+        );
+
+        // A second pass folds the previous summary in rather than stacking another.
+        req.messages.push(ChatMessage::assistant("third answer"));
+        req.messages.push(ChatMessage::user("fourth question"));
+        let replaced = compress_history(&mut req, &cfg(10), &summarizer)
+            .await
+            .unwrap();
+        assert_eq!(replaced, Some(3));
+        assert_eq!(req.messages.len(), 5);
+        assert_eq!(req.messages[2].content, "third question");
+        let seen = summarizer.seen.lock().unwrap().clone().unwrap();
+        assert!(
+            seen.messages[1]
+                .content
+                .text()
+                .starts_with("system: Summary")
+        );
+    }
+
+    #[tokio::test]
+    async fn short_or_disabled_histories_are_untouched() {
+        let summarizer = Summarizer::default();
+        let mut req = history();
+        assert_eq!(
+            compress_history(&mut req, &cfg(10_000), &summarizer)
+                .await
+                .unwrap(),
+            None
+        );
+        let disabled = SummarizationCfg {
+            model: None,
+            ..cfg(0)
+        };
+        assert_eq!(
+            compress_history(&mut req, &disabled, &summarizer)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(req, history());
+        assert!(summarizer.seen.lock().unwrap().is_none());
+        assert!(!is_due(&req, &cfg(10_000)) && !is_due(&req, &disabled));
+        assert!(is_due(&req, &cfg(10)));
+    }
+}
@@ -0,0 +1,193 @@
+//! Server access log events and an NDJSON writer for them.
+//!
+//! One `AccessLog` is emitted per HTTP request handled by `aiproxy serve`. Prompt and
+//! response text are carried only when the server's `ContentPolicy` allows it; by default
+//! they are left out entirely.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::CoreResult;
+use crate::telemetry::{ProviderTrace, TelemetrySink};
+
+/// One handled server request.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AccessLog {
+    pub ts_ms: u64,
+    pub method: String,
+    /// Route pattern (e.g. `/admin/providers/{name}/drain`), not the raw URI.
+    pub path: String,
+    /// Redacted client key (`***abcd`); `None` for anonymous callers.
+    pub key_id: Option<String>,
+    pub model: Option<String>,
+    pub status: u16,
+    /// Until the last byte was sent, for streamed responses too.
+    pub latency_ms: u64,
+    pub tokens_prompt: Option<u32>,
+    pub tokens_completion: Option<u32>,
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<String>,
+}
+
+/// What an access log may say about prompt and response text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ContentPolicy {
+    /// No content is logged.
+    #[default]
+    Omit,
+    /// Content is logged with credentials and email addresses masked.
+    Redact,
+    /// Content is logged verbatim.
+    Full,
+}
+
+impl ContentPolicy {
+    /// `text` as it may appear in the log, or `None` when content is omitted.
+    pub fn apply(self, text: &str) -> Option<String> {
+        match self {
+            Self::Omit => None,
+            Self::Redact => Some(redact(text)),
+            Self::Full => Some(text.to_string()),
+        }
+    }
+}
+
+impl FromStr for ContentPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "omit" => Ok(Self::Omit),
+            "redact" => Ok(Self::Redact),
+            "full" => Ok(Self::Full),
+            other => Err(format!(
+                "unknown content policy '{other}' (expected omit, redact or full)"
+            )),
+        }
+    }
+}
+
+const MASK: &str = "[REDACTED]";
+
+static SECRETS: Lazy<Vec<Regex>> = Lazy::new(|| {
+    [
+        // Bearer tokens, then provider-style API keys (sk-..., sk-ant-..., sk-or-...).
+        r"(?i)bearer\s+[A-Za-z0-9._~+/=-]{8,}",
+        r"\bsk-[A-Za-z0-9_-]{8,}",
+        r"\bAKIA[0-9A-Z]{16}\b",
+        r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}",
+    ]
+    .iter()
+    .map(|p| Regex::new(p).expect("valid redaction pattern"))
+    .collect()
+});
+
+/// Mask bearer tokens, API keys and email addresses in `text`.
+pub fn redact(text: &str) -> String {
+    SECRETS.iter().fold(text.to_string(), |acc, re| {
+        re.replace_all(&acc, MASK).into_owned()
+    })
+}
+
+/// `redact` every string in `value`, keys aside.
+pub fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) => *s = redact(s),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        serde_json::Value::Object(map) => map.values_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+/// Telemetry sink that writes each `AccessLog` as one NDJSON line.
+pub struct AccessLogWriter {
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLogWriter {
+    pub fn new(out: impl Write + Send + 'static) -> Self {
+        Self {
+            out: Mutex::new(Box::new(out)),
+        }
+    }
+
+    /// Append to `path`, creating it and its parent directory if needed.
+    pub fn open(path: &Path) -> CoreResult<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok(Self::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        ))
+    }
+}
+
+impl TelemetrySink for AccessLogWriter {
+    fn record(&self, _trace: ProviderTrace) {}
+
+    fn record_access(&self, log: AccessLog) {
+        if let Ok(mut line) = serde_json::to_vec(&log) {
+            line.push(b'\n');
+            // Logging must never fail a request.
+            let _ = self.out.lock().unwrap().write_all(&line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn redact_masks_credentials_and_emails() {
+        let text = "key sk-ant-abc123456789 from a.b@example.com via Bearer abcdefghijkl ok";
+        assert_eq!(
+            redact(text),
+            "key [REDACTED] from [REDACTED] via [REDACTED] ok"
+        );
+        assert_eq!(ContentPolicy::Omit.apply(text), None);
+        assert_eq!(ContentPolicy::Full.apply(text).as_deref(), Some(text));
+        assert_eq!("redact".parse(), Ok(ContentPolicy::Redact));
+        assert!("all".parse::<ContentPolicy>().is_err());
+    }
+
+    #[derive(Clone, Default)]
+    struct Buf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buf {
+        fn write(&mut self, b: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(b)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn writer_emits_one_line_per_request_without_absent_content() {
+        let buf = Buf::default();
+        let sink = AccessLogWriter::new(buf.clone());
+        sink.record_access(AccessLog {
+            method: "POST".into(),
+            path: "/v1/chat/completions".into(),
+            status: 200,
+            ..Default::default()
+        });
+        let text = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(text.trim_end()).unwrap();
+        assert_eq!(line["status"], 200);
+        assert!(line.get("prompt").is_none());
+        assert_eq!(text.lines().count(), 1);
+    }
+}
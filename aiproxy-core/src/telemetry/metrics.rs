@@ -0,0 +1,627 @@
+//! Prometheus metrics aggregated from completion telemetry.
+//!
+//! `MetricsSink` keeps running totals per provider, model and key id (the redacted key tail,
+//! or `anonymous`) and renders them in the Prometheus text exposition format. Server access
+//! events add a per-route HTTP request counter, and guardrail events a trigger counter.
+//! Retried calls add retry and backoff counters per provider and model, and response cache
+//! lookups a hit/miss counter. Failed completions and failed HTTP calls (from `ProviderTrace`
+//! events) are also counted by error kind.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use crate::config::GuardrailAction;
+use crate::model::GuardrailKind;
+use crate::pricing;
+use crate::telemetry::access::AccessLog;
+use crate::telemetry::{
+    CacheLog, CircuitLog, CompletionLog, GuardrailLog, ProviderTrace, TelemetrySink,
+};
+use crate::usage::key_label;
+
+/// Upper bounds (seconds) of the request duration histogram buckets.
+const LATENCY_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Labels {
+    provider: String,
+    model: String,
+    key_id: String,
+}
+
+#[derive(Debug, Default)]
+struct Series {
+    ok: u64,
+    errors: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    cost_usd: f64,
+    /// Cumulative counts per `LATENCY_BUCKETS` entry.
+    latency_buckets: Vec<u64>,
+    latency_sum: f64,
+    latency_count: u64,
+}
+
+/// Guardrail hits are counted by (provider, model, kind, action).
+type GuardrailKey = (String, String, &'static str, &'static str);
+
+/// Cache lookups are counted by (provider, model, kind, result).
+type CacheKey = (String, String, &'static str, &'static str);
+
+/// Telemetry sink that exposes completions as Prometheus series.
+#[derive(Debug, Default)]
+pub struct MetricsSink {
+    series: Mutex<BTreeMap<Labels, Series>>,
+    /// Server requests by (method, route, status).
+    http: Mutex<BTreeMap<(String, String, u16), u64>>,
+    guardrail: Mutex<BTreeMap<GuardrailKey, u64>>,
+    /// Retries by (provider, model, reason).
+    retries: Mutex<BTreeMap<(String, String, String), u64>>,
+    /// Seconds spent backing off, by (provider, model).
+    backoff: Mutex<BTreeMap<(String, String), f64>>,
+    cache: Mutex<BTreeMap<CacheKey, u64>>,
+    /// Failures by (provider, model, error kind).
+    errors: Mutex<BTreeMap<(String, String, String), u64>>,
+    /// Circuit breaker transitions by (provider, state entered).
+    circuit: Mutex<BTreeMap<(String, &'static str), u64>>,
+}
+
+impl MetricsSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn count_error(&self, provider: &str, model: &str, kind: &str) {
+        *self
+            .errors
+            .lock()
+            .unwrap()
+            .entry((provider.to_string(), model.to_string(), kind.to_string()))
+            .or_default() += 1;
+    }
+
+    /// Current values in the Prometheus text format (version 0.0.4).
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "aiproxy_requests_total",
+            "counter",
+            "Completed provider calls.",
+        );
+        for (l, s) in series.iter() {
+            for (status, n) in [("ok", s.ok), ("error", s.errors)] {
+                if n > 0 {
+                    sample(
+                        &mut out,
+                        "aiproxy_requests_total",
+                        l,
+                        &[("status", status)],
+                        n,
+                    );
+                }
+            }
+        }
+
+        header(
+            &mut out,
+            "aiproxy_tokens_total",
+            "counter",
+            "Tokens reported by providers.",
+        );
+        for (l, s) in series.iter() {
+            for (kind, n) in [
+                ("prompt", s.prompt_tokens),
+                ("completion", s.completion_tokens),
+            ] {
+                sample(&mut out, "aiproxy_tokens_total", l, &[("kind", kind)], n);
+            }
+        }
+
+        header(
+            &mut out,
+            "aiproxy_cost_usd_total",
+            "counter",
+            "Estimated spend in USD for models with a known price.",
+        );
+        for (l, s) in series.iter() {
+            sample(&mut out, "aiproxy_cost_usd_total", l, &[], s.cost_usd);
+        }
+
+        header(
+            &mut out,
+            "aiproxy_request_duration_seconds",
+            "histogram",
+            "Provider call latency.",
+        );
+        for (l, s) in series.iter().filter(|(_, s)| s.latency_count > 0) {
+            for (le, n) in LATENCY_BUCKETS.iter().zip(&s.latency_buckets) {
+                let le = le.to_string();
+                sample(
+                    &mut out,
+                    "aiproxy_request_duration_seconds_bucket",
+                    l,
+                    &[("le", &le)],
+                    n,
+                );
+            }
+            sample(
+                &mut out,
+                "aiproxy_request_duration_seconds_bucket",
+                l,
+                &[("le", "+Inf")],
+                s.latency_count,
+            );
+            sample(
+                &mut out,
+                "aiproxy_request_duration_seconds_sum",
+                l,
+                &[],
+                s.latency_sum,
+            );
+            sample(
+                &mut out,
+                "aiproxy_request_duration_seconds_count",
+                l,
+                &[],
+                s.latency_count,
+            );
+        }
+
+        let http = self.http.lock().unwrap();
+        if !http.is_empty() {
+            header(
+                &mut out,
+                "aiproxy_http_requests_total",
+                "counter",
+                "Requests handled by the server.",
+            );
+        }
+        for ((method, path, status), n) in http.iter() {
+            let _ = writeln!(
+                out,
+                "aiproxy_http_requests_total{{method=\"{}\",path=\"{}\",status=\"{status}\"}} {n}",
+                escape(method),
+                escape(path)
+            );
+        }
+
+        let guardrail = self.guardrail.lock().unwrap();
+        if !guardrail.is_empty() {
+            header(
+                &mut out,
+                "aiproxy_guardrail_triggers_total",
+                "counter",
+                "Output guardrail hits on generated responses.",
+            );
+        }
+        for ((provider, model, kind, action), n) in guardrail.iter() {
+            let _ = writeln!(
+                out,
+                "aiproxy_guardrail_triggers_total{{provider=\"{}\",model=\"{}\",kind=\"{kind}\",action=\"{action}\"}} {n}",
+                escape(provider),
+                escape(model)
+            );
+        }
+
+        let retries = self.retries.lock().unwrap();
+        if !retries.is_empty() {
+            header(
+                &mut out,
+                "aiproxy_retries_total",
+                "counter",
+                "Provider call attempts after the first, by reason.",
+            );
+        }
+        for ((provider, model, reason), n) in retries.iter() {
+            let _ = writeln!(
+                out,
+                "aiproxy_retries_total{{provider=\"{}\",model=\"{}\",reason=\"{}\"}} {n}",
+                escape(provider),
+                escape(model),
+                escape(reason)
+            );
+        }
+        let backoff = self.backoff.lock().unwrap();
+        if !backoff.is_empty() {
+            header(
+                &mut out,
+                "aiproxy_retry_backoff_seconds_total",
+                "counter",
+                "Time spent waiting between attempts.",
+            );
+        }
+        for ((provider, model), secs) in backoff.iter() {
+            let _ = writeln!(
+                out,
+                "aiproxy_retry_backoff_seconds_total{{provider=\"{}\",model=\"{}\"}} {secs}",
+                escape(provider),
+                escape(model)
+            );
+        }
+
+        let cache = self.cache.lock().unwrap();
+        if !cache.is_empty() {
+            header(
+                &mut out,
+                "aiproxy_cache_requests_total",
+                "counter",
+                "Response cache lookups, by hit or miss.",
+            );
+        }
+        for ((provider, model, kind, result), n) in cache.iter() {
+            let _ = writeln!(
+                out,
+                "aiproxy_cache_requests_total{{provider=\"{}\",model=\"{}\",kind=\"{kind}\",result=\"{result}\"}} {n}",
+                escape(provider),
+                escape(model)
+            );
+        }
+
+        let errors = self.errors.lock().unwrap();
+        if !errors.is_empty() {
+            header(
+                &mut out,
+                "aiproxy_errors_total",
+                "counter",
+                "Failed provider calls, by error kind.",
+            );
+        }
+        for ((provider, model, kind), n) in errors.iter() {
+            let _ = writeln!(
+                out,
+                "aiproxy_errors_total{{provider=\"{}\",model=\"{}\",kind=\"{}\"}} {n}",
+                escape(provider),
+                escape(model),
+                escape(kind)
+            );
+        }
+
+        let circuit = self.circuit.lock().unwrap();
+        if !circuit.is_empty() {
+            header(
+                &mut out,
+                "aiproxy_circuit_transitions_total",
+                "counter",
+                "Circuit breaker transitions, by the state entered.",
+            );
+        }
+        for ((provider, state), n) in circuit.iter() {
+            let _ = writeln!(
+                out,
+                "aiproxy_circuit_transitions_total{{provider=\"{}\",state=\"{state}\"}} {n}",
+                escape(provider)
+            );
+        }
+        out
+    }
+}
+
+impl TelemetrySink for MetricsSink {
+    /// HTTP failures are reported as traces; the client does not know the provider's name,
+    /// so they are labelled `http`.
+    fn record(&self, trace: ProviderTrace) {
+        if let Some(kind) = &trace.error_kind {
+            self.count_error(
+                trace.provider.as_deref().unwrap_or_default(),
+                trace.model.as_deref().unwrap_or_default(),
+                kind,
+            );
+        }
+    }
+
+    fn record_completion(&self, log: CompletionLog) {
+        let labels = Labels {
+            provider: log.provider.clone().unwrap_or_default(),
+            model: log.model.clone().unwrap_or_default(),
+            key_id: log
+                .client_key
+                .as_deref()
+                .map_or_else(|| "anonymous".to_string(), key_label),
+        };
+        if log.retries > 0 {
+            let mut retries = self.retries.lock().unwrap();
+            for reason in &log.retry_reasons {
+                *retries
+                    .entry((
+                        labels.provider.clone(),
+                        labels.model.clone(),
+                        reason.clone(),
+                    ))
+                    .or_default() += 1;
+            }
+            *self
+                .backoff
+                .lock()
+                .unwrap()
+                .entry((labels.provider.clone(), labels.model.clone()))
+                .or_default() += log.backoff_ms as f64 / 1000.0;
+        }
+        if let Some(kind) = &log.error_kind {
+            self.count_error(&labels.provider, &labels.model, kind);
+        }
+        let mut series = self.series.lock().unwrap();
+        let s = series.entry(labels).or_default();
+        if log.error_kind.is_some() {
+            s.errors += 1;
+        } else {
+            s.ok += 1;
+            let prompt = log.tokens_prompt.unwrap_or(0);
+            let completion = log.tokens_completion.unwrap_or(0);
+            s.prompt_tokens += u64::from(prompt);
+            s.completion_tokens += u64::from(completion);
+            let estimate = || {
+                let provider = log.provider.as_deref().unwrap_or("");
+                pricing::cost_usd(provider, log.model.as_deref()?, prompt, completion)
+            };
+            s.cost_usd += log.cost_usd.or_else(estimate).unwrap_or(0.0);
+        }
+        if let Some(ms) = log.latency_ms {
+            let secs = ms as f64 / 1000.0;
+            if s.latency_buckets.is_empty() {
+                s.latency_buckets = vec![0; LATENCY_BUCKETS.len()];
+            }
+            for (le, n) in LATENCY_BUCKETS.iter().zip(s.latency_buckets.iter_mut()) {
+                if secs <= *le {
+                    *n += 1;
+                }
+            }
+            s.latency_sum += secs;
+            s.latency_count += 1;
+        }
+    }
+
+    fn record_access(&self, log: AccessLog) {
+        *self
+            .http
+            .lock()
+            .unwrap()
+            .entry((log.method, log.path, log.status))
+            .or_default() += 1;
+    }
+
+    fn record_guardrail(&self, log: GuardrailLog) {
+        let action = match log.action {
+            GuardrailAction::Off => "off",
+            GuardrailAction::Annotate => "annotate",
+            GuardrailAction::Redact => "redact",
+            GuardrailAction::Block => "block",
+        };
+        let mut counts = self.guardrail.lock().unwrap();
+        for hit in &log.hits {
+            let kind = match hit.kind {
+                GuardrailKind::Deny => "deny",
+                GuardrailKind::Pattern => "pattern",
+                GuardrailKind::Moderation => "moderation",
+            };
+            *counts
+                .entry((log.provider.clone(), log.model.clone(), kind, action))
+                .or_default() += 1;
+        }
+    }
+
+    fn record_cache(&self, log: CacheLog) {
+        let result = if log.hit { "hit" } else { "miss" };
+        *self
+            .cache
+            .lock()
+            .unwrap()
+            .entry((log.provider, log.model, log.kind, result))
+            .or_default() += 1;
+    }
+
+    fn record_circuit(&self, log: CircuitLog) {
+        *self
+            .circuit
+            .lock()
+            .unwrap()
+            .entry((log.provider, log.state.as_str()))
+            .or_default() += 1;
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn sample(
+    out: &mut String,
+    name: &str,
+    l: &Labels,
+    extra: &[(&str, &str)],
+    value: impl std::fmt::Display,
+) {
+    let mut labels = format!(
+        "provider=\"{}\",model=\"{}\",key_id=\"{}\"",
+        escape(&l.provider),
+        escape(&l.model),
+        escape(&l.key_id)
+    );
+    for (k, v) in extra {
+        let _ = write!(labels, ",{k}=\"{}\"", escape(v));
+    }
+    let _ = writeln!(out, "{name}{{{labels}}} {value}");
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::CircuitState;
+
+    fn log(key: Option<&str>, latency_ms: u64, error: bool) -> CompletionLog {
+        let mut log = CompletionLog::new()
+            .provider("openai")
+            .model("gpt-4o")
+            .client_key_opt(key)
+            .latency_ms(latency_ms)
+            .tokens(Some(1_000), Some(500), Some(1_500));
+        if error {
+            log = log.error_kind_opt(Some("provider_error"));
+        }
+        log
+    }
+
+    #[test]
+    fn renders_counters_and_histogram_per_key() {
+        let m = MetricsSink::new();
+        m.record_completion(log(Some("sk-secret-abcd"), 200, false));
+        m.record_completion(log(Some("sk-secret-abcd"), 3_000, false));
+        m.record_completion(log(None, 50, true));
+        let text = m.render();
+
+        let l = r#"provider="openai",model="gpt-4o",key_id="***abcd""#;
+        assert!(text.contains(&format!("aiproxy_requests_total{{{l},status=\"ok\"}} 2")));
+        assert!(text.contains(&format!("aiproxy_tokens_total{{{l},kind=\"prompt\"}} 2000")));
+        assert!(text.contains(&format!(
+            "aiproxy_request_duration_seconds_bucket{{{l},le=\"0.25\"}} 1"
+        )));
+        assert!(text.contains(&format!(
+            "aiproxy_request_duration_seconds_bucket{{{l},le=\"5\"}} 2"
+        )));
+        assert!(text.contains(&format!("aiproxy_request_duration_seconds_count{{{l}}} 2")));
+        assert!(text.contains(r#"key_id="anonymous",status="error"} 1"#));
+        assert!(!text.contains("sk-secret"));
+        // 2 × (1000 × $2.50 + 500 × $10) per 1M tokens
+        assert!(text.contains(&format!("aiproxy_cost_usd_total{{{l}}} 0.015")));
+    }
+
+    #[test]
+    fn retries_count_per_reason_with_backoff_time() {
+        let m = MetricsSink::new();
+        m.record_completion(log(None, 100, false));
+        assert!(!m.render().contains("aiproxy_retries_total"));
+        let reasons = |r: &[&str]| r.iter().map(|s| s.to_string()).collect();
+        m.record_completion(
+            log(None, 100, false).retried(reasons(&["rate_limited", "503"]), 1_500),
+        );
+        m.record_completion(log(None, 100, true).retried(reasons(&["503"]), 500));
+        let text = m.render();
+        let l = r#"provider="openai",model="gpt-4o""#;
+        assert!(text.contains(&format!("aiproxy_retries_total{{{l},reason=\"503\"}} 2")));
+        assert!(text.contains(&format!(
+            "aiproxy_retries_total{{{l},reason=\"rate_limited\"}} 1"
+        )));
+        assert!(text.contains(&format!("aiproxy_retry_backoff_seconds_total{{{l}}} 2")));
+    }
+
+    #[test]
+    fn access_events_count_per_route_and_status() {
+        let m = MetricsSink::new();
+        assert!(!m.render().contains("aiproxy_http_requests_total"));
+        for status in [200, 200, 429] {
+            m.record_access(AccessLog {
+                method: "POST".into(),
+                path: "/v1/messages".into(),
+                status,
+                ..Default::default()
+            });
+        }
+        let text = m.render();
+        assert!(text.contains(
+            r#"aiproxy_http_requests_total{method="POST",path="/v1/messages",status="200"} 2"#
+        ));
+        assert!(text.contains(r#"status="429"} 1"#));
+    }
+
+    #[test]
+    fn guardrail_hits_count_per_kind_and_action() {
+        let m = MetricsSink::new();
+        assert!(!m.render().contains("aiproxy_guardrail_triggers_total"));
+        m.record_guardrail(GuardrailLog {
+            provider: "openai".into(),
+            model: "gpt-4o".into(),
+            action: GuardrailAction::Redact,
+            hits: vec![
+                crate::model::GuardrailHit {
+                    kind: GuardrailKind::Deny,
+                    rule: "secret".into(),
+                };
+                2
+            ],
+        });
+        assert!(m.render().contains(
+            r#"aiproxy_guardrail_triggers_total{provider="openai",model="gpt-4o",kind="deny",action="redact"} 2"#
+        ));
+    }
+
+    #[test]
+    fn cache_lookups_count_hits_and_misses() {
+        let m = MetricsSink::new();
+        assert!(!m.render().contains("aiproxy_cache_requests_total"));
+        for hit in [false, true, true] {
+            m.record_cache(CacheLog {
+                provider: "openai".into(),
+                model: "gpt-4o".into(),
+                kind: "chat",
+                hit,
+            });
+        }
+        let text = m.render();
+        assert!(text.contains(
+            r#"aiproxy_cache_requests_total{provider="openai",model="gpt-4o",kind="chat",result="hit"} 2"#
+        ));
+        assert!(text.contains(
+            r#"aiproxy_cache_requests_total{provider="openai",model="gpt-4o",kind="chat",result="miss"} 1"#
+        ));
+    }
+
+    #[test]
+    fn errors_count_per_kind_from_completions_and_traces() {
+        let m = MetricsSink::new();
+        assert!(!m.render().contains("aiproxy_errors_total"));
+        m.record_completion(log(None, 100, true));
+        m.record(
+            ProviderTrace::new()
+                .provider("http")
+                .model_opt(Some("gpt-4o"))
+                .error_kind("http_error"),
+        );
+        m.record(ProviderTrace::new().provider("http").latency_ms(5));
+        let text = m.render();
+        assert!(text.contains(
+            r#"aiproxy_errors_total{provider="openai",model="gpt-4o",kind="provider_error"} 1"#
+        ));
+        assert!(text.contains(
+            r#"aiproxy_errors_total{provider="http",model="gpt-4o",kind="http_error"} 1"#
+        ));
+    }
+
+    #[test]
+    fn circuit_transitions_count_per_state() {
+        let m = MetricsSink::new();
+        assert!(!m.render().contains("aiproxy_circuit_transitions_total"));
+        for state in [
+            CircuitState::Open,
+            CircuitState::HalfOpen,
+            CircuitState::Open,
+        ] {
+            m.record_circuit(CircuitLog {
+                provider: "openai".into(),
+                state,
+                failures: 5,
+            });
+        }
+        let text = m.render();
+        assert!(
+            text.contains(r#"aiproxy_circuit_transitions_total{provider="openai",state="open"} 2"#)
+        );
+        assert!(text.contains(
+            r#"aiproxy_circuit_transitions_total{provider="openai",state="half_open"} 1"#
+        ));
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
@@ -1,9 +1,14 @@
 //! Telemetry primitives for provider-agnostic tracing.
 //! By default, no telemetry is emitted unless a sink is installed via `set_telemetry_sink`.
 
+pub mod access;
 pub mod keys;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "otel")]
+pub mod otel;
 pub mod types;
-#[cfg(test)]
+#[cfg(any(test, feature = "test-utils"))]
 pub mod test_span;
 
 pub use keys::*;
@@ -24,6 +29,60 @@ pub trait TelemetrySink: Send + Sync + 'static {
 
     // 1.15.5: optional completion event; default no-op to avoid breaking existing sinks
     fn record_completion(&self, _log: crate::telemetry::CompletionLog) {}
+
+    /// Server access event; default no-op so completion-only sinks need not care.
+    fn record_access(&self, _log: crate::telemetry::access::AccessLog) {}
+
+    /// Output guardrail trigger; default no-op.
+    fn record_guardrail(&self, _log: crate::telemetry::GuardrailLog) {}
+
+    /// Response cache hit or miss; default no-op.
+    fn record_cache(&self, _log: crate::telemetry::CacheLog) {}
+
+    /// Circuit breaker opening, half-opening or closing; default no-op.
+    fn record_circuit(&self, _log: crate::telemetry::CircuitLog) {}
+}
+
+/// Forwards every event to each inner sink, so one process can feed several consumers
+/// through the single global slot.
+pub struct FanoutSink(pub Vec<Arc<dyn TelemetrySink>>);
+
+impl TelemetrySink for FanoutSink {
+    fn record(&self, trace: crate::telemetry::ProviderTrace) {
+        for sink in &self.0 {
+            sink.record(trace.clone());
+        }
+    }
+
+    fn record_completion(&self, log: crate::telemetry::CompletionLog) {
+        for sink in &self.0 {
+            sink.record_completion(log.clone());
+        }
+    }
+
+    fn record_access(&self, log: crate::telemetry::access::AccessLog) {
+        for sink in &self.0 {
+            sink.record_access(log.clone());
+        }
+    }
+
+    fn record_guardrail(&self, log: crate::telemetry::GuardrailLog) {
+        for sink in &self.0 {
+            sink.record_guardrail(log.clone());
+        }
+    }
+
+    fn record_cache(&self, log: crate::telemetry::CacheLog) {
+        for sink in &self.0 {
+            sink.record_cache(log.clone());
+        }
+    }
+
+    fn record_circuit(&self, log: crate::telemetry::CircuitLog) {
+        for sink in &self.0 {
+            sink.record_circuit(log.clone());
+        }
+    }
 }
 
 static TELEMETRY_SINK: OnceCell<Arc<dyn TelemetrySink>> = OnceCell::new();
@@ -31,7 +90,7 @@ static TELEMETRY_SINK: OnceCell<Arc<dyn TelemetrySink>> = OnceCell::new();
 // In tests, gate emission to only the calling test thread to avoid cross-test interference.
 #[cfg(test)]
 thread_local! {
-    static TEST_CAPTURE: std::cell::Cell<bool> = std::cell::Cell::new(false);
+    static TEST_CAPTURE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
 }
 
 /// Install a global telemetry sink. Returns `false` if a sink is already installed.
@@ -73,6 +132,64 @@ pub(crate) fn emit_completion(log: crate::telemetry::CompletionLog) {
     }
 }
 
+/// Emit a server access event if a sink is installed. Public because the server front-ends
+/// live outside this crate.
+#[inline]
+pub fn emit_access(log: crate::telemetry::access::AccessLog) {
+    #[cfg(test)]
+    {
+        if !TEST_CAPTURE.with(|c| c.get()) {
+            return;
+        }
+    }
+    if let Some(sink) = TELEMETRY_SINK.get() {
+        sink.record_access(log);
+    }
+}
+
+/// Emit an output guardrail trigger if a sink is installed. Crate-visible by design.
+#[inline]
+pub(crate) fn emit_guardrail(log: crate::telemetry::GuardrailLog) {
+    #[cfg(test)]
+    {
+        if !TEST_CAPTURE.with(|c| c.get()) {
+            return;
+        }
+    }
+    if let Some(sink) = TELEMETRY_SINK.get() {
+        sink.record_guardrail(log);
+    }
+}
+
+/// Emit a response cache hit or miss if a sink is installed. Crate-visible by design.
+#[inline]
+#[cfg_attr(not(feature = "native"), allow(dead_code))]
+pub(crate) fn emit_cache(log: crate::telemetry::CacheLog) {
+    #[cfg(test)]
+    {
+        if !TEST_CAPTURE.with(|c| c.get()) {
+            return;
+        }
+    }
+    if let Some(sink) = TELEMETRY_SINK.get() {
+        sink.record_cache(log);
+    }
+}
+
+/// Emit a circuit breaker transition if a sink is installed. Crate-visible by design.
+#[inline]
+pub(crate) fn emit_circuit(log: crate::telemetry::CircuitLog) {
+    #[cfg(test)]
+    {
+        if !TEST_CAPTURE.with(|c| c.get()) {
+            return;
+        }
+    }
+    if let Some(sink) = TELEMETRY_SINK.get() {
+        sink.record_circuit(log);
+    }
+}
+
 #[cfg(test)]
 /// Test-only helper: enable or disable capture for the current test thread.
 ///
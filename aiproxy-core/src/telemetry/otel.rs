@@ -0,0 +1,477 @@
+//! OpenTelemetry export, behind the `otel` feature. `OtlpSink` turns `ProviderTrace` and
+//! `CompletionLog` events into OTLP client spans, attributed with the `telemetry::keys`
+//! names, and posts them as OTLP/HTTP JSON to `<endpoint>/v1/traces`.
+//!
+//! W3C trace context: a caller's `traceparent` header is parsed with `TraceContext::parse`
+//! and made current for a future with `scope`. While it is current, `HttpClient` forwards it
+//! on every upstream request and exported spans join its trace as children, so proxy calls
+//! show up in the caller's distributed trace. Events emitted outside the scope, such as
+//! those of spawned stream bridges, start traces of their own.
+//!
+//! Neither prompts, responses nor client keys are exported.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Value, json};
+
+use crate::error::{AiProxyError, CoreResult};
+use crate::providers::hash::splitmix;
+use crate::telemetry::keys::*;
+use crate::telemetry::{CompletionLog, ProviderTrace, TelemetrySink};
+
+/// Spans kept between flushes; the oldest are dropped beyond this.
+const MAX_PENDING: usize = 2048;
+
+/// OTLP `SpanKind` for calls to a remote service.
+const SPAN_KIND_CLIENT: u8 = 3;
+const STATUS_ERROR: u8 = 2;
+
+/// A W3C trace context (`traceparent`): the trace and the caller's span within it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    /// 32 lowercase hex digits.
+    pub trace_id: String,
+    /// 16 lowercase hex digits.
+    pub span_id: String,
+    pub sampled: bool,
+}
+
+fn is_id(s: &str, len: usize) -> bool {
+    s.len() == len
+        && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+        && s.bytes().any(|b| b != b'0')
+}
+
+impl TraceContext {
+    /// Parse a `traceparent` header: `<version>-<trace id>-<parent id>-<flags>`. Unknown
+    /// versions are read as version `00`, as the spec asks; `ff` and all-zero ids are invalid.
+    pub fn parse(header: &str) -> Option<Self> {
+        let mut parts = header.trim().split('-');
+        let (version, trace_id, span_id, flags) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        let trailing = version == "00" && parts.next().is_some();
+        if version.len() != 2 || version == "ff" || flags.len() != 2 || trailing {
+            return None;
+        }
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        (is_id(trace_id, 32) && is_id(span_id, 16)).then(|| Self {
+            trace_id: trace_id.to_string(),
+            span_id: span_id.to_string(),
+            sampled: flags & 1 == 1,
+        })
+    }
+
+    /// A new trace, for events with no caller context.
+    pub fn root() -> Self {
+        Self {
+            trace_id: random_hex(2),
+            span_id: random_hex(1),
+            sampled: true,
+        }
+    }
+
+    /// The `traceparent` header value.
+    pub fn header(&self) -> String {
+        let flags = if self.sampled { "01" } else { "00" };
+        format!("00-{}-{}-{flags}", self.trace_id, self.span_id)
+    }
+}
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+}
+
+/// Run `fut` with `cx` as the current trace context.
+pub async fn scope<F: Future>(cx: TraceContext, fut: F) -> F::Output {
+    CURRENT.scope(cx, fut).await
+}
+
+/// The trace context of the running task, if it is inside a `scope`.
+pub fn current() -> Option<TraceContext> {
+    CURRENT.try_with(Clone::clone).ok()
+}
+
+static IDS: AtomicU64 = AtomicU64::new(0);
+
+/// `words` random 64-bit words as hex, for span and trace ids.
+fn random_hex(words: usize) -> String {
+    let mut state = IDS.fetch_add(1, Ordering::Relaxed) ^ now_ns();
+    (0..words)
+        .map(|_| format!("{:016x}", splitmix(&mut state).max(1)))
+        .collect()
+}
+
+fn now_ns() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos() as u64)
+}
+
+fn string_attr(out: &mut Vec<Value>, key: &str, value: Option<&str>) {
+    if let Some(v) = value {
+        out.push(json!({"key": key, "value": {"stringValue": v}}));
+    }
+}
+
+/// OTLP JSON carries 64-bit integers as strings.
+fn int_attr(out: &mut Vec<Value>, key: &str, value: Option<u64>) {
+    if let Some(v) = value {
+        out.push(json!({"key": key, "value": {"intValue": v.to_string()}}));
+    }
+}
+
+struct SpanParts<'a> {
+    name: &'a str,
+    span_id: Option<&'a str>,
+    parent_span_id: Option<&'a str>,
+    end_ns: u64,
+    latency_ms: Option<u64>,
+    attributes: Vec<Value>,
+    error: Option<(&'a str, Option<&'a str>)>,
+}
+
+/// An OTLP span in the current trace (or a new one), ending at `end_ns`.
+fn span(parts: SpanParts<'_>) -> Value {
+    let cx = current();
+    let trace_id = cx
+        .as_ref()
+        .map_or_else(|| random_hex(2), |c| c.trace_id.clone());
+    let parent = parts
+        .parent_span_id
+        .map(str::to_string)
+        .or(cx.map(|c| c.span_id));
+    let start_ns = parts
+        .end_ns
+        .saturating_sub(parts.latency_ms.unwrap_or(0) * 1_000_000);
+    let mut span = json!({
+        "traceId": trace_id,
+        "spanId": parts.span_id.map_or_else(|| random_hex(1), str::to_string),
+        "name": parts.name,
+        "kind": SPAN_KIND_CLIENT,
+        "startTimeUnixNano": start_ns.to_string(),
+        "endTimeUnixNano": parts.end_ns.to_string(),
+        "attributes": parts.attributes,
+    });
+    if let Some(parent) = parent {
+        span["parentSpanId"] = parent.into();
+    }
+    if let Some((kind, message)) = parts.error {
+        span["status"] = json!({"code": STATUS_ERROR, "message": message.unwrap_or(kind)});
+    }
+    span
+}
+
+/// Telemetry sink exporting events as OTLP spans; see the module docs.
+#[derive(Debug)]
+pub struct OtlpSink {
+    endpoint: String,
+    service_name: String,
+    // A plain client rather than `HttpClient`, whose own telemetry would feed back here.
+    http: reqwest::Client,
+    pending: Mutex<Vec<Value>>,
+}
+
+impl OtlpSink {
+    /// Export to the collector at `endpoint` (e.g. `http://localhost:4318`) as `service_name`.
+    pub fn new(endpoint: &str, service_name: &str) -> Self {
+        Self {
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            service_name: service_name.to_string(),
+            http: reqwest::Client::new(),
+            pending: Mutex::default(),
+        }
+    }
+
+    /// Spans waiting for the next flush.
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    fn push(&self, span: Value) {
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= MAX_PENDING {
+            pending.remove(0);
+        }
+        pending.push(span);
+    }
+
+    /// `spans` as an OTLP `ExportTraceServiceRequest`.
+    fn body(&self, spans: Vec<Value>) -> Value {
+        json!({"resourceSpans": [{
+            "resource": {"attributes": [
+                {"key": "service.name", "value": {"stringValue": self.service_name}}
+            ]},
+            "scopeSpans": [{
+                "scope": {"name": "aiproxy-core", "version": env!("CARGO_PKG_VERSION")},
+                "spans": spans,
+            }],
+        }]})
+    }
+
+    /// Send the pending spans and return how many were sent. Spans the collector did not
+    /// take are dropped rather than retried.
+    pub async fn flush(&self) -> CoreResult<usize> {
+        let spans = std::mem::take(&mut *self.pending.lock().unwrap());
+        if spans.is_empty() {
+            return Ok(0);
+        }
+        let n = spans.len();
+        let url = format!("{}/v1/traces", self.endpoint);
+        let resp = self
+            .http
+            .post(&url)
+            .json(&self.body(spans))
+            .send()
+            .await
+            .map_err(|e| AiProxyError::Other(e.into()))?;
+        if !resp.status().is_success() {
+            return Err(AiProxyError::Other(anyhow::anyhow!(
+                "OTLP export to {url} failed with HTTP {}",
+                resp.status()
+            )));
+        }
+        Ok(n)
+    }
+
+    /// Flush every `every` on a background task, for as long as the runtime runs.
+    pub fn spawn(self: Arc<Self>, every: Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(every).await;
+                if let Err(e) = self.flush().await {
+                    tracing::warn!(error = %e, "dropping spans");
+                }
+            }
+        })
+    }
+}
+
+impl TelemetrySink for OtlpSink {
+    fn record(&self, trace: ProviderTrace) {
+        let mut attributes = vec![];
+        string_attr(&mut attributes, KEY_PROVIDER, trace.provider.as_deref());
+        string_attr(&mut attributes, KEY_MODEL, trace.model.as_deref());
+        string_attr(&mut attributes, KEY_TURN_ID, trace.turn_id.as_deref());
+        string_attr(&mut attributes, KEY_REQUEST_ID, trace.request_id.as_deref());
+        string_attr(
+            &mut attributes,
+            KEY_PROVIDER_REQUEST_ID,
+            trace.provider_request_id.as_deref(),
+        );
+        let latency_ms = trace.latency_ms.map(|ms| ms as u64);
+        int_attr(&mut attributes, KEY_LATENCY_MS, latency_ms);
+        string_attr(
+            &mut attributes,
+            KEY_FINISH_REASON,
+            trace.finish_reason.as_deref(),
+        );
+        int_attr(
+            &mut attributes,
+            KEY_TOKENS_PROMPT,
+            trace.tokens_prompt.map(u64::from),
+        );
+        int_attr(
+            &mut attributes,
+            KEY_TOKENS_COMPLETION,
+            trace.tokens_completion.map(u64::from),
+        );
+        int_attr(
+            &mut attributes,
+            KEY_TOKENS_TOTAL,
+            trace.tokens_total.map(u64::from),
+        );
+        string_attr(&mut attributes, KEY_ERROR_KIND, trace.error_kind.as_deref());
+        string_attr(
+            &mut attributes,
+            KEY_ERROR_MESSAGE,
+            trace.error_message.as_deref(),
+        );
+        self.push(span(SpanParts {
+            name: "llm.provider_call",
+            span_id: None,
+            parent_span_id: None,
+            end_ns: now_ns(),
+            latency_ms,
+            attributes,
+            error: trace
+                .error_kind
+                .as_deref()
+                .map(|kind| (kind, trace.error_message.as_deref())),
+        }));
+    }
+
+    fn record_completion(&self, log: CompletionLog) {
+        let mut attributes = vec![];
+        string_attr(&mut attributes, KEY_PROVIDER, log.provider.as_deref());
+        string_attr(&mut attributes, KEY_MODEL, log.model.as_deref());
+        string_attr(&mut attributes, KEY_TURN_ID, log.turn_id.as_deref());
+        string_attr(&mut attributes, KEY_REQUEST_ID, log.request_id.as_deref());
+        string_attr(
+            &mut attributes,
+            KEY_PROVIDER_REQUEST_ID,
+            log.provider_request_id.as_deref(),
+        );
+        int_attr(&mut attributes, KEY_LATENCY_MS, log.latency_ms);
+        string_attr(
+            &mut attributes,
+            KEY_FINISH_REASON,
+            log.stop_reason.as_deref(),
+        );
+        int_attr(
+            &mut attributes,
+            KEY_TOKENS_PROMPT,
+            log.tokens_prompt.map(u64::from),
+        );
+        int_attr(
+            &mut attributes,
+            KEY_TOKENS_COMPLETION,
+            log.tokens_completion.map(u64::from),
+        );
+        int_attr(
+            &mut attributes,
+            KEY_TOKENS_TOTAL,
+            log.tokens_total.map(u64::from),
+        );
+        string_attr(&mut attributes, KEY_ERROR_KIND, log.error_kind.as_deref());
+        string_attr(
+            &mut attributes,
+            KEY_ERROR_MESSAGE,
+            log.error_message.as_deref(),
+        );
+        // Completions are logged once the response is in, so they end at `created_at_ms`.
+        let end_ns = log.created_at_ms.map_or_else(now_ns, |ms| ms * 1_000_000);
+        self.push(span(SpanParts {
+            name: log.span_name.as_deref().unwrap_or("llm.completion"),
+            span_id: log.span_id.as_deref(),
+            parent_span_id: log.parent_span_id.as_deref(),
+            end_ns,
+            latency_ms: log.latency_ms,
+            attributes,
+            error: log
+                .error_kind
+                .as_deref()
+                .map(|kind| (kind, log.error_message.as_deref())),
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::{HttpClient, RequestCtx};
+    use httpmock::{Method::POST, MockServer};
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn attr<'a>(span: &'a Value, key: &str) -> Option<&'a Value> {
+        span["attributes"]
+            .as_array()?
+            .iter()
+            .find(|a| a["key"] == key)
+            .map(|a| &a["value"])
+    }
+
+    #[test]
+    fn traceparent_round_trips_and_rejects_invalid_headers() {
+        let cx = TraceContext::parse(TRACEPARENT).unwrap();
+        assert_eq!(cx.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(cx.span_id, "00f067aa0ba902b7");
+        assert!(cx.sampled);
+        assert_eq!(cx.header(), TRACEPARENT);
+        for bad in [
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00F067AA0BA902B7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            assert_eq!(TraceContext::parse(bad), None, "{bad}");
+        }
+        let root = TraceContext::root();
+        assert!(TraceContext::parse(&root.header()).is_some());
+    }
+
+    #[tokio::test]
+    async fn completions_become_child_spans_without_content() {
+        let sink = OtlpSink::new("http://collector", "test");
+        let cx = TraceContext::parse(TRACEPARENT).unwrap();
+        scope(cx, async {
+            sink.record_completion(
+                CompletionLog::new()
+                    .provider("openai")
+                    .model("gpt-4o")
+                    .client_key_opt(Some("sk-secret"))
+                    .text_opt(Some("the answer"))
+                    .created_at_ms(2_000)
+                    .latency_ms(500)
+                    .tokens(Some(7), Some(3), Some(10)),
+            );
+        })
+        .await;
+        sink.record(
+            ProviderTrace::new()
+                .provider("http")
+                .error_kind("http_error")
+                .error_message("503"),
+        );
+
+        let pending = sink.pending.lock().unwrap();
+        let child = &pending[0];
+        assert_eq!(child["traceId"], "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(child["parentSpanId"], "00f067aa0ba902b7");
+        assert_eq!(child["startTimeUnixNano"], "1500000000");
+        assert_eq!(child["endTimeUnixNano"], "2000000000");
+        assert_eq!(attr(child, KEY_MODEL).unwrap()["stringValue"], "gpt-4o");
+        assert_eq!(attr(child, KEY_TOKENS_TOTAL).unwrap()["intValue"], "10");
+        let text = child.to_string();
+        assert!(!text.contains("sk-secret") && !text.contains("the answer"));
+
+        let root = &pending[1];
+        assert!(root.get("parentSpanId").is_none());
+        assert_ne!(root["traceId"], child["traceId"]);
+        assert_eq!(root["status"]["code"], STATUS_ERROR);
+        assert_eq!(root["status"]["message"], "503");
+    }
+
+    #[tokio::test]
+    async fn flush_posts_pending_spans_to_the_collector() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/traces")
+                .body_contains(r#""service.name""#)
+                .body_contains(r#""name":"llm.completion""#);
+            then.status(200).body("{}");
+        });
+        let sink = OtlpSink::new(&server.base_url(), "aiproxy");
+        assert_eq!(sink.flush().await.unwrap(), 0);
+        sink.record_completion(CompletionLog::new().provider("openai"));
+        assert_eq!(sink.flush().await.unwrap(), 1);
+        assert_eq!(sink.pending(), 0);
+        m.assert();
+    }
+
+    #[tokio::test]
+    async fn the_current_context_is_forwarded_upstream() {
+        let server = MockServer::start();
+        let m = server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/x")
+                .header("traceparent", TRACEPARENT);
+            then.status(200).json_body(json!({}));
+        });
+        let http = HttpClient::new_default().unwrap();
+        let url = format!("{}/v1/x", server.base_url());
+        let cx = TraceContext::parse(TRACEPARENT).unwrap();
+        scope(cx, async {
+            http.post_json::<_, Value>(&url, &json!({}), &[], &RequestCtx::default())
+                .await
+                .unwrap();
+        })
+        .await;
+        m.assert();
+    }
+}
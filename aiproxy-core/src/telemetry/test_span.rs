@@ -1,4 +1,4 @@
-#![cfg(test)]
+//! Span capture for tests: a `tracing` layer recording each span's name and fields.
 
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
@@ -101,6 +101,8 @@ where
 static GUARDS: once_cell::sync::Lazy<Mutex<Vec<tracing::subscriber::DefaultGuard>>> =
     once_cell::sync::Lazy::new(|| Mutex::new(Vec::new()));
 
+/// Capture spans on the current thread from now on; the returned store fills as they are
+/// created and recorded. The subscriber stays installed for the rest of the process.
 pub fn install_capture() -> Arc<SpanStore> {
     use tracing_subscriber::prelude::*;
     let store = Arc::new(SpanStore::default());
@@ -13,6 +13,10 @@ pub struct ProviderTrace {
     /// Model identifier, e.g. "gpt-4o", "claude-3-opus".
     pub model: Option<String>,
 
+    /// The client's name for `model` when a `routing.aliases` entry replaced it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requested_model: Option<String>,
+
     /// Your internal request id, if you generate one.
     pub request_id: Option<String>,
 
@@ -88,6 +92,14 @@ impl ProviderTrace {
         self.model = Some(model.to_string());
         self
     }
+    pub fn model_opt(mut self, model: Option<&str>) -> Self {
+        self.model = model.map(|s| s.to_string());
+        self
+    }
+    pub fn requested_model_opt(mut self, model: Option<&str>) -> Self {
+        self.requested_model = model.map(|s| s.to_string());
+        self
+    }
     pub fn request_id_opt(mut self, rid: Option<&str>) -> Self {
         self.request_id = rid.map(|s| s.to_string());
         self
@@ -123,9 +135,13 @@ impl ProviderTrace {
 pub struct CompletionLog {
     pub provider: Option<String>,
     pub model: Option<String>,
+    /// The client's name for `model` when a `routing.aliases` entry replaced it.
+    pub requested_model: Option<String>,
     pub request_id: Option<String>,
     pub turn_id: Option<String>,
     pub provider_request_id: Option<String>,
+    /// Caller's client key as given on the request; redact before persisting.
+    pub client_key: Option<String>,
     pub created_at_ms: Option<u64>,
     pub latency_ms: Option<u64>,
 
@@ -137,6 +153,14 @@ pub struct CompletionLog {
     pub tokens_prompt: Option<u32>,
     pub tokens_completion: Option<u32>,
     pub tokens_total: Option<u32>,
+    /// Estimated USD cost, as on the response; `None` for models without a known price.
+    pub cost_usd: Option<f64>,
+
+    /// Attempts after the first, with why each one was made (e.g. `rate_limited`, `503`).
+    pub retries: u32,
+    pub retry_reasons: Vec<String>,
+    /// Time spent waiting between attempts.
+    pub backoff_ms: u64,
 
     pub span_name: Option<String>,
     pub span_id: Option<String>,
@@ -147,9 +171,11 @@ impl CompletionLog {
     pub fn new() -> Self { Self::default() }
     pub fn provider(mut self, v: &str) -> Self { self.provider = Some(v.to_string()); self }
     pub fn model(mut self, v: &str) -> Self { self.model = Some(v.to_string()); self }
+    pub fn requested_model_opt(mut self, v: Option<&str>) -> Self { self.requested_model = v.map(|s| s.to_string()); self }
     pub fn request_id_opt(mut self, v: Option<&str>) -> Self { self.request_id = v.map(|s| s.to_string()); self }
     pub fn turn_id_opt(mut self, v: Option<&str>) -> Self { self.turn_id = v.map(|s| s.to_string()); self }
     pub fn provider_request_id_opt(mut self, v: Option<&str>) -> Self { self.provider_request_id = v.map(|s| s.to_string()); self }
+    pub fn client_key_opt(mut self, v: Option<&str>) -> Self { self.client_key = v.map(|s| s.to_string()); self }
     pub fn created_at_ms(mut self, v: u64) -> Self { self.created_at_ms = Some(v); self }
     pub fn latency_ms(mut self, v: u64) -> Self { self.latency_ms = Some(v); self }
     pub fn stop_reason_opt(mut self, v: Option<&str>) -> Self { self.stop_reason = v.map(|s| s.to_string()); self }
@@ -159,6 +185,10 @@ impl CompletionLog {
     pub fn tokens(mut self, p: Option<u32>, c: Option<u32>, t: Option<u32>) -> Self {
         self.tokens_prompt = p; self.tokens_completion = c; self.tokens_total = t; self
     }
+    pub fn cost_usd_opt(mut self, v: Option<f64>) -> Self { self.cost_usd = v; self }
+    pub fn retried(mut self, reasons: Vec<String>, backoff_ms: u64) -> Self {
+        self.retries = reasons.len() as u32; self.retry_reasons = reasons; self.backoff_ms = backoff_ms; self
+    }
     pub fn span(mut self, name: Option<&str>, id: Option<&str>, parent: Option<&str>) -> Self {
         self.span_name = name.map(|s| s.to_string());
         self.span_id = id.map(|s| s.to_string());
@@ -167,6 +197,56 @@ impl CompletionLog {
     }
 }
 
+/// An output guardrail tripping on a response, emitted once per guarded call with hits.
+#[derive(Debug, Clone, Serialize)]
+pub struct GuardrailLog {
+    pub provider: String,
+    pub model: String,
+    pub action: crate::config::GuardrailAction,
+    pub hits: Vec<crate::model::GuardrailHit>,
+}
+
+/// One response cache lookup (`providers::caching`), emitted per cacheable call.
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheLog {
+    pub provider: String,
+    pub model: String,
+    /// `chat` or `embed`.
+    pub kind: &'static str,
+    pub hit: bool,
+}
+
+/// A provider's circuit breaker (`providers::circuit`) after a transition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail at once, without reaching the provider.
+    Open,
+    /// The cooldown is over; one call goes through to test the provider.
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Closed => "closed",
+            Self::Open => "open",
+            Self::HalfOpen => "half_open",
+        }
+    }
+}
+
+/// One circuit breaker transition, emitted as it happens.
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitLog {
+    pub provider: String,
+    pub state: CircuitState,
+    /// Consecutive failures counted when the transition happened.
+    pub failures: u32,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
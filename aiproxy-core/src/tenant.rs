@@ -0,0 +1,206 @@
+//! Multi-tenancy: one proxy process serving several isolated customers. Each client key
+//! resolves to at most one `Tenant`, whose requests use that tenant's own provider
+//! credentials, routing rules and shared budget. Callers whose key belongs to no tenant get
+//! the process-wide registry and routing as before.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::{Config, RoutingCfg, TenantCfg};
+use crate::error::{AiProxyError, CoreResult};
+use crate::limits::{KeyLimits, Limiter};
+use crate::provider_factory::ProviderRegistry;
+use crate::router::RoutingResolver;
+
+/// One configured tenant, resolved once at startup.
+pub struct Tenant {
+    pub name: String,
+    /// Client keys that resolve to this tenant.
+    pub keys: Vec<String>,
+    /// Providers built with the tenant's credentials.
+    pub registry: ProviderRegistry,
+    /// The tenant's rules ahead of the global ones.
+    pub router: RoutingResolver,
+    /// Budget shared by all of the tenant's keys, counted under the tenant name.
+    pub limiter: Limiter,
+}
+
+impl Tenant {
+    fn from_cfg(
+        name: &str,
+        tenant: &TenantCfg,
+        cfg: &Config,
+        env: &impl Fn(&str) -> Option<String>,
+    ) -> CoreResult<Self> {
+        let unset = |field: &str, var: &str| {
+            AiProxyError::Validation(format!("tenants.{name}.{field}: ${var} is not set"))
+        };
+        let keys: Vec<String> = env(&tenant.keys_env)
+            .ok_or_else(|| unset("keys_env", &tenant.keys_env))?
+            .split(',')
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+        let mut credentials = HashMap::new();
+        for (provider, var) in &tenant.api_key_env {
+            let key = env(var).ok_or_else(|| unset(&format!("api_key_env.{provider}"), var))?;
+            credentials.insert(provider.as_str(), key);
+        }
+        let registry = ProviderRegistry::from_config_with_keys(cfg, |provider| {
+            credentials
+                .get(provider)
+                .cloned()
+                .or_else(|| env(&format!("{}_API_KEY", provider.to_ascii_uppercase())))
+        })?;
+        let router = RoutingResolver::from_routing(&RoutingCfg {
+            default: tenant
+                .routing_default
+                .clone()
+                .unwrap_or_else(|| cfg.routing.default.clone()),
+            rules: tenant
+                .routing
+                .iter()
+                .chain(&cfg.routing.rules)
+                .cloned()
+                .collect(),
+            aliases: cfg.routing.aliases.clone(),
+        })?;
+        Ok(Self {
+            name: name.to_string(),
+            keys,
+            registry,
+            router,
+            limiter: Limiter::new(KeyLimits {
+                rpm: tenant.rpm,
+                tpm: tenant.tpm,
+                daily_tokens: tenant.daily_tokens,
+            }),
+        })
+    }
+}
+
+/// Every configured tenant, indexed by client key.
+#[derive(Default)]
+pub struct Tenants {
+    by_key: HashMap<String, Arc<Tenant>>,
+    all: Vec<Arc<Tenant>>,
+}
+
+impl Tenants {
+    /// Resolve `cfg.tenants`, reading keys and credentials from the environment.
+    pub fn from_config(cfg: &Config) -> CoreResult<Self> {
+        Self::from_config_with_env(cfg, |var| std::env::var(var).ok())
+    }
+
+    /// As `from_config`, with env vars looked up through `env`.
+    pub fn from_config_with_env(
+        cfg: &Config,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> CoreResult<Self> {
+        let mut out = Self::default();
+        for (name, tenant_cfg) in &cfg.tenants {
+            let tenant = Arc::new(Tenant::from_cfg(name, tenant_cfg, cfg, &env)?);
+            for key in &tenant.keys {
+                if let Some(other) = out.by_key.insert(key.clone(), tenant.clone()) {
+                    return Err(AiProxyError::Validation(format!(
+                        "a client key belongs to both tenants '{}' and '{name}'",
+                        other.name
+                    )));
+                }
+            }
+            out.all.push(tenant);
+        }
+        Ok(out)
+    }
+
+    /// The tenant `client_key` belongs to, if any.
+    pub fn resolve(&self, client_key: &str) -> Option<Arc<Tenant>> {
+        self.by_key.get(client_key).cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Tenant>> {
+        self.all.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.all.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RoutingRule;
+
+    fn cfg() -> Config {
+        let mut cfg = crate::test_util::minimal_config();
+        cfg.tenants.insert(
+            "acme".into(),
+            TenantCfg {
+                keys_env: "ACME_KEYS".into(),
+                api_key_env: [("openai".to_string(), "ACME_OPENAI_KEY".to_string())].into(),
+                routing: vec![RoutingRule {
+                    model: "^gpt-".into(),
+                    provider: "openai".into(),
+                    providers: vec![],
+                    split: vec![],
+                    shadow: None,
+                }],
+                daily_tokens: Some(1_000),
+                ..TenantCfg::default()
+            },
+        );
+        cfg.tenants.insert(
+            "globex".into(),
+            TenantCfg {
+                keys_env: "GLOBEX_KEYS".into(),
+                ..TenantCfg::default()
+            },
+        );
+        cfg
+    }
+
+    fn env(var: &str) -> Option<String> {
+        match var {
+            "ACME_KEYS" => Some("acme-1, acme-2".into()),
+            "ACME_OPENAI_KEY" => Some(format!("sk-{}", "a".repeat(40))),
+            "GLOBEX_KEYS" => Some("globex-1".into()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn keys_resolve_to_their_tenant_with_its_own_providers_and_routes() {
+        let tenants = Tenants::from_config_with_env(&cfg(), env).unwrap();
+        let acme = tenants.resolve("acme-2").unwrap();
+        assert_eq!(acme.name, "acme");
+        assert!(acme.registry.chat("openai").is_some());
+        assert_eq!(acme.router.pick_provider_name("gpt-4o"), "openai");
+        assert_eq!(acme.router.pick_provider_name("other"), "null");
+        assert_eq!(acme.limiter.limits().daily_tokens, Some(1_000));
+
+        let globex = tenants.resolve("globex-1").unwrap();
+        assert!(globex.registry.chat("openai").is_none());
+        assert_eq!(globex.router.pick_provider_name("gpt-4o"), "null");
+        assert!(tenants.resolve("stranger").is_none());
+        assert_eq!(tenants.iter().count(), 2);
+    }
+
+    #[test]
+    fn unset_credentials_and_shared_keys_are_rejected() {
+        let missing = |var: &str| (var != "ACME_OPENAI_KEY").then(|| env(var)).flatten();
+        let Err(err) = Tenants::from_config_with_env(&cfg(), missing) else {
+            panic!("tenants resolved");
+        };
+        assert!(err.to_string().contains("tenants.acme.api_key_env.openai"));
+
+        let shared = |var: &str| match var {
+            "GLOBEX_KEYS" => Some("acme-1".into()),
+            _ => env(var),
+        };
+        let Err(err) = Tenants::from_config_with_env(&cfg(), shared) else {
+            panic!("tenants resolved");
+        };
+        assert!(err.to_string().contains("both tenants 'acme' and 'globex'"));
+    }
+}
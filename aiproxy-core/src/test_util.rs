@@ -1,9 +1,19 @@
-#![cfg(test)]
+//! Test scaffolding shared by this crate's tests and, with the `test-utils` feature, by
+//! downstream integration tests: a capturing telemetry sink, a provider recording the models
+//! it is asked for, request builders and registry constructors that need no environment
+//! variables. Span capture lives in `telemetry::test_span`.
 
 use std::sync::{Arc, Mutex};
 
+use async_trait::async_trait;
 use once_cell::sync::Lazy;
 
+use crate::config::{CacheCfg, Config, FsyncPolicy, Providers, RoutingCfg, TranscriptCfg};
+use crate::error::CoreResult;
+use crate::model::{ChatRequest, ChatResponse, EmbedRequest, EmbedResponse};
+use crate::provider::{ChatProvider, EmbedProvider, NullProvider};
+use crate::provider_factory::ProviderRegistry;
+use crate::providers::openai::OpenAI;
 use crate::telemetry::{self, ProviderTrace, TelemetrySink};
 
 // Shared storage for ProviderTrace events emitted during tests
@@ -22,7 +32,9 @@ impl TelemetrySink for TestTraceSink {
 /// Install the global trace sink (idempotent) and enable capture for this thread.
 pub fn install_trace_sink() {
     // Try installing; ignore if already set in this process
-    let _ = telemetry::set_telemetry_sink(Arc::new(TestTraceSink::default()));
+    let _ = telemetry::set_telemetry_sink(Arc::new(TestTraceSink));
+    // Outside this crate's own tests every thread emits, so there is nothing to enable.
+    #[cfg(test)]
     telemetry::test_set_capture_enabled(true);
     clear_traces();
 }
@@ -42,3 +54,116 @@ pub fn find_trace<F: Fn(&ProviderTrace) -> bool>(pred: F) -> Option<ProviderTrac
         .cloned()
 }
 
+/// `NullProvider`, named `seen`, recording the model and requested model of each chat and
+/// embedding request it gets.
+#[derive(Debug, Default)]
+pub struct Seen(Mutex<Vec<(String, Option<String>)>>);
+
+impl Seen {
+    /// The models asked for so far, oldest first.
+    pub fn models(&self) -> Vec<String> {
+        self.requests().into_iter().map(|(m, _)| m).collect()
+    }
+
+    /// As `models`, each with the request's `requested_model`.
+    pub fn requests(&self) -> Vec<(String, Option<String>)> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn push(&self, model: &str, requested: &Option<String>) {
+        let names = (model.to_string(), requested.clone());
+        self.0.lock().unwrap().push(names);
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl ChatProvider for Seen {
+    fn name(&self) -> &str {
+        "seen"
+    }
+
+    async fn chat(&self, req: ChatRequest) -> CoreResult<ChatResponse> {
+        self.push(&req.model, &req.requested_model);
+        NullProvider.chat(req).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl EmbedProvider for Seen {
+    fn name(&self) -> &str {
+        "seen"
+    }
+
+    async fn embed(&self, req: EmbedRequest) -> CoreResult<EmbedResponse> {
+        self.push(&req.model, &req.requested_model);
+        NullProvider.embed(req).await
+    }
+}
+
+/// A config that registers only the `null` provider and routes everything to it, with an
+/// in-memory cache and every optional section at its default.
+pub fn minimal_config() -> Config {
+    Config {
+        providers: Providers {
+            openai: None,
+            anthropic: None,
+            openrouter: None,
+            hash: None,
+            ollama: None,
+            replay: None,
+        },
+        cache: CacheCfg {
+            path: ":memory:".into(),
+            ttl_seconds: if cfg!(feature = "native") { 60 } else { 0 },
+        },
+        transcript: TranscriptCfg {
+            dir: ".tx".into(),
+            segment_mb: 64,
+            fsync: FsyncPolicy::Commit,
+            redact_builtin: true,
+        },
+        routing: RoutingCfg {
+            default: "null".into(),
+            rules: vec![],
+            aliases: Default::default(),
+        },
+        http: Default::default(),
+        privacy: Default::default(),
+        truncation: Default::default(),
+        screening: Default::default(),
+        guardrail: Default::default(),
+        summarization: Default::default(),
+        validation: Default::default(),
+        embeddings: Default::default(),
+        dedup: Default::default(),
+        catalog: Default::default(),
+        pricing: Default::default(),
+        chaos: Default::default(),
+        tenants: Default::default(),
+    }
+}
+
+/// A one-turn chat request.
+pub fn chat_request(model: &str, prompt: &str) -> ChatRequest {
+    ChatRequest::builder().model(model).user(prompt).build()
+}
+
+/// An embedding request for `inputs`.
+pub fn embed_request<S: Into<String>>(
+    model: &str,
+    inputs: impl IntoIterator<Item = S>,
+) -> EmbedRequest {
+    EmbedRequest::builder().model(model).inputs(inputs).build()
+}
+
+/// The OpenAI adapter pointed at `base_url` (e.g. a mock server) with a dummy key.
+pub fn mock_openai(base_url: &str) -> Arc<OpenAI> {
+    Arc::new(OpenAI::new_for_tests(base_url))
+}
+
+/// A registry with `null` and an OpenAI adapter pointed at `base_url`.
+pub fn mock_openai_registry(base_url: &str) -> ProviderRegistry {
+    ProviderRegistry::with_openai_for_tests(mock_openai(base_url))
+}
@@ -0,0 +1,222 @@
+//! Prompt token counting.
+//!
+//! OpenAI-family models (`gpt-*`, `o1`/`o3`/`o4`, OpenAI embeddings, also behind an `openai/`
+//! routing prefix) are counted with their tiktoken encoding. Other models fall back to an
+//! estimate of about four characters per token. Both add a fixed overhead per message for the
+//! role and framing tokens providers add. Counts are meant for budgeting, not billing;
+//! providers' reported usage remains the source of truth, and `ChatProvider::count_tokens`
+//! gives an exact count where a provider offers one.
+
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::CoreBPE;
+use tiktoken_rs::tokenizer::{Tokenizer, get_tokenizer};
+
+use crate::model::{ChatMessage, ChatRequest};
+use crate::provider::ChatProvider;
+
+/// Tokens a provider spends framing each message (role markers, separators).
+pub const MESSAGE_OVERHEAD: usize = 4;
+
+/// Characters per token assumed by the estimate.
+pub const CHARS_PER_TOKEN: usize = 4;
+
+/// How tokens are counted for a model.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Counter {
+    /// tiktoken `o200k_base` (GPT-4o and later).
+    O200kBase,
+    /// tiktoken `cl100k_base` (GPT-4, GPT-3.5, ada-002 embeddings).
+    Cl100kBase,
+    /// About four characters per token.
+    Heuristic,
+}
+
+impl Counter {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::O200kBase => "o200k_base",
+            Self::Cl100kBase => "cl100k_base",
+            Self::Heuristic => "heuristic",
+        }
+    }
+
+    fn bpe(self) -> Option<&'static CoreBPE> {
+        match self {
+            Self::O200kBase => Some(tiktoken_rs::o200k_base_singleton()),
+            Self::Cl100kBase => Some(tiktoken_rs::cl100k_base_singleton()),
+            Self::Heuristic => None,
+        }
+    }
+}
+
+/// The counter used for `model`: the catalog's tokenizer when it names one, else tiktoken's.
+pub fn counter_for(model: &str) -> Counter {
+    if let Some(counter) = crate::catalog::tokenizer(model) {
+        return counter;
+    }
+    let model = model.strip_prefix("openai/").unwrap_or(model);
+    match get_tokenizer(model) {
+        Some(Tokenizer::O200kBase) => Counter::O200kBase,
+        Some(Tokenizer::Cl100kBase) => Counter::Cl100kBase,
+        // Older encodings are for retired completion models; estimate those.
+        Some(_) => Counter::Heuristic,
+        // OpenAI models newer than the tiktoken table use its latest encoding.
+        None if model.starts_with("gpt-") => Counter::O200kBase,
+        None => Counter::Heuristic,
+    }
+}
+
+fn count_with(counter: Counter, text: &str) -> usize {
+    match counter.bpe() {
+        Some(bpe) => bpe.encode_ordinary(text).len(),
+        None => text.chars().count().div_ceil(CHARS_PER_TOKEN),
+    }
+}
+
+/// Tokens in `text` for `model`.
+pub fn count_text(model: &str, text: &str) -> usize {
+    count_with(counter_for(model), text)
+}
+
+/// Tokens one message contributes to the prompt, framing included.
+pub fn count_message(model: &str, message: &ChatMessage) -> usize {
+    MESSAGE_OVERHEAD + count_text(model, &message.content.text())
+}
+
+/// Prompt tokens for `messages` sent to `model`.
+pub fn count_tokens(model: &str, messages: &[ChatMessage]) -> usize {
+    let counter = counter_for(model);
+    messages
+        .iter()
+        .map(|m| MESSAGE_OVERHEAD + count_with(counter, &m.content.text()))
+        .sum()
+}
+
+/// A prompt's size, and whether the provider counted it exactly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PromptCount {
+    pub tokens: u32,
+    pub exact: bool,
+}
+
+/// Prompt tokens for `req` sent to `provider`. With `exact`, a provider with a counting
+/// endpoint (Anthropic) is asked first; otherwise, or if it has none or the call fails, the
+/// prompt is counted locally with `count_tokens`.
+pub async fn count_prompt(
+    provider: &dyn ChatProvider,
+    req: &ChatRequest,
+    exact: bool,
+) -> PromptCount {
+    if exact {
+        match provider.count_tokens(req).await {
+            Ok(Some(tokens)) => {
+                return PromptCount {
+                    tokens,
+                    exact: true,
+                };
+            }
+            Ok(None) => {}
+            Err(e) => {
+                tracing::debug!(provider = provider.name(), error = %e, "token count failed; estimating")
+            }
+        }
+    }
+    PromptCount {
+        tokens: count_tokens(&req.model, &req.messages) as u32,
+        exact: false,
+    }
+}
+
+/// The longest suffix of `text` that fits in `max_tokens` for `model`.
+pub fn tail_within<'a>(model: &str, text: &'a str, max_tokens: usize) -> &'a str {
+    let counter = counter_for(model);
+    let starts: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    // Binary search for the earliest char start whose suffix fits. A longer suffix almost
+    // always takes at least as many tokens; where BPE merges break that, the result still
+    // fits but may be a few characters shorter than it could be.
+    let (mut lo, mut hi) = (0, starts.len());
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if count_with(counter, &text[starts[mid]..]) <= max_tokens {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    starts.get(lo).map_or("", |&i| &text[i..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_round_up_and_include_framing() {
+        assert_eq!(count_text("m", ""), 0);
+        assert_eq!(count_text("m", "abcde"), 2);
+        let messages = [ChatMessage::user("abcd"), ChatMessage::assistant("")];
+        assert_eq!(count_tokens("m", &messages), 1 + 2 * MESSAGE_OVERHEAD);
+    }
+
+    /// Counts every prompt as 99 tokens.
+    #[derive(Debug)]
+    struct Counting;
+
+    #[async_trait::async_trait]
+    impl ChatProvider for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        async fn chat(
+            &self,
+            req: ChatRequest,
+        ) -> crate::error::CoreResult<crate::model::ChatResponse> {
+            crate::provider::NullProvider.chat(req).await
+        }
+
+        async fn count_tokens(&self, _req: &ChatRequest) -> crate::error::CoreResult<Option<u32>> {
+            Ok(Some(99))
+        }
+    }
+
+    #[tokio::test]
+    async fn prompt_counts_are_exact_only_when_asked_and_available() {
+        let req = ChatRequest::builder().model("m").user("abcd").build();
+        let estimate = PromptCount {
+            tokens: 1 + MESSAGE_OVERHEAD as u32,
+            exact: false,
+        };
+        assert_eq!(count_prompt(&Counting, &req, false).await, estimate);
+        assert_eq!(
+            count_prompt(&Counting, &req, true).await,
+            PromptCount {
+                tokens: 99,
+                exact: true
+            }
+        );
+        let null = crate::provider::NullProvider;
+        assert_eq!(count_prompt(&null, &req, true).await, estimate);
+    }
+
+    #[test]
+    fn openai_models_use_tiktoken() {
+        assert_eq!(counter_for("gpt-4o-mini"), Counter::O200kBase);
+        assert_eq!(counter_for("openai/gpt-4o"), Counter::O200kBase);
+        assert_eq!(counter_for("gpt-4-0613"), Counter::Cl100kBase);
+        assert_eq!(counter_for("gpt-99"), Counter::O200kBase);
+        assert_eq!(counter_for("claude-3-5-sonnet"), Counter::Heuristic);
+        assert_eq!(count_text("gpt-4o", "hello world"), 2);
+        assert_eq!(count_text("gpt-4", "hello world"), 2);
+    }
+
+    #[test]
+    fn tail_keeps_the_most_recent_text_within_budget() {
+        assert_eq!(tail_within("m", "abcdefghij", 2), "cdefghij");
+        assert_eq!(tail_within("m", "abc", 0), "");
+        assert_eq!(tail_within("m", "héllo", 10), "héllo");
+        let tail = tail_within("gpt-4o", "one two three four five", 2);
+        assert_eq!(tail, " four five");
+    }
+}
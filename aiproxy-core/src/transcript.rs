@@ -0,0 +1,408 @@
+//! Transcripts configured by `[transcript]`: an append-only JSONL record of every chat and
+//! embed call, request and response (or error) together.
+//!
+//! Records go to numbered segments, `<dir>/transcripts/000001.jsonl` and up. A new segment
+//! starts once the current one would pass `segment_mb`. Each record's id is
+//! `<segment>:<byte offset>`, so a `ChatResponse.transcript_id` leads straight to its line.
+//!
+//! Requests never carry the caller's key, only its redacted label. With `redact_builtin`,
+//! every string in the record is also passed through `telemetry::access::redact`. Embedding
+//! vectors are not written, only their count and dimensions.
+//!
+//! A background thread does the writing, so `append` only assigns the id and queues the
+//! line; `flush` waits for the queue to drain. `fsync` decides durability. `always` syncs
+//! every record. `commit` syncs when a segment is closed, on `flush` and when the writer is
+//! dropped, which also waits for queued records. `off` leaves flushing to the OS.
+//!
+//! Ids are offsets the line will have once every queued line is written. If a write fails
+//! they no longer match the file, so the writer stops: the thread drops every later line and
+//! `append` returns an error instead of an id.
+//!
+//! Nothing is recorded until an application opens a writer and passes it to `install`.
+//! `ProviderRegistry::from_config` then wraps every chat and embed provider it builds in a
+//! `providers::recording::RecordingProvider`.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread::JoinHandle;
+
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::config::{FsyncPolicy, TranscriptCfg};
+use crate::error::{AiProxyError, CoreResult};
+use crate::telemetry::access::{redact, redact_json};
+use crate::usage::key_label;
+
+/// One call as written to a segment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptRecord {
+    /// `<segment>:<byte offset>`, assigned by `TranscriptWriter::append`.
+    #[serde(default)]
+    pub id: String,
+    pub ts_ms: u64,
+    /// `chat`, `chat_stream` or `embed`.
+    pub kind: String,
+    pub provider: String,
+    pub model: String,
+    pub latency_ms: u64,
+    pub request: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TranscriptRecord {
+    /// A record of `request` (any serializable request type) sent to `provider`, with the
+    /// caller's key replaced by its label.
+    pub fn new(kind: &str, provider: &str, model: &str, request: &impl Serialize) -> Self {
+        let mut request = serde_json::to_value(request).unwrap_or(Value::Null);
+        if let Some(key) = request.get("client_key").and_then(Value::as_str) {
+            request["client_key"] = key_label(key).into();
+        }
+        Self {
+            id: String::new(),
+            ts_ms: crate::platform::SystemTime::now()
+                .duration_since(crate::platform::UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            kind: kind.to_string(),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            latency_ms: 0,
+            request,
+            response: None,
+            error: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Segment {
+    number: u32,
+    file: File,
+    size: u64,
+}
+
+/// Where the next line goes: the segment `append` last queued to and its size once every
+/// queued line is written.
+#[derive(Debug)]
+struct Position {
+    number: u32,
+    size: u64,
+}
+
+/// Work for the writer thread.
+enum Job {
+    /// Append `line` to segment `number`, opening it first if it is not the current one.
+    Line { number: u32, line: Vec<u8> },
+    /// Reply once every earlier line is written.
+    Flush(mpsc::Sender<()>),
+}
+
+/// `record` as the next line at `pos`. The id is part of the line, so the line is built
+/// again if the record moves to another segment.
+fn place(record: &mut TranscriptRecord, pos: &Position) -> CoreResult<Vec<u8>> {
+    record.id = format!("{:06}:{}", pos.number, pos.size);
+    let mut line = serde_json::to_vec(record).map_err(|e| AiProxyError::Other(e.into()))?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+fn stopped() -> AiProxyError {
+    std::io::Error::other("transcript writer thread stopped").into()
+}
+
+fn failed() -> AiProxyError {
+    std::io::Error::other("transcript writer stopped after a write error").into()
+}
+
+/// Appends `TranscriptRecord`s to rotating segments; shared by every provider it records.
+#[derive(Debug)]
+pub struct TranscriptWriter {
+    dir: PathBuf,
+    max_bytes: u64,
+    redact: bool,
+    position: Mutex<Position>,
+    /// Set by the thread when a write fails.
+    failed: Arc<AtomicBool>,
+    /// Taken on drop, which ends the thread.
+    jobs: Option<mpsc::Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TranscriptWriter {
+    /// Open `<cfg.dir>/transcripts`, creating it if needed, and continue the newest segment.
+    pub fn open(cfg: &TranscriptCfg) -> CoreResult<Self> {
+        let dir = Path::new(&cfg.dir).join("transcripts");
+        std::fs::create_dir_all(&dir)?;
+        let newest = std::fs::read_dir(&dir)?
+            .filter_map(|e| {
+                let name = e.ok()?.file_name();
+                name.to_str()?.strip_suffix(".jsonl")?.parse::<u32>().ok()
+            })
+            .max()
+            .unwrap_or(1);
+        let seg = Self::open_segment(&dir, newest)?;
+        let position = Position {
+            number: seg.number,
+            size: seg.size,
+        };
+        let (jobs, queue) = mpsc::channel();
+        let failed = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name("aiproxy-transcript".into())
+            .spawn({
+                let (dir, fsync, failed) = (dir.clone(), cfg.fsync.clone(), failed.clone());
+                move || write_segments(&dir, seg, &fsync, &failed, queue)
+            })?;
+        Ok(Self {
+            dir,
+            max_bytes: u64::from(cfg.segment_mb.max(1)) * 1024 * 1024,
+            redact: cfg.redact_builtin,
+            position: Mutex::new(position),
+            failed,
+            jobs: Some(jobs),
+            thread: Some(thread),
+        })
+    }
+
+    fn open_segment(dir: &Path, number: u32) -> CoreResult<Segment> {
+        let path = dir.join(format!("{number:06}.jsonl"));
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+        Ok(Segment { number, file, size })
+    }
+
+    /// The directory segments are written to.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn segment_path(&self, number: u32) -> PathBuf {
+        self.dir.join(format!("{number:06}.jsonl"))
+    }
+
+    /// Queue `record` for writing, to a new segment if it would overflow the current one,
+    /// and return the id it was given. Fails once an earlier write has failed.
+    pub fn append(&self, mut record: TranscriptRecord) -> CoreResult<String> {
+        if self.redact {
+            redact_json(&mut record.request);
+            if let Some(response) = &mut record.response {
+                redact_json(response);
+            }
+            record.error = record.error.as_deref().map(redact);
+        }
+        // Queued under the lock, so lines reach the thread in offset order.
+        let mut pos = self.position.lock().unwrap();
+        if self.failed.load(Ordering::Acquire) {
+            return Err(failed());
+        }
+        let mut line = place(&mut record, &pos)?;
+        if pos.size > 0 && pos.size + line.len() as u64 > self.max_bytes {
+            *pos = Position {
+                number: pos.number + 1,
+                size: 0,
+            };
+            line = place(&mut record, &pos)?;
+        }
+        let size = line.len() as u64;
+        self.send(Job::Line {
+            number: pos.number,
+            line,
+        })?;
+        pos.size += size;
+        Ok(record.id)
+    }
+
+    /// Block until every record appended so far is written, and synced unless `fsync` is
+    /// `off`.
+    pub fn flush(&self) -> CoreResult<()> {
+        let (done, wait) = mpsc::channel();
+        self.send(Job::Flush(done))?;
+        wait.recv().map_err(|_| stopped())
+    }
+
+    fn send(&self, job: Job) -> CoreResult<()> {
+        let jobs = self.jobs.as_ref().expect("jobs is only taken on drop");
+        jobs.send(job).map_err(|_| stopped())
+    }
+}
+
+impl Drop for TranscriptWriter {
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The writer thread: every queued line, in order, then a last sync under `commit`. After a
+/// failed write the lines left in the queue are dropped, since their ids are wrong.
+fn write_segments(
+    dir: &Path,
+    mut seg: Segment,
+    fsync: &FsyncPolicy,
+    failed: &AtomicBool,
+    queue: mpsc::Receiver<Job>,
+) {
+    for job in queue {
+        match job {
+            Job::Line { .. } if failed.load(Ordering::Acquire) => {}
+            Job::Line { number, line } => {
+                if let Err(e) = write_line(dir, &mut seg, fsync, number, &line) {
+                    tracing::warn!(error = %e, segment = number, "transcript writer stopped");
+                    failed.store(true, Ordering::Release);
+                }
+            }
+            Job::Flush(done) => {
+                if *fsync != FsyncPolicy::Off {
+                    let _ = seg.file.sync_data();
+                }
+                let _ = done.send(());
+            }
+        }
+    }
+    if *fsync != FsyncPolicy::Off {
+        let _ = seg.file.sync_data();
+    }
+}
+
+fn write_line(
+    dir: &Path,
+    seg: &mut Segment,
+    fsync: &FsyncPolicy,
+    number: u32,
+    line: &[u8],
+) -> CoreResult<()> {
+    if number != seg.number {
+        if *fsync != FsyncPolicy::Off {
+            seg.file.sync_data()?;
+        }
+        *seg = TranscriptWriter::open_segment(dir, number)?;
+    }
+    seg.file.write_all(line)?;
+    seg.size += line.len() as u64;
+    if *fsync == FsyncPolicy::Always {
+        seg.file.sync_data()?;
+    }
+    Ok(())
+}
+
+/// Every record in the segment at `path`, in order.
+pub fn read_segment(path: &Path) -> CoreResult<Vec<TranscriptRecord>> {
+    let mut records = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        records.push(
+            serde_json::from_str(&line).map_err(|e| {
+                AiProxyError::Validation(format!("{}:{}: {e}", path.display(), i + 1))
+            })?,
+        );
+    }
+    Ok(records)
+}
+
+static WRITER: OnceCell<Arc<TranscriptWriter>> = OnceCell::new();
+
+/// Record the calls of every registry built from now on. Returns `false` if a writer is
+/// already installed.
+pub fn install(writer: Arc<TranscriptWriter>) -> bool {
+    WRITER.set(writer).is_ok()
+}
+
+/// The installed writer, if any.
+pub fn installed() -> Option<Arc<TranscriptWriter>> {
+    WRITER.get().cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::ChatRequest;
+
+    fn cfg(dir: &Path, segment_mb: u32) -> TranscriptCfg {
+        TranscriptCfg {
+            dir: dir.to_string_lossy().into_owned(),
+            segment_mb,
+            fsync: FsyncPolicy::Always,
+            redact_builtin: true,
+        }
+    }
+
+    fn record(text: &str) -> TranscriptRecord {
+        let req = ChatRequest::builder()
+            .model("m")
+            .user(text)
+            .client_key("sk-secret-key-1234")
+            .build();
+        let mut rec = TranscriptRecord::new("chat", "null", "m", &req);
+        rec.response = Some(serde_json::json!({"text": "ok"}));
+        rec
+    }
+
+    #[test]
+    fn records_are_addressed_by_segment_and_offset() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = TranscriptWriter::open(&cfg(dir.path(), 64)).unwrap();
+        let first = writer.append(record("hi")).unwrap();
+        let second = writer.append(record("mail me at a@example.com")).unwrap();
+        assert_eq!(first, "000001:0");
+        writer.flush().unwrap();
+        let saved = read_segment(&writer.segment_path(1)).unwrap();
+        assert_eq!(saved.len(), 2);
+        assert_eq!(saved[1].id, second);
+        let offset: usize = second.split_once(':').unwrap().1.parse().unwrap();
+        let raw = std::fs::read_to_string(writer.segment_path(1)).unwrap();
+        assert!(raw[offset..].starts_with(&format!("{{\"id\":\"{second}\"")));
+
+        assert_eq!(saved[0].request["client_key"], "***1234");
+        let text = saved[1].request["messages"][0]["content"].to_string();
+        assert!(!text.contains("a@example.com"), "{text}");
+    }
+
+    #[test]
+    fn segments_rotate_and_reopening_continues_the_newest() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = TranscriptWriter::open(&cfg(dir.path(), 1)).unwrap();
+        let big = "x".repeat(400 * 1024);
+        let ids: Vec<String> = (0..3)
+            .map(|_| writer.append(record(&big)).unwrap())
+            .collect();
+        let segments: Vec<&str> = ids.iter().map(|id| &id[..6]).collect();
+        assert_eq!(segments, ["000001", "000001", "000002"]);
+        drop(writer);
+
+        let reopened = TranscriptWriter::open(&cfg(dir.path(), 1)).unwrap();
+        assert!(
+            reopened
+                .append(record("hi"))
+                .unwrap()
+                .starts_with("000002:")
+        );
+        reopened.flush().unwrap();
+        assert_eq!(read_segment(&reopened.segment_path(2)).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn no_ids_are_issued_after_a_failed_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = TranscriptWriter::open(&cfg(dir.path(), 1)).unwrap();
+        let big = "x".repeat(600 * 1024);
+        writer.append(record(&big)).unwrap();
+        writer.flush().unwrap();
+        // The next record starts segment 2, which can no longer be created.
+        std::fs::remove_dir_all(writer.dir()).unwrap();
+        writer.append(record(&big)).unwrap();
+        writer.flush().unwrap();
+        assert!(writer.append(record("hi")).is_err());
+    }
+}
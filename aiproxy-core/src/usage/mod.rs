@@ -0,0 +1,282 @@
+//! Usage accounting fed by completion telemetry, and spend summaries over it.
+//!
+//! Each completed call becomes one record, appended as an NDJSON line by `UsageLog` or stored
+//! as a row by the SQLite-backed `store::UsageStore` (feature `native`). Client keys are
+//! stored only as a redacted tail (`***abcd`) so either is safe to keep next to transcripts.
+
+#[cfg(feature = "native")]
+pub mod store;
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AiProxyError, CoreResult};
+use crate::pricing;
+use crate::telemetry::{CompletionLog, ProviderTrace, TelemetrySink};
+
+/// File name of the usage log inside the transcript directory.
+pub const USAGE_FILE: &str = "usage.ndjson";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub ts_ms: u64,
+    pub provider: String,
+    pub model: String,
+    pub client_key: Option<String>,
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+    pub cost_usd: Option<f64>,
+}
+
+/// Redacted form of a client key: its last four characters.
+pub fn key_label(key: &str) -> String {
+    let tail: Vec<char> = key.chars().rev().take(4).collect();
+    format!("***{}", tail.into_iter().rev().collect::<String>())
+}
+
+impl UsageRecord {
+    /// `None` for failed calls, which are not billed.
+    pub fn from_completion(log: &CompletionLog) -> Option<Self> {
+        if log.error_kind.is_some() {
+            return None;
+        }
+        let model = log.model.clone().unwrap_or_default();
+        let prompt_tokens = log.tokens_prompt.unwrap_or(0);
+        let completion_tokens = log.tokens_completion.unwrap_or(0);
+        let provider = log.provider.clone().unwrap_or_default();
+        let estimate = || pricing::cost_usd(&provider, &model, prompt_tokens, completion_tokens);
+        Some(Self {
+            ts_ms: log.created_at_ms.unwrap_or(0),
+            cost_usd: log.cost_usd.or_else(estimate),
+            provider,
+            model,
+            client_key: log.client_key.as_deref().map(key_label),
+            prompt_tokens,
+            completion_tokens,
+        })
+    }
+}
+
+/// Telemetry sink that appends a `UsageRecord` per completion.
+pub struct UsageLog {
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl UsageLog {
+    pub fn open(path: impl Into<PathBuf>) -> CoreResult<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, rec: &UsageRecord) -> CoreResult<()> {
+        let mut line = serde_json::to_vec(rec).map_err(|e| AiProxyError::Other(e.into()))?;
+        line.push(b'\n');
+        // One write per record keeps lines whole when several processes share the file.
+        self.file.lock().unwrap().write_all(&line)?;
+        Ok(())
+    }
+}
+
+impl TelemetrySink for UsageLog {
+    fn record(&self, _trace: ProviderTrace) {}
+
+    fn record_completion(&self, log: CompletionLog) {
+        if let Some(rec) = UsageRecord::from_completion(&log) {
+            // Usage accounting must never fail a request.
+            let _ = self.append(&rec);
+        }
+    }
+}
+
+/// Records at or after `since_ms`. A missing file is empty; unparsable lines are skipped.
+pub fn read_since(path: &Path, since_ms: u64) -> CoreResult<Vec<UsageRecord>> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut out = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Ok(rec) = serde_json::from_str::<UsageRecord>(&line?)
+            && rec.ts_ms >= since_ms
+        {
+            out.push(rec);
+        }
+    }
+    Ok(out)
+}
+
+/// `YYYY-MM-DD` (UTC) of a millisecond timestamp.
+pub fn utc_day(ts_ms: u64) -> String {
+    // Civil-from-days, after Howard Hinnant's `chrono`-compatible algorithm.
+    let z = (ts_ms / 86_400_000) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    Model,
+    Provider,
+    ClientKey,
+    /// UTC calendar day.
+    Day,
+}
+
+impl GroupBy {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Model => "model",
+            Self::Provider => "provider",
+            Self::ClientKey => "client_key",
+            Self::Day => "day",
+        }
+    }
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "model" => Ok(Self::Model),
+            "provider" => Ok(Self::Provider),
+            "client_key" | "client-key" => Ok(Self::ClientKey),
+            "day" => Ok(Self::Day),
+            other => Err(format!(
+                "unknown group '{other}' (expected model, provider, client_key or day)"
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageSummary {
+    pub key: String,
+    pub requests: usize,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    /// Sum over records with a known price; `unpriced` counts the rest.
+    pub cost_usd: f64,
+    pub unpriced: usize,
+}
+
+/// Totals per group, most expensive first (ties broken by key).
+pub fn summarize(records: &[UsageRecord], group_by: GroupBy) -> Vec<UsageSummary> {
+    let mut groups: BTreeMap<String, UsageSummary> = BTreeMap::new();
+    for r in records {
+        let key = match group_by {
+            GroupBy::Model => r.model.clone(),
+            GroupBy::Provider => r.provider.clone(),
+            GroupBy::ClientKey => r.client_key.clone().unwrap_or_else(|| "(none)".into()),
+            GroupBy::Day => utc_day(r.ts_ms),
+        };
+        let s = groups.entry(key.clone()).or_insert_with(|| UsageSummary {
+            key,
+            requests: 0,
+            prompt_tokens: 0,
+            completion_tokens: 0,
+            cost_usd: 0.0,
+            unpriced: 0,
+        });
+        s.requests += 1;
+        s.prompt_tokens += u64::from(r.prompt_tokens);
+        s.completion_tokens += u64::from(r.completion_tokens);
+        match r.cost_usd {
+            Some(c) => s.cost_usd += c,
+            None => s.unpriced += 1,
+        }
+    }
+    let mut out: Vec<UsageSummary> = groups.into_values().collect();
+    out.sort_by(|a, b| b.cost_usd.total_cmp(&a.cost_usd).then(a.key.cmp(&b.key)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn completion(model: &str, key: Option<&str>, ts: u64) -> CompletionLog {
+        let mut log = CompletionLog::new()
+            .provider("openai")
+            .model(model)
+            .created_at_ms(ts)
+            .tokens(Some(1_000), Some(2_000), Some(3_000));
+        log.client_key = key.map(Into::into);
+        log
+    }
+
+    #[test]
+    fn sink_appends_and_reads_back_since() {
+        let dir = tempdir().unwrap();
+        let log = UsageLog::open(dir.path().join("nested").join(USAGE_FILE)).unwrap();
+        log.record_completion(completion("gpt-4o", Some("client-secret-abcd"), 10));
+        log.record_completion(completion("gpt-4o", None, 20));
+        log.record_completion(completion("gpt-4o", None, 30).error_kind_opt(Some("timeout")));
+
+        let all = read_since(log.path(), 0).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].client_key.as_deref(), Some("***abcd"));
+        assert!(all[0].cost_usd.is_some());
+        assert_eq!(read_since(log.path(), 15).unwrap().len(), 1);
+        assert!(
+            read_since(&dir.path().join("missing"), 0)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn summarize_groups_and_orders_by_cost() {
+        let recs: Vec<UsageRecord> = [
+            completion("gpt-4o-mini", Some("k1"), 1),
+            completion("gpt-4o", Some("k1"), 2),
+            completion("gpt-4o", Some("k2"), 3),
+            completion("mystery", None, 4),
+        ]
+        .iter()
+        .filter_map(UsageRecord::from_completion)
+        .collect();
+
+        let by_model = summarize(&recs, GroupBy::Model);
+        let keys: Vec<&str> = by_model.iter().map(|s| s.key.as_str()).collect();
+        assert_eq!(keys, vec!["gpt-4o", "gpt-4o-mini", "mystery"]);
+        assert_eq!(by_model[0].requests, 2);
+        assert_eq!(by_model[0].completion_tokens, 4_000);
+        assert_eq!(by_model[2].unpriced, 1);
+
+        let by_key = summarize(&recs, GroupBy::ClientKey);
+        assert_eq!(by_key.len(), 3);
+        assert!(by_key.iter().any(|s| s.key == "(none)"));
+        assert_eq!("client-key".parse::<GroupBy>(), Ok(GroupBy::ClientKey));
+        assert_eq!(summarize(&recs, GroupBy::Day)[0].key, "1970-01-01");
+        assert_eq!(utc_day(1_709_251_200_000), "2024-03-01");
+        assert!("team".parse::<GroupBy>().is_err());
+    }
+}
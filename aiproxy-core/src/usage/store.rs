@@ -0,0 +1,248 @@
+//! SQLite-backed usage store: one row per completed call, indexed by day and client key.
+//!
+//! Unlike the NDJSON log it answers aggregate queries without reading every record, which is
+//! what `aiproxy usage` and restart-proof daily quotas (`Limiter::preload_day_tokens`) need.
+//! Several processes may share one database file; writers wait on each other briefly.
+
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rusqlite::{Connection, params};
+
+use super::{GroupBy, UsageRecord, UsageSummary, utc_day};
+use crate::error::{AiProxyError, CoreResult};
+use crate::telemetry::{CompletionLog, ProviderTrace, TelemetrySink};
+
+/// File name of the usage database inside the transcript directory.
+pub const USAGE_DB: &str = "usage.sqlite";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS usage (
+    ts_ms INTEGER NOT NULL,
+    day TEXT NOT NULL,
+    client_key TEXT,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL,
+    prompt_tokens INTEGER NOT NULL,
+    completion_tokens INTEGER NOT NULL,
+    cost_usd REAL
+);
+CREATE INDEX IF NOT EXISTS usage_day_key ON usage (day, client_key);
+CREATE INDEX IF NOT EXISTS usage_ts ON usage (ts_ms);
+";
+
+fn db_err(e: rusqlite::Error) -> AiProxyError {
+    AiProxyError::Other(e.into())
+}
+
+/// Which records a query covers. Unset fields match everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageFilter {
+    /// Records at or after this timestamp.
+    pub since_ms: u64,
+    /// Redacted client key (`usage::key_label`).
+    pub client_key: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+}
+
+/// Telemetry sink and query API over a usage database.
+pub struct UsageStore {
+    conn: Mutex<Connection>,
+}
+
+impl UsageStore {
+    /// Open (creating if needed) the database at `path`.
+    pub fn open(path: &Path) -> CoreResult<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path).map_err(db_err)?;
+        conn.busy_timeout(Duration::from_secs(5)).map_err(db_err)?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(db_err)?;
+        Self::init(conn)
+    }
+
+    pub fn open_in_memory() -> CoreResult<Self> {
+        Self::init(Connection::open_in_memory().map_err(db_err)?)
+    }
+
+    fn init(conn: Connection) -> CoreResult<Self> {
+        conn.execute_batch(SCHEMA).map_err(db_err)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn insert(&self, rec: &UsageRecord) -> CoreResult<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT INTO usage (ts_ms, day, client_key, provider, model, prompt_tokens, \
+                 completion_tokens, cost_usd) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    rec.ts_ms as i64,
+                    utc_day(rec.ts_ms),
+                    rec.client_key,
+                    rec.provider,
+                    rec.model,
+                    rec.prompt_tokens,
+                    rec.completion_tokens,
+                    rec.cost_usd,
+                ],
+            )
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    /// Totals per group over the records `filter` selects, most expensive first (ties broken
+    /// by key), like `usage::summarize`.
+    pub fn aggregate(
+        &self,
+        filter: &UsageFilter,
+        group_by: GroupBy,
+    ) -> CoreResult<Vec<UsageSummary>> {
+        let key = match group_by {
+            GroupBy::Model => "model",
+            GroupBy::Provider => "provider",
+            GroupBy::ClientKey => "COALESCE(client_key, '(none)')",
+            GroupBy::Day => "day",
+        };
+        let sql = format!(
+            "SELECT {key} AS k, COUNT(*), SUM(prompt_tokens), SUM(completion_tokens), \
+             COALESCE(SUM(cost_usd), 0.0), SUM(cost_usd IS NULL) FROM usage \
+             WHERE ts_ms >= ?1 AND (?2 IS NULL OR client_key = ?2) \
+             AND (?3 IS NULL OR provider = ?3) AND (?4 IS NULL OR model = ?4) \
+             GROUP BY k ORDER BY 5 DESC, k"
+        );
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&sql).map_err(db_err)?;
+        let rows = stmt
+            .query_map(
+                params![
+                    filter.since_ms as i64,
+                    filter.client_key,
+                    filter.provider,
+                    filter.model,
+                ],
+                |row| {
+                    Ok(UsageSummary {
+                        key: row.get(0)?,
+                        requests: row.get::<_, i64>(1)? as usize,
+                        prompt_tokens: row.get::<_, i64>(2)? as u64,
+                        completion_tokens: row.get::<_, i64>(3)? as u64,
+                        cost_usd: row.get(4)?,
+                        unpriced: row.get::<_, i64>(5)? as usize,
+                    })
+                },
+            )
+            .map_err(db_err)?;
+        rows.collect::<Result<_, _>>().map_err(db_err)
+    }
+
+    /// Prompt plus completion tokens recorded for `client_key` (redacted) on `day`
+    /// (`YYYY-MM-DD`, UTC).
+    pub fn day_tokens(&self, client_key: &str, day: &str) -> CoreResult<u64> {
+        let total: i64 = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT COALESCE(SUM(prompt_tokens + completion_tokens), 0) FROM usage \
+                 WHERE day = ?1 AND client_key = ?2",
+                params![day, client_key],
+                |row| row.get(0),
+            )
+            .map_err(db_err)?;
+        Ok(total as u64)
+    }
+}
+
+impl TelemetrySink for UsageStore {
+    fn record(&self, _trace: ProviderTrace) {}
+
+    fn record_completion(&self, log: CompletionLog) {
+        if let Some(rec) = UsageRecord::from_completion(&log) {
+            // Usage accounting must never fail a request.
+            let _ = self.insert(&rec);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn record(model: &str, key: Option<&str>, ts_ms: u64, cost_usd: Option<f64>) -> UsageRecord {
+        UsageRecord {
+            ts_ms,
+            provider: "openai".into(),
+            model: model.into(),
+            client_key: key.map(Into::into),
+            prompt_tokens: 100,
+            completion_tokens: 50,
+            cost_usd,
+        }
+    }
+
+    #[test]
+    fn aggregates_match_the_in_memory_summary() {
+        let store = UsageStore::open_in_memory().unwrap();
+        let recs = [
+            record("gpt-4o", Some("***k1"), 1_000, Some(0.5)),
+            record("gpt-4o", Some("***k2"), 2_000, Some(0.25)),
+            record("gpt-4o-mini", Some("***k1"), 3_000, Some(0.1)),
+            record("mystery", None, 86_400_000, None),
+        ];
+        for r in &recs {
+            store.insert(r).unwrap();
+        }
+        for group_by in [
+            GroupBy::Model,
+            GroupBy::Provider,
+            GroupBy::ClientKey,
+            GroupBy::Day,
+        ] {
+            assert_eq!(
+                store.aggregate(&UsageFilter::default(), group_by).unwrap(),
+                super::super::summarize(&recs, group_by),
+                "{group_by:?}"
+            );
+        }
+
+        let k1 = UsageFilter {
+            since_ms: 2_000,
+            client_key: Some("***k1".into()),
+            ..UsageFilter::default()
+        };
+        let rows = store.aggregate(&k1, GroupBy::Model).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].key, "gpt-4o-mini");
+    }
+
+    #[test]
+    fn sink_persists_day_totals_across_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("nested").join(USAGE_DB);
+        let store = UsageStore::open(&path).unwrap();
+        let log = CompletionLog::new()
+            .provider("openai")
+            .model("gpt-4o")
+            .created_at_ms(86_400_000 + 5)
+            .tokens(Some(1_000), Some(2_000), Some(3_000));
+        let mut keyed = log.clone();
+        keyed.client_key = Some("client-secret-abcd".into());
+        store.record_completion(keyed.clone());
+        store.record_completion(keyed.clone().error_kind_opt(Some("timeout")));
+        store.record_completion(log);
+        drop(store);
+
+        let store = UsageStore::open(&path).unwrap();
+        assert_eq!(store.day_tokens("***abcd", "1970-01-02").unwrap(), 3_000);
+        assert_eq!(store.day_tokens("***abcd", "1970-01-01").unwrap(), 0);
+    }
+}
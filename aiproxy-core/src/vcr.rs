@@ -0,0 +1,388 @@
+//! Record/replay of provider HTTP traffic ("VCR" mode) at the `HttpClient` layer.
+//!
+//! In `record` mode every exchange is sent upstream as usual and appended to a cassette: a
+//! JSON file of request/response pairs, SSE bodies included. In `replay` mode requests are
+//! answered from the cassette and nothing leaves the process, which makes integration tests
+//! deterministic and allows offline work against real payload shapes.
+//!
+//! Requests match on method, path (with query) and JSON body; the host is ignored so a
+//! cassette recorded against one base URL replays against any other. Request headers are
+//! never written, so API keys stay out of cassettes. Recording buffers each response body
+//! before handing it on, so streams arrive all at once while recording.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{VcrCfg, VcrMode};
+use crate::error::{AiProxyError, CoreResult};
+
+/// Response headers not worth keeping in a cassette.
+const SKIPPED_HEADERS: &[&str] = &["set-cookie", "content-length", "transfer-encoding"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// Path and query, without scheme or host.
+    pub path: String,
+    #[serde(default)]
+    pub body: serde_json::Value,
+}
+
+impl RecordedRequest {
+    fn of(req: &reqwest::Request) -> Self {
+        let url = req.url();
+        let path = match url.query() {
+            Some(q) => format!("{}?{q}", url.path()),
+            None => url.path().to_string(),
+        };
+        let body = req
+            .body()
+            .and_then(|b| b.as_bytes())
+            .map_or(serde_json::Value::Null, |b| {
+                serde_json::from_slice(b).unwrap_or_else(|_| {
+                    serde_json::Value::String(String::from_utf8_lossy(b).into_owned())
+                })
+            });
+        Self {
+            method: req.method().to_string(),
+            path,
+            body,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// The whole body as text; for streams, every SSE line as received.
+    pub body: String,
+}
+
+impl RecordedResponse {
+    #[cfg(target_arch = "wasm32")]
+    fn into_response(self) -> CoreResult<reqwest::Response> {
+        // reqwest's `fetch` responses can only come from the host; cassettes need files anyway.
+        Err(AiProxyError::Validation(
+            "cassette replay is not available on wasm32".into(),
+        ))
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn into_response(self) -> CoreResult<reqwest::Response> {
+        let mut builder = http::Response::builder().status(self.status);
+        for (k, v) in &self.headers {
+            builder = builder.header(k, v);
+        }
+        let resp = builder
+            .body(self.body)
+            .map_err(|e| AiProxyError::Other(anyhow::anyhow!("bad recorded response: {e}")))?;
+        Ok(reqwest::Response::from(resp))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: RecordedRequest,
+    pub response: RecordedResponse,
+}
+
+#[derive(Debug, Default)]
+struct Tape {
+    interactions: Vec<Interaction>,
+    /// Which interactions replay has already served.
+    played: Vec<bool>,
+}
+
+/// One cassette file, shared by every `HttpClient` built from the same config.
+#[derive(Debug)]
+pub struct Cassette {
+    path: PathBuf,
+    mode: VcrMode,
+    tape: Mutex<Tape>,
+}
+
+impl Cassette {
+    /// Start recording into `path`, replacing any previous recording there.
+    pub fn record(path: impl Into<PathBuf>) -> CoreResult<Self> {
+        let path = path.into();
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let cassette = Self {
+            path,
+            mode: VcrMode::Record,
+            tape: Mutex::new(Tape::default()),
+        };
+        cassette.save(&[])?;
+        Ok(cassette)
+    }
+
+    /// Load `path` for replay.
+    pub fn replay(path: impl Into<PathBuf>) -> CoreResult<Self> {
+        let path = path.into();
+        let bytes = std::fs::read(&path)?;
+        let interactions: Vec<Interaction> = serde_json::from_slice(&bytes)
+            .map_err(|e| AiProxyError::Validation(format!("cassette {}: {e}", path.display())))?;
+        Ok(Self {
+            path,
+            mode: VcrMode::Replay,
+            tape: Mutex::new(Tape {
+                played: vec![false; interactions.len()],
+                interactions,
+            }),
+        })
+    }
+
+    /// The cassette `cfg` asks for, or `None` when VCR is off.
+    pub fn from_cfg(cfg: &VcrCfg) -> CoreResult<Option<Arc<Self>>> {
+        let path = match (cfg.mode, &cfg.cassette) {
+            (VcrMode::Off, _) => return Ok(None),
+            (_, Some(path)) => path,
+            (_, None) => {
+                return Err(AiProxyError::Validation(
+                    "http.vcr.cassette is required when http.vcr.mode is record or replay".into(),
+                ));
+            }
+        };
+        let cassette = match cfg.mode {
+            VcrMode::Record => Self::record(path)?,
+            _ => Self::replay(path)?,
+        };
+        Ok(Some(Arc::new(cassette)))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn mode(&self) -> VcrMode {
+        self.mode
+    }
+
+    /// Everything recorded or loaded so far.
+    pub fn interactions(&self) -> Vec<Interaction> {
+        self.tape.lock().unwrap().interactions.clone()
+    }
+
+    fn save(&self, interactions: &[Interaction]) -> CoreResult<()> {
+        let json =
+            serde_json::to_vec_pretty(interactions).map_err(|e| AiProxyError::Other(e.into()))?;
+        std::fs::write(&self.path, json)?;
+        Ok(())
+    }
+
+    /// Send `req` upstream (recording) or answer it from the tape (replaying).
+    pub(crate) async fn execute(
+        &self,
+        client: &reqwest::Client,
+        req: reqwest::Request,
+    ) -> CoreResult<Result<reqwest::Response, reqwest::Error>> {
+        let key = RecordedRequest::of(&req);
+        if self.mode == VcrMode::Replay {
+            return self.play(&key)?.into_response().map(Ok);
+        }
+        let resp = match client.execute(req).await {
+            Ok(resp) => resp,
+            Err(e) => return Ok(Err(e)),
+        };
+        let status = resp.status().as_u16();
+        let headers = resp
+            .headers()
+            .iter()
+            .filter(|(k, _)| !SKIPPED_HEADERS.contains(&k.as_str()))
+            .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let body = match resp.bytes().await {
+            Ok(b) => String::from_utf8_lossy(&b).into_owned(),
+            Err(e) => return Ok(Err(e)),
+        };
+        let response = RecordedResponse {
+            status,
+            headers,
+            body,
+        };
+        {
+            let mut tape = self.tape.lock().unwrap();
+            tape.interactions.push(Interaction {
+                request: key,
+                response: response.clone(),
+            });
+            tape.played.push(true);
+            self.save(&tape.interactions)?;
+        }
+        response.into_response().map(Ok)
+    }
+
+    /// The first unplayed interaction matching `key`, else the last one that matched before;
+    /// identical requests replay their recordings in order, then repeat the final one.
+    fn play(&self, key: &RecordedRequest) -> CoreResult<RecordedResponse> {
+        let mut tape = self.tape.lock().unwrap();
+        let matching: Vec<usize> = (0..tape.interactions.len())
+            .filter(|&i| tape.interactions[i].request == *key)
+            .collect();
+        let Some(&i) = matching
+            .iter()
+            .find(|&&i| !tape.played[i])
+            .or(matching.last())
+        else {
+            return Err(AiProxyError::Other(anyhow::anyhow!(
+                "no recorded response for {} {} in cassette {}",
+                key.method,
+                key.path,
+                self.path.display()
+            )));
+        };
+        tape.played[i] = true;
+        Ok(tape.interactions[i].response.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http_client::{HttpClient, RequestCtx};
+    use futures_util::StreamExt;
+    use httpmock::prelude::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    async fn sse_text(client: &HttpClient, url: &str) -> CoreResult<Vec<String>> {
+        let (stream, _) = client
+            .post_sse_lines(url, &json!({"stream": true}), &[], &RequestCtx::default())
+            .await?;
+        stream
+            .map(|l| l.map(|l| l.line))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn recorded_json_and_sse_replay_without_the_server() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("tapes").join("chat.json");
+        let server = MockServer::start();
+        server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat")
+                .json_body(json!({"n": 1}));
+            then.status(200)
+                .header("x-request-id", "req-1")
+                .json_body(json!({"answer": 1}));
+        });
+        server.mock(|when, then| {
+            when.method(POST)
+                .path("/v1/chat")
+                .json_body(json!({"n": 2}));
+            then.status(429)
+                .header("retry-after", "7")
+                .body("slow down");
+        });
+        server.mock(|when, then| {
+            when.method(POST).path("/v1/stream");
+            then.status(200)
+                .header("content-type", "text/event-stream")
+                .body("data: {\"delta\":\"Hi\"}\n\ndata: [DONE]\n\n");
+        });
+
+        let ctx = RequestCtx::default();
+        let recorder = HttpClient::new_default()
+            .unwrap()
+            .with_vcr(Arc::new(Cassette::record(&path).unwrap()));
+        let url = |p: &str| format!("{}{p}", server.base_url());
+        let (v, rid, _) = recorder
+            .post_json::<_, serde_json::Value>(&url("/v1/chat"), &json!({"n": 1}), &[], &ctx)
+            .await
+            .unwrap();
+        assert_eq!((v, rid.as_deref()), (json!({"answer": 1}), Some("req-1")));
+        let err = recorder
+            .post_json::<_, serde_json::Value>(&url("/v1/chat"), &json!({"n": 2}), &[], &ctx)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err,
+            AiProxyError::RateLimited {
+                retry_after: Some(7),
+                ..
+            }
+        ));
+        let recorded = sse_text(&recorder, &url("/v1/stream")).await.unwrap();
+        drop(server);
+
+        let player = HttpClient::new_default()
+            .unwrap()
+            .with_vcr(Arc::new(Cassette::replay(&path).unwrap()));
+        let elsewhere = "http://127.0.0.1:9";
+        let (v, rid, _) = player
+            .post_json::<_, serde_json::Value>(
+                &format!("{elsewhere}/v1/chat"),
+                &json!({"n": 1}),
+                &[],
+                &ctx,
+            )
+            .await
+            .unwrap();
+        assert_eq!((v, rid.as_deref()), (json!({"answer": 1}), Some("req-1")));
+        let err = player
+            .post_json::<_, serde_json::Value>(
+                &format!("{elsewhere}/v1/chat"),
+                &json!({"n": 2}),
+                &[],
+                &ctx,
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AiProxyError::RateLimited { .. }));
+        let replayed = sse_text(&player, &format!("{elsewhere}/v1/stream"))
+            .await
+            .unwrap();
+        assert_eq!(replayed, recorded);
+        assert!(replayed.iter().any(|l| l.contains("[DONE]")));
+
+        let err = player
+            .post_json::<_, serde_json::Value>(
+                &format!("{elsewhere}/v1/chat"),
+                &json!({"n": 3}),
+                &[],
+                &ctx,
+            )
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("no recorded response for POST /v1/chat")
+        );
+    }
+
+    #[test]
+    fn config_selects_the_cassette_mode() {
+        assert!(Cassette::from_cfg(&VcrCfg::default()).unwrap().is_none());
+        let missing = VcrCfg {
+            mode: VcrMode::Replay,
+            cassette: None,
+        };
+        assert!(Cassette::from_cfg(&missing).is_err());
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("c.json").to_string_lossy().into_owned();
+        let rec = Cassette::from_cfg(&VcrCfg {
+            mode: VcrMode::Record,
+            cassette: Some(path.clone()),
+        })
+        .unwrap()
+        .unwrap();
+        assert_eq!(rec.mode(), VcrMode::Record);
+        let play = Cassette::from_cfg(&VcrCfg {
+            mode: VcrMode::Replay,
+            cassette: Some(path),
+        })
+        .unwrap()
+        .unwrap();
+        assert!(play.interactions().is_empty());
+    }
+}